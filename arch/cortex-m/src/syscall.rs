@@ -112,6 +112,9 @@ impl kernel::syscall::UserspaceKernelBoundary for SysCall {
                 operand: r0,
                 arg0: r1,
             }),
+            5 => Some(kernel::syscall::Syscall::YIELDFOR {
+                callback_ptr: r0 as *mut (),
+            }),
             _ => None,
         }
     }
@@ -462,4 +465,15 @@ impl kernel::syscall::UserspaceKernelBoundary for SysCall {
             },
         ));
     }
+
+    unsafe fn process_crash_fmt(
+        &self,
+        stack_pointer: *const usize,
+        _state: &CortexMStoredState,
+        writer: &mut Write,
+    ) {
+        let lr = read_volatile(stack_pointer.offset(5));
+        let pc = read_volatile(stack_pointer.offset(6));
+        let _ = writer.write_fmt(format_args!("PC: {:#010X} LR: {:#010X}", pc, lr));
+    }
 }
@@ -1,6 +1,7 @@
 //! Implementation of the ARM memory protection unit.
 
 use core::cmp;
+use core::fmt;
 use kernel;
 use kernel::common::math;
 use kernel::common::registers::{FieldValue, ReadOnly, ReadWrite};
@@ -153,6 +154,34 @@ impl Default for CortexMConfig {
     }
 }
 
+impl fmt::Display for CortexMConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (number, region) in self.regions.iter().enumerate() {
+            if let (Some((start, size)), Some(permissions)) =
+                (region.location(), region.permissions())
+            {
+                let start = start as usize;
+                let attributes = region.attributes().value;
+                let srd = (attributes >> 8) & 0xff;
+                write!(
+                    f,
+                    " Region {}: {:#010X} - {:#010X} ({} bytes) {:?}",
+                    number,
+                    start,
+                    start + size,
+                    size,
+                    permissions,
+                )?;
+                if srd != 0 {
+                    write!(f, " SRD={:#04X}", srd)?;
+                }
+                write!(f, "\r\n")?;
+            }
+        }
+        Ok(())
+    }
+}
+
 impl CortexMConfig {
     fn unused_region_number(&self) -> Option<usize> {
         for (number, region) in self.regions.iter().enumerate() {
@@ -171,6 +200,7 @@ impl CortexMConfig {
 #[derive(Copy, Clone)]
 pub struct CortexMRegion {
     location: Option<(*const u8, usize)>,
+    permissions: Option<mpu::Permissions>,
     base_address: FieldValue<u32, RegionBaseAddress::Register>,
     attributes: FieldValue<u32, RegionAttributes::Register>,
 }
@@ -234,6 +264,7 @@ impl CortexMRegion {
 
         CortexMRegion {
             location: Some((logical_start, logical_size)),
+            permissions: Some(permissions),
             base_address: base_address,
             attributes: attributes,
         }
@@ -242,6 +273,7 @@ impl CortexMRegion {
     fn empty(region_num: usize) -> CortexMRegion {
         CortexMRegion {
             location: None,
+            permissions: None,
             base_address: RegionBaseAddress::VALID::UseRBAR
                 + RegionBaseAddress::REGION.val(region_num as u32),
             attributes: RegionAttributes::ENABLE::CLEAR,
@@ -252,6 +284,10 @@ impl CortexMRegion {
         self.location
     }
 
+    fn permissions(&self) -> Option<mpu::Permissions> {
+        self.permissions
+    }
+
     fn base_address(&self) -> FieldValue<u32, RegionBaseAddress::Register> {
         self.base_address
     }
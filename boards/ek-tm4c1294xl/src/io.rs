@@ -44,7 +44,7 @@ impl Write for Writer {
 #[no_mangle]
 #[panic_implementation]
 pub unsafe extern "C" fn panic_fmt(pi: &PanicInfo) -> ! {
-    let led = &mut led::LedLow::new(&mut tm4c129x::gpio::PF[0]);
+    let led = &mut led::LedLow::new(&tm4c129x::gpio::PF[0]);
     let writer = &mut WRITER;
     debug::panic(&mut [led], writer, pi, &cortexm4::support::nop, &PROCESSES)
 }
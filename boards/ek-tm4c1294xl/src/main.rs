@@ -50,10 +50,18 @@ struct EkTm4c1294xl {
         'static,
         VirtualMuxAlarm<'static, tm4c129x::gpt::AlarmTimer>,
     >,
-    gpio: &'static capsules::gpio::GPIO<'static, tm4c129x::gpio::GPIOPin>,
+    gpio: &'static capsules::gpio::GPIO<
+        'static,
+        tm4c129x::gpio::GPIOPin,
+        VirtualMuxAlarm<'static, tm4c129x::gpt::AlarmTimer>,
+    >,
     ipc: kernel::ipc::IPC,
-    led: &'static capsules::led::LED<'static, tm4c129x::gpio::GPIOPin>,
-    button: &'static capsules::button::Button<'static, tm4c129x::gpio::GPIOPin>,
+    led: &'static capsules::led::LED<'static>,
+    button: &'static capsules::button::Button<
+        'static,
+        tm4c129x::gpio::GPIOPin,
+        VirtualMuxAlarm<'static, tm4c129x::gpt::AlarmTimer>,
+    >,
 }
 
 /// Mapping of integer syscalls to objects that implement syscalls.
@@ -145,6 +153,17 @@ pub unsafe fn reset_handler() {
         MuxAlarm::new(alarm_timer)
     );
     alarm_timer.configure(mux_alarm);
+    let wrap_alarm = static_init!(
+        VirtualMuxAlarm<'static, tm4c129x::gpt::AlarmTimer>,
+        VirtualMuxAlarm::new(mux_alarm)
+    );
+    let wrap_counter = static_init!(
+        capsules::alarm::WrapCounter<'static, VirtualMuxAlarm<'static, tm4c129x::gpt::AlarmTimer>>,
+        capsules::alarm::WrapCounter::new(wrap_alarm)
+    );
+    wrap_alarm.set_client(wrap_counter);
+    wrap_counter.start();
+
     let virtual_alarm1 = static_init!(
         VirtualMuxAlarm<'static, tm4c129x::gpt::AlarmTimer>,
         VirtualMuxAlarm::new(mux_alarm)
@@ -153,6 +172,7 @@ pub unsafe fn reset_handler() {
         capsules::alarm::AlarmDriver<'static, VirtualMuxAlarm<'static, tm4c129x::gpt::AlarmTimer>>,
         capsules::alarm::AlarmDriver::new(
             virtual_alarm1,
+            wrap_counter,
             board_kernel.create_grant(&memory_allocation_capability)
         )
     );
@@ -160,32 +180,21 @@ pub unsafe fn reset_handler() {
 
     // LEDs
     let led_pins = static_init!(
-        [(
-            &'static tm4c129x::gpio::GPIOPin,
-            capsules::led::ActivationMode
-        ); 4],
+        [kernel::hil::led::LedHigh<'static>; 4],
         [
-            (
-                &tm4c129x::gpio::PF[0],
-                capsules::led::ActivationMode::ActiveHigh
-            ), // D1
-            (
-                &tm4c129x::gpio::PF[4],
-                capsules::led::ActivationMode::ActiveHigh
-            ), // D2
-            (
-                &tm4c129x::gpio::PN[0],
-                capsules::led::ActivationMode::ActiveHigh
-            ), // D3
-            (
-                &tm4c129x::gpio::PN[1],
-                capsules::led::ActivationMode::ActiveHigh
-            ), // D4
+            kernel::hil::led::LedHigh::new(&tm4c129x::gpio::PF[0]), // D1
+            kernel::hil::led::LedHigh::new(&tm4c129x::gpio::PF[4]), // D2
+            kernel::hil::led::LedHigh::new(&tm4c129x::gpio::PN[0]), // D3
+            kernel::hil::led::LedHigh::new(&tm4c129x::gpio::PN[1]), // D4
         ]
     );
+    let leds = static_init!(
+        [&'static kernel::hil::led::Led; 4],
+        [&led_pins[0], &led_pins[1], &led_pins[2], &led_pins[3]]
+    );
     let led = static_init!(
-        capsules::led::LED<'static, tm4c129x::gpio::GPIOPin>,
-        capsules::led::LED::new(led_pins)
+        capsules::led::LED<'static>,
+        capsules::led::LED::new(leds)
     );
 
     // BUTTONs
@@ -202,16 +211,27 @@ pub unsafe fn reset_handler() {
             ), //USR_SW2
         ]
     );
+    let button_alarm = static_init!(
+        VirtualMuxAlarm<'static, tm4c129x::gpt::AlarmTimer>,
+        VirtualMuxAlarm::new(mux_alarm)
+    );
     let button = static_init!(
-        capsules::button::Button<'static, tm4c129x::gpio::GPIOPin>,
+        capsules::button::Button<
+            'static,
+            tm4c129x::gpio::GPIOPin,
+            VirtualMuxAlarm<'static, tm4c129x::gpt::AlarmTimer>,
+        >,
         capsules::button::Button::new(
             button_pins,
+            button_alarm,
+            0,
             board_kernel.create_grant(&memory_allocation_capability)
         )
     );
     for &(btn, _) in button_pins.iter() {
         btn.set_client(button);
     }
+    button_alarm.set_client(button);
 
     // set GPIO driver controlling remaining GPIO pins
     let gpio_pins = static_init!(
@@ -223,13 +243,24 @@ pub unsafe fn reset_handler() {
             &tm4c129x::gpio::PC[7],
         ]
     );
+    let gpio_alarm = static_init!(
+        VirtualMuxAlarm<'static, tm4c129x::gpt::AlarmTimer>,
+        VirtualMuxAlarm::new(mux_alarm)
+    );
+    let gpio_pin_group = static_init!(
+        kernel::hil::gpio::PinGroup<'static, tm4c129x::gpio::GPIOPin>,
+        kernel::hil::gpio::PinGroup::new(gpio_pins)
+    );
     let gpio = static_init!(
-        capsules::gpio::GPIO<'static, tm4c129x::gpio::GPIOPin>,
-        capsules::gpio::GPIO::new(gpio_pins)
+        capsules::gpio::GPIO<
+            'static,
+            tm4c129x::gpio::GPIOPin,
+            VirtualMuxAlarm<'static, tm4c129x::gpt::AlarmTimer>,
+        >,
+        capsules::gpio::GPIO::new(gpio_pin_group, gpio_alarm)
     );
-    for pin in gpio_pins.iter() {
-        pin.set_client(gpio);
-    }
+    gpio_pin_group.set_client(gpio);
+    gpio_alarm.set_client(gpio);
 
     let tm4c1294 = EkTm4c1294xl {
         console: console,
@@ -51,7 +51,7 @@ pub unsafe extern "C" fn panic_fmt(pi: &PanicInfo) -> ! {
     led_blue.enable_output();
     led_blue.set();
 
-    let led_red = &mut led::LedLow::new(&mut sam4l::gpio::PA[13]);
+    let led_red = &mut led::LedLow::new(&sam4l::gpio::PA[13]);
     let writer = &mut WRITER;
     debug::panic(
         &mut [led_red],
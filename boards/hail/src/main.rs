@@ -65,7 +65,11 @@ pub static mut STACK_MEMORY: [u8; 0x1000] = [0; 0x1000];
 /// capsules for this platform.
 struct Hail {
     console: &'static capsules::console::Console<'static, UartDevice<'static>>,
-    gpio: &'static capsules::gpio::GPIO<'static, sam4l::gpio::GPIOPin>,
+    gpio: &'static capsules::gpio::GPIO<
+        'static,
+        sam4l::gpio::GPIOPin,
+        VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>,
+    >,
     alarm: &'static capsules::alarm::AlarmDriver<
         'static,
         VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>,
@@ -80,8 +84,12 @@ struct Hail {
         sam4l::usart::USART,
     >,
     adc: &'static capsules::adc::Adc<'static, sam4l::adc::Adc>,
-    led: &'static capsules::led::LED<'static, sam4l::gpio::GPIOPin>,
-    button: &'static capsules::button::Button<'static, sam4l::gpio::GPIOPin>,
+    led: &'static capsules::led::LED<'static>,
+    button: &'static capsules::button::Button<
+        'static,
+        sam4l::gpio::GPIOPin,
+        VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>,
+    >,
     rng: &'static capsules::rng::RngDriver<'static>,
     ipc: kernel::ipc::IPC,
     crc: &'static capsules::crc::Crc<'static, sam4l::crccu::Crccu<'static>>,
@@ -361,6 +369,17 @@ pub unsafe fn reset_handler() {
     hil::sensors::AmbientLight::set_client(isl29035, ambient_light);
 
     // Alarm
+    let wrap_alarm = static_init!(
+        VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+        VirtualMuxAlarm::new(mux_alarm)
+    );
+    let wrap_counter = static_init!(
+        capsules::alarm::WrapCounter<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
+        capsules::alarm::WrapCounter::new(wrap_alarm)
+    );
+    wrap_alarm.set_client(wrap_counter);
+    wrap_counter.start();
+
     let virtual_alarm1 = static_init!(
         VirtualMuxAlarm<'static, sam4l::ast::Ast>,
         VirtualMuxAlarm::new(mux_alarm)
@@ -369,6 +388,7 @@ pub unsafe fn reset_handler() {
         capsules::alarm::AlarmDriver<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
         capsules::alarm::AlarmDriver::new(
             virtual_alarm1,
+            wrap_counter,
             board_kernel.create_grant(&memory_allocation_capability)
         )
     );
@@ -416,7 +436,10 @@ pub unsafe fn reset_handler() {
     // Create the SPI system call capsule, passing the client
     let spi_syscalls = static_init!(
         capsules::spi::Spi<'static, VirtualSpiMasterDevice<'static, sam4l::spi::SpiHw>>,
-        capsules::spi::Spi::new(syscall_spi_device)
+        capsules::spi::Spi::new(
+            syscall_spi_device,
+            board_kernel.create_grant(&memory_allocation_capability)
+        )
     );
 
     spi_syscalls.config_buffers(&mut SPI_READ_BUF, &mut SPI_WRITE_BUF);
@@ -424,25 +447,20 @@ pub unsafe fn reset_handler() {
 
     // LEDs
     let led_pins = static_init!(
-        [(&'static sam4l::gpio::GPIOPin, capsules::led::ActivationMode); 3],
+        [kernel::hil::led::LedLow<'static>; 3],
         [
-            (
-                &sam4l::gpio::PA[13],
-                capsules::led::ActivationMode::ActiveLow
-            ), // Red
-            (
-                &sam4l::gpio::PA[15],
-                capsules::led::ActivationMode::ActiveLow
-            ), // Green
-            (
-                &sam4l::gpio::PA[14],
-                capsules::led::ActivationMode::ActiveLow
-            ),
+            kernel::hil::led::LedLow::new(&sam4l::gpio::PA[13]), // Red
+            kernel::hil::led::LedLow::new(&sam4l::gpio::PA[15]), // Green
+            kernel::hil::led::LedLow::new(&sam4l::gpio::PA[14]), // Blue
         ]
-    ); // Blue
+    );
+    let leds = static_init!(
+        [&'static kernel::hil::led::Led; 3],
+        [&led_pins[0], &led_pins[1], &led_pins[2]]
+    );
     let led = static_init!(
-        capsules::led::LED<'static, sam4l::gpio::GPIOPin>,
-        capsules::led::LED::new(led_pins)
+        capsules::led::LED<'static>,
+        capsules::led::LED::new(leds)
     );
 
     // BUTTONs
@@ -453,16 +471,27 @@ pub unsafe fn reset_handler() {
             capsules::button::GpioMode::LowWhenPressed
         )]
     );
+    let button_alarm = static_init!(
+        VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+        VirtualMuxAlarm::new(mux_alarm)
+    );
     let button = static_init!(
-        capsules::button::Button<'static, sam4l::gpio::GPIOPin>,
+        capsules::button::Button<
+            'static,
+            sam4l::gpio::GPIOPin,
+            VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+        >,
         capsules::button::Button::new(
             button_pins,
+            button_alarm,
+            0,
             board_kernel.create_grant(&memory_allocation_capability)
         )
     );
     for &(btn, _) in button_pins.iter() {
         btn.set_client(button);
     }
+    button_alarm.set_client(button);
 
     // Setup ADC
     let adc_channels = static_init!(
@@ -513,13 +542,24 @@ pub unsafe fn reset_handler() {
             &sam4l::gpio::PB[12],
         ]
     ); // D7
-    let gpio = static_init!(
-        capsules::gpio::GPIO<'static, sam4l::gpio::GPIOPin>,
-        capsules::gpio::GPIO::new(gpio_pins)
+    let gpio_alarm = static_init!(
+        VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+        VirtualMuxAlarm::new(mux_alarm)
     );
-    for pin in gpio_pins.iter() {
-        pin.set_client(gpio);
-    }
+    let gpio_pin_group = static_init!(
+        kernel::hil::gpio::PinGroup<'static, sam4l::gpio::GPIOPin>,
+        kernel::hil::gpio::PinGroup::new(gpio_pins)
+    );
+    let gpio = static_init!(
+        capsules::gpio::GPIO<
+            'static,
+            sam4l::gpio::GPIOPin,
+            VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+        >,
+        capsules::gpio::GPIO::new(gpio_pin_group, gpio_alarm)
+    );
+    gpio_pin_group.set_client(gpio);
+    gpio_alarm.set_client(gpio);
 
     // CRC
     let crc = static_init!(
@@ -14,7 +14,7 @@
 
 #![allow(dead_code)] // Components are intended to be conditionally included
 
-use capsules::alarm::AlarmDriver;
+use capsules::alarm::{AlarmDriver, WrapCounter};
 use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use kernel;
 use kernel::capabilities;
@@ -44,13 +44,28 @@ impl Component for AlarmDriverComponent {
     unsafe fn finalize(&mut self) -> Self::Output {
         let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
 
+        let wrap_alarm = static_init!(
+            VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+            VirtualMuxAlarm::new(self.alarm_mux)
+        );
+        let wrap_counter = static_init!(
+            WrapCounter<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
+            WrapCounter::new(wrap_alarm)
+        );
+        wrap_alarm.set_client(wrap_counter);
+        wrap_counter.start();
+
         let virtual_alarm1 = static_init!(
             VirtualMuxAlarm<'static, sam4l::ast::Ast>,
             VirtualMuxAlarm::new(self.alarm_mux)
         );
         let alarm = static_init!(
             AlarmDriver<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
-            AlarmDriver::new(virtual_alarm1, self.board_kernel.create_grant(&grant_cap))
+            AlarmDriver::new(
+                virtual_alarm1,
+                wrap_counter,
+                self.board_kernel.create_grant(&grant_cap)
+            )
         );
 
         virtual_alarm1.set_client(alarm);
@@ -0,0 +1,61 @@
+//! Component for IEEE 802.15.4 association on the imix board.
+//!
+//! This provides one Component, AssociationComponent, which adds a user to
+//! the 802.15.4 MAC mux through which the board can join a PAN and be
+//! allocated a short address by its coordinator.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let association = AssociationComponent::new(mux_mac, mux_alarm).finalize();
+//! association.associate(PAN_ID, MacAddress::Short(0x0000));
+//! ```
+
+#![allow(dead_code)] // Components are intended to be conditionally included
+
+use capsules::ieee802154::association::{Association, ASSOC_BUF};
+use capsules::ieee802154::device::MacDevice;
+use capsules::ieee802154::virtual_mac::{MacUser, MuxMac};
+use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use kernel::component::Component;
+use sam4l;
+
+pub struct AssociationComponent {
+    mux_mac: &'static MuxMac<'static>,
+    alarm_mux: &'static MuxAlarm<'static, sam4l::ast::Ast<'static>>,
+}
+
+impl AssociationComponent {
+    pub fn new(
+        mux_mac: &'static MuxMac<'static>,
+        alarm: &'static MuxAlarm<'static, sam4l::ast::Ast<'static>>,
+    ) -> AssociationComponent {
+        AssociationComponent {
+            mux_mac: mux_mac,
+            alarm_mux: alarm,
+        }
+    }
+}
+
+impl Component for AssociationComponent {
+    type Output = &'static Association<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>>;
+
+    unsafe fn finalize(&mut self) -> Self::Output {
+        let assoc_alarm = static_init!(
+            VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+            VirtualMuxAlarm::new(self.alarm_mux)
+        );
+        let assoc_mac = static_init!(MacUser<'static>, MacUser::new(self.mux_mac));
+        self.mux_mac.add_user(assoc_mac);
+
+        let association = static_init!(
+            Association<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
+            Association::new(assoc_mac, assoc_alarm, &mut ASSOC_BUF)
+        );
+        assoc_mac.set_transmit_client(association);
+        assoc_mac.set_receive_client(association);
+        assoc_alarm.set_client(association);
+
+        association
+    }
+}
@@ -2,12 +2,12 @@
 //!
 //! This provides one Component, ButtonComponent, which implements a
 //! userspace syscall interface to the one imix on-board button (pin
-//! 24).
+//! 24). Presses are debounced for 20 ms.
 //!
 //! Usage
 //! -----
 //! ```rust
-//! let button = ButtonComponent::new().finalize();
+//! let button = ButtonComponent::new(board_kernel, mux_alarm).finalize();
 //! ```
 
 // Author: Philip Levis <pal@cs.stanford.edu>
@@ -16,6 +16,7 @@
 #![allow(dead_code)] // Components are intended to be conditionally included
 
 use capsules::button;
+use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use kernel;
 use kernel::capabilities;
 use kernel::component::Component;
@@ -23,18 +24,27 @@ use sam4l;
 
 pub struct ButtonComponent {
     board_kernel: &'static kernel::Kernel,
+    alarm_mux: &'static MuxAlarm<'static, sam4l::ast::Ast<'static>>,
 }
 
 impl ButtonComponent {
-    pub fn new(board_kernel: &'static kernel::Kernel) -> ButtonComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        alarm: &'static MuxAlarm<'static, sam4l::ast::Ast<'static>>,
+    ) -> ButtonComponent {
         ButtonComponent {
             board_kernel: board_kernel,
+            alarm_mux: alarm,
         }
     }
 }
 
 impl Component for ButtonComponent {
-    type Output = &'static button::Button<'static, sam4l::gpio::GPIOPin>;
+    type Output = &'static button::Button<
+        'static,
+        sam4l::gpio::GPIOPin,
+        VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>,
+    >;
 
     unsafe fn finalize(&mut self) -> Self::Output {
         let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
@@ -44,13 +54,27 @@ impl Component for ButtonComponent {
             [(&sam4l::gpio::PC[24], button::GpioMode::LowWhenPressed)]
         );
 
+        let button_alarm = static_init!(
+            VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+            VirtualMuxAlarm::new(self.alarm_mux)
+        );
         let button = static_init!(
-            button::Button<'static, sam4l::gpio::GPIOPin>,
-            button::Button::new(button_pins, self.board_kernel.create_grant(&grant_cap))
+            button::Button<
+                'static,
+                sam4l::gpio::GPIOPin,
+                VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>,
+            >,
+            button::Button::new(
+                button_pins,
+                button_alarm,
+                20,
+                self.board_kernel.create_grant(&grant_cap)
+            )
         );
         for &(btn, _) in button_pins.iter() {
             btn.set_client(button);
         }
+        button_alarm.set_client(button);
 
         button
     }
@@ -66,6 +66,8 @@ impl Component for ConsoleComponent {
         );
         hil::uart::UART::set_client(console_uart, console);
         console.initialize();
+        console.enable_line_mode(&mut console::LINE_BUF);
+        console.enable_echo(&mut console::ECHO_BUF);
 
         // Create virtual device for kernel debug.
         let debugger_uart = static_init!(UartDevice, UartDevice::new(self.uart_mux, false));
@@ -9,7 +9,7 @@
 //! Usage
 //! -----
 //! ```rust
-//! let gpio = GpioComponent::new().finalize();
+//! let gpio = GpioComponent::new(mux_alarm).finalize();
 //! ```
 
 // Author: Philip Levis <pal@cs.stanford.edu>
@@ -18,19 +18,27 @@
 #![allow(dead_code)] // Components are intended to be conditionally included
 
 use capsules::gpio;
+use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use kernel::component::Component;
+use kernel::hil::gpio::PinGroup;
 use sam4l;
 
-pub struct GpioComponent {}
+pub struct GpioComponent {
+    alarm_mux: &'static MuxAlarm<'static, sam4l::ast::Ast<'static>>,
+}
 
 impl GpioComponent {
-    pub fn new() -> GpioComponent {
-        GpioComponent {}
+    pub fn new(alarm: &'static MuxAlarm<'static, sam4l::ast::Ast<'static>>) -> GpioComponent {
+        GpioComponent { alarm_mux: alarm }
     }
 }
 
 impl Component for GpioComponent {
-    type Output = &'static gpio::GPIO<'static, sam4l::gpio::GPIOPin>;
+    type Output = &'static gpio::GPIO<
+        'static,
+        sam4l::gpio::GPIOPin,
+        VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>,
+    >;
 
     unsafe fn finalize(&mut self) -> Self::Output {
         let gpio_pins = static_init!(
@@ -46,13 +54,20 @@ impl Component for GpioComponent {
             ]
         );
 
+        let gpio_alarm = static_init!(
+            VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+            VirtualMuxAlarm::new(self.alarm_mux)
+        );
+        let gpio_pin_group = static_init!(
+            PinGroup<'static, sam4l::gpio::GPIOPin>,
+            PinGroup::new(gpio_pins)
+        );
         let gpio = static_init!(
-            gpio::GPIO<'static, sam4l::gpio::GPIOPin>,
-            gpio::GPIO::new(gpio_pins)
+            gpio::GPIO<'static, sam4l::gpio::GPIOPin, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
+            gpio::GPIO::new(gpio_pin_group, gpio_alarm)
         );
-        for pin in gpio_pins.iter() {
-            pin.set_client(gpio);
-        }
+        gpio_pin_group.set_client(gpio);
+        gpio_alarm.set_client(gpio);
 
         gpio
     }
@@ -16,6 +16,7 @@
 
 use capsules::led;
 use kernel::component::Component;
+use kernel::hil::led::{Led, LedHigh};
 use sam4l;
 
 pub struct LedComponent {}
@@ -27,20 +28,21 @@ impl LedComponent {
 }
 
 impl Component for LedComponent {
-    type Output = &'static led::LED<'static, sam4l::gpio::GPIOPin>;
+    type Output = &'static led::LED<'static>;
 
     unsafe fn finalize(&mut self) -> Self::Output {
         let led_pins = static_init!(
-            [(&'static sam4l::gpio::GPIOPin, led::ActivationMode); 2],
+            [LedHigh<'static>; 2],
             [
-                (&sam4l::gpio::PC[22], led::ActivationMode::ActiveHigh),
-                (&sam4l::gpio::PC[10], led::ActivationMode::ActiveHigh),
+                LedHigh::new(&sam4l::gpio::PC[22]),
+                LedHigh::new(&sam4l::gpio::PC[10]),
             ]
         );
-        let led = static_init!(
-            led::LED<'static, sam4l::gpio::GPIOPin>,
-            led::LED::new(led_pins)
+        let leds = static_init!(
+            [&'static Led; 2],
+            [&led_pins[0], &led_pins[1]]
         );
+        let led = static_init!(led::LED<'static>, led::LED::new(leds));
         led
     }
 }
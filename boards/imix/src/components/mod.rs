@@ -1,6 +1,7 @@
 pub mod adc;
 pub mod alarm;
 pub mod analog_comparator;
+pub mod association;
 pub mod button;
 pub mod console;
 pub mod crc;
@@ -11,6 +12,7 @@ pub mod led;
 pub mod nonvolatile_storage;
 pub mod nrf51822;
 pub mod process_console;
+pub mod process_supervisor;
 pub mod radio;
 pub mod rf233;
 pub mod rng;
@@ -22,6 +24,7 @@ pub mod usb;
 pub use self::adc::AdcComponent;
 pub use self::alarm::AlarmDriverComponent;
 pub use self::analog_comparator::AcComponent;
+pub use self::association::AssociationComponent;
 pub use self::button::ButtonComponent;
 pub use self::console::ConsoleComponent;
 pub use self::crc::CrcComponent;
@@ -32,6 +35,7 @@ pub use self::led::LedComponent;
 pub use self::nonvolatile_storage::NonvolatileStorageComponent;
 pub use self::nrf51822::Nrf51822Component;
 pub use self::process_console::ProcessConsoleComponent;
+pub use self::process_supervisor::ProcessSupervisorComponent;
 pub use self::radio::RadioComponent;
 pub use self::rf233::RF233Component;
 pub use self::rng::RngComponent;
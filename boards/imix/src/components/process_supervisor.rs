@@ -0,0 +1,55 @@
+//! Component for the process supervisor syscall interface on imix board.
+//!
+//! This provides one Component, ProcessSupervisorComponent, which lets the
+//! app in one process slot terminate, restart and inspect the others.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let process_supervisor = ProcessSupervisorComponent::new(board_kernel, 0).finalize();
+//! ```
+
+#![allow(dead_code)] // Components are intended to be conditionally included
+
+use capsules::process_supervisor;
+use kernel;
+use kernel::capabilities;
+use kernel::component::Component;
+
+pub struct ProcessSupervisorComponent {
+    board_kernel: &'static kernel::Kernel,
+    supervisor_index: usize,
+}
+
+impl ProcessSupervisorComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        supervisor_index: usize,
+    ) -> ProcessSupervisorComponent {
+        ProcessSupervisorComponent {
+            board_kernel: board_kernel,
+            supervisor_index: supervisor_index,
+        }
+    }
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+impl Component for ProcessSupervisorComponent {
+    type Output = &'static process_supervisor::ProcessSupervisor<Capability>;
+
+    unsafe fn finalize(&mut self) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        static_init!(
+            process_supervisor::ProcessSupervisor<Capability>,
+            process_supervisor::ProcessSupervisor::new(
+                self.board_kernel,
+                self.supervisor_index,
+                self.board_kernel.create_grant(&grant_cap),
+                Capability,
+            )
+        )
+    }
+}
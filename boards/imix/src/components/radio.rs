@@ -7,7 +7,8 @@
 //! Usage
 //! -----
 //! ```rust
-//! let (radio_driver, mux_mac) = RadioComponent::new(rf233, PAN_ID, 0x1008).finalize();
+//! let (radio_driver, mux_mac) =
+//!     RadioComponent::new(board_kernel, rf233, PAN_ID, 0x1008, eui64).finalize();
 //! ```
 
 // Author: Philip Levis <pal@cs.stanford.edu>
@@ -38,6 +39,7 @@ pub struct RadioComponent {
     rf233: &'static RF233Device,
     pan_id: capsules::net::ieee802154::PanID,
     short_addr: u16,
+    long_addr: [u8; 8],
 }
 
 impl RadioComponent {
@@ -46,12 +48,14 @@ impl RadioComponent {
         rf233: &'static RF233Device,
         pan_id: capsules::net::ieee802154::PanID,
         addr: u16,
+        long_addr: [u8; 8],
     ) -> RadioComponent {
         RadioComponent {
             board_kernel: board_kernel,
             rf233: rf233,
             pan_id: pan_id,
             short_addr: addr,
+            long_addr: long_addr,
         }
     }
 }
@@ -131,6 +135,7 @@ impl Component for RadioComponent {
         radio_mac.set_receive_client(radio_driver);
         radio_mac.set_pan(self.pan_id);
         radio_mac.set_address(self.short_addr);
+        radio_mac.set_address_long(self.long_addr);
 
         (radio_driver, mux_mac)
     }
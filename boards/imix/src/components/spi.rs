@@ -9,7 +9,7 @@
 //! Usage
 //! -----
 //! ```rust
-//! let spi_syscalls = SpiSyscallComponent::new(mux_spi).finalize();
+//! let spi_syscalls = SpiSyscallComponent::new(board_kernel, mux_spi).finalize();
 //! let rf233_spi = SpiComponent::new(mux_spi).finalize();
 //! ```
 
@@ -20,10 +20,13 @@
 
 use capsules::spi::Spi;
 use capsules::virtual_spi::{MuxSpiMaster, VirtualSpiMasterDevice};
+use kernel;
+use kernel::capabilities;
 use kernel::component::Component;
 use sam4l;
 
 pub struct SpiSyscallComponent {
+    board_kernel: &'static kernel::Kernel,
     spi_mux: &'static MuxSpiMaster<'static, sam4l::spi::SpiHw>,
 }
 
@@ -32,8 +35,14 @@ pub struct SpiComponent {
 }
 
 impl SpiSyscallComponent {
-    pub fn new(mux: &'static MuxSpiMaster<'static, sam4l::spi::SpiHw>) -> Self {
-        SpiSyscallComponent { spi_mux: mux }
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        mux: &'static MuxSpiMaster<'static, sam4l::spi::SpiHw>,
+    ) -> Self {
+        SpiSyscallComponent {
+            board_kernel: board_kernel,
+            spi_mux: mux,
+        }
     }
 }
 
@@ -41,6 +50,8 @@ impl Component for SpiSyscallComponent {
     type Output = &'static Spi<'static, VirtualSpiMasterDevice<'static, sam4l::spi::SpiHw>>;
 
     unsafe fn finalize(&mut self) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
         let syscall_spi_device = static_init!(
             VirtualSpiMasterDevice<'static, sam4l::spi::SpiHw>,
             VirtualSpiMasterDevice::new(self.spi_mux, 3)
@@ -48,7 +59,7 @@ impl Component for SpiSyscallComponent {
 
         let spi_syscalls = static_init!(
             Spi<'static, VirtualSpiMasterDevice<'static, sam4l::spi::SpiHw>>,
-            Spi::new(syscall_spi_device)
+            Spi::new(syscall_spi_device, self.board_kernel.create_grant(&grant_cap))
         );
 
         static mut SPI_READ_BUF: [u8; 1024] = [0; 1024];
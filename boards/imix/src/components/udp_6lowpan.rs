@@ -114,7 +114,8 @@ impl Component for UDPComponent {
                     id: 0,
                     compress: false,
                 },
-                &sam4l::ast::AST
+                &sam4l::ast::AST,
+                sixlowpan_state::DEFAULT_FRAG_TIMEOUT
             )
         );
 
@@ -173,6 +174,9 @@ impl Component for UDPComponent {
             capsules::net::ipv6::ipv6_recv::IP6RecvStruct::new()
         );
         sixlowpan_state.set_rx_client(ip_receive);
+        for addr in self.interface_list.iter() {
+            ip_receive.join_solicited_node(*addr);
+        }
 
         let udp_recv = static_init!(UDPReceiver<'static>, UDPReceiver::new());
         ip_receive.set_client(udp_recv);
@@ -37,7 +37,9 @@ use capsules::net::ipv6::ip_utils::IPAddr;
 use capsules::net::ipv6::ipv6::{IP6Packet, IPPayload, TransportHeader};
 use capsules::net::ipv6::ipv6_send::{IP6SendStruct, IP6Sender};
 use capsules::net::sixlowpan::sixlowpan_compression;
-use capsules::net::sixlowpan::sixlowpan_state::{Sixlowpan, SixlowpanState, TxState};
+use capsules::net::sixlowpan::sixlowpan_state::{
+    Sixlowpan, SixlowpanState, TxState, DEFAULT_FRAG_TIMEOUT,
+};
 use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use core::cell::Cell;
 use kernel::hil::radio;
@@ -90,7 +92,8 @@ pub unsafe fn initialize_all(
                 id: 0,
                 compress: false,
             },
-            &sam4l::ast::AST
+            &sam4l::ast::AST,
+            DEFAULT_FRAG_TIMEOUT
         )
     );
 
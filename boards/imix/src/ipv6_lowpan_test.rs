@@ -47,7 +47,7 @@ use capsules::net::ipv6::ip_utils::{ip6_nh, IPAddr};
 use capsules::net::ipv6::ipv6::{IP6Header, IP6Packet, IPPayload, TransportHeader};
 use capsules::net::sixlowpan::sixlowpan_compression;
 use capsules::net::sixlowpan::sixlowpan_state::{
-    RxState, Sixlowpan, SixlowpanRxClient, SixlowpanState, TxState,
+    RxState, Sixlowpan, SixlowpanRxClient, SixlowpanState, TxState, DEFAULT_FRAG_TIMEOUT,
 };
 use capsules::net::udp::udp::UDPHeader;
 use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
@@ -160,7 +160,8 @@ pub unsafe fn initialize_all(
                 id: 0,
                 compress: false,
             },
-            &sam4l::ast::AST
+            &sam4l::ast::AST,
+            DEFAULT_FRAG_TIMEOUT
         )
     );
 
@@ -44,6 +44,7 @@ use kernel::hil::Controller;
 use components::adc::AdcComponent;
 use components::alarm::AlarmDriverComponent;
 use components::analog_comparator::AcComponent;
+use components::association::AssociationComponent;
 use components::button::ButtonComponent;
 use components::console::ConsoleComponent;
 use components::crc::CrcComponent;
@@ -54,6 +55,7 @@ use components::led::LedComponent;
 use components::nonvolatile_storage::NonvolatileStorageComponent;
 use components::nrf51822::Nrf51822Component;
 use components::process_console::ProcessConsoleComponent;
+use components::process_supervisor::ProcessSupervisorComponent;
 use components::radio::RadioComponent;
 use components::rf233::RF233Component;
 use components::rng::RngComponent;
@@ -108,6 +110,12 @@ const NUM_PROCS: usize = 2;
 // onto each device. This makes MAC address configuration a good target for capabilities -
 // only allow one app per board to have control of MAC address configuration?
 const RADIO_CHANNEL: u8 = 26;
+// The channels and transmit power the radio may use in the region the board is
+// deployed in.
+const RADIO_LIMITS: radio::RegulatoryLimits = radio::RegulatoryLimits {
+    channels: radio::CHANNELS_2450MHZ,
+    max_tx_power: 4,
+};
 const DST_MAC_ADDR: MacAddress = MacAddress::Short(57330);
 const DEFAULT_CTX_PREFIX_LEN: u8 = 8; //Length of context for 6LoWPAN compression
 const DEFAULT_CTX_PREFIX: [u8; 16] = [0x0 as u8; 16]; //Context for 6LoWPAN Compression
@@ -133,14 +141,22 @@ struct Imix {
         components::process_console::Capability,
     >,
     console: &'static capsules::console::Console<'static, UartDevice<'static>>,
-    gpio: &'static capsules::gpio::GPIO<'static, sam4l::gpio::GPIOPin>,
+    gpio: &'static capsules::gpio::GPIO<
+        'static,
+        sam4l::gpio::GPIOPin,
+        VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>,
+    >,
     alarm: &'static AlarmDriver<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>>,
     temp: &'static capsules::temperature::TemperatureSensor<'static>,
     humidity: &'static capsules::humidity::HumiditySensor<'static>,
     ambient_light: &'static capsules::ambient_light::AmbientLight<'static>,
     adc: &'static capsules::adc::Adc<'static, sam4l::adc::Adc>,
-    led: &'static capsules::led::LED<'static, sam4l::gpio::GPIOPin>,
-    button: &'static capsules::button::Button<'static, sam4l::gpio::GPIOPin>,
+    led: &'static capsules::led::LED<'static>,
+    button: &'static capsules::button::Button<
+        'static,
+        sam4l::gpio::GPIOPin,
+        VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>,
+    >,
     rng: &'static capsules::rng::RngDriver<'static>,
     analog_comparator: &'static capsules::analog_comparator::AnalogComparator<
         'static,
@@ -161,6 +177,9 @@ struct Imix {
         sam4l::usart::USART,
     >,
     nonvolatile_storage: &'static capsules::nonvolatile_storage_driver::NonvolatileStorage<'static>,
+    process_supervisor: &'static capsules::process_supervisor::ProcessSupervisor<
+        components::process_supervisor::Capability,
+    >,
 }
 
 // The RF233 radio stack requires our buffers for its SPI operations:
@@ -201,6 +220,7 @@ impl kernel::Platform for Imix {
             capsules::nrf51822_serialization::DRIVER_NUM => f(Some(self.nrf51822)),
             capsules::nonvolatile_storage_driver::DRIVER_NUM => f(Some(self.nonvolatile_storage)),
             capsules::rng::DRIVER_NUM => f(Some(self.rng)),
+            capsules::process_supervisor::DRIVER_NUM => f(Some(self.process_supervisor)),
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
             _ => f(None),
         }
@@ -360,7 +380,7 @@ pub unsafe fn reset_handler() {
     sam4l::spi::SPI.set_client(mux_spi);
     sam4l::spi::SPI.init();
 
-    let spi_syscalls = SpiSyscallComponent::new(mux_spi).finalize();
+    let spi_syscalls = SpiSyscallComponent::new(board_kernel, mux_spi).finalize();
     let rf233_spi = SpiComponent::new(mux_spi).finalize();
     let rf233 = RF233Component::new(
         rf233_spi,
@@ -376,9 +396,9 @@ pub unsafe fn reset_handler() {
     // sam4l::gpio::PC[16].clear();
 
     let adc = AdcComponent::new().finalize();
-    let gpio = GpioComponent::new().finalize();
+    let gpio = GpioComponent::new(mux_alarm).finalize();
     let led = LedComponent::new().finalize();
-    let button = ButtonComponent::new(board_kernel).finalize();
+    let button = ButtonComponent::new(board_kernel, mux_alarm).finalize();
     let crc = CrcComponent::new(board_kernel).finalize();
     let analog_comparator = AcComponent::new().finalize();
     let rng = RngComponent::new(board_kernel).finalize();
@@ -390,14 +410,22 @@ pub unsafe fn reset_handler() {
     let serial_num_bottom_16 = (serial_num.get_lower_64() & 0x0000_0000_0000_ffff) as u16;
 
     let src_mac_from_serial_num: MacAddress = MacAddress::Short(serial_num_bottom_16);
+    let eui64 = capsules::ieee802154::mac::eui64_from_device_id(&serial_num);
 
     // Can this initialize be pushed earlier, or into component? -pal
     rf233.initialize(&mut RF233_BUF, &mut RF233_REG_WRITE, &mut RF233_REG_READ);
+    let limits_result = rf233.set_regulatory_limits(RADIO_LIMITS);
+    if limits_result != kernel::ReturnCode::SUCCESS {
+        panic!("Failed to apply radio regulatory limits: {:?}", limits_result);
+    }
     let (radio_driver, mux_mac) =
-        RadioComponent::new(board_kernel, rf233, PAN_ID, serial_num_bottom_16).finalize();
+        RadioComponent::new(board_kernel, rf233, PAN_ID, serial_num_bottom_16, eui64).finalize();
+    let _association = AssociationComponent::new(mux_mac, mux_alarm).finalize();
 
     let usb_driver = UsbComponent::new(board_kernel).finalize();
     let nonvolatile_storage = NonvolatileStorageComponent::new(board_kernel).finalize();
+    // The first app in flash supervises the others.
+    let process_supervisor = ProcessSupervisorComponent::new(board_kernel, 0).finalize();
 
     let local_ip_ifaces = static_init!(
         [IPAddr; 3],
@@ -447,6 +475,7 @@ pub unsafe fn reset_handler() {
         usb_driver,
         nrf51822: nrf_serialization,
         nonvolatile_storage: nonvolatile_storage,
+        process_supervisor: process_supervisor,
     };
 
     let chip = static_init!(sam4l::chip::Sam4l, sam4l::chip::Sam4l::new());
@@ -41,7 +41,7 @@ pub unsafe extern "C" fn panic_fmt(pi: &PanicInfo) -> ! {
     // 6 = Red led, 7 = Green led
     const LED_PIN: usize = 6;
 
-    let led = &mut led::LedLow::new(&mut cc26x2::gpio::PORT[LED_PIN]);
+    let led = &mut led::LedLow::new(&cc26x2::gpio::PORT[LED_PIN]);
     let writer = &mut WRITER;
     debug::panic(&mut [led], writer, pi, &cortexm4::support::nop, &PROCESSES)
 }
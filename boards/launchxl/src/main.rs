@@ -20,6 +20,7 @@ use kernel::hil;
 use kernel::hil::entropy::Entropy32;
 use kernel::hil::i2c::I2CMaster;
 use kernel::hil::rng::Rng;
+use kernel::hil::time::MultiAlarm;
 
 #[macro_use]
 pub mod io;
@@ -46,14 +47,22 @@ static mut APP_MEMORY: [u8; 0xA000] = [0; 0xA000];
 pub static mut STACK_MEMORY: [u8; 0x1000] = [0; 0x1000];
 
 pub struct Platform {
-    gpio: &'static capsules::gpio::GPIO<'static, cc26x2::gpio::GPIOPin>,
-    led: &'static capsules::led::LED<'static, cc26x2::gpio::GPIOPin>,
+    gpio: &'static capsules::gpio::GPIO<
+        'static,
+        cc26x2::gpio::GPIOPin,
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, cc26x2::rtc::Rtc>,
+    >,
+    led: &'static capsules::led::LED<'static>,
     console: &'static capsules::console::Console<'static, UartDevice<'static>>,
-    button: &'static capsules::button::Button<'static, cc26x2::gpio::GPIOPin>,
-    alarm: &'static capsules::alarm::AlarmDriver<
+    button: &'static capsules::button::Button<
         'static,
+        cc26x2::gpio::GPIOPin,
         capsules::virtual_alarm::VirtualMuxAlarm<'static, cc26x2::rtc::Rtc>,
     >,
+    alarm: &'static capsules::alarm::AlarmDriver<
+        'static,
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, cc26x2::rtc::RtcChannel>,
+    >,
     rng: &'static capsules::rng::RngDriver<'static>,
     i2c_master: &'static capsules::i2c_master::I2CMasterDriver<cc26x2::i2c::I2CMaster<'static>>,
 }
@@ -124,24 +133,21 @@ pub unsafe fn reset_handler() {
 
     // LEDs
     let led_pins = static_init!(
-        [(
-            &'static cc26x2::gpio::GPIOPin,
-            capsules::led::ActivationMode
-        ); 2],
+        [kernel::hil::led::LedHigh<'static>; 2],
         [
-            (
-                &cc26x2::gpio::PORT[PIN_FN::RED_LED as usize],
-                capsules::led::ActivationMode::ActiveHigh
-            ), // Red
-            (
-                &cc26x2::gpio::PORT[PIN_FN::GREEN_LED as usize],
-                capsules::led::ActivationMode::ActiveHigh
-            ), // Green
+            // Red
+            kernel::hil::led::LedHigh::new(&cc26x2::gpio::PORT[PIN_FN::RED_LED as usize]),
+            // Green
+            kernel::hil::led::LedHigh::new(&cc26x2::gpio::PORT[PIN_FN::GREEN_LED as usize]),
         ]
     );
+    let leds = static_init!(
+        [&'static kernel::hil::led::Led; 2],
+        [&led_pins[0], &led_pins[1]]
+    );
     let led = static_init!(
-        capsules::led::LED<'static, cc26x2::gpio::GPIOPin>,
-        capsules::led::LED::new(led_pins)
+        capsules::led::LED<'static>,
+        capsules::led::LED::new(leds)
     );
 
     // BUTTONS
@@ -158,17 +164,6 @@ pub unsafe fn reset_handler() {
             ), // Button 2
         ]
     );
-    let button = static_init!(
-        capsules::button::Button<'static, cc26x2::gpio::GPIOPin>,
-        capsules::button::Button::new(
-            button_pins,
-            board_kernel.create_grant(&memory_allocation_capability)
-        )
-    );
-    for &(btn, _) in button_pins.iter() {
-        btn.set_client(button);
-    }
-
     // UART
 
     // Create a shared UART channel for the console and for kernel debug.
@@ -234,6 +229,37 @@ pub unsafe fn reset_handler() {
     cc26x2::i2c::I2C0.set_client(i2c_master);
     cc26x2::i2c::I2C0.enable();
 
+    let rtc = &cc26x2::rtc::RTC;
+    rtc.start();
+
+    let mux_alarm = static_init!(
+        capsules::virtual_alarm::MuxAlarm<'static, cc26x2::rtc::Rtc>,
+        capsules::virtual_alarm::MuxAlarm::new(&cc26x2::rtc::RTC)
+    );
+    rtc.set_client(mux_alarm);
+
+    let button_alarm = static_init!(
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, cc26x2::rtc::Rtc>,
+        capsules::virtual_alarm::VirtualMuxAlarm::new(mux_alarm)
+    );
+    let button = static_init!(
+        capsules::button::Button<
+            'static,
+            cc26x2::gpio::GPIOPin,
+            capsules::virtual_alarm::VirtualMuxAlarm<'static, cc26x2::rtc::Rtc>,
+        >,
+        capsules::button::Button::new(
+            button_pins,
+            button_alarm,
+            0,
+            board_kernel.create_grant(&memory_allocation_capability)
+        )
+    );
+    for &(btn, _) in button_pins.iter() {
+        btn.set_client(button);
+    }
+    button_alarm.set_client(button);
+
     // Setup for remaining GPIO pins
     let gpio_pins = static_init!(
         [&'static cc26x2::gpio::GPIOPin; 1],
@@ -243,34 +269,60 @@ pub unsafe fn reset_handler() {
             &cc26x2::gpio::PORT[PIN_FN::GPIO0 as usize],
         ]
     );
+    let gpio_alarm = static_init!(
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, cc26x2::rtc::Rtc>,
+        capsules::virtual_alarm::VirtualMuxAlarm::new(mux_alarm)
+    );
+    let gpio_pin_group = static_init!(
+        kernel::hil::gpio::PinGroup<'static, cc26x2::gpio::GPIOPin>,
+        kernel::hil::gpio::PinGroup::new(gpio_pins)
+    );
     let gpio = static_init!(
-        capsules::gpio::GPIO<'static, cc26x2::gpio::GPIOPin>,
-        capsules::gpio::GPIO::new(gpio_pins)
+        capsules::gpio::GPIO<
+            'static,
+            cc26x2::gpio::GPIOPin,
+            capsules::virtual_alarm::VirtualMuxAlarm<'static, cc26x2::rtc::Rtc>,
+        >,
+        capsules::gpio::GPIO::new(gpio_pin_group, gpio_alarm)
     );
-    for pin in gpio_pins.iter() {
-        pin.set_client(gpio);
-    }
-
-    let rtc = &cc26x2::rtc::RTC;
-    rtc.start();
+    gpio_pin_group.set_client(gpio);
+    gpio_alarm.set_client(gpio);
+
+    // Userspace alarms get their own RTC compare channel, so the kernel's
+    // alarms on channel 1 are not rescanned each time an app alarm fires.
+    let app_alarm_channel = rtc.compare(2).unwrap();
+    let app_mux_alarm = static_init!(
+        capsules::virtual_alarm::MuxAlarm<'static, cc26x2::rtc::RtcChannel>,
+        capsules::virtual_alarm::MuxAlarm::new(app_alarm_channel)
+    );
+    app_alarm_channel.set_client(app_mux_alarm);
 
-    let mux_alarm = static_init!(
-        capsules::virtual_alarm::MuxAlarm<'static, cc26x2::rtc::Rtc>,
-        capsules::virtual_alarm::MuxAlarm::new(&cc26x2::rtc::RTC)
+    let wrap_alarm = static_init!(
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, cc26x2::rtc::RtcChannel>,
+        capsules::virtual_alarm::VirtualMuxAlarm::new(app_mux_alarm)
     );
-    rtc.set_client(mux_alarm);
+    let wrap_counter = static_init!(
+        capsules::alarm::WrapCounter<
+            'static,
+            capsules::virtual_alarm::VirtualMuxAlarm<'static, cc26x2::rtc::RtcChannel>,
+        >,
+        capsules::alarm::WrapCounter::new(wrap_alarm)
+    );
+    wrap_alarm.set_client(wrap_counter);
+    wrap_counter.start();
 
     let virtual_alarm1 = static_init!(
-        capsules::virtual_alarm::VirtualMuxAlarm<'static, cc26x2::rtc::Rtc>,
-        capsules::virtual_alarm::VirtualMuxAlarm::new(mux_alarm)
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, cc26x2::rtc::RtcChannel>,
+        capsules::virtual_alarm::VirtualMuxAlarm::new(app_mux_alarm)
     );
     let alarm = static_init!(
         capsules::alarm::AlarmDriver<
             'static,
-            capsules::virtual_alarm::VirtualMuxAlarm<'static, cc26x2::rtc::Rtc>,
+            capsules::virtual_alarm::VirtualMuxAlarm<'static, cc26x2::rtc::RtcChannel>,
         >,
         capsules::alarm::AlarmDriver::new(
             virtual_alarm1,
+            wrap_counter,
             board_kernel.create_grant(&memory_allocation_capability)
         )
     );
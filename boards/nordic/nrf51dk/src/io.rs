@@ -44,7 +44,7 @@ impl Write for Writer {
 pub unsafe extern "C" fn panic_fmt(pi: &PanicInfo) -> ! {
     // The nRF51 DK LEDs (see back of board)
     const LED1_PIN: usize = 21;
-    let led = &mut led::LedLow::new(&mut nrf5x::gpio::PORT[LED1_PIN]);
+    let led = &mut led::LedLow::new(&nrf5x::gpio::PORT[LED1_PIN]);
     let writer = &mut WRITER;
     debug::panic(&mut [led], writer, pi, &cortexm0::support::nop, &PROCESSES)
 }
@@ -67,6 +67,7 @@ use capsules::virtual_uart::{UartDevice, UartMux};
 use kernel::capabilities;
 use kernel::hil;
 use kernel::hil::entropy::Entropy32;
+use kernel::hil::power_manager::PowerManager;
 use kernel::hil::rng::Rng;
 use kernel::hil::uart::UART;
 use kernel::{Chip, SysTick};
@@ -116,10 +117,18 @@ pub struct Platform {
         nrf51::radio::Radio,
         VirtualMuxAlarm<'static, Rtc>,
     >,
-    button: &'static capsules::button::Button<'static, nrf5x::gpio::GPIOPin>,
+    button: &'static capsules::button::Button<
+        'static,
+        nrf5x::gpio::GPIOPin,
+        VirtualMuxAlarm<'static, Rtc>,
+    >,
     console: &'static capsules::console::Console<'static, UartDevice<'static>>,
-    gpio: &'static capsules::gpio::GPIO<'static, nrf5x::gpio::GPIOPin>,
-    led: &'static capsules::led::LED<'static, nrf5x::gpio::GPIOPin>,
+    gpio: &'static capsules::gpio::GPIO<
+        'static,
+        nrf5x::gpio::GPIOPin,
+        VirtualMuxAlarm<'static, Rtc>,
+    >,
+    led: &'static capsules::led::LED<'static>,
     temp: &'static capsules::temperature::TemperatureSensor<'static>,
     alarm: &'static AlarmDriver<'static, VirtualMuxAlarm<'static, Rtc>>,
     rng: &'static capsules::rng::RngDriver<'static>,
@@ -161,29 +170,21 @@ pub unsafe fn reset_handler() {
 
     // LEDs
     let led_pins = static_init!(
-        [(&'static nrf5x::gpio::GPIOPin, capsules::led::ActivationMode); 4],
+        [kernel::hil::led::LedLow<'static>; 4],
         [
-            (
-                &nrf5x::gpio::PORT[LED1_PIN],
-                capsules::led::ActivationMode::ActiveLow
-            ), // 21
-            (
-                &nrf5x::gpio::PORT[LED2_PIN],
-                capsules::led::ActivationMode::ActiveLow
-            ), // 22
-            (
-                &nrf5x::gpio::PORT[LED3_PIN],
-                capsules::led::ActivationMode::ActiveLow
-            ), // 23
-            (
-                &nrf5x::gpio::PORT[LED4_PIN],
-                capsules::led::ActivationMode::ActiveLow
-            ), // 24
+            kernel::hil::led::LedLow::new(&nrf5x::gpio::PORT[LED1_PIN]), // 21
+            kernel::hil::led::LedLow::new(&nrf5x::gpio::PORT[LED2_PIN]), // 22
+            kernel::hil::led::LedLow::new(&nrf5x::gpio::PORT[LED3_PIN]), // 23
+            kernel::hil::led::LedLow::new(&nrf5x::gpio::PORT[LED4_PIN]), // 24
         ]
     );
+    let leds = static_init!(
+        [&'static kernel::hil::led::Led; 4],
+        [&led_pins[0], &led_pins[1], &led_pins[2], &led_pins[3]]
+    );
     let led = static_init!(
-        capsules::led::LED<'static, nrf5x::gpio::GPIOPin>,
-        capsules::led::LED::new(led_pins)
+        capsules::led::LED<'static>,
+        capsules::led::LED::new(leds)
     );
 
     let button_pins = static_init!(
@@ -207,19 +208,6 @@ pub unsafe fn reset_handler() {
             ), // 20
         ]
     );
-    let button = static_init!(
-        capsules::button::Button<'static, nrf5x::gpio::GPIOPin>,
-        capsules::button::Button::new(
-            button_pins,
-            board_kernel.create_grant(&memory_allocation_capability)
-        )
-    );
-    for &(btn, _) in button_pins.iter() {
-        use kernel::hil::gpio::PinCtl;
-        btn.set_input_mode(kernel::hil::gpio::InputMode::PullUp);
-        btn.set_client(button);
-    }
-
     let gpio_pins = static_init!(
         [&'static nrf5x::gpio::GPIOPin; 11],
         [
@@ -244,14 +232,6 @@ pub unsafe fn reset_handler() {
         Some(&nrf5x::gpio::PORT[LED3_PIN]),
     );
 
-    let gpio = static_init!(
-        capsules::gpio::GPIO<'static, nrf5x::gpio::GPIOPin>,
-        capsules::gpio::GPIO::new(gpio_pins)
-    );
-    for pin in gpio_pins.iter() {
-        pin.set_client(gpio);
-    }
-
     nrf51::uart::UART0.initialize(
         Pinmux::new(9),  /*. tx  */
         Pinmux::new(11), /* rx  */
@@ -311,6 +291,52 @@ pub unsafe fn reset_handler() {
     let mux_alarm = static_init!(MuxAlarm<'static, Rtc>, MuxAlarm::new(&RTC));
     rtc.set_client(mux_alarm);
 
+    let button_alarm = static_init!(
+        VirtualMuxAlarm<'static, Rtc>,
+        VirtualMuxAlarm::new(mux_alarm)
+    );
+    let button = static_init!(
+        capsules::button::Button<'static, nrf5x::gpio::GPIOPin, VirtualMuxAlarm<'static, Rtc>>,
+        capsules::button::Button::new(
+            button_pins,
+            button_alarm,
+            0,
+            board_kernel.create_grant(&memory_allocation_capability)
+        )
+    );
+    for &(btn, _) in button_pins.iter() {
+        use kernel::hil::gpio::PinCtl;
+        btn.set_input_mode(kernel::hil::gpio::InputMode::PullUp);
+        btn.set_client(button);
+    }
+    button_alarm.set_client(button);
+
+    let gpio_alarm = static_init!(
+        VirtualMuxAlarm<'static, Rtc>,
+        VirtualMuxAlarm::new(mux_alarm)
+    );
+    let gpio_pin_group = static_init!(
+        kernel::hil::gpio::PinGroup<'static, nrf5x::gpio::GPIOPin>,
+        kernel::hil::gpio::PinGroup::new(gpio_pins)
+    );
+    let gpio = static_init!(
+        capsules::gpio::GPIO<'static, nrf5x::gpio::GPIOPin, VirtualMuxAlarm<'static, Rtc>>,
+        capsules::gpio::GPIO::new(gpio_pin_group, gpio_alarm)
+    );
+    gpio_pin_group.set_client(gpio);
+    gpio_alarm.set_client(gpio);
+
+    let wrap_alarm = static_init!(
+        VirtualMuxAlarm<'static, Rtc>,
+        VirtualMuxAlarm::new(mux_alarm)
+    );
+    let wrap_counter = static_init!(
+        capsules::alarm::WrapCounter<'static, VirtualMuxAlarm<'static, Rtc>>,
+        capsules::alarm::WrapCounter::new(wrap_alarm)
+    );
+    wrap_alarm.set_client(wrap_counter);
+    wrap_counter.start();
+
     let virtual_alarm1 = static_init!(
         VirtualMuxAlarm<'static, Rtc>,
         VirtualMuxAlarm::new(mux_alarm)
@@ -319,6 +345,7 @@ pub unsafe fn reset_handler() {
         AlarmDriver<'static, VirtualMuxAlarm<'static, Rtc>>,
         AlarmDriver::new(
             virtual_alarm1,
+            wrap_counter,
             board_kernel.create_grant(&memory_allocation_capability)
         )
     );
@@ -329,14 +356,27 @@ pub unsafe fn reset_handler() {
         VirtualMuxAlarm::new(mux_alarm)
     );
 
+    // The temperature sensor is shared through a mux so that other capsules
+    // can read it alongside the syscall driver.
+    let mux_temp = static_init!(
+        capsules::virtual_temperature::MuxTemperature<'static>,
+        capsules::virtual_temperature::MuxTemperature::new(&nrf5x::temperature::TEMP)
+    );
+    kernel::hil::sensors::TemperatureDriver::set_client(&nrf5x::temperature::TEMP, mux_temp);
+
+    let virtual_temp = static_init!(
+        capsules::virtual_temperature::VirtualTemperature<'static>,
+        capsules::virtual_temperature::VirtualTemperature::new(mux_temp)
+    );
+    virtual_temp.setup();
     let temp = static_init!(
         capsules::temperature::TemperatureSensor<'static>,
         capsules::temperature::TemperatureSensor::new(
-            &mut nrf5x::temperature::TEMP,
+            virtual_temp,
             board_kernel.create_grant(&memory_allocation_capability)
         )
     );
-    kernel::hil::sensors::TemperatureDriver::set_client(&nrf5x::temperature::TEMP, temp);
+    kernel::hil::sensors::TemperatureDriver::set_client(virtual_temp, temp);
 
     let entropy_to_random = static_init!(
         capsules::rng::Entropy32ToRandom<'static>,
@@ -375,8 +415,10 @@ pub unsafe fn reset_handler() {
     );
     ble_radio_virtual_alarm.set_client(ble_radio);
 
-    // Start all of the clocks. Low power operation will require a better
-    // approach than this.
+    // Register the peripherals that constrain how deeply the chip may sleep
+    // when there is no work to do.
+    nrf5x::power::POWER.register(&nrf5x::timer::ALARM1);
+
     nrf51::clock::CLOCK.low_stop();
     nrf51::clock::CLOCK.high_stop();
 
@@ -44,7 +44,7 @@ impl Write for Writer {
 pub unsafe extern "C" fn panic_fmt(pi: &PanicInfo) -> ! {
     // The nRF52840DK LEDs (see back of board)
     const LED1_PIN: usize = 13;
-    let led = &mut led::LedLow::new(&mut nrf5x::gpio::PORT[LED1_PIN]);
+    let led = &mut led::LedLow::new(&nrf5x::gpio::PORT[LED1_PIN]);
     let writer = &mut WRITER;
     debug::panic(&mut [led], writer, pi, &cortexm4::support::nop, &PROCESSES)
 }
@@ -45,6 +45,9 @@ const SPI_MX25R6435F_CHIP_SELECT: usize = 17;
 const SPI_MX25R6435F_WRITE_PROTECT_PIN: usize = 22;
 const SPI_MX25R6435F_HOLD_PIN: usize = 23;
 
+// Edge capture input, on the Arduino header.
+const EDGE_CAPTURE_PIN: usize = 26;
+
 /// UART Writer
 #[macro_use]
 pub mod io;
@@ -95,26 +98,18 @@ pub unsafe fn reset_handler() {
 
     // LEDs
     let led_pins = static_init!(
-        [(&'static nrf5x::gpio::GPIOPin, capsules::led::ActivationMode); 4],
+        [kernel::hil::led::LedLow<'static>; 4],
         [
-            (
-                &nrf5x::gpio::PORT[LED1_PIN],
-                capsules::led::ActivationMode::ActiveLow
-            ),
-            (
-                &nrf5x::gpio::PORT[LED2_PIN],
-                capsules::led::ActivationMode::ActiveLow
-            ),
-            (
-                &nrf5x::gpio::PORT[LED3_PIN],
-                capsules::led::ActivationMode::ActiveLow
-            ),
-            (
-                &nrf5x::gpio::PORT[LED4_PIN],
-                capsules::led::ActivationMode::ActiveLow
-            ),
+            kernel::hil::led::LedLow::new(&nrf5x::gpio::PORT[LED1_PIN]),
+            kernel::hil::led::LedLow::new(&nrf5x::gpio::PORT[LED2_PIN]),
+            kernel::hil::led::LedLow::new(&nrf5x::gpio::PORT[LED3_PIN]),
+            kernel::hil::led::LedLow::new(&nrf5x::gpio::PORT[LED4_PIN]),
         ]
     );
+    let leds = static_init!(
+        [&'static kernel::hil::led::Led; 4],
+        [&led_pins[0], &led_pins[1], &led_pins[2], &led_pins[3]]
+    );
 
     let button_pins = static_init!(
         [(&'static nrf5x::gpio::GPIOPin, capsules::button::GpioMode); 4],
@@ -147,7 +142,7 @@ pub unsafe fn reset_handler() {
         LED1_PIN,
         LED2_PIN,
         LED3_PIN,
-        led_pins,
+        leds,
         &UartPins::new(UART_RTS, UART_TXD, UART_RXD, UART_CTS),
         &SpiPins::new(SPI_MOSI, SPI_MISO, SPI_CLK),
         &Some(SpiMX25R6435FPins::new(
@@ -155,6 +150,8 @@ pub unsafe fn reset_handler() {
             SPI_MX25R6435F_WRITE_PROTECT_PIN,
             SPI_MX25R6435F_HOLD_PIN,
         )),
+        None,
+        Some(EDGE_CAPTURE_PIN),
         button_pins,
         &mut APP_MEMORY,
         &mut PROCESSES,
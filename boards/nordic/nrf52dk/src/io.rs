@@ -1,12 +1,15 @@
 use core::fmt::Write;
 use core::panic::PanicInfo;
+use core::{ptr, slice};
 use cortexm4;
 use kernel::debug;
+use kernel::hil::flash;
 use kernel::hil::led;
 use kernel::hil::uart::{self, UART};
 use nrf52;
 use nrf5x;
 
+use CRASH_LOG;
 use PROCESSES;
 
 struct Writer {
@@ -37,6 +40,29 @@ impl Write for Writer {
     }
 }
 
+/// Page buffer that the crash record is formatted into during a panic.
+static mut CRASH_LOG_BUFFER: nrf52::nvmc::NrfPage = nrf52::nvmc::NrfPage::new();
+
+/// Print the crash record saved by the last panic, if there is one, and then
+/// erase it so that it is only reported once.
+pub unsafe fn print_crash_log() {
+    // The page is written behind the compiler's back, so read it through a
+    // pointer the compiler cannot see into rather than its initial value.
+    let page = ptr::read_volatile(&CRASH_LOG.0.as_ptr());
+    if let Some(record) = debug::crash_log_record(slice::from_raw_parts(page, 4096)) {
+        let writer = &mut WRITER;
+        let _ = writer.write_fmt(format_args!(
+            "\r\nCrash log from a previous boot:\r\n{}",
+            record
+        ));
+        // The NVMC erases the page before `erase_page` returns. No flash
+        // client is registered on this board, so the completion callback
+        // goes nowhere.
+        let crash_log_page = &CRASH_LOG as *const _ as usize / 4096;
+        flash::Flash::erase_page(&nrf52::nvmc::NVMC, crash_log_page);
+    }
+}
+
 #[cfg(not(test))]
 #[no_mangle]
 #[panic_implementation]
@@ -44,7 +70,21 @@ impl Write for Writer {
 pub unsafe extern "C" fn panic_fmt(pi: &PanicInfo) -> ! {
     // The nRF52 DK LEDs (see back of board)
     const LED1_PIN: usize = 17;
-    let led = &mut led::LedLow::new(&mut nrf5x::gpio::PORT[LED1_PIN]);
+    let led = &mut led::LedLow::new(&nrf5x::gpio::PORT[LED1_PIN]);
     let writer = &mut WRITER;
-    debug::panic(&mut [led], writer, pi, &cortexm4::support::nop, &PROCESSES)
+    debug::panic_begin(&cortexm4::support::nop);
+    debug::panic_banner(writer, pi);
+    debug::flush(writer);
+    debug::panic_process_info(&PROCESSES, writer);
+    // Save the crash record only after the console output, so that a fault
+    // while writing flash cannot lose the panic report on the UART.
+    let crash_log_page = &CRASH_LOG as *const _ as usize / 4096;
+    debug::panic_crash_log(
+        &nrf52::nvmc::NVMC,
+        crash_log_page,
+        &mut CRASH_LOG_BUFFER,
+        pi,
+        &PROCESSES,
+    );
+    debug::panic_blink_forever(&mut [led])
 }
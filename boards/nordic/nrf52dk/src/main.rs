@@ -62,7 +62,7 @@
 
 #![no_std]
 #![no_main]
-#![feature(panic_implementation)]
+#![feature(panic_implementation, used)]
 #![deny(missing_docs)]
 
 extern crate capsules;
@@ -98,6 +98,9 @@ const SPI_MOSI: usize = 22;
 const SPI_MISO: usize = 23;
 const SPI_CLK: usize = 24;
 
+// Edge counter input, on the top mid header.
+const EDGE_COUNTER_PIN: usize = 11;
+
 /// UART Writer
 #[macro_use]
 pub mod io;
@@ -121,6 +124,16 @@ static mut APP_MEMORY: [u8; 32768] = [0; 32768];
 static mut PROCESSES: [Option<&'static kernel::procs::ProcessType>; NUM_PROCS] =
     [None, None, None, None];
 
+/// A flash page, aligned so that it can be erased and written on its own.
+#[repr(align(4096))]
+pub struct CrashLogPage(pub [u8; 4096]);
+
+/// Flash page that the panic handler saves a crash record to. It reads as
+/// erased flash until the first panic.
+#[link_section = ".storage"]
+#[used]
+pub static CRASH_LOG: CrashLogPage = CrashLogPage([0xff; 4096]);
+
 /// Dummy buffer that causes the linker to reserve enough space for the stack.
 #[no_mangle]
 #[link_section = ".stack_buffer"]
@@ -132,9 +145,12 @@ pub unsafe fn reset_handler() {
     // Loads relocations and clears BSS
     nrf52::init();
 
+    // Report the crash record left by the previous boot, if any.
+    io::print_crash_log();
+
     // GPIOs
     let gpio_pins = static_init!(
-        [&'static nrf5x::gpio::GPIOPin; 12],
+        [&'static nrf5x::gpio::GPIOPin; 11],
         [
             &nrf5x::gpio::PORT[3], // Bottom right header on DK board
             &nrf5x::gpio::PORT[4],
@@ -143,7 +159,6 @@ pub unsafe fn reset_handler() {
             &nrf5x::gpio::PORT[30],
             &nrf5x::gpio::PORT[31], // -----
             &nrf5x::gpio::PORT[12], // Top mid header on DK board
-            &nrf5x::gpio::PORT[11], // -----
             &nrf5x::gpio::PORT[27], // Top left header on DK board
             &nrf5x::gpio::PORT[26],
             &nrf5x::gpio::PORT[2],
@@ -153,26 +168,18 @@ pub unsafe fn reset_handler() {
 
     // LEDs
     let led_pins = static_init!(
-        [(&'static nrf5x::gpio::GPIOPin, capsules::led::ActivationMode); 4],
+        [kernel::hil::led::LedLow<'static>; 4],
         [
-            (
-                &nrf5x::gpio::PORT[LED1_PIN],
-                capsules::led::ActivationMode::ActiveLow
-            ),
-            (
-                &nrf5x::gpio::PORT[LED2_PIN],
-                capsules::led::ActivationMode::ActiveLow
-            ),
-            (
-                &nrf5x::gpio::PORT[LED3_PIN],
-                capsules::led::ActivationMode::ActiveLow
-            ),
-            (
-                &nrf5x::gpio::PORT[LED4_PIN],
-                capsules::led::ActivationMode::ActiveLow
-            ),
+            kernel::hil::led::LedLow::new(&nrf5x::gpio::PORT[LED1_PIN]),
+            kernel::hil::led::LedLow::new(&nrf5x::gpio::PORT[LED2_PIN]),
+            kernel::hil::led::LedLow::new(&nrf5x::gpio::PORT[LED3_PIN]),
+            kernel::hil::led::LedLow::new(&nrf5x::gpio::PORT[LED4_PIN]),
         ]
     );
+    let leds = static_init!(
+        [&'static kernel::hil::led::Led; 4],
+        [&led_pins[0], &led_pins[1], &led_pins[2], &led_pins[3]]
+    );
 
     let button_pins = static_init!(
         [(&'static nrf5x::gpio::GPIOPin, capsules::button::GpioMode); 4],
@@ -205,10 +212,12 @@ pub unsafe fn reset_handler() {
         LED1_PIN,
         LED2_PIN,
         LED3_PIN,
-        led_pins,
+        leds,
         &UartPins::new(UART_RTS, UART_TXD, UART_RXD, UART_CTS),
         &SpiPins::new(SPI_MOSI, SPI_MISO, SPI_CLK),
         &None,
+        Some(EDGE_COUNTER_PIN),
+        None,
         button_pins,
         &mut APP_MEMORY,
         &mut PROCESSES,
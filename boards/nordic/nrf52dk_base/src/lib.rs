@@ -22,6 +22,7 @@ use capsules::virtual_uart::{UartDevice, UartMux};
 use kernel::capabilities;
 use kernel::hil;
 use kernel::hil::entropy::Entropy32;
+use kernel::hil::power_manager::PowerManager;
 use kernel::hil::rng::Rng;
 use nrf5x::rtc::Rtc;
 
@@ -74,17 +75,38 @@ impl UartPins {
 
 /// Supported drivers by the platform
 pub struct Platform {
+    analog_comparator:
+        &'static capsules::analog_comparator::AnalogComparator<'static, nrf52::comp::Comp>,
     ble_radio: &'static capsules::ble_advertising_driver::BLE<
         'static,
         nrf52::radio::Radio,
         VirtualMuxAlarm<'static, Rtc>,
     >,
-    button: &'static capsules::button::Button<'static, nrf5x::gpio::GPIOPin>,
+    button: &'static capsules::button::Button<
+        'static,
+        nrf5x::gpio::GPIOPin,
+        VirtualMuxAlarm<'static, Rtc>,
+    >,
     console: &'static capsules::console::Console<'static, UartDevice<'static>>,
-    gpio: &'static capsules::gpio::GPIO<'static, nrf5x::gpio::GPIOPin>,
-    led: &'static capsules::led::LED<'static, nrf5x::gpio::GPIOPin>,
+    device_id: &'static capsules::device_id::DeviceIdDriver<'static>,
+    // Only boards that set aside a pin for them have an edge counter or edge
+    // capture.
+    edge_capture: Option<&'static capsules::edge_capture::EdgeCapture<'static>>,
+    edge_counter: Option<&'static capsules::edge_counter::EdgeCounter<'static>>,
+    energy_detect: &'static capsules::energy_detect::EnergyDetectDriver<'static>,
+    gpio: &'static capsules::gpio::GPIO<
+        'static,
+        nrf5x::gpio::GPIOPin,
+        VirtualMuxAlarm<'static, Rtc>,
+    >,
+    led: &'static capsules::led::LED<'static>,
     rng: &'static capsules::rng::RngDriver<'static>,
+    prng: &'static capsules::prng::PrngDriver<'static>,
     temp: &'static capsules::temperature::TemperatureSensor<'static>,
+    sampler: &'static capsules::periodic_sampler::PeriodicSampler<
+        'static,
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
+    >,
     ipc: kernel::ipc::IPC,
     alarm: &'static capsules::alarm::AlarmDriver<
         'static,
@@ -101,14 +123,21 @@ impl kernel::Platform for Platform {
         F: FnOnce(Option<&kernel::Driver>) -> R,
     {
         match driver_num {
+            capsules::analog_comparator::DRIVER_NUM => f(Some(self.analog_comparator)),
             capsules::console::DRIVER_NUM => f(Some(self.console)),
+            capsules::device_id::DRIVER_NUM => f(Some(self.device_id)),
+            capsules::edge_capture::DRIVER_NUM => f(self.edge_capture.map_or(None, |ec| Some(ec))),
+            capsules::edge_counter::DRIVER_NUM => f(self.edge_counter.map_or(None, |ec| Some(ec))),
             capsules::gpio::DRIVER_NUM => f(Some(self.gpio)),
             capsules::alarm::DRIVER_NUM => f(Some(self.alarm)),
             capsules::led::DRIVER_NUM => f(Some(self.led)),
             capsules::button::DRIVER_NUM => f(Some(self.button)),
             capsules::rng::DRIVER_NUM => f(Some(self.rng)),
+            capsules::prng::DRIVER_NUM => f(Some(self.prng)),
             capsules::ble_advertising_driver::DRIVER_NUM => f(Some(self.ble_radio)),
+            capsules::energy_detect::DRIVER_NUM => f(Some(self.energy_detect)),
             capsules::temperature::DRIVER_NUM => f(Some(self.temp)),
+            capsules::periodic_sampler::DRIVER_NUM => f(Some(self.sampler)),
             capsules::nonvolatile_storage_driver::DRIVER_NUM => {
                 f(self.nonvolatile_storage.map_or(None, |nv| Some(nv)))
             }
@@ -127,10 +156,12 @@ pub unsafe fn setup_board(
     debug_pin1_index: usize,
     debug_pin2_index: usize,
     debug_pin3_index: usize,
-    led_pins: &'static mut [(&'static nrf5x::gpio::GPIOPin, capsules::led::ActivationMode)],
+    leds: &'static [&'static kernel::hil::led::Led],
     uart_pins: &UartPins,
     spi_pins: &SpiPins,
     mx25r6435f: &Option<SpiMX25R6435FPins>,
+    edge_counter_pin: Option<usize>,
+    edge_capture_pin: Option<usize>,
     button_pins: &'static mut [(&'static nrf5x::gpio::GPIOPin, capsules::button::GpioMode)],
     app_memory: &mut [u8],
     process_pointers: &'static mut [Option<&'static kernel::procs::ProcessType>],
@@ -159,25 +190,35 @@ pub unsafe fn setup_board(
         Some(&nrf5x::gpio::PORT[debug_pin3_index]),
     );
 
-    let gpio = static_init!(
-        capsules::gpio::GPIO<'static, nrf5x::gpio::GPIOPin>,
-        capsules::gpio::GPIO::new(gpio_pins)
-    );
-    for pin in gpio_pins.iter() {
-        pin.set_client(gpio);
-    }
-
     // LEDs
     let led = static_init!(
-        capsules::led::LED<'static, nrf5x::gpio::GPIOPin>,
-        capsules::led::LED::new(led_pins)
+        capsules::led::LED<'static>,
+        capsules::led::LED::new(leds)
     );
 
+    let rtc = &nrf5x::rtc::RTC;
+    rtc.start();
+    let mux_alarm = static_init!(
+        capsules::virtual_alarm::MuxAlarm<'static, nrf5x::rtc::Rtc>,
+        capsules::virtual_alarm::MuxAlarm::new(&nrf5x::rtc::RTC)
+    );
+    rtc.set_client(mux_alarm);
+
     // Buttons
+    let button_alarm = static_init!(
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
+        capsules::virtual_alarm::VirtualMuxAlarm::new(mux_alarm)
+    );
     let button = static_init!(
-        capsules::button::Button<'static, nrf5x::gpio::GPIOPin>,
+        capsules::button::Button<
+            'static,
+            nrf5x::gpio::GPIOPin,
+            capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
+        >,
         capsules::button::Button::new(
             button_pins,
+            button_alarm,
+            0,
             board_kernel.create_grant(&memory_allocation_capability)
         )
     );
@@ -186,14 +227,40 @@ pub unsafe fn setup_board(
         btn.set_input_mode(kernel::hil::gpio::InputMode::PullUp);
         btn.set_client(button);
     }
+    button_alarm.set_client(button);
 
-    let rtc = &nrf5x::rtc::RTC;
-    rtc.start();
-    let mux_alarm = static_init!(
-        capsules::virtual_alarm::MuxAlarm<'static, nrf5x::rtc::Rtc>,
-        capsules::virtual_alarm::MuxAlarm::new(&nrf5x::rtc::RTC)
+    let gpio_alarm = static_init!(
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
+        capsules::virtual_alarm::VirtualMuxAlarm::new(mux_alarm)
     );
-    rtc.set_client(mux_alarm);
+    let gpio_pin_group = static_init!(
+        kernel::hil::gpio::PinGroup<'static, nrf5x::gpio::GPIOPin>,
+        kernel::hil::gpio::PinGroup::new(gpio_pins)
+    );
+    let gpio = static_init!(
+        capsules::gpio::GPIO<
+            'static,
+            nrf5x::gpio::GPIOPin,
+            capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
+        >,
+        capsules::gpio::GPIO::new(gpio_pin_group, gpio_alarm)
+    );
+    gpio_pin_group.set_client(gpio);
+    gpio_alarm.set_client(gpio);
+
+    let wrap_alarm = static_init!(
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
+        capsules::virtual_alarm::VirtualMuxAlarm::new(mux_alarm)
+    );
+    let wrap_counter = static_init!(
+        capsules::alarm::WrapCounter<
+            'static,
+            capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
+        >,
+        capsules::alarm::WrapCounter::new(wrap_alarm)
+    );
+    wrap_alarm.set_client(wrap_counter);
+    wrap_counter.start();
 
     let virtual_alarm1 = static_init!(
         capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
@@ -206,6 +273,7 @@ pub unsafe fn setup_board(
         >,
         capsules::alarm::AlarmDriver::new(
             virtual_alarm1,
+            wrap_counter,
             board_kernel.create_grant(&memory_allocation_capability)
         )
     );
@@ -236,6 +304,8 @@ pub unsafe fn setup_board(
         nrf5x::pinmux::Pinmux::new(uart_pins.cts as u32),
         nrf5x::pinmux::Pinmux::new(uart_pins.rts as u32),
     );
+    // Keep receiving while the console's receive ring copies each chunk.
+    nrf52::uart::UARTE0.enable_rx_double_buffering(&mut nrf52::uart::RX_SPARE_BUF);
     let console = static_init!(
         capsules::console::Console<UartDevice>,
         capsules::console::Console::new(
@@ -248,6 +318,9 @@ pub unsafe fn setup_board(
     );
     kernel::hil::uart::UART::set_client(console_uart, console);
     console.initialize();
+    console.enable_receive_ring(&mut capsules::console::RX_RING_BUF);
+    console.enable_line_mode(&mut capsules::console::LINE_BUF);
+    console.enable_echo(&mut capsules::console::ECHO_BUF);
 
     // Create virtual device for kernel debug.
     let debugger_uart = static_init!(UartDevice, UartDevice::new(uart_mux, false));
@@ -291,29 +364,185 @@ pub unsafe fn setup_board(
     );
     ble_radio_virtual_alarm.set_client(ble_radio);
 
+    let energy_detect = static_init!(
+        capsules::energy_detect::EnergyDetectDriver<'static>,
+        capsules::energy_detect::EnergyDetectDriver::new(
+            &nrf52::radio::RADIO,
+            board_kernel.create_grant(&memory_allocation_capability)
+        )
+    );
+    kernel::hil::radio::EnergyDetect::set_energy_detect_client(
+        &nrf52::radio::RADIO,
+        energy_detect,
+    );
+
+    let mux_temp = static_init!(
+        capsules::virtual_temperature::MuxTemperature<'static>,
+        capsules::virtual_temperature::MuxTemperature::new(&nrf5x::temperature::TEMP)
+    );
+    kernel::hil::sensors::TemperatureDriver::set_client(&nrf5x::temperature::TEMP, mux_temp);
+
+    let virtual_temp = static_init!(
+        capsules::virtual_temperature::VirtualTemperature<'static>,
+        capsules::virtual_temperature::VirtualTemperature::new(mux_temp)
+    );
+    virtual_temp.setup();
     let temp = static_init!(
         capsules::temperature::TemperatureSensor<'static>,
         capsules::temperature::TemperatureSensor::new(
-            &mut nrf5x::temperature::TEMP,
+            virtual_temp,
+            board_kernel.create_grant(&memory_allocation_capability)
+        )
+    );
+    kernel::hil::sensors::TemperatureDriver::set_client(virtual_temp, temp);
+
+    let sampler_temp = static_init!(
+        capsules::virtual_temperature::VirtualTemperature<'static>,
+        capsules::virtual_temperature::VirtualTemperature::new(mux_temp)
+    );
+    sampler_temp.setup();
+    let sampler_source = static_init!(
+        capsules::periodic_sampler::TemperatureSource<'static>,
+        capsules::periodic_sampler::TemperatureSource::new(sampler_temp)
+    );
+    kernel::hil::sensors::TemperatureDriver::set_client(sampler_temp, sampler_source);
+    let sampler_alarm = static_init!(
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
+        capsules::virtual_alarm::VirtualMuxAlarm::new(mux_alarm)
+    );
+    let sampler = static_init!(
+        capsules::periodic_sampler::PeriodicSampler<
+            'static,
+            capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
+        >,
+        capsules::periodic_sampler::PeriodicSampler::new(
+            sampler_source,
+            sampler_alarm,
+            board_kernel.create_grant(&memory_allocation_capability)
+        )
+    );
+    sampler_alarm.set_client(sampler);
+    capsules::periodic_sampler::SampleSource::set_client(sampler_source, sampler);
+
+    let device_id = static_init!(
+        capsules::device_id::DeviceIdDriver<'static>,
+        capsules::device_id::DeviceIdDriver::new(
+            &nrf52::ficr::FICR_INSTANCE,
             board_kernel.create_grant(&memory_allocation_capability)
         )
     );
-    kernel::hil::sensors::TemperatureDriver::set_client(&nrf5x::temperature::TEMP, temp);
 
     let entropy_to_random = static_init!(
         capsules::rng::Entropy32ToRandom<'static>,
         capsules::rng::Entropy32ToRandom::new(&nrf5x::trng::TRNG)
     );
 
+    let mux_rng = static_init!(
+        capsules::virtual_rng::MuxRngMaster<'static>,
+        capsules::virtual_rng::MuxRngMaster::new(entropy_to_random)
+    );
+    nrf5x::trng::TRNG.set_client(entropy_to_random);
+    entropy_to_random.set_client(mux_rng);
+
+    let rng_device = static_init!(
+        capsules::virtual_rng::VirtualRngMasterDevice<'static>,
+        capsules::virtual_rng::VirtualRngMasterDevice::new(mux_rng)
+    );
     let rng = static_init!(
         capsules::rng::RngDriver<'static>,
         capsules::rng::RngDriver::new(
-            entropy_to_random,
+            rng_device,
             board_kernel.create_grant(&memory_allocation_capability)
         )
     );
-    nrf5x::trng::TRNG.set_client(entropy_to_random);
-    entropy_to_random.set_client(rng);
+    rng_device.set_client(rng);
+
+    let prng_device = static_init!(
+        capsules::virtual_rng::VirtualRngMasterDevice<'static>,
+        capsules::virtual_rng::VirtualRngMasterDevice::new(mux_rng)
+    );
+    let prng = static_init!(
+        capsules::prng::PrngDriver<'static>,
+        capsules::prng::PrngDriver::new(
+            prng_device,
+            board_kernel.create_grant(&memory_allocation_capability)
+        )
+    );
+    prng_device.set_client(prng);
+
+    // Analog comparator, on inputs AIN0-AIN7
+    let ac_channels = static_init!(
+        [&'static nrf52::comp::CompChannel; 8],
+        [
+            &nrf52::comp::CHANNEL_AIN0,
+            &nrf52::comp::CHANNEL_AIN1,
+            &nrf52::comp::CHANNEL_AIN2,
+            &nrf52::comp::CHANNEL_AIN3,
+            &nrf52::comp::CHANNEL_AIN4,
+            &nrf52::comp::CHANNEL_AIN5,
+            &nrf52::comp::CHANNEL_AIN6,
+            &nrf52::comp::CHANNEL_AIN7,
+        ]
+    );
+    let analog_comparator = static_init!(
+        capsules::analog_comparator::AnalogComparator<'static, nrf52::comp::Comp>,
+        capsules::analog_comparator::AnalogComparator::new(&nrf52::comp::COMP, ac_channels)
+    );
+    nrf52::comp::COMP.set_client(analog_comparator);
+
+    // The edge counter and edge capture both need TIMER2.
+    debug_assert!(
+        edge_counter_pin.is_none() || edge_capture_pin.is_none(),
+        "The edge counter and edge capture cannot both be used."
+    );
+
+    // Count edges on the board's edge counter pin with TIMER2 and PPI channel 0.
+    let edge_counter: Option<&'static capsules::edge_counter::EdgeCounter<'static>> =
+        if let Some(pin) = edge_counter_pin {
+            let nrf52_edge_counter = static_init!(
+                nrf52::edge_counter::EdgeCounter<'static>,
+                nrf52::edge_counter::EdgeCounter::new(
+                    &nrf5x::gpio::PORT[pin],
+                    &nrf5x::timer::TIMER2,
+                    &nrf52::ppi::PPI,
+                    0
+                )
+            );
+            let edge_counter = static_init!(
+                capsules::edge_counter::EdgeCounter<'static>,
+                capsules::edge_counter::EdgeCounter::new(nrf52_edge_counter)
+            );
+            Some(edge_counter)
+        } else {
+            None
+        };
+
+    // Timestamp edges on the board's edge capture pin with TIMER2 and PPI
+    // channel 1.
+    let edge_capture: Option<&'static capsules::edge_capture::EdgeCapture<'static>> =
+        if let Some(pin) = edge_capture_pin {
+            let nrf52_edge_capture = static_init!(
+                nrf52::edge_capture::EdgeCapture<'static>,
+                nrf52::edge_capture::EdgeCapture::new(
+                    &nrf5x::gpio::PORT[pin],
+                    &nrf5x::timer::TIMER2,
+                    &nrf52::ppi::PPI,
+                    1
+                )
+            );
+            nrf5x::gpio::PORT[pin].set_client(nrf52_edge_capture);
+            let edge_capture = static_init!(
+                capsules::edge_capture::EdgeCapture<'static>,
+                capsules::edge_capture::EdgeCapture::new(
+                    nrf52_edge_capture,
+                    board_kernel.create_grant(&memory_allocation_capability)
+                )
+            );
+            hil::gpio::EdgeCapture::set_client(nrf52_edge_capture, edge_capture);
+            Some(edge_capture)
+        } else {
+            None
+        };
 
     // SPI
     let mux_spi = static_init!(
@@ -401,8 +630,14 @@ pub unsafe fn setup_board(
         None
     };
 
-    // Start all of the clocks. Low power operation will require a better
-    // approach than this.
+    // Register the peripherals that constrain how deeply the chip may sleep
+    // when there is no work to do. Each of them needs the high frequency
+    // clock while it is active.
+    nrf5x::power::POWER.register(&nrf5x::timer::ALARM1);
+    nrf5x::power::POWER.register(&nrf52::uart::UARTE0);
+    nrf5x::power::POWER.register(&nrf52::spi::SPIM0);
+    nrf5x::power::POWER.register(&nrf52::radio::RADIO);
+
     nrf52::clock::CLOCK.low_stop();
     nrf52::clock::CLOCK.high_stop();
 
@@ -414,13 +649,20 @@ pub unsafe fn setup_board(
     while !nrf52::clock::CLOCK.high_started() {}
 
     let platform = Platform {
+        analog_comparator: analog_comparator,
         button: button,
         ble_radio: ble_radio,
         console: console,
+        device_id: device_id,
+        edge_capture: edge_capture,
+        edge_counter: edge_counter,
+        energy_detect: energy_detect,
         led: led,
         gpio: gpio,
         rng: rng,
+        prng: prng,
         temp: temp,
+        sampler: sampler,
         alarm: alarm,
         nonvolatile_storage: nonvolatile_storage,
         ipc: kernel::ipc::IPC::new(board_kernel, &memory_allocation_capability),
@@ -445,5 +687,9 @@ pub unsafe fn setup_board(
         &process_management_capability,
     );
 
+    // Reset the chip if the main loop stops running for two seconds.
+    kernel::hil::watchdog::Watchdog::start(&nrf52::wdt::WDT, 2000);
+    board_kernel.set_watchdog(&nrf52::wdt::WDT, &main_loop_capability);
+
     board_kernel.kernel_loop(&platform, chip, Some(&platform.ipc), &main_loop_capability);
 }
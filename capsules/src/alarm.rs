@@ -1,4 +1,45 @@
 //! Provides userspace applications with a alarm API.
+//!
+//! Each app has one outstanding notification. It is either one-shot, firing
+//! once and then disarming itself, or periodic, re-arming itself every
+//! `period` tics. Periodic notifications are re-armed relative to the time
+//! they were scheduled to fire, not the time the callback ran, so they do not
+//! drift.
+//!
+//! Apps can also read a 64-bit monotonic tic count, made of the counter value
+//! and the number of times the counter has wrapped. A `WrapCounter` counts the
+//! wraps. It keeps its own virtual alarm armed half a counter period ahead, so
+//! it reads the counter at least twice per period and never misses a wrap,
+//! even when no app is using the driver.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let wrap_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! let wrap_counter = static_init!(
+//!     capsules::alarm::WrapCounter<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
+//!     capsules::alarm::WrapCounter::new(wrap_alarm)
+//! );
+//! wrap_alarm.set_client(wrap_counter);
+//! wrap_counter.start();
+//! let virtual_alarm1 = static_init!(
+//!     VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! let alarm = static_init!(
+//!     capsules::alarm::AlarmDriver<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
+//!     capsules::alarm::AlarmDriver::new(
+//!         virtual_alarm1,
+//!         wrap_counter,
+//!         board_kernel.create_grant(&memory_allocation_capability)
+//!     )
+//! );
+//! virtual_alarm1.set_client(alarm);
+//! ```
 
 use core::cell::Cell;
 use kernel::hil::time::{self, Alarm, Frequency};
@@ -11,12 +52,38 @@ pub const DRIVER_NUM: usize = 0x00000000;
 enum Expiration {
     Disabled,
     Abs(u32),
+    /// Fires at `next`, then every `period` tics after it. `id` is the first
+    /// expiration and identifies the notification to userspace.
+    Periodic { next: u32, period: u32, id: u32 },
+}
+
+impl Expiration {
+    /// The next time this notification fires, if it is armed.
+    fn next(&self) -> Option<u32> {
+        match *self {
+            Expiration::Disabled => None,
+            Expiration::Abs(exp) => Some(exp),
+            Expiration::Periodic { next, .. } => Some(next),
+        }
+    }
+
+    /// The identifier returned to userspace when the notification was set.
+    fn id(&self) -> Option<u32> {
+        match *self {
+            Expiration::Disabled => None,
+            Expiration::Abs(exp) => Some(exp),
+            Expiration::Periodic { id, .. } => Some(id),
+        }
+    }
 }
 
 #[derive(Copy, Clone)]
 pub struct AlarmData {
     expiration: Expiration,
     callback: Option<Callback>,
+    /// The high 32 bits of the tic count latched by the app's last monotonic
+    /// read.
+    high_tics: u32,
 }
 
 impl Default for AlarmData {
@@ -24,41 +91,112 @@ impl Default for AlarmData {
         AlarmData {
             expiration: Expiration::Disabled,
             callback: None,
+            high_tics: 0,
+        }
+    }
+}
+
+/// Extends an alarm's counter to a 64-bit monotonic tic count by counting how
+/// many times it has wrapped. `alarm` must be a virtual alarm of its own,
+/// sharing the counter of the alarm the driver uses.
+pub struct WrapCounter<'a, A: Alarm> {
+    alarm: &'a A,
+    /// The counter value when it was last read, and the number of times the
+    /// counter had wrapped by then.
+    last_now: Cell<u32>,
+    wraps: Cell<u32>,
+}
+
+impl<A: Alarm> WrapCounter<'a, A> {
+    pub const fn new(alarm: &'a A) -> WrapCounter<'a, A> {
+        WrapCounter {
+            alarm: alarm,
+            last_now: Cell::new(0),
+            wraps: Cell::new(0),
         }
     }
+
+    /// Start counting wraps. Must be called once the counter is the client
+    /// of its alarm.
+    pub fn start(&self) {
+        self.last_now.set(self.alarm.now());
+        self.arm();
+    }
+
+    // Arm the alarm half a counter period after the last read, so that the
+    // counter cannot wrap twice between two reads.
+    fn arm(&self) {
+        let max_tics = self.alarm.max_tics();
+        let half_period = max_tics / 2 + 1;
+        self.alarm
+            .set_alarm(self.last_now.get().wrapping_add(half_period) & max_tics);
+    }
+
+    /// Reads the counter as a 64-bit tic count, counting a wrap if the
+    /// counter went backwards since the last read.
+    pub fn count(&self) -> u64 {
+        let now = self.alarm.now();
+        if now < self.last_now.get() {
+            self.wraps.set(self.wraps.get().wrapping_add(1));
+        }
+        self.last_now.set(now);
+        self.wraps.get() as u64 * (self.alarm.max_tics() as u64 + 1) + now as u64
+    }
+}
+
+impl<A: Alarm> time::Client for WrapCounter<'a, A> {
+    fn fired(&self) {
+        self.count();
+        self.arm();
+    }
 }
 
 pub struct AlarmDriver<'a, A: Alarm> {
     alarm: &'a A,
+    wrap_counter: &'a WrapCounter<'a, A>,
     num_armed: Cell<usize>,
     app_alarm: Grant<AlarmData>,
     prev: Cell<u32>,
 }
 
 impl<A: Alarm> AlarmDriver<'a, A> {
-    pub const fn new(alarm: &'a A, grant: Grant<AlarmData>) -> AlarmDriver<'a, A> {
+    pub const fn new(
+        alarm: &'a A,
+        wrap_counter: &'a WrapCounter<'a, A>,
+        grant: Grant<AlarmData>,
+    ) -> AlarmDriver<'a, A> {
         AlarmDriver {
             alarm: alarm,
+            wrap_counter: wrap_counter,
             num_armed: Cell::new(0),
             app_alarm: grant,
             prev: Cell::new(0),
         }
     }
 
+    /// Arm the caller's notification, replacing any outstanding one.
+    fn arm(&self, td: &mut AlarmData, expiration: Expiration) {
+        // if previously unarmed, but now will become armed
+        if let Expiration::Disabled = td.expiration {
+            self.num_armed.set(self.num_armed.get() + 1);
+        }
+        td.expiration = expiration;
+    }
+
     fn reset_active_alarm(&self, now: u32) -> Option<u32> {
         self.prev.set(now);
         let mut next_alarm = u32::max_value();
         let mut next_dist = u32::max_value();
         for alarm in self.app_alarm.iter() {
-            alarm.enter(|alarm, _| match alarm.expiration {
-                Expiration::Abs(exp) => {
+            alarm.enter(|alarm, _| match alarm.expiration.next() {
+                Some(exp) => {
                     let t_dist = exp.wrapping_sub(now);
                     if next_dist > t_dist {
                         next_alarm = exp;
                         next_dist = t_dist;
                     }
                 }
-                Expiration::Disabled => {}
+                None => {}
             });
         }
         if next_alarm != u32::max_value() {
@@ -98,6 +236,12 @@ impl<A: Alarm> Driver for AlarmDriver<'a, A> {
     /// - `2`: Read the the current clock value
     /// - `3`: Stop the alarm if it is outstanding
     /// - `4`: Set an alarm to fire at a given clock value `time`.
+    /// - `5`: Set a one-shot alarm to fire `data` tics from now.
+    /// - `6`: Set a periodic alarm to fire every `data` tics, starting `data`
+    ///   tics from now.
+    /// - `7`: Read the low 32 bits of the monotonic tic count, latching the
+    ///   high 32 bits for command `8`.
+    /// - `8`: Return the high 32 bits latched by the last command `7`.
     fn command(&self, cmd_type: usize, data: usize, _: usize, caller_id: AppId) -> ReturnCode {
         // Returns the error code to return to the user and whether we need to
         // reset which is the next active alarm. We only _don't_ reset if we're
@@ -119,12 +263,12 @@ impl<A: Alarm> Driver for AlarmDriver<'a, A> {
                     },
                     3 /* Stop */ => {
                         let alarm_id = data as u32;
-                        match td.expiration {
-                            Expiration::Disabled => {
+                        match td.expiration.id() {
+                            None => {
                                 // Request to stop when already stopped
                                 (ReturnCode::EALREADY, false)
                             },
-                            Expiration::Abs(exp) if exp != alarm_id => {
+                            Some(id) if id != alarm_id => {
                                 // Request to stop invalid alarm id
                                 (ReturnCode::EINVAL, false)
                             },
@@ -138,13 +282,36 @@ impl<A: Alarm> Driver for AlarmDriver<'a, A> {
                     },
                     4 /* Set absolute expiration */ => {
                         let time = data;
-                        // if previously unarmed, but now will become armed
-                        if let Expiration::Disabled = td.expiration {
-                            self.num_armed.set(self.num_armed.get() + 1);
-                        }
-                        td.expiration = Expiration::Abs(time as u32);
+                        self.arm(td, Expiration::Abs(time as u32));
                         (ReturnCode::SuccessWithValue { value: time }, true)
                     },
+                    5 /* Set one-shot expiration relative to now */ => {
+                        let time = now.wrapping_add(data as u32);
+                        self.arm(td, Expiration::Abs(time));
+                        (ReturnCode::SuccessWithValue { value: time as usize }, true)
+                    },
+                    6 /* Set periodic expiration */ => {
+                        let period = data as u32;
+                        if period == 0 {
+                            (ReturnCode::EINVAL, false)
+                        } else {
+                            let time = now.wrapping_add(period);
+                            self.arm(td, Expiration::Periodic {
+                                next: time,
+                                period: period,
+                                id: time,
+                            });
+                            (ReturnCode::SuccessWithValue { value: time as usize }, true)
+                        }
+                    },
+                    7 /* Read monotonic tics */ => {
+                        let count = self.wrap_counter.count();
+                        td.high_tics = (count >> 32) as u32;
+                        (ReturnCode::SuccessWithValue { value: count as u32 as usize }, false)
+                    },
+                    8 /* Read latched monotonic high word */ => {
+                        (ReturnCode::SuccessWithValue { value: td.high_tics as usize }, false)
+                    },
                     _ => (ReturnCode::ENOSUPPORT, false)
                 };
                 if reset {
@@ -159,13 +326,22 @@ fn has_expired(alarm: u32, now: u32, prev: u32) -> bool {
     now.wrapping_sub(prev) >= alarm.wrapping_sub(prev)
 }
 
+/// The first deadline of a periodic notification that was due at `next`
+/// that is still in the future at `now`, skipping any periods that have
+/// already passed. Computed with one division rather than stepping through
+/// the missed periods, since it runs in interrupt context. `period` must not
+/// be zero.
+fn next_period(next: u32, period: u32, now: u32) -> u32 {
+    let missed = now.wrapping_sub(next) / period;
+    next.wrapping_add(missed.wrapping_add(1).wrapping_mul(period))
+}
+
 impl<A: Alarm> time::Client for AlarmDriver<'a, A> {
     fn fired(&self) {
         let now = self.alarm.now();
-        self.app_alarm.each(|alarm| {
-            if let Expiration::Abs(exp) = alarm.expiration {
-                let expired = has_expired(exp, now, self.prev.get());
-                if expired {
+        self.app_alarm.each(|alarm| match alarm.expiration {
+            Expiration::Abs(exp) => {
+                if has_expired(exp, now, self.prev.get()) {
                     alarm.expiration = Expiration::Disabled;
                     self.num_armed.set(self.num_armed.get() - 1);
                     alarm
@@ -173,6 +349,21 @@ impl<A: Alarm> time::Client for AlarmDriver<'a, A> {
                         .map(|mut cb| cb.schedule(now as usize, exp as usize, 0));
                 }
             }
+            Expiration::Periodic { next, period, id } => {
+                if has_expired(next, now, self.prev.get()) {
+                    // Re-arm relative to when this period was due rather than
+                    // `now`, so that the notification does not drift.
+                    alarm.expiration = Expiration::Periodic {
+                        next: next_period(next, period, now),
+                        period: period,
+                        id: id,
+                    };
+                    alarm
+                        .callback
+                        .map(|mut cb| cb.schedule(now as usize, id as usize, 0));
+                }
+            }
+            Expiration::Disabled => {}
         });
 
         // If there are armed alarms left, reset the underlying alarm to the
@@ -189,3 +380,40 @@ impl<A: Alarm> time::Client for AlarmDriver<'a, A> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn periodic_deadlines_do_not_drift() {
+        let period = 100;
+        let mut next = 1000;
+        // The callback is handled a varying amount late each period.
+        for (i, late) in [0, 7, 99, 1, 50, 0, 98, 13, 64, 2].iter().enumerate() {
+            let now = next + late;
+            assert!(has_expired(next, now, next));
+            next = next_period(next, period, now);
+            assert_eq!(next, 1000 + period * (i as u32 + 1));
+        }
+        assert_eq!(next, 1000 + 10 * period);
+    }
+
+    #[test]
+    fn missed_periods_are_skipped() {
+        // Handled three and a half periods late, so the next deadline is the
+        // fourth period after the one that was due.
+        assert_eq!(next_period(1000, 100, 1350), 1400);
+        // Exactly on a later deadline, which has therefore passed.
+        assert_eq!(next_period(1000, 100, 1300), 1400);
+        // Periods far in the past are skipped in one step.
+        assert_eq!(next_period(0, 3, 3_000_000_001), 3_000_000_003);
+    }
+
+    #[test]
+    fn periods_wrap_with_the_counter() {
+        let next = u32::max_value() - 49;
+        assert_eq!(next_period(next, 100, next.wrapping_add(10)), 50);
+        assert_eq!(next_period(next, 100, 120), 150);
+    }
+}
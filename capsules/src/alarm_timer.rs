@@ -0,0 +1,95 @@
+//! Provides the `Timer` interface on top of an `Alarm`.
+//!
+//! An `Alarm` fires at an absolute counter value, so a client that wants to
+//! be notified every N tics has to compute each new deadline itself. An
+//! `AlarmTimer` does this for it. A repeating timer advances its deadline by
+//! the interval from the previous deadline rather than from the time the
+//! callback ran, so the firings stay at multiples of the interval and delays
+//! in handling one firing do not accumulate. If whole intervals were missed,
+//! the timer skips ahead to the next multiple in the future.
+//!
+//! The client is signaled through the `time::Client` trait, like an alarm
+//! client, and the timer is cancelled with `Time::disable`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let timer_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! let timer = static_init!(
+//!     capsules::alarm_timer::AlarmTimer<'static, VirtualMuxAlarm<'static, sam4l::ast::Ast>>,
+//!     capsules::alarm_timer::AlarmTimer::new(timer_alarm)
+//! );
+//! timer_alarm.set_client(timer);
+//! timer.set_client(client);
+//! timer.repeat(1000);
+//! ```
+
+use core::cell::Cell;
+use kernel::common::cells::OptionalCell;
+use kernel::hil::time::{self, Alarm, Time, Timer};
+
+pub struct AlarmTimer<'a, A: Alarm> {
+    alarm: &'a A,
+    /// The interval of a repeating timer, or `None` for a one-shot timer.
+    interval: Cell<Option<u32>>,
+    client: OptionalCell<&'a time::Client>,
+}
+
+impl<A: Alarm> AlarmTimer<'a, A> {
+    pub const fn new(alarm: &'a A) -> AlarmTimer<'a, A> {
+        AlarmTimer {
+            alarm: alarm,
+            interval: Cell::new(None),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a time::Client) {
+        self.client.set(client);
+    }
+}
+
+impl<A: Alarm> Time for AlarmTimer<'a, A> {
+    type Frequency = A::Frequency;
+
+    fn disable(&self) {
+        self.interval.set(None);
+        self.alarm.disable();
+    }
+
+    fn is_armed(&self) -> bool {
+        self.alarm.is_armed()
+    }
+}
+
+impl<A: Alarm> Timer for AlarmTimer<'a, A> {
+    fn oneshot(&self, interval: u32) {
+        self.interval.set(None);
+        self.alarm.set_alarm(self.alarm.now().wrapping_add(interval));
+    }
+
+    fn repeat(&self, interval: u32) {
+        // A zero interval would fire continuously
+        let interval = if interval == 0 { 1 } else { interval };
+        self.interval.set(Some(interval));
+        self.alarm.set_alarm(self.alarm.now().wrapping_add(interval));
+    }
+}
+
+impl<A: Alarm> time::Client for AlarmTimer<'a, A> {
+    fn fired(&self) {
+        // Re-arm before signaling the client, so that it can cancel or change
+        // the timer from its callback.
+        if let Some(interval) = self.interval.get() {
+            let deadline = self.alarm.get_alarm();
+            let missed = self.alarm.now().wrapping_sub(deadline) / interval;
+            let next = deadline.wrapping_add(interval.wrapping_mul(missed + 1));
+            self.alarm.set_alarm(next);
+        }
+        self.client.map(|client| client.fired());
+    }
+}
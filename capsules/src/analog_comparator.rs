@@ -99,6 +99,16 @@ impl<'a, A: hil::analog_comparator::AnalogComparator> AnalogComparator<'a, A> {
 
         return result;
     }
+
+    // Set the reference level of a channel
+    fn set_reference(&self, channel: usize, millivolts: usize) -> ReturnCode {
+        if channel >= self.channels.len() {
+            return ReturnCode::EINVAL;
+        }
+        // Convert channel index
+        let chan = self.channels[channel];
+        self.analog_comparator.set_reference(chan, millivolts)
+    }
 }
 
 impl<'a, A: hil::analog_comparator::AnalogComparator> Driver for AnalogComparator<'a, A> {
@@ -116,7 +126,16 @@ impl<'a, A: hil::analog_comparator::AnalogComparator> Driver for AnalogComparato
     /// - `3`: Stop interrupt-based comparisons.
     ///        Input x chooses the desired comparator ACx (e.g. 0 or 1 for
     ///        hail, 0-3 for imix)
-    fn command(&self, command_num: usize, channel: usize, _: usize, _: AppId) -> ReturnCode {
+    /// - `4`: Set the reference level, in millivolts, that comparator ACx
+    ///        compares its input against. Only supported by comparators with
+    ///        a configurable internal reference (e.g. the nRF52 COMP).
+    fn command(
+        &self,
+        command_num: usize,
+        channel: usize,
+        millivolts: usize,
+        _: AppId,
+    ) -> ReturnCode {
         match command_num {
             0 => ReturnCode::SuccessWithValue {
                 value: self.channels.len() as usize,
@@ -128,6 +147,8 @@ impl<'a, A: hil::analog_comparator::AnalogComparator> Driver for AnalogComparato
 
             3 => self.stop_comparing(channel),
 
+            4 => self.set_reference(channel, millivolts),
+
             _ => return ReturnCode::ENOSUPPORT,
         }
     }
@@ -65,11 +65,7 @@ impl AppFlash<'a> {
             .enter(appid, |app, _| {
                 // Check that this is a valid range in the app's flash.
                 let flash_length = app.buffer.as_mut().map_or(0, |app_buffer| app_buffer.len());
-                let (app_flash_start, app_flash_end) = appid.get_editable_flash_range();
-                if flash_address < app_flash_start
-                    || flash_address >= app_flash_end
-                    || flash_address + flash_length >= app_flash_end
-                {
+                if !appid.in_app_flash_bounds(flash_address, flash_length) {
                     return ReturnCode::EINVAL;
                 }
 
@@ -14,9 +14,11 @@
 //!
 //! The allow systems calls are used for buffers from allocated by userland
 //!
-//! There are two different buffers:
+//! There are three different buffers:
 //! * 0: Advertising data
 //! * 1: Passive scanning buffer
+//! * 2: Contents of an AD structure to add to the advertising data with
+//!      command 4
 //!
 //! The possible return codes from the 'allow' system call indicate the following:
 //!
@@ -48,14 +50,33 @@
 //!
 //! * 0: start advertisement
 //! * 1: stop advertisement or scanning
+//! * 2: configure the transmit power
+//! * 3: clear the advertising data
+//! * 4: add an AD structure to the advertising data
 //! * 5: start scanning
+//! * 6: set the advertising interval
+//!
+//! An app can hand-pack the advertising data in buffer 0, in which case the
+//! whole buffer is advertised. Alternatively, it clears the advertising data
+//! with command 3 and adds AD structures by type with command 4, passing the
+//! AD type as the first argument and the structure's contents in buffer 2.
+//! The kernel writes each structure, with its length prefix, into buffer 0,
+//! and only the structures added are advertised. Command 4 returns `ESIZE` if
+//! the structure does not fit in the 31 bytes of advertising data or in
+//! buffer 0.
 //!
 //! The possible return codes from the `command` system call indicate the following:
 //!
 //! * SUCCESS:      The command was successful
 //! * EBUSY:        The driver is currently busy with other tasks
+//! * ESIZE:        The AD structure does not fit in the advertising data
+//! * EINVAL:       The advertising interval or transmit power is out of range
 //! * ENOSUPPORT:   The operation is not supported
 //!
+//! The advertising interval is in milliseconds and must be between 20 ms and
+//! 10.24 s. Changing it while advertising takes effect from the next
+//! advertising event.
+//!
 //! Usage
 //! -----
 //!
@@ -114,6 +135,10 @@ const PACKET_ADDR_LEN: usize = 6;
 const PACKET_LENGTH: usize = 39;
 const ADV_HEADER_TXADD_OFFSET: usize = 6;
 
+// BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 4.4.2.2
+const ADV_INTERVAL_MIN_MS: usize = 20;
+const ADV_INTERVAL_MAX_MS: usize = 10240;
+
 #[derive(PartialEq, Debug)]
 enum BLEState {
     NotInitialized,
@@ -167,6 +192,10 @@ pub struct App {
 
     // Advertising meta-data
     adv_data: Option<kernel::AppSlice<kernel::Shared, u8>>,
+    /// The length of the AD structures added with command 4, or `None` if
+    /// the app packed `adv_data` itself.
+    adv_data_len: Option<usize>,
+    ad_structure: Option<kernel::AppSlice<kernel::Shared, u8>>,
     address: [u8; PACKET_ADDR_LEN],
     pdu_type: AdvPduType,
     advertisement_interval_ms: u32,
@@ -188,6 +217,8 @@ impl Default for App {
         App {
             alarm_data: AlarmData::new(),
             adv_data: None,
+            adv_data_len: None,
+            ad_structure: None,
             scan_buffer: None,
             address: [0; PACKET_ADDR_LEN],
             pdu_type: ADV_NONCONN_IND,
@@ -241,8 +272,10 @@ impl App {
                 ble.kernel_tx
                     .take()
                     .map(|kernel_tx| {
-                        let adv_data_len =
-                            cmp::min(kernel_tx.len() - PACKET_ADDR_LEN - 2, adv_data.len());
+                        let adv_data_len = cmp::min(
+                            kernel_tx.len() - PACKET_ADDR_LEN - 2,
+                            self.adv_data_len.unwrap_or(adv_data.len()),
+                        );
                         let adv_data_corrected = &adv_data.as_ref()[..adv_data_len];
                         let payload_len = adv_data_corrected.len() + PACKET_ADDR_LEN;
                         {
@@ -292,8 +325,11 @@ impl App {
         self.alarm_data.t0 = now;
         let nonce = self.random_nonce() % 10;
 
-        let period_ms = (self.advertisement_interval_ms + nonce) * F::frequency() / 1000;
-        self.alarm_data.expiration = Expiration::Abs(now.wrapping_add(period_ms));
+        // Computed in 64 bits, as a 10.24 s interval overflows 32 bits at
+        // clock frequencies above about 400 kHz
+        let period_ms =
+            (self.advertisement_interval_ms + nonce) as u64 * F::frequency() as u64 / 1000;
+        self.alarm_data.expiration = Expiration::Abs(now.wrapping_add(period_ms as u32));
     }
 }
 
@@ -616,6 +652,51 @@ where
                     }).unwrap_or_else(|err| err.into())
             }
 
+            // Clear the advertising data
+            3 => self
+                .app
+                .enter(appid, |app, _| {
+                    if app.process_status != Some(BLEState::ScanningIdle)
+                        && app.process_status != Some(BLEState::AdvertisingIdle)
+                    {
+                        app.adv_data_len = Some(0);
+                        ReturnCode::SUCCESS
+                    } else {
+                        ReturnCode::EBUSY
+                    }
+                }).unwrap_or_else(|err| err.into()),
+
+            // Add an AD structure to the advertising data
+            //
+            // data - AD type
+            4 => self
+                .app
+                .enter(appid, |app, _| {
+                    if app.process_status == Some(BLEState::ScanningIdle)
+                        || app.process_status == Some(BLEState::AdvertisingIdle)
+                    {
+                        return ReturnCode::EBUSY;
+                    }
+                    // Borrow the fields of the app directly, rather than
+                    // through the grant, so that they can be borrowed apart.
+                    let app: &mut App = app;
+                    let ad_type = data as u8;
+                    let len = app.adv_data_len.unwrap_or(0);
+                    let (result, new_len) = match (&mut app.adv_data, &app.ad_structure) {
+                        (&mut Some(ref mut adv_data), &Some(ref contents)) => {
+                            let mut builder =
+                                ble_advertising::AdvData::with_len(adv_data.as_mut(), len);
+                            let result = builder.add(ad_type, contents.as_ref());
+                            (result, builder.len())
+                        }
+                        _ => (ReturnCode::EINVAL, len),
+                    };
+                    if result == ReturnCode::SUCCESS {
+                        app.adv_data_len = Some(new_len);
+                    }
+                    result
+                }).unwrap_or_else(|err| err.into()),
+
             // Passive scanning mode
             5 => self
                 .app
@@ -630,6 +711,26 @@ where
                     }
                 }).unwrap_or_else(|err| err.into()),
 
+            // Set the advertising interval
+            //
+            // data - Advertising interval in ms
+            6 => {
+                if data < ADV_INTERVAL_MIN_MS || data > ADV_INTERVAL_MAX_MS {
+                    return ReturnCode::EINVAL;
+                }
+                self.app
+                    .enter(appid, |app, _| {
+                        app.advertisement_interval_ms = data as u32;
+                        // Reschedule an app that is waiting for its next
+                        // advertising event with the new interval
+                        if app.process_status == Some(BLEState::AdvertisingIdle) {
+                            app.set_next_alarm::<A::Frequency>(self.alarm.now());
+                            self.reset_active_alarm();
+                        }
+                        ReturnCode::SUCCESS
+                    }).unwrap_or_else(|err| err.into())
+            }
+
             _ => ReturnCode::ENOSUPPORT,
         }
     }
@@ -646,6 +747,7 @@ where
                 .app
                 .enter(appid, |app, _| {
                     app.adv_data = slice;
+                    app.adv_data_len = None;
                     if let ReturnCode::SUCCESS = app.generate_random_address(appid) {
                         app.process_status = Some(BLEState::Initialized);
                         ReturnCode::SUCCESS
@@ -666,6 +768,14 @@ where
                     _ => ReturnCode::EINVAL,
                 }).unwrap_or_else(|err| err.into()),
 
+            // AD structure contents
+            2 => self
+                .app
+                .enter(appid, |app, _| {
+                    app.ad_structure = slice;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into()),
+
             // Operation not supported
             _ => ReturnCode::ENOSUPPORT,
         }
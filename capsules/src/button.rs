@@ -11,14 +11,34 @@
 //! let button_pins = static_init!(
 //!     [&'static sam4l::gpio::GPIOPin; 1],
 //!     [&sam4l::gpio::PA[16]]);
+//! let button_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     VirtualMuxAlarm::new(mux_alarm));
 //! let button = static_init!(
-//!     capsules::button::Button<'static, sam4l::gpio::GPIOPin>,
-//!     capsules::button::Button::new(button_pins, kernel::Grant::create()));
+//!     capsules::button::Button<
+//!         'static,
+//!         sam4l::gpio::GPIOPin,
+//!         VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     >,
+//!     capsules::button::Button::new(button_pins, button_alarm, 20, kernel::Grant::create()));
 //! for btn in button_pins.iter() {
 //!     btn.set_client(button);
 //! }
+//! button_alarm.set_client(button);
 //! ```
 //!
+//! Debouncing
+//! ----------
+//!
+//! Mechanical buttons bounce, producing a burst of edges for a single press.
+//! How long they bounce depends on the buttons, so the board chooses a
+//! debounce interval, in milliseconds, when it creates the capsule. While it
+//! is non-zero, an edge does not generate a callback immediately. Instead the
+//! capsule waits until no button has changed for the whole interval, then
+//! reports the settled state of each button that changed, once. Buttons that
+//! bounce back to their previously reported state generate no callback. An
+//! interval of 0 reports every edge.
+//!
 //! Syscall Interface
 //! -----------------
 //!
@@ -50,8 +70,10 @@
 //!   of the button.
 
 use core::cell::Cell;
+use core::cmp;
 use kernel::hil;
 use kernel::hil::gpio::{Client, InterruptMode};
+use kernel::hil::time::{self, Alarm, Frequency};
 use kernel::{AppId, Callback, Driver, Grant, ReturnCode};
 
 /// Syscall driver number.
@@ -78,25 +100,91 @@ pub enum ButtonState {
     Pressed = 1,
 }
 
+/// Converts a debounce interval in milliseconds to tics of frequency `F`,
+/// keeping it well within an alarm's wrapping range.
+fn debounce_tics<F: Frequency>(debounce_ms: u32) -> u32 {
+    let tics = debounce_ms as u64 * F::frequency() as u64 / 1000;
+    cmp::min(tics, (u32::max_value() / 2) as u64) as u32
+}
+
+/// Debounce state for all of the buttons, one bit per button in each map.
+struct Debounce {
+    /// Debounce interval in alarm tics, or 0 if edges are reported directly.
+    tics: u32,
+    /// Buttons that have seen an edge in the current debounce window.
+    pending: Cell<SubscribeMap>,
+    /// Last state reported to apps for each button, set if it was pressed.
+    reported: Cell<SubscribeMap>,
+}
+
+impl Debounce {
+    fn new(tics: u32) -> Debounce {
+        Debounce {
+            tics: tics,
+            pending: Cell::new(0),
+            reported: Cell::new(0),
+        }
+    }
+
+    fn is_pending(&self, pin_num: usize) -> bool {
+        self.pending.get() & (1 << pin_num) != 0
+    }
+
+    fn set_reported(&self, pin_num: usize, button_state: ButtonState) {
+        match button_state {
+            ButtonState::Pressed => self.reported.set(self.reported.get() | 1 << pin_num),
+            ButtonState::NotPressed => self.reported.set(self.reported.get() & !(1 << pin_num)),
+        }
+    }
+
+    /// Notes an edge on `pin_num`. Returns whether the edge starts or extends
+    /// a debounce window, rather than being reported directly.
+    fn edge(&self, pin_num: usize) -> bool {
+        if self.tics == 0 {
+            false
+        } else {
+            self.pending.set(self.pending.get() | 1 << pin_num);
+            true
+        }
+    }
+
+    /// Ends the debounce window, given which buttons are now `pressed`.
+    /// Returns the buttons that saw an edge during the window and have
+    /// settled in a different state than was last reported.
+    fn settle(&self, pressed: SubscribeMap) -> SubscribeMap {
+        let changed = self.pending.get() & (pressed ^ self.reported.get());
+        self.pending.set(0);
+        changed
+    }
+}
+
 /// Manages the list of GPIO pins that are connected to buttons and which apps
 /// are listening for interrupts from which buttons.
-pub struct Button<'a, G: hil::gpio::Pin> {
+pub struct Button<'a, G: hil::gpio::Pin, A: Alarm + 'a> {
     pins: &'a [(&'a G, GpioMode)],
+    alarm: &'a A,
     apps: Grant<(Option<Callback>, SubscribeMap)>,
+    debounce: Debounce,
 }
 
-impl<G: hil::gpio::Pin + hil::gpio::PinCtl> Button<'a, G> {
+impl<G: hil::gpio::Pin + hil::gpio::PinCtl, A: Alarm> Button<'a, G, A> {
+    /// Edges are debounced for `debounce_ms` milliseconds, or reported
+    /// directly if it is 0.
     pub fn new(
         pins: &'a [(&'a G, GpioMode)],
+        alarm: &'a A,
+        debounce_ms: u32,
         grant: Grant<(Option<Callback>, SubscribeMap)>,
-    ) -> Button<'a, G> {
+    ) -> Button<'a, G, A> {
         for &(pin, _) in pins.iter() {
             pin.make_input();
         }
 
         Button {
             pins: pins,
+            alarm: alarm,
             apps: grant,
+            debounce: Debounce::new(debounce_tics::<A::Frequency>(debounce_ms)),
         }
     }
 
@@ -113,9 +201,33 @@ impl<G: hil::gpio::Pin + hil::gpio::PinCtl> Button<'a, G> {
             },
         }
     }
+
+    /// Schedule callbacks for all apps listening to `pin_num` and remember the
+    /// reported state.
+    fn report(&self, pin_num: usize, button_state: ButtonState) {
+        let interrupt_count = Cell::new(0);
+        self.debounce.set_reported(pin_num, button_state);
+
+        // schedule callback with the pin number and value
+        self.apps.each(|cntr| {
+            cntr.0.map(|mut callback| {
+                if cntr.1 & (1 << pin_num) != 0 {
+                    interrupt_count.set(interrupt_count.get() + 1);
+                    callback.schedule(pin_num, button_state as usize, 0);
+                }
+            });
+        });
+
+        // It's possible we got an interrupt for a process that has since died
+        // (and didn't unregister the interrupt). Lazily disable interrupts for
+        // this button if so.
+        if interrupt_count.get() == 0 {
+            self.pins[pin_num].0.disable_interrupt();
+        }
+    }
 }
 
-impl<G: hil::gpio::Pin + hil::gpio::PinCtl> Driver for Button<'a, G> {
+impl<G: hil::gpio::Pin + hil::gpio::PinCtl, A: Alarm> Driver for Button<'a, G, A> {
     /// Set callbacks.
     ///
     /// ### `subscribe_num`
@@ -156,9 +268,9 @@ impl<G: hil::gpio::Pin + hil::gpio::PinCtl> Driver for Button<'a, G> {
     ///
     /// - `0`: Driver check and get number of buttons on the board.
     /// - `1`: Enable interrupts for a given button. This will enable both press
-    ///   and depress events.
+    ///   and depress events. Returns `EALREADY` if they are already enabled.
     /// - `2`: Disable interrupts for a button. No affect or reliance on
-    ///   registered callback.
+    ///   registered callback. Returns `EALREADY` if they are already disabled.
     /// - `3`: Read the current state of the button.
     fn command(&self, command_num: usize, data: usize, _: usize, appid: AppId) -> ReturnCode {
         let pins = self.pins;
@@ -173,7 +285,14 @@ impl<G: hil::gpio::Pin + hil::gpio::PinCtl> Driver for Button<'a, G> {
                 if data < pins.len() {
                     self.apps
                         .enter(appid, |cntr, _| {
+                            if cntr.1 & (1 << data) != 0 {
+                                return ReturnCode::EALREADY;
+                            }
                             cntr.1 |= 1 << data;
+                            if !self.debounce.is_pending(data) {
+                                self.debounce
+                                    .set_reported(data, self.get_button_state(data));
+                            }
                             pins[data]
                                 .0
                                 .enable_interrupt(data, InterruptMode::EitherEdge);
@@ -192,9 +311,15 @@ impl<G: hil::gpio::Pin + hil::gpio::PinCtl> Driver for Button<'a, G> {
                     let res = self
                         .apps
                         .enter(appid, |cntr, _| {
+                            if cntr.1 & (1 << data) == 0 {
+                                return ReturnCode::EALREADY;
+                            }
                             cntr.1 &= !(1 << data);
                             ReturnCode::SUCCESS
                         }).unwrap_or_else(|err| err.into());
+                    if res != ReturnCode::SUCCESS {
+                        return res;
+                    }
 
                     // are any processes waiting for this button?
                     let interrupt_count = Cell::new(0);
@@ -233,27 +358,100 @@ impl<G: hil::gpio::Pin + hil::gpio::PinCtl> Driver for Button<'a, G> {
     }
 }
 
-impl<G: hil::gpio::Pin + hil::gpio::PinCtl> Client for Button<'a, G> {
+impl<G: hil::gpio::Pin + hil::gpio::PinCtl, A: Alarm> Client for Button<'a, G, A> {
     fn fired(&self, pin_num: usize) {
-        // Read the value of the pin and get the button state.
-        let button_state = self.get_button_state(pin_num);
-        let interrupt_count = Cell::new(0);
+        if self.debounce.edge(pin_num) {
+            // Suppress the edge and (re)start the debounce window, so buttons
+            // are only sampled once they have been quiet for the interval.
+            self.alarm
+                .set_alarm(self.alarm.now().wrapping_add(self.debounce.tics));
+        } else {
+            // Read the value of the pin and get the button state.
+            let button_state = self.get_button_state(pin_num);
+            self.report(pin_num, button_state);
+        }
+    }
+}
 
-        // schedule callback with the pin number and value
-        self.apps.each(|cntr| {
-            cntr.0.map(|mut callback| {
-                if cntr.1 & (1 << pin_num) != 0 {
-                    interrupt_count.set(interrupt_count.get() + 1);
-                    callback.schedule(pin_num, button_state as usize, 0);
+impl<G: hil::gpio::Pin + hil::gpio::PinCtl, A: Alarm> time::Client for Button<'a, G, A> {
+    fn fired(&self) {
+        let mut pressed = 0;
+        for pin_num in 0..self.pins.len() {
+            if self.debounce.is_pending(pin_num) {
+                if let ButtonState::Pressed = self.get_button_state(pin_num) {
+                    pressed |= 1 << pin_num;
                 }
-            });
-        });
+            }
+        }
 
-        // It's possible we got an interrupt for a process that has since died
-        // (and didn't unregister the interrupt). Lazily disable interrupts for
-        // this button if so.
-        if interrupt_count.get() == 0 {
-            self.pins[pin_num].0.disable_interrupt();
+        let changed = self.debounce.settle(pressed);
+        for pin_num in 0..self.pins.len() {
+            if changed & (1 << pin_num) != 0 {
+                if pressed & (1 << pin_num) != 0 {
+                    self.report(pin_num, ButtonState::Pressed);
+                } else {
+                    self.report(pin_num, ButtonState::NotPressed);
+                }
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel::hil::time::{Freq16KHz, Freq32KHz};
+
+    #[test]
+    fn debounce_interval_is_converted_to_tics() {
+        assert_eq!(debounce_tics::<Freq16KHz>(0), 0);
+        assert_eq!(debounce_tics::<Freq16KHz>(20), 320);
+        assert_eq!(debounce_tics::<Freq32KHz>(1000), 32768);
+        assert_eq!(debounce_tics::<Freq32KHz>(u32::max_value()), u32::max_value() / 2);
+    }
+
+    #[test]
+    fn without_interval_edges_are_reported_directly() {
+        let debounce = Debounce::new(0);
+        assert!(!debounce.edge(0));
+        assert!(!debounce.is_pending(0));
+    }
+
+    #[test]
+    fn burst_of_edges_is_reported_once() {
+        let debounce = Debounce::new(320);
+
+        // A press that bounces five times before settling is one change.
+        for _ in 0..5 {
+            assert!(debounce.edge(0));
+        }
+        assert!(debounce.is_pending(0));
+        assert_eq!(debounce.settle(0b1), 0b1);
+        assert!(!debounce.is_pending(0));
+        debounce.set_reported(0, ButtonState::Pressed);
+
+        // A burst that settles back in the reported state is no change.
+        for _ in 0..4 {
+            assert!(debounce.edge(0));
+        }
+        assert_eq!(debounce.settle(0b1), 0);
+
+        // The release is reported once it settles.
+        for _ in 0..3 {
+            assert!(debounce.edge(0));
+        }
+        assert_eq!(debounce.settle(0b0), 0b1);
+    }
+
+    #[test]
+    fn only_buttons_with_edges_are_reported() {
+        let debounce = Debounce::new(320);
+        assert!(debounce.edge(0));
+        assert!(debounce.edge(2));
+        assert!(debounce.edge(2));
+
+        // Button 1 reads as pressed but saw no edge, and button 2 bounced
+        // back to released.
+        assert_eq!(debounce.settle(0b011), 0b001);
+    }
+}
@@ -33,9 +33,74 @@
 //! When the buffer has been written successfully, the buffer is released from
 //! the driver. Successive writes must call `allow` each time a buffer is to be
 //! written.
+//!
+//! Receive Ring
+//! ------------
+//!
+//! By default the console only receives from the UART while an app has a read
+//! outstanding, so bytes that arrive between reads are lost. A board can
+//! instead give the console a ring buffer:
+//!
+//! ```rust
+//! console.initialize();
+//! console.enable_receive_ring(&mut console::RX_RING_BUF);
+//! ```
+//!
+//! The console then keeps a UART receive posted at all times and copies each
+//! completed chunk into the ring. App reads are served from the ring. If bytes
+//! arrive while the ring is full they are dropped, and the next read that
+//! completes reports `ESIZE` instead of `SUCCESS`.
+//!
+//! Line Mode
+//! ---------
+//!
+//! Apps that read commands can ask for a callback only once a full line has
+//! been received, by switching to line mode with command 4. This requires the
+//! board to give the console storage for the line being received:
+//!
+//! ```rust
+//! console.enable_line_mode(&mut console::LINE_BUF);
+//! ```
+//!
+//! In line mode a read completes when a `\n` is received, and delivers the
+//! line without the terminator. Backspace and delete remove the last byte of
+//! the line and carriage returns are dropped. If a line is longer than the
+//! line storage or the app's read length, it is truncated and the read reports
+//! `ESIZE`.
+//!
+//! Flushing
+//! --------
+//!
+//! Before rebooting or handing the UART to another user, the kernel can wait
+//! for all console output to be sent by calling `flush()`. The console calls
+//! its `FlushClient` once no write is pending and the UART has completed the
+//! last transmit. Apps can do the same with command 5.
+//!
+//! Echo
+//! ----
+//!
+//! Interactive shells need typed characters echoed back to the terminal. Apps
+//! can turn echo on with command 6 if the board gives the console storage for
+//! bytes waiting to be echoed:
+//!
+//! ```rust
+//! console.enable_echo(&mut console::ECHO_BUF);
+//! ```
+//!
+//! While echo is on, received bytes are transmitted back through the same
+//! transmit buffer as app writes. They are only sent once the write in
+//! progress has completed, so echoes are never interleaved with an app's
+//! output. Backspace and delete are echoed as backspace, space, backspace,
+//! erasing the last character on the terminal. Bytes received while the echo
+//! storage is full are not echoed.
+//!
+//! Echo belongs to the app that turned it on. Other apps cannot change it until
+//! that app turns it off again or exits.
 
+use core::cell::Cell;
 use core::cmp;
-use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::common::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::common::{Queue, RingBuffer};
 use kernel::hil::uart::{self, Client, UART};
 use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
 
@@ -53,10 +118,216 @@ pub struct App {
     read_callback: Option<Callback>,
     read_buffer: Option<AppSlice<Shared, u8>>,
     read_len: usize,
+    line_mode: bool,
+
+    flush_callback: Option<Callback>,
+    flush_pending: bool,
 }
 
 pub static mut WRITE_BUF: [u8; 64] = [0; 64];
 pub static mut READ_BUF: [u8; 64] = [0; 64];
+pub static mut RX_RING_BUF: [u8; 256] = [0; 256];
+pub static mut LINE_BUF: [u8; 64] = [0; 64];
+pub static mut ECHO_BUF: [u8; 32] = [0; 32];
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+
+/// Sent in place of a received backspace or delete, to erase the last
+/// character on the terminal.
+const ERASE_SEQUENCE: [u8; 3] = [BACKSPACE, b' ', BACKSPACE];
+
+/// Identifies a UART receive error to the app in the third argument of the
+/// read callback, so that line errors can be told apart.
+fn line_error(error: uart::Error) -> usize {
+    match error {
+        uart::Error::ParityError => 1,
+        uart::Error::FramingError => 2,
+        uart::Error::OverrunError => 3,
+        uart::Error::BreakError => 4,
+        _ => 0,
+    }
+}
+
+/// Notified when all console output has been transmitted.
+pub trait FlushClient {
+    fn flush_done(&self);
+}
+
+/// A flush requested by the kernel, waiting for the transmit path to become
+/// idle.
+struct FlushRequest<'a> {
+    client: OptionalCell<&'a FlushClient>,
+    requested: Cell<bool>,
+}
+
+impl FlushRequest<'a> {
+    fn new() -> FlushRequest<'a> {
+        FlushRequest {
+            client: OptionalCell::empty(),
+            requested: Cell::new(false),
+        }
+    }
+
+    fn set_client(&self, client: &'a FlushClient) {
+        self.client.set(client);
+    }
+
+    /// Request a flush. Returns `EALREADY` if the transmit path is already
+    /// idle, in which case the client will not be called.
+    fn request(&self, tx_busy: bool) -> ReturnCode {
+        if !tx_busy {
+            ReturnCode::EALREADY
+        } else {
+            self.requested.set(true);
+            ReturnCode::SUCCESS
+        }
+    }
+
+    /// Call the client if a flush was requested, now that the transmit path
+    /// is idle.
+    fn complete(&self) {
+        if self.requested.get() {
+            self.requested.set(false);
+            self.client.map(|client| client.flush_done());
+        }
+    }
+}
+
+/// Received bytes waiting to be echoed.
+struct EchoQueue {
+    ring: MapCell<RingBuffer<'static, u8>>,
+    /// How many bytes the ring can hold, which is one less than its length.
+    capacity: Cell<usize>,
+}
+
+impl EchoQueue {
+    fn new() -> EchoQueue {
+        EchoQueue {
+            ring: MapCell::empty(),
+            capacity: Cell::new(0),
+        }
+    }
+
+    fn set_buffer(&self, buffer: &'static mut [u8]) {
+        self.capacity.set(buffer.len().saturating_sub(1));
+        self.ring.put(RingBuffer::new(buffer));
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.ring.is_some()
+    }
+
+    /// Queue `bytes`, replacing backspace and delete with the erase sequence.
+    /// Bytes that do not fit are dropped.
+    fn push(&self, bytes: &[u8]) {
+        self.ring.map(|ring| {
+            for byte in bytes.iter() {
+                match *byte {
+                    BACKSPACE | DELETE => {
+                        // Only echo the whole sequence, or the terminal is
+                        // left with a half-erased character.
+                        let free = self.capacity.get() - ring.len();
+                        if free >= ERASE_SEQUENCE.len() {
+                            for b in ERASE_SEQUENCE.iter() {
+                                ring.enqueue(*b);
+                            }
+                        }
+                    }
+                    _ => {
+                        ring.enqueue(*byte);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Move as many queued bytes as fit into `buffer`, returning how many
+    /// were moved.
+    fn fill(&self, buffer: &mut [u8]) -> usize {
+        self.ring.map_or(0, |ring| {
+            let mut len = 0;
+            while len < buffer.len() {
+                match ring.dequeue() {
+                    Some(byte) => buffer[len] = byte,
+                    None => break,
+                }
+                len += 1;
+            }
+            len
+        })
+    }
+}
+
+/// The line being received by an app in line mode.
+struct LineBuffer {
+    buffer: TakeCell<'static, [u8]>,
+    len: Cell<usize>,
+    /// Whether bytes were dropped because the line did not fit.
+    overflow: Cell<bool>,
+}
+
+impl LineBuffer {
+    fn new() -> LineBuffer {
+        LineBuffer {
+            buffer: TakeCell::empty(),
+            len: Cell::new(0),
+            overflow: Cell::new(false),
+        }
+    }
+
+    fn set_buffer(&self, buffer: &'static mut [u8]) {
+        self.buffer.replace(buffer);
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.buffer.is_some()
+    }
+
+    /// Add a received byte to the line. Returns true if the byte ends the
+    /// line.
+    fn feed(&self, byte: u8) -> bool {
+        match byte {
+            b'\n' => return true,
+            b'\r' => {}
+            BACKSPACE | DELETE => {
+                self.len.set(self.len.get().saturating_sub(1));
+            }
+            _ => {
+                self.buffer.map(|line| {
+                    let len = self.len.get();
+                    if len < line.len() {
+                        line[len] = byte;
+                        self.len.set(len + 1);
+                    } else {
+                        self.overflow.set(true);
+                    }
+                });
+            }
+        }
+        false
+    }
+
+    /// Copy as much of the line as fits into `out` and start a new line.
+    /// Returns the number of bytes copied and the result of the read: `ESIZE`
+    /// if the line was truncated, `ECANCEL` if the read was aborted before
+    /// the line ended, and `SUCCESS` otherwise.
+    fn take(&self, out: &mut [u8], aborted: bool) -> (usize, ReturnCode) {
+        let line_len = self.len.get();
+        let len = cmp::min(line_len, out.len());
+        let rcode = if self.overflow.get() || len < line_len {
+            ReturnCode::ESIZE
+        } else if aborted {
+            ReturnCode::ECANCEL
+        } else {
+            ReturnCode::SUCCESS
+        };
+        self.buffer.map(|line| out[..len].copy_from_slice(&line[..len]));
+        self.len.set(0);
+        self.overflow.set(false);
+        (len, rcode)
+    }
+}
 
 pub struct Console<'a, U: UART> {
     uart: &'a U,
@@ -65,6 +336,15 @@ pub struct Console<'a, U: UART> {
     tx_buffer: TakeCell<'static, [u8]>,
     rx_in_progress: OptionalCell<AppId>,
     rx_buffer: TakeCell<'static, [u8]>,
+    rx_ring: MapCell<RingBuffer<'static, u8>>,
+    rx_overflow: Cell<bool>,
+    rx_abort_requested: Cell<bool>,
+    line: LineBuffer,
+    flush: FlushRequest<'a>,
+    echo: EchoQueue,
+    /// The app that turned echo on, if echo is on.
+    echo_owner: OptionalCell<AppId>,
+    echo_in_progress: Cell<bool>,
     baud_rate: u32,
 }
 
@@ -83,6 +363,14 @@ impl<U: UART> Console<'a, U> {
             tx_buffer: TakeCell::new(tx_buffer),
             rx_in_progress: OptionalCell::empty(),
             rx_buffer: TakeCell::new(rx_buffer),
+            rx_ring: MapCell::empty(),
+            rx_overflow: Cell::new(false),
+            rx_abort_requested: Cell::new(false),
+            line: LineBuffer::new(),
+            flush: FlushRequest::new(),
+            echo: EchoQueue::new(),
+            echo_owner: OptionalCell::empty(),
+            echo_in_progress: Cell::new(false),
             baud_rate: baud_rate,
         }
     }
@@ -96,6 +384,91 @@ impl<U: UART> Console<'a, U> {
         });
     }
 
+    /// Buffer received bytes in `ring` so that no data is lost while apps are
+    /// not reading. Must be called after `initialize()`.
+    pub fn enable_receive_ring(&self, ring: &'static mut [u8]) {
+        self.rx_ring.put(RingBuffer::new(ring));
+        self.receive_chunk(None);
+    }
+
+    /// Allow apps to switch to line-buffered receives, accumulating each line
+    /// in `line_buffer` until it is complete.
+    pub fn enable_line_mode(&self, line_buffer: &'static mut [u8]) {
+        self.line.set_buffer(line_buffer);
+    }
+
+    /// Allow apps to turn on echo, holding received bytes in `echo_buffer`
+    /// until they can be transmitted.
+    pub fn enable_echo(&self, echo_buffer: &'static mut [u8]) {
+        self.echo.set_buffer(echo_buffer);
+    }
+
+    pub fn set_flush_client(&self, client: &'a FlushClient) {
+        self.flush.set_client(client);
+    }
+
+    /// Wait for all pending output to be transmitted. Returns `SUCCESS` if
+    /// the flush client will be called once the UART has finished sending
+    /// the last byte, or `EALREADY` if no output is pending.
+    pub fn flush(&self) -> ReturnCode {
+        self.flush.request(self.tx_busy())
+    }
+
+    /// Whether the UART is transmitting an app write or echoed bytes.
+    fn tx_busy(&self) -> bool {
+        self.tx_in_progress.is_some() || self.echo_in_progress.get()
+    }
+
+    /// Whether echo is on. Echo turns off once the app that turned it on no
+    /// longer exists.
+    fn echo_on(&self) -> bool {
+        self.echo_owner.map_or(false, |owner| {
+            let alive = self.apps.enter(*owner, |_, _| ()).is_ok();
+            if !alive {
+                self.echo_owner.clear();
+            }
+            alive
+        })
+    }
+
+    /// Queue received bytes to be echoed and start sending them if the UART
+    /// is idle.
+    fn echo_received(&self, bytes: &[u8]) {
+        self.echo.push(bytes);
+        self.echo_start();
+    }
+
+    /// Transmit bytes waiting to be echoed, unless the UART is busy.
+    fn echo_start(&self) {
+        if self.tx_busy() {
+            return;
+        }
+        self.tx_buffer.take().map(|buffer| {
+            let len = self.echo.fill(buffer);
+            if len == 0 {
+                self.tx_buffer.replace(buffer);
+            } else {
+                self.echo_in_progress.set(true);
+                self.uart.transmit(buffer, len);
+            }
+        });
+    }
+
+    /// Signal every outstanding flush once the transmit path is idle. Writes
+    /// that are waiting for the UART always start as soon as it is free, so
+    /// no write is pending once no transmit is in progress.
+    fn flush_complete(&self) {
+        self.flush.complete();
+        for cntr in self.apps.iter() {
+            cntr.enter(|app, _| {
+                if app.flush_pending {
+                    app.flush_pending = false;
+                    app.flush_callback.map(|mut cb| cb.schedule(0, 0, 0));
+                }
+            });
+        }
+    }
+
     /// Internal helper function for setting up a new send transaction
     fn send_new(&self, app_id: AppId, app: &mut App, len: usize) -> ReturnCode {
         match app.write_buffer.take() {
@@ -127,7 +500,7 @@ impl<U: UART> Console<'a, U> {
     /// Internal helper function for sending data for an existing transaction.
     /// Cannot fail. If can't send now, it will schedule for sending later.
     fn send(&self, app_id: AppId, app: &mut App, slice: AppSlice<Shared, u8>) {
-        if self.tx_in_progress.is_none() {
+        if !self.tx_busy() {
             self.tx_in_progress.set(app_id);
             self.tx_buffer.take().map(|buffer| {
                 let mut transaction_len = app.write_remaining;
@@ -161,6 +534,13 @@ impl<U: UART> Console<'a, U> {
 
     /// Internal helper function for starting a receive operation
     fn receive_new(&self, app_id: AppId, app: &mut App, len: usize) -> ReturnCode {
+        if app.line_mode {
+            return self.receive_new_line(app_id, app, len);
+        }
+        if self.rx_ring.is_some() {
+            return self.receive_new_ring(app_id, app, len);
+        }
+
         if self.rx_buffer.is_none() {
             // For now, we tolerate only one concurrent receive operation on this console.
             // Competing apps will have to retry until success.
@@ -190,6 +570,247 @@ impl<U: UART> Console<'a, U> {
             }
         }
     }
+
+    /// Start a receive operation that is served from the receive ring.
+    fn receive_new_ring(&self, app_id: AppId, app: &mut App, len: usize) -> ReturnCode {
+        if self.rx_in_progress.is_some() {
+            return ReturnCode::EBUSY;
+        }
+
+        let read_len = match app.read_buffer {
+            Some(ref slice) => cmp::min(len, slice.len()),
+            None => return ReturnCode::EINVAL,
+        };
+        app.read_len = read_len;
+        self.rx_in_progress.set(app_id);
+
+        // The ring may already hold everything this read needs.
+        if self.ring_bytes_ready(read_len) {
+            self.deliver_from_ring(app, false);
+            self.rx_in_progress.clear();
+        } else if self.rx_buffer.is_some() {
+            self.receive_chunk(Some(read_len));
+        } else {
+            // Flush whatever the UART has received so far. The receive is
+            // re-posted sized to what this read still needs.
+            self.uart.abort_receive();
+        }
+        ReturnCode::SUCCESS
+    }
+
+    /// Start a receive operation that completes once a full line is received.
+    fn receive_new_line(&self, app_id: AppId, app: &mut App, len: usize) -> ReturnCode {
+        if self.rx_in_progress.is_some() || (self.rx_ring.is_none() && self.rx_buffer.is_none()) {
+            return ReturnCode::EBUSY;
+        }
+
+        let read_len = match app.read_buffer {
+            Some(ref slice) => cmp::min(len, slice.len()),
+            None => return ReturnCode::EINVAL,
+        };
+        app.read_len = read_len;
+        self.rx_in_progress.set(app_id);
+
+        if self.rx_ring.is_none() {
+            self.rx_buffer.take().map(|buffer| {
+                self.uart.receive(buffer, 1);
+            });
+        } else if self.line_from_ring() {
+            self.deliver_line(app, false);
+            self.rx_in_progress.clear();
+        } else if self.rx_buffer.is_some() {
+            self.receive_chunk(Some(1));
+        } else {
+            // Flush the UART so that the receive is re-posted one byte at a
+            // time, completing the line as soon as its end arrives.
+            self.uart.abort_receive();
+        }
+        ReturnCode::SUCCESS
+    }
+
+    /// Move bytes from the receive ring to the line being received until the
+    /// line ends. Returns true if the line is complete.
+    fn line_from_ring(&self) -> bool {
+        self.rx_ring.map_or(false, |ring| {
+            while let Some(byte) = ring.dequeue() {
+                if self.line.feed(byte) {
+                    return true;
+                }
+            }
+            false
+        })
+    }
+
+    /// Copy the received line into the app's read buffer, signal the app, and
+    /// start a new line.
+    fn deliver_line(&self, app: &mut App, aborted: bool) {
+        match app.read_buffer.take() {
+            Some(mut app_buffer) => {
+                let read_len = cmp::min(app.read_len, app_buffer.len());
+                let (len, rcode) = self.line.take(&mut app_buffer.as_mut()[..read_len], aborted);
+                app.read_callback.map(|mut cb| {
+                    cb.schedule(From::from(rcode), len, 0);
+                });
+            }
+            None => {
+                self.line.take(&mut [], aborted);
+                app.read_callback.map(|mut cb| {
+                    cb.schedule(From::from(ReturnCode::EINVAL), 0, 0);
+                });
+            }
+        }
+    }
+
+    /// Handle a completed one byte UART receive for an app in line mode.
+    fn receive_complete_line(
+        &self,
+        appid: AppId,
+        buffer: &'static mut [u8],
+        rx_len: usize,
+        error: uart::Error,
+    ) {
+        let line_done = buffer[..rx_len]
+            .iter()
+            .fold(false, |done, byte| done || self.line.feed(*byte));
+        let wait_for_more = self
+            .apps
+            .enter(appid, |app, _| match error {
+                uart::Error::CommandComplete | uart::Error::Aborted => {
+                    if line_done || error == uart::Error::Aborted {
+                        self.deliver_line(app, !line_done);
+                        false
+                    } else {
+                        true
+                    }
+                }
+                _ => {
+                    // Some UART error occurred
+                    app.read_callback.map(|mut cb| {
+                        cb.schedule(From::from(ReturnCode::FAIL), 0, line_error(error));
+                    });
+                    false
+                }
+            }).unwrap_or(false);
+
+        if wait_for_more {
+            self.rx_in_progress.set(appid);
+            self.uart.receive(buffer, 1);
+        } else {
+            self.rx_buffer.replace(buffer);
+        }
+    }
+
+    /// Whether the receive ring can complete a read of `read_len` bytes.
+    fn ring_bytes_ready(&self, read_len: usize) -> bool {
+        self.rx_ring
+            .map_or(false, |ring| ring.len() >= read_len || ring.is_full())
+    }
+
+    /// Post the next UART receive when using the receive ring. If an app is
+    /// waiting for `read_len` bytes, only ask for the bytes it still needs so
+    /// that its read completes as soon as they arrive.
+    fn receive_chunk(&self, read_len: Option<usize>) {
+        self.rx_buffer.take().map(|buffer| {
+            let buffered = self.rx_ring.map_or(0, |ring| ring.len());
+            let needed = read_len.map_or(buffer.len(), |len| len.saturating_sub(buffered));
+            let len = cmp::max(1, cmp::min(needed, buffer.len()));
+            self.uart.receive(buffer, len);
+        });
+    }
+
+    /// Copy up to `app.read_len` bytes from the receive ring into the app's
+    /// read buffer and signal the app.
+    fn deliver_from_ring(&self, app: &mut App, aborted: bool) {
+        let available = self.rx_ring.map_or(0, |ring| ring.len());
+        let len = cmp::min(available, app.read_len);
+        let rcode = if self.rx_overflow.get() {
+            ReturnCode::ESIZE
+        } else if len < app.read_len && aborted {
+            ReturnCode::ECANCEL
+        } else {
+            ReturnCode::SUCCESS
+        };
+        self.rx_overflow.set(false);
+
+        match app.read_buffer.take() {
+            Some(mut app_buffer) => {
+                self.rx_ring.map(|ring| {
+                    for a in app_buffer.iter_mut().take(len) {
+                        *a = ring.dequeue().unwrap_or(0);
+                    }
+                });
+                app.read_callback.map(|mut cb| {
+                    cb.schedule(From::from(rcode), len, 0);
+                });
+            }
+            None => {
+                app.read_callback.map(|mut cb| {
+                    cb.schedule(From::from(ReturnCode::EINVAL), 0, 0);
+                });
+            }
+        }
+    }
+
+    /// Handle a completed UART receive when using the receive ring.
+    fn receive_complete_ring(&self, buffer: &'static mut [u8], rx_len: usize, error: uart::Error) {
+        let aborted = self.rx_abort_requested.get();
+        self.rx_abort_requested.set(false);
+
+        match error {
+            uart::Error::CommandComplete | uart::Error::Aborted => {
+                self.rx_ring.map(|ring| {
+                    for b in buffer.iter().take(rx_len) {
+                        if !ring.enqueue(*b) {
+                            self.rx_overflow.set(true);
+                        }
+                    }
+                });
+                self.rx_buffer.replace(buffer);
+
+                self.rx_in_progress.take().map(|appid| {
+                    let done = self
+                        .apps
+                        .enter(appid, |app, _| {
+                            if app.line_mode {
+                                if self.line_from_ring() || aborted {
+                                    self.deliver_line(app, aborted);
+                                    true
+                                } else {
+                                    false
+                                }
+                            } else if aborted || self.ring_bytes_ready(app.read_len) {
+                                self.deliver_from_ring(app, aborted);
+                                true
+                            } else {
+                                false
+                            }
+                        }).unwrap_or(true);
+                    if !done {
+                        self.rx_in_progress.set(appid);
+                    }
+                });
+            }
+            _ => {
+                // Some UART error occurred
+                self.rx_buffer.replace(buffer);
+                self.rx_in_progress.take().map(|appid| {
+                    self.apps
+                        .enter(appid, |app, _| {
+                            app.read_callback.map(|mut cb| {
+                                cb.schedule(From::from(ReturnCode::FAIL), 0, line_error(error));
+                            });
+                        }).unwrap_or_default();
+                });
+            }
+        }
+
+        let read_len = self.rx_in_progress.map_or(None, |appid| {
+            self.apps
+                .enter(*appid, |app, _| if app.line_mode { 1 } else { app.read_len })
+                .ok()
+        });
+        self.receive_chunk(read_len);
+    }
 }
 
 impl<U: UART> Driver for Console<'a, U> {
@@ -227,6 +848,8 @@ impl<U: UART> Driver for Console<'a, U> {
     /// ### `subscribe_num`
     ///
     /// - `1`: Write buffer completed callback
+    /// - `2`: Read buffer completed callback
+    /// - `3`: Flush completed callback
     fn subscribe(
         &self,
         subscribe_num: usize,
@@ -246,6 +869,12 @@ impl<U: UART> Driver for Console<'a, U> {
                     ReturnCode::SUCCESS
                 }).unwrap_or_else(|err| err.into())
             },
+            3 /* flush done */ => {
+                self.apps.enter(app_id, |app, _| {
+                    app.flush_callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
             _ => ReturnCode::ENOSUPPORT
         }
     }
@@ -261,6 +890,14 @@ impl<U: UART> Driver for Console<'a, U> {
     ///        passed in `arg1`
     /// - `3`: Cancel any in progress receives and return (via callback)
     ///        what has been received so far.
+    /// - `4`: Set the receive mode: `0` delivers bytes as they arrive and `1`
+    ///        delivers one line per receive. Returns `ENOSUPPORT` if the board
+    ///        has not enabled line mode and `EBUSY` during a receive.
+    /// - `5`: Call the flush callback once all pending output, from any app,
+    ///        has been transmitted. Returns `EALREADY` if nothing is pending.
+    /// - `6`: Turn echo of received bytes off (`0`) or on (`1`). Returns
+    ///        `ENOSUPPORT` if the board has not enabled echo and `EBUSY` if
+    ///        another app turned echo on.
     fn command(&self, cmd_num: usize, arg1: usize, _: usize, appid: AppId) -> ReturnCode {
         match cmd_num {
             0 /* check if present */ => ReturnCode::SUCCESS,
@@ -277,9 +914,50 @@ impl<U: UART> Driver for Console<'a, U> {
                 }).unwrap_or_else(|err| err.into())
             },
             3 /* abort rx */ => {
+                if self.rx_ring.is_some() {
+                    self.rx_abort_requested.set(true);
+                }
                 self.uart.abort_receive();
                 ReturnCode::SUCCESS
             }
+            4 /* set receive mode */ => {
+                let line_mode = match arg1 {
+                    0 => false,
+                    1 if self.line.is_enabled() => true,
+                    1 => return ReturnCode::ENOSUPPORT,
+                    _ => return ReturnCode::EINVAL,
+                };
+                if self.rx_in_progress.map_or(false, |id| *id == appid) {
+                    return ReturnCode::EBUSY;
+                }
+                self.apps.enter(appid, |app, _| {
+                    app.line_mode = line_mode;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            }
+            5 /* flush */ => {
+                if !self.tx_busy() {
+                    return ReturnCode::EALREADY;
+                }
+                self.apps.enter(appid, |app, _| {
+                    app.flush_pending = true;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            }
+            6 /* set echo */ => {
+                if !self.echo.is_enabled() {
+                    return ReturnCode::ENOSUPPORT;
+                }
+                if self.echo_on() && self.echo_owner.map_or(false, |owner| *owner != appid) {
+                    return ReturnCode::EBUSY;
+                }
+                match arg1 {
+                    0 => self.echo_owner.clear(),
+                    1 => self.echo_owner.set(appid),
+                    _ => return ReturnCode::EINVAL,
+                }
+                ReturnCode::SUCCESS
+            }
             _ => ReturnCode::ENOSUPPORT
         }
     }
@@ -290,6 +968,7 @@ impl<U: UART> Client for Console<'a, U> {
         // Either print more from the AppSlice or send a callback to the
         // application.
         self.tx_buffer.replace(buffer);
+        self.echo_in_progress.set(false);
         self.tx_in_progress.take().map(|appid| {
             self.apps.enter(appid, |app, _| {
                 match self.send_continue(appid, app) {
@@ -317,9 +996,13 @@ impl<U: UART> Client for Console<'a, U> {
             })
         });
 
+        // Echo typed bytes once the current AppSlice is done, so they do not
+        // appear in the middle of it.
+        self.echo_start();
+
         // If we are not printing more from the current AppSlice,
         // see if any other applications have pending messages.
-        if self.tx_in_progress.is_none() {
+        if !self.tx_busy() {
             for cntr in self.apps.iter() {
                 let started_tx = cntr.enter(|app, _| {
                     if app.pending_write {
@@ -347,9 +1030,37 @@ impl<U: UART> Client for Console<'a, U> {
                 }
             }
         }
+
+        // Nothing more to send, so the final byte has left the UART.
+        if !self.tx_busy() {
+            self.flush_complete();
+        }
     }
 
     fn receive_complete(&self, buffer: &'static mut [u8], rx_len: usize, error: uart::Error) {
+        if self.echo_on()
+            && (error == uart::Error::CommandComplete || error == uart::Error::Aborted)
+        {
+            self.echo_received(&buffer[..rx_len]);
+        }
+
+        if self.rx_ring.is_some() {
+            self.receive_complete_ring(buffer, rx_len, error);
+            return;
+        }
+
+        let line_app = self.rx_in_progress.map_or(None, |appid| {
+            self.apps
+                .enter(*appid, |app, _| app.line_mode)
+                .ok()
+                .and_then(|line_mode| if line_mode { Some(*appid) } else { None })
+        });
+        if let Some(appid) = line_app {
+            self.rx_in_progress.clear();
+            self.receive_complete_line(appid, buffer, rx_len, error);
+            return;
+        }
+
         self.rx_in_progress
             .take()
             .map(|appid| {
@@ -379,7 +1090,7 @@ impl<U: UART> Client for Console<'a, U> {
                                 }
                                 _ => {
                                     // Some UART error occurred
-                                    cb.schedule(From::from(ReturnCode::FAIL), 0, 0);
+                                    cb.schedule(From::from(ReturnCode::FAIL), 0, line_error(error));
                                 }
                             }
                         });
@@ -390,3 +1101,185 @@ impl<U: UART> Client for Console<'a, U> {
         self.rx_buffer.replace(buffer);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::boxed::Box;
+    use self::std::vec;
+    use super::*;
+
+    fn line_buffer(len: usize) -> LineBuffer {
+        let line = LineBuffer::new();
+        line.set_buffer(Box::leak(vec![0; len].into_boxed_slice()));
+        line
+    }
+
+    fn feed(line: &LineBuffer, bytes: &[u8]) -> bool {
+        bytes.iter().fold(false, |done, byte| done || line.feed(*byte))
+    }
+
+    #[test]
+    fn line_is_delivered_without_terminator() {
+        let line = line_buffer(16);
+        assert!(!feed(&line, b"led on"));
+        assert!(feed(&line, b"\r\n"));
+
+        let mut out = [0; 16];
+        assert_eq!(line.take(&mut out, false), (6, ReturnCode::SUCCESS));
+        assert_eq!(&out[..6], b"led on");
+
+        // The next line starts empty.
+        assert!(feed(&line, b"x\n"));
+        assert_eq!(line.take(&mut out, false), (1, ReturnCode::SUCCESS));
+        assert_eq!(out[0], b'x');
+    }
+
+    #[test]
+    fn backspace_and_delete_remove_last_byte() {
+        let line = line_buffer(16);
+        assert!(feed(&line, b"lex\x08d o\x7fon\n"));
+        let mut out = [0; 16];
+        assert_eq!(line.take(&mut out, false), (6, ReturnCode::SUCCESS));
+        assert_eq!(&out[..6], b"led on");
+
+        // Erasing an empty line leaves it empty.
+        assert!(feed(&line, b"\x08\x08ok\n"));
+        assert_eq!(line.take(&mut out, false), (2, ReturnCode::SUCCESS));
+        assert_eq!(&out[..2], b"ok");
+    }
+
+    #[test]
+    fn long_line_is_truncated() {
+        // Longer than the line storage
+        let line = line_buffer(4);
+        assert!(feed(&line, b"abcdef\n"));
+        let mut out = [0; 16];
+        assert_eq!(line.take(&mut out, false), (4, ReturnCode::ESIZE));
+        assert_eq!(&out[..4], b"abcd");
+
+        // Longer than the app's read
+        let line = line_buffer(16);
+        assert!(feed(&line, b"abcdef\n"));
+        assert_eq!(line.take(&mut out[..3], false), (3, ReturnCode::ESIZE));
+        assert_eq!(&out[..3], b"abc");
+
+        // The overflow does not carry over to the next line.
+        assert!(feed(&line, b"ab\n"));
+        assert_eq!(line.take(&mut out, false), (2, ReturnCode::SUCCESS));
+    }
+
+    #[test]
+    fn aborted_line_is_delivered_as_cancelled() {
+        let line = line_buffer(16);
+        assert!(!feed(&line, b"partial"));
+        let mut out = [0; 16];
+        assert_eq!(line.take(&mut out, true), (7, ReturnCode::ECANCEL));
+        assert_eq!(&out[..7], b"partial");
+    }
+
+    fn echo_queue(len: usize) -> EchoQueue {
+        let echo = EchoQueue::new();
+        echo.set_buffer(Box::leak(vec![0; len].into_boxed_slice()));
+        echo
+    }
+
+    #[test]
+    fn echoed_bytes_are_sent_in_order() {
+        let echo = echo_queue(16);
+        echo.push(b"ab");
+        echo.push(b"c");
+
+        // Bytes that do not fit in the transmit buffer wait for the next one.
+        let mut buffer = [0; 2];
+        assert_eq!(echo.fill(&mut buffer), 2);
+        assert_eq!(&buffer, b"ab");
+        assert_eq!(echo.fill(&mut buffer), 1);
+        assert_eq!(buffer[0], b'c');
+        assert_eq!(echo.fill(&mut buffer), 0);
+    }
+
+    #[test]
+    fn backspace_and_delete_are_echoed_as_erase() {
+        let echo = echo_queue(16);
+        echo.push(b"a\x08b\x7f");
+        let mut buffer = [0; 16];
+        assert_eq!(echo.fill(&mut buffer), 8);
+        assert_eq!(&buffer[..8], b"a\x08 \x08b\x08 \x08");
+    }
+
+    #[test]
+    fn full_echo_queue_drops_bytes() {
+        // Holds 4 bytes
+        let echo = echo_queue(5);
+        echo.push(b"abcdef");
+        let mut buffer = [0; 16];
+        assert_eq!(echo.fill(&mut buffer), 4);
+        assert_eq!(&buffer[..4], b"abcd");
+
+        // An erase sequence is dropped whole rather than split.
+        echo.push(b"ab\x08");
+        assert_eq!(echo.fill(&mut buffer), 2);
+        assert_eq!(&buffer[..2], b"ab");
+        echo.push(b"a\x08");
+        assert_eq!(echo.fill(&mut buffer), 4);
+        assert_eq!(&buffer[..4], b"a\x08 \x08");
+    }
+
+    #[test]
+    fn line_errors_are_reported_with_documented_codes() {
+        assert_eq!(line_error(uart::Error::ParityError), 1);
+        assert_eq!(line_error(uart::Error::FramingError), 2);
+        assert_eq!(line_error(uart::Error::OverrunError), 3);
+        assert_eq!(line_error(uart::Error::BreakError), 4);
+
+        // Other failures are not line errors.
+        assert_eq!(line_error(uart::Error::RepeatCallError), 0);
+        assert_eq!(line_error(uart::Error::ResetError), 0);
+    }
+
+    struct FlushCounter {
+        flushes: Cell<usize>,
+    }
+
+    impl FlushClient for FlushCounter {
+        fn flush_done(&self) {
+            self.flushes.set(self.flushes.get() + 1);
+        }
+    }
+
+    #[test]
+    fn flush_completes_once_output_is_sent() {
+        let counter = FlushCounter {
+            flushes: Cell::new(0),
+        };
+        let flush = FlushRequest::new();
+        flush.set_client(&counter);
+
+        // Requests made while output is pending are all answered by the
+        // one completion.
+        assert_eq!(flush.request(true), ReturnCode::SUCCESS);
+        assert_eq!(flush.request(true), ReturnCode::SUCCESS);
+        assert_eq!(counter.flushes.get(), 0);
+        flush.complete();
+        assert_eq!(counter.flushes.get(), 1);
+
+        // Later transmits do not complete a flush that was already done.
+        flush.complete();
+        assert_eq!(counter.flushes.get(), 1);
+    }
+
+    #[test]
+    fn flush_with_nothing_pending_is_already_done() {
+        let counter = FlushCounter {
+            flushes: Cell::new(0),
+        };
+        let flush = FlushRequest::new();
+        flush.set_client(&counter);
+
+        assert_eq!(flush.request(false), ReturnCode::EALREADY);
+        flush.complete();
+        assert_eq!(counter.flushes.get(), 0);
+    }
+}
@@ -0,0 +1,95 @@
+//! Provides userspace with the chip's unique device identifier.
+//!
+//! Apps can use the identifier to form EUI-64 addresses or to identify the
+//! device to a server. The identifier is copied synchronously into a buffer
+//! the app has shared with `allow`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let device_id = static_init!(
+//!     capsules::device_id::DeviceIdDriver<'static>,
+//!     capsules::device_id::DeviceIdDriver::new(
+//!         &nrf52::ficr::FICR_INSTANCE,
+//!         board_kernel.create_grant(&memory_allocation_capability)));
+//! ```
+
+use kernel::hil::device_id::DeviceId;
+use kernel::{AppId, AppSlice, Driver, Grant, ReturnCode, Shared};
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = 0x00009;
+
+#[derive(Default)]
+pub struct App {
+    buffer: Option<AppSlice<Shared, u8>>,
+}
+
+pub struct DeviceIdDriver<'a> {
+    device_id: &'a DeviceId,
+    apps: Grant<App>,
+}
+
+impl DeviceIdDriver<'a> {
+    pub fn new(device_id: &'a DeviceId, grant: Grant<App>) -> DeviceIdDriver<'a> {
+        DeviceIdDriver {
+            device_id: device_id,
+            apps: grant,
+        }
+    }
+}
+
+impl Driver for DeviceIdDriver<'a> {
+    /// Share a buffer to read the identifier into.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: Buffer for the identifier.
+    fn allow(
+        &self,
+        appid: AppId,
+        allow_num: usize,
+        slice: Option<AppSlice<Shared, u8>>,
+    ) -> ReturnCode {
+        match allow_num {
+            0 => self
+                .apps
+                .enter(appid, |app, _| {
+                    app.buffer = slice;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into()),
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+
+    /// Read the device identifier.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Get the length of the identifier in bytes.
+    /// - `2`: Copy the identifier into the allowed buffer, most significant
+    ///        byte first, and return the number of bytes copied. Returns
+    ///        `ERESERVE` if no buffer has been allowed.
+    fn command(&self, command_num: usize, _: usize, _: usize, appid: AppId) -> ReturnCode {
+        match command_num {
+            0 /* check if present */ => ReturnCode::SUCCESS,
+
+            1 => ReturnCode::SuccessWithValue {
+                value: self.device_id.id_len(),
+            },
+
+            2 => self
+                .apps
+                .enter(appid, |app, _| match app.buffer {
+                    Some(ref mut buffer) => ReturnCode::SuccessWithValue {
+                        value: self.device_id.read_id(buffer.as_mut()),
+                    },
+                    None => ReturnCode::ERESERVE,
+                }).unwrap_or_else(|err| err.into()),
+
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Provides userspace with timestamps of the edges on a GPIO pin.
+//!
+//! Each edge is reported to subscribed apps with its direction and a
+//! timestamp, so that apps can measure pulse widths and frequencies by
+//! subtracting timestamps. The timestamps are latched by the hardware when
+//! the edge happens, through a `hil::gpio::EdgeCapture` implementation such
+//! as the nRF52's, so they include neither the kernel's interrupt latency nor
+//! the time the app waits to be scheduled. The pin is shared by all apps.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let edge_capture = static_init!(
+//!     capsules::edge_capture::EdgeCapture<'static>,
+//!     capsules::edge_capture::EdgeCapture::new(
+//!         nrf52_edge_capture,
+//!         board_kernel.create_grant(&memory_allocation_capability)));
+//! hil::gpio::EdgeCapture::set_client(nrf52_edge_capture, edge_capture);
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Command
+//!
+//! #### `command_num`
+//!
+//! - `0`: Driver check.
+//! - `1`: Start timestamping edges for this app.
+//! - `2`: Stop timestamping edges for this app.
+//! - `3`: Get the frequency of the timestamps, in Hz.
+//!
+//! ### Subscribe
+//!
+//! #### `subscribe_num`
+//!
+//! - `0`: Set the callback for edges. It is called with the direction of the
+//!   edge (1 for rising, 0 for falling) and its timestamp.
+
+use core::cell::Cell;
+use kernel::hil;
+use kernel::hil::gpio::EdgeCaptureClient;
+use kernel::{AppId, Callback, Driver, Grant, ReturnCode};
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = 0x0000A;
+
+#[derive(Default)]
+pub struct App {
+    callback: Option<Callback>,
+    capturing: bool,
+}
+
+pub struct EdgeCapture<'a> {
+    capture: &'a hil::gpio::EdgeCapture,
+    apps: Grant<App>,
+}
+
+impl EdgeCapture<'a> {
+    pub fn new(capture: &'a hil::gpio::EdgeCapture, grant: Grant<App>) -> EdgeCapture<'a> {
+        EdgeCapture {
+            capture: capture,
+            apps: grant,
+        }
+    }
+
+    fn any_capturing(&self) -> bool {
+        let capturing = Cell::new(false);
+        self.apps.each(|app| {
+            if app.capturing {
+                capturing.set(true);
+            }
+        });
+        capturing.get()
+    }
+}
+
+impl Driver for EdgeCapture<'a> {
+    fn subscribe(
+        &self,
+        subscribe_num: usize,
+        callback: Option<Callback>,
+        app_id: AppId,
+    ) -> ReturnCode {
+        match subscribe_num {
+            0 => self
+                .apps
+                .enter(app_id, |app, _| {
+                    app.callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into()),
+
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+
+    fn command(&self, command_num: usize, _: usize, _: usize, appid: AppId) -> ReturnCode {
+        match command_num {
+            0 /* check if present */ => ReturnCode::SUCCESS,
+
+            1 => {
+                let start = !self.any_capturing();
+                self.apps
+                    .enter(appid, |app, _| {
+                        if app.capturing {
+                            return ReturnCode::EALREADY;
+                        }
+                        if start {
+                            let res = self.capture.start();
+                            if res != ReturnCode::SUCCESS {
+                                return res;
+                            }
+                        }
+                        app.capturing = true;
+                        ReturnCode::SUCCESS
+                    }).unwrap_or_else(|err| err.into())
+            }
+
+            2 => {
+                let res = self
+                    .apps
+                    .enter(appid, |app, _| {
+                        if !app.capturing {
+                            return ReturnCode::EALREADY;
+                        }
+                        app.capturing = false;
+                        ReturnCode::SUCCESS
+                    }).unwrap_or_else(|err| err.into());
+                if res == ReturnCode::SUCCESS && !self.any_capturing() {
+                    self.capture.stop();
+                }
+                res
+            }
+
+            3 => ReturnCode::SuccessWithValue {
+                value: self.capture.frequency() as usize,
+            },
+
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+}
+
+impl EdgeCaptureClient for EdgeCapture<'a> {
+    fn captured(&self, rising: bool, timestamp: u32) {
+        self.apps.each(|app| {
+            if app.capturing {
+                app.callback
+                    .map(|mut callback| callback.schedule(rising as usize, timestamp as usize, 0));
+            }
+        });
+    }
+}
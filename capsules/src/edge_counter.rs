@@ -0,0 +1,77 @@
+//! Provides userspace with a hardware edge counter on a GPIO pin.
+//!
+//! Edges are counted by the hardware, so reading a tachometer or encoder at
+//! high frequencies does not cost a callback per edge. Apps start counting,
+//! then read the count periodically and reset it as needed. The counter is
+//! shared by all apps.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let edge_counter = static_init!(
+//!     capsules::edge_counter::EdgeCounter<'static>,
+//!     capsules::edge_counter::EdgeCounter::new(nrf52_edge_counter));
+//! ```
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = 0x00000008;
+
+use kernel::hil;
+use kernel::hil::gpio::InterruptMode;
+use kernel::{AppId, Driver, ReturnCode};
+
+pub struct EdgeCounter<'a> {
+    counter: &'a hil::gpio::EdgeCounter,
+}
+
+impl EdgeCounter<'a> {
+    pub fn new(counter: &'a hil::gpio::EdgeCounter) -> EdgeCounter<'a> {
+        EdgeCounter { counter: counter }
+    }
+}
+
+impl Driver for EdgeCounter<'a> {
+    /// Control the edge counter.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Start counting. `data` selects the edges to count: `0` for
+    ///   either edge, `1` for rising edges and `2` for falling edges. Returns
+    ///   `EBUSY` if already counting.
+    /// - `2`: Stop counting. The count is kept.
+    /// - `3`: Read the number of edges counted since the last reset.
+    /// - `4`: Reset the count to zero.
+    fn command(&self, command_num: usize, data: usize, _: usize, _: AppId) -> ReturnCode {
+        match command_num {
+            0 /* check if present */ => ReturnCode::SUCCESS,
+
+            1 => {
+                let mode = match data {
+                    0 => InterruptMode::EitherEdge,
+                    1 => InterruptMode::RisingEdge,
+                    2 => InterruptMode::FallingEdge,
+                    _ => return ReturnCode::EINVAL,
+                };
+                self.counter.start(mode)
+            }
+
+            2 => {
+                self.counter.stop();
+                ReturnCode::SUCCESS
+            }
+
+            3 => ReturnCode::SuccessWithValue {
+                value: self.counter.count() as usize,
+            },
+
+            4 => {
+                self.counter.reset();
+                ReturnCode::SUCCESS
+            }
+
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Provides userspace with energy detect measurements on 802.15.4 channels.
+//!
+//! Apps can measure the energy on a channel before choosing one to use, or
+//! to tune a clear channel assessment threshold. Only one measurement runs at
+//! a time; a request made while another app's measurement is in progress
+//! returns `EBUSY`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let energy_detect = static_init!(
+//!     capsules::energy_detect::EnergyDetectDriver<'static>,
+//!     capsules::energy_detect::EnergyDetectDriver::new(
+//!         &nrf52::radio::RADIO,
+//!         board_kernel.create_grant(&memory_allocation_capability)));
+//! kernel::hil::radio::EnergyDetect::set_energy_detect_client(
+//!     &nrf52::radio::RADIO,
+//!     energy_detect);
+//! ```
+
+use kernel::common::cells::OptionalCell;
+use kernel::hil::radio;
+use kernel::{AppId, Callback, Driver, Grant, ReturnCode};
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = 0x30003;
+
+#[derive(Default)]
+pub struct App {
+    callback: Option<Callback>,
+}
+
+pub struct EnergyDetectDriver<'a> {
+    radio: &'a radio::EnergyDetect,
+    apps: Grant<App>,
+    current_app: OptionalCell<AppId>,
+}
+
+impl EnergyDetectDriver<'a> {
+    pub fn new(radio: &'a radio::EnergyDetect, grant: Grant<App>) -> EnergyDetectDriver<'a> {
+        EnergyDetectDriver {
+            radio: radio,
+            apps: grant,
+            current_app: OptionalCell::empty(),
+        }
+    }
+}
+
+impl Driver for EnergyDetectDriver<'a> {
+    /// Subscribe to energy detect results.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: Set the callback for a finished measurement. It is called with
+    ///   the `ReturnCode` of the measurement and, on success, the measured
+    ///   energy in dBm as a signed value.
+    fn subscribe(
+        &self,
+        subscribe_num: usize,
+        callback: Option<Callback>,
+        app_id: AppId,
+    ) -> ReturnCode {
+        match subscribe_num {
+            0 => self
+                .apps
+                .enter(app_id, |app, _| {
+                    app.callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into()),
+
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+
+    /// Start a measurement.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Measure the energy on channel `data` (11-26). Returns `EINVAL`
+    ///   for an invalid channel and `EBUSY` if a measurement is in progress
+    ///   or the radio is in use.
+    fn command(&self, command_num: usize, data: usize, _: usize, appid: AppId) -> ReturnCode {
+        match command_num {
+            0 /* check if present */ => ReturnCode::SUCCESS,
+
+            1 => {
+                if self.current_app.is_some() {
+                    return ReturnCode::EBUSY;
+                }
+                if data > u8::max_value() as usize {
+                    return ReturnCode::EINVAL;
+                }
+                let result = self.radio.energy_detect(data as u8);
+                if result == ReturnCode::SUCCESS {
+                    self.current_app.set(appid);
+                }
+                result
+            }
+
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+}
+
+impl radio::EnergyDetectClient for EnergyDetectDriver<'a> {
+    fn energy_detect_done(&self, rssi: i8, result: ReturnCode) {
+        self.current_app.take().map(|appid| {
+            let _ = self.apps.enter(appid, |app, _| {
+                app.callback.map(|mut cb| {
+                    cb.schedule(usize::from(result), rssi as isize as usize, 0);
+                });
+            });
+        });
+    }
+}
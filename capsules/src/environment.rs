@@ -0,0 +1,206 @@
+//! Provides userspace with access to the temperature, humidity, and pressure
+//! readings of a combined environment sensor, such as a BME280.
+//!
+//! Each kind of reading comes from its own `hil::sensors` driver, so the
+//! readings may also come from separate sensors. A board passes only the
+//! drivers for the sensors it has, and the commands for the others return
+//! `ENODEVICE`. Each kind of reading can be in progress at the same time, and
+//! every app that asked for a kind of reading while it was in progress
+//! receives it.
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `subscribe` System Call
+//!
+//! * `0`: Set the callback that receives readings. The callback's first
+//!   argument is the command number of the reading (`1`, `2`, or `3`), and its
+//!   second argument is the reading.
+//!
+//! ### `command` System Call
+//!
+//! * `0`: Check whether the driver exists.
+//! * `1`: Read the temperature in hundredths of degrees centigrade.
+//! * `2`: Read the humidity in hundredths of percent.
+//! * `3`: Read the pressure in pascals.
+//!
+//! The read commands return `EBUSY` if the app is already waiting for that
+//! kind of reading, and `ENODEVICE` if the board has no sensor for it.
+//!
+//! Usage
+//! -----
+//!
+//! You need drivers that provide the `hil::sensors` traits for the readings
+//! the board supports.
+//!
+//! ```rust
+//! let environment = static_init!(
+//!     capsules::environment::EnvironmentSensor<'static>,
+//!     capsules::environment::EnvironmentSensor::new(
+//!         Some(bme280),
+//!         Some(bme280),
+//!         Some(bme280),
+//!         board_kernel.create_grant(&memory_allocation_capability)
+//!     )
+//! );
+//! kernel::hil::sensors::TemperatureDriver::set_client(bme280, environment);
+//! kernel::hil::sensors::HumidityDriver::set_client(bme280, environment);
+//! kernel::hil::sensors::PressureDriver::set_client(bme280, environment);
+//! ```
+
+use core::cell::Cell;
+use kernel::hil::sensors::{HumidityClient, HumidityDriver};
+use kernel::hil::sensors::{PressureClient, PressureDriver};
+use kernel::hil::sensors::{TemperatureClient, TemperatureDriver};
+use kernel::{AppId, Callback, Driver, Grant, ReturnCode};
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = 0x60006;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Reading {
+    Temperature = 1,
+    Humidity = 2,
+    Pressure = 3,
+}
+
+#[derive(Default)]
+pub struct App {
+    callback: Option<Callback>,
+    /// Which kinds of reading the app is waiting for, one bit per `Reading`.
+    pending: u8,
+}
+
+pub struct EnvironmentSensor<'a> {
+    temperature: Option<&'a TemperatureDriver>,
+    humidity: Option<&'a HumidityDriver>,
+    pressure: Option<&'a PressureDriver>,
+    apps: Grant<App>,
+    /// Which kinds of reading are in progress, one bit per `Reading`.
+    busy: Cell<u8>,
+}
+
+impl EnvironmentSensor<'a> {
+    pub fn new(
+        temperature: Option<&'a TemperatureDriver>,
+        humidity: Option<&'a HumidityDriver>,
+        pressure: Option<&'a PressureDriver>,
+        grant: Grant<App>,
+    ) -> EnvironmentSensor<'a> {
+        EnvironmentSensor {
+            temperature: temperature,
+            humidity: humidity,
+            pressure: pressure,
+            apps: grant,
+            busy: Cell::new(0),
+        }
+    }
+
+    fn start_reading(&self, reading: Reading) -> ReturnCode {
+        match reading {
+            Reading::Temperature => self
+                .temperature
+                .map_or(ReturnCode::ENODEVICE, |sensor| sensor.read_temperature()),
+            Reading::Humidity => self
+                .humidity
+                .map_or(ReturnCode::ENODEVICE, |sensor| sensor.read_humidity()),
+            Reading::Pressure => self
+                .pressure
+                .map_or(ReturnCode::ENODEVICE, |sensor| sensor.read_pressure()),
+        }
+    }
+
+    /// Asks for a reading on behalf of `appid`, starting it unless it is
+    /// already in progress for another app.
+    fn enqueue_reading(&self, reading: Reading, appid: AppId) -> ReturnCode {
+        let bit = 1 << reading as u8;
+        self.apps
+            .enter(appid, |app, _| {
+                if app.pending & bit != 0 {
+                    return ReturnCode::EBUSY;
+                }
+                if self.busy.get() & bit == 0 {
+                    let result = self.start_reading(reading);
+                    if result != ReturnCode::SUCCESS {
+                        return result;
+                    }
+                    self.busy.set(self.busy.get() | bit);
+                }
+                app.pending |= bit;
+                ReturnCode::SUCCESS
+            }).unwrap_or_else(|err| err.into())
+    }
+
+    /// Delivers a finished reading to every app waiting for it.
+    fn reading_done(&self, reading: Reading, value: usize) {
+        let bit = 1 << reading as u8;
+        self.busy.set(self.busy.get() & !bit);
+        self.apps.each(|app| {
+            if app.pending & bit != 0 {
+                app.pending &= !bit;
+                app.callback
+                    .map(|mut cb| cb.schedule(reading as usize, value, 0));
+            }
+        });
+    }
+}
+
+impl TemperatureClient for EnvironmentSensor<'a> {
+    fn callback(&self, value: usize) {
+        self.reading_done(Reading::Temperature, value);
+    }
+}
+
+impl HumidityClient for EnvironmentSensor<'a> {
+    fn callback(&self, value: usize) {
+        self.reading_done(Reading::Humidity, value);
+    }
+}
+
+impl PressureClient for EnvironmentSensor<'a> {
+    fn callback(&self, value: usize) {
+        self.reading_done(Reading::Pressure, value);
+    }
+}
+
+impl Driver for EnvironmentSensor<'a> {
+    /// Subscribe to readings.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: Set the callback that receives readings.
+    fn subscribe(
+        &self,
+        subscribe_num: usize,
+        callback: Option<Callback>,
+        app_id: AppId,
+    ) -> ReturnCode {
+        match subscribe_num {
+            0 => self
+                .apps
+                .enter(app_id, |app, _| {
+                    app.callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into()),
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+
+    /// Start readings.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Read the temperature.
+    /// - `2`: Read the humidity.
+    /// - `3`: Read the pressure.
+    fn command(&self, command_num: usize, _: usize, _: usize, appid: AppId) -> ReturnCode {
+        match command_num {
+            0 => ReturnCode::SUCCESS,
+            1 => self.enqueue_reading(Reading::Temperature, appid),
+            2 => self.enqueue_reading(Reading::Humidity, appid),
+            3 => self.enqueue_reading(Reading::Pressure, appid),
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Lets an app make itself fault, for testing how the kernel handles faults.
+//!
+//! Exercising the `FaultResponse` handling of the kernel, such as restarting
+//! a faulted app, otherwise requires an app that crashes. This driver puts
+//! the calling app into the fault state on request, exactly as if it had
+//! caused an MPU violation, so test harnesses can trigger a fault
+//! deterministically.
+//!
+//! The capsule is only compiled when the `fault_injection` feature of the
+//! capsules crate is enabled, and needs the `ProcessManagementCapability`,
+//! so it cannot be included in production builds by accident.
+//!
+//! The fault cannot be applied while the kernel is still handling the
+//! command, since the kernel writes the command's return value to the app's
+//! stack afterwards. Instead, the fault is applied when an alarm fires
+//! shortly after the command returns. Apps should yield after the command.
+//!
+//! Usage
+//! -----
+//!
+//! Enable the feature for the board in its `Cargo.toml`:
+//!
+//! ```toml
+//! capsules = { path = "../../capsules", features = ["fault_injection"] }
+//! ```
+//!
+//! ```rust
+//! struct ProcessMgmtCap;
+//! unsafe impl capabilities::ProcessManagementCapability for ProcessMgmtCap {}
+//! let fault_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! let fault_injector = static_init!(
+//!     capsules::fault_injector::FaultInjector<
+//!         'static,
+//!         VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!         ProcessMgmtCap,
+//!     >,
+//!     capsules::fault_injector::FaultInjector::new(board_kernel, fault_alarm, ProcessMgmtCap)
+//! );
+//! fault_alarm.set_client(fault_injector);
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! - Command 0: check whether the driver exists.
+//! - Command 1: fault the calling app shortly after the command returns.
+//!   Returns `EBUSY` if a fault is already pending for any app.
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::common::cells::OptionalCell;
+use kernel::hil::time::{self, Alarm, Frequency};
+use kernel::{AppId, Driver, Kernel, ReturnCode};
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = 0x10002;
+
+pub struct FaultInjector<'a, A: Alarm, C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    capability: C,
+    pending: PendingFault<'a, A>,
+}
+
+impl<A: Alarm, C: ProcessManagementCapability> FaultInjector<'a, A, C> {
+    pub fn new(kernel: &'static Kernel, alarm: &'a A, capability: C) -> FaultInjector<'a, A, C> {
+        FaultInjector {
+            kernel: kernel,
+            capability: capability,
+            pending: PendingFault::new(alarm),
+        }
+    }
+}
+
+impl<A: Alarm, C: ProcessManagementCapability> time::Client for FaultInjector<'a, A, C> {
+    fn fired(&self) {
+        self.pending.take().map(|target| {
+            self.kernel
+                .process_each_capability(&self.capability, |i, process| {
+                    if i == target {
+                        process.set_fault_state();
+                    }
+                });
+        });
+    }
+}
+
+impl<A: Alarm, C: ProcessManagementCapability> Driver for FaultInjector<'a, A, C> {
+    /// Fault the calling app.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Fault the calling app shortly after the command returns.
+    fn command(&self, command_num: usize, _: usize, _: usize, appid: AppId) -> ReturnCode {
+        match command_num {
+            0 => ReturnCode::SUCCESS,
+            1 => self.pending.request(appid.idx()),
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+}
+
+/// The app waiting to be faulted, and the alarm that triggers the fault.
+struct PendingFault<'a, A: Alarm> {
+    alarm: &'a A,
+    /// Index of the app to fault when the alarm fires.
+    target: OptionalCell<usize>,
+}
+
+impl<A: Alarm> PendingFault<'a, A> {
+    fn new(alarm: &'a A) -> PendingFault<'a, A> {
+        PendingFault {
+            alarm: alarm,
+            target: OptionalCell::empty(),
+        }
+    }
+
+    /// Arm the alarm to fault app `target` about a millisecond from now.
+    fn request(&self, target: usize) -> ReturnCode {
+        if self.target.is_some() {
+            return ReturnCode::EBUSY;
+        }
+        self.target.set(target);
+        let delay = <A::Frequency>::frequency() / 1000 + 1;
+        self.alarm.set_alarm(self.alarm.now().wrapping_add(delay));
+        ReturnCode::SUCCESS
+    }
+
+    /// The app to fault now that the alarm has fired, if any.
+    fn take(&self) -> Option<usize> {
+        self.target.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+    use kernel::hil::time::{Freq32KHz, Time};
+
+    struct TestAlarm {
+        now: Cell<u32>,
+        alarm: Cell<Option<u32>>,
+    }
+
+    impl Time for TestAlarm {
+        type Frequency = Freq32KHz;
+
+        fn disable(&self) {
+            self.alarm.set(None);
+        }
+
+        fn is_armed(&self) -> bool {
+            self.alarm.get().is_some()
+        }
+    }
+
+    impl Alarm for TestAlarm {
+        fn now(&self) -> u32 {
+            self.now.get()
+        }
+
+        fn set_alarm(&self, tics: u32) {
+            self.alarm.set(Some(tics));
+        }
+
+        fn get_alarm(&self) -> u32 {
+            self.alarm.get().unwrap_or(0)
+        }
+    }
+
+    #[test]
+    fn app_can_fault_again_after_restart() {
+        let alarm = TestAlarm {
+            now: Cell::new(0xFFFF_FFF0),
+            alarm: Cell::new(None),
+        };
+        let pending = PendingFault::new(&alarm);
+
+        // The fault is applied about a millisecond after the command.
+        assert_eq!(pending.request(2), ReturnCode::SUCCESS);
+        assert_eq!(alarm.alarm.get(), Some(0xFFFF_FFF0u32.wrapping_add(33)));
+
+        // Only one fault can be pending, for any app.
+        assert_eq!(pending.request(2), ReturnCode::EBUSY);
+        assert_eq!(pending.request(1), ReturnCode::EBUSY);
+
+        // When the alarm fires the app is faulted, and once it has restarted
+        // it can ask to fault again.
+        assert_eq!(pending.take(), Some(2));
+        assert_eq!(pending.take(), None);
+        assert_eq!(pending.request(2), ReturnCode::SUCCESS);
+        assert_eq!(pending.take(), Some(2));
+    }
+}
@@ -3,7 +3,7 @@
 //! GPIOs are presented through a driver interface with synchronous commands
 //! and a callback for interrupts.
 //!
-//! This capsule takes an array of pins to expose as generic GPIOs.
+//! This capsule takes a group of pins to expose as generic GPIOs.
 //! Note that this capsule is used for general purpose GPIOs. Pins that are
 //! attached to LEDs or buttons are generally wired directly to those capsules,
 //! not through this capsule as an intermediary.
@@ -18,12 +18,21 @@
 //!      &sam4l::gpio::PB[15],
 //!      &sam4l::gpio::PB[11],
 //!      &sam4l::gpio::PB[12]]);
+//! let gpio_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     VirtualMuxAlarm::new(mux_alarm));
+//! let gpio_pin_group = static_init!(
+//!     kernel::hil::gpio::PinGroup<'static, sam4l::gpio::GPIOPin>,
+//!     kernel::hil::gpio::PinGroup::new(gpio_pins));
 //! let gpio = static_init!(
-//!     capsules::gpio::GPIO<'static, sam4l::gpio::GPIOPin>,
-//!     capsules::gpio::GPIO::new(gpio_pins));
-//! for pin in gpio_pins.iter() {
-//!     pin.set_client(gpio);
-//! }
+//!     capsules::gpio::GPIO<
+//!         'static,
+//!         sam4l::gpio::GPIOPin,
+//!         VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     >,
+//!     capsules::gpio::GPIO::new(gpio_pin_group, gpio_alarm));
+//! gpio_pin_group.set_client(gpio);
+//! gpio_alarm.set_client(gpio);
 //! ```
 //!
 //! Syscall Interface
@@ -33,38 +42,92 @@
 //!
 //! ### Commands
 //!
-//! All GPIO operations are synchronous.
+//! All GPIO operations are synchronous, except for pulses.
 //!
 //! Commands control and query GPIO information, namely how many GPIOs are
 //! present, the GPIO direction and state, and whether they should interrupt.
 //!
+//! A pulse drives a pin to a level for a number of microseconds and then
+//! returns it to the opposite, idle level. The pulse length is timed with an
+//! alarm, so its precision is limited by the alarm's frequency. Only one pulse
+//! can be in progress at a time.
+//!
+//! Batch commands write or read several pins at once, selected by a mask with
+//! one bit per pin number. On chips that support port access, all selected
+//! pins that belong to the same hardware port change in a single register
+//! write, so they switch at the same instant, and are read in a single
+//! register read. Pins on different ports, and pins on chips without port
+//! access, are written one after another, with no other code running in
+//! between.
+//!
 //! ### Subscribes
 //!
-//! The GPIO interface provides only one callback, which is used for pins that
-//! have had interrupts enabled.
+//! The GPIO interface provides one callback for pins that have had interrupts
+//! enabled, and one callback for when a pulse has finished.
 
 /// Syscall driver number.
 pub const DRIVER_NUM: usize = 0x00000004;
 
+use core::cell::Cell;
 use kernel::common::cells::OptionalCell;
-use kernel::hil::gpio::{Client, InputMode, InterruptMode, Pin, PinCtl};
+use kernel::hil::gpio::{Client, InputMode, InterruptMode, Pin, PinCtl, PinGroup};
+use kernel::hil::time::{self, Alarm, Frequency};
 use kernel::{AppId, Callback, Driver, ReturnCode};
 
-pub struct GPIO<'a, G: Pin> {
-    pins: &'a [&'a G],
+pub struct GPIO<'a, G: Pin, A: Alarm + 'a> {
+    pins: &'a PinGroup<'a, G>,
+    alarm: &'a A,
     callback: OptionalCell<Callback>,
+    pulse_callback: OptionalCell<Callback>,
+    /// The pin being pulsed, if a pulse is in progress.
+    pulse_pin: OptionalCell<usize>,
+    /// Whether the pulse in progress drives the pin high.
+    pulse_high: Cell<bool>,
 }
 
-impl<G: Pin + PinCtl> GPIO<'a, G> {
-    pub fn new(pins: &'a [&'a G]) -> GPIO<'a, G> {
+impl<G: Pin + PinCtl, A: Alarm> GPIO<'a, G, A> {
+    pub fn new(pins: &'a PinGroup<'a, G>, alarm: &'a A) -> GPIO<'a, G, A> {
         GPIO {
             pins: pins,
+            alarm: alarm,
             callback: OptionalCell::empty(),
+            pulse_callback: OptionalCell::empty(),
+            pulse_pin: OptionalCell::empty(),
+            pulse_high: Cell::new(false),
+        }
+    }
+
+    /// Drive `pin_num` to the pulse level and arm the alarm to return it to
+    /// the idle level after `duration_us` microseconds.
+    fn start_pulse(&self, pin_num: usize, high: bool, duration_us: usize) -> ReturnCode {
+        if self.pulse_pin.is_some() {
+            return ReturnCode::EBUSY;
+        }
+
+        let tics = (duration_us as u64 * A::Frequency::frequency() as u64) / 1_000_000;
+        // A pulse longer than the alarm can count is rejected rather than
+        // silently shortened
+        if tics > u32::max_value() as u64 {
+            return ReturnCode::EINVAL;
+        }
+        // Always wait at least one tic, so a short pulse is still visible
+        let tics = if tics == 0 { 1 } else { tics as u32 };
+
+        let pin = self.pins.as_ref()[pin_num];
+        pin.make_output();
+        if high {
+            pin.set();
+        } else {
+            pin.clear();
         }
+        self.pulse_pin.set(pin_num);
+        self.pulse_high.set(high);
+        self.alarm.set_alarm(self.alarm.now().wrapping_add(tics));
+        ReturnCode::SUCCESS
     }
 
     fn configure_input_pin(&self, pin_num: usize, config: usize) -> ReturnCode {
-        let pin = self.pins[pin_num];
+        let pin = self.pins.as_ref()[pin_num];
         pin.make_input();
         match config {
             0 => {
@@ -106,7 +169,7 @@ impl<G: Pin + PinCtl> GPIO<'a, G> {
     }
 }
 
-impl<G: Pin> Client for GPIO<'a, G> {
+impl<G: Pin, A: Alarm> Client for GPIO<'a, G, A> {
     fn fired(&self, pin_num: usize) {
         // read the value of the pin
         let pins = self.pins.as_ref();
@@ -118,13 +181,30 @@ impl<G: Pin> Client for GPIO<'a, G> {
     }
 }
 
-impl<G: Pin + PinCtl> Driver for GPIO<'a, G> {
+impl<G: Pin, A: Alarm> time::Client for GPIO<'a, G, A> {
+    fn fired(&self) {
+        self.pulse_pin.take().map(|pin_num| {
+            // Return the pin to its idle level
+            let pin = self.pins.as_ref()[pin_num];
+            if self.pulse_high.get() {
+                pin.clear();
+            } else {
+                pin.set();
+            }
+            self.pulse_callback.map(|cb| cb.schedule(pin_num, 0, 0));
+        });
+    }
+}
+
+impl<G: Pin + PinCtl, A: Alarm> Driver for GPIO<'a, G, A> {
     /// Subscribe to GPIO pin events.
     ///
     /// ### `subscribe_num`
     ///
     /// - `0`: Subscribe to interrupts from all pins with interrupts enabled.
     ///        The callback signature is `fn(pin_num: usize, pin_state: bool)`
+    /// - `1`: Subscribe to the end of pulses started with command `10`.
+    ///        The callback signature is `fn(pin_num: usize)`
     fn subscribe(
         &self,
         subscribe_num: usize,
@@ -139,6 +219,12 @@ impl<G: Pin + PinCtl> Driver for GPIO<'a, G> {
                 ReturnCode::SUCCESS
             }
 
+            // subscribe to the end of pulses
+            1 => {
+                self.pulse_callback.insert(callback);
+                ReturnCode::SUCCESS
+            }
+
             // default
             _ => ReturnCode::ENOSUPPORT,
         }
@@ -161,6 +247,9 @@ impl<G: Pin + PinCtl> Driver for GPIO<'a, G> {
     ///                   Set to `0` to interrupt on either edge.
     ///                   Set to `1` for rising edge.
     ///                   Set to `2` for falling edge.
+    ///   - `pulse_level`: The level a pulse drives the pin to.
+    ///                   Set to `0` for a low pulse.
+    ///                   Set to `1` for a high pulse.
     ///
     /// ### `command_num`
     ///
@@ -174,6 +263,10 @@ impl<G: Pin + PinCtl> Driver for GPIO<'a, G> {
     /// - `7`: Configure interrupt on `pin` with `irq_config` in 0x00XX00000
     /// - `8`: Disable interrupt on `pin`.
     /// - `9`: Disable `pin`.
+    /// - `10`: Enable output on `pin` and pulse it with `pulse_level` in
+    ///         0x00XX0000 for `data2` microseconds. Returns `EBUSY` if a pulse
+    ///         is already in progress, or `EINVAL` if the pulse is longer
+    ///         than the alarm can time.
     fn command(&self, command_num: usize, data1: usize, data2: usize, _: AppId) -> ReturnCode {
         let pins = self.pins.as_ref();
         let pin = data1;
@@ -278,6 +371,23 @@ impl<G: Pin + PinCtl> Driver for GPIO<'a, G> {
                 }
             }
 
+            // pulse pin
+            10 => {
+                let pin = data1 & 0xFFFF;
+                let pulse_level = (data1 >> 16) & 0xFF;
+                if pin >= pins.len() || pulse_level > 1 {
+                    ReturnCode::EINVAL /* impossible pin or level */
+                } else {
+                    self.start_pulse(pin, pulse_level == 1, data2)
+                }
+            }
+
+            // write several pins at once
+            11 => self.pins.write(data1, data2),
+
+            // read several pins at once
+            12 => self.pins.read(data1),
+
             // default
             _ => ReturnCode::ENOSUPPORT,
         }
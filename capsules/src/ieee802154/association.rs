@@ -0,0 +1,438 @@
+//! Implements the IEEE 802.15.4 association procedure, through which a device
+//! joins a PAN and is allocated a short address by the PAN coordinator.
+//!
+//! A joining device calls `associate` with the PAN ID and address of the
+//! coordinator. This sends an association request command from the device's
+//! extended address, and the client is notified with the allocated short
+//! address once the coordinator's association response arrives. The device's
+//! PAN ID and short address are then configured automatically.
+//!
+//! A device configured as a coordinator with `set_coordinator` answers
+//! association requests by allocating short addresses from a range, giving a
+//! device the same address if it associates again. Responses are transmitted
+//! directly rather than held for the device to poll, so joining devices must
+//! keep their receiver on until the response arrives. A joining device gives
+//! up with `ENOACK` if no response arrives within macResponseWaitTime of the
+//! request being acknowledged.
+//!
+//! PAN ID conflict detection can also be enabled with
+//! `set_conflict_detection`. A coordinator then reports a conflict when it
+//! hears a beacon for its PAN from any other coordinator. An associated device
+//! reports one when it hears a beacon for its PAN from a coordinator other than
+//! the one it joined through. The board can resolve the conflict, for example
+//! by moving to a different PAN ID.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let assoc_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     VirtualMuxAlarm::new(mux_alarm));
+//! let assoc_mac = static_init!(
+//!     capsules::ieee802154::virtual_mac::MacUser<'static>,
+//!     capsules::ieee802154::virtual_mac::MacUser::new(mux_mac));
+//! mux_mac.add_user(assoc_mac);
+//! let association = static_init!(
+//!     capsules::ieee802154::association::Association<
+//!         'static,
+//!         VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     >,
+//!     capsules::ieee802154::association::Association::new(
+//!         assoc_mac,
+//!         assoc_alarm,
+//!         &mut capsules::ieee802154::association::ASSOC_BUF));
+//! assoc_mac.set_transmit_client(association);
+//! assoc_mac.set_receive_client(association);
+//! assoc_alarm.set_client(association);
+//!
+//! // On the coordinator, allocate addresses 0x0100 to 0x010f
+//! association.set_coordinator(Some((0x0100, 16)));
+//!
+//! // On a joining device
+//! association.set_client(client);
+//! association.associate(0xABCD, MacAddress::Short(0x0000));
+//!
+//! // Report beacons from other coordinators using the same PAN ID
+//! association.set_conflict_client(conflict_client);
+//! association.set_conflict_detection(true);
+//! ```
+
+use core::cell::Cell;
+use ieee802154::device::{MacDevice, RxClient, TxClient};
+use ieee802154::framer::Frame;
+use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::hil::radio;
+use kernel::hil::time::{self, Alarm, Frequency};
+use kernel::ReturnCode;
+use net::ieee802154::{FrameType, Header, MacAddress, PanID};
+
+/// Buffer for transmitting association requests and responses.
+pub static mut ASSOC_BUF: [u8; radio::MAX_BUF_SIZE] = [0; radio::MAX_BUF_SIZE];
+
+/// The number of devices a coordinator remembers the addresses of.
+pub const MAX_ASSOCIATED_DEVICES: usize = 8;
+
+/// IEEE 802.15.4-2015, 7.5.1: command identifiers
+const ASSOCIATION_REQUEST_COMMAND_ID: u8 = 0x01;
+const ASSOCIATION_RESPONSE_COMMAND_ID: u8 = 0x02;
+
+/// IEEE 802.15.4-2015, 7.5.2: capability information. Joining devices ask
+/// the coordinator to allocate a short address, and keep their receiver on
+/// while idle so that the response can be sent directly.
+const CAPABILITY_RX_ON_WHEN_IDLE: u8 = 1 << 3;
+const CAPABILITY_ALLOCATE_ADDRESS: u8 = 1 << 7;
+
+/// IEEE 802.15.4-2015, Table 7-50: association status
+const STATUS_SUCCESS: u8 = 0x00;
+const STATUS_PAN_AT_CAPACITY: u8 = 0x01;
+
+/// IEEE 802.15.4-2015, 8.4.3.1: macResponseWaitTime is 32 base superframe
+/// durations of 960 symbols, each 16 us long on the 2.4 GHz O-QPSK PHY.
+const RESPONSE_WAIT_TIME_US: u32 = 32 * 960 * 16;
+
+/// Association requests are sent before the device belongs to a PAN, so they
+/// carry the broadcast PAN ID as their source PAN.
+const BROADCAST_PAN: PanID = 0xffff;
+
+/// A client notified when an association attempt completes.
+pub trait AssociationClient {
+    /// `result` is `SUCCESS` if the device joined the PAN with short address
+    /// `short_addr`, `ENOACK` if the coordinator did not acknowledge the
+    /// request or did not respond in time, `ENOMEM` if the PAN is at capacity, and `FAIL` if the
+    /// coordinator denied access or the request could not be sent.
+    fn associated(&self, result: ReturnCode, short_addr: u16);
+}
+
+/// A client notified when another coordinator is using the local PAN ID.
+pub trait PanIdConflictClient {
+    /// Called when a beacon for `pan_id`, the local PAN ID, is received from
+    /// `coordinator`, which is not this device's coordinator.
+    fn pan_id_conflict(&self, pan_id: PanID, coordinator: MacAddress);
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum State {
+    Idle,
+    /// The association request is being transmitted.
+    Requesting(PanID, MacAddress),
+    /// The request was acknowledged; waiting for the response.
+    AwaitingResponse(PanID, MacAddress),
+    /// The coordinator is transmitting an association response.
+    Responding,
+}
+
+pub struct Association<'a, A: Alarm + 'a> {
+    mac: &'a MacDevice<'a>,
+    alarm: &'a A,
+    tx_buf: TakeCell<'static, [u8]>,
+    state: Cell<State>,
+    client: OptionalCell<&'a AssociationClient>,
+    /// The first short address allocated by the coordinator and the number of
+    /// addresses available, or `None` if this device is not a coordinator.
+    address_range: Cell<Option<(u16, u16)>>,
+    /// The extended address of each associated device and the short address
+    /// it was allocated.
+    devices: Cell<[Option<([u8; 8], u16)>; MAX_ASSOCIATED_DEVICES]>,
+    /// The addresses of the coordinator this device associated through, as
+    /// given to `associate` and as the source of its response.
+    coordinator: Cell<[Option<MacAddress>; 2]>,
+    conflict_detection: Cell<bool>,
+    conflict_client: OptionalCell<&'a PanIdConflictClient>,
+}
+
+impl<A: Alarm> Association<'a, A> {
+    pub fn new(
+        mac: &'a MacDevice<'a>,
+        alarm: &'a A,
+        tx_buf: &'static mut [u8],
+    ) -> Association<'a, A> {
+        Association {
+            mac: mac,
+            alarm: alarm,
+            tx_buf: TakeCell::new(tx_buf),
+            state: Cell::new(State::Idle),
+            client: OptionalCell::empty(),
+            address_range: Cell::new(None),
+            devices: Cell::new([None; MAX_ASSOCIATED_DEVICES]),
+            coordinator: Cell::new([None; 2]),
+            conflict_detection: Cell::new(false),
+            conflict_client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a AssociationClient) {
+        self.client.set(client);
+    }
+
+    pub fn set_conflict_client(&self, client: &'a PanIdConflictClient) {
+        self.conflict_client.set(client);
+    }
+
+    /// Enables or disables reporting PAN ID conflicts to the conflict client.
+    pub fn set_conflict_detection(&self, enabled: bool) {
+        self.conflict_detection.set(enabled);
+    }
+
+    /// Makes this device act as the PAN coordinator, allocating `count` short
+    /// addresses starting at `first` to devices that associate with it, or
+    /// stops answering association requests if `range` is `None`.
+    pub fn set_coordinator(&self, range: Option<(u16, u16)>) {
+        self.address_range.set(range);
+        self.devices.set([None; MAX_ASSOCIATED_DEVICES]);
+    }
+
+    /// Requests to join the PAN `pan_id` through its coordinator at
+    /// `coordinator_addr`. The client is notified once the attempt completes.
+    /// Returns `EBUSY` if an association is already in progress.
+    pub fn associate(&self, pan_id: PanID, coordinator_addr: MacAddress) -> ReturnCode {
+        if self.state.get() != State::Idle {
+            return ReturnCode::EBUSY;
+        }
+        let buf = match self.tx_buf.take() {
+            Some(buf) => buf,
+            None => return ReturnCode::EBUSY,
+        };
+        let frame = self.mac.prepare_command_frame(
+            buf,
+            pan_id,
+            coordinator_addr,
+            BROADCAST_PAN,
+            MacAddress::Long(self.mac.get_address_long()),
+            ASSOCIATION_REQUEST_COMMAND_ID,
+        );
+        let mut frame = match frame {
+            Ok(frame) => frame,
+            Err(buf) => {
+                self.tx_buf.replace(buf);
+                return ReturnCode::FAIL;
+            }
+        };
+        let capability = CAPABILITY_ALLOCATE_ADDRESS | CAPABILITY_RX_ON_WHEN_IDLE;
+        let rval = frame.append_payload(&[capability]);
+        if rval != ReturnCode::SUCCESS {
+            self.tx_buf.replace(frame.into_buf());
+            return rval;
+        }
+        self.state.set(State::Requesting(pan_id, coordinator_addr));
+        self.transmit(frame)
+    }
+
+    /// Abandons an association attempt that is waiting for a response.
+    pub fn cancel(&self) {
+        if let State::AwaitingResponse(..) = self.state.get() {
+            self.alarm.disable();
+            self.state.set(State::Idle);
+        }
+    }
+
+    fn transmit(&self, frame: Frame) -> ReturnCode {
+        let (rval, buf) = self.mac.transmit(frame);
+        if let Some(buf) = buf {
+            self.tx_buf.replace(buf);
+        }
+        if rval != ReturnCode::SUCCESS {
+            self.state.set(State::Idle);
+        }
+        rval
+    }
+
+    /// Returns the short address allocated to the device with extended
+    /// address `device`, allocating a new one if it has not associated
+    /// before. Returns `None` if all addresses are in use.
+    fn allocate_address(&self, device: [u8; 8]) -> Option<u16> {
+        let (first, count) = self.address_range.get()?;
+        let mut devices = self.devices.get();
+        if let Some(&Some((_, addr))) = devices
+            .iter()
+            .find(|entry| entry.map_or(false, |(long, _)| long == device))
+        {
+            return Some(addr);
+        }
+        let addr = (0..count)
+            .map(|i| first.wrapping_add(i))
+            .find(|addr| !devices.iter().any(|entry| entry.map_or(false, |(_, a)| a == *addr)))?;
+        {
+            let slot = devices.iter_mut().find(|entry| entry.is_none())?;
+            *slot = Some((device, addr));
+        }
+        self.devices.set(devices);
+        Some(addr)
+    }
+
+    /// Answers an association request from the device with extended address
+    /// `device`. Requests that arrive while a response is being sent are
+    /// dropped, and the device is expected to retry.
+    fn receive_request(&self, pan_id: PanID, device: [u8; 8]) {
+        if self.address_range.get().is_none() || self.state.get() != State::Idle {
+            return;
+        }
+        let (short_addr, status) = match self.allocate_address(device) {
+            Some(addr) => (addr, STATUS_SUCCESS),
+            None => (0xffff, STATUS_PAN_AT_CAPACITY),
+        };
+        let buf = match self.tx_buf.take() {
+            Some(buf) => buf,
+            None => return,
+        };
+        let frame = self.mac.prepare_command_frame(
+            buf,
+            pan_id,
+            MacAddress::Long(device),
+            pan_id,
+            MacAddress::Long(self.mac.get_address_long()),
+            ASSOCIATION_RESPONSE_COMMAND_ID,
+        );
+        let mut frame = match frame {
+            Ok(frame) => frame,
+            Err(buf) => {
+                self.tx_buf.replace(buf);
+                return;
+            }
+        };
+        let content = [short_addr as u8, (short_addr >> 8) as u8, status];
+        if frame.append_payload(&content) != ReturnCode::SUCCESS {
+            self.tx_buf.replace(frame.into_buf());
+            return;
+        }
+        self.state.set(State::Responding);
+        self.transmit(frame);
+    }
+
+    /// Completes an association attempt with the coordinator's response,
+    /// which was sent from `responder`.
+    fn receive_response(
+        &self,
+        pan_id: PanID,
+        coordinator: MacAddress,
+        responder: Option<MacAddress>,
+        short_addr: u16,
+        status: u8,
+    ) {
+        self.alarm.disable();
+        self.state.set(State::Idle);
+        let result = match status {
+            STATUS_SUCCESS => {
+                self.coordinator.set([Some(coordinator), responder]);
+                self.mac.set_pan(pan_id);
+                self.mac.set_address(short_addr);
+                self.mac.config_commit();
+                ReturnCode::SUCCESS
+            }
+            STATUS_PAN_AT_CAPACITY => ReturnCode::ENOMEM,
+            _ => ReturnCode::FAIL,
+        };
+        self.client
+            .map(|client| client.associated(result, short_addr));
+    }
+
+    /// Reports a PAN ID conflict if a beacon for the local PAN was sent by a
+    /// coordinator other than this device's own. Devices that are neither a
+    /// coordinator nor associated with one cannot tell, so never report one.
+    fn receive_beacon(&self, header: &Header) {
+        if !self.conflict_detection.get() || header.src_pan != Some(self.mac.get_pan()) {
+            return;
+        }
+        let src_addr = match header.src_addr {
+            Some(addr) => addr,
+            None => return,
+        };
+        let coordinator = self.coordinator.get();
+        let conflict = if self.address_range.get().is_some() {
+            src_addr != MacAddress::Short(self.mac.get_address())
+                && src_addr != MacAddress::Long(self.mac.get_address_long())
+        } else {
+            coordinator[0].is_some() && !coordinator.contains(&Some(src_addr))
+        };
+        if conflict {
+            self.conflict_client
+                .map(|client| client.pan_id_conflict(self.mac.get_pan(), src_addr));
+        }
+    }
+}
+
+impl<A: Alarm> TxClient for Association<'a, A> {
+    fn send_done(&self, spi_buf: &'static mut [u8], acked: bool, result: ReturnCode) {
+        self.tx_buf.replace(spi_buf);
+        match self.state.get() {
+            State::Requesting(pan_id, coordinator) => {
+                if result == ReturnCode::SUCCESS && acked {
+                    self.state
+                        .set(State::AwaitingResponse(pan_id, coordinator));
+                    let tics = (RESPONSE_WAIT_TIME_US as u64 * A::Frequency::frequency() as u64)
+                        / 1_000_000;
+                    self.alarm
+                        .set_alarm(self.alarm.now().wrapping_add(tics as u32));
+                } else {
+                    self.state.set(State::Idle);
+                    let result = if result == ReturnCode::SUCCESS {
+                        ReturnCode::ENOACK
+                    } else {
+                        ReturnCode::FAIL
+                    };
+                    self.client.map(|client| client.associated(result, 0));
+                }
+            }
+            State::Responding => self.state.set(State::Idle),
+            _ => {}
+        }
+    }
+}
+
+impl<A: Alarm> time::Client for Association<'a, A> {
+    fn fired(&self) {
+        if let State::AwaitingResponse(..) = self.state.get() {
+            self.state.set(State::Idle);
+            self.client
+                .map(|client| client.associated(ReturnCode::ENOACK, 0));
+        }
+    }
+}
+
+impl<A: Alarm> RxClient for Association<'a, A> {
+    fn receive<'b>(
+        &self,
+        buf: &'b [u8],
+        header: Header<'b>,
+        data_offset: usize,
+        data_len: usize,
+        _lqi: u8,
+        _rssi: i8,
+    ) {
+        if header.frame_type == FrameType::Beacon {
+            self.receive_beacon(&header);
+            return;
+        }
+        if header.frame_type != FrameType::MACCommand || data_len == 0 {
+            return;
+        }
+        let payload = &buf[data_offset..data_offset + data_len];
+        let own_addr = MacAddress::Long(self.mac.get_address_long());
+        match payload[0] {
+            ASSOCIATION_REQUEST_COMMAND_ID => {
+                if let (Some(pan_id), Some(MacAddress::Long(device))) =
+                    (header.dst_pan, header.src_addr)
+                {
+                    if pan_id == self.mac.get_pan() {
+                        self.receive_request(pan_id, device);
+                    }
+                }
+            }
+            ASSOCIATION_RESPONSE_COMMAND_ID if payload.len() >= 4 => {
+                if let State::AwaitingResponse(pan_id, coordinator) = self.state.get() {
+                    if header.dst_addr == Some(own_addr) {
+                        let short_addr = payload[1] as u16 | (payload[2] as u16) << 8;
+                        self.receive_response(
+                            pan_id,
+                            coordinator,
+                            header.src_addr,
+                            short_addr,
+                            payload[3],
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -13,7 +13,7 @@
 
 use ieee802154::framer::Frame;
 use kernel::ReturnCode;
-use net::ieee802154::{Header, KeyId, MacAddress, PanID, SecurityLevel};
+use net::ieee802154::{Header, HeaderIE, KeyId, MacAddress, PanID, SecurityLevel};
 
 pub trait MacDevice<'a> {
     /// Sets the transmission client of this MAC device
@@ -69,6 +69,36 @@ pub trait MacDevice<'a> {
         security_needed: Option<(SecurityLevel, KeyId)>,
     ) -> Result<Frame, &'static mut [u8]>;
 
+    /// Same as `prepare_data_frame`, but also writes the given header
+    /// information elements (e.g. a time correction IE) into the MAC header.
+    /// Header IE list termination is handled automatically, so `header_ies`
+    /// must not contain termination IEs. At most
+    /// `net::ieee802154::MAX_HEADER_IES` elements can be attached. Frames
+    /// carrying IEs are sent as IEEE 802.15.4-2015 frames.
+    fn prepare_data_frame_with_ies(
+        &self,
+        buf: &'static mut [u8],
+        dst_pan: PanID,
+        dst_addr: MacAddress,
+        src_pan: PanID,
+        src_addr: MacAddress,
+        security_needed: Option<(SecurityLevel, KeyId)>,
+        header_ies: &[HeaderIE],
+    ) -> Result<Frame, &'static mut [u8]>;
+
+    /// Prepares an unsecured MAC command frame for the command identified by
+    /// `command_id`, which is written as the first byte of the payload. The
+    /// command's content can then be appended as payload.
+    fn prepare_command_frame(
+        &self,
+        buf: &'static mut [u8],
+        dst_pan: PanID,
+        dst_addr: MacAddress,
+        src_pan: PanID,
+        src_addr: MacAddress,
+        command_id: u8,
+    ) -> Result<Frame, &'static mut [u8]>;
+
     /// Transmits a frame that has been prepared by the above process. If the
     /// transmission process fails, the buffer inside the frame is returned so
     /// that it can be re-used.
@@ -110,5 +140,16 @@ pub trait RxClient {
     /// `buf`, so that the payload of the frame is contained in
     /// `buf[data_offset..data_offset + data_len]`.
     /// - `data_len`: Length of the data payload
-    fn receive<'a>(&self, buf: &'a [u8], header: Header<'a>, data_offset: usize, data_len: usize);
+    /// - `lqi`: Link quality indicator reported by the radio for the frame,
+    /// from 0 to 255, with higher values meaning a better link
+    /// - `rssi`: Received signal strength of the frame in dBm
+    fn receive<'a>(
+        &self,
+        buf: &'a [u8],
+        header: Header<'a>,
+        data_offset: usize,
+        data_len: usize,
+        lqi: u8,
+        rssi: i8,
+    );
 }
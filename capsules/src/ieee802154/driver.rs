@@ -823,7 +823,15 @@ fn encode_address(addr: &Option<MacAddress>) -> usize {
 }
 
 impl device::RxClient for RadioDriver<'a> {
-    fn receive<'b>(&self, buf: &'b [u8], header: Header<'b>, data_offset: usize, data_len: usize) {
+    fn receive<'b>(
+        &self,
+        buf: &'b [u8],
+        header: Header<'b>,
+        data_offset: usize,
+        data_len: usize,
+        _lqi: u8,
+        _rssi: i8,
+    ) {
         self.apps.each(|app| {
             app.app_read.take().as_mut().map(|rbuf| {
                 let rbuf = rbuf.as_mut();
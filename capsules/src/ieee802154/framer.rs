@@ -50,6 +50,39 @@
 //!     });
 //! ```
 //!
+//! A coordinator can also hold frames for sleepy children that poll for data.
+//! Such frames are queued with `transmit_indirect` and are only transmitted
+//! once the destination sends a data request command. Frames prepared for a
+//! device with queued frames have the frame pending bit set. Completion of an
+//! indirect transmission is reported to the client set with
+//! `set_indirect_transmit_client`:
+//!
+//! ```rust
+//! mac_device.set_indirect_transmit_client(indirect_client);
+//! let (rval, _) = mac_device.transmit_indirect(frame);
+//! ```
+//!
+//! Received frames can be filtered before they are decrypted and passed to the
+//! receive client. With address filtering enabled, frames addressed to another
+//! PAN or device are dropped, while broadcast frames are still accepted. With
+//! source filtering enabled, only frames from the source addresses added with
+//! `add_allowed_source` are accepted:
+//!
+//! ```rust
+//! mac_device.set_address_filter(true);
+//! mac_device.add_allowed_source(MacAddress::Short(0x1540));
+//! mac_device.set_source_filter(true);
+//! ```
+//!
+//! Frames that request an acknowledgement are retransmitted if the radio
+//! reports that no acknowledgement arrived within its acknowledgement wait,
+//! up to `DEFAULT_MAX_FRAME_RETRIES` times before the client is told the frame
+//! was not acknowledged. The number of retries can be changed:
+//!
+//! ```rust
+//! mac_device.set_max_frame_retries(5);
+//! ```
+//!
 //! You should also be able to set up the userspace driver for receiving/sending
 //! 802.15.4 frames:
 //!
@@ -77,7 +110,8 @@ use kernel::hil::radio;
 use kernel::hil::symmetric_encryption::{CCMClient, AES128CCM};
 use kernel::ReturnCode;
 use net::ieee802154::{
-    FrameType, FrameVersion, Header, KeyId, MacAddress, PanID, Security, SecurityLevel,
+    frame_control, FrameType, FrameVersion, Header, HeaderIE, KeyId, MacAddress, PanID, Security,
+    SecurityLevel, MAX_HEADER_IES,
 };
 use net::stream::SResult;
 use net::stream::{encode_bytes, encode_u32, encode_u8};
@@ -114,6 +148,9 @@ struct FrameInfo {
 
     // Security level, key, and nonce
     security_params: Option<(SecurityLevel, [u8; 16], [u8; 13])>,
+
+    // Whether CSMA-CA is performed before transmitting the frame
+    cca: bool,
 }
 
 impl Frame {
@@ -138,6 +175,11 @@ impl Frame {
 
         ReturnCode::SUCCESS
     }
+
+    /// Transmits the frame immediately, without performing CSMA-CA
+    pub fn disable_cca(&mut self) {
+        self.info.cca = false;
+    }
 }
 
 impl FrameInfo {
@@ -195,6 +237,15 @@ impl FrameInfo {
     }
 }
 
+/// Whether the frame in `buf` requests an acknowledgement.
+fn ack_requested(buf: &[u8]) -> bool {
+    if buf.len() < radio::PSDU_OFFSET + 2 {
+        return false;
+    }
+    let fcf = buf[radio::PSDU_OFFSET] as u16 | (buf[radio::PSDU_OFFSET + 1] as u16) << 8;
+    fcf & frame_control::ACK_REQUESTED != 0
+}
+
 fn get_ccm_nonce(device_addr: &[u8; 8], frame_counter: u32, level: SecurityLevel) -> [u8; 13] {
     let mut nonce = [0u8; 13];
     let encode_ccm_nonce = |buf: &mut [u8]| {
@@ -244,6 +295,31 @@ pub trait DeviceProcedure {
     fn lookup_addr_long(&self, addr: MacAddress) -> Option<([u8; 8])>;
 }
 
+/// The number of frames that can be held for indirect transmission at once.
+pub const MAX_INDIRECT_FRAMES: usize = 4;
+
+/// IEEE 802.15.4-2015, 7.5.1: command identifier of the data request command
+const DATA_REQUEST_COMMAND_ID: u8 = 0x04;
+
+/// A frame held for indirect transmission until `dst_addr` polls for it.
+struct IndirectFrame {
+    dst_addr: MacAddress,
+    frame: Frame,
+}
+
+/// The number of source addresses that can be accepted when source filtering
+/// is enabled.
+pub const MAX_ALLOWED_SOURCES: usize = 4;
+
+/// IEEE 802.15.4-2015, 8.4.2: the default and largest number of retransmissions
+/// of a frame that was not acknowledged (macMaxFrameRetries)
+pub const DEFAULT_MAX_FRAME_RETRIES: u8 = 3;
+pub const MAX_FRAME_RETRIES: u8 = 7;
+
+/// IEEE 802.15.4-2015, 7.3.2: PAN ID and short address accepted by all devices
+const BROADCAST_PAN: PanID = 0xffff;
+const BROADCAST_ADDR: u16 = 0xffff;
+
 /// This state enum describes the state of the transmission pipeline.
 /// Conditionally-present state is also included as fields in the enum variants.
 /// We can view the transmission process as a state machine driven by the
@@ -253,6 +329,11 @@ pub trait DeviceProcedure {
 /// - `send_done` callbacks from the underlying radio
 /// - `config_done` callbacks from the underlying radio (if, for example,
 ///   configuration was in progress when a transmission was requested)
+///
+/// A frame the radio refuses with `EBUSY` waits in `ReadyToTransmit`, and
+/// both `send_done` and `config_done` retry it. Once the radio accepts the
+/// frame the state returns to `Idle`, so further callbacks leave it alone and
+/// the frame is sent exactly once.
 #[derive(Eq, PartialEq, Debug)]
 enum TxState {
     /// There is no frame to be transmitted.
@@ -307,11 +388,38 @@ pub struct Framer<'a, M: Mac, A: AES128CCM<'a>> {
     /// associated state information.
     tx_state: MapCell<TxState>,
     tx_client: OptionalCell<&'a TxClient>,
+    /// The length and CCA setting of the frame the radio accepted, needed to
+    /// retransmit it, and whether it is an indirect frame.
+    tx_params: Cell<(usize, bool, bool)>,
+    /// How many times the frame given to the radio has been retransmitted.
+    tx_retries: Cell<u8>,
+    max_frame_retries: Cell<u8>,
+
+    /// Frames held until their destination sends a data request.
+    indirect_frames: [MapCell<IndirectFrame>; MAX_INDIRECT_FRAMES],
+    /// Whether an indirect frame is in the transmission pipeline or being
+    /// sent by the radio. Only one is released at a time.
+    indirect_in_flight: Cell<bool>,
+    /// Whether the frame in `tx_state`, which the radio has not accepted
+    /// yet, is an indirect frame, whose completion is reported to
+    /// `indirect_tx_client`.
+    tx_indirect: Cell<bool>,
+    indirect_tx_client: OptionalCell<&'a TxClient>,
 
     /// Reception pipeline state. Similar to the above, this should never be
     /// `None`, except when transitioning between states.
     rx_state: MapCell<RxState>,
     rx_client: OptionalCell<&'a RxClient>,
+    /// LQI and RSSI of the frame in the reception pipeline, reported to the
+    /// client along with the frame.
+    rx_link_quality: Cell<(u8, i8)>,
+
+    /// Whether to drop received frames not addressed to this device.
+    address_filter: Cell<bool>,
+    /// Whether to drop received frames whose source is not in
+    /// `allowed_sources`.
+    source_filter: Cell<bool>,
+    allowed_sources: Cell<[Option<MacAddress>; MAX_ALLOWED_SOURCES]>,
 }
 
 impl<M: Mac, A: AES128CCM<'a>> Framer<'a, M, A> {
@@ -324,8 +432,317 @@ impl<M: Mac, A: AES128CCM<'a>> Framer<'a, M, A> {
             device_procedure: OptionalCell::empty(),
             tx_state: MapCell::new(TxState::Idle),
             tx_client: OptionalCell::empty(),
+            tx_params: Cell::new((0, false, false)),
+            tx_retries: Cell::new(0),
+            max_frame_retries: Cell::new(DEFAULT_MAX_FRAME_RETRIES),
+            indirect_frames: [
+                MapCell::empty(),
+                MapCell::empty(),
+                MapCell::empty(),
+                MapCell::empty(),
+            ],
+            indirect_in_flight: Cell::new(false),
+            tx_indirect: Cell::new(false),
+            indirect_tx_client: OptionalCell::empty(),
             rx_state: MapCell::new(RxState::Idle),
             rx_client: OptionalCell::empty(),
+            rx_link_quality: Cell::new((0, 0)),
+            address_filter: Cell::new(false),
+            source_filter: Cell::new(false),
+            allowed_sources: Cell::new([None; MAX_ALLOWED_SOURCES]),
+        }
+    }
+
+    /// Sets how many times a frame that is not acknowledged is retransmitted
+    /// before the transmission fails. Returns `EINVAL` if `retries` is above
+    /// `MAX_FRAME_RETRIES`.
+    pub fn set_max_frame_retries(&self, retries: u8) -> ReturnCode {
+        if retries > MAX_FRAME_RETRIES {
+            return ReturnCode::EINVAL;
+        }
+        self.max_frame_retries.set(retries);
+        ReturnCode::SUCCESS
+    }
+
+    /// Enables or disables dropping received frames whose destination PAN ID
+    /// or address does not match this device. Broadcast frames are always
+    /// accepted.
+    pub fn set_address_filter(&self, enabled: bool) {
+        self.address_filter.set(enabled);
+    }
+
+    /// Enables or disables dropping received frames whose source address was
+    /// not added with `add_allowed_source`.
+    pub fn set_source_filter(&self, enabled: bool) {
+        self.source_filter.set(enabled);
+    }
+
+    /// Adds a source address to the list of sources accepted when source
+    /// filtering is enabled. Returns `ENOMEM` if the list is full.
+    pub fn add_allowed_source(&self, addr: MacAddress) -> ReturnCode {
+        let mut sources = self.allowed_sources.get();
+        if sources.iter().any(|src| *src == Some(addr)) {
+            return ReturnCode::SUCCESS;
+        }
+        match sources.iter_mut().find(|src| src.is_none()) {
+            Some(slot) => *slot = Some(addr),
+            None => return ReturnCode::ENOMEM,
+        }
+        self.allowed_sources.set(sources);
+        ReturnCode::SUCCESS
+    }
+
+    /// Removes all addresses from the list of accepted sources.
+    pub fn clear_allowed_sources(&self) {
+        self.allowed_sources.set([None; MAX_ALLOWED_SOURCES]);
+    }
+
+    /// Applies the configured address and source filters to a received
+    /// frame, returning whether the frame should be processed further.
+    fn accept_frame(&self, header: &Header) -> bool {
+        if self.address_filter.get() {
+            let pan_matches = header
+                .dst_pan
+                .map_or(true, |pan| pan == BROADCAST_PAN || pan == self.get_pan());
+            let addr_matches = header.dst_addr.map_or(true, |addr| match addr {
+                MacAddress::Short(addr) => addr == BROADCAST_ADDR || addr == self.get_address(),
+                MacAddress::Long(addr) => addr == self.get_address_long(),
+            });
+            if !pan_matches || !addr_matches {
+                return false;
+            }
+        }
+        if self.source_filter.get() {
+            let sources = self.allowed_sources.get();
+            let allowed = header
+                .src_addr
+                .map_or(false, |addr| sources.iter().any(|src| *src == Some(addr)));
+            if !allowed {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Sets the client that is notified when an indirect transmission
+    /// completes.
+    pub fn set_indirect_transmit_client(&self, client: &'a TxClient) {
+        self.indirect_tx_client.set(client);
+    }
+
+    /// Holds a frame prepared with `prepare_data_frame` until its destination
+    /// polls for it with a data request command, after which the frame is
+    /// transmitted and returned through the indirect transmit client. Returns
+    /// `ENOMEM` along with the buffer if no more frames can be held.
+    pub fn transmit_indirect(&self, frame: Frame) -> (ReturnCode, Option<&'static mut [u8]>) {
+        let dst_addr = match Header::decode(&frame.buf[radio::PSDU_OFFSET..], false).done() {
+            Some((_, (header, _))) => header.dst_addr,
+            None => None,
+        };
+        let dst_addr = match dst_addr {
+            Some(dst_addr) => dst_addr,
+            None => return (ReturnCode::EINVAL, Some(frame.into_buf())),
+        };
+
+        match self.indirect_frames.iter().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                slot.put(IndirectFrame {
+                    dst_addr: dst_addr,
+                    frame: frame,
+                });
+                (ReturnCode::SUCCESS, None)
+            }
+            None => (ReturnCode::ENOMEM, Some(frame.into_buf())),
+        }
+    }
+
+    /// Returns true if a frame is being held for `addr`.
+    pub fn indirect_frame_pending(&self, addr: MacAddress) -> bool {
+        self.indirect_frames
+            .iter()
+            .any(|slot| slot.map_or(false, |indirect| indirect.dst_addr == addr))
+    }
+
+    /// Transmits the first frame held for `addr`, if any. The frame pending
+    /// bit of the frame is updated to indicate whether more frames are held
+    /// for the same destination.
+    fn release_indirect_frame(&self, addr: MacAddress) {
+        // The frame must enter an empty pipeline, so that `tx_indirect`
+        // describes it and not a frame already waiting for the radio.
+        let pipeline_idle = self.tx_state.map_or(false, |state| match *state {
+            TxState::Idle => true,
+            _ => false,
+        });
+        if self.indirect_in_flight.get() || !pipeline_idle {
+            return;
+        }
+        let slot = match self
+            .indirect_frames
+            .iter()
+            .find(|slot| slot.map_or(false, |indirect| indirect.dst_addr == addr))
+        {
+            Some(slot) => slot,
+            None => return,
+        };
+        let frame = match slot.take() {
+            Some(indirect) => indirect.frame,
+            None => return,
+        };
+        let info = frame.info;
+
+        // The frame control field is encoded little-endian, and the frame
+        // pending bit is in its first byte.
+        let pending_bit = frame_control::FRAME_PENDING as u8;
+        if self.indirect_frame_pending(addr) {
+            frame.buf[radio::PSDU_OFFSET] |= pending_bit;
+        } else {
+            frame.buf[radio::PSDU_OFFSET] &= !pending_bit;
+        }
+
+        self.indirect_in_flight.set(true);
+        self.tx_indirect.set(true);
+        let (rval, buf) = self.transmit(frame);
+        if rval != ReturnCode::SUCCESS {
+            self.tx_indirect.set(false);
+        }
+        match rval {
+            ReturnCode::SUCCESS => {}
+            ReturnCode::EBUSY => {
+                // Keep holding the frame until the next data request
+                self.indirect_in_flight.set(false);
+                buf.map(|buf| {
+                    slot.put(IndirectFrame {
+                        dst_addr: addr,
+                        frame: Frame {
+                            buf: buf,
+                            info: info,
+                        },
+                    })
+                });
+            }
+            _ => match buf {
+                Some(buf) => self.tx_done(buf, false, rval, true),
+                None => self.indirect_in_flight.set(false),
+            },
+        }
+    }
+
+    /// If the received frame is a data request command, transmits a frame
+    /// held for the device that sent it.
+    fn handle_data_request(&self, header: &Header, payload: &[u8]) {
+        if header.frame_type == FrameType::MACCommand
+            && payload.first() == Some(&DATA_REQUEST_COMMAND_ID)
+        {
+            header
+                .src_addr
+                .map(|src_addr| self.release_indirect_frame(src_addr));
+        }
+    }
+
+    /// Returns a transmitted buffer to the client that requested the
+    /// transmission, which is `indirect_tx_client` if `indirect` is set.
+    fn tx_done(&self, buf: &'static mut [u8], acked: bool, result: ReturnCode, indirect: bool) {
+        if indirect {
+            self.indirect_in_flight.set(false);
+            self.indirect_tx_client.map(move |client| {
+                client.send_done(buf, acked, result);
+            });
+        } else {
+            self.tx_client.map(move |client| {
+                client.send_done(buf, acked, result);
+            });
+        }
+    }
+
+    /// Writes the MAC header of a frame of type `frame_type` into `buf`.
+    fn prepare_frame(
+        &self,
+        frame_type: FrameType,
+        buf: &'static mut [u8],
+        dst_pan: PanID,
+        dst_addr: MacAddress,
+        src_pan: PanID,
+        src_addr: MacAddress,
+        security_needed: Option<(SecurityLevel, KeyId)>,
+        header_ies: &[HeaderIE],
+    ) -> Result<Frame, &'static mut [u8]> {
+        if header_ies.len() > MAX_HEADER_IES {
+            return Err(buf);
+        }
+        let mut ies: [HeaderIE; MAX_HEADER_IES] = Default::default();
+        ies[..header_ies.len()].copy_from_slice(header_ies);
+
+        // IEEE 802.15.4-2015: 9.2.1, outgoing frame security
+        // Steps a-e of the security procedure are implemented here.
+
+        // TODO: For Thread, in the case of `KeyIdMode::Source4Index`, the source
+        // address should instead be some constant defined in their
+        // specification.
+        let src_addr_long = self.get_address_long();
+        let security_desc = security_needed.and_then(|(level, key_id)| {
+            self.lookup_key(level, key_id).map(|key| {
+                // TODO: lookup frame counter for device
+                let frame_counter = 0;
+                let nonce = get_ccm_nonce(&src_addr_long, frame_counter, level);
+                (
+                    Security {
+                        level: level,
+                        asn_in_nonce: false,
+                        frame_counter: Some(frame_counter),
+                        key_id: key_id,
+                    },
+                    key,
+                    nonce,
+                )
+            })
+        });
+        if security_needed.is_some() && security_desc.is_none() {
+            // If security was requested, fail when desired key was not found.
+            return Err(buf);
+        }
+
+        // Construct MAC header
+        let security = security_desc.map(|(sec, _, _)| sec);
+        let mic_len = security.map_or(0, |sec| sec.level.mic_len());
+        let header = Header {
+            frame_type: frame_type,
+            // TODO: Also set this in hardware so that ACKs set this flag to
+            // the right value.
+            frame_pending: self.indirect_frame_pending(dst_addr),
+            // Unicast data frames request acknowledgement
+            ack_requested: true,
+            // Information elements are only defined for 2015 frames
+            version: if header_ies.is_empty() {
+                FrameVersion::V2006
+            } else {
+                FrameVersion::V2015
+            },
+            seq: Some(self.data_sequence.get()),
+            dst_pan: Some(dst_pan),
+            dst_addr: Some(dst_addr),
+            src_pan: Some(src_pan),
+            src_addr: Some(src_addr),
+            security: security,
+            header_ies: ies,
+            header_ies_len: header_ies.len(),
+            payload_ies: Default::default(),
+            payload_ies_len: 0,
+        };
+
+        match header.encode(&mut buf[radio::PSDU_OFFSET..], true).done() {
+            Some((data_offset, mac_payload_offset)) => Ok(Frame {
+                buf: buf,
+                info: FrameInfo {
+                    frame_type: frame_type,
+                    mac_payload_offset: mac_payload_offset,
+                    data_offset: data_offset,
+                    data_len: 0,
+                    mic_len: mic_len,
+                    security_params: security_desc.map(|(sec, key, nonce)| (sec.level, key, nonce)),
+                    cca: true,
+                },
+            }),
+            None => Err(buf),
         }
     }
 
@@ -387,6 +804,12 @@ impl<M: Mac, A: AES128CCM<'a>> Framer<'a, M, A> {
         let result = Header::decode(&buf[radio::PSDU_OFFSET..], false)
             .done()
             .and_then(|(data_offset, (header, mac_payload_offset))| {
+                // Drop frames rejected by the receive filters before spending
+                // any effort on them.
+                if !self.accept_frame(&header) {
+                    return None;
+                }
+
                 // Note: there is a complication here regarding the offsets.
                 // When the received frame has security enabled, the payload
                 // (including the payload IEs) is encrypted, and hence the data
@@ -451,12 +874,23 @@ impl<M: Mac, A: AES128CCM<'a>> Framer<'a, M, A> {
                             data_len: data_len,
                             mic_len: mic_len,
                             security_params: Some((security.level, key, nonce)),
+                            cca: true,
                         })
                     }
                 } else {
                     // No security needed, can yield the frame immediately
+                    let data_start = radio::PSDU_OFFSET + data_offset;
+                    self.handle_data_request(&header, &buf[data_start..data_start + data_len]);
+                    let (lqi, rssi) = self.rx_link_quality.get();
                     self.rx_client.map(|client| {
-                        client.receive(&buf, header, radio::PSDU_OFFSET + data_offset, data_len);
+                        client.receive(
+                            &buf,
+                            header,
+                            radio::PSDU_OFFSET + data_offset,
+                            data_len,
+                            lqi,
+                            rssi,
+                        );
                     });
                     None
                 }
@@ -468,6 +902,24 @@ impl<M: Mac, A: AES128CCM<'a>> Framer<'a, M, A> {
         }
     }
 
+    /// Retries a transmission that was waiting for the radio to finish its
+    /// current transmission or configuration, returning the buffer to the
+    /// client if the retry fails.
+    fn resume_transmit(&self) {
+        let (rval, buf) = self.step_transmit_state();
+        if let Some(buf) = buf {
+            self.abort_transmit(buf, rval);
+        }
+    }
+
+    /// Returns the buffer of the frame in `tx_state`, which the radio never
+    /// accepted, to the client that requested the transmission.
+    fn abort_transmit(&self, buf: &'static mut [u8], result: ReturnCode) {
+        let indirect = self.tx_indirect.get();
+        self.tx_indirect.set(false);
+        self.tx_done(buf, false, result, indirect);
+    }
+
     /// Advances the transmission pipeline if it can be advanced.
     fn step_transmit_state(&self) -> (ReturnCode, Option<&'static mut [u8]>) {
         self.tx_state
@@ -507,16 +959,18 @@ impl<M: Mac, A: AES128CCM<'a>> Framer<'a, M, A> {
                                         ReturnCode::SUCCESS => {
                                             (TxState::Encrypting(info), (res, None))
                                         }
-                                        ReturnCode::EBUSY => {
-                                            let buf = match opt_buf {
-                                                Some(buf) => buf,
-                                                None => panic!("aes_ccm did not return the buffer"),
-                                            };
-                                            (
+                                        ReturnCode::EBUSY => match opt_buf {
+                                            None => {
+                                                // The frame is lost, but the
+                                                // pipeline can still recover.
+                                                debug!("aes_ccm did not return the tx buffer");
+                                                (TxState::Idle, (ReturnCode::FAIL, None))
+                                            }
+                                            Some(buf) => (
                                                 TxState::ReadyToEncrypt(info, buf),
                                                 (ReturnCode::SUCCESS, None),
-                                            )
-                                        }
+                                            ),
+                                        },
                                         _ => (TxState::Idle, (res, opt_buf)),
                                     }
                                 }
@@ -529,10 +983,20 @@ impl<M: Mac, A: AES128CCM<'a>> Framer<'a, M, A> {
                         (TxState::Encrypting(info), (ReturnCode::SUCCESS, None))
                     }
                     TxState::ReadyToTransmit(info, buf) => {
-                        let (rval, buf) = self.mac.transmit(buf, info.secured_length());
+                        let (rval, buf) = self.mac.transmit(buf, info.secured_length(), info.cca);
+                        if rval == ReturnCode::SUCCESS {
+                            // The radio may still be retrying an earlier
+                            // frame when one is refused, so only record the
+                            // parameters of a frame the radio accepted.
+                            let indirect = self.tx_indirect.get();
+                            self.tx_indirect.set(false);
+                            self.tx_params
+                                .set((info.secured_length(), info.cca, indirect));
+                            self.tx_retries.set(0);
+                        }
                         match rval {
                             // If the radio is busy, just wait for either a
-                            // transmit_done or config_done callback to trigger
+                            // send_done or config_done callback to trigger
                             // this state transition again
                             ReturnCode::EBUSY => {
                                 match buf {
@@ -587,13 +1051,16 @@ impl<M: Mac, A: AES128CCM<'a>> Framer<'a, M, A> {
                                 );
                                 match res {
                                     ReturnCode::SUCCESS => (RxState::Decrypting(info), None),
-                                    ReturnCode::EBUSY => {
-                                        let buf = match opt_buf {
-                                            Some(buf) => buf,
-                                            None => panic!("aes_ccm did not return the buffer"),
-                                        };
-                                        (RxState::ReadyToDecrypt(info, buf), None)
-                                    }
+                                    ReturnCode::EBUSY => match opt_buf {
+                                        None => {
+                                            // The radio is left without a
+                                            // receive buffer, but the pipeline
+                                            // can still recover.
+                                            debug!("aes_ccm did not return the rx buffer");
+                                            (RxState::Idle, None)
+                                        }
+                                        Some(buf) => (RxState::ReadyToDecrypt(info, buf), None),
+                                    },
                                     _ => (RxState::Idle, opt_buf),
                                 }
                             }
@@ -622,12 +1089,18 @@ impl<M: Mac, A: AES128CCM<'a>> Framer<'a, M, A> {
                         // This is so that it is possible to tell if the
                         // frame was secured or unsecured, while still
                         // always receiving the frame payload in plaintext.
+                        let data_start = radio::PSDU_OFFSET + data_offset;
+                        let data_end = radio::PSDU_OFFSET + frame_len;
+                        self.handle_data_request(&header, &buf[data_start..data_end]);
+                        let (lqi, rssi) = self.rx_link_quality.get();
                         self.rx_client.map(|client| {
                             client.receive(
                                 &buf,
                                 header,
                                 radio::PSDU_OFFSET + data_offset,
                                 frame_len - data_offset,
+                                lqi,
+                                rssi,
                             );
                         });
                     }
@@ -695,71 +1168,61 @@ impl<M: Mac, A: AES128CCM<'a>> MacDevice<'a> for Framer<'a, M, A> {
         src_addr: MacAddress,
         security_needed: Option<(SecurityLevel, KeyId)>,
     ) -> Result<Frame, &'static mut [u8]> {
-        // IEEE 802.15.4-2015: 9.2.1, outgoing frame security
-        // Steps a-e of the security procedure are implemented here.
-
-        // TODO: For Thread, in the case of `KeyIdMode::Source4Index`, the source
-        // address should instead be some constant defined in their
-        // specification.
-        let src_addr_long = self.get_address_long();
-        let security_desc = security_needed.and_then(|(level, key_id)| {
-            self.lookup_key(level, key_id).map(|key| {
-                // TODO: lookup frame counter for device
-                let frame_counter = 0;
-                let nonce = get_ccm_nonce(&src_addr_long, frame_counter, level);
-                (
-                    Security {
-                        level: level,
-                        asn_in_nonce: false,
-                        frame_counter: Some(frame_counter),
-                        key_id: key_id,
-                    },
-                    key,
-                    nonce,
-                )
-            })
-        });
-        if security_needed.is_some() && security_desc.is_none() {
-            // If security was requested, fail when desired key was not found.
-            return Err(buf);
-        }
+        self.prepare_data_frame_with_ies(
+            buf,
+            dst_pan,
+            dst_addr,
+            src_pan,
+            src_addr,
+            security_needed,
+            &[],
+        )
+    }
 
-        // Construct MAC header
-        let security = security_desc.map(|(sec, _, _)| sec);
-        let mic_len = security.map_or(0, |sec| sec.level.mic_len());
-        let header = Header {
-            frame_type: FrameType::Data,
-            /* TODO: determine this by looking at queue, and also set it in
-             * hardware so that ACKs set this flag to the right value. */
-            frame_pending: false,
-            // Unicast data frames request acknowledgement
-            ack_requested: true,
-            version: FrameVersion::V2006,
-            seq: Some(self.data_sequence.get()),
-            dst_pan: Some(dst_pan),
-            dst_addr: Some(dst_addr),
-            src_pan: Some(src_pan),
-            src_addr: Some(src_addr),
-            security: security,
-            header_ies: Default::default(),
-            header_ies_len: 0,
-            payload_ies: Default::default(),
-            payload_ies_len: 0,
-        };
+    fn prepare_data_frame_with_ies(
+        &self,
+        buf: &'static mut [u8],
+        dst_pan: PanID,
+        dst_addr: MacAddress,
+        src_pan: PanID,
+        src_addr: MacAddress,
+        security_needed: Option<(SecurityLevel, KeyId)>,
+        header_ies: &[HeaderIE],
+    ) -> Result<Frame, &'static mut [u8]> {
+        self.prepare_frame(
+            FrameType::Data,
+            buf,
+            dst_pan,
+            dst_addr,
+            src_pan,
+            src_addr,
+            security_needed,
+            header_ies,
+        )
+    }
 
-        match header.encode(&mut buf[radio::PSDU_OFFSET..], true).done() {
-            Some((data_offset, mac_payload_offset)) => Ok(Frame {
-                buf: buf,
-                info: FrameInfo {
-                    frame_type: FrameType::Data,
-                    mac_payload_offset: mac_payload_offset,
-                    data_offset: data_offset,
-                    data_len: 0,
-                    mic_len: mic_len,
-                    security_params: security_desc.map(|(sec, key, nonce)| (sec.level, key, nonce)),
-                },
-            }),
-            None => Err(buf),
+    fn prepare_command_frame(
+        &self,
+        buf: &'static mut [u8],
+        dst_pan: PanID,
+        dst_addr: MacAddress,
+        src_pan: PanID,
+        src_addr: MacAddress,
+        command_id: u8,
+    ) -> Result<Frame, &'static mut [u8]> {
+        let mut frame = self.prepare_frame(
+            FrameType::MACCommand,
+            buf,
+            dst_pan,
+            dst_addr,
+            src_pan,
+            src_addr,
+            None,
+            &[],
+        )?;
+        match frame.append_payload(&[command_id]) {
+            ReturnCode::SUCCESS => Ok(frame),
+            _ => Err(frame.into_buf()),
         }
     }
 
@@ -787,15 +1250,44 @@ impl<M: Mac, A: AES128CCM<'a>> MacDevice<'a> for Framer<'a, M, A> {
 
 impl<M: Mac, A: AES128CCM<'a>> radio::TxClient for Framer<'a, M, A> {
     fn send_done(&self, buf: &'static mut [u8], acked: bool, result: ReturnCode) {
+        // Retransmit a frame that was sent but not acknowledged. The radio has
+        // already waited for the acknowledgement, so this is done right away.
+        let retry = result == ReturnCode::SUCCESS
+            && !acked
+            && ack_requested(buf)
+            && self.tx_retries.get() < self.max_frame_retries.get();
+        let (buf, result) = if retry {
+            self.tx_retries.set(self.tx_retries.get() + 1);
+            let (len, cca, _) = self.tx_params.get();
+            match self.mac.transmit(buf, len, cca) {
+                (_, None) => return,
+                (rval, Some(buf)) => (buf, rval),
+            }
+        } else {
+            (buf, result)
+        };
+
         self.data_sequence.set(self.data_sequence.get() + 1);
-        self.tx_client.map(move |client| {
-            client.send_done(buf, acked, result);
-        });
+        // A frame may have been refused while this one was being sent; it
+        // goes first so frames leave in the order they were requested. The
+        // completed frame is routed by the parameters recorded when the radio
+        // accepted it, which the waiting frame replaces once it is accepted.
+        let (_, _, indirect) = self.tx_params.get();
+        self.resume_transmit();
+        self.tx_done(buf, acked, result, indirect);
     }
 }
 
 impl<M: Mac, A: AES128CCM<'a>> radio::RxClient for Framer<'a, M, A> {
-    fn receive(&self, buf: &'static mut [u8], frame_len: usize, crc_valid: bool, _: ReturnCode) {
+    fn receive(
+        &self,
+        buf: &'static mut [u8],
+        frame_len: usize,
+        lqi: u8,
+        rssi: i8,
+        crc_valid: bool,
+        _: ReturnCode,
+    ) {
         // Drop all frames with invalid CRC
         if !crc_valid {
             self.mac.set_receive_buffer(buf);
@@ -807,6 +1299,7 @@ impl<M: Mac, A: AES128CCM<'a>> radio::RxClient for Framer<'a, M, A> {
                 RxState::Idle => {
                     // We can start processing a new received frame only if
                     // the reception pipeline is free
+                    self.rx_link_quality.set((lqi, rssi));
                     self.incoming_frame_security(buf, frame_len)
                 }
                 other_state => {
@@ -829,13 +1322,7 @@ impl<M: Mac, A: AES128CCM<'a>> radio::ConfigClient for Framer<'a, M, A> {
         // The transmission pipeline is the only state machine that
         // waits for the configuration procedure to complete before
         // advancing.
-        let (rval, buf) = self.step_transmit_state();
-        if let Some(buf) = buf {
-            // Return the buffer to the transmit client
-            self.tx_client.map(move |client| {
-                client.send_done(buf, false, rval);
-            });
-        }
+        self.resume_transmit();
     }
 }
 
@@ -858,9 +1345,7 @@ impl<M: Mac, A: AES128CCM<'a>> CCMClient for Framer<'a, M, A> {
 
                     if let Some(buf) = opt_buf {
                         // Abort the transmission process. Return the buffer to the client.
-                        self.tx_client.map(move |client| {
-                            client.send_done(buf, false, rval);
-                        });
+                        self.abort_transmit(buf, rval);
                     }
                     None
                 }
@@ -896,6 +1381,9 @@ impl<M: Mac, A: AES128CCM<'a>> CCMClient for Framer<'a, M, A> {
                             _ => false,
                         };
                         self.rx_state.replace(other_state);
+                        // Neither pipeline was waiting for this buffer, so
+                        // there is nobody to return it to.
+                        debug!("aes_ccm returned an unexpected buffer");
                     }
                 };
             });
@@ -906,12 +1394,207 @@ impl<M: Mac, A: AES128CCM<'a>> CCMClient for Framer<'a, M, A> {
             let (rval, opt_buf) = self.step_transmit_state();
             if let Some(buf) = opt_buf {
                 // Return the buffer to the client.
-                self.tx_client.map(move |client| {
-                    client.send_done(buf, false, rval);
-                });
+                self.abort_transmit(buf, rval);
             }
         } else if rx_waiting {
             self.step_receive_state();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::boxed::Box;
+    use self::std::vec;
+    use super::*;
+    use kernel::common::cells::TakeCell;
+
+    /// Give a test value the `'static` lifetime that boards get from
+    /// `static_init!`.
+    fn leak<T>(value: T) -> &'static T {
+        Box::leak(Box::new(value))
+    }
+
+    /// A MAC that accepts every frame and holds its buffer until the test
+    /// reports the transmission done.
+    struct FakeMac {
+        tx_buf: TakeCell<'static, [u8]>,
+        transmits: Cell<usize>,
+    }
+
+    impl Mac for FakeMac {
+        fn initialize(&self, _mac_buf: &'static mut [u8]) -> ReturnCode {
+            ReturnCode::SUCCESS
+        }
+
+        fn set_config_client(&self, _client: &'static radio::ConfigClient) {}
+        fn set_transmit_client(&self, _client: &'static radio::TxClient) {}
+        fn set_receive_client(&self, _client: &'static radio::RxClient) {}
+        fn set_receive_buffer(&self, _buffer: &'static mut [u8]) {}
+
+        fn get_address(&self) -> u16 {
+            2
+        }
+
+        fn get_address_long(&self) -> [u8; 8] {
+            [0; 8]
+        }
+
+        fn get_pan(&self) -> u16 {
+            0xabcd
+        }
+
+        fn set_address(&self, _addr: u16) {}
+        fn set_address_long(&self, _addr: [u8; 8]) {}
+        fn set_pan(&self, _id: u16) {}
+
+        fn config_commit(&self) {}
+
+        fn is_on(&self) -> bool {
+            true
+        }
+
+        fn transmit(
+            &self,
+            full_mac_frame: &'static mut [u8],
+            _frame_len: usize,
+            _cca: bool,
+        ) -> (ReturnCode, Option<&'static mut [u8]>) {
+            self.transmits.set(self.transmits.get() + 1);
+            self.tx_buf.replace(full_mac_frame);
+            (ReturnCode::SUCCESS, None)
+        }
+    }
+
+    /// Unsecured frames never reach the CCM engine.
+    struct NoCcm;
+
+    impl AES128CCM<'a> for NoCcm {
+        fn set_client(&'a self, _client: &'a CCMClient) {}
+
+        fn set_key(&self, _key: &[u8]) -> ReturnCode {
+            ReturnCode::ENOSUPPORT
+        }
+
+        fn set_nonce(&self, _nonce: &[u8]) -> ReturnCode {
+            ReturnCode::ENOSUPPORT
+        }
+
+        fn crypt(
+            &self,
+            buf: &'static mut [u8],
+            _a_off: usize,
+            _m_off: usize,
+            _m_len: usize,
+            _mic_len: usize,
+            _confidential: bool,
+            _encrypting: bool,
+        ) -> (ReturnCode, Option<&'static mut [u8]>) {
+            (ReturnCode::ENOSUPPORT, Some(buf))
+        }
+    }
+
+    struct TestClient {
+        done: Cell<Option<(bool, ReturnCode)>>,
+    }
+
+    impl TxClient for TestClient {
+        fn send_done(&self, _spi_buf: &'static mut [u8], acked: bool, result: ReturnCode) {
+            assert!(self.done.get().is_none());
+            self.done.set(Some((acked, result)));
+        }
+    }
+
+    fn framer() -> (
+        &'static Framer<'static, FakeMac, NoCcm>,
+        &'static FakeMac,
+        &'static TestClient,
+    ) {
+        let mac = leak(FakeMac {
+            tx_buf: TakeCell::empty(),
+            transmits: Cell::new(0),
+        });
+        let framer = leak(Framer::new(mac, leak(NoCcm)));
+        let client = leak(TestClient {
+            done: Cell::new(None),
+        });
+        framer.set_transmit_client(client);
+        (framer, mac, client)
+    }
+
+    // Sends a unicast data frame, which requests an acknowledgement.
+    fn send(framer: &Framer<'static, FakeMac, NoCcm>) {
+        let buf = Box::leak(vec![0; radio::MAX_BUF_SIZE].into_boxed_slice());
+        let frame = framer
+            .prepare_data_frame(
+                buf,
+                0xabcd,
+                MacAddress::Short(1),
+                0xabcd,
+                MacAddress::Short(2),
+                None,
+            ).ok()
+            .expect("frame was not prepared");
+        assert_eq!(framer.transmit(frame).0, ReturnCode::SUCCESS);
+    }
+
+    // Reports the frame held by the MAC as sent.
+    fn send_done(framer: &Framer<'static, FakeMac, NoCcm>, mac: &FakeMac, acked: bool) {
+        let buf = mac.tx_buf.take().expect("no frame was transmitted");
+        radio::TxClient::send_done(framer, buf, acked, ReturnCode::SUCCESS);
+    }
+
+    #[test]
+    fn unacknowledged_frame_is_retried_up_to_limit() {
+        let (framer, mac, client) = framer();
+        send(framer);
+
+        for retry in 1..=DEFAULT_MAX_FRAME_RETRIES as usize {
+            send_done(framer, mac, false);
+            assert_eq!(mac.transmits.get(), 1 + retry);
+            assert_eq!(client.done.get(), None);
+        }
+        send_done(framer, mac, false);
+        assert_eq!(mac.transmits.get(), 1 + DEFAULT_MAX_FRAME_RETRIES as usize);
+        assert_eq!(client.done.get(), Some((false, ReturnCode::SUCCESS)));
+    }
+
+    #[test]
+    fn acknowledgement_ends_retries() {
+        let (framer, mac, client) = framer();
+        send(framer);
+
+        send_done(framer, mac, false);
+        send_done(framer, mac, true);
+        assert_eq!(mac.transmits.get(), 2);
+        assert_eq!(client.done.get(), Some((true, ReturnCode::SUCCESS)));
+    }
+
+    #[test]
+    fn retries_restart_for_each_frame() {
+        let (framer, mac, client) = framer();
+        assert_eq!(framer.set_max_frame_retries(1), ReturnCode::SUCCESS);
+
+        send(framer);
+        send_done(framer, mac, false);
+        send_done(framer, mac, false);
+        assert_eq!(client.done.get(), Some((false, ReturnCode::SUCCESS)));
+        client.done.set(None);
+
+        send(framer);
+        send_done(framer, mac, false);
+        assert_eq!(client.done.get(), None);
+        send_done(framer, mac, false);
+        assert_eq!(mac.transmits.get(), 4);
+        assert_eq!(client.done.get(), Some((false, ReturnCode::SUCCESS)));
+    }
+
+    #[test]
+    fn max_frame_retries_is_bounded() {
+        let (framer, _, _) = framer();
+        assert_eq!(framer.set_max_frame_retries(MAX_FRAME_RETRIES), ReturnCode::SUCCESS);
+        assert_eq!(framer.set_max_frame_retries(MAX_FRAME_RETRIES + 1), ReturnCode::EINVAL);
+    }
+}
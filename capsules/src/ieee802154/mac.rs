@@ -10,10 +10,30 @@
 //! through each frame for transmission.
 
 use kernel::common::cells::OptionalCell;
+use kernel::hil::device_id::DeviceId;
 use kernel::hil::radio;
 use kernel::ReturnCode;
 use net::ieee802154::{Header, MacAddress};
 
+/// Derives an EUI-64 for the radio from the chip's factory device identifier,
+/// so that each device gets a unique extended address at boot. If the
+/// identifier is longer than 8 bytes, its least significant 8 bytes are used.
+/// Since the identifier is not an IEEE-assigned EUI-64, the address is marked
+/// as locally administered by setting the universal/local bit, and as an
+/// individual address by clearing the group bit.
+pub fn eui64_from_device_id(device_id: &DeviceId) -> [u8; 8] {
+    let mut id = [0; 16];
+    let len = device_id.read_id(&mut id);
+    let mut eui64 = [0; 8];
+    if len >= eui64.len() {
+        eui64.copy_from_slice(&id[len - 8..len]);
+    } else {
+        eui64[8 - len..].copy_from_slice(&id[..len]);
+    }
+    eui64[0] = (eui64[0] | 0x02) & !0x01;
+    eui64
+}
+
 pub trait Mac {
     /// Initializes the layer; may require a buffer to temporarily retaining frames to be
     /// transmitted
@@ -53,11 +73,13 @@ pub trait Mac {
     fn is_on(&self) -> bool;
 
     /// Transmits complete MAC frames, which must be prepared by an ieee802154::device::MacDevice
-    /// before being passed to the Mac layer. Returns the frame buffer in case of an error.
+    /// before being passed to the Mac layer. If `cca` is false, the frame is sent without
+    /// performing CSMA-CA. Returns the frame buffer in case of an error.
     fn transmit(
         &self,
         full_mac_frame: &'static mut [u8],
         frame_len: usize,
+        cca: bool,
     ) -> (ReturnCode, Option<&'static mut [u8]>);
 }
 
@@ -141,8 +163,9 @@ impl<R: radio::Radio> Mac for AwakeMac<'a, R> {
         &self,
         full_mac_frame: &'static mut [u8],
         frame_len: usize,
+        cca: bool,
     ) -> (ReturnCode, Option<&'static mut [u8]>) {
-        self.radio.transmit(full_mac_frame, frame_len)
+        self.radio.transmit(full_mac_frame, frame_len, cca)
     }
 }
 
@@ -159,6 +182,8 @@ impl<R: radio::Radio> radio::RxClient for AwakeMac<'a, R> {
         &self,
         buf: &'static mut [u8],
         frame_len: usize,
+        lqi: u8,
+        rssi: i8,
         crc_valid: bool,
         result: ReturnCode,
     ) {
@@ -176,7 +201,7 @@ impl<R: radio::Radio> radio::RxClient for AwakeMac<'a, R> {
         if addr_match {
             debug!("[AwakeMAC] Rcvd a 15.4 frame addressed to this device");
             self.rx_client.map(move |c| {
-                c.receive(buf, frame_len, crc_valid, result);
+                c.receive(buf, frame_len, lqi, rssi, crc_valid, result);
             });
         } else {
             debug!("[AwakeMAC] Received a packet, but not addressed to us");
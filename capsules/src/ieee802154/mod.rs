@@ -1,3 +1,4 @@
+pub mod association;
 pub mod device;
 pub mod framer;
 pub mod mac;
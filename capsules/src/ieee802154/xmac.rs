@@ -154,6 +154,7 @@ pub struct XMac<'a, R: radio::Radio, A: Alarm> {
     tx_header: Cell<Option<XMacHeaderInfo>>,
     tx_payload: TakeCell<'static, [u8]>,
     tx_len: Cell<usize>,
+    tx_cca: Cell<bool>,
 
     tx_preamble_pending: Cell<bool>,
     tx_preamble_seq_num: Cell<u8>,
@@ -175,6 +176,7 @@ impl<R: radio::Radio, A: Alarm> XMac<'a, R, A> {
             tx_header: Cell::new(None),
             tx_payload: TakeCell::empty(),
             tx_len: Cell::new(0),
+            tx_cca: Cell::new(true),
             tx_preamble_pending: Cell::new(false),
             tx_preamble_seq_num: Cell::new(0),
             tx_preamble_buf: TakeCell::empty(),
@@ -249,7 +251,7 @@ impl<R: radio::Radio, A: Alarm> XMac<'a, R, A> {
             match header.encode(&mut buf[radio::PSDU_OFFSET..], true).done() {
                 // If we can successfully encode the preamble, transmit.
                 Some((data_offset, _)) => {
-                    result = self.radio.transmit(buf, data_offset + radio::PSDU_OFFSET);
+                    result = self.radio.transmit(buf, data_offset + radio::PSDU_OFFSET, true);
                 }
                 None => {
                     self.tx_preamble_buf.replace(buf);
@@ -272,7 +274,7 @@ impl<R: radio::Radio, A: Alarm> XMac<'a, R, A> {
             let result: (ReturnCode, Option<&'static mut [u8]>);
             let tx_buf = self.tx_payload.take().unwrap();
 
-            result = self.radio.transmit(tx_buf, self.tx_len.get());
+            result = self.radio.transmit(tx_buf, self.tx_len.get(), self.tx_cca.get());
 
             if result.0 != ReturnCode::SUCCESS {
                 self.call_tx_client(result.1.unwrap(), false, result.0);
@@ -296,6 +298,8 @@ impl<R: radio::Radio, A: Alarm> XMac<'a, R, A> {
         &self,
         buf: &'static mut [u8],
         len: usize,
+        lqi: u8,
+        rssi: i8,
         crc_valid: bool,
         result: ReturnCode,
     ) {
@@ -303,7 +307,7 @@ impl<R: radio::Radio, A: Alarm> XMac<'a, R, A> {
         self.sleep();
 
         self.rx_client.map(move |c| {
-            c.receive(buf, len, crc_valid, result);
+            c.receive(buf, len, lqi, rssi, crc_valid, result);
         });
     }
 }
@@ -326,10 +330,14 @@ impl<R: radio::Radio, A: Alarm> rng::Client for XMac<'a, R, A> {
                     // the callback and randomly determine the remaining time
                     // spent backing off.
                     let time_remaining_ms =
-                        (((self.alarm.get_alarm().wrapping_sub(self.alarm.now())) as f32
+                        ((self.alarm.remaining_tics().unwrap_or(0) as f32
                             / <A::Frequency>::frequency() as f32)
                             * 1000.0) as u32;
-                    self.set_timer_ms::<A>(random % time_remaining_ms);
+                    if time_remaining_ms > 0 {
+                        self.set_timer_ms::<A>(random % time_remaining_ms);
+                    } else {
+                        self.set_timer_ms::<A>(0);
+                    }
                 }
                 rng::Continue::Done
             }
@@ -403,6 +411,7 @@ impl<R: radio::Radio, A: Alarm> Mac for XMac<'a, R, A> {
         &self,
         full_mac_frame: &'static mut [u8],
         frame_len: usize,
+        cca: bool,
     ) -> (ReturnCode, Option<&'static mut [u8]>) {
         // If the radio is busy, we already have data to transmit, or the buffer
         // size is wrong, fail before attempting to send any preamble packets
@@ -432,6 +441,7 @@ impl<R: radio::Radio, A: Alarm> Mac for XMac<'a, R, A> {
         match self.tx_header.get() {
             Some(_) => {
                 self.tx_payload.replace(full_mac_frame);
+                self.tx_cca.set(cca);
             }
             None => {
                 return (ReturnCode::FAIL, Some(full_mac_frame));
@@ -560,6 +570,8 @@ impl<R: radio::Radio, A: Alarm> radio::RxClient for XMac<'a, R, A> {
         &self,
         buf: &'static mut [u8],
         frame_len: usize,
+        lqi: u8,
+        rssi: i8,
         crc_valid: bool,
         result: ReturnCode,
     ) {
@@ -621,7 +633,7 @@ impl<R: radio::Radio, A: Alarm> radio::RxClient for XMac<'a, R, A> {
 
         if data_received {
             self.rx_pending.set(false);
-            self.call_rx_client(buf, frame_len, crc_valid, result);
+            self.call_rx_client(buf, frame_len, lqi, rssi, crc_valid, result);
         } else {
             self.radio.set_receive_buffer(buf);
         }
@@ -4,23 +4,29 @@
 //! to know which of the GPIO pins exposed across the syscall interface are
 //! LEDs.
 //!
-//! This capsule takes an array of pins and the polarity of the LED (active high
-//! or active low). This allows the board to configure how the underlying GPIO
-//! must be controlled to turn on and off LEDs, such that the syscall driver
-//! interface can be agnostic to the LED polarity.
+//! This capsule takes an array of references to LEDs implementing
+//! `hil::led::Led`, which need not all be of the same type. For LEDs driven
+//! by a GPIO pin, `hil::led::LedHigh` and `hil::led::LedLow` handle the
+//! polarity of the LED (active high or active low), such that the syscall
+//! driver interface can be agnostic to the LED polarity. LEDs driven some
+//! other way, for example through PWM or a GPIO expander, can be used by
+//! implementing `hil::led::Led` for them.
 //!
 //! Usage
 //! -----
 //!
 //! ```rust
 //! let led_pins = static_init!(
-//!     [(&'static sam4l::gpio::GPIOPin, capsules::led::ActivationMode); 3],
-//!     [(&sam4l::gpio::PA[13], capsules::led::ActivationMode::ActiveLow),   // Red
-//!      (&sam4l::gpio::PA[15], capsules::led::ActivationMode::ActiveLow),   // Green
-//!      (&sam4l::gpio::PA[14], capsules::led::ActivationMode::ActiveLow)]); // Blue
+//!     [kernel::hil::led::LedLow<'static>; 3],
+//!     [kernel::hil::led::LedLow::new(&sam4l::gpio::PA[13]),   // Red
+//!      kernel::hil::led::LedLow::new(&sam4l::gpio::PA[15]),   // Green
+//!      kernel::hil::led::LedLow::new(&sam4l::gpio::PA[14])]); // Blue
+//! let leds = static_init!(
+//!     [&'static kernel::hil::led::Led; 3],
+//!     [&led_pins[0], &led_pins[1], &led_pins[2]]);
 //! let led = static_init!(
-//!     capsules::led::LED<'static, sam4l::gpio::GPIOPin>,
-//!     capsules::led::LED::new(led_pins));
+//!     capsules::led::LED<'static>,
+//!     capsules::led::LED::new(leds));
 //! ```
 //!
 //! Syscall Interface
@@ -54,37 +60,25 @@ use kernel::{AppId, Driver, ReturnCode};
 /// Syscall driver number.
 pub const DRIVER_NUM: usize = 0x00000002;
 
-/// Whether the LEDs are active high or active low on this platform.
-#[derive(Clone, Copy)]
-pub enum ActivationMode {
-    ActiveHigh,
-    ActiveLow,
+/// Holds the array of LEDs and implements a `Driver` interface to control
+/// them.
+pub struct LED<'a> {
+    leds: &'a [&'a hil::led::Led],
 }
 
-/// Holds the array of GPIO pins attached to the LEDs and implements a `Driver`
-/// interface to control them.
-pub struct LED<'a, G: hil::gpio::Pin> {
-    pins_init: &'a [(&'a G, ActivationMode)],
-}
-
-impl<G: hil::gpio::Pin + hil::gpio::PinCtl> LED<'a, G> {
-    pub fn new(pins_init: &'a [(&'a G, ActivationMode)]) -> LED<'a, G> {
-        // Make all pins output and off
-        for &(pin, mode) in pins_init.as_ref().iter() {
-            pin.make_output();
-            match mode {
-                ActivationMode::ActiveHigh => pin.clear(),
-                ActivationMode::ActiveLow => pin.set(),
-            }
+impl LED<'a> {
+    pub fn new(leds: &'a [&'a hil::led::Led]) -> LED<'a> {
+        // Initialize all LEDs and turn them off
+        for led in leds.iter() {
+            led.init();
+            led.off();
         }
 
-        LED {
-            pins_init: pins_init,
-        }
+        LED { leds: leds }
     }
 }
 
-impl<G: hil::gpio::Pin + hil::gpio::PinCtl> Driver for LED<'a, G> {
+impl Driver for LED<'a> {
     /// Control the LEDs.
     ///
     /// ### `command_num`
@@ -98,48 +92,39 @@ impl<G: hil::gpio::Pin + hil::gpio::PinCtl> Driver for LED<'a, G> {
     /// - `3`: Toggle the LED at index specified by `data` on or off. Returns
     ///        `EINVAL` if the LED index is not valid.
     fn command(&self, command_num: usize, data: usize, _: usize, _: AppId) -> ReturnCode {
-        let pins_init = self.pins_init.as_ref();
+        let leds = self.leds;
         match command_num {
             // get number of LEDs
             0 => ReturnCode::SuccessWithValue {
-                value: pins_init.len() as usize,
+                value: leds.len() as usize,
             },
 
             // on
             1 => {
-                if data >= pins_init.len() {
-                    ReturnCode::EINVAL /* impossible pin */
+                if data >= leds.len() {
+                    ReturnCode::EINVAL /* impossible LED */
                 } else {
-                    let (pin, mode) = pins_init[data];
-                    match mode {
-                        ActivationMode::ActiveHigh => pin.set(),
-                        ActivationMode::ActiveLow => pin.clear(),
-                    }
+                    leds[data].on();
                     ReturnCode::SUCCESS
                 }
             }
 
             // off
             2 => {
-                if data >= pins_init.len() {
-                    ReturnCode::EINVAL /* impossible pin */
+                if data >= leds.len() {
+                    ReturnCode::EINVAL /* impossible LED */
                 } else {
-                    let (pin, mode) = pins_init[data];
-                    match mode {
-                        ActivationMode::ActiveHigh => pin.clear(),
-                        ActivationMode::ActiveLow => pin.set(),
-                    }
+                    leds[data].off();
                     ReturnCode::SUCCESS
                 }
             }
 
             // toggle
             3 => {
-                if data >= pins_init.len() {
-                    ReturnCode::EINVAL /* impossible pin */
+                if data >= leds.len() {
+                    ReturnCode::EINVAL /* impossible LED */
                 } else {
-                    let (pin, _) = pins_init[data];
-                    pin.toggle();
+                    leds[data].toggle();
                     ReturnCode::SUCCESS
                 }
             }
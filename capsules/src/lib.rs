@@ -18,6 +18,7 @@ pub mod net;
 pub mod adc;
 pub mod aes_ccm;
 pub mod alarm;
+pub mod alarm_timer;
 pub mod ambient_light;
 pub mod analog_comparator;
 pub mod app_flash_driver;
@@ -27,6 +28,13 @@ pub mod console;
 pub mod crc;
 pub mod dac;
 pub mod debug_process_restart;
+pub mod device_id;
+pub mod edge_capture;
+pub mod edge_counter;
+pub mod energy_detect;
+pub mod environment;
+#[cfg(feature = "fault_injection")]
+pub mod fault_injector;
 pub mod fm25cl;
 pub mod fxos8700cq;
 pub mod gpio;
@@ -37,6 +45,7 @@ pub mod i2c_master_slave_driver;
 pub mod ieee802154;
 pub mod isl29035;
 pub mod led;
+pub mod log_storage;
 pub mod lps25hb;
 pub mod ltc294x;
 pub mod max17205;
@@ -46,13 +55,17 @@ pub mod ninedof;
 pub mod nonvolatile_storage_driver;
 pub mod nonvolatile_to_pages;
 pub mod nrf51822_serialization;
+pub mod periodic_sampler;
 pub mod pca9544a;
+pub mod prng;
 pub mod process_console;
+pub mod process_supervisor;
 pub mod rf233;
 pub mod rf233_const;
 pub mod rng;
 pub mod sdcard;
 pub mod segger_rtt;
+pub mod sha256;
 pub mod si7021;
 pub mod spi;
 pub mod temperature;
@@ -64,5 +77,7 @@ pub mod usbc_client;
 pub mod virtual_alarm;
 pub mod virtual_flash;
 pub mod virtual_i2c;
+pub mod virtual_rng;
 pub mod virtual_spi;
+pub mod virtual_temperature;
 pub mod virtual_uart;
@@ -0,0 +1,858 @@
+//! Circular append-only log stored in a region of flash.
+//!
+//! Entries are appended to the log as length-prefixed records and read back
+//! in the order they were appended. When the region is full, the page holding
+//! the oldest entries is reused, so the log keeps the most recent entries.
+//! This is useful for event logging on devices without a filesystem.
+//!
+//! Each page of the region starts with a 32-bit sequence number, which
+//! increases every time a page is started, so that the oldest and newest
+//! pages can be found after a reboot. Records follow, each a 16-bit length, a
+//! CRC-16 of the entry, and then the entry itself. Records do not span pages,
+//! so an entry can be at most a page long, less the page and record headers.
+//!
+//! ```plain
+//! ┌──────────┬────────┬───────┬───────┬────────┬───────┬───────┬─────┬───────────┐
+//! │ sequence │ length │ CRC   │ entry │ length │ CRC   │ entry │ ... │ 0xff 0xff │
+//! │ (4 B)    │ (2 B)  │ (2 B) │       │ (2 B)  │ (2 B) │       │     │ (erased)  │
+//! └──────────┴────────┴───────┴───────┴────────┴───────┴───────┴─────┴───────────┘
+//! ```
+//!
+//! Flash pages can only be written whole, and writing a page erases it first,
+//! so appends are collected in a copy of the newest page in RAM, and each
+//! page is written to flash once, when it fills. Entries still in RAM are
+//! lost on reset. `sync` writes them to flash without waiting for the page to
+//! fill, at the cost of writing the page again once it does.
+//!
+//! Power loss while a page is written can leave torn records. Reading a page
+//! stops at the first record whose length does not fit in the rest of the
+//! page or whose CRC does not match its entry, and appends after a torn
+//! record continue on the next page.
+//!
+//! The region should be erased before it is used as a log for the first time.
+//!
+//! Usage
+//! -----
+//!
+//! ```
+//! pub static mut LOG_PAGE: nrf52::nvmc::NrfPage = nrf52::nvmc::NrfPage::new();
+//! pub static mut LOG_SPARE_PAGE: nrf52::nvmc::NrfPage = nrf52::nvmc::NrfPage::new();
+//! let log = static_init!(
+//!     capsules::log_storage::LogStorage<'static, nrf52::nvmc::Nvmc>,
+//!     capsules::log_storage::LogStorage::new(
+//!         &nrf52::nvmc::NVMC,
+//!         &mut LOG_PAGE,
+//!         &mut LOG_SPARE_PAGE,
+//!         0x60,   // First page of the region
+//!         8));    // Number of pages in the region
+//! hil::flash::HasClient::set_client(&nrf52::nvmc::NVMC, log);
+//! log.set_client(client);
+//! log.mount();
+//! ```
+
+use core::cell::Cell;
+use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::hil;
+use kernel::ReturnCode;
+
+/// Size of the sequence number at the start of each page.
+const PAGE_HEADER_SIZE: usize = 4;
+
+/// Size of the length and CRC at the start of each record.
+const RECORD_HEADER_SIZE: usize = 4;
+
+/// Sequence number of a page that has been erased and not yet started.
+const ERASED_SEQUENCE: u32 = 0xffffffff;
+
+/// Receives the results of log operations.
+pub trait LogStorageClient {
+    /// The oldest and newest entries of the log have been found, and entries
+    /// can be appended and read.
+    fn mount_done(&self, result: ReturnCode);
+
+    /// Entries held in RAM were written to flash, either because their page
+    /// filled or because of a call to `sync`. If `result` is `SUCCESS`, every
+    /// entry appended before the write started is stored in flash.
+    fn write_done(&self, result: ReturnCode);
+
+    /// The next entry, `length` bytes long, was read into `buffer`. `length`
+    /// is zero if there are no more entries. If the entry does not fit in
+    /// `buffer`, `result` is `ESIZE`, nothing is read, and the entry can be
+    /// read again with a larger buffer.
+    fn read_done(&self, buffer: &'static mut [u8], length: usize, result: ReturnCode);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Idle,
+    /// Reading each page of the region to find the oldest and newest pages.
+    Mount(usize),
+    /// Reading the newest page to find where its records end.
+    MountScan,
+    /// Reading the page at the read position.
+    Read,
+    /// Writing a page of entries.
+    Write,
+}
+
+pub struct LogStorage<'a, F: hil::flash::Flash + 'static> {
+    driver: &'a F,
+    client: OptionalCell<&'a LogStorageClient>,
+    /// The newest page, which appends are added to.
+    pagebuffer: TakeCell<'static, F::Page>,
+    /// A second page used to read flash and to write pages while appends
+    /// continue in `pagebuffer`. It is empty while a flash operation is in
+    /// progress.
+    sparebuffer: TakeCell<'static, F::Page>,
+    /// The region of flash used for the log, in pages.
+    first_page: usize,
+    num_pages: usize,
+    state: Cell<State>,
+    mounted: Cell<bool>,
+    /// The page holding the oldest entries and the number of pages in use,
+    /// as indices into the region.
+    oldest: Cell<usize>,
+    used_pages: Cell<usize>,
+    /// The page being appended to and the offset of the next record in it.
+    newest: Cell<usize>,
+    append_offset: Cell<usize>,
+    /// Whether the page being appended to holds entries not yet in flash.
+    dirty: Cell<bool>,
+    next_sequence: Cell<u32>,
+    /// The page and offset of the next record to read.
+    read_page: Cell<usize>,
+    read_offset: Cell<usize>,
+    /// The oldest and newest pages found while mounting, with their sequence
+    /// numbers.
+    mount_oldest: Cell<Option<(usize, u32)>>,
+    mount_newest: Cell<Option<(usize, u32)>>,
+    /// The buffer being read into.
+    buffer: TakeCell<'static, [u8]>,
+}
+
+/// Reads the little-endian value of `len` bytes at `offset` in `page`.
+fn read_le(page: &[u8], offset: usize, len: usize) -> u32 {
+    (0..len).fold(0, |value, i| value | (page[offset + i] as u32) << (8 * i))
+}
+
+/// CRC-16/CCITT-FALSE of `data`.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xffff, |crc, byte| {
+        let mut crc = crc ^ (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Returns the length of the entry in the record at `offset` in `page`, or
+/// `None` if there is no complete and intact record there.
+fn record_at(page: &[u8], offset: usize) -> Option<usize> {
+    if offset + RECORD_HEADER_SIZE > page.len() {
+        return None;
+    }
+    let length = read_le(page, offset, 2) as usize;
+    let start = offset + RECORD_HEADER_SIZE;
+    if length == 0 || start + length > page.len() {
+        // Either erased flash, or a record torn by power loss.
+        return None;
+    }
+    let crc = read_le(page, offset + 2, 2) as u16;
+    if crc16(&page[start..start + length]) != crc {
+        return None;
+    }
+    Some(length)
+}
+
+impl<F: hil::flash::Flash> LogStorage<'a, F> {
+    pub fn new(
+        driver: &'a F,
+        pagebuffer: &'static mut F::Page,
+        sparebuffer: &'static mut F::Page,
+        first_page: usize,
+        num_pages: usize,
+    ) -> LogStorage<'a, F> {
+        LogStorage {
+            driver: driver,
+            client: OptionalCell::empty(),
+            pagebuffer: TakeCell::new(pagebuffer),
+            sparebuffer: TakeCell::new(sparebuffer),
+            first_page: first_page,
+            num_pages: num_pages,
+            state: Cell::new(State::Idle),
+            mounted: Cell::new(false),
+            oldest: Cell::new(0),
+            used_pages: Cell::new(0),
+            newest: Cell::new(0),
+            append_offset: Cell::new(0),
+            dirty: Cell::new(false),
+            next_sequence: Cell::new(0),
+            read_page: Cell::new(0),
+            read_offset: Cell::new(PAGE_HEADER_SIZE),
+            mount_oldest: Cell::new(None),
+            mount_newest: Cell::new(None),
+            buffer: TakeCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a LogStorageClient) {
+        self.client.set(client);
+    }
+
+    /// Finds the oldest and newest entries of the log. Must complete before
+    /// entries are appended or read. The read position starts at the oldest
+    /// entry. Entries appended since the last write to flash are discarded.
+    pub fn mount(&self) -> ReturnCode {
+        if self.state.get() != State::Idle {
+            return ReturnCode::EBUSY;
+        }
+        if self.num_pages == 0 {
+            return ReturnCode::EINVAL;
+        }
+        self.mounted.set(false);
+        self.dirty.set(false);
+        self.mount_oldest.set(None);
+        self.mount_newest.set(None);
+        self.read_region_page(State::Mount(0), 0)
+    }
+
+    /// Appends `entry` to the log. The entry is kept in RAM until its page
+    /// fills or `sync` is called. If the log is full, the oldest page of
+    /// entries is reused to make room. Returns `EBUSY` if the entry needs a
+    /// new page while a page is still being written or read.
+    pub fn append(&self, entry: &[u8]) -> ReturnCode {
+        if !self.mounted.get() {
+            return ReturnCode::FAIL;
+        }
+        let length = entry.len();
+        if length == 0 {
+            return ReturnCode::EINVAL;
+        }
+        let page_size = self.page_size();
+        if PAGE_HEADER_SIZE + RECORD_HEADER_SIZE + length > page_size {
+            return ReturnCode::ESIZE;
+        }
+        if self.append_offset.get() + RECORD_HEADER_SIZE + length > page_size {
+            let rval = self.start_next_page();
+            if rval != ReturnCode::SUCCESS {
+                return rval;
+            }
+        }
+
+        let offset = self.append_offset.get();
+        let crc = crc16(entry);
+        self.pagebuffer.map(|pagebuffer| {
+            let page = pagebuffer.as_mut();
+            page[offset] = length as u8;
+            page[offset + 1] = (length >> 8) as u8;
+            page[offset + 2] = crc as u8;
+            page[offset + 3] = (crc >> 8) as u8;
+            page[offset + RECORD_HEADER_SIZE..offset + RECORD_HEADER_SIZE + length]
+                .copy_from_slice(entry);
+        });
+        self.append_offset
+            .set(offset + RECORD_HEADER_SIZE + length);
+        self.dirty.set(true);
+        ReturnCode::SUCCESS
+    }
+
+    /// Writes the entries held in RAM to flash. The client's `write_done` is
+    /// called once they are stored. Returns `EALREADY` if every entry is
+    /// already in flash, and `EBUSY` if a page is being written or read.
+    pub fn sync(&self) -> ReturnCode {
+        if !self.mounted.get() {
+            return ReturnCode::FAIL;
+        }
+        if !self.dirty.get() {
+            return ReturnCode::EALREADY;
+        }
+        let sparebuffer = match self.sparebuffer.take() {
+            Some(sparebuffer) => sparebuffer,
+            None => return ReturnCode::EBUSY,
+        };
+        self.pagebuffer.map(|pagebuffer| {
+            sparebuffer
+                .as_mut()
+                .copy_from_slice(pagebuffer.as_mut());
+        });
+        self.write_region_page(self.newest.get(), sparebuffer)
+    }
+
+    /// Reads the entry at the read position into `buffer` and moves the read
+    /// position to the following entry. Returns `EBUSY` if a page is being
+    /// written or read.
+    pub fn read_next(&self, buffer: &'static mut [u8]) -> ReturnCode {
+        if !self.mounted.get() {
+            return ReturnCode::FAIL;
+        }
+        if self.state.get() != State::Idle {
+            return ReturnCode::EBUSY;
+        }
+        self.buffer.replace(buffer);
+        let rval = self.read_region_page(State::Read, self.read_page.get());
+        if rval != ReturnCode::SUCCESS {
+            self.buffer.take();
+        }
+        rval
+    }
+
+    /// Moves the read position back to the oldest entry.
+    pub fn rewind(&self) {
+        self.read_page.set(self.oldest.get());
+        self.read_offset.set(PAGE_HEADER_SIZE);
+    }
+
+    fn page_size(&self) -> usize {
+        self.pagebuffer
+            .map_or(0, |pagebuffer| pagebuffer.as_mut().len())
+    }
+
+    fn next_page(&self, page: usize) -> usize {
+        (page + 1) % self.num_pages
+    }
+
+    /// Reads `page` of the region into the spare page buffer.
+    fn read_region_page(&self, state: State, page: usize) -> ReturnCode {
+        self.sparebuffer
+            .take()
+            .map_or(ReturnCode::EBUSY, |sparebuffer| {
+                self.state.set(state);
+                let rval = self.driver.read_page(self.first_page + page, sparebuffer);
+                if rval != ReturnCode::SUCCESS {
+                    self.state.set(State::Idle);
+                }
+                rval
+            })
+    }
+
+    /// Writes `pagebuffer` to `page` of the region. The buffer becomes the
+    /// spare page buffer once the write completes.
+    fn write_region_page(&self, page: usize, pagebuffer: &'static mut F::Page) -> ReturnCode {
+        self.state.set(State::Write);
+        self.dirty.set(false);
+        let rval = self.driver.write_page(self.first_page + page, pagebuffer);
+        if rval != ReturnCode::SUCCESS {
+            self.state.set(State::Idle);
+            self.dirty.set(true);
+        }
+        rval
+    }
+
+    /// Writes the newest page to flash if it holds entries that are not
+    /// stored yet, and starts the following page in RAM, evicting the oldest
+    /// page if the region is full.
+    fn start_next_page(&self) -> ReturnCode {
+        let sparebuffer = match self.sparebuffer.take() {
+            Some(sparebuffer) => sparebuffer,
+            None => return ReturnCode::EBUSY,
+        };
+        if self.dirty.get() {
+            // Appends continue in the spare buffer while the full page is
+            // written from the other one.
+            let full = match self.pagebuffer.take() {
+                Some(full) => full,
+                None => {
+                    self.sparebuffer.replace(sparebuffer);
+                    return ReturnCode::FAIL;
+                }
+            };
+            self.pagebuffer.replace(sparebuffer);
+            let rval = self.write_region_page(self.newest.get(), full);
+            if rval != ReturnCode::SUCCESS {
+                return rval;
+            }
+        } else {
+            self.sparebuffer.replace(sparebuffer);
+        }
+
+        let next = self.next_page(self.newest.get());
+        if self.used_pages.get() == self.num_pages {
+            let oldest = self.next_page(self.oldest.get());
+            self.oldest.set(oldest);
+            if self.read_page.get() == next {
+                self.read_page.set(oldest);
+                self.read_offset.set(PAGE_HEADER_SIZE);
+            }
+        } else {
+            self.used_pages.set(self.used_pages.get() + 1);
+        }
+        self.newest.set(next);
+        self.append_offset.set(PAGE_HEADER_SIZE);
+
+        let sequence = self.next_sequence.get();
+        self.next_sequence.set(sequence.wrapping_add(1));
+        self.pagebuffer.map(|pagebuffer| {
+            let page = pagebuffer.as_mut();
+            for byte in page.iter_mut() {
+                *byte = 0xff;
+            }
+            for i in 0..PAGE_HEADER_SIZE {
+                page[i] = (sequence >> (8 * i)) as u8;
+            }
+        });
+        ReturnCode::SUCCESS
+    }
+
+    fn read_done(&self, length: usize, result: ReturnCode) {
+        self.buffer.take().map(|buffer| {
+            self.client
+                .map(move |client| client.read_done(buffer, length, result));
+        });
+    }
+
+    /// Copies the record at the read position out of the page that holds
+    /// it. Returns `true` if there are no more records in the page and the
+    /// read must continue on the following page.
+    fn read_record(&self, page: &mut [u8]) -> bool {
+        let page_num = self.read_page.get();
+        let offset = self.read_offset.get();
+        let at_end = page_num == self.newest.get() && offset >= self.append_offset.get();
+        let record = if at_end {
+            None
+        } else {
+            record_at(page, offset)
+        };
+        match record {
+            Some(length) => {
+                let fits = self.buffer.map_or(false, |buffer| {
+                    if length > buffer.len() {
+                        return false;
+                    }
+                    let start = offset + RECORD_HEADER_SIZE;
+                    buffer[..length].copy_from_slice(&page[start..start + length]);
+                    true
+                });
+                if fits {
+                    self.read_offset.set(offset + RECORD_HEADER_SIZE + length);
+                    self.read_done(length, ReturnCode::SUCCESS);
+                } else {
+                    self.read_done(length, ReturnCode::ESIZE);
+                }
+                false
+            }
+            None if page_num == self.newest.get() || self.used_pages.get() == 0 => {
+                self.read_done(0, ReturnCode::SUCCESS);
+                false
+            }
+            None => {
+                // The rest of this page is empty or torn; continue with the
+                // next page.
+                self.read_page.set(self.next_page(page_num));
+                self.read_offset.set(PAGE_HEADER_SIZE);
+                true
+            }
+        }
+    }
+
+    /// Records the sequence number of a page read while mounting, and reads
+    /// the next page, or the newest page once all have been read.
+    fn mount_page(&self, page: usize, sequence: u32) {
+        if sequence != ERASED_SEQUENCE {
+            self.used_pages.set(self.used_pages.get() + 1);
+            if self.mount_oldest.get().map_or(true, |(_, s)| sequence < s) {
+                self.mount_oldest.set(Some((page, sequence)));
+            }
+            if self.mount_newest.get().map_or(true, |(_, s)| sequence > s) {
+                self.mount_newest.set(Some((page, sequence)));
+            }
+        }
+        let rval = if page + 1 < self.num_pages {
+            self.read_region_page(State::Mount(page + 1), page + 1)
+        } else {
+            match (self.mount_oldest.get(), self.mount_newest.get()) {
+                (Some((oldest, _)), Some((newest, sequence))) => {
+                    self.oldest.set(oldest);
+                    self.newest.set(newest);
+                    self.next_sequence.set(sequence.wrapping_add(1));
+                    self.read_region_page(State::MountScan, newest)
+                }
+                _ => {
+                    // The log is empty. The first append starts the first
+                    // page of the region.
+                    self.oldest.set(0);
+                    self.newest.set(self.num_pages - 1);
+                    self.append_offset.set(self.page_size());
+                    self.next_sequence.set(0);
+                    self.mount_done(ReturnCode::SUCCESS)
+                }
+            }
+        };
+        if rval != ReturnCode::SUCCESS {
+            self.mount_done(rval);
+        }
+    }
+
+    fn mount_done(&self, result: ReturnCode) -> ReturnCode {
+        self.state.set(State::Idle);
+        self.mounted.set(result == ReturnCode::SUCCESS);
+        self.rewind();
+        self.client.map(|client| client.mount_done(result));
+        ReturnCode::SUCCESS
+    }
+}
+
+impl<F: hil::flash::Flash> hil::flash::Client<F> for LogStorage<'a, F> {
+    fn read_complete(&self, sparebuffer: &'static mut F::Page, error: hil::flash::Error) {
+        let state = self.state.get();
+        self.state.set(State::Idle);
+        if error != hil::flash::Error::CommandComplete {
+            self.sparebuffer.replace(sparebuffer);
+            match state {
+                State::Mount(_) | State::MountScan => {
+                    self.used_pages.set(0);
+                    self.mount_done(ReturnCode::FAIL);
+                }
+                _ => self.read_done(0, ReturnCode::FAIL),
+            }
+            return;
+        }
+        match state {
+            State::Mount(page) => {
+                let sequence = read_le(sparebuffer.as_mut(), 0, PAGE_HEADER_SIZE);
+                self.sparebuffer.replace(sparebuffer);
+                if page == 0 {
+                    self.used_pages.set(0);
+                }
+                self.mount_page(page, sequence);
+            }
+            State::MountScan => {
+                let mut offset = PAGE_HEADER_SIZE;
+                {
+                    let page = sparebuffer.as_mut();
+                    while let Some(length) = record_at(page, offset) {
+                        offset += RECORD_HEADER_SIZE + length;
+                    }
+                    // Records are only followed by erased flash, so anything
+                    // else is a torn record and the page must not be
+                    // appended to again.
+                    if page[offset..].iter().any(|b| *b != 0xff) {
+                        offset = page.len();
+                    }
+                }
+                self.append_offset.set(offset);
+                // The newest page becomes the one appends are added to.
+                match self.pagebuffer.take() {
+                    Some(pagebuffer) => {
+                        self.pagebuffer.replace(sparebuffer);
+                        self.sparebuffer.replace(pagebuffer);
+                        self.mount_done(ReturnCode::SUCCESS);
+                    }
+                    None => {
+                        self.sparebuffer.replace(sparebuffer);
+                        self.mount_done(ReturnCode::FAIL);
+                    }
+                }
+            }
+            State::Read => {
+                // Records of the newest page may not be in flash yet, so read
+                // them from RAM instead.
+                let read_next_page = if self.read_page.get() == self.newest.get() {
+                    self.sparebuffer.replace(sparebuffer);
+                    self.pagebuffer
+                        .map_or(false, |pagebuffer| self.read_record(pagebuffer.as_mut()))
+                } else {
+                    let read_next_page = self.read_record(sparebuffer.as_mut());
+                    self.sparebuffer.replace(sparebuffer);
+                    read_next_page
+                };
+                if read_next_page {
+                    let rval = self.read_region_page(State::Read, self.read_page.get());
+                    if rval != ReturnCode::SUCCESS {
+                        self.read_done(0, rval);
+                    }
+                }
+            }
+            _ => {
+                self.sparebuffer.replace(sparebuffer);
+            }
+        }
+    }
+
+    fn write_complete(&self, pagebuffer: &'static mut F::Page, error: hil::flash::Error) {
+        self.sparebuffer.replace(pagebuffer);
+        if self.state.get() != State::Write {
+            return;
+        }
+        self.state.set(State::Idle);
+        let result = if error == hil::flash::Error::CommandComplete {
+            ReturnCode::SUCCESS
+        } else {
+            // The entries of the page being appended to may not have reached
+            // flash, so keep them for the next write.
+            self.dirty.set(true);
+            ReturnCode::FAIL
+        };
+        self.client.map(|client| client.write_done(result));
+    }
+
+    fn erase_complete(&self, _error: hil::flash::Error) {}
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::boxed::Box;
+    use self::std::cell::RefCell;
+    use self::std::vec;
+    use self::std::vec::Vec;
+    use super::*;
+
+    const PAGE_SIZE: usize = 64;
+    const FLASH_PAGES: usize = 6;
+    /// The log uses pages 2 to 4 of the flash.
+    const FIRST_PAGE: usize = 2;
+    const NUM_PAGES: usize = 3;
+
+    fn leak<T>(v: T) -> &'static mut T {
+        Box::leak(Box::new(v))
+    }
+
+    struct TestPage([u8; PAGE_SIZE]);
+
+    impl AsMut<[u8]> for TestPage {
+        fn as_mut(&mut self) -> &mut [u8] {
+            &mut self.0
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    enum Op {
+        Read(usize),
+        Write(usize),
+    }
+
+    /// Flash that completes an operation only when `run` is called, as the
+    /// hardware would from a later interrupt.
+    struct TestFlash {
+        pages: RefCell<[[u8; PAGE_SIZE]; FLASH_PAGES]>,
+        pending: Cell<Option<Op>>,
+        buffer: TakeCell<'static, TestPage>,
+        client: OptionalCell<&'static hil::flash::Client<TestFlash>>,
+    }
+
+    impl TestFlash {
+        fn new() -> TestFlash {
+            TestFlash {
+                pages: RefCell::new([[0xff; PAGE_SIZE]; FLASH_PAGES]),
+                pending: Cell::new(None),
+                buffer: TakeCell::empty(),
+                client: OptionalCell::empty(),
+            }
+        }
+
+        fn start(&self, op: Op, buf: &'static mut TestPage) -> ReturnCode {
+            assert!(self.pending.get().is_none(), "flash is busy");
+            self.pending.set(Some(op));
+            self.buffer.replace(buf);
+            ReturnCode::SUCCESS
+        }
+
+        /// Completes operations until the flash is idle.
+        fn run(&self) {
+            while let Some(op) = self.pending.take() {
+                let buf = self.buffer.take().unwrap();
+                match op {
+                    Op::Read(page) => {
+                        buf.0.copy_from_slice(&self.pages.borrow()[page]);
+                        self.client.map(move |client| {
+                            client.read_complete(buf, hil::flash::Error::CommandComplete)
+                        });
+                    }
+                    Op::Write(page) => {
+                        self.pages.borrow_mut()[page].copy_from_slice(&buf.0);
+                        self.client.map(move |client| {
+                            client.write_complete(buf, hil::flash::Error::CommandComplete)
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    impl hil::flash::Flash for TestFlash {
+        type Page = TestPage;
+
+        fn read_page(&self, page_number: usize, buf: &'static mut TestPage) -> ReturnCode {
+            self.start(Op::Read(page_number), buf)
+        }
+
+        fn write_page(&self, page_number: usize, buf: &'static mut TestPage) -> ReturnCode {
+            self.start(Op::Write(page_number), buf)
+        }
+
+        fn erase_page(&self, _page_number: usize) -> ReturnCode {
+            ReturnCode::ENOSUPPORT
+        }
+    }
+
+    struct TestClient {
+        mounted: Cell<Option<ReturnCode>>,
+        writes: Cell<usize>,
+        read: RefCell<Option<(Vec<u8>, ReturnCode)>>,
+    }
+
+    impl LogStorageClient for TestClient {
+        fn mount_done(&self, result: ReturnCode) {
+            self.mounted.set(Some(result));
+        }
+
+        fn write_done(&self, result: ReturnCode) {
+            assert_eq!(result, ReturnCode::SUCCESS);
+            self.writes.set(self.writes.get() + 1);
+        }
+
+        fn read_done(&self, buffer: &'static mut [u8], length: usize, result: ReturnCode) {
+            let entry = if result == ReturnCode::SUCCESS {
+                buffer[..length].to_vec()
+            } else {
+                Vec::new()
+            };
+            *self.read.borrow_mut() = Some((entry, result));
+        }
+    }
+
+    type Log = LogStorage<'static, TestFlash>;
+
+    /// Mounts a log on `flash`, as after a reboot.
+    fn mount(flash: &'static TestFlash) -> (&'static Log, &'static TestClient) {
+        let log = leak(LogStorage::new(
+            flash,
+            leak(TestPage([0; PAGE_SIZE])),
+            leak(TestPage([0; PAGE_SIZE])),
+            FIRST_PAGE,
+            NUM_PAGES,
+        ));
+        let client = leak(TestClient {
+            mounted: Cell::new(None),
+            writes: Cell::new(0),
+            read: RefCell::new(None),
+        });
+        flash.client.set(log);
+        log.set_client(client);
+        assert_eq!(log.mount(), ReturnCode::SUCCESS);
+        flash.run();
+        assert_eq!(client.mounted.get(), Some(ReturnCode::SUCCESS));
+        (log, client)
+    }
+
+    /// Reads the entry at the read position into a buffer of `len` bytes.
+    fn read(
+        log: &Log,
+        flash: &TestFlash,
+        client: &TestClient,
+        len: usize,
+    ) -> (Vec<u8>, ReturnCode) {
+        let buffer = Box::leak(vec![0; len].into_boxed_slice());
+        assert_eq!(log.read_next(buffer), ReturnCode::SUCCESS);
+        flash.run();
+        client.read.borrow_mut().take().unwrap()
+    }
+
+    /// Reads every entry from the read position on.
+    fn read_all(log: &Log, flash: &TestFlash, client: &TestClient) -> Vec<Vec<u8>> {
+        let mut entries = Vec::new();
+        loop {
+            let (entry, result) = read(log, flash, client, PAGE_SIZE);
+            assert_eq!(result, ReturnCode::SUCCESS);
+            if entry.is_empty() {
+                return entries;
+            }
+            entries.push(entry);
+        }
+    }
+
+    /// An 8-byte entry, so that five records fit in a page.
+    fn entry(n: u8) -> Vec<u8> {
+        vec![n; 8]
+    }
+
+    #[test]
+    fn synced_entries_survive_remount() {
+        let flash = leak(TestFlash::new());
+        let (log, client) = mount(flash);
+        assert!(read_all(log, flash, client).is_empty());
+
+        for n in 0..3 {
+            assert_eq!(log.append(&entry(n)), ReturnCode::SUCCESS);
+        }
+        // Entries not yet in flash are read from RAM.
+        assert_eq!(read_all(log, flash, client), [entry(0), entry(1), entry(2)]);
+
+        assert_eq!(log.sync(), ReturnCode::SUCCESS);
+        flash.run();
+        assert_eq!(client.writes.get(), 1);
+        assert_eq!(log.sync(), ReturnCode::EALREADY);
+        assert_eq!(log.append(&entry(3)), ReturnCode::SUCCESS);
+
+        // The unsynced entry is lost, and appends continue after the others.
+        let (log, client) = mount(flash);
+        assert_eq!(read_all(log, flash, client), [entry(0), entry(1), entry(2)]);
+        assert_eq!(log.append(&entry(4)), ReturnCode::SUCCESS);
+        log.rewind();
+        assert_eq!(
+            read_all(log, flash, client),
+            [entry(0), entry(1), entry(2), entry(4)]
+        );
+    }
+
+    #[test]
+    fn full_log_keeps_newest_pages() {
+        let flash = leak(TestFlash::new());
+        let (log, client) = mount(flash);
+
+        for n in 0..30 {
+            assert_eq!(log.append(&entry(n)), ReturnCode::SUCCESS);
+            flash.run();
+        }
+        assert_eq!(log.sync(), ReturnCode::SUCCESS);
+        flash.run();
+
+        let newest: Vec<Vec<u8>> = (15..30).map(entry).collect();
+        assert_eq!(read_all(log, flash, client), newest);
+        let (log, client) = mount(flash);
+        assert_eq!(read_all(log, flash, client), newest);
+    }
+
+    #[test]
+    fn torn_record_ends_its_page() {
+        let flash = leak(TestFlash::new());
+        let (log, _) = mount(flash);
+        for n in 0..3 {
+            log.append(&entry(n));
+        }
+        log.sync();
+        flash.run();
+
+        // Corrupt the second entry, as if power was lost while writing it.
+        let second = PAGE_HEADER_SIZE + 2 * RECORD_HEADER_SIZE + 8;
+        flash.pages.borrow_mut()[FIRST_PAGE][second] ^= 1;
+
+        // Appends after the torn record start a new page.
+        let (log, client) = mount(flash);
+        assert_eq!(log.append(&entry(3)), ReturnCode::SUCCESS);
+        flash.run();
+        assert_eq!(read_all(log, flash, client), [entry(0), entry(3)]);
+    }
+
+    #[test]
+    fn entries_must_fit() {
+        let flash = leak(TestFlash::new());
+        let (log, client) = mount(flash);
+
+        let largest = PAGE_SIZE - PAGE_HEADER_SIZE - RECORD_HEADER_SIZE;
+        assert_eq!(log.append(&vec![1; largest + 1]), ReturnCode::ESIZE);
+        assert_eq!(log.append(&[]), ReturnCode::EINVAL);
+        assert_eq!(log.append(&vec![1; largest]), ReturnCode::SUCCESS);
+
+        // An entry that does not fit in the read buffer can be read again
+        // with a larger one.
+        assert_eq!(read(log, flash, client, 8), (Vec::new(), ReturnCode::ESIZE));
+        assert_eq!(read_all(log, flash, client), [vec![1; largest]]);
+    }
+}
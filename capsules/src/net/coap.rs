@@ -0,0 +1,517 @@
+//! This file contains a helper for CoAP block-wise transfers (RFC 7959) over
+//! UDP. It lets a capsule send a payload that is larger than a single UDP
+//! datagram as a sequence of CoAP messages, each carrying one block of the
+//! payload and a Block1 or Block2 option, and reassembles such a sequence of
+//! incoming messages into the original payload.
+//!
+//! Only the framing of the blocks is implemented. Blocks are sent as
+//! non-confirmable messages, one after another as each send completes; the
+//! request/response exchange and retransmissions of RFC 7252 are left to the
+//! capsule using this helper. This operates at the application layer, and is
+//! independent of 6LoWPAN fragmentation, which is still used for each
+//! datagram if needed.
+//!
+//! Usage
+//! -----
+//!
+//! ```
+//! let coap_send = static_init!(
+//!     capsules::net::coap::BlockwiseSender<'static>,
+//!     capsules::net::coap::BlockwiseSender::new(udp_send, &mut COAP_MSG_BUF)
+//! );
+//! udp_send.set_client(coap_send);
+//! coap_send.set_client(my_capsule);
+//!
+//! let coap_recv = static_init!(
+//!     capsules::net::coap::BlockwiseReceiver<'static>,
+//!     capsules::net::coap::BlockwiseReceiver::new(5683, &mut COAP_RECV_BUF)
+//! );
+//! udp_recv.set_client(coap_recv);
+//! coap_recv.set_client(my_capsule);
+//! ```
+
+use core::cell::Cell;
+use core::cmp::min;
+use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::ReturnCode;
+use net::ipv6::ip_utils::IPAddr;
+use net::stream::SResult;
+use net::stream::{decode_u16, decode_u8, encode_bytes, encode_u16, encode_u8};
+use net::udp::udp_recv::UDPRecvClient;
+use net::udp::udp_send::{UDPSendClient, UDPSender};
+
+/// Size of the fixed CoAP message header.
+pub const COAP_HDR_LEN: usize = 4;
+
+/// Largest message overhead added by `BlockwiseSender`: the header, a block
+/// option of up to 4 bytes, and the payload marker.
+pub const COAP_BLOCK_OVERHEAD: usize = COAP_HDR_LEN + 4 + 1;
+
+const COAP_VERSION: u8 = 1;
+const COAP_TYPE_NON: u8 = 1;
+const PAYLOAD_MARKER: u8 = 0xff;
+
+// Block numbers are at most 20 bits long
+const MAX_BLOCK_NUM: u32 = (1 << 20) - 1;
+
+/// The CoAP option used to carry the block of a transfer. Block1 is used for
+/// payloads of requests, and Block2 for payloads of responses.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BlockOption {
+    Block1 = 27,
+    Block2 = 23,
+}
+
+/// The value of a Block1 or Block2 option.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Block {
+    /// Number of this block within the payload
+    pub num: u32,
+    /// Whether more blocks follow this one
+    pub more: bool,
+    /// Size exponent, so that the block size is `2^(szx + 4)`
+    pub szx: u8,
+}
+
+impl Block {
+    /// Size of each block of the transfer in bytes, from 16 to 1024.
+    pub fn size(&self) -> usize {
+        1 << (self.szx + 4)
+    }
+
+    fn value(&self) -> u32 {
+        (self.num << 4) | ((self.more as u32) << 3) | (self.szx as u32)
+    }
+
+    fn from_value(value: u32) -> Option<Block> {
+        let szx = (value & 0x7) as u8;
+        // A size exponent of 7 is reserved
+        if szx == 7 {
+            return None;
+        }
+        Some(Block {
+            num: value >> 4,
+            more: value & 0x8 != 0,
+            szx: szx,
+        })
+    }
+}
+
+/// Encodes a CoAP message carrying `block` and `payload` into `buf`, and
+/// returns the length of the message.
+fn encode_block_message(
+    buf: &mut [u8],
+    code: u8,
+    message_id: u16,
+    option: BlockOption,
+    block: Block,
+    payload: &[u8],
+) -> SResult<usize> {
+    // No token is used, so the token length is 0
+    let off = enc_consume!(buf; encode_u8, (COAP_VERSION << 6) | (COAP_TYPE_NON << 4));
+    let off = enc_consume!(buf, off; encode_u8, code);
+    let off = enc_consume!(buf, off; encode_u16, message_id);
+
+    // The block option is the only option, so its delta is its number. Both
+    // option numbers need the one byte delta extension.
+    let value = block.value();
+    let value_len = if value == 0 {
+        0
+    } else if value < (1 << 8) {
+        1
+    } else if value < (1 << 16) {
+        2
+    } else {
+        3
+    };
+    let off = enc_consume!(buf, off; encode_u8, (13 << 4) | value_len as u8);
+    let off = enc_consume!(buf, off; encode_u8, option as u8 - 13);
+    let mut off = off;
+    for i in (0..value_len).rev() {
+        off = enc_consume!(buf, off; encode_u8, (value >> (8 * i)) as u8);
+    }
+
+    if payload.len() > 0 {
+        let off = enc_consume!(buf, off; encode_u8, PAYLOAD_MARKER);
+        let off = enc_consume!(buf, off; encode_bytes, payload);
+        stream_done!(off, off);
+    }
+    stream_done!(off, off);
+}
+
+/// Decodes the extended option delta or length for the 4-bit value `nibble`.
+fn decode_option_ext(buf: &[u8], nibble: u8) -> SResult<u32> {
+    match nibble {
+        13 => {
+            let (off, ext) = dec_try!(buf; decode_u8);
+            stream_done!(off, ext as u32 + 13);
+        }
+        14 => {
+            let (off, ext) = dec_try!(buf; decode_u16);
+            stream_done!(off, ext as u32 + 269);
+        }
+        15 => stream_err!(),
+        _ => stream_done!(0, nibble as u32),
+    }
+}
+
+/// Decodes a CoAP message, returning its code, its block option if it has
+/// one, and the offset of its payload. Options other than Block1 and Block2
+/// are skipped.
+fn decode_block_message(buf: &[u8]) -> SResult<(u8, Option<(BlockOption, Block)>)> {
+    let (off, ver_type_tkl) = dec_try!(buf; decode_u8);
+    stream_cond!(ver_type_tkl >> 6 == COAP_VERSION);
+    let token_len = (ver_type_tkl & 0xf) as usize;
+    stream_cond!(token_len <= 8);
+    let (off, code) = dec_try!(buf, off; decode_u8);
+    let (off, _message_id) = dec_try!(buf, off; decode_u16);
+    stream_len_cond!(buf, off + token_len);
+    let mut off = off + token_len;
+
+    let mut option_num: u32 = 0;
+    let mut block = None;
+    while off < buf.len() {
+        if buf[off] == PAYLOAD_MARKER {
+            // The payload marker must be followed by a non-empty payload
+            stream_cond!(off + 1 < buf.len());
+            stream_done!(off + 1, (code, block));
+        }
+        let delta_nibble = buf[off] >> 4;
+        let len_nibble = buf[off] & 0xf;
+        off += 1;
+        let (ext_len, delta) = dec_try!(buf, off; decode_option_ext, delta_nibble);
+        off = ext_len;
+        let (ext_len, len) = dec_try!(buf, off; decode_option_ext, len_nibble);
+        off = ext_len;
+        let len = len as usize;
+        stream_len_cond!(buf, off + len);
+
+        option_num += delta;
+        let option = if option_num == BlockOption::Block1 as u32 {
+            Some(BlockOption::Block1)
+        } else if option_num == BlockOption::Block2 as u32 {
+            Some(BlockOption::Block2)
+        } else {
+            None
+        };
+        if let Some(option) = option {
+            stream_cond!(len <= 3);
+            let value = buf[off..off + len]
+                .iter()
+                .fold(0, |value, byte| (value << 8) | *byte as u32);
+            let value = stream_from_option!(Block::from_value(value));
+            block = Some((option, value));
+        }
+        off += len;
+    }
+    stream_done!(off, (code, block));
+}
+
+/// Implemented by the capsule using a `BlockwiseSender` to learn when a
+/// block-wise transfer has completed.
+pub trait BlockwiseSendClient {
+    /// Called once all blocks of the payload have been sent, or sending one
+    /// of them failed. The payload buffer is returned.
+    fn send_blockwise_done(&self, payload: &'static mut [u8], result: ReturnCode);
+}
+
+/// Sends a payload as a sequence of CoAP messages carrying one block each.
+/// It must be set as the client of the `UDPSender` it uses.
+pub struct BlockwiseSender<'a> {
+    udp_sender: &'a UDPSender<'a>,
+    msg_buf: TakeCell<'static, [u8]>,
+    client: OptionalCell<&'a BlockwiseSendClient>,
+
+    // State of the transfer in progress
+    payload: TakeCell<'static, [u8]>,
+    payload_len: Cell<usize>,
+    option: Cell<BlockOption>,
+    code: Cell<u8>,
+    szx: Cell<u8>,
+    next_num: Cell<u32>,
+    message_id: Cell<u16>,
+    dest: Cell<IPAddr>,
+    dst_port: Cell<u16>,
+    src_port: Cell<u16>,
+}
+
+impl BlockwiseSender<'a> {
+    /// Creates a new `BlockwiseSender`. Each message is encoded in `msg_buf`
+    /// before it is sent, so `msg_buf` must be at least
+    /// `COAP_BLOCK_OVERHEAD` bytes larger than the largest block size used.
+    pub fn new(udp_sender: &'a UDPSender<'a>, msg_buf: &'static mut [u8]) -> BlockwiseSender<'a> {
+        BlockwiseSender {
+            udp_sender: udp_sender,
+            msg_buf: TakeCell::new(msg_buf),
+            client: OptionalCell::empty(),
+            payload: TakeCell::empty(),
+            payload_len: Cell::new(0),
+            option: Cell::new(BlockOption::Block1),
+            code: Cell::new(0),
+            szx: Cell::new(0),
+            next_num: Cell::new(0),
+            message_id: Cell::new(0),
+            dest: Cell::new(IPAddr([0; 16])),
+            dst_port: Cell::new(0),
+            src_port: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a BlockwiseSendClient) {
+        self.client.set(client);
+    }
+
+    /// Sends the first `len` bytes of `payload` to `dest` in blocks of
+    /// `2^(szx + 4)` bytes, each in a message with the given CoAP `code` and
+    /// a `option` block option. The client is called back once the last
+    /// block has been sent.
+    ///
+    /// Returns `EBUSY` if a transfer is already in progress, `EINVAL` if
+    /// `szx` or `len` is invalid, `ESIZE` if a block does not fit in the
+    /// message buffer, or the error of sending the first block. On error,
+    /// the payload buffer is returned.
+    pub fn send_blockwise(
+        &self,
+        dest: IPAddr,
+        dst_port: u16,
+        src_port: u16,
+        option: BlockOption,
+        code: u8,
+        szx: u8,
+        payload: &'static mut [u8],
+        len: usize,
+    ) -> (ReturnCode, Option<&'static mut [u8]>) {
+        if self.payload.is_some() {
+            return (ReturnCode::EBUSY, Some(payload));
+        }
+        if szx > 6 || len > payload.len() {
+            return (ReturnCode::EINVAL, Some(payload));
+        }
+        let block_size = 1 << (szx + 4);
+        if len / block_size > MAX_BLOCK_NUM as usize {
+            return (ReturnCode::EINVAL, Some(payload));
+        }
+        let fits = self
+            .msg_buf
+            .map_or(false, |buf| buf.len() >= block_size + COAP_BLOCK_OVERHEAD);
+        if !fits {
+            return (ReturnCode::ESIZE, Some(payload));
+        }
+
+        self.dest.set(dest);
+        self.dst_port.set(dst_port);
+        self.src_port.set(src_port);
+        self.option.set(option);
+        self.code.set(code);
+        self.szx.set(szx);
+        self.next_num.set(0);
+        self.payload_len.set(len);
+        self.payload.replace(payload);
+
+        let result = self.send_next_block();
+        if result != ReturnCode::SUCCESS {
+            return (result, self.payload.take());
+        }
+        (ReturnCode::SUCCESS, None)
+    }
+
+    // Returns whether more blocks follow the one numbered `num`
+    fn has_more(&self, num: u32) -> bool {
+        let block_size = 1 << (self.szx.get() + 4);
+        (num as usize + 1) * block_size < self.payload_len.get()
+    }
+
+    fn send_next_block(&self) -> ReturnCode {
+        let num = self.next_num.get();
+        let block = Block {
+            num: num,
+            more: self.has_more(num),
+            szx: self.szx.get(),
+        };
+        let start = num as usize * block.size();
+        let end = min(start + block.size(), self.payload_len.get());
+        let message_id = self.message_id.get().wrapping_add(1);
+        self.message_id.set(message_id);
+
+        self.payload
+            .map_or(ReturnCode::FAIL, |payload| {
+                self.msg_buf.map_or(ReturnCode::EBUSY, |buf| {
+                    let encoded = encode_block_message(
+                        buf,
+                        self.code.get(),
+                        message_id,
+                        self.option.get(),
+                        block,
+                        &payload[start..end],
+                    );
+                    match encoded.done() {
+                        Some((_, msg_len)) => self.udp_sender.send_to(
+                            self.dest.get(),
+                            self.dst_port.get(),
+                            self.src_port.get(),
+                            &buf[..msg_len],
+                        ),
+                        None => ReturnCode::ESIZE,
+                    }
+                })
+            })
+    }
+
+    fn finish(&self, result: ReturnCode) {
+        self.payload.take().map(|payload| {
+            self.client
+                .map(move |client| client.send_blockwise_done(payload, result));
+        });
+    }
+}
+
+impl UDPSendClient for BlockwiseSender<'a> {
+    fn send_done(&self, result: ReturnCode) {
+        if self.payload.is_none() {
+            return;
+        }
+        let num = self.next_num.get();
+        if result != ReturnCode::SUCCESS || !self.has_more(num) {
+            self.finish(result);
+            return;
+        }
+        self.next_num.set(num + 1);
+        let result = self.send_next_block();
+        if result != ReturnCode::SUCCESS {
+            self.finish(result);
+        }
+    }
+}
+
+/// Implemented by the capsule using a `BlockwiseReceiver` to receive
+/// reassembled payloads.
+pub trait BlockwiseRecvClient {
+    /// Called when the last block of a transfer has been received, with the
+    /// reassembled `payload` and the CoAP `code` of the last message. A
+    /// message without a block option is passed on as a transfer of a single
+    /// block. `result` is `ESIZE`, with an empty payload, if a transfer did
+    /// not fit in the reassembly buffer.
+    fn receive_blockwise(
+        &self,
+        src_addr: IPAddr,
+        src_port: u16,
+        code: u8,
+        payload: &[u8],
+        result: ReturnCode,
+    );
+}
+
+/// Reassembles the blocks of CoAP messages received on a port. It must be
+/// set as the client of a `UDPReceiver`. One transfer is reassembled at a
+/// time: a block 0 starts a new transfer, and blocks that are out of order
+/// or from another source are dropped.
+pub struct BlockwiseReceiver<'a> {
+    port: u16,
+    buffer: TakeCell<'static, [u8]>,
+    client: OptionalCell<&'a BlockwiseRecvClient>,
+
+    // Source, next expected block number, and length so far of the transfer
+    // being reassembled, if any
+    src: Cell<Option<(IPAddr, u16)>>,
+    next_num: Cell<u32>,
+    len: Cell<usize>,
+}
+
+impl BlockwiseReceiver<'a> {
+    /// Creates a new `BlockwiseReceiver` for messages to `port`, reassembling
+    /// payloads of up to `buffer.len()` bytes.
+    pub fn new(port: u16, buffer: &'static mut [u8]) -> BlockwiseReceiver<'a> {
+        BlockwiseReceiver {
+            port: port,
+            buffer: TakeCell::new(buffer),
+            client: OptionalCell::empty(),
+            src: Cell::new(None),
+            next_num: Cell::new(0),
+            len: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a BlockwiseRecvClient) {
+        self.client.set(client);
+    }
+
+    fn is_current_source(&self, src_addr: IPAddr, src_port: u16) -> bool {
+        self.src.get().map_or(false, |(addr, port)| {
+            addr.0 == src_addr.0 && port == src_port
+        })
+    }
+
+    fn deliver(&self, src_addr: IPAddr, src_port: u16, code: u8, len: usize, result: ReturnCode) {
+        self.src.set(None);
+        self.buffer.map(|buffer| {
+            self.client.map(|client| {
+                client.receive_blockwise(src_addr, src_port, code, &buffer[..len], result)
+            });
+        });
+    }
+}
+
+impl UDPRecvClient for BlockwiseReceiver<'a> {
+    fn receive(
+        &self,
+        src_addr: IPAddr,
+        _dst_addr: IPAddr,
+        src_port: u16,
+        dst_port: u16,
+        payload: &[u8],
+    ) {
+        if dst_port != self.port {
+            return;
+        }
+        let (offset, (code, block)) = match decode_block_message(payload).done() {
+            Some(decoded) => decoded,
+            None => return,
+        };
+        let data = &payload[offset..];
+
+        let block = match block {
+            Some((_, block)) => block,
+            None => Block {
+                num: 0,
+                more: false,
+                szx: 6,
+            },
+        };
+
+        if block.num == 0 {
+            self.src.set(Some((src_addr, src_port)));
+            self.next_num.set(0);
+            self.len.set(0);
+        } else if !self.is_current_source(src_addr, src_port) || block.num != self.next_num.get()
+        {
+            return;
+        }
+        // Every block but the last carries exactly the block size
+        if block.more && data.len() != block.size() {
+            self.src.set(None);
+            return;
+        }
+
+        let start = block.num as usize * block.size();
+        let end = start + data.len();
+        let fits = self.buffer.map_or(false, |buffer| {
+            if end <= buffer.len() {
+                buffer[start..end].copy_from_slice(data);
+                true
+            } else {
+                false
+            }
+        });
+        if !fits {
+            self.deliver(src_addr, src_port, code, 0, ReturnCode::ESIZE);
+            return;
+        }
+
+        self.len.set(end);
+        if block.more {
+            self.next_num.set(block.num + 1);
+        } else {
+            self.deliver(src_addr, src_port, code, end, ReturnCode::SUCCESS);
+        }
+    }
+}
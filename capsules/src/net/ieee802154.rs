@@ -39,7 +39,7 @@ impl MacAddress {
 
 pub type PanID = u16;
 
-mod frame_control {
+pub mod frame_control {
     pub const FRAME_TYPE_MASK: u16 = 0b111;
     pub const SECURITY_ENABLED: u16 = 1 << 3;
     pub const FRAME_PENDING: u16 = 1 << 4;
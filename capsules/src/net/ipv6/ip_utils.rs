@@ -111,73 +111,205 @@ impl IPAddr {
     pub fn is_multicast(&self) -> bool {
         self.0[0] == 0xff
     }
-}
 
-pub fn compute_udp_checksum(
-    ip6_header: &IP6Header,
-    udp_header: &UDPHeader,
-    udp_length: u16,
-    payload: &[u8],
-) -> u16 {
-    //This checksum is calculated according to some of the recommendations found in RFC 1071.
+    /// Returns the scope of the address (RFC 4291, 2.7): 0x2 for link-local
+    /// and loopback addresses, 0xe for global addresses, or the scope field of
+    /// a multicast address.
+    pub fn scope(&self) -> u8 {
+        if self.is_multicast() {
+            self.0[1] & 0x0f
+        } else if self.is_unicast_link_local() || self.is_loopback() {
+            0x2
+        } else {
+            0xe
+        }
+    }
 
-    let src_port = udp_header.get_src_port();
-    let dst_port = udp_header.get_dst_port();
-    let mut sum: u32 = 0;
-    {
-        //First, iterate through src/dst address and add them to the sum
-        let mut i = 0;
-        while i <= 14 {
-            let msb_src: u16 = ((ip6_header.src_addr.0[i]) as u16) << 8;
-            let lsb_src: u16 = ip6_header.src_addr.0[i + 1] as u16;
-            let temp_src: u16 = msb_src + lsb_src;
-            sum += temp_src as u32;
-
-            let msb_dst: u16 = ((ip6_header.dst_addr.0[i]) as u16) << 8;
-            let lsb_dst: u16 = ip6_header.dst_addr.0[i + 1] as u16;
-            let temp_dst: u16 = msb_dst + lsb_dst;
-            sum += temp_dst as u32;
-
-            i += 2; //Iterate two bytes at a time bc 16 bit checksum
+    pub fn is_loopback(&self) -> bool {
+        self.0[..15].iter().all(|&b| b == 0) && self.0[15] == 1
+    }
+
+    /// Returns the number of leading bits this address has in common with
+    /// `other`.
+    pub fn common_prefix_len(&self, other: &IPAddr) -> usize {
+        for (i, (a, b)) in self.0.iter().zip(other.0.iter()).enumerate() {
+            if a != b {
+                return i * 8 + (a ^ b).leading_zeros() as usize;
+            }
         }
+        128
+    }
+
+    /// Returns the solicited-node multicast address (RFC 4291, 2.7.1) of this
+    /// unicast address, ff02::1:ffXX:XXXX, formed from its low 24 bits.
+    pub fn solicited_node(&self) -> IPAddr {
+        let mut addr = IPAddr([0; 16]);
+        addr.0[0] = 0xff;
+        addr.0[1] = 0x02;
+        addr.0[11] = 0x01;
+        addr.0[12] = 0xff;
+        addr.0[13..16].copy_from_slice(&self.0[13..16]);
+        addr
     }
-    sum += udp_header.get_len() as u32;
-    //Finally, add UDP next header
-    sum += 17; //was "padded next header"
-
-    //return sum as u16;
-    //Next, add the UDP header elements to the sum
-    sum += src_port as u32;
-    sum += dst_port as u32;
-    sum += udp_header.get_len() as u32;
-    sum += udp_header.get_cksum() as u32;
-    //Now just need to iterate thru data and add it to the sum
-    {
-        let mut i: usize = 0;
-        while i < ((udp_length - 8) as usize) {
-            let msb_dat: u16 = ((payload[i]) as u16) << 8;
-            let mut lsb_dat: u16 = 0;
-            if i + 1 < udp_length as usize - 8 {
-                lsb_dat = payload[i + 1] as u16;
+}
+
+/// Chooses the source address for a packet sent to `dst` from the addresses
+/// this node holds, following the rules of RFC 6724, 5 that apply to a node
+/// without address lifetimes, labels or temporary addresses: prefer the
+/// destination itself, then an address of the smallest scope that still
+/// reaches the destination, then the longest matching prefix. Returns `None`
+/// if `candidates` is empty.
+pub fn select_source_address(candidates: &[IPAddr], dst: &IPAddr) -> Option<IPAddr> {
+    let dst_scope = dst.scope();
+    let mut best: Option<IPAddr> = None;
+    for candidate in candidates.iter().filter(|addr| !addr.is_unspecified()) {
+        let better = match best {
+            None => true,
+            Some(ref best) => {
+                // Rule 1: prefer the same address
+                if candidate == dst || best == dst {
+                    candidate == dst
+                } else if candidate.scope() != best.scope() {
+                    // Rule 2: prefer the smallest scope that is at least the
+                    // scope of the destination
+                    if best.scope() < candidate.scope() {
+                        best.scope() < dst_scope
+                    } else {
+                        candidate.scope() >= dst_scope
+                    }
+                } else {
+                    // Rule 8: prefer the longest matching prefix
+                    candidate.common_prefix_len(dst) > best.common_prefix_len(dst)
+                }
             }
-            let temp_dat: u16 = msb_dat + lsb_dat;
-            sum += temp_dat as u32;
+        };
+        if better {
+            best = Some(*candidate);
+        }
+    }
+    best
+}
+
+/// An incremental Internet checksum (RFC 1071). Data can be added in pieces
+/// of any length, including while it is being copied, and the result is the
+/// same as summing it all at once.
+#[derive(Copy, Clone, Default)]
+pub struct Checksum {
+    sum: u32,
+    // The first byte of a 16-bit word whose second byte has not been added yet
+    odd_byte: Option<u8>,
+}
 
-            i += 2; //Iterate two bytes at a time bc 16 bit checksum
+impl Checksum {
+    pub fn new() -> Checksum {
+        Checksum::default()
+    }
+
+    pub fn add_u16(&mut self, value: u16) {
+        self.sum += value as u32;
+        // Fold before the sum can overflow
+        if self.sum & 0x8000_0000 != 0 {
+            self.sum = (self.sum >> 16) + (self.sum & 0xffff);
         }
     }
-    //now all 16 bit addition has occurred
 
-    while sum > 65535 {
-        let sum_high: u32 = sum >> 16; //upper 16 bits of sum
-        let sum_low: u32 = sum & 65535; //lower 16 bits of sum
-        sum = sum_high + sum_low;
+    pub fn add_bytes(&mut self, bytes: &[u8]) {
+        for b in bytes.iter() {
+            self.add_byte(*b);
+        }
     }
 
-    //Finally, flip all bits
-    sum = !sum;
-    sum = sum & 65535; //Remove upper 16 bits (which should be FFFF after flip)
-    (sum as u16) //Return result as u16 in host byte order */
+    /// Copy `src` into the start of `dst` and add it to the checksum in the
+    /// same pass. Panics if `dst` is shorter than `src`.
+    pub fn copy_and_add(&mut self, dst: &mut [u8], src: &[u8]) {
+        for (d, s) in dst[..src.len()].iter_mut().zip(src.iter()) {
+            *d = *s;
+            self.add_byte(*s);
+        }
+    }
+
+    /// Add the IPv6 pseudo-header (RFC 8200, 8.1) for an upper-layer packet
+    /// of `len` bytes with next header `next_header`.
+    pub fn add_ipv6_pseudo_header(&mut self, ip6_header: &IP6Header, len: u32, next_header: u8) {
+        self.add_bytes(&ip6_header.src_addr.0);
+        self.add_bytes(&ip6_header.dst_addr.0);
+        self.add_u16((len >> 16) as u16);
+        self.add_u16(len as u16);
+        self.add_u16(next_header as u16);
+    }
+
+    /// Add the data summed by `other`, which follows the data added so far.
+    /// The data added so far must be an even number of bytes long.
+    pub fn add_checksum(&mut self, other: &Checksum) {
+        let sum = other.folded_sum();
+        self.add_u16(sum);
+    }
+
+    /// The one's complement of the sum, in host byte order.
+    pub fn finish(&self) -> u16 {
+        !self.folded_sum()
+    }
+
+    fn add_byte(&mut self, byte: u8) {
+        match self.odd_byte.take() {
+            Some(msb) => self.add_u16(((msb as u16) << 8) | byte as u16),
+            None => self.odd_byte = Some(byte),
+        }
+    }
+
+    // The sum folded to 16 bits, with an unpaired final byte padded with zero
+    fn folded_sum(&self) -> u16 {
+        let mut sum = self.sum;
+        if let Some(msb) = self.odd_byte {
+            sum += (msb as u32) << 8;
+        }
+        while sum > 0xffff {
+            sum = (sum >> 16) + (sum & 0xffff);
+        }
+        sum as u16
+    }
+}
+
+/// Computes the UDP checksum of a datagram whose payload has already been
+/// summed into `payload_sum`, e.g. while it was copied into a packet buffer.
+/// The checksum field of `udp_header` is included, so the result is 0 for a
+/// valid received datagram. The result is in host byte order.
+pub fn compute_udp_checksum_from_sum(
+    ip6_header: &IP6Header,
+    udp_header: &UDPHeader,
+    payload_sum: &Checksum,
+) -> u16 {
+    let mut checksum = Checksum::new();
+    checksum.add_ipv6_pseudo_header(ip6_header, udp_header.get_len() as u32, ip6_nh::UDP);
+    checksum.add_u16(udp_header.get_src_port());
+    checksum.add_u16(udp_header.get_dst_port());
+    checksum.add_u16(udp_header.get_len());
+    checksum.add_u16(udp_header.get_cksum());
+    checksum.add_checksum(payload_sum);
+    checksum.finish()
+}
+
+/// Computes the UDP checksum over the first `udp_length - 8` bytes of
+/// `payload`. See `compute_udp_checksum_from_sum`.
+pub fn compute_udp_checksum(
+    ip6_header: &IP6Header,
+    udp_header: &UDPHeader,
+    udp_length: u16,
+    payload: &[u8],
+) -> u16 {
+    let mut payload_sum = Checksum::new();
+    payload_sum.add_bytes(&payload[..(udp_length - 8) as usize]);
+    compute_udp_checksum_from_sum(ip6_header, udp_header, &payload_sum)
+}
+
+/// Computes UDP checksums. The IPv6 layer uses the software implementation by
+/// default; a board can substitute an implementation backed by a hardware
+/// checksum engine.
+pub trait UdpChecksum {
+    /// Returns the checksum of the datagram with header `udp_header` and
+    /// payload `payload`, as `compute_udp_checksum` does.
+    fn udp_checksum(&self, ip6_header: &IP6Header, udp_header: &UDPHeader, payload: &[u8])
+        -> u16;
 }
 
 pub fn compute_icmp_checksum(
@@ -197,7 +329,9 @@ pub fn compute_icmp_checksum(
 
     // add options
     match icmp_header.get_options() {
-        ICMP6HeaderOptions::Type1 { unused } | ICMP6HeaderOptions::Type3 { unused } => {
+        ICMP6HeaderOptions::Type1 { unused }
+        | ICMP6HeaderOptions::Type3 { unused }
+        | ICMP6HeaderOptions::Type133 { unused } => {
             sum += unused >> 16; // upper 16 bits
             sum += unused & 0xffff; // lower 16 bits
         }
@@ -205,6 +339,14 @@ pub fn compute_icmp_checksum(
             sum += id as u32;
             sum += seqno as u32;
         }
+        ICMP6HeaderOptions::Type134 {
+            cur_hop_limit,
+            flags,
+            router_lifetime,
+        } => {
+            sum += ((cur_hop_limit as u32) << 8) + flags as u32;
+            sum += router_lifetime as u32;
+        }
     }
 
     // add icmp payload
@@ -64,17 +64,24 @@
 // a major problem in general, it makes handling encapsulated IPv6 packets
 // (as required by 6LoWPAN) difficult.
 
+use core::cmp::{max, min};
 use kernel::ReturnCode;
 use net::icmpv6::icmpv6::ICMP6Header;
-use net::ipv6::ip_utils::{compute_icmp_checksum, compute_udp_checksum, ip6_nh, IPAddr};
+use net::ipv6::ip_utils::{
+    compute_icmp_checksum, compute_udp_checksum, compute_udp_checksum_from_sum, ip6_nh, Checksum,
+    IPAddr, UdpChecksum,
+};
 use net::stream::SResult;
 use net::stream::{decode_bytes, decode_u16, decode_u8};
 use net::stream::{encode_bytes, encode_u16, encode_u8};
 use net::tcp::TCPHeader;
 use net::udp::udp::UDPHeader;
 
+pub const IP6_HDR_LEN: usize = 40;
 pub const UDP_HDR_LEN: usize = 8;
 pub const ICMP_HDR_LEN: usize = 8;
+/// Hop limit of packets whose sender has not chosen one.
+pub const DEFAULT_HOP_LIMIT: u8 = 255;
 
 /// This is the struct definition for an IPv6 header. It contains (in order)
 /// the same fields as a normal IPv6 header.
@@ -92,12 +99,11 @@ pub struct IP6Header {
 impl Default for IP6Header {
     fn default() -> IP6Header {
         let version = 0x60;
-        let hop_limit = 255;
         IP6Header {
             version_class_flow: [version, 0, 0, 0],
             payload_len: 0,
             next_header: ip6_nh::NO_NEXT,
-            hop_limit: hop_limit,
+            hop_limit: DEFAULT_HOP_LIMIT,
             src_addr: IPAddr::new(),
             dst_addr: IPAddr::new(),
         }
@@ -348,20 +354,39 @@ impl IPPayload<'a> {
             // TODO: Error
         }
         self.payload[..payload.len()].copy_from_slice(&payload);
+        self.set_header(transport_header, payload.len())
+    }
+
+    /// Same as `set_payload`, but also sums the payload into `payload_sum`
+    /// while copying it, so that the transport checksum can be computed
+    /// without a second pass over the payload.
+    pub fn set_payload_summed(
+        &mut self,
+        transport_header: TransportHeader,
+        payload: &[u8],
+        payload_sum: &mut Checksum,
+    ) -> (u8, u16) {
+        payload_sum.copy_and_add(self.payload, payload);
+        self.set_header(transport_header, payload.len())
+    }
+
+    // Sets the transport header, filling in its length for a payload of
+    // `payload_len` bytes
+    fn set_header(&mut self, transport_header: TransportHeader, payload_len: usize) -> (u8, u16) {
         match transport_header {
             TransportHeader::UDP(mut udp_header) => {
-                let length = (payload.len() + udp_header.get_hdr_size()) as u16;
+                let length = (payload_len + udp_header.get_hdr_size()) as u16;
                 udp_header.set_len(length);
                 self.header = transport_header;
                 (ip6_nh::UDP, length)
             }
             TransportHeader::ICMP(mut icmp_header) => {
-                let length = (payload.len() + icmp_header.get_hdr_size()) as u16;
+                let length = (payload_len + icmp_header.get_hdr_size()) as u16;
                 icmp_header.set_len(length);
                 self.header = transport_header;
                 (ip6_nh::ICMP, length)
             }
-            _ => (ip6_nh::NO_NEXT, payload.len() as u16),
+            _ => (ip6_nh::NO_NEXT, payload_len as u16),
         }
     }
 
@@ -451,6 +476,23 @@ impl IP6Packet<'a> {
         40 + transport_hdr_size
     }
 
+    /// Same as `set_transport_checksum`, but computes UDP checksums with
+    /// `engine`, e.g. a hardware checksum engine.
+    pub fn set_transport_checksum_with(&mut self, engine: &UdpChecksum) {
+        match self.payload.header {
+            TransportHeader::UDP(ref mut udp_header) => {
+                let payload_len = udp_header.get_len() as usize - udp_header.get_hdr_size();
+                let cksum = engine.udp_checksum(
+                    &self.header,
+                    &udp_header,
+                    &self.payload.payload[..payload_len],
+                );
+                udp_header.set_cksum(cksum);
+            }
+            _ => self.set_transport_checksum(),
+        }
+    }
+
     pub fn set_transport_checksum(&mut self) {
         // Looks at internal buffer assuming
         // it contains a valid IP packet, checks the payload type. If the payload
@@ -498,6 +540,27 @@ impl IP6Packet<'a> {
         self.header.set_payload_len(payload_len);
     }
 
+    /// Same as calling `set_payload` followed by `set_transport_checksum`,
+    /// but computes a UDP checksum while the payload is copied instead of in
+    /// a second pass over it.
+    pub fn set_payload_and_checksum(&mut self, transport_header: TransportHeader, payload: &[u8]) {
+        let mut payload_sum = Checksum::new();
+        let (next_header, payload_len) =
+            self.payload
+                .set_payload_summed(transport_header, payload, &mut payload_sum);
+        self.header.set_next_header(next_header);
+        self.header.set_payload_len(payload_len);
+
+        match self.payload.header {
+            TransportHeader::UDP(ref mut udp_header) => {
+                udp_header.set_cksum(0);
+                let cksum = compute_udp_checksum_from_sum(&self.header, &udp_header, &payload_sum);
+                udp_header.set_cksum(cksum);
+            }
+            _ => self.set_transport_checksum(),
+        }
+    }
+
     // TODO: Do we need a decode equivalent? I don't think so, but we might
 
     pub fn encode(&self, buf: &mut [u8]) -> SResult<usize> {
@@ -507,4 +570,65 @@ impl IP6Packet<'a> {
         let (off, _) = ip6_header.encode(buf).done().unwrap();
         self.payload.encode(buf, off)
     }
+
+    /// This function serializes `len` bytes of the packet's headers (the
+    /// IPv6 header followed by the transport header), starting `offset`
+    /// bytes into them. Only the headers that overlap this window are
+    /// encoded, and the serialized bytes are passed to `write` in order, in
+    /// one or more chunks. This lets a packet's headers be split across
+    /// 6LoWPAN fragments without serializing the whole packet for each one.
+    ///
+    /// # Arguments
+    ///
+    /// `offset` - Offset into the headers of the first byte to serialize
+    /// `len` - Number of bytes to serialize
+    /// `write` - Receives the serialized bytes
+    ///
+    /// # Return Value
+    ///
+    /// `SResult<()>` - The number of bytes serialized, or `SResult::Error` if
+    /// the transport header cannot be serialized (TCP is not supported yet),
+    /// in which case nothing is passed to `write`
+    pub fn encode_headers<F: FnMut(&[u8])>(
+        &self,
+        offset: usize,
+        len: usize,
+        mut write: F,
+    ) -> SResult<()> {
+        let transport_hdr_size = match self.payload.header {
+            TransportHeader::UDP(udp_header) => udp_header.get_hdr_size(),
+            TransportHeader::ICMP(icmp_header) => icmp_header.get_hdr_size(),
+            TransportHeader::TCP(_) => return SResult::Error(()),
+        };
+        let end = min(offset + len, IP6_HDR_LEN + transport_hdr_size);
+        if offset >= end {
+            return SResult::Done(0, ());
+        }
+
+        if offset < IP6_HDR_LEN {
+            let mut ip6_header = [0 as u8; IP6_HDR_LEN];
+            let _ = self.header.encode(&mut ip6_header);
+            write(&ip6_header[offset..min(end, IP6_HDR_LEN)]);
+        }
+
+        if end > IP6_HDR_LEN {
+            let start = max(offset, IP6_HDR_LEN) - IP6_HDR_LEN;
+            let end = end - IP6_HDR_LEN;
+            match self.payload.header {
+                TransportHeader::UDP(udp_header) => {
+                    let mut transport_header = [0 as u8; UDP_HDR_LEN];
+                    let _ = udp_header.encode(&mut transport_header, 0);
+                    write(&transport_header[start..end]);
+                }
+                TransportHeader::ICMP(icmp_header) => {
+                    let mut transport_header = [0 as u8; ICMP_HDR_LEN];
+                    let _ = icmp_header.encode(&mut transport_header, 0);
+                    write(&transport_header[start..end]);
+                }
+                // Rejected above
+                TransportHeader::TCP(_) => {}
+            }
+        }
+        SResult::Done(end - offset, ())
+    }
 }
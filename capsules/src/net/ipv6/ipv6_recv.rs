@@ -1,5 +1,7 @@
+use core::cell::Cell;
 use kernel::common::cells::OptionalCell;
 use kernel::ReturnCode;
+use net::ipv6::ip_utils::IPAddr;
 use net::ipv6::ipv6::IP6Header;
 use net::sixlowpan::sixlowpan_state::SixlowpanRxClient;
 
@@ -26,6 +28,16 @@ use net::sixlowpan::sixlowpan_state::SixlowpanRxClient;
   packets up to userland.
 */
 
+/// The maximum number of multicast groups that can be joined, in addition to
+/// the all-nodes group.
+pub const MAX_MULTICAST_GROUPS: usize = 8;
+
+/// The link-local all-nodes multicast address, ff02::1, which every node
+/// receives (RFC 4291, 2.8).
+const ALL_NODES: IPAddr = IPAddr([
+    0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01,
+]);
+
 pub trait IP6RecvClient {
     // TODO: What should the upper layers receive?
     fn receive(&self, header: IP6Header, payload: &[u8]);
@@ -40,8 +52,12 @@ pub trait IP6Receiver<'a> {
     fn set_client(&self, client: &'a IP6RecvClient);
 }
 
+/// Received packets whose destination is a multicast address are dropped
+/// unless the node has joined that group with `join_group`. Nodes should join
+/// the solicited-node group of each of their unicast addresses.
 pub struct IP6RecvStruct<'a> {
     client: OptionalCell<&'a IP6RecvClient>,
+    groups: Cell<[Option<IPAddr>; MAX_MULTICAST_GROUPS]>,
 }
 
 impl<'a> IP6Receiver<'a> for IP6RecvStruct<'a> {
@@ -54,7 +70,50 @@ impl<'a> IP6RecvStruct<'a> {
     pub fn new() -> IP6RecvStruct<'a> {
         IP6RecvStruct {
             client: OptionalCell::empty(),
+            groups: Cell::new([None; MAX_MULTICAST_GROUPS]),
+        }
+    }
+
+    /// Receive packets sent to the multicast group `group`. Returns `EINVAL`
+    /// if `group` is not a multicast address, `EALREADY` if the group has
+    /// already been joined, and `ENOMEM` if the table of groups is full.
+    pub fn join_group(&self, group: IPAddr) -> ReturnCode {
+        if !group.is_multicast() {
+            return ReturnCode::EINVAL;
+        }
+        if self.is_member(&group) {
+            return ReturnCode::EALREADY;
         }
+        let mut groups = self.groups.get();
+        match groups.iter_mut().find(|g| g.is_none()) {
+            Some(slot) => *slot = Some(group),
+            None => return ReturnCode::ENOMEM,
+        }
+        self.groups.set(groups);
+        ReturnCode::SUCCESS
+    }
+
+    /// Join the solicited-node multicast group of the unicast address
+    /// `addr`.
+    pub fn join_solicited_node(&self, addr: IPAddr) -> ReturnCode {
+        self.join_group(addr.solicited_node())
+    }
+
+    /// Stop receiving packets sent to `group`. Returns `EINVAL` if the group
+    /// has not been joined.
+    pub fn leave_group(&self, group: IPAddr) -> ReturnCode {
+        let mut groups = self.groups.get();
+        match groups.iter_mut().find(|g| **g == Some(group)) {
+            Some(slot) => *slot = None,
+            None => return ReturnCode::EINVAL,
+        }
+        self.groups.set(groups);
+        ReturnCode::SUCCESS
+    }
+
+    /// Whether packets sent to the multicast address `group` are received.
+    pub fn is_member(&self, group: &IPAddr) -> bool {
+        *group == ALL_NODES || self.groups.get().iter().any(|g| *g == Some(*group))
     }
 }
 
@@ -66,6 +125,10 @@ impl<'a> SixlowpanRxClient for IP6RecvStruct<'a> {
         }
         match IP6Header::decode(buf).done() {
             Some((offset, ip6_header)) => {
+                let dst_addr = ip6_header.get_dst_addr();
+                if dst_addr.is_multicast() && !self.is_member(&dst_addr) {
+                    return; // Dropped, not a member of the group
+                }
                 let checksum_result = ip6_header.check_transport_checksum(&buf[offset..len]);
                 if checksum_result == ReturnCode::FAIL {
                     debug!("dropped!: {:?}", checksum_result);
@@ -17,15 +17,20 @@
 // interface.
 
 use core::cell::Cell;
+use core::cmp::min;
 use ieee802154::device::{MacDevice, TxClient};
 use kernel::common::cells::{OptionalCell, TakeCell};
 use kernel::hil::time::{self, Frequency};
 use kernel::ReturnCode;
 use net::ieee802154::MacAddress;
-use net::ipv6::ip_utils::IPAddr;
-use net::ipv6::ipv6::{IP6Header, IP6Packet, TransportHeader};
+use net::ipv6::ip_utils::{self, IPAddr, UdpChecksum};
+use net::ipv6::ipv6::{IP6Header, IP6Packet, TransportHeader, DEFAULT_HOP_LIMIT};
 use net::sixlowpan::sixlowpan_state::TxState;
 
+/// The number of addresses an `IP6SendStruct` can choose a source address
+/// from.
+const MAX_SRC_ADDRS: usize = 4;
+
 /// This trait must be implemented by upper layers in order to receive
 /// the `send_done` callback when a transmission has completed. The upper
 /// layer must then call `IP6Sender.set_client` in order to receive this
@@ -48,13 +53,23 @@ pub trait IP6Sender<'a> {
     fn set_client(&self, client: &'a IP6SendClient);
 
     /// This method sets the source address for packets sent from the
-    /// `IP6Sender` instance.
+    /// `IP6Sender` instance, replacing any addresses added before.
     ///
     /// # Arguments
     /// `src_addr` - `IPAddr` to set as the source address for packets sent
     /// from this instance of `IP6Sender`
     fn set_addr(&self, src_addr: IPAddr);
 
+    /// This method adds an address, such as a global address alongside a
+    /// link-local one, that the `IP6Sender` instance may use as the source
+    /// address of packets. The source address of each packet is chosen from
+    /// the addresses set according to its destination (RFC 6724). Returns
+    /// `ENOMEM` if no more addresses can be added.
+    ///
+    /// # Arguments
+    /// `src_addr` - `IPAddr` to add to the candidate source addresses
+    fn add_addr(&self, src_addr: IPAddr) -> ReturnCode;
+
     /// This method sets the gateway/next hop MAC address for this `IP6Sender`
     /// instance.
     ///
@@ -62,6 +77,24 @@ pub trait IP6Sender<'a> {
     /// `gateway` - MAC address to send the constructed packet to
     fn set_gateway(&self, gateway: MacAddress);
 
+    /// This method sets the hop limit written into the header of packets
+    /// subsequently sent from this `IP6Sender` instance. It defaults to
+    /// `DEFAULT_HOP_LIMIT`.
+    ///
+    /// # Arguments
+    /// `hop_limit` - Hop limit of subsequent packets
+    fn set_hop_limit(&self, hop_limit: u8);
+
+    /// This method sets the traffic class written into the header of packets
+    /// subsequently sent from this `IP6Sender` instance. Both fields default
+    /// to 0.
+    ///
+    /// # Arguments
+    /// `dscp` - Differentiated services code point (the upper 6 bits of the
+    /// traffic class)
+    /// `ecn` - Explicit congestion notification (the lower 2 bits)
+    fn set_traffic_class(&self, dscp: u8, ecn: u8);
+
     /// This method sets the `IP6Header` for the `IP6Sender` instance
     ///
     /// # Arguments
@@ -88,14 +121,24 @@ pub struct IP6SendStruct<'a, A: time::Alarm> {
     alarm: &'a A, // Alarm so we can introduce a small delay between fragments to ensure
     // successful reception on receivers with slow copies out of the radio buffer
     // (imix)
-    src_addr: Cell<IPAddr>,
+    src_addrs: Cell<[IPAddr; MAX_SRC_ADDRS]>,
     gateway: Cell<MacAddress>,
+    hop_limit: Cell<u8>,
+    dscp: Cell<u8>,
+    ecn: Cell<u8>,
     tx_buf: TakeCell<'static, [u8]>,
     sixlowpan: TxState<'a>,
     radio: &'a MacDevice<'a>,
     dst_mac_addr: MacAddress,
     src_mac_addr: MacAddress,
     client: OptionalCell<&'a IP6SendClient>,
+    checksum_engine: OptionalCell<&'a UdpChecksum>,
+    /// How many times a failed fragment is sent again before the packet is
+    /// abandoned, and the delay before the first retry in microseconds,
+    /// which doubles with each retry.
+    max_retries: Cell<usize>,
+    retry_backoff: Cell<u32>,
+    retries: Cell<usize>,
 }
 
 impl<A: time::Alarm> IP6Sender<'a> for IP6SendStruct<'a, A> {
@@ -104,13 +147,37 @@ impl<A: time::Alarm> IP6Sender<'a> for IP6SendStruct<'a, A> {
     }
 
     fn set_addr(&self, src_addr: IPAddr) {
-        self.src_addr.set(src_addr);
+        let mut src_addrs = [IPAddr::new(); MAX_SRC_ADDRS];
+        src_addrs[0] = src_addr;
+        self.src_addrs.set(src_addrs);
+    }
+
+    fn add_addr(&self, src_addr: IPAddr) -> ReturnCode {
+        let mut src_addrs = self.src_addrs.get();
+        if src_addrs.contains(&src_addr) {
+            return ReturnCode::SUCCESS;
+        }
+        match src_addrs.iter_mut().find(|addr| addr.is_unspecified()) {
+            Some(slot) => *slot = src_addr,
+            None => return ReturnCode::ENOMEM,
+        }
+        self.src_addrs.set(src_addrs);
+        ReturnCode::SUCCESS
     }
 
     fn set_gateway(&self, gateway: MacAddress) {
         self.gateway.set(gateway);
     }
 
+    fn set_hop_limit(&self, hop_limit: u8) {
+        self.hop_limit.set(hop_limit);
+    }
+
+    fn set_traffic_class(&self, dscp: u8, ecn: u8) {
+        self.dscp.set(dscp);
+        self.ecn.set(ecn);
+    }
+
     fn set_header(&mut self, ip6_header: IP6Header) {
         self.ip6_packet
             .map(|ip6_packet| ip6_packet.header = ip6_header);
@@ -147,24 +214,57 @@ impl<A: time::Alarm> IP6SendStruct<'a, A> {
         IP6SendStruct {
             ip6_packet: TakeCell::new(ip6_packet),
             alarm: alarm,
-            src_addr: Cell::new(IPAddr::new()),
+            src_addrs: Cell::new([IPAddr::new(); MAX_SRC_ADDRS]),
             gateway: Cell::new(dst_mac_addr),
+            hop_limit: Cell::new(DEFAULT_HOP_LIMIT),
+            dscp: Cell::new(0),
+            ecn: Cell::new(0),
             tx_buf: TakeCell::new(tx_buf),
             sixlowpan: sixlowpan,
             radio: radio,
             dst_mac_addr: dst_mac_addr,
             src_mac_addr: src_mac_addr,
             client: OptionalCell::empty(),
+            checksum_engine: OptionalCell::empty(),
+            max_retries: Cell::new(0),
+            retry_backoff: Cell::new(0),
+            retries: Cell::new(0),
         }
     }
 
+    /// Compute UDP checksums of sent packets with `engine`, e.g. a hardware
+    /// checksum engine, instead of in software while the payload is copied.
+    pub fn set_checksum_engine(&self, engine: &'a UdpChecksum) {
+        self.checksum_engine.set(engine);
+    }
+
+    /// Send a fragment whose transmission fails again up to `max_retries`
+    /// times before abandoning the packet, waiting `backoff` microseconds
+    /// before the first retry and twice as long before each following one.
+    /// By default, a packet is abandoned as soon as one of its fragments
+    /// fails.
+    pub fn set_retry_policy(&self, max_retries: usize, backoff: u32) {
+        self.max_retries.set(max_retries);
+        self.retry_backoff.set(backoff);
+    }
+
     fn init_packet(&self, dst_addr: IPAddr, transport_header: TransportHeader, payload: &[u8]) {
         self.ip6_packet.map(|ip6_packet| {
             ip6_packet.header = IP6Header::default();
-            ip6_packet.header.src_addr = self.src_addr.get();
+            ip6_packet.header.src_addr =
+                ip_utils::select_source_address(&self.src_addrs.get(), &dst_addr)
+                    .unwrap_or(IPAddr::new());
             ip6_packet.header.dst_addr = dst_addr;
-            ip6_packet.set_payload(transport_header, payload);
-            ip6_packet.set_transport_checksum();
+            ip6_packet.header.set_hop_limit(self.hop_limit.get());
+            ip6_packet.header.set_dscp(self.dscp.get());
+            ip6_packet.header.set_ecn(self.ecn.get());
+            match self.checksum_engine.map_or(None, |engine| Some(*engine)) {
+                Some(engine) => {
+                    ip6_packet.set_payload(transport_header, payload);
+                    ip6_packet.set_transport_checksum_with(engine);
+                }
+                None => ip6_packet.set_payload_and_checksum(transport_header, payload),
+            }
         });
     }
 
@@ -232,6 +332,25 @@ impl<A: time::Alarm> TxClient for IP6SendStruct<'a, A> {
     fn send_done(&self, tx_buf: &'static mut [u8], acked: bool, result: ReturnCode) {
         self.tx_buf.replace(tx_buf);
         debug!("Send result: {:?}, acked: {}", result, acked);
+        if result != ReturnCode::SUCCESS {
+            let retries = self.retries.get();
+            if retries < self.max_retries.get() {
+                self.retries.set(retries + 1);
+                self.sixlowpan.retry_fragment();
+                let backoff = (self.retry_backoff.get() as u64) << min(retries, 16);
+                let interval = backoff * <A::Frequency>::frequency() as u64 / 1000000;
+                let tics = self.alarm.now().wrapping_add(interval as u32);
+                self.alarm.set_alarm(tics);
+            } else {
+                // The receiver cannot reassemble the packet without this
+                // fragment, so there is no point sending the rest.
+                self.retries.set(0);
+                self.sixlowpan.abort();
+                self.send_completed(result);
+            }
+            return;
+        }
+        self.retries.set(0);
         // Below code adds delay between fragments. Despite some efforts
         // to fix this bug, I find that without it the receiving imix cannot
         // receive more than 2 fragments in a single packet without hanging
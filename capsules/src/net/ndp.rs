@@ -0,0 +1,467 @@
+//! This file contains the types and methods for constructing and parsing the
+//! Neighbor Discovery (RFC 4861) Router Solicitation and Router Advertisement
+//! messages, along with the options used by 6LoWPAN nodes (RFC 6775): the
+//! Source Link-Layer Address Option, the Prefix Information Option and the
+//! 6LoWPAN Context Option.
+//!
+//! The ICMPv6 headers of these messages are represented by the `Type133`
+//! (Router Solicitation) and `Type134` (Router Advertisement) variants of
+//! `ICMP6HeaderOptions`; this module handles the message bodies that follow
+//! the 8 byte ICMPv6 header.
+//!
+//! The `NdpReceiver` sits between the IPv6 receive layer and its client. It
+//! consumes Router Advertisements, updating the 6LoWPAN contexts and the
+//! node's global address from the options they carry, and passes all other
+//! packets through unchanged.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let ndp_recv = static_init!(
+//!     capsules::net::ndp::NdpReceiver<'static>,
+//!     capsules::net::ndp::NdpReceiver::new(context_table, &src_mac_addr));
+//! ip_receive.set_client(ndp_recv);
+//! ndp_recv.set_client(udp_recv);
+//! ```
+
+use core::cell::Cell;
+use kernel::common::cells::OptionalCell;
+use net::icmpv6::icmpv6::{ICMP6Header, ICMP6HeaderOptions};
+use net::ieee802154::MacAddress;
+use net::ipv6::ip_utils::{ip6_nh, IPAddr};
+use net::ipv6::ipv6::IP6Header;
+use net::ipv6::ipv6_recv::{IP6RecvClient, IP6Receiver};
+use net::sixlowpan::sixlowpan_compression::{compute_iid, ContextTable};
+use net::stream::SResult;
+use net::stream::{decode_bytes, decode_u16, decode_u32, decode_u8};
+use net::stream::{encode_bytes, encode_u16, encode_u32, encode_u8};
+
+/// Option type of the Source Link-Layer Address Option.
+pub const OPT_SRC_LINK_LAYER_ADDR: u8 = 1;
+/// Option type of the Prefix Information Option.
+pub const OPT_PREFIX_INFO: u8 = 3;
+/// Option type of the 6LoWPAN Context Option (RFC 6775).
+pub const OPT_6LOWPAN_CONTEXT: u8 = 34;
+
+/// Neighbor Discovery messages must be sent with a hop limit of 255, which
+/// lets receivers check that they were not forwarded by a router.
+pub const NDP_HOP_LIMIT: u8 = 255;
+
+/// Size of the Router Advertisement body that follows the ICMPv6 header.
+pub const RA_BODY_SIZE: usize = 8;
+
+const PIO_SIZE: usize = 32;
+const PIO_FLAG_ON_LINK: u8 = 0x80;
+const PIO_FLAG_AUTONOMOUS: u8 = 0x40;
+
+const CONTEXT_FLAG_COMPRESS: u8 = 0x10;
+const CONTEXT_ID_MASK: u8 = 0x0f;
+
+/// Length of the prefix used to form addresses from interface identifiers.
+const IID_PREFIX_LEN: u8 = 64;
+
+/// Encodes a Source Link-Layer Address Option for `mac_addr` at `offset`,
+/// padded to a multiple of 8 bytes as described in RFC 4944, section 8.
+/// Router Solicitations carry this option so that routers can reply without
+/// resolving the sender's address.
+pub fn encode_src_link_layer_addr(
+    buf: &mut [u8],
+    offset: usize,
+    mac_addr: MacAddress,
+) -> SResult<usize> {
+    let (addr_len, units) = match mac_addr {
+        MacAddress::Short(_) => (2, 1),
+        MacAddress::Long(_) => (8, 2),
+    };
+    let mut off = offset;
+    off = enc_consume!(buf, off; encode_u8, OPT_SRC_LINK_LAYER_ADDR);
+    off = enc_consume!(buf, off; encode_u8, units);
+    off = enc_consume!(buf, off; mac_addr; encode);
+    let padding = units as usize * 8 - 2 - addr_len;
+    off = enc_consume!(buf, off; encode_bytes, &[0; 6][..padding]);
+    stream_done!(off, off);
+}
+
+/// Encodes the body of a Router Solicitation into `buf`, returning its
+/// length. The body is the reserved field, which is part of the ICMPv6 header,
+/// followed by a Source Link-Layer Address Option if `src_mac` is given. The
+/// option must be omitted when the source address is unspecified.
+pub fn encode_router_solicitation(buf: &mut [u8], src_mac: Option<MacAddress>) -> SResult<usize> {
+    match src_mac {
+        Some(mac_addr) => encode_src_link_layer_addr(buf, 0, mac_addr),
+        None => stream_done!(0, 0),
+    }
+}
+
+/// The fields of a Router Advertisement that follow the ICMPv6 header. The
+/// hop limit, flags and router lifetime are part of the header itself.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RouterAdvertisement {
+    pub reachable_time: u32,
+    pub retrans_timer: u32,
+}
+
+impl RouterAdvertisement {
+    pub fn encode(&self, buf: &mut [u8], offset: usize) -> SResult<usize> {
+        let mut off = offset;
+        off = enc_consume!(buf, off; encode_u32, self.reachable_time);
+        off = enc_consume!(buf, off; encode_u32, self.retrans_timer);
+        stream_done!(off, off);
+    }
+
+    pub fn decode(buf: &[u8]) -> SResult<RouterAdvertisement> {
+        let (off, reachable_time) = dec_try!(buf, 0; decode_u32);
+        let (off, retrans_timer) = dec_try!(buf, off; decode_u32);
+        stream_done!(
+            off,
+            RouterAdvertisement {
+                reachable_time: reachable_time,
+                retrans_timer: retrans_timer,
+            }
+        );
+    }
+}
+
+/// A Prefix Information Option, which advertises an on-link prefix and
+/// whether nodes may form addresses from it (RFC 4861, 4.6.2). Lifetimes are
+/// in seconds, with `0xffffffff` meaning infinity.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PrefixInfo {
+    pub prefix_len: u8,
+    pub on_link: bool,
+    pub autonomous: bool,
+    pub valid_lifetime: u32,
+    pub preferred_lifetime: u32,
+    pub prefix: [u8; 16],
+}
+
+impl PrefixInfo {
+    pub fn encode(&self, buf: &mut [u8], offset: usize) -> SResult<usize> {
+        let mut flags = 0;
+        if self.on_link {
+            flags |= PIO_FLAG_ON_LINK;
+        }
+        if self.autonomous {
+            flags |= PIO_FLAG_AUTONOMOUS;
+        }
+        let mut off = offset;
+        off = enc_consume!(buf, off; encode_u8, OPT_PREFIX_INFO);
+        off = enc_consume!(buf, off; encode_u8, (PIO_SIZE / 8) as u8);
+        off = enc_consume!(buf, off; encode_u8, self.prefix_len);
+        off = enc_consume!(buf, off; encode_u8, flags);
+        off = enc_consume!(buf, off; encode_u32, self.valid_lifetime);
+        off = enc_consume!(buf, off; encode_u32, self.preferred_lifetime);
+        off = enc_consume!(buf, off; encode_u32, 0);
+        off = enc_consume!(buf, off; encode_bytes, &self.prefix);
+        stream_done!(off, off);
+    }
+
+    /// Decodes a Prefix Information Option, including its type and length.
+    pub fn decode(buf: &[u8]) -> SResult<PrefixInfo> {
+        let (off, opt_type) = dec_try!(buf, 0; decode_u8);
+        let (off, units) = dec_try!(buf, off; decode_u8);
+        stream_cond!(opt_type == OPT_PREFIX_INFO && units as usize * 8 == PIO_SIZE);
+        let (off, prefix_len) = dec_try!(buf, off; decode_u8);
+        stream_cond!(prefix_len <= 128);
+        let (off, flags) = dec_try!(buf, off; decode_u8);
+        let (off, valid_lifetime) = dec_try!(buf, off; decode_u32);
+        let (off, preferred_lifetime) = dec_try!(buf, off; decode_u32);
+        let (off, _) = dec_try!(buf, off; decode_u32);
+        let mut prefix = [0; 16];
+        let off = dec_consume!(buf, off; decode_bytes, &mut prefix);
+        stream_done!(
+            off,
+            PrefixInfo {
+                prefix_len: prefix_len,
+                on_link: flags & PIO_FLAG_ON_LINK != 0,
+                autonomous: flags & PIO_FLAG_AUTONOMOUS != 0,
+                valid_lifetime: valid_lifetime,
+                preferred_lifetime: preferred_lifetime,
+                prefix: prefix,
+            }
+        );
+    }
+}
+
+/// A 6LoWPAN Context Option, which distributes the prefix used for
+/// context-based header compression under a context identifier (RFC 6775,
+/// 4.2). The valid lifetime is in units of 60 seconds, and a lifetime of 0
+/// withdraws the context.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ContextOption {
+    pub context_len: u8,
+    pub compress: bool,
+    pub id: u8,
+    pub valid_lifetime: u16,
+    pub prefix: [u8; 16],
+}
+
+impl ContextOption {
+    /// The prefix field holds 8 bytes when the context is at most 64 bits
+    /// long, and 16 bytes otherwise.
+    fn prefix_field_len(&self) -> usize {
+        if self.context_len > 64 {
+            16
+        } else {
+            8
+        }
+    }
+
+    pub fn encode(&self, buf: &mut [u8], offset: usize) -> SResult<usize> {
+        let mut flags = self.id & CONTEXT_ID_MASK;
+        if self.compress {
+            flags |= CONTEXT_FLAG_COMPRESS;
+        }
+        let prefix_len = self.prefix_field_len();
+        let mut off = offset;
+        off = enc_consume!(buf, off; encode_u8, OPT_6LOWPAN_CONTEXT);
+        off = enc_consume!(buf, off; encode_u8, ((prefix_len + 8) / 8) as u8);
+        off = enc_consume!(buf, off; encode_u8, self.context_len);
+        off = enc_consume!(buf, off; encode_u8, flags);
+        off = enc_consume!(buf, off; encode_u16, 0);
+        off = enc_consume!(buf, off; encode_u16, self.valid_lifetime);
+        off = enc_consume!(buf, off; encode_bytes, &self.prefix[..prefix_len]);
+        stream_done!(off, off);
+    }
+
+    /// Decodes a 6LoWPAN Context Option, including its type and length.
+    pub fn decode(buf: &[u8]) -> SResult<ContextOption> {
+        let (off, opt_type) = dec_try!(buf, 0; decode_u8);
+        let (off, units) = dec_try!(buf, off; decode_u8);
+        stream_cond!(opt_type == OPT_6LOWPAN_CONTEXT && (units == 2 || units == 3));
+        let (off, context_len) = dec_try!(buf, off; decode_u8);
+        stream_cond!(context_len <= 128);
+        let (off, flags) = dec_try!(buf, off; decode_u8);
+        let (off, _) = dec_try!(buf, off; decode_u16);
+        let (off, valid_lifetime) = dec_try!(buf, off; decode_u16);
+        let prefix_len = units as usize * 8 - 8;
+        stream_cond!(context_len as usize <= prefix_len * 8);
+        let mut prefix = [0; 16];
+        let off = dec_consume!(buf, off; decode_bytes, &mut prefix[..prefix_len]);
+        stream_done!(
+            off,
+            ContextOption {
+                context_len: context_len,
+                compress: flags & CONTEXT_FLAG_COMPRESS != 0,
+                id: flags & CONTEXT_ID_MASK,
+                valid_lifetime: valid_lifetime,
+                prefix: prefix,
+            }
+        );
+    }
+}
+
+/// A Neighbor Discovery option. Options this module does not interpret are
+/// returned as `Unknown` with their type, so that they can be skipped.
+#[derive(Copy, Clone, Debug)]
+pub enum NdpOption<'a> {
+    SrcLinkLayerAddr(&'a [u8]),
+    PrefixInfo(PrefixInfo),
+    Context(ContextOption),
+    Unknown(u8),
+}
+
+/// An iterator over the options in the body of a Neighbor Discovery message.
+/// Iteration stops at the end of the buffer or at the first malformed option,
+/// after which `is_malformed` returns true; RFC 4861 requires that messages
+/// containing an option of length 0 be discarded.
+pub struct NdpOptions<'a> {
+    buf: &'a [u8],
+    malformed: bool,
+}
+
+impl NdpOptions<'a> {
+    pub fn new(buf: &'a [u8]) -> NdpOptions<'a> {
+        NdpOptions {
+            buf: buf,
+            malformed: false,
+        }
+    }
+
+    pub fn is_malformed(&self) -> bool {
+        self.malformed
+    }
+
+    fn stop(&mut self) -> Option<NdpOption<'a>> {
+        self.malformed = true;
+        self.buf = &[];
+        None
+    }
+}
+
+impl Iterator for NdpOptions<'a> {
+    type Item = NdpOption<'a>;
+
+    fn next(&mut self) -> Option<NdpOption<'a>> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        if self.buf.len() < 2 {
+            return self.stop();
+        }
+        let len = self.buf[1] as usize * 8;
+        if len == 0 || len > self.buf.len() {
+            return self.stop();
+        }
+        let opt_buf = &self.buf[..len];
+        self.buf = &self.buf[len..];
+
+        let option = match opt_buf[0] {
+            OPT_SRC_LINK_LAYER_ADDR => Some(NdpOption::SrcLinkLayerAddr(&opt_buf[2..])),
+            OPT_PREFIX_INFO => PrefixInfo::decode(opt_buf)
+                .done()
+                .map(|(_, pio)| NdpOption::PrefixInfo(pio)),
+            OPT_6LOWPAN_CONTEXT => ContextOption::decode(opt_buf)
+                .done()
+                .map(|(_, context)| NdpOption::Context(context)),
+            opt_type => Some(NdpOption::Unknown(opt_type)),
+        };
+        match option {
+            Some(option) => Some(option),
+            None => self.stop(),
+        }
+    }
+}
+
+/// A client notified when a Router Advertisement changes the node's global
+/// address, for example to update the source address of outgoing packets.
+pub trait NdpClient {
+    /// Called with the new address, or `None` if the prefix it was formed
+    /// from has been withdrawn.
+    fn address_changed(&self, addr: Option<IPAddr>);
+}
+
+/// Processes received Router Advertisements and passes all other packets to
+/// its client.
+///
+/// Each 6LoWPAN Context Option adds the context to, or with a lifetime of 0
+/// removes it from, the `ContextTable`. Each Prefix Information Option with
+/// the autonomous flag set and a 64 bit prefix forms a global address from the
+/// prefix and the node's interface identifier.
+pub struct NdpReceiver<'a> {
+    client: OptionalCell<&'a IP6RecvClient>,
+    ndp_client: OptionalCell<&'a NdpClient>,
+    contexts: &'a ContextTable,
+    iid: [u8; 8],
+    address: Cell<Option<IPAddr>>,
+}
+
+impl IP6Receiver<'a> for NdpReceiver<'a> {
+    fn set_client(&self, client: &'a IP6RecvClient) {
+        self.client.set(client);
+    }
+}
+
+impl NdpReceiver<'a> {
+    pub fn new(contexts: &'a ContextTable, mac_addr: &MacAddress) -> NdpReceiver<'a> {
+        NdpReceiver {
+            client: OptionalCell::empty(),
+            ndp_client: OptionalCell::empty(),
+            contexts: contexts,
+            iid: compute_iid(mac_addr),
+            address: Cell::new(None),
+        }
+    }
+
+    pub fn set_ndp_client(&self, client: &'a NdpClient) {
+        self.ndp_client.set(client);
+    }
+
+    /// Returns the global address formed from the most recently advertised
+    /// prefix, if any.
+    pub fn get_address(&self) -> Option<IPAddr> {
+        self.address.get()
+    }
+
+    fn set_address(&self, addr: Option<IPAddr>) {
+        if self.address.get() != addr {
+            self.address.set(addr);
+            self.ndp_client.map(|client| client.address_changed(addr));
+        }
+    }
+
+    /// Returns the body of `payload` if it is a valid Router Advertisement,
+    /// which must come from a link-local address with a hop limit of 255
+    /// (RFC 4861, 6.1.2).
+    fn router_advertisement_body<'b>(
+        &self,
+        header: &IP6Header,
+        payload: &'b [u8],
+    ) -> Option<&'b [u8]> {
+        if header.get_next_header() != ip6_nh::ICMP
+            || header.get_hop_limit() != NDP_HOP_LIMIT
+            || !header.get_src_addr().is_unicast_link_local()
+        {
+            return None;
+        }
+        match ICMP6Header::decode(payload).done() {
+            Some((off, icmp_header)) => match icmp_header.get_options() {
+                ICMP6HeaderOptions::Type134 { .. } if icmp_header.get_code() == 0 => {
+                    Some(&payload[off..])
+                }
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    fn receive_router_advertisement(&self, body: &[u8]) {
+        if RouterAdvertisement::decode(body).done().is_none() {
+            return;
+        }
+
+        // Check every option before acting on any of them, since the whole
+        // message must be discarded if one is malformed.
+        let mut options = NdpOptions::new(&body[RA_BODY_SIZE..]);
+        while let Some(_) = options.next() {}
+        if options.is_malformed() {
+            return;
+        }
+
+        for option in NdpOptions::new(&body[RA_BODY_SIZE..]) {
+            match option {
+                NdpOption::Context(context) => {
+                    if context.valid_lifetime == 0 {
+                        self.contexts.remove_context(context.id);
+                    } else {
+                        self.contexts.set_context(
+                            context.id,
+                            &context.prefix,
+                            context.context_len,
+                            context.compress,
+                        );
+                    }
+                }
+                NdpOption::PrefixInfo(pio) => {
+                    if pio.autonomous && pio.prefix_len == IID_PREFIX_LEN {
+                        self.receive_prefix(&pio);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn receive_prefix(&self, pio: &PrefixInfo) {
+        let mut addr = IPAddr::new();
+        addr.0[..8].copy_from_slice(&pio.prefix[..8]);
+        addr.0[8..].copy_from_slice(&self.iid);
+        if pio.valid_lifetime != 0 {
+            self.set_address(Some(addr));
+        } else if self.address.get() == Some(addr) {
+            self.set_address(None);
+        }
+    }
+}
+
+impl IP6RecvClient for NdpReceiver<'a> {
+    fn receive(&self, header: IP6Header, payload: &[u8]) {
+        match self.router_advertisement_body(&header, payload) {
+            Some(body) => self.receive_router_advertisement(body),
+            None => {
+                self.client.map(|client| client.receive(header, payload));
+            }
+        }
+    }
+}
@@ -1,8 +1,10 @@
 /// Implements the 6LoWPAN specification for sending IPv6 datagrams over
 /// 802.15.4 packets efficiently, as detailed in RFC 6282.
+use core::cell::Cell;
 use core::mem;
 use core::result::Result;
-use net::ieee802154::MacAddress;
+use kernel::ReturnCode;
+use net::ieee802154::{MacAddress, SecurityLevel};
 use net::ipv6::ip_utils::{compute_udp_checksum, ip6_nh, IPAddr};
 use net::ipv6::ipv6::{IP6Header, IP6Packet, TransportHeader};
 use net::udp::udp::UDPHeader;
@@ -152,6 +154,142 @@ impl ContextStore for Context {
     }
 }
 
+/// The number of contexts that can be identified by the 4-bit context
+/// identifiers carried in the LoWPAN_IPHC CID extension.
+pub const MAX_CONTEXTS: usize = 16;
+
+/// A `ContextStore` whose contexts can be added and removed at runtime, for
+/// example as they are learned from the 6LoWPAN Context Option of received
+/// Router Advertisements (RFC 6775).
+///
+/// Context 0 is provided when the table is created and is never changed or
+/// removed, since it always holds the mesh-local prefix.
+pub struct ContextTable {
+    contexts: Cell<[Option<Context>; MAX_CONTEXTS]>,
+}
+
+impl ContextTable {
+    pub fn new(context_0: Context) -> ContextTable {
+        let mut contexts = [None; MAX_CONTEXTS];
+        contexts[0] = Some(Context {
+            id: 0,
+            ..context_0
+        });
+        ContextTable {
+            contexts: Cell::new(contexts),
+        }
+    }
+
+    /// Adds the context with identifier `id`, replacing any context already
+    /// stored under that identifier. Only contexts with `compress` set are
+    /// used when compressing outgoing packets, but all of them can be used to
+    /// decompress incoming ones.
+    ///
+    /// Returns `EINVAL` if `id` is 0 or not a valid context identifier, or if
+    /// `prefix` is too short to hold `prefix_len` bits.
+    pub fn set_context(
+        &self,
+        id: u8,
+        prefix: &[u8],
+        prefix_len: u8,
+        compress: bool,
+    ) -> ReturnCode {
+        let prefix_bytes = ((prefix_len as usize) + 7) / 8;
+        if id == 0
+            || id as usize >= MAX_CONTEXTS
+            || prefix_len > 128
+            || prefix.len() < prefix_bytes
+        {
+            return ReturnCode::EINVAL;
+        }
+
+        let mut context = Context {
+            prefix: [0; 16],
+            prefix_len: prefix_len,
+            id: id,
+            compress: compress,
+        };
+        context.prefix[..prefix_bytes].copy_from_slice(&prefix[..prefix_bytes]);
+
+        let mut contexts = self.contexts.get();
+        contexts[id as usize] = Some(context);
+        self.contexts.set(contexts);
+        ReturnCode::SUCCESS
+    }
+
+    /// Removes the context with identifier `id`. Returns `EINVAL` if `id` is 0
+    /// or not a valid context identifier.
+    pub fn remove_context(&self, id: u8) -> ReturnCode {
+        if id == 0 || id as usize >= MAX_CONTEXTS {
+            return ReturnCode::EINVAL;
+        }
+        let mut contexts = self.contexts.get();
+        contexts[id as usize] = None;
+        self.contexts.set(contexts);
+        ReturnCode::SUCCESS
+    }
+}
+
+impl ContextStore for ContextTable {
+    /// Returns the context with the longest prefix matching `ip_addr`,
+    /// preferring contexts that may be used for compression.
+    fn get_context_from_addr(&self, ip_addr: IPAddr) -> Option<Context> {
+        let mut best: Option<Context> = None;
+        for ctx in self.contexts.get().iter().filter_map(|ctx| *ctx) {
+            if !util::matches_prefix(&ip_addr.0, &ctx.prefix, ctx.prefix_len) {
+                continue;
+            }
+            let better = match best {
+                None => true,
+                Some(b) => (ctx.compress, ctx.prefix_len) > (b.compress, b.prefix_len),
+            };
+            if better {
+                best = Some(ctx);
+            }
+        }
+        best
+    }
+
+    fn get_context_from_id(&self, ctx_id: u8) -> Option<Context> {
+        if (ctx_id as usize) < MAX_CONTEXTS {
+            self.contexts.get()[ctx_id as usize]
+        } else {
+            None
+        }
+    }
+
+    fn get_context_from_prefix(&self, prefix: &[u8], prefix_len: u8) -> Option<Context> {
+        self.contexts.get().iter().filter_map(|ctx| *ctx).find(|ctx| {
+            ctx.prefix_len == prefix_len && util::matches_prefix(prefix, &ctx.prefix, prefix_len)
+        })
+    }
+}
+
+/// Decides, for each outgoing UDP datagram, whether its checksum may be
+/// elided. RFC 6282, 4.3.2 only permits this when the upper layer agrees and
+/// the datagram is protected by another integrity check, such as the MIC of
+/// a secured link-layer frame. Datagrams that must be fragmented always carry
+/// their checksum inline, whatever the policy.
+pub trait UdpChecksumPolicy {
+    /// `security` is the security level of the frames the datagram is sent
+    /// in, or `None` if they are not secured.
+    fn may_elide_checksum(&self, udp_header: &UDPHeader, security: Option<SecurityLevel>)
+        -> bool;
+}
+
+/// Permits checksum elision for all datagrams sent in frames protected by a
+/// MIC.
+pub struct MicChecksumPolicy;
+
+impl UdpChecksumPolicy for MicChecksumPolicy {
+    fn may_elide_checksum(&self, _: &UDPHeader, security: Option<SecurityLevel>) -> bool {
+        match security {
+            None | Some(SecurityLevel::None) => false,
+            Some(_) => true,
+        }
+    }
+}
+
 pub fn is_lowpan(packet: &[u8]) -> bool {
     (packet[0] & iphc::DISPATCH[0]) == iphc::DISPATCH[0]
 }
@@ -182,12 +320,18 @@ fn nhc_to_ip6_nh(nhc: u8) -> Result<u8, ()> {
 /// bytes consumed from the IPv6 datagram `written` is the number of
 /// compressed header bytes written into `buf`. Payload bytes and
 /// non-compressed next headers are not written, so the remaining `buf.len()
-/// - consumed` bytes must still be copied over to `buf`.
+/// - consumed` bytes must still be copied over to `buf`. Returns `Err(())`
+/// if the compressed header does not fit in `buf`.
+///
+/// The UDP checksum is elided if `checksum_policy` permits it for frames
+/// secured at level `security`, and carried inline otherwise.
 pub fn compress<'a>(
     ctx_store: &ContextStore,
     ip6_packet: &'a IP6Packet<'a>,
     src_mac_addr: MacAddress,
     dst_mac_addr: MacAddress,
+    checksum_policy: Option<&UdpChecksumPolicy>,
+    security: Option<SecurityLevel>,
     mut buf: &mut [u8],
 ) -> Result<(usize, usize), ()> {
     // Note that consumed should be constant, and equal sizeof(IP6Header)
@@ -201,6 +345,7 @@ pub fn compress<'a>(
     let mut written: usize = 2;
 
     // Initialize the LOWPAN_IPHC header
+    check_len(buf.len(), 0, 2)?;
     buf[0..2].copy_from_slice(&iphc::DISPATCH);
 
     let mut src_ctx: Option<Context> = ctx_store.get_context_from_addr(ip6_header.src_addr);
@@ -222,19 +367,19 @@ pub fn compress<'a>(
     dst_ctx = dst_ctx.and_then(|ctx| if ctx.compress { Some(ctx) } else { None });
 
     // Context Identifier Extension
-    compress_cie(&src_ctx, &dst_ctx, &mut buf, &mut written);
+    compress_cie(&src_ctx, &dst_ctx, &mut buf, &mut written)?;
 
     // Traffic Class & Flow Label
-    compress_tf(&ip6_header, &mut buf, &mut written);
+    compress_tf(&ip6_header, &mut buf, &mut written)?;
 
     // Next Header
 
     //let (mut is_nhc, mut nh_len): (bool, u8) = is_ip6_nh_compressible(ip6_packet)?;
     let is_nhc = ip6_header.next_header == ip6_nh::UDP;
-    compress_nh(&ip6_header, is_nhc, &mut buf, &mut written);
+    compress_nh(&ip6_header, is_nhc, &mut buf, &mut written)?;
 
     // Hop Limit
-    compress_hl(&ip6_header, &mut buf, &mut written);
+    compress_hl(&ip6_header, &mut buf, &mut written)?;
 
     // Source Address
     compress_src(
@@ -243,11 +388,11 @@ pub fn compress<'a>(
         &src_ctx,
         &mut buf,
         &mut written,
-    );
+    )?;
 
     // Destination Address
     if ip6_header.dst_addr.is_multicast() {
-        compress_multicast(&ip6_header.dst_addr, &dst_ctx, &mut buf, &mut written);
+        compress_multicast(&ip6_header.dst_addr, &dst_ctx, &mut buf, &mut written)?;
     } else {
         compress_dst(
             &ip6_header.dst_addr,
@@ -255,7 +400,7 @@ pub fn compress<'a>(
             &dst_ctx,
             &mut buf,
             &mut written,
-        );
+        )?;
     }
 
     // Next Headers
@@ -268,12 +413,16 @@ pub fn compress<'a>(
                 let mut nhc_header = nhc::DISPATCH_UDP;
 
                 // Leave a space for the UDP LoWPAN_NHC byte
+                check_len(buf.len(), written, 1)?;
                 let udp_nh_offset = written;
                 written += 1;
 
                 // Compress ports and checksum
-                nhc_header |= compress_udp_ports(&udp_header, &mut buf, &mut written);
-                nhc_header |= compress_udp_checksum(&udp_header, &mut buf, &mut written);
+                nhc_header |= compress_udp_ports(&udp_header, &mut buf, &mut written)?;
+                let elide_checksum = checksum_policy
+                    .map_or(false, |policy| policy.may_elide_checksum(&udp_header, security));
+                nhc_header |=
+                    compress_udp_checksum(&udp_header, elide_checksum, &mut buf, &mut written)?;
 
                 // Write the UDP LoWPAN_NHC byte
                 buf[udp_nh_offset] = nhc_header;
@@ -287,12 +436,22 @@ pub fn compress<'a>(
     Ok((consumed, written))
 }
 
+// Returns an error if `len` bytes starting at `offset` do not fit in a buffer
+// of `buf_len` bytes.
+fn check_len(buf_len: usize, offset: usize, len: usize) -> Result<(), ()> {
+    if offset + len > buf_len {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
 fn compress_cie(
     src_ctx: &Option<Context>,
     dst_ctx: &Option<Context>,
     buf: &mut [u8],
     written: &mut usize,
-) {
+) -> Result<(), ()> {
     let mut cie: u8 = 0;
 
     src_ctx.as_ref().map(|ctx| {
@@ -307,13 +466,15 @@ fn compress_cie(
     });
 
     if cie != 0 {
+        check_len(buf.len(), *written, 1)?;
         buf[1] |= iphc::CID;
         buf[*written] = cie;
         *written += 1;
     }
+    Ok(())
 }
 
-fn compress_tf(ip6_header: &IP6Header, buf: &mut [u8], written: &mut usize) {
+fn compress_tf(ip6_header: &IP6Header, buf: &mut [u8], written: &mut usize) -> Result<(), ()> {
     let ecn = ip6_header.get_ecn();
     let dscp = ip6_header.get_dscp();
     let flow = ip6_header.get_flow_label();
@@ -326,6 +487,7 @@ fn compress_tf(ip6_header: &IP6Header, buf: &mut [u8], written: &mut usize) {
     if dscp == 0 && (ecn == 0 || flow != 0) {
         tf_encoding |= iphc::TF_TRAFFIC_CLASS;
     } else {
+        check_len(buf.len(), *written, 1)?;
         buf[*written] = dscp;
         *written += 1;
     }
@@ -334,6 +496,7 @@ fn compress_tf(ip6_header: &IP6Header, buf: &mut [u8], written: &mut usize) {
     if flow == 0 {
         tf_encoding |= iphc::TF_FLOW_LABEL;
     } else {
+        check_len(buf.len(), *written, 3)?;
         buf[*written] = ((flow >> 16) & 0x0f) as u8;
         buf[*written + 1] = (flow >> 8) as u8;
         buf[*written + 2] = flow as u8;
@@ -344,29 +507,39 @@ fn compress_tf(ip6_header: &IP6Header, buf: &mut [u8], written: &mut usize) {
         buf[old_offset] |= ecn << 6;
     }
     buf[0] |= tf_encoding;
+    Ok(())
 }
 
-fn compress_nh(ip6_header: &IP6Header, is_nhc: bool, buf: &mut [u8], written: &mut usize) {
+fn compress_nh(
+    ip6_header: &IP6Header,
+    is_nhc: bool,
+    buf: &mut [u8],
+    written: &mut usize,
+) -> Result<(), ()> {
     if is_nhc {
         buf[0] |= iphc::NH;
     } else {
+        check_len(buf.len(), *written, 1)?;
         buf[*written] = ip6_header.next_header;
         *written += 1;
     }
+    Ok(())
 }
 
-fn compress_hl(ip6_header: &IP6Header, buf: &mut [u8], written: &mut usize) {
+fn compress_hl(ip6_header: &IP6Header, buf: &mut [u8], written: &mut usize) -> Result<(), ()> {
     let hop_limit_flag = match ip6_header.hop_limit {
         1 => iphc::HLIM_1,
         64 => iphc::HLIM_64,
         255 => iphc::HLIM_255,
         _ => {
+            check_len(buf.len(), *written, 1)?;
             buf[*written] = ip6_header.hop_limit;
             *written += 1;
             iphc::HLIM_INLINE
         }
     };
     buf[0] |= hop_limit_flag;
+    Ok(())
 }
 
 // TODO: We should check to see whether context or link local compression
@@ -378,22 +551,24 @@ fn compress_src(
     src_ctx: &Option<Context>,
     buf: &mut [u8],
     written: &mut usize,
-) {
+) -> Result<(), ()> {
     if src_ip_addr.is_unspecified() {
         // SAC = 1, SAM = 00
         buf[1] |= iphc::SAC;
     } else if src_ip_addr.is_unicast_link_local() {
         // SAC = 0, SAM = 01, 10, 11
-        compress_iid(src_ip_addr, src_mac_addr, true, buf, written);
+        compress_iid(src_ip_addr, src_mac_addr, true, buf, written)?;
     } else if src_ctx.is_some() {
         // SAC = 1, SAM = 01, 10, 11
         buf[1] |= iphc::SAC;
-        compress_iid(src_ip_addr, src_mac_addr, true, buf, written);
+        compress_iid(src_ip_addr, src_mac_addr, true, buf, written)?;
     } else {
         // SAC = 0, SAM = 00
+        check_len(buf.len(), *written, 16)?;
         buf[*written..*written + 16].copy_from_slice(&src_ip_addr.0);
         *written += 16;
     }
+    Ok(())
 }
 
 // TODO: For the SAC = 0, SAM = 11 case in IPv6-encapsulated headers,
@@ -405,7 +580,7 @@ fn compress_iid(
     is_src: bool,
     buf: &mut [u8],
     written: &mut usize,
-) {
+) -> Result<(), ()> {
     let iid: [u8; 8] = compute_iid(mac_addr);
     if ip_addr.0[8..16] == iid {
         // SAM/DAM = 11, 0 bits
@@ -421,6 +596,7 @@ fn compress_iid(
         } else {
             iphc::DAM_MODE2
         };
+        check_len(buf.len(), *written, 2)?;
         buf[*written..*written + 2].copy_from_slice(&ip_addr.0[14..16]);
         *written += 2;
     } else {
@@ -430,9 +606,11 @@ fn compress_iid(
         } else {
             iphc::DAM_MODE1
         };
+        check_len(buf.len(), *written, 8)?;
         buf[*written..*written + 8].copy_from_slice(&ip_addr.0[8..16]);
         *written += 8;
     }
+    Ok(())
 }
 
 // Compresses non-multicast destination address
@@ -445,23 +623,25 @@ fn compress_dst(
     dst_ctx: &Option<Context>,
     buf: &mut [u8],
     written: &mut usize,
-) {
+) -> Result<(), ()> {
     // Assumes dst_ip_addr is not a multicast address (prefix ffXX)
     if dst_ip_addr.is_unicast_link_local() {
         // Link local compression
         // M = 0, DAC = 0, DAM = 01, 10, 11
-        compress_iid(dst_ip_addr, dst_mac_addr, false, buf, written);
+        compress_iid(dst_ip_addr, dst_mac_addr, false, buf, written)?;
     } else if dst_ctx.is_some() {
         // Context compression
         // DAC = 1, DAM = 01, 10, 11
         buf[1] |= iphc::DAC;
-        compress_iid(dst_ip_addr, dst_mac_addr, false, buf, written);
+        compress_iid(dst_ip_addr, dst_mac_addr, false, buf, written)?;
     } else {
         // Full address inline
         // DAC = 0, DAM = 00
+        check_len(buf.len(), *written, 16)?;
         buf[*written..*written + 16].copy_from_slice(&dst_ip_addr.0);
         *written += 16;
     }
+    Ok(())
 }
 
 // Compresses multicast destination addresses
@@ -470,12 +650,13 @@ fn compress_multicast(
     dst_ctx: &Option<Context>,
     buf: &mut [u8],
     written: &mut usize,
-) {
+) -> Result<(), ()> {
     // Assumes dst_ip_addr is indeed a multicast address (prefix ffXX)
     buf[1] |= iphc::MULTICAST;
     if dst_ctx.is_some() {
         // M = 1, DAC = 1, DAM = 00
         buf[1] |= iphc::DAC;
+        check_len(buf.len(), *written, 6)?;
         buf[*written..*written + 2].copy_from_slice(&dst_ip_addr.0[1..3]);
         buf[*written + 2..*written + 6].copy_from_slice(&dst_ip_addr.0[12..16]);
         *written += 6;
@@ -484,32 +665,41 @@ fn compress_multicast(
         if dst_ip_addr.0[1] == 0x02 && dst_ip_addr.0[2..15].iter().all(|&b| b == 0) {
             // DAM = 11
             buf[1] |= iphc::DAM_MODE3;
+            check_len(buf.len(), *written, 1)?;
             buf[*written] = dst_ip_addr.0[15];
             *written += 1;
         } else {
             if !dst_ip_addr.0[2..11].iter().all(|&b| b == 0) {
                 // DAM = 00
                 buf[1] |= iphc::DAM_INLINE;
+                check_len(buf.len(), *written, 16)?;
                 buf[*written..*written + 16].copy_from_slice(&dst_ip_addr.0);
                 *written += 16;
             } else if !dst_ip_addr.0[11..13].iter().all(|&b| b == 0) {
                 // DAM = 01, ffXX::00XX:XXXX:XXXX
                 buf[1] |= iphc::DAM_MODE1;
+                check_len(buf.len(), *written, 6)?;
                 buf[*written] = dst_ip_addr.0[1];
                 buf[*written + 1..*written + 6].copy_from_slice(&dst_ip_addr.0[11..16]);
                 *written += 6;
             } else {
                 // DAM = 10, ffXX::00XX:XXXX
                 buf[1] |= iphc::DAM_MODE2;
+                check_len(buf.len(), *written, 4)?;
                 buf[*written] = dst_ip_addr.0[1];
                 buf[*written + 1..*written + 4].copy_from_slice(&dst_ip_addr.0[13..16]);
                 *written += 4;
             }
         }
     }
+    Ok(())
 }
 
-fn compress_udp_ports(udp_header: &UDPHeader, buf: &mut [u8], written: &mut usize) -> u8 {
+fn compress_udp_ports(
+    udp_header: &UDPHeader,
+    buf: &mut [u8],
+    written: &mut usize,
+) -> Result<u8, ()> {
     // Need to deal with fields in network byte order when writing directly to buf
     let src_port = udp_header.get_src_port().to_be();
     let dst_port = udp_header.get_dst_port().to_be();
@@ -520,6 +710,7 @@ fn compress_udp_ports(udp_header: &UDPHeader, buf: &mut [u8], written: &mut usiz
     {
         // Both can be compressed to 4 bits
         udp_port_nhc |= nhc::UDP_SRC_PORT_FLAG | nhc::UDP_DST_PORT_FLAG;
+        check_len(buf.len(), *written, 1)?;
         // This should compress the ports to a single 8-bit value,
         // with the source port before the destination port
         buf[*written] = (((src_port & !nhc::UDP_4BIT_PORT_MASK) << 4)
@@ -528,15 +719,18 @@ fn compress_udp_ports(udp_header: &UDPHeader, buf: &mut [u8], written: &mut usiz
     } else if (src_port & nhc::UDP_8BIT_PORT_MASK) == nhc::UDP_8BIT_PORT {
         // Source port compressed to 8 bits, destination port uncompressed
         udp_port_nhc |= nhc::UDP_SRC_PORT_FLAG;
+        check_len(buf.len(), *written, 3)?;
         buf[*written] = (src_port & !nhc::UDP_8BIT_PORT_MASK) as u8;
         u16_to_slice(dst_port.to_be(), &mut buf[*written + 1..*written + 3]);
         *written += 3;
     } else if (dst_port & nhc::UDP_8BIT_PORT_MASK) == nhc::UDP_8BIT_PORT {
         udp_port_nhc |= nhc::UDP_DST_PORT_FLAG;
+        check_len(buf.len(), *written, 3)?;
         u16_to_slice(src_port.to_be(), &mut buf[*written..*written + 2]);
-        buf[*written + 3] = (dst_port & !nhc::UDP_8BIT_PORT_MASK) as u8;
+        buf[*written + 2] = (dst_port & !nhc::UDP_8BIT_PORT_MASK) as u8;
         *written += 3;
     } else {
+        check_len(buf.len(), *written, 4)?;
         buf[*written] = src_port as u8;
         buf[*written + 1] = (src_port >> 8) as u8;
         buf[*written + 2] = dst_port as u8;
@@ -544,19 +738,26 @@ fn compress_udp_ports(udp_header: &UDPHeader, buf: &mut [u8], written: &mut usiz
         //buf[*written..*written + 4].copy_from_slice(&udp_header[0..4]);
         *written += 4;
     }
-    return udp_port_nhc;
+    Ok(udp_port_nhc)
 }
 
-// NOTE: We currently only support (or intend to support) carrying the UDP
-// checksum inline.
-fn compress_udp_checksum(udp_header: &UDPHeader, buf: &mut [u8], written: &mut usize) -> u8 {
+fn compress_udp_checksum(
+    udp_header: &UDPHeader,
+    elide: bool,
+    buf: &mut [u8],
+    written: &mut usize,
+) -> Result<u8, ()> {
+    if elide {
+        return Ok(nhc::UDP_CHECKSUM_FLAG);
+    }
     // get_cksum returns cksum in host byte order
     let cksum = udp_header.get_cksum().to_be();
+    check_len(buf.len(), *written, 2)?;
     buf[*written] = cksum as u8;
     buf[*written + 1] = (cksum >> 8) as u8;
     *written += 2;
     // Inline checksum corresponds to the 0 flag
-    0
+    Ok(0)
 }
 
 /// Decompresses a 6loWPAN header into a full IPv6 header
@@ -599,6 +800,10 @@ fn compress_udp_checksum(udp_header: &UDPHeader, buf: &mut [u8], written: &mut u
 ///
 /// * `written` is the number of uncompressed header bytes written into
 /// `out_buf`.
+///
+/// `Err(())` is returned if the header is invalid, if `buf` ends before the
+/// compressed header does, or if the uncompressed header does not fit in
+/// `out_buf`.
 pub fn decompress(
     ctx_store: &ContextStore,
     buf: &[u8],
@@ -609,6 +814,8 @@ pub fn decompress(
     is_fragment: bool,
 ) -> Result<(usize, usize), ()> {
     // Get the LOWPAN_IPHC header (the first two bytes are the header)
+    check_len(buf.len(), 0, 2)?;
+    check_len(out_buf.len(), 0, mem::size_of::<IP6Header>())?;
     let iphc_header_1: u8 = buf[0];
     let iphc_header_2: u8 = buf[1];
     let mut consumed: usize = 2;
@@ -620,10 +827,10 @@ pub fn decompress(
     let (src_ctx, dst_ctx) = decompress_cie(ctx_store, iphc_header_1, &buf, &mut consumed)?;
 
     // Traffic Class & Flow Label
-    decompress_tf(&mut ip6_header, iphc_header_1, &buf, &mut consumed);
+    decompress_tf(&mut ip6_header, iphc_header_1, &buf, &mut consumed)?;
 
     // Next Header
-    let (mut is_nhc, mut next_header) = decompress_nh(iphc_header_1, &buf, &mut consumed);
+    let (mut is_nhc, mut next_header) = decompress_nh(iphc_header_1, &buf, &mut consumed)?;
 
     // Hop Limit
     decompress_hl(&mut ip6_header, iphc_header_1, &buf, &mut consumed)?;
@@ -661,6 +868,7 @@ pub fn decompress(
     // next_header is already set if is_nhc is false, otherwise it can be
     // determined from the LoWPAN NHC header byte
     if is_nhc {
+        check_len(buf.len(), consumed, 1)?;
         next_header = nhc_to_ip6_nh(buf[consumed])?;
     }
     ip6_header.set_next_header(next_header);
@@ -670,6 +878,7 @@ pub fn decompress(
     // next header in buf.
     while is_nhc {
         // Advance past the LoWPAN NHC byte
+        check_len(buf.len(), consumed, 1)?;
         let nhc_header = buf[consumed];
         consumed += 1;
 
@@ -695,14 +904,14 @@ pub fn decompress(
                 // UDP length includes UDP header and data in bytes
                 // Below line works bc udp nh must be last nh per 6282
                 let mut udp_length = if is_fragment {
-                    dgram_size - written as u16
+                    dgram_size.checked_sub(written as u16).ok_or(())?
                 } else {
                     buf.len() as u16 - consumed as u16
                 };
 
                 // Decompress UDP header fields
                 let consumed_before_port_decompress = consumed;
-                let (src_port, dst_port) = decompress_udp_ports(nhc_header, &buf, &mut consumed);
+                let (src_port, dst_port) = decompress_udp_ports(nhc_header, &buf, &mut consumed)?;
 
                 //need to add any growth from decompression to the udp length if we used the buf
                 //len to calculate the length
@@ -724,6 +933,7 @@ pub fn decompress(
                 // to_be(), because src_port.to_be() returns the src_port in little endian..
                 // Accordingly, the udp_length must also be written in little endian for this
                 // to work.
+                check_len(next_headers.len(), 0, 8)?;
                 u16_to_slice(src_port.to_be(), &mut next_headers[0..2]);
                 u16_to_slice(dst_port.to_be(), &mut next_headers[2..4]);
                 u16_to_slice(udp_length, &mut next_headers[4..6]);
@@ -736,7 +946,7 @@ pub fn decompress(
                     &buf,
                     &mut consumed,
                     is_fragment,
-                );
+                )?;
                 u16_to_slice(udp_checksum.to_be(), &mut next_headers[6..8]);
 
                 written += 8;
@@ -751,13 +961,16 @@ pub fn decompress(
                 is_nhc = (nhc_header & nhc::NH) != 0;
 
                 // len is the number of octets following the length field
+                check_len(buf.len(), consumed, 1)?;
                 let len = buf[consumed] as usize;
                 consumed += 1;
 
                 // Check that there is a next header in the buffer,
                 // which must be the case if the last next header specifies
                 // NH = 1
-                if consumed + len >= buf.len() {
+                // An extension header is at least 8 octets long, 6 of which
+                // follow the next header and length fields
+                if consumed + len >= buf.len() || len < 6 {
                     return Err(());
                 }
 
@@ -780,6 +993,7 @@ pub fn decompress(
                 };
 
                 // Fill in the extended header in uncompressed IPv6 format
+                check_len(next_headers.len(), 0, 8 + hdr_len_field * 8)?;
                 next_headers[0] = next_header;
                 next_headers[1] = hdr_len_field as u8;
                 // Copies over the remaining options.
@@ -810,7 +1024,9 @@ pub fn decompress(
     // including extension headers. This is thus the uncompressed
     // size of the IPv6 packet - the fixed IPv6 header.
     let payload_len = if is_fragment {
-        (dgram_size as usize) - mem::size_of::<IP6Header>()
+        (dgram_size as usize)
+            .checked_sub(mem::size_of::<IP6Header>())
+            .ok_or(())?
     } else {
         written + (buf.len() - consumed) - mem::size_of::<IP6Header>()
     };
@@ -828,6 +1044,7 @@ fn decompress_cie(
     let ctx_0 = ctx_store.get_context_0();
     let (mut src_ctx, mut dst_ctx) = (ctx_0, ctx_0);
     if iphc_header & iphc::CID != 0 {
+        check_len(buf.len(), *consumed, 1)?;
         let sci = buf[*consumed] >> 4;
         let dci = buf[*consumed] & 0xf;
         *consumed += 1;
@@ -842,9 +1059,21 @@ fn decompress_cie(
     Ok((src_ctx, dst_ctx))
 }
 
-fn decompress_tf(ip6_header: &mut IP6Header, iphc_header: u8, buf: &[u8], consumed: &mut usize) {
+fn decompress_tf(
+    ip6_header: &mut IP6Header,
+    iphc_header: u8,
+    buf: &[u8],
+    consumed: &mut usize,
+) -> Result<(), ()> {
     let fl_compressed = (iphc_header & iphc::TF_FLOW_LABEL) != 0;
     let tc_compressed = (iphc_header & iphc::TF_TRAFFIC_CLASS) != 0;
+    let inline_len = match (tc_compressed, fl_compressed) {
+        (true, true) => 0,
+        (false, true) => 1,
+        (true, false) => 3,
+        (false, false) => 4,
+    };
+    check_len(buf.len(), *consumed, inline_len)?;
 
     // Determine ECN and DSCP separately because the order is different
     // from the IPv6 traffic class field.
@@ -869,16 +1098,18 @@ fn decompress_tf(ip6_header: &mut IP6Header, iphc_header: u8, buf: &[u8], consum
         *consumed += 3;
         ip6_header.set_flow_label(flow);
     }
+    Ok(())
 }
 
-fn decompress_nh(iphc_header: u8, buf: &[u8], consumed: &mut usize) -> (bool, u8) {
+fn decompress_nh(iphc_header: u8, buf: &[u8], consumed: &mut usize) -> Result<(bool, u8), ()> {
     let is_nhc = (iphc_header & iphc::NH) != 0;
     let mut next_header: u8 = 0;
     if !is_nhc {
+        check_len(buf.len(), *consumed, 1)?;
         next_header = buf[*consumed];
         *consumed += 1;
     }
-    return (is_nhc, next_header);
+    Ok((is_nhc, next_header))
 }
 
 fn decompress_hl(
@@ -892,6 +1123,7 @@ fn decompress_hl(
         iphc::HLIM_64 => 64,
         iphc::HLIM_255 => 255,
         iphc::HLIM_INLINE => {
+            check_len(buf.len(), *consumed, 1)?;
             let hl = buf[*consumed];
             *consumed += 1;
             hl
@@ -983,6 +1215,7 @@ fn decompress_multicast(
                     // compression is invalid.
                     return Err(());
                 }
+                check_len(buf.len(), *consumed, 6)?;
                 ip_addr.0[0] = 0xff;
                 ip_addr.0[1] = buf[*consumed];
                 ip_addr.0[2] = buf[*consumed + 1];
@@ -1000,12 +1233,14 @@ fn decompress_multicast(
         match dam_mode {
             // DAC = 0, DAM = 00: Inline
             iphc::DAM_INLINE => {
+                check_len(buf.len(), *consumed, 16)?;
                 ip_addr.0.copy_from_slice(&buf[*consumed..*consumed + 16]);
                 *consumed += 16;
             }
             // DAC = 0, DAM = 01: 48 bits
             // ffXX::00XX:XXXX:XXXX
             iphc::DAM_MODE1 => {
+                check_len(buf.len(), *consumed, 6)?;
                 ip_addr.0[0] = 0xff;
                 ip_addr.0[1] = buf[*consumed];
                 *consumed += 1;
@@ -1015,6 +1250,7 @@ fn decompress_multicast(
             // DAC = 0, DAM = 10: 32 bits
             // ffXX::00XX:XXXX
             iphc::DAM_MODE2 => {
+                check_len(buf.len(), *consumed, 4)?;
                 ip_addr.0[0] = 0xff;
                 ip_addr.0[1] = buf[*consumed];
                 *consumed += 1;
@@ -1024,6 +1260,7 @@ fn decompress_multicast(
             // DAC = 0, DAM = 11: 8 bits
             // ff02::00XX
             iphc::DAM_MODE3 => {
+                check_len(buf.len(), *consumed, 1)?;
                 ip_addr.0[0] = 0xff;
                 ip_addr.0[1] = 0x02;
                 ip_addr.0[15] = buf[*consumed];
@@ -1047,12 +1284,14 @@ fn decompress_iid_link_local(
         // SAM, DAM = 00: Inline
         iphc::SAM_INLINE => {
             // SAM_INLINE is equivalent to DAM_INLINE
+            check_len(buf.len(), *consumed, 16)?;
             ip_addr.0.copy_from_slice(&buf[*consumed..*consumed + 16]);
             *consumed += 16;
         }
         // SAM, DAM = 01: 64 bits
         // Link-local prefix (64 bits) + 64 bits carried inline
         iphc::SAM_MODE1 | iphc::DAM_MODE1 => {
+            check_len(buf.len(), *consumed, 8)?;
             ip_addr.set_unicast_link_local();
             ip_addr.0[8..16].copy_from_slice(&buf[*consumed..*consumed + 8]);
             *consumed += 8;
@@ -1060,6 +1299,7 @@ fn decompress_iid_link_local(
         // SAM, DAM = 11: 16 bits
         // Link-local prefix (112 bits) + 0000:00ff:fe00:XXXX
         iphc::SAM_MODE2 | iphc::DAM_MODE2 => {
+            check_len(buf.len(), *consumed, 2)?;
             ip_addr.set_unicast_link_local();
             ip_addr.0[11..13].copy_from_slice(&iphc::MAC_BASE[3..5]);
             ip_addr.0[14..16].copy_from_slice(&buf[*consumed..*consumed + 2]);
@@ -1094,12 +1334,14 @@ fn decompress_iid_context(
         // SAM, DAM = 01: 64 bits
         // Suffix is the 64 bits carried inline
         iphc::SAM_MODE1 | iphc::DAM_MODE1 => {
+            check_len(buf.len(), *consumed, 8)?;
             ip_addr.0[8..16].copy_from_slice(&buf[*consumed..*consumed + 8]);
             *consumed += 8;
         }
         // SAM, DAM = 10: 16 bits
         // Suffix is 0000:00ff:fe00:XXXX
         iphc::SAM_MODE2 | iphc::DAM_MODE2 => {
+            check_len(buf.len(), *consumed, 2)?;
             ip_addr.0[8..16].copy_from_slice(&iphc::MAC_BASE);
             ip_addr.0[14..16].copy_from_slice(&buf[*consumed..*consumed + 2]);
             *consumed += 2;
@@ -1119,9 +1361,19 @@ fn decompress_iid_context(
 }
 
 // Returns the UDP ports in host byte-order
-fn decompress_udp_ports(udp_nhc: u8, buf: &[u8], consumed: &mut usize) -> (u16, u16) {
+fn decompress_udp_ports(
+    udp_nhc: u8,
+    buf: &[u8],
+    consumed: &mut usize,
+) -> Result<(u16, u16), ()> {
     let src_compressed = (udp_nhc & nhc::UDP_SRC_PORT_FLAG) != 0;
     let dst_compressed = (udp_nhc & nhc::UDP_DST_PORT_FLAG) != 0;
+    let inline_len = match (src_compressed, dst_compressed) {
+        (true, true) => 1,
+        (true, false) | (false, true) => 3,
+        (false, false) => 4,
+    };
+    check_len(buf.len(), *consumed, inline_len)?;
 
     let src_port;
     let dst_port;
@@ -1150,7 +1402,7 @@ fn decompress_udp_ports(udp_nhc: u8, buf: &[u8], consumed: &mut usize) -> (u16,
         dst_port = u16::from_be(slice_to_u16(&buf[*consumed + 2..*consumed + 4]));
         *consumed += 4;
     }
-    (src_port, dst_port)
+    Ok((src_port, dst_port))
 }
 
 // Returns the UDP checksum in host byte-order
@@ -1162,7 +1414,7 @@ fn decompress_udp_checksum(
     buf: &[u8],
     consumed: &mut usize,
     is_fragment: bool,
-) -> u16 {
+) -> Result<u16, ()> {
     // TODO: In keeping with Postel's Law, we accept UDP packets that elide the
     // checksum (per RFC 6282). We are not sure if we should continue to support
     // this feature however.
@@ -1171,7 +1423,7 @@ fn decompress_udp_checksum(
     if (udp_nhc & nhc::UDP_CHECKSUM_FLAG) != 0 && !is_fragment {
         let mut udp_header_copy: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 0];
         udp_header_copy.copy_from_slice(udp_header);
-        match UDPHeader::decode(&udp_header_copy).done() {
+        Ok(match UDPHeader::decode(&udp_header_copy).done() {
             Some((_offset, hdr)) => u16::from_be(compute_udp_checksum(
                 ip6_header,
                 &hdr,
@@ -1179,10 +1431,95 @@ fn decompress_udp_checksum(
                 &buf[*consumed..],
             )),
             None => 0, //Will be dropped  by IP layer
-        }
+        })
     } else {
+        check_len(buf.len(), *consumed, 2)?;
         let checksum = u16::from_be(slice_to_u16(&buf[*consumed..*consumed + 2]));
         *consumed += 2;
-        checksum
+        Ok(checksum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use net::ipv6::ipv6::IPPayload;
+
+    const SRC_MAC: MacAddress = MacAddress::Short(0x1234);
+    const DST_MAC: MacAddress = MacAddress::Short(0x5678);
+
+    const NO_CONTEXT: Context = Context {
+        prefix: [0; 16],
+        prefix_len: 0,
+        id: 0,
+        compress: false,
+    };
+
+    // Compresses a header between link-local addresses derived from the MAC
+    // addresses with the given hop limit, DSCP and ECN, and returns the
+    // header that decompressing it yields, along with the compressed length.
+    fn round_trip(hop_limit: u8, dscp: u8, ecn: u8) -> (IP6Header, usize) {
+        let mut payload = [0u8; 0];
+        let mut packet = IP6Packet::new(IPPayload::new(
+            TransportHeader::UDP(UDPHeader::new()),
+            &mut payload,
+        ));
+        packet.header.src_addr = IPAddr::generate_from_mac(SRC_MAC);
+        packet.header.dst_addr = IPAddr::generate_from_mac(DST_MAC);
+        packet.header.set_hop_limit(hop_limit);
+        packet.header.set_dscp(dscp);
+        packet.header.set_ecn(ecn);
+
+        let mut compressed = [0u8; 64];
+        let (consumed, written) = compress(
+            &NO_CONTEXT,
+            &packet,
+            SRC_MAC,
+            DST_MAC,
+            None,
+            None,
+            &mut compressed,
+        ).expect("compression failed");
+        assert_eq!(consumed, mem::size_of::<IP6Header>());
+
+        let mut decompressed = [0u8; 64];
+        let dgram_size = mem::size_of::<IP6Header>() as u16;
+        decompress(
+            &NO_CONTEXT,
+            &compressed[..written],
+            SRC_MAC,
+            DST_MAC,
+            &mut decompressed,
+            dgram_size,
+            false,
+        ).expect("decompression failed");
+        let (_, header) = IP6Header::decode(&decompressed)
+            .done()
+            .expect("invalid IPv6 header");
+        (header, written)
+    }
+
+    #[test]
+    fn hop_limit_and_traffic_class_survive_compression() {
+        // Hop limit 1 has a compressed encoding, and the traffic class is
+        // carried inline.
+        let (header, _) = round_trip(1, 46, 1);
+        assert_eq!(header.get_hop_limit(), 1);
+        assert_eq!(header.get_dscp(), 46);
+        assert_eq!(header.get_ecn(), 1);
+        assert_eq!(header.get_src_addr(), IPAddr::generate_from_mac(SRC_MAC));
+        assert_eq!(header.get_dst_addr(), IPAddr::generate_from_mac(DST_MAC));
+    }
+
+    #[test]
+    fn uncompressible_hop_limit_is_carried_inline() {
+        let (header, written) = round_trip(17, 0, 0);
+        assert_eq!(header.get_hop_limit(), 17);
+        assert_eq!(header.get_dscp(), 0);
+        assert_eq!(header.get_ecn(), 0);
+
+        // Only the hop limit and the next header are not elided.
+        let (_, default_written) = round_trip(64, 0, 0);
+        assert_eq!(written, default_written + 1);
     }
 }
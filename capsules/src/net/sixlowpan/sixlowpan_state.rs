@@ -37,6 +37,12 @@
 //! [SixlowpanRxClient](trait.SixlowpanRxClient.html) trait, which is called
 //! after a packet is fully received.
 //!
+//! Frames that carry an RFC 4944 mesh addressing header are first passed to
+//! the [SixlowpanMeshClient](trait.SixlowpanMeshClient.html), if one is set,
+//! which decides whether this node consumes the frame, forwards it or drops
+//! it. Without a client, frames whose final destination is this node are
+//! consumed and all others are dropped.
+//!
 //! At a high level, clients interact with this module as shown in the diagrams
 //! below:
 //!
@@ -235,18 +241,37 @@ use kernel::hil::time;
 use kernel::hil::time::Frequency;
 use kernel::ReturnCode;
 use net::frag_utils::Bitmap;
-use net::ieee802154::{Header, KeyId, MacAddress, PanID, SecurityLevel};
-use net::ipv6::ipv6::IP6Packet;
+use net::ieee802154::{AddressMode, Header, KeyId, MacAddress, PanID, SecurityLevel};
+use net::ipv6::ipv6::{IP6Packet, IP6_HDR_LEN};
 use net::sixlowpan::sixlowpan_compression;
-use net::sixlowpan::sixlowpan_compression::{is_lowpan, ContextStore};
+use net::sixlowpan::sixlowpan_compression::{is_lowpan, ContextStore, UdpChecksumPolicy};
+use net::stream::{encode_u8, SResult};
 use net::util::{slice_to_u16, u16_to_slice};
 
-// Reassembly timeout in seconds
-const FRAG_TIMEOUT: u32 = 60;
+/// Default reassembly timeout in seconds
+pub const DEFAULT_FRAG_TIMEOUT: u32 = 60;
+
+/// Counts of the reasons packet reassemblies failed, for diagnosing lost
+/// fragments. The counts wrap around on overflow.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ReassemblyStats {
+    /// Partially reassembled packets dropped by the reassembly timeout
+    pub timeouts: u32,
+    /// Packets dropped because a fragment overlapped one already received
+    pub overlaps: u32,
+    /// Datagrams or fragments rejected for extending past the datagram size
+    /// or the reassembly buffer
+    pub out_of_bounds: u32,
+    /// Reassembled packets dropped because their IPv6 header did not match
+    /// the datagram that was received
+    pub invalid_headers: u32,
+}
 
 /// Objects that implement this trait can set themselves to be the client
 /// for the [Sixlowpan](struct.Sixlowpan.html) struct, and will then receive
-/// a callback once an IPv6 packet has been fully reassembled.
+/// a callback once an IPv6 packet has been fully reassembled. A `result` of
+/// `ReturnCode::EINVAL` indicates that the packet was reassembled, but its
+/// IPv6 header is malformed or disagrees with the number of bytes received.
 pub trait SixlowpanRxClient {
     fn receive<'a>(&self, buf: &'a [u8], len: usize, result: ReturnCode);
 }
@@ -295,9 +320,104 @@ fn is_fragment(packet: &[u8]) -> bool {
     (mask == lowpan_frag::FRAGN_HDR) || (mask == lowpan_frag::FRAG1_HDR)
 }
 
+pub mod lowpan_mesh {
+    pub const DISPATCH: u8 = 0b10000000;
+    pub const DISPATCH_MASK: u8 = 0b11000000;
+    pub const ORIGINATOR_SHORT: u8 = 0b00100000;
+    pub const FINAL_SHORT: u8 = 0b00010000;
+    pub const HOPS_LEFT_MASK: u8 = 0b00001111;
+}
+
+fn is_mesh(packet: &[u8]) -> bool {
+    packet.len() > 0 && (packet[0] & lowpan_mesh::DISPATCH_MASK) == lowpan_mesh::DISPATCH
+}
+
+/// The RFC 4944 mesh addressing header, which carries the link-layer
+/// addresses of the originator and the final destination of a frame that is
+/// forwarded over multiple hops.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct MeshHeader {
+    /// Remaining number of hops, at most 14. A node forwarding the frame
+    /// decrements this and drops the frame once it reaches 0.
+    pub hops_left: u8,
+    pub originator: MacAddress,
+    pub final_dest: MacAddress,
+}
+
+impl MeshHeader {
+    pub fn encode(&self, buf: &mut [u8]) -> SResult {
+        // A hops left value of 0xf is reserved for an extended hops left
+        // field, which is not supported.
+        stream_cond!(self.hops_left < lowpan_mesh::HOPS_LEFT_MASK);
+        let mut dispatch = lowpan_mesh::DISPATCH | self.hops_left;
+        if let MacAddress::Short(_) = self.originator {
+            dispatch |= lowpan_mesh::ORIGINATOR_SHORT;
+        }
+        if let MacAddress::Short(_) = self.final_dest {
+            dispatch |= lowpan_mesh::FINAL_SHORT;
+        }
+        let off = enc_consume!(buf; encode_u8, dispatch);
+        let off = enc_consume!(buf, off; self.originator; encode);
+        let off = enc_consume!(buf, off; self.final_dest; encode);
+        stream_done!(off);
+    }
+
+    pub fn decode(buf: &[u8]) -> SResult<MeshHeader> {
+        stream_len_cond!(buf, 1);
+        let dispatch = buf[0];
+        stream_cond!(dispatch & lowpan_mesh::DISPATCH_MASK == lowpan_mesh::DISPATCH);
+        let hops_left = dispatch & lowpan_mesh::HOPS_LEFT_MASK;
+        stream_cond!(hops_left < lowpan_mesh::HOPS_LEFT_MASK);
+
+        let address_mode = |short| {
+            if short {
+                AddressMode::Short
+            } else {
+                AddressMode::Long
+            }
+        };
+        let originator_mode = address_mode(dispatch & lowpan_mesh::ORIGINATOR_SHORT != 0);
+        let final_mode = address_mode(dispatch & lowpan_mesh::FINAL_SHORT != 0);
+        let (off, originator) = dec_try!(buf, 1; MacAddress::decode, originator_mode);
+        let (off, final_dest) = dec_try!(buf, off; MacAddress::decode, final_mode);
+        stream_done!(
+            off,
+            MeshHeader {
+                hops_left: hops_left,
+                originator: stream_from_option!(originator),
+                final_dest: stream_from_option!(final_dest),
+            }
+        );
+    }
+}
+
+/// What the Sixlowpan layer should do with a received mesh-addressed frame.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum MeshAction {
+    /// This node is the final destination; decompress and reassemble the
+    /// frame as usual.
+    Consume,
+    /// The frame is not for this node. It has been handed off for forwarding
+    /// and should not be processed further.
+    Forward,
+    /// The frame is not for this node and will not be forwarded, for example
+    /// because no hops are left.
+    Drop,
+}
+
+/// Objects that implement this trait decide whether mesh-addressed frames
+/// are consumed by this node or forwarded towards their final destination.
+pub trait SixlowpanMeshClient {
+    /// Called for each received frame with a mesh header. `payload` is the
+    /// remainder of the frame after the mesh header. A client that forwards
+    /// the frame must copy `payload` before returning.
+    fn mesh_frame(&self, header: MeshHeader, payload: &[u8]) -> MeshAction;
+}
+
 pub trait SixlowpanState<'a> {
     fn next_dgram_tag(&self) -> u16;
     fn get_ctx_store(&self) -> &ContextStore;
+    fn get_checksum_policy(&self) -> Option<&'a UdpChecksumPolicy>;
     fn add_rx_state(&self, rx_state: &'a RxState<'a>);
     fn set_rx_client(&'a self, client: &'a SixlowpanRxClient);
 }
@@ -320,6 +440,9 @@ pub struct TxState<'a> {
     dgram_tag: Cell<u16>, // Used to identify particular fragment streams
     dgram_size: Cell<u16>,
     dgram_offset: Cell<usize>,
+    /// Offset of the fragment most recently prepared, to which
+    /// `retry_fragment` rewinds.
+    frag_offset: Cell<usize>,
 
     busy: Cell<bool>,
     // We need a reference to sixlowpan to compute and increment
@@ -347,6 +470,7 @@ impl TxState<'a> {
             dgram_tag: Cell::new(0),
             dgram_size: Cell::new(0),
             dgram_offset: Cell::new(0),
+            frag_offset: Cell::new(0),
 
             busy: Cell::new(false),
             sixlowpan: sixlowpan,
@@ -429,6 +553,11 @@ impl TxState<'a> {
         if !self.busy.get() {
             let frame = self.start_transmit(ip6_packet, frame, self.sixlowpan.get_ctx_store())?;
             Ok((false, frame))
+        } else if self.dgram_offset.get() == 0 {
+            // The first fragment is being retried
+            let frame =
+                self.prepare_first_fragment(ip6_packet, frame, self.sixlowpan.get_ctx_store())?;
+            Ok((false, frame))
         } else if self.is_transmit_done() {
             self.end_transmit();
             Ok((true, frame))
@@ -445,6 +574,19 @@ impl TxState<'a> {
         }
     }
 
+    /// Rewinds to the fragment most recently returned by `next_fragment`, so
+    /// that the next call to `next_fragment` prepares it again. This is used
+    /// to retry a fragment whose transmission failed.
+    pub fn retry_fragment(&self) {
+        self.dgram_offset.set(self.frag_offset.get());
+    }
+
+    /// Abandons the packet being sent, so that `init` can be called for a new
+    /// packet.
+    pub fn abort(&self) {
+        self.end_transmit();
+    }
+
     fn is_transmit_done(&self) -> bool {
         self.dgram_size.get() as usize <= self.dgram_offset.get()
     }
@@ -472,26 +614,41 @@ impl TxState<'a> {
         // Here, we assume that the compressed headers fit in the first MTU
         // fragment. This is consistent with RFC 6282.
         let mut lowpan_packet = [0 as u8; radio::MAX_FRAME_SIZE as usize];
-        let (consumed, written) = {
-            match sixlowpan_compression::compress(
+        let security = self.security.get().map(|(level, _)| level);
+        let compress = |checksum_policy, buf: &mut [u8]| {
+            sixlowpan_compression::compress(
                 ctx_store,
                 ip6_packet,
                 self.src_mac_addr.get(),
                 self.dst_mac_addr.get(),
-                &mut lowpan_packet,
-            ) {
-                Err(_) => return Err((ReturnCode::FAIL, frame.into_buf())),
-                Ok(result) => result,
-            }
+                checksum_policy,
+                security,
+                buf,
+            )
         };
 
-        let remaining_payload = ip6_packet.get_total_len() as usize - consumed;
-        let lowpan_len = written + remaining_payload;
-
         // TODO: This -2 is added to account for the FCS; this should be changed
         // in the MAC code
         let mut remaining_capacity = frame.remaining_data_capacity() - 2;
 
+        let checksum_policy = self.sixlowpan.get_checksum_policy();
+        let mut result = compress(checksum_policy, &mut lowpan_packet);
+        // Receivers cannot recompute an elided checksum from the first
+        // fragment alone, so fragmented datagrams carry it inline.
+        if let Ok((consumed, written)) = result {
+            let lowpan_len = written + ip6_packet.get_total_len() as usize - consumed;
+            if lowpan_len > remaining_capacity && checksum_policy.is_some() {
+                result = compress(None, &mut lowpan_packet);
+            }
+        }
+        let (consumed, written) = match result {
+            Err(_) => return Err((ReturnCode::FAIL, frame.into_buf())),
+            Ok(result) => result,
+        };
+
+        let remaining_payload = ip6_packet.get_total_len() as usize - consumed;
+        let lowpan_len = written + remaining_payload;
+
         // Need to fragment
         if lowpan_len > remaining_capacity {
             remaining_capacity -= self.write_frag_hdr(&mut frame, true);
@@ -513,11 +670,14 @@ impl TxState<'a> {
         } else {
             remaining_payload
         };
-        // TODO: Check success
         let (payload_len, consumed) =
-            self.write_additional_headers(ip6_packet, &mut frame, consumed, payload_len);
+            match self.write_additional_headers(ip6_packet, &mut frame, consumed, payload_len) {
+                Ok(result) => result,
+                Err(rc) => return Err((rc, frame.into_buf())),
+            };
 
         frame.append_payload(&ip6_packet.get_payload()[0..payload_len]);
+        self.frag_offset.set(0);
         self.dgram_offset.set(consumed + payload_len);
         Ok(frame)
     }
@@ -541,7 +701,10 @@ impl TxState<'a> {
         };
 
         let (payload_len, dgram_offset) =
-            self.write_additional_headers(ip6_packet, &mut frame, dgram_offset, payload_len);
+            match self.write_additional_headers(ip6_packet, &mut frame, dgram_offset, payload_len) {
+                Ok(result) => result,
+                Err(rc) => return Err((rc, frame.into_buf())),
+            };
 
         if payload_len > 0 {
             let payload_offset = dgram_offset - ip6_packet.get_total_hdr_size();
@@ -551,35 +714,27 @@ impl TxState<'a> {
         }
 
         // Update the offset to be used for the next fragment
+        self.frag_offset.set(self.dgram_offset.get());
         self.dgram_offset.set(dgram_offset + payload_len);
         Ok(frame)
     }
 
-    // NOTE: This function will not work for headers that span past the first
-    // frame.
+    // Writes the uncompressed headers that fall in this fragment, i.e. those
+    // that 6LoWPAN compression did not consume. Returns ENOSUPPORT if the
+    // packet's transport header cannot be serialized.
     fn write_additional_headers<'b>(
         &self,
         ip6_packet: &'b IP6Packet<'b>,
         frame: &mut Frame,
         dgram_offset: usize,
         payload_len: usize,
-    ) -> (usize, usize) {
-        let total_hdr_len = ip6_packet.get_total_hdr_size();
-        let mut payload_len = payload_len;
-        let mut dgram_offset = dgram_offset;
-        if total_hdr_len > dgram_offset {
-            let headers_to_write = min(payload_len, total_hdr_len - dgram_offset);
-            // TODO: Note that in order to serialize the headers, we need to
-            // statically allocate room on the stack. However, we do not know
-            // how many additional headers we have until runtime. This
-            // functionality should be fixed in the future.
-            let mut headers = [0 as u8; 60];
-            ip6_packet.encode(&mut headers);
-            frame.append_payload(&mut headers[dgram_offset..dgram_offset + headers_to_write]);
-            payload_len -= headers_to_write;
-            dgram_offset += headers_to_write;
+    ) -> Result<(usize, usize), ReturnCode> {
+        match ip6_packet.encode_headers(dgram_offset, payload_len, |headers| {
+            frame.append_payload(headers);
+        }) {
+            SResult::Done(written, ()) => Ok((payload_len - written, dgram_offset + written)),
+            _ => Err(ReturnCode::ENOSUPPORT),
         }
-        (payload_len, dgram_offset)
     }
 
     fn write_frag_hdr(&self, frame: &mut Frame, first_frag: bool) -> usize {
@@ -624,6 +779,8 @@ impl TxState<'a> {
 /// two `RxState`s are sufficient for normal-case operation.
 pub struct RxState<'a> {
     packet: TakeCell<'static, [u8]>,
+    // Length of `packet`, which bounds the datagrams this state can reassemble.
+    packet_len: usize,
     bitmap: MapCell<Bitmap>,
     dst_mac_addr: Cell<MacAddress>,
     src_mac_addr: Cell<MacAddress>,
@@ -634,6 +791,7 @@ pub struct RxState<'a> {
     busy: Cell<bool>,
     // The time when packet reassembly started for the current packet.
     start_time: Cell<u32>,
+    stats: Cell<ReassemblyStats>,
 
     next: ListLink<'a, RxState<'a>>,
 }
@@ -649,10 +807,12 @@ impl RxState<'a> {
     ///
     /// # Arguments
     ///
-    /// `packet` - A buffer for reassembling an IPv6 packet. Currently, we
-    /// assume this to be 1280 bytes long (the minimum IPv6 MTU size).
+    /// `packet` - A buffer for reassembling an IPv6 packet. Datagrams larger
+    /// than this buffer are rejected, so it should normally be at least 1280
+    /// bytes long (the minimum IPv6 MTU size).
     pub fn new(packet: &'static mut [u8]) -> RxState<'a> {
         RxState {
+            packet_len: packet.len(),
             packet: TakeCell::new(packet),
             bitmap: MapCell::new(Bitmap::new()),
             dst_mac_addr: Cell::new(MacAddress::Short(0)),
@@ -661,10 +821,22 @@ impl RxState<'a> {
             dgram_size: Cell::new(0),
             busy: Cell::new(false),
             start_time: Cell::new(0),
+            stats: Cell::new(ReassemblyStats::default()),
             next: ListLink::empty(),
         }
     }
 
+    /// Returns the reassembly failures seen by this state.
+    pub fn stats(&self) -> ReassemblyStats {
+        self.stats.get()
+    }
+
+    fn count<F: FnOnce(&mut ReassemblyStats)>(&self, f: F) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+        self.stats.set(stats);
+    }
+
     fn is_my_fragment(
         &self,
         src_mac_addr: MacAddress,
@@ -681,14 +853,21 @@ impl RxState<'a> {
 
     // Checks if a given RxState is free or expired (and thus, can be freed).
     // This function implements the reassembly timeout for 6LoWPAN lazily.
-    fn is_busy(&self, frequency: u32, current_time: u32) -> bool {
-        let expired = current_time >= (self.start_time.get() + FRAG_TIMEOUT * frequency);
+    // The elapsed time is computed modulo the width of the clock, whose
+    // largest value is `max_tics`, so that the timeout is also correct if the
+    // clock wraps around during reassembly.
+    fn is_busy(&self, current_time: u32, timeout_tics: u32, max_tics: u32) -> bool {
+        let elapsed = current_time.wrapping_sub(self.start_time.get()) & max_tics;
+        let expired = self.busy.get() && elapsed >= timeout_tics;
         if expired {
+            self.count(|stats| stats.timeouts = stats.timeouts.wrapping_add(1));
             self.end_receive(None, ReturnCode::FAIL);
         }
         self.busy.get()
     }
 
+    // Claims this state for a new datagram. Returns `ESIZE`, leaving the state
+    // free, if the datagram would not fit in the packet buffer.
     fn start_receive(
         &self,
         src_mac_addr: MacAddress,
@@ -696,7 +875,11 @@ impl RxState<'a> {
         dgram_size: u16,
         dgram_tag: u16,
         current_tics: u32,
-    ) {
+    ) -> ReturnCode {
+        if dgram_size as usize > self.packet_len {
+            self.count(|stats| stats.out_of_bounds = stats.out_of_bounds.wrapping_add(1));
+            return ReturnCode::ESIZE;
+        }
         self.dst_mac_addr.set(dst_mac_addr);
         self.src_mac_addr.set(src_mac_addr);
         self.dgram_tag.set(dgram_tag);
@@ -704,11 +887,14 @@ impl RxState<'a> {
         self.busy.set(true);
         self.bitmap.map(|bitmap| bitmap.clear());
         self.start_time.set(current_tics);
+        ReturnCode::SUCCESS
     }
 
     // This function assumes that the payload is a slice starting from the
     // actual payload (no 802.15.4 headers, no fragmentation headers), and
-    // returns true if the packet is completely reassembled.
+    // returns true if the packet is completely reassembled. A fragment that
+    // would extend past the datagram size or the packet buffer is rejected
+    // with `ESIZE` before anything is copied.
     fn receive_next_frame(
         &self,
         payload: &[u8],
@@ -718,8 +904,9 @@ impl RxState<'a> {
         ctx_store: &ContextStore,
     ) -> Result<bool, ReturnCode> {
         let mut packet = self.packet.take().ok_or(ReturnCode::ENOMEM)?;
-        let uncompressed_len = if dgram_offset == 0 {
-            let (consumed, written) = sixlowpan_compression::decompress(
+        let limit = min(dgram_size as usize, packet.len());
+        let result = if dgram_offset == 0 {
+            sixlowpan_compression::decompress(
                 ctx_store,
                 &payload[0..payload_len as usize],
                 self.src_mac_addr.get(),
@@ -727,22 +914,35 @@ impl RxState<'a> {
                 &mut packet,
                 dgram_size,
                 true,
-            ).map_err(|_| ReturnCode::FAIL)?;
-            let remaining = payload_len - consumed;
-            packet[written..written + remaining]
-                .copy_from_slice(&payload[consumed..consumed + remaining]);
-            written + remaining
+            ).map_err(|_| ReturnCode::FAIL)
+            .and_then(|(consumed, written)| {
+                let remaining = payload_len - consumed;
+                if written + remaining > limit {
+                    return Err(ReturnCode::ESIZE);
+                }
+                packet[written..written + remaining]
+                    .copy_from_slice(&payload[consumed..consumed + remaining]);
+                Ok(written + remaining)
+            })
+        } else if dgram_offset + payload_len > limit {
+            Err(ReturnCode::ESIZE)
         } else {
             packet[dgram_offset..dgram_offset + payload_len]
                 .copy_from_slice(&payload[0..payload_len]);
-            payload_len
+            Ok(payload_len)
         };
         self.packet.replace(packet);
+        if result == Err(ReturnCode::ESIZE) {
+            self.count(|stats| stats.out_of_bounds = stats.out_of_bounds.wrapping_add(1));
+        }
+        let uncompressed_len = result?;
+
         if !self.bitmap.map_or(false, |bitmap| {
             bitmap.set_bits(dgram_offset / 8, (dgram_offset + uncompressed_len) / 8)
         }) {
             // If this fails, we received an overlapping fragment. We can simply
             // drop the packet in this case.
+            self.count(|stats| stats.overlaps = stats.overlaps.wrapping_add(1));
             Err(ReturnCode::FAIL)
         } else {
             self.bitmap
@@ -751,6 +951,26 @@ impl RxState<'a> {
         }
     }
 
+    // Checks that a fully reassembled datagram starts with an IPv6 header
+    // whose version is 6 and whose payload length accounts for exactly the
+    // bytes that were received. Returns `EINVAL` otherwise, so that upper
+    // layers never parse a payload length that overruns the delivered data.
+    fn check_ip6_header(&self) -> ReturnCode {
+        let dgram_size = self.dgram_size.get() as usize;
+        let valid = self.packet.map_or(false, |packet| {
+            dgram_size >= IP6_HDR_LEN
+                && dgram_size <= packet.len()
+                && packet[0] >> 4 == 6
+                && slice_to_u16(&packet[4..6]) as usize + IP6_HDR_LEN == dgram_size
+        });
+        if valid {
+            ReturnCode::SUCCESS
+        } else {
+            self.count(|stats| stats.invalid_headers = stats.invalid_headers.wrapping_add(1));
+            ReturnCode::EINVAL
+        }
+    }
+
     fn end_receive(&self, client: Option<&'a SixlowpanRxClient>, result: ReturnCode) {
         self.busy.set(false);
         self.bitmap.map(|bitmap| bitmap.clear());
@@ -785,20 +1005,35 @@ pub struct Sixlowpan<'a, A: time::Alarm, C: ContextStore> {
     clock: &'a A,
     tx_dgram_tag: Cell<u16>,
     rx_client: Cell<Option<&'a SixlowpanRxClient>>,
+    mesh_client: Cell<Option<&'a SixlowpanMeshClient>>,
+    checksum_policy: Cell<Option<&'a UdpChecksumPolicy>>,
 
     // Receive state
     rx_states: List<'a, RxState<'a>>,
+    // Reassembly timeout in seconds
+    frag_timeout: u32,
 }
 
 // This function is called after receiving a frame
 impl<A: time::Alarm, C: ContextStore> RxClient for Sixlowpan<'a, A, C> {
-    fn receive<'b>(&self, buf: &'b [u8], header: Header<'b>, data_offset: usize, data_len: usize) {
-        // We return if retcode is not valid, as it does not make sense to issue
-        // a callback for an invalid frame reception
-        // TODO: Handle the case where the addresses are None/elided - they
-        // should not default to the zero address
-        let src_mac_addr = header.src_addr.unwrap_or(MacAddress::Short(0));
-        let dst_mac_addr = header.dst_addr.unwrap_or(MacAddress::Short(0));
+    fn receive<'b>(
+        &self,
+        buf: &'b [u8],
+        header: Header<'b>,
+        data_offset: usize,
+        data_len: usize,
+        _lqi: u8,
+        _rssi: i8,
+    ) {
+        // Both addresses are needed to derive the IIDs during decompression
+        // and to match fragments to the datagram they belong to. An elided
+        // address implicitly refers to the PAN coordinator, which this layer
+        // does not know, so the frame is dropped. No callback is issued, as
+        // no datagram has been started for the frame.
+        let (src_mac_addr, dst_mac_addr) = match (header.src_addr, header.dst_addr) {
+            (Some(src_mac_addr), Some(dst_mac_addr)) => (src_mac_addr, dst_mac_addr),
+            _ => return,
+        };
 
         let (rx_state, returncode) = self.receive_frame(
             &buf[data_offset..data_offset + data_len],
@@ -808,7 +1043,14 @@ impl<A: time::Alarm, C: ContextStore> RxClient for Sixlowpan<'a, A, C> {
         );
         // Reception completed if rx_state is not None. Note that this can
         // also occur for some fail states (e.g. dropping an invalid packet)
-        rx_state.map(|state| state.end_receive(self.rx_client.get(), returncode));
+        rx_state.map(|state| {
+            let returncode = if returncode == ReturnCode::SUCCESS {
+                state.check_ip6_header()
+            } else {
+                returncode
+            };
+            state.end_receive(self.rx_client.get(), returncode)
+        });
     }
 }
 
@@ -828,6 +1070,10 @@ impl<A: time::Alarm, C: ContextStore> SixlowpanState<'a> for Sixlowpan<'a, A, C>
         &self.ctx_store
     }
 
+    fn get_checksum_policy(&self) -> Option<&'a UdpChecksumPolicy> {
+        self.checksum_policy.get()
+    }
+
     /// Adds an additional `RxState` for reassembling IPv6 packets
     ///
     /// Each [RxState](struct.RxState.html) struct allows an additional IPv6
@@ -856,18 +1102,74 @@ impl<A: time::Alarm, C: ContextStore> Sixlowpan<'a, A, C> {
     ///
     /// * `clock` - A implementation of `Alarm` used for tracking the timing of
     /// frame arrival. The clock should be continue running during sleep and
-    /// have an accuracy of at least 60 seconds.
-    pub fn new(ctx_store: C, clock: &'a A) -> Sixlowpan<'a, A, C> {
+    /// have an accuracy of at least `frag_timeout`.
+    ///
+    /// * `frag_timeout` - The reassembly timeout, in seconds. A partially
+    /// reassembled packet is dropped once this much time has passed since its
+    /// first fragment arrived, freeing its `RxState` for a new packet.
+    /// `DEFAULT_FRAG_TIMEOUT` is the usual choice.
+    pub fn new(ctx_store: C, clock: &'a A, frag_timeout: u32) -> Sixlowpan<'a, A, C> {
         Sixlowpan {
             ctx_store: ctx_store,
             clock: clock,
             tx_dgram_tag: Cell::new(0),
             rx_client: Cell::new(None),
+            mesh_client: Cell::new(None),
+            checksum_policy: Cell::new(None),
 
             rx_states: List::new(),
+            frag_timeout: frag_timeout,
         }
     }
 
+    /// Returns the reassembly failures seen by all of the `RxState`s, so that
+    /// a board can log why received packets were lost.
+    pub fn reassembly_stats(&self) -> ReassemblyStats {
+        self.rx_states
+            .iter()
+            .fold(ReassemblyStats::default(), |total, state| {
+                let stats = state.stats();
+                ReassemblyStats {
+                    timeouts: total.timeouts.wrapping_add(stats.timeouts),
+                    overlaps: total.overlaps.wrapping_add(stats.overlaps),
+                    out_of_bounds: total.out_of_bounds.wrapping_add(stats.out_of_bounds),
+                    invalid_headers: total.invalid_headers.wrapping_add(stats.invalid_headers),
+                }
+            })
+    }
+
+    // The reassembly timeout in clock tics, limited to one period of the
+    // clock so that it can be measured
+    fn frag_timeout_tics(&self) -> u32 {
+        self.frag_timeout
+            .saturating_mul(A::Frequency::frequency())
+            .min(self.clock.max_tics())
+    }
+
+    // Whether `state` is in use by a reassembly that has not timed out
+    fn is_busy(&self, state: &RxState) -> bool {
+        state.is_busy(
+            self.clock.now(),
+            self.frag_timeout_tics(),
+            self.clock.max_tics(),
+        )
+    }
+
+    /// Sets the client that decides whether mesh-addressed frames are
+    /// consumed, forwarded or dropped. Without a client this node cannot
+    /// forward, so a mesh-addressed frame is consumed if its final destination
+    /// is this node's address and dropped otherwise.
+    pub fn set_mesh_client(&self, client: &'a SixlowpanMeshClient) {
+        self.mesh_client.set(Some(client));
+    }
+
+    /// Sets the policy that decides whether outgoing UDP datagrams may have
+    /// their checksum elided. Without a policy, checksums are always carried
+    /// inline.
+    pub fn set_checksum_policy(&self, policy: &'a UdpChecksumPolicy) {
+        self.checksum_policy.set(Some(policy));
+    }
+
     fn receive_frame(
         &self,
         packet: &[u8],
@@ -875,6 +1177,31 @@ impl<A: time::Alarm, C: ContextStore> Sixlowpan<'a, A, C> {
         src_mac_addr: MacAddress,
         dst_mac_addr: MacAddress,
     ) -> (Option<&RxState<'a>>, ReturnCode) {
+        if is_mesh(packet) {
+            let (offset, mesh) = match MeshHeader::decode(&packet[0..packet_len]).done() {
+                Some(result) => result,
+                None => return (None, ReturnCode::FAIL),
+            };
+            let payload = &packet[offset..packet_len];
+            let action = match self.mesh_client.get() {
+                Some(client) => client.mesh_frame(mesh, payload),
+                None if mesh.final_dest == dst_mac_addr => MeshAction::Consume,
+                None => MeshAction::Drop,
+            };
+            return match action {
+                // RFC 4944: the addresses used for decompression are the
+                // originator and final destination from the mesh header.
+                MeshAction::Consume => self.receive_frame(
+                    payload,
+                    packet_len - offset,
+                    mesh.originator,
+                    mesh.final_dest,
+                ),
+                MeshAction::Forward => (None, ReturnCode::SUCCESS),
+                MeshAction::Drop => (None, ReturnCode::FAIL),
+            };
+        }
+
         if is_fragment(packet) {
             let (is_frag1, dgram_size, dgram_tag, dgram_offset) = get_frag_hdr(&packet[0..5]);
             let offset_to_payload = if is_frag1 {
@@ -906,16 +1233,19 @@ impl<A: time::Alarm, C: ContextStore> Sixlowpan<'a, A, C> {
         let rx_state = self
             .rx_states
             .iter()
-            .find(|state| !state.is_busy(self.clock.now(), A::Frequency::frequency()));
+            .find(|state| !self.is_busy(state));
         rx_state
             .map(|state| {
-                state.start_receive(
+                let result = state.start_receive(
                     src_mac_addr,
                     dst_mac_addr,
                     payload_len as u16,
                     0,
                     self.clock.now(),
                 );
+                if result != ReturnCode::SUCCESS {
+                    return (None, result);
+                }
                 // The packet buffer should *always* be there; in particular,
                 // since this state is not busy, it must have the packet buffer.
                 // Otherwise, we are in an inconsistent state and can fail.
@@ -977,19 +1307,20 @@ impl<A: time::Alarm, C: ContextStore> Sixlowpan<'a, A, C> {
             rx_state = self
                 .rx_states
                 .iter()
-                .find(|state| !state.is_busy(self.clock.now(), A::Frequency::frequency()));
+                .find(|state| !self.is_busy(state));
             // Initialize new state
-            rx_state.map(|state| {
-                state.start_receive(
+            let result = match rx_state {
+                Some(state) => state.start_receive(
                     src_mac_addr,
                     dst_mac_addr,
                     dgram_size,
                     dgram_tag,
                     self.clock.now(),
-                )
-            });
-            if rx_state.is_none() {
-                return (None, ReturnCode::ENOMEM);
+                ),
+                None => ReturnCode::ENOMEM,
+            };
+            if result != ReturnCode::SUCCESS {
+                return (None, result);
             }
         }
         rx_state
@@ -1004,7 +1335,7 @@ impl<A: time::Alarm, C: ContextStore> Sixlowpan<'a, A, C> {
                 );
                 match res {
                     // Some error occurred
-                    Err(_) => (Some(state), ReturnCode::FAIL),
+                    Err(code) => (Some(state), code),
                     Ok(complete) => {
                         if complete {
                             // Packet fully reassembled
@@ -1030,3 +1361,438 @@ impl<A: time::Alarm, C: ContextStore> Sixlowpan<'a, A, C> {
         // TODO: Need to get buffer back from Mac layer on disassociation
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::boxed::Box;
+    use self::std::vec;
+    use self::std::vec::Vec;
+    use super::*;
+    use kernel::hil::time::{Alarm, Freq32KHz, Time};
+    use net::ieee802154::{FrameType, FrameVersion};
+    use net::ipv6::ip_utils::IPAddr;
+    use net::ipv6::ipv6::{IP6Header, IPPayload, TransportHeader};
+    use net::sixlowpan::sixlowpan_compression::Context;
+    use net::udp::udp::UDPHeader;
+
+    const SRC: MacAddress = MacAddress::Short(0x1234);
+    const DST: MacAddress = MacAddress::Short(0x5678);
+
+    // The largest values of a 32-bit clock and of a 24-bit clock such as the
+    // nRF5x RTC
+    const MAX_TICS_32: u32 = 0xFFFF_FFFF;
+    const MAX_TICS_24: u32 = 0xFF_FFFF;
+
+    fn rx_state(len: usize) -> RxState<'static> {
+        RxState::new(Box::leak(vec![0; len].into_boxed_slice()))
+    }
+
+    #[test]
+    fn reassembly_times_out() {
+        let state = rx_state(128);
+        assert_eq!(state.start_receive(SRC, DST, 64, 1, 1000), ReturnCode::SUCCESS);
+
+        assert!(state.is_busy(1000 + 99, 100, MAX_TICS_32));
+        assert_eq!(state.stats().timeouts, 0);
+        assert!(!state.is_busy(1000 + 100, 100, MAX_TICS_32));
+        assert_eq!(state.stats().timeouts, 1);
+
+        // A free state is not timed out again.
+        assert!(!state.is_busy(1000 + 500, 100, MAX_TICS_32));
+        assert_eq!(state.stats().timeouts, 1);
+    }
+
+    #[test]
+    fn reassembly_timeout_survives_clock_wraparound() {
+        let state = rx_state(128);
+        let start = 0xFFFF_FF00;
+        assert_eq!(state.start_receive(SRC, DST, 64, 1, start), ReturnCode::SUCCESS);
+
+        assert!(state.is_busy(0x10, 0x200, MAX_TICS_32));
+        assert_eq!(state.stats().timeouts, 0);
+        assert!(!state.is_busy(0x100, 0x200, MAX_TICS_32));
+        assert_eq!(state.stats().timeouts, 1);
+    }
+
+    #[test]
+    fn reassembly_timeout_survives_narrow_clock_wraparound() {
+        let state = rx_state(128);
+        let start = 0xFF_FF00;
+        assert_eq!(state.start_receive(SRC, DST, 64, 1, start), ReturnCode::SUCCESS);
+
+        assert!(state.is_busy(0x10, 0x200, MAX_TICS_24));
+        assert_eq!(state.stats().timeouts, 0);
+        assert!(!state.is_busy(0x100, 0x200, MAX_TICS_24));
+        assert_eq!(state.stats().timeouts, 1);
+    }
+
+    #[test]
+    fn datagram_larger_than_buffer_is_rejected() {
+        let state = rx_state(1280);
+        assert_eq!(state.start_receive(SRC, DST, 2000, 1, 0), ReturnCode::ESIZE);
+        assert_eq!(state.stats().out_of_bounds, 1);
+        // The state stays free for the next datagram.
+        assert!(!state.is_busy(0, 100, MAX_TICS_32));
+        assert_eq!(state.start_receive(SRC, DST, 1280, 2, 0), ReturnCode::SUCCESS);
+        assert!(state.is_busy(0, 100, MAX_TICS_32));
+        assert_eq!(state.stats().out_of_bounds, 1);
+    }
+
+    // Starts a reassembly of `dgram_size` bytes and writes an IPv6 header
+    // with the given version and payload length into the packet buffer.
+    fn reassembled(dgram_size: u16, version: u8, payload_len: u16) -> RxState<'static> {
+        let state = rx_state(128);
+        assert_eq!(state.start_receive(SRC, DST, dgram_size, 1, 0), ReturnCode::SUCCESS);
+        state.packet.map(|packet| {
+            packet[0] = version << 4;
+            u16_to_slice(payload_len, &mut packet[4..6]);
+        });
+        state
+    }
+
+    #[test]
+    fn valid_ip6_header_is_accepted() {
+        let state = reassembled(48, 6, 8);
+        assert_eq!(state.check_ip6_header(), ReturnCode::SUCCESS);
+        assert_eq!(state.stats().invalid_headers, 0);
+    }
+
+    #[test]
+    fn wrong_ip6_version_is_rejected() {
+        let state = reassembled(48, 4, 8);
+        assert_eq!(state.check_ip6_header(), ReturnCode::EINVAL);
+        assert_eq!(state.stats().invalid_headers, 1);
+    }
+
+    #[test]
+    fn mismatched_payload_length_is_rejected() {
+        let state = reassembled(48, 6, 100);
+        assert_eq!(state.check_ip6_header(), ReturnCode::EINVAL);
+        let state = reassembled(48, 6, 7);
+        assert_eq!(state.check_ip6_header(), ReturnCode::EINVAL);
+        assert_eq!(state.stats().invalid_headers, 1);
+    }
+
+    #[test]
+    fn datagram_shorter_than_ip6_header_is_rejected() {
+        let state = reassembled(IP6_HDR_LEN as u16 - 1, 6, 0);
+        assert_eq!(state.check_ip6_header(), ReturnCode::EINVAL);
+        assert_eq!(state.stats().invalid_headers, 1);
+    }
+
+    #[test]
+    fn fragment_past_end_of_datagram_is_rejected() {
+        let state = rx_state(128);
+        assert_eq!(state.start_receive(SRC, DST, 48, 1, 0), ReturnCode::SUCCESS);
+
+        // A fragment that ends exactly at the datagram size fits.
+        let fragment = [0xaa; 16];
+        assert_eq!(state.receive_next_frame(&fragment, 16, 48, 32, &NO_CONTEXT), Ok(false));
+        assert_eq!(state.stats().out_of_bounds, 0);
+
+        // One that runs past it is dropped, even though the buffer has room,
+        // and nothing past the datagram is written.
+        let result = state.receive_next_frame(&fragment, 16, 48, 40, &NO_CONTEXT);
+        assert_eq!(result, Err(ReturnCode::ESIZE));
+        assert_eq!(state.stats().out_of_bounds, 1);
+        state.packet.map(|packet| assert!(packet[48..].iter().all(|&b| b == 0)));
+    }
+
+    #[test]
+    fn first_fragment_past_end_of_datagram_is_rejected() {
+        let state = rx_state(128);
+        assert_eq!(state.start_receive(SRC, DST, 40, 1, 0), ReturnCode::SUCCESS);
+
+        // The header decompresses to the full 40 bytes, leaving no room for
+        // the inline payload that follows it.
+        let mut fragment = compressed_header(SRC, DST);
+        fragment.extend_from_slice(&[0xaa; 8]);
+        let result = state.receive_next_frame(&fragment, fragment.len(), 40, 0, &NO_CONTEXT);
+        assert_eq!(result, Err(ReturnCode::ESIZE));
+        assert_eq!(state.stats().out_of_bounds, 1);
+    }
+
+    const NO_CONTEXT: Context = Context {
+        prefix: [0; 16],
+        prefix_len: 0,
+        id: 0,
+        compress: false,
+    };
+
+    struct Clock {
+        now: Cell<u32>,
+    }
+
+    impl Time for Clock {
+        type Frequency = Freq32KHz;
+
+        fn disable(&self) {}
+
+        fn is_armed(&self) -> bool {
+            false
+        }
+    }
+
+    impl Alarm for Clock {
+        fn now(&self) -> u32 {
+            self.now.get()
+        }
+
+        fn set_alarm(&self, _tics: u32) {}
+
+        fn get_alarm(&self) -> u32 {
+            0
+        }
+    }
+
+    type TestSixlowpan = Sixlowpan<'static, Clock, Context>;
+
+    // A `Sixlowpan` with one 128-byte `RxState`, which is returned along
+    // with the clock
+    fn sixlowpan(
+        frag_timeout: u32,
+    ) -> (&'static TestSixlowpan, &'static RxState<'static>, &'static Clock) {
+        let clock = Box::leak(Box::new(Clock { now: Cell::new(0) }));
+        let sixlowpan = Box::leak(Box::new(Sixlowpan::new(NO_CONTEXT, clock, frag_timeout)));
+        let state = Box::leak(Box::new(rx_state(128)));
+        sixlowpan.add_rx_state(state);
+        (sixlowpan, state, clock)
+    }
+
+    // An IPHC-compressed IPv6 header with no payload, between the link-local
+    // addresses of `src` and `dst`
+    fn compressed_header(src: MacAddress, dst: MacAddress) -> Vec<u8> {
+        let mut payload = [0u8; 0];
+        let mut packet = IP6Packet::new(IPPayload::new(
+            TransportHeader::UDP(UDPHeader::new()),
+            &mut payload,
+        ));
+        packet.header.src_addr = IPAddr::generate_from_mac(src);
+        packet.header.dst_addr = IPAddr::generate_from_mac(dst);
+        let mut buf = [0u8; 64];
+        let (_, written) = sixlowpan_compression::compress(
+            &NO_CONTEXT,
+            &packet,
+            src,
+            dst,
+            None,
+            None,
+            &mut buf,
+        ).expect("compression failed");
+        buf[..written].to_vec()
+    }
+
+    // A frame with a mesh header from `originator` to `final_dest`, carrying
+    // a compressed header between their link-local addresses
+    fn mesh_frame(originator: MacAddress, final_dest: MacAddress) -> Vec<u8> {
+        let mesh = MeshHeader {
+            hops_left: 3,
+            originator: originator,
+            final_dest: final_dest,
+        };
+        let mut frame = vec![0u8; 5];
+        assert_eq!(mesh.encode(&mut frame).done(), Some((5, ())));
+        frame.extend_from_slice(&compressed_header(originator, final_dest));
+        frame
+    }
+
+    #[test]
+    fn mesh_header_round_trip() {
+        let mesh = MeshHeader {
+            hops_left: 5,
+            originator: MacAddress::Short(0x1234),
+            final_dest: MacAddress::Short(0xbeef),
+        };
+        let mut buf = [0u8; 8];
+        assert_eq!(mesh.encode(&mut buf).done(), Some((5, ())));
+        assert_eq!(buf[0], 0b1011_0101);
+        assert_eq!(MeshHeader::decode(&buf[..5]).done(), Some((5, mesh)));
+
+        let mesh = MeshHeader {
+            hops_left: 0,
+            originator: MacAddress::Long([1, 2, 3, 4, 5, 6, 7, 8]),
+            final_dest: MacAddress::Short(0xbeef),
+        };
+        assert_eq!(mesh.encode(&mut buf).done(), None);
+        let mut buf = [0u8; 16];
+        assert_eq!(mesh.encode(&mut buf).done(), Some((11, ())));
+        assert_eq!(MeshHeader::decode(&buf[..11]).done(), Some((11, mesh)));
+    }
+
+    #[test]
+    fn extended_hops_left_is_rejected() {
+        let mut buf = [0b1011_1111, 0x12, 0x34, 0xbe, 0xef];
+        assert_eq!(MeshHeader::decode(&buf).done(), None);
+        buf[0] = 0b0111_0001;
+        assert_eq!(MeshHeader::decode(&buf).done(), None);
+    }
+
+    #[test]
+    fn mesh_frame_for_this_node_is_consumed_without_client() {
+        let (sixlowpan, state, _) = sixlowpan(DEFAULT_FRAG_TIMEOUT);
+        let originator = MacAddress::Short(0x1111);
+        let last_hop = MacAddress::Short(0x2222);
+        let frame = mesh_frame(originator, DST);
+
+        let (received, result) = sixlowpan.receive_frame(&frame, frame.len(), last_hop, DST);
+        assert!(received.is_some());
+        assert_eq!(result, ReturnCode::SUCCESS);
+
+        // The IIDs come from the mesh header, not from the last hop.
+        let (_, header) = state
+            .packet
+            .map(|packet| IP6Header::decode(packet).done())
+            .unwrap()
+            .expect("invalid IPv6 header");
+        assert_eq!(header.get_src_addr(), IPAddr::generate_from_mac(originator));
+        assert_eq!(header.get_dst_addr(), IPAddr::generate_from_mac(DST));
+    }
+
+    #[test]
+    fn mesh_frame_for_another_node_is_dropped_without_client() {
+        let (sixlowpan, state, _) = sixlowpan(DEFAULT_FRAG_TIMEOUT);
+        let frame = mesh_frame(SRC, MacAddress::Short(0x9999));
+
+        let (received, result) = sixlowpan.receive_frame(&frame, frame.len(), SRC, DST);
+        assert!(received.is_none());
+        assert_eq!(result, ReturnCode::FAIL);
+        assert!(!state.is_busy(0, 100, MAX_TICS_32));
+    }
+
+    struct Forwarder {
+        frames: Cell<usize>,
+        payload_len: Cell<usize>,
+    }
+
+    impl SixlowpanMeshClient for Forwarder {
+        fn mesh_frame(&self, header: MeshHeader, payload: &[u8]) -> MeshAction {
+            assert_eq!(header.final_dest, MacAddress::Short(0x9999));
+            self.frames.set(self.frames.get() + 1);
+            self.payload_len.set(payload.len());
+            MeshAction::Forward
+        }
+    }
+
+    #[test]
+    fn mesh_frame_is_handed_to_client() {
+        let (sixlowpan, state, _) = sixlowpan(DEFAULT_FRAG_TIMEOUT);
+        let forwarder = Box::leak(Box::new(Forwarder {
+            frames: Cell::new(0),
+            payload_len: Cell::new(0),
+        }));
+        sixlowpan.set_mesh_client(forwarder);
+        let frame = mesh_frame(SRC, MacAddress::Short(0x9999));
+
+        let (received, result) = sixlowpan.receive_frame(&frame, frame.len(), SRC, DST);
+        assert!(received.is_none());
+        assert_eq!(result, ReturnCode::SUCCESS);
+        assert_eq!(forwarder.frames.get(), 1);
+        assert_eq!(forwarder.payload_len.get(), frame.len() - 5);
+        assert!(!state.is_busy(0, 100, MAX_TICS_32));
+    }
+
+    #[test]
+    fn stale_reassembly_expires_after_configured_timeout() {
+        let (sixlowpan, state, clock) = sixlowpan(2);
+        let timeout = 2 * Freq32KHz::frequency();
+        let start = 1000;
+        clock.now.set(start);
+
+        // The first fragment of a datagram whose other fragments never come
+        let mut fragment = vec![0u8; lowpan_frag::FRAG1_HDR_SIZE];
+        set_frag_hdr(100, 7, 0, &mut fragment, true);
+        fragment.extend_from_slice(&compressed_header(SRC, DST));
+        let (received, result) = sixlowpan.receive_frame(&fragment, fragment.len(), SRC, DST);
+        assert!(received.is_none());
+        assert_eq!(result, ReturnCode::SUCCESS);
+
+        // Until the timeout passes, the only state is held by the stale
+        // reassembly.
+        let packet = compressed_header(SRC, DST);
+        clock.now.set(start + timeout - 1);
+        let (received, result) = sixlowpan.receive_frame(&packet, packet.len(), SRC, DST);
+        assert!(received.is_none());
+        assert_eq!(result, ReturnCode::ENOMEM);
+        assert_eq!(state.stats().timeouts, 0);
+
+        clock.now.set(start + timeout);
+        let (received, result) = sixlowpan.receive_frame(&packet, packet.len(), SRC, DST);
+        assert!(received.is_some());
+        assert_eq!(result, ReturnCode::SUCCESS);
+        assert_eq!(state.stats().timeouts, 1);
+    }
+
+    // A data frame header with the given, possibly elided, addresses
+    fn frame_header(src: Option<MacAddress>, dst: Option<MacAddress>) -> Header<'static> {
+        Header {
+            frame_type: FrameType::Data,
+            frame_pending: false,
+            ack_requested: false,
+            version: FrameVersion::V2006,
+            seq: Some(0),
+            dst_pan: Some(0xabcd),
+            dst_addr: dst,
+            src_pan: Some(0xabcd),
+            src_addr: src,
+            security: None,
+            header_ies: Default::default(),
+            header_ies_len: 0,
+            payload_ies: Default::default(),
+            payload_ies_len: 0,
+        }
+    }
+
+    struct Receiver {
+        datagrams: Cell<usize>,
+        len: Cell<usize>,
+    }
+
+    impl SixlowpanRxClient for Receiver {
+        fn receive(&self, _buf: &[u8], len: usize, _result: ReturnCode) {
+            self.datagrams.set(self.datagrams.get() + 1);
+            self.len.set(len);
+        }
+    }
+
+    #[test]
+    fn elided_source_does_not_match_short_address_zero() {
+        let (sixlowpan, state, _) = sixlowpan(DEFAULT_FRAG_TIMEOUT);
+        let receiver = Box::leak(Box::new(Receiver {
+            datagrams: Cell::new(0),
+            len: Cell::new(0),
+        }));
+        sixlowpan.set_rx_client(receiver);
+        let node_zero = MacAddress::Short(0);
+
+        // The first fragment of a datagram from a node whose short address
+        // really is 0. Its header decompresses to the first 40 bytes.
+        let mut first = vec![0u8; lowpan_frag::FRAG1_HDR_SIZE];
+        set_frag_hdr(100, 7, 0, &mut first, true);
+        first.extend_from_slice(&compressed_header(node_zero, DST));
+        sixlowpan.receive(&first, frame_header(Some(node_zero), Some(DST)), 0, first.len(), 0, 0);
+        assert!(state.is_busy(0, 100, MAX_TICS_32));
+
+        let mut rest = vec![0u8; lowpan_frag::FRAGN_HDR_SIZE];
+        set_frag_hdr(100, 7, 40, &mut rest, false);
+        rest.extend_from_slice(&[0xaa; 60]);
+
+        // The rest of the datagram with the source elided is dropped rather
+        // than completing node 0's datagram.
+        sixlowpan.receive(&rest, frame_header(None, Some(DST)), 0, rest.len(), 0, 0);
+        assert!(state.is_busy(0, 100, MAX_TICS_32));
+        assert_eq!(receiver.datagrams.get(), 0);
+
+        // So is a frame with the destination elided.
+        sixlowpan.receive(&rest, frame_header(Some(node_zero), None), 0, rest.len(), 0, 0);
+        assert!(state.is_busy(0, 100, MAX_TICS_32));
+        assert_eq!(receiver.datagrams.get(), 0);
+
+        // The real fragment from node 0 still completes it.
+        sixlowpan.receive(&rest, frame_header(Some(node_zero), Some(DST)), 0, rest.len(), 0, 0);
+        assert!(!state.is_busy(0, 100, MAX_TICS_32));
+        assert_eq!(receiver.datagrams.get(), 1);
+        assert_eq!(receiver.len.get(), 100);
+    }
+}
@@ -1,8 +1,37 @@
-/* Though TCP has not yet been implemented for the Tock Networking stackm
-   this file defines the structure of the TCPHeader and TCPPacket structs
-   so that TCPPacket can be included for clarity as part of the
-   TransportPacket enum */
+//! This file contains the structs and methods associated with the TCP header,
+//! along with a minimal receive path that parses TCP segments carried in
+//! IPv6 packets and passes them to a client. There is no TCP state machine
+//! yet; this is the first step towards a TCP capsule.
 
+use kernel::common::cells::OptionalCell;
+use net::ipv6::ip_utils::{ip6_nh, IPAddr};
+use net::ipv6::ipv6::IP6Header;
+use net::ipv6::ipv6_recv::IP6RecvClient;
+use net::stream::SResult;
+use net::stream::{decode_u16, decode_u32};
+
+/// Size of a TCP header without options.
+pub const TCP_HDR_LEN: usize = 20;
+
+pub mod tcp_flags {
+    pub const FIN: u16 = 1 << 0;
+    pub const SYN: u16 = 1 << 1;
+    pub const RST: u16 = 1 << 2;
+    pub const PSH: u16 = 1 << 3;
+    pub const ACK: u16 = 1 << 4;
+    pub const URG: u16 = 1 << 5;
+    pub const ECE: u16 = 1 << 6;
+    pub const CWR: u16 = 1 << 7;
+    pub const NS: u16 = 1 << 8;
+    pub const MASK: u16 = 0x1ff;
+}
+
+// Note: All TCP Header fields are stored in network byte order
+
+/// The `TCPHeader` struct follows the layout for the TCP segment header.
+/// As with `UDPHeader`, getters are provided to avoid confusion with
+/// endian-ness.
+#[derive(Copy, Clone, Debug)]
 pub struct TCPHeader {
     pub src_port: u16,
     pub dst_port: u16,
@@ -14,24 +43,297 @@ pub struct TCPHeader {
     pub urg_ptr: u16,
 }
 
-/*
-impl TCPPacket<'a> {
-    pub fn new(buf: &mut [u8]) -> TCPPacket<'a> {
-        let header = TCPHeader {
+impl Default for TCPHeader {
+    fn default() -> TCPHeader {
+        TCPHeader {
             src_port: 0,
             dst_port: 0,
             seq_num: 0,
             ack_num: 0,
-            offset_and_control: 0,
+            offset_and_control: (((TCP_HDR_LEN / 4) as u16) << 12).to_be(),
             window: 0,
             cksum: 0,
             urg_ptr: 0,
+        }
+    }
+}
+
+impl TCPHeader {
+    pub fn new() -> TCPHeader {
+        TCPHeader::default()
+    }
+
+    pub fn get_src_port(&self) -> u16 {
+        u16::from_be(self.src_port)
+    }
+
+    pub fn get_dst_port(&self) -> u16 {
+        u16::from_be(self.dst_port)
+    }
+
+    pub fn get_seq_num(&self) -> u32 {
+        u32::from_be(self.seq_num)
+    }
+
+    pub fn get_ack_num(&self) -> u32 {
+        u32::from_be(self.ack_num)
+    }
+
+    /// Returns the control flags, which can be tested against the constants
+    /// in `tcp_flags`.
+    pub fn get_flags(&self) -> u16 {
+        u16::from_be(self.offset_and_control) & tcp_flags::MASK
+    }
+
+    pub fn get_window(&self) -> u16 {
+        u16::from_be(self.window)
+    }
+
+    pub fn get_cksum(&self) -> u16 {
+        u16::from_be(self.cksum)
+    }
+
+    pub fn get_urg_ptr(&self) -> u16 {
+        u16::from_be(self.urg_ptr)
+    }
+
+    /// Returns the size of the header including options, which is also the
+    /// offset of the segment data.
+    pub fn get_hdr_size(&self) -> usize {
+        ((u16::from_be(self.offset_and_control) >> 12) as usize) * 4
+    }
+
+    /// This function deserializes the `TCPHeader` from the provided buffer.
+    /// Options are not parsed, but the header must fit in `buf`.
+    ///
+    /// # Arguments
+    ///
+    /// `buf` - The byte array corresponding to a serialized `TCPHeader`
+    ///
+    /// # Return Value
+    ///
+    /// This function returns a `TCPHeader` struct wrapped in an SResult. The
+    /// offset is the start of the segment data.
+    pub fn decode(buf: &[u8]) -> SResult<TCPHeader> {
+        stream_len_cond!(buf, TCP_HDR_LEN);
+        let mut tcp_header = Self::new();
+        let off = 0;
+        let (off, src_port) = dec_try!(buf, off; decode_u16);
+        tcp_header.src_port = u16::from_be(src_port);
+        let (off, dst_port) = dec_try!(buf, off; decode_u16);
+        tcp_header.dst_port = u16::from_be(dst_port);
+        let (off, seq_num) = dec_try!(buf, off; decode_u32);
+        tcp_header.seq_num = u32::from_be(seq_num);
+        let (off, ack_num) = dec_try!(buf, off; decode_u32);
+        tcp_header.ack_num = u32::from_be(ack_num);
+        let (off, offset_and_control) = dec_try!(buf, off; decode_u16);
+        tcp_header.offset_and_control = u16::from_be(offset_and_control);
+        let (off, window) = dec_try!(buf, off; decode_u16);
+        tcp_header.window = u16::from_be(window);
+        let (off, cksum) = dec_try!(buf, off; decode_u16);
+        tcp_header.cksum = u16::from_be(cksum);
+        let (off, urg_ptr) = dec_try!(buf, off; decode_u16);
+        tcp_header.urg_ptr = u16::from_be(urg_ptr);
+
+        let hdr_size = tcp_header.get_hdr_size();
+        stream_cond!(hdr_size >= off);
+        stream_len_cond!(buf, hdr_size);
+        stream_done!(hdr_size, tcp_header);
+    }
+}
+
+/// Computes the TCP checksum of `segment` (header and data) over the IPv6
+/// pseudo-header. If the checksum field of `segment` is filled in, the result
+/// is 0 for a valid segment; if it is zeroed, the result is the value to
+/// place in the checksum field. The result is in host byte order.
+pub fn compute_tcp_checksum(ip6_header: &IP6Header, segment: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+
+    // IPv6 pseudo-header: addresses, upper-layer length, and next header
+    let src_addr = ip6_header.get_src_addr();
+    let dst_addr = ip6_header.get_dst_addr();
+    let mut i = 0;
+    while i < 16 {
+        sum += ((src_addr.0[i] as u32) << 8) + src_addr.0[i + 1] as u32;
+        sum += ((dst_addr.0[i] as u32) << 8) + dst_addr.0[i + 1] as u32;
+        i += 2;
+    }
+    let len = segment.len() as u32;
+    sum += len >> 16;
+    sum += len & 0xffff;
+    sum += ip6_nh::TCP as u32;
+
+    // Segment, padded with a zero byte if its length is odd
+    let mut i = 0;
+    while i < segment.len() {
+        let msb = (segment[i] as u32) << 8;
+        let lsb = if i + 1 < segment.len() {
+            segment[i + 1] as u32
+        } else {
+            0
         };
-        TCPPacket {
-            head: header,
-            payload: buf,
-            len: 0,
+        sum += msb + lsb;
+        i += 2;
+    }
+
+    // carry overflow
+    while sum > 0xffff {
+        sum = (sum >> 16) + (sum & 0xffff);
+    }
+
+    !sum as u16
+}
+
+/// The TCP receive path implements this client interface trait to pass
+/// parsed segments up to a TCP implementation.
+pub trait TCPRecvClient {
+    fn receive(&self, src_addr: IPAddr, dst_addr: IPAddr, header: TCPHeader, payload: &[u8]);
+}
+
+/// This struct is set as the client of an IP6Receiver. It checks the TCP
+/// checksum of each received segment, parses its header, and passes valid
+/// segments to the `TCPRecvClient`.
+pub struct TCPReceiver<'a> {
+    client: OptionalCell<&'a TCPRecvClient>,
+}
+
+impl<'a> TCPReceiver<'a> {
+    pub fn new() -> TCPReceiver<'a> {
+        TCPReceiver {
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a TCPRecvClient) {
+        self.client.set(client);
+    }
+}
+
+impl<'a> IP6RecvClient for TCPReceiver<'a> {
+    fn receive(&self, ip_header: IP6Header, payload: &[u8]) {
+        if ip_header.get_next_header() != ip6_nh::TCP {
+            return;
+        }
+        if compute_tcp_checksum(&ip_header, payload) != 0 {
+            debug!("[TCP_RECV] Error: bad checksum");
+            return;
         }
+        match TCPHeader::decode(payload).done() {
+            Some((offset, tcp_header)) => {
+                self.client.map(|client| {
+                    client.receive(
+                        ip_header.get_src_addr(),
+                        ip_header.get_dst_addr(),
+                        tcp_header,
+                        &payload[offset..],
+                    );
+                });
+            }
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::boxed::Box;
+    use super::*;
+    use core::cell::Cell;
+
+    // A SYN from fe80::1 port 49152 to fe80::2 port 80 carrying "tock!",
+    // with its checksum filled in
+    const SEGMENT: [u8; 25] = [
+        0xc0, 0x00, 0x00, 0x50, 0x12, 0x34, 0x56, 0x78, 0x00, 0x00, 0x00, 0x00, 0x50, 0x02, 0xff,
+        0xff, 0x91, 0x01, 0x00, 0x00, 0x74, 0x6f, 0x63, 0x6b, 0x21,
+    ];
+    const CHECKSUM: u16 = 0x9101;
+
+    fn ip6_header() -> IP6Header {
+        let mut header = IP6Header::new();
+        header.src_addr.0[0] = 0xfe;
+        header.src_addr.0[1] = 0x80;
+        header.src_addr.0[15] = 0x01;
+        header.dst_addr.0[0] = 0xfe;
+        header.dst_addr.0[1] = 0x80;
+        header.dst_addr.0[15] = 0x02;
+        header.set_next_header(ip6_nh::TCP);
+        header.set_payload_len(SEGMENT.len() as u16);
+        header
+    }
+
+    fn leak<T>(value: T) -> &'static T {
+        Box::leak(Box::new(value))
+    }
+
+    struct Recorder {
+        received: Cell<usize>,
+        seq_num: Cell<u32>,
+        payload_len: Cell<usize>,
+    }
+
+    impl TCPRecvClient for Recorder {
+        fn receive(&self, _src: IPAddr, _dst: IPAddr, header: TCPHeader, payload: &[u8]) {
+            self.received.set(self.received.get() + 1);
+            self.seq_num.set(header.get_seq_num());
+            self.payload_len.set(payload.len());
+        }
+    }
+
+    fn receiver() -> (&'static TCPReceiver<'static>, &'static Recorder) {
+        let recorder = leak(Recorder {
+            received: Cell::new(0),
+            seq_num: Cell::new(0),
+            payload_len: Cell::new(0),
+        });
+        let receiver = leak(TCPReceiver::new());
+        receiver.set_client(recorder);
+        (receiver, recorder)
+    }
+
+    #[test]
+    fn default_header_has_no_options() {
+        let header = TCPHeader::new();
+        assert_eq!(header.get_hdr_size(), TCP_HDR_LEN);
+        assert_eq!(header.get_flags(), 0);
+    }
+
+    #[test]
+    fn header_fields_are_decoded() {
+        let (offset, header) = TCPHeader::decode(&SEGMENT).done().unwrap();
+        assert_eq!(offset, TCP_HDR_LEN);
+        assert_eq!(header.get_src_port(), 49152);
+        assert_eq!(header.get_dst_port(), 80);
+        assert_eq!(header.get_seq_num(), 0x12345678);
+        assert_eq!(header.get_ack_num(), 0);
+        assert_eq!(header.get_flags(), tcp_flags::SYN);
+        assert_eq!(header.get_window(), 0xffff);
+        assert_eq!(header.get_cksum(), CHECKSUM);
+    }
+
+    #[test]
+    fn known_segment_checksum() {
+        let mut segment = SEGMENT;
+        assert_eq!(compute_tcp_checksum(&ip6_header(), &segment), 0);
+        segment[16] = 0;
+        segment[17] = 0;
+        assert_eq!(compute_tcp_checksum(&ip6_header(), &segment), CHECKSUM);
+    }
+
+    #[test]
+    fn valid_segment_is_delivered() {
+        let (receiver, recorder) = receiver();
+        IP6RecvClient::receive(receiver, ip6_header(), &SEGMENT);
+        assert_eq!(recorder.received.get(), 1);
+        assert_eq!(recorder.seq_num.get(), 0x12345678);
+        assert_eq!(recorder.payload_len.get(), 5);
+    }
+
+    #[test]
+    fn corrupted_segment_fails_checksum() {
+        let mut segment = SEGMENT;
+        segment[SEGMENT.len() - 1] ^= 0x01;
+        assert_ne!(compute_tcp_checksum(&ip6_header(), &segment), 0);
     }
 }
-*/
@@ -0,0 +1,507 @@
+//! Implements the joiner side of Thread commissioning, through which a new
+//! device is authenticated by a commissioner and admitted to a Thread
+//! network. This is covered in Chapter 8 of the Thread 1.1.1 Specification.
+//!
+//! Commissioning a joiner works as follows:
+//!
+//!     1. The joiner multicasts an MLE Discovery Request carrying a
+//!        Discovery Request TLV with the Joiner flag set.
+//!     2. Nearby routers answer with an MLE Discovery Response. A router
+//!        that accepts joiners includes the Joiner UDP Port TLV.
+//!     3. The joiner runs a DTLS handshake with the commissioner, relayed by
+//!        that joiner router, authenticated with EC-JPAKE using the joiner's
+//!        pre-shared key (PSKd).
+//!     4. Over the secured session, the joiner sends JOIN_FIN.req, a CoAP
+//!        POST to `c/jf` with its State and vendor TLVs, and the commissioner
+//!        answers with JOIN_FIN.rsp, whose State TLV accepts or rejects the
+//!        joiner.
+//!
+//! This module builds and parses the messages exchanged during these steps
+//! and drives the joiner through them over UDP. The cryptography of step 3,
+//! and the DTLS record protection of step 4, are delegated to an
+//! implementation of `JoinerSecurity`. The joiner does not retransmit lost
+//! messages or time out on its own; a caller that gives up on an attempt
+//! should call `cancel`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let joiner = static_init!(
+//!     capsules::net::thread::joiner::Joiner<'static>,
+//!     capsules::net::thread::joiner::Joiner::new(
+//!         udp_send,
+//!         dtls_jpake,
+//!         &VENDOR_INFO,
+//!         &mut capsules::net::thread::joiner::JOINER_BUF
+//!     )
+//! );
+//! udp_send.set_client(joiner);
+//! udp_recv.set_client(joiner);
+//! joiner.set_client(client);
+//! joiner.start(b"J01NME");
+//! ```
+
+use core::cell::Cell;
+use kernel::common::cells::{OptionalCell, TakeCell};
+use kernel::ReturnCode;
+use net::ipv6::ip_utils::IPAddr;
+use net::thread::tlv::{CommissioningState, CommissioningTlv, Tlv};
+use net::udp::udp_recv::UDPRecvClient;
+use net::udp::udp_send::{UDPSendClient, UDPSender};
+
+/// Buffer for building commissioning messages. Half of it holds the
+/// plaintext of a message that must be protected before it is sent.
+pub static mut JOINER_BUF: [u8; 256] = [0; 256];
+
+/// The UDP port MLE messages are sent to and from.
+pub const MLE_PORT: u16 = 19788;
+
+/// The UDP port the joiner runs the commissioning session from.
+pub const JOINER_PORT: u16 = 1000;
+
+/// MLE messages sent without MLE security start with this byte, and
+/// discovery messages are always sent this way. (See 4.3.1)
+const MLE_SECURITY_NONE: u8 = 255;
+
+/// MLE command types. (See 4.4)
+const MLE_DISCOVERY_REQUEST: u8 = 16;
+const MLE_DISCOVERY_RESPONSE: u8 = 17;
+
+/// The version advertised in the Discovery Request TLV.
+const THREAD_VERSION: u8 = 2;
+
+/// The link-local all-nodes multicast address, ff02::1, to which discovery
+/// requests are sent.
+const ALL_NODES: IPAddr = IPAddr([
+    0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01,
+]);
+
+/// RFC 7252, 3: version 1 confirmable message header, POST request, and the
+/// 2.04 Changed response code.
+const COAP_CON_HEADER: u8 = 0b0100_0000;
+const COAP_POST: u8 = 0x02;
+const COAP_CHANGED: u8 = 0x44;
+const COAP_PAYLOAD_MARKER: u8 = 0xff;
+
+/// Uri-Path options `c` and `jf`, the JOIN_FIN.req resource. (See 8.10.4)
+const JOIN_FIN_URI_PATH: [u8; 5] = [0xb1, b'c', 0x02, b'j', b'f'];
+
+/// Vendor information the joiner reports to the commissioner in
+/// JOIN_FIN.req.
+pub struct VendorInfo<'a> {
+    pub name: &'a [u8],
+    pub model: &'a [u8],
+    pub sw_version: &'a [u8],
+    pub stack_version: [u8; 6],
+}
+
+/// Progress of the DTLS handshake after processing a message from the
+/// commissioner.
+pub enum HandshakeResult {
+    /// The handshake continues. The given number of bytes of records were
+    /// written to the output buffer to be sent to the commissioner.
+    InProgress(usize),
+    /// The handshake completed and the session is established. As above,
+    /// the given number of bytes of final records must be sent.
+    Complete(usize),
+    /// The commissioner could not be authenticated.
+    Failed,
+}
+
+/// The DTLS session, authenticated with EC-JPAKE, that secures the
+/// commissioning exchange. Messages are passed to and from it as the
+/// contents of UDP datagrams exchanged with the joiner router.
+pub trait JoinerSecurity {
+    /// Starts a new handshake authenticated with the joiner's pre-shared
+    /// key `pskd`, discarding any previous session. Writes the first
+    /// handshake records to `out` and returns their length.
+    fn start_handshake(&self, pskd: &[u8], out: &mut [u8]) -> Result<usize, ReturnCode>;
+
+    /// Processes handshake records received from the commissioner, writing
+    /// any records to send in reply to `out`.
+    fn continue_handshake(&self, records: &[u8], out: &mut [u8]) -> HandshakeResult;
+
+    /// Protects application data for the established session, writing the
+    /// resulting record to `out` and returning its length.
+    fn seal(&self, plaintext: &[u8], out: &mut [u8]) -> Result<usize, ReturnCode>;
+
+    /// Authenticates and decrypts an application data record of the
+    /// established session, writing the plaintext to `out` and returning
+    /// its length.
+    fn open(&self, record: &[u8], out: &mut [u8]) -> Result<usize, ReturnCode>;
+}
+
+/// A client notified when commissioning completes.
+pub trait JoinerClient {
+    /// `result` is `SUCCESS` if the commissioner accepted the joiner, `FAIL`
+    /// if it rejected the joiner or could not be authenticated, and any other
+    /// error if a message could not be sent.
+    fn joined(&self, result: ReturnCode);
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum State {
+    Idle,
+    /// The discovery request was sent; waiting for a joiner router.
+    Discovering,
+    /// Running the DTLS handshake with the commissioner through the joiner
+    /// router at the given address and port.
+    Handshaking(IPAddr, u16),
+    /// JOIN_FIN.req is sent, or will be once the final handshake records
+    /// are sent; waiting for JOIN_FIN.rsp.
+    Finalizing(IPAddr, u16),
+}
+
+pub struct Joiner<'a> {
+    udp_send: &'a UDPSender<'a>,
+    security: &'a JoinerSecurity,
+    vendor: &'a VendorInfo<'a>,
+    buf: TakeCell<'static, [u8]>,
+    client: OptionalCell<&'a JoinerClient>,
+    state: Cell<State>,
+    pskd: OptionalCell<&'a [u8]>,
+    /// Whether JOIN_FIN.req must be sent once the send in progress, which
+    /// carries the final handshake records, completes.
+    join_fin_pending: Cell<bool>,
+    message_id: Cell<u16>,
+}
+
+impl Joiner<'a> {
+    pub fn new(
+        udp_send: &'a UDPSender<'a>,
+        security: &'a JoinerSecurity,
+        vendor: &'a VendorInfo<'a>,
+        buf: &'static mut [u8],
+    ) -> Joiner<'a> {
+        Joiner {
+            udp_send: udp_send,
+            security: security,
+            vendor: vendor,
+            buf: TakeCell::new(buf),
+            client: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            pskd: OptionalCell::empty(),
+            join_fin_pending: Cell::new(false),
+            message_id: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a JoinerClient) {
+        self.client.set(client);
+    }
+
+    /// Starts commissioning with the joiner's pre-shared key `pskd`. The
+    /// client is notified once the attempt completes. Returns `EBUSY` if
+    /// commissioning is already in progress.
+    pub fn start(&self, pskd: &'a [u8]) -> ReturnCode {
+        if self.state.get() != State::Idle {
+            return ReturnCode::EBUSY;
+        }
+        self.pskd.set(pskd);
+        self.state.set(State::Discovering);
+        let rval = self.send_with(ALL_NODES, MLE_PORT, MLE_PORT, |buf| {
+            encode_discovery_request(buf).ok_or(ReturnCode::ESIZE)
+        });
+        if rval != ReturnCode::SUCCESS {
+            self.state.set(State::Idle);
+        }
+        rval
+    }
+
+    /// Abandons commissioning. The client is not notified.
+    pub fn cancel(&self) {
+        self.state.set(State::Idle);
+        self.join_fin_pending.set(false);
+    }
+
+    /// Builds a message in the buffer with `build`, which returns its length,
+    /// and sends it.
+    fn send_with<F>(&self, dest: IPAddr, dst_port: u16, src_port: u16, build: F) -> ReturnCode
+    where
+        F: FnOnce(&mut [u8]) -> Result<usize, ReturnCode>,
+    {
+        self.buf
+            .map_or(ReturnCode::EBUSY, |buf| match build(buf) {
+                Ok(len) => self.udp_send.send_to(dest, dst_port, src_port, &buf[..len]),
+                Err(err) => err,
+            })
+    }
+
+    /// Ends the commissioning attempt and notifies the client.
+    fn finish(&self, result: ReturnCode) {
+        self.cancel();
+        self.client.map(|client| client.joined(result));
+    }
+
+    /// Starts the DTLS handshake with the commissioner through the joiner
+    /// router that answered the discovery request.
+    fn start_handshake(&self, router: IPAddr, port: u16) {
+        self.state.set(State::Handshaking(router, port));
+        let security = self.security;
+        let pskd = self.pskd.unwrap_or(&[]);
+        let rval = self.send_with(router, port, JOINER_PORT, |buf| {
+            security.start_handshake(pskd, buf)
+        });
+        if rval != ReturnCode::SUCCESS {
+            self.finish(rval);
+        }
+    }
+
+    /// Processes handshake records from the commissioner, sending any reply
+    /// and then JOIN_FIN.req once the handshake completes.
+    fn continue_handshake(&self, router: IPAddr, port: u16, records: &[u8]) {
+        let security = self.security;
+        let udp_send = self.udp_send;
+        let result = self.buf.map_or(Err(ReturnCode::EBUSY), |buf| {
+            let (len, complete) = match security.continue_handshake(records, buf) {
+                HandshakeResult::InProgress(len) => (len, false),
+                HandshakeResult::Complete(len) => (len, true),
+                HandshakeResult::Failed => return Err(ReturnCode::FAIL),
+            };
+            if len > 0 {
+                let rval = udp_send.send_to(router, port, JOINER_PORT, &buf[..len]);
+                if rval != ReturnCode::SUCCESS {
+                    return Err(rval);
+                }
+            }
+            Ok((len > 0, complete))
+        });
+        match result {
+            Err(err) => self.finish(err),
+            Ok((_, false)) => {}
+            Ok((sent, true)) => {
+                self.state.set(State::Finalizing(router, port));
+                if sent {
+                    self.join_fin_pending.set(true);
+                } else {
+                    self.send_join_fin(router, port);
+                }
+            }
+        }
+    }
+
+    /// Sends JOIN_FIN.req over the established session.
+    fn send_join_fin(&self, router: IPAddr, port: u16) {
+        let message_id = self.message_id.get().wrapping_add(1);
+        self.message_id.set(message_id);
+        let security = self.security;
+        let vendor = self.vendor;
+        let rval = self.send_with(router, port, JOINER_PORT, |buf| {
+            let half = buf.len() / 2;
+            let (plaintext, record) = buf.split_at_mut(half);
+            let len = encode_join_fin_request(plaintext, message_id, vendor)
+                .ok_or(ReturnCode::ESIZE)?;
+            let record_len = security.seal(&plaintext[..len], record)?;
+            // Move the record to the start of the buffer to send it.
+            for i in 0..record_len {
+                plaintext[i] = record[i];
+            }
+            Ok(record_len)
+        });
+        if rval != ReturnCode::SUCCESS {
+            self.finish(rval);
+        }
+    }
+
+    /// Completes commissioning with JOIN_FIN.rsp.
+    fn receive_join_fin(&self, record: &[u8]) {
+        let security = self.security;
+        let accepted = self.buf.map_or(None, |buf| {
+            let len = security.open(record, buf).ok()?;
+            decode_join_fin_response(&buf[..len])
+        });
+        match accepted {
+            Some(true) => self.finish(ReturnCode::SUCCESS),
+            Some(false) => self.finish(ReturnCode::FAIL),
+            // Not a JOIN_FIN.rsp; keep waiting.
+            None => {}
+        }
+    }
+}
+
+impl UDPSendClient for Joiner<'a> {
+    fn send_done(&self, result: ReturnCode) {
+        if result != ReturnCode::SUCCESS {
+            if self.state.get() != State::Idle {
+                self.finish(result);
+            }
+            return;
+        }
+        if self.join_fin_pending.get() {
+            self.join_fin_pending.set(false);
+            if let State::Finalizing(router, port) = self.state.get() {
+                self.send_join_fin(router, port);
+            }
+        }
+    }
+}
+
+impl UDPRecvClient for Joiner<'a> {
+    fn receive(
+        &self,
+        src_addr: IPAddr,
+        _dst_addr: IPAddr,
+        src_port: u16,
+        dst_port: u16,
+        payload: &[u8],
+    ) {
+        match self.state.get() {
+            State::Discovering => {
+                if dst_port == MLE_PORT {
+                    if let Some(port) = decode_discovery_response(payload) {
+                        self.start_handshake(src_addr, port);
+                    }
+                }
+            }
+            State::Handshaking(router, port) => {
+                if src_addr == router && src_port == port && dst_port == JOINER_PORT {
+                    self.continue_handshake(router, port, payload);
+                }
+            }
+            State::Finalizing(router, port) => {
+                if src_addr == router
+                    && src_port == port
+                    && dst_port == JOINER_PORT
+                    && !self.join_fin_pending.get()
+                {
+                    self.receive_join_fin(payload);
+                }
+            }
+            State::Idle => {}
+        }
+    }
+}
+
+/// Calls `f` with each TLV in `buf`, including the type and length fields.
+/// Stops at the first TLV that does not fit in `buf`.
+fn for_each_tlv<F: FnMut(&[u8])>(buf: &[u8], mut f: F) {
+    let mut offset = 0;
+    while offset + 2 <= buf.len() {
+        let end = offset + 2 + buf[offset + 1] as usize;
+        if end > buf.len() {
+            return;
+        }
+        f(&buf[offset..end]);
+        offset = end;
+    }
+}
+
+/// Writes an MLE Discovery Request from a joiner to `buf` and returns its
+/// length.
+pub fn encode_discovery_request(buf: &mut [u8]) -> Option<usize> {
+    let mut discovery = [0u8; 4];
+    let discovery_len = CommissioningTlv::DiscoveryRequest {
+        version: THREAD_VERSION,
+        joiner: true,
+    }.encode(&mut discovery)
+        .done()?
+        .0;
+    if buf.len() < 2 {
+        return None;
+    }
+    buf[0] = MLE_SECURITY_NONE;
+    buf[1] = MLE_DISCOVERY_REQUEST;
+    let (len, _) = Tlv::ThreadDiscovery(&discovery[..discovery_len])
+        .encode(&mut buf[2..])
+        .done()?;
+    Some(2 + len)
+}
+
+/// Parses an MLE Discovery Response, returning the joiner UDP port of the
+/// router that sent it, or `None` if the message is not a discovery response
+/// or the router does not accept joiners.
+pub fn decode_discovery_response(buf: &[u8]) -> Option<u16> {
+    if buf.len() < 2 || buf[0] != MLE_SECURITY_NONE || buf[1] != MLE_DISCOVERY_RESPONSE {
+        return None;
+    }
+    let mut response = false;
+    let mut joiner_port = None;
+    for_each_tlv(&buf[2..], |tlv| {
+        if let Some((_, Tlv::ThreadDiscovery(commissioning_tlvs))) = Tlv::decode(tlv).done() {
+            for_each_tlv(commissioning_tlvs, |tlv| match CommissioningTlv::decode(tlv).done() {
+                Some((_, CommissioningTlv::DiscoveryResponse { .. })) => response = true,
+                Some((_, CommissioningTlv::JoinerUdpPort(port))) => joiner_port = Some(port),
+                _ => {}
+            });
+        }
+    });
+    if response {
+        joiner_port
+    } else {
+        None
+    }
+}
+
+/// Writes the CoAP message of JOIN_FIN.req to `buf` and returns its length.
+pub fn encode_join_fin_request(
+    buf: &mut [u8],
+    message_id: u16,
+    vendor: &VendorInfo,
+) -> Option<usize> {
+    let header = [
+        COAP_CON_HEADER,
+        COAP_POST,
+        (message_id >> 8) as u8,
+        message_id as u8,
+    ];
+    let mut offset = 0;
+    for bytes in [&header[..], &JOIN_FIN_URI_PATH[..], &[COAP_PAYLOAD_MARKER][..]].iter() {
+        buf.get_mut(offset..offset + bytes.len())?
+            .copy_from_slice(bytes);
+        offset += bytes.len();
+    }
+    let tlvs = [
+        CommissioningTlv::State(CommissioningState::Accept as u8),
+        CommissioningTlv::VendorName(vendor.name),
+        CommissioningTlv::VendorModel(vendor.model),
+        CommissioningTlv::VendorSwVersion(vendor.sw_version),
+        CommissioningTlv::VendorStackVersion(vendor.stack_version),
+    ];
+    for tlv in tlvs.iter() {
+        offset += tlv.encode(buf.get_mut(offset..)?).done()?.0;
+    }
+    Some(offset)
+}
+
+/// Parses the CoAP message of JOIN_FIN.rsp, returning whether the
+/// commissioner accepted the joiner, or `None` if the message is not a
+/// JOIN_FIN.rsp.
+pub fn decode_join_fin_response(buf: &[u8]) -> Option<bool> {
+    if buf.len() < 4 || buf[0] >> 6 != 1 || buf[1] != COAP_CHANGED {
+        return None;
+    }
+    // Skip the token and any options to find the payload.
+    let mut offset = 4 + (buf[0] & 0x0f) as usize;
+    while *buf.get(offset)? != COAP_PAYLOAD_MARKER {
+        let delta = buf[offset] >> 4;
+        let len_nibble = buf[offset] & 0x0f;
+        if delta == 15 || len_nibble == 15 {
+            return None;
+        }
+        offset += 1 + coap_extended_len(delta);
+        let len = match len_nibble {
+            13 => 13 + *buf.get(offset)? as usize,
+            14 => 269 + ((*buf.get(offset)? as usize) << 8 | *buf.get(offset + 1)? as usize),
+            _ => len_nibble as usize,
+        };
+        offset += coap_extended_len(len_nibble) + len;
+    }
+    let mut accepted = None;
+    for_each_tlv(&buf[offset + 1..], |tlv| {
+        if let Some((_, CommissioningTlv::State(state))) = CommissioningTlv::decode(tlv).done() {
+            accepted = Some(state == CommissioningState::Accept as u8);
+        }
+    });
+    accepted
+}
+
+/// The number of extended bytes following an option header byte for a delta
+/// or length nibble of `nibble`. (RFC 7252, 3.1)
+fn coap_extended_len(nibble: u8) -> usize {
+    match nibble {
+        13 => 1,
+        14 => 2,
+        _ => 0,
+    }
+}
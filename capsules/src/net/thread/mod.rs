@@ -1 +1,2 @@
 pub mod tlv;
+pub mod joiner;
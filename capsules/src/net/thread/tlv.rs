@@ -59,7 +59,7 @@
 
 use core::mem;
 use net::stream::SResult;
-use net::stream::{decode_bytes_be, decode_u16, decode_u32, decode_u8};
+use net::stream::{decode_bytes, decode_bytes_be, decode_u16, decode_u32, decode_u8};
 use net::stream::{encode_bytes, encode_bytes_be, encode_u16, encode_u32, encode_u8};
 
 const TL_WIDTH: usize = 2; // Type and length fields of TLV are each one byte.
@@ -113,10 +113,10 @@ pub enum Tlv<'a> {
     PanId
     ActiveTimestamp
     PendingTimestamp
-    ThreadDiscovery
     */
     ActiveOperationalDataset(&'a [u8]),
     PendingOperationalDataset(&'a [u8]),
+    ThreadDiscovery(&'a [u8]),
 }
 
 impl Tlv<'a> {
@@ -283,6 +283,12 @@ impl Tlv<'a> {
                 offset = enc_consume!(buf, offset; encode_bytes, network_mgmt_tlvs);
                 stream_done!(offset)
             }
+            Tlv::ThreadDiscovery(ref commissioning_tlvs) => {
+                let value_width = commissioning_tlvs.len();
+                let mut offset = enc_consume!(buf; self; encode_tl, value_width);
+                offset = enc_consume!(buf, offset; encode_bytes, commissioning_tlvs);
+                stream_done!(offset)
+            }
         }
     }
 
@@ -421,6 +427,10 @@ impl Tlv<'a> {
                 offset + length as usize,
                 Tlv::PendingOperationalDataset(&buf[offset..offset + length as usize])
             ),
+            TlvType::ThreadDiscovery => stream_done!(
+                offset + length as usize,
+                Tlv::ThreadDiscovery(&buf[offset..offset + length as usize])
+            ),
             TlvType::NotPresent => stream_err!(),
         }
     }
@@ -462,10 +472,7 @@ pub enum TlvType {
     */
     ActiveOperationalDataset = 24,
     PendingOperationalDataset = 25,
-    /*
-    TODO: Not required to implement MLE for SED
     ThreadDiscovery = 26,
-    */
     NotPresent,
 }
 
@@ -490,6 +497,7 @@ impl From<u8> for TlvType {
             18 => TlvType::Version,
             24 => TlvType::ActiveOperationalDataset,
             25 => TlvType::PendingOperationalDataset,
+            26 => TlvType::ThreadDiscovery,
             _ => TlvType::NotPresent,
         }
     }
@@ -516,6 +524,7 @@ impl<'a, 'b> From<&'a Tlv<'b>> for TlvType {
             Tlv::Version(_) => TlvType::Version,
             Tlv::ActiveOperationalDataset(_) => TlvType::ActiveOperationalDataset,
             Tlv::PendingOperationalDataset(_) => TlvType::PendingOperationalDataset,
+            Tlv::ThreadDiscovery(_) => TlvType::ThreadDiscovery,
         }
     }
 }
@@ -1500,3 +1509,264 @@ impl ChannelMaskEntry {
         )
     }
 }
+
+/// These TLVs are exchanged while commissioning a joiner onto a Thread
+/// network. The Discovery Request and Discovery Response TLVs are carried
+/// inside the value of an MLE Thread Discovery TLV, and the others in the
+/// messages exchanged between the joiner and the commissioner.
+/// See Section 8.10.3.
+pub enum CommissioningTlv<'a> {
+    State(u8),
+    JoinerUdpPort(u16),
+    JoinerIid([u8; 8]), // Joiner interface identifier length 8 bytes.
+    JoinerRouterLocator(u16),
+    JoinerRouterKek([u8; 16]), // Key encryption key length 128 bits = 16 bytes.
+    ProvisioningUrl(&'a [u8]),
+    VendorName(&'a [u8]),
+    VendorModel(&'a [u8]),
+    VendorSwVersion(&'a [u8]),
+    VendorStackVersion([u8; 6]), // Vendor stack version length 6 bytes.
+    DiscoveryRequest {
+        version: u8,
+        joiner: bool,
+    },
+    DiscoveryResponse {
+        version: u8,
+        native_commissioner: bool,
+    },
+}
+
+impl CommissioningTlv<'a> {
+    /// Serializes TLV data in `buf` into the format specific to the
+    /// Commissioning TLV type.
+    pub fn encode(&self, buf: &mut [u8]) -> SResult {
+        match *self {
+            CommissioningTlv::State(state) => {
+                let value_width = mem::size_of::<u8>();
+                let mut offset = enc_consume!(buf; self; encode_tl, value_width);
+                offset = enc_consume!(buf, offset; encode_u8, state);
+                stream_done!(offset)
+            }
+            CommissioningTlv::JoinerUdpPort(port) => {
+                let value_width = mem::size_of::<u16>();
+                let mut offset = enc_consume!(buf; self; encode_tl, value_width);
+                offset = enc_consume!(buf, offset; encode_u16, port);
+                stream_done!(offset)
+            }
+            CommissioningTlv::JoinerIid(ref iid) => {
+                let value_width = iid.len();
+                let mut offset = enc_consume!(buf; self; encode_tl, value_width);
+                offset = enc_consume!(buf, offset; encode_bytes, iid);
+                stream_done!(offset)
+            }
+            CommissioningTlv::JoinerRouterLocator(rloc_16) => {
+                let value_width = mem::size_of::<u16>();
+                let mut offset = enc_consume!(buf; self; encode_tl, value_width);
+                offset = enc_consume!(buf, offset; encode_u16, rloc_16);
+                stream_done!(offset)
+            }
+            CommissioningTlv::JoinerRouterKek(ref kek) => {
+                let value_width = kek.len();
+                let mut offset = enc_consume!(buf; self; encode_tl, value_width);
+                offset = enc_consume!(buf, offset; encode_bytes, kek);
+                stream_done!(offset)
+            }
+            CommissioningTlv::ProvisioningUrl(value)
+            | CommissioningTlv::VendorName(value)
+            | CommissioningTlv::VendorModel(value)
+            | CommissioningTlv::VendorSwVersion(value) => {
+                // Provisioning URL max length 64 bytes. Vendor strings are
+                // shorter. (See 8.10.3)
+                stream_cond!(value.len() <= 64);
+                let value_width = value.len();
+                let mut offset = enc_consume!(buf; self; encode_tl, value_width);
+                offset = enc_consume!(buf, offset; encode_bytes, value);
+                stream_done!(offset)
+            }
+            CommissioningTlv::VendorStackVersion(ref version) => {
+                let value_width = version.len();
+                let mut offset = enc_consume!(buf; self; encode_tl, value_width);
+                offset = enc_consume!(buf, offset; encode_bytes, version);
+                stream_done!(offset)
+            }
+            CommissioningTlv::DiscoveryRequest { version, joiner } => {
+                let value_width = mem::size_of::<u16>();
+                let mut offset = enc_consume!(buf; self; encode_tl, value_width);
+                let j_bit = if joiner { DISCOVERY_FLAG } else { 0 };
+                offset = enc_consume!(buf, offset; encode_u8, version << 4 | j_bit);
+                offset = enc_consume!(buf, offset; encode_u8, 0);
+                stream_done!(offset)
+            }
+            CommissioningTlv::DiscoveryResponse {
+                version,
+                native_commissioner,
+            } => {
+                let value_width = mem::size_of::<u16>();
+                let mut offset = enc_consume!(buf; self; encode_tl, value_width);
+                let n_bit = if native_commissioner {
+                    DISCOVERY_FLAG
+                } else {
+                    0
+                };
+                offset = enc_consume!(buf, offset; encode_u8, version << 4 | n_bit);
+                offset = enc_consume!(buf, offset; encode_u8, 0);
+                stream_done!(offset)
+            }
+        }
+    }
+
+    fn encode_tl(&self, buf: &mut [u8], value_width: usize) -> SResult {
+        stream_len_cond!(buf, TL_WIDTH + value_width);
+        buf[0] = CommissioningTlvType::from(self) as u8;
+        buf[1] = value_width as u8;
+        stream_done!(TL_WIDTH)
+    }
+
+    /// Deserializes TLV data from `buf` into the Commissioning TLV variant
+    /// specific to the TLV type.
+    /// `SResult::Error` is returned if the type field does not match any
+    /// implemented TLV type.
+    pub fn decode(buf: &[u8]) -> SResult<CommissioningTlv> {
+        let (offset, tlv_type_raw) = dec_try!(buf; decode_u8);
+        let tlv_type = CommissioningTlvType::from(tlv_type_raw);
+        let (offset, length) = dec_try!(buf, offset; decode_u8);
+        let length = length as usize;
+        stream_len_cond!(buf, offset + length);
+        match tlv_type {
+            CommissioningTlvType::State => {
+                let (offset, state) = dec_try!(buf, offset; decode_u8);
+                stream_done!(offset, CommissioningTlv::State(state))
+            }
+            CommissioningTlvType::JoinerUdpPort => {
+                let (offset, port) = dec_try!(buf, offset; decode_u16);
+                stream_done!(offset, CommissioningTlv::JoinerUdpPort(port))
+            }
+            CommissioningTlvType::JoinerIid => {
+                let mut iid = [0u8; 8];
+                let offset = dec_consume!(buf, offset; decode_bytes, &mut iid);
+                stream_done!(offset, CommissioningTlv::JoinerIid(iid))
+            }
+            CommissioningTlvType::JoinerRouterLocator => {
+                let (offset, rloc_16) = dec_try!(buf, offset; decode_u16);
+                stream_done!(offset, CommissioningTlv::JoinerRouterLocator(rloc_16))
+            }
+            CommissioningTlvType::JoinerRouterKek => {
+                let mut kek = [0u8; 16];
+                let offset = dec_consume!(buf, offset; decode_bytes, &mut kek);
+                stream_done!(offset, CommissioningTlv::JoinerRouterKek(kek))
+            }
+            CommissioningTlvType::ProvisioningUrl => stream_done!(
+                offset + length,
+                CommissioningTlv::ProvisioningUrl(&buf[offset..offset + length])
+            ),
+            CommissioningTlvType::VendorName => stream_done!(
+                offset + length,
+                CommissioningTlv::VendorName(&buf[offset..offset + length])
+            ),
+            CommissioningTlvType::VendorModel => stream_done!(
+                offset + length,
+                CommissioningTlv::VendorModel(&buf[offset..offset + length])
+            ),
+            CommissioningTlvType::VendorSwVersion => stream_done!(
+                offset + length,
+                CommissioningTlv::VendorSwVersion(&buf[offset..offset + length])
+            ),
+            CommissioningTlvType::VendorStackVersion => {
+                let mut version = [0u8; 6];
+                let offset = dec_consume!(buf, offset; decode_bytes, &mut version);
+                stream_done!(offset, CommissioningTlv::VendorStackVersion(version))
+            }
+            CommissioningTlvType::DiscoveryRequest => {
+                let (_, flags) = dec_try!(buf, offset; decode_u8);
+                stream_done!(
+                    offset + length,
+                    CommissioningTlv::DiscoveryRequest {
+                        version: flags >> 4,
+                        joiner: flags & DISCOVERY_FLAG != 0,
+                    }
+                )
+            }
+            CommissioningTlvType::DiscoveryResponse => {
+                let (_, flags) = dec_try!(buf, offset; decode_u8);
+                stream_done!(
+                    offset + length,
+                    CommissioningTlv::DiscoveryResponse {
+                        version: flags >> 4,
+                        native_commissioner: flags & DISCOVERY_FLAG != 0,
+                    }
+                )
+            }
+            CommissioningTlvType::NotPresent => stream_err!(),
+        }
+    }
+}
+
+/// The Joiner flag of a Discovery Request TLV and the Native Commissioner
+/// flag of a Discovery Response TLV, following the 4-bit version.
+const DISCOVERY_FLAG: u8 = 0b0000_1000;
+
+/// Value encoded in the type field of a Commissioning TLV.
+#[repr(u8)]
+pub enum CommissioningTlvType {
+    State = 16,
+    JoinerUdpPort = 18,
+    JoinerIid = 19,
+    JoinerRouterLocator = 20,
+    JoinerRouterKek = 21,
+    ProvisioningUrl = 32,
+    VendorName = 33,
+    VendorModel = 34,
+    VendorSwVersion = 35,
+    VendorStackVersion = 37,
+    DiscoveryRequest = 128,
+    DiscoveryResponse = 129,
+    NotPresent,
+}
+
+impl From<u8> for CommissioningTlvType {
+    fn from(type_num: u8) -> Self {
+        match type_num {
+            16 => CommissioningTlvType::State,
+            18 => CommissioningTlvType::JoinerUdpPort,
+            19 => CommissioningTlvType::JoinerIid,
+            20 => CommissioningTlvType::JoinerRouterLocator,
+            21 => CommissioningTlvType::JoinerRouterKek,
+            32 => CommissioningTlvType::ProvisioningUrl,
+            33 => CommissioningTlvType::VendorName,
+            34 => CommissioningTlvType::VendorModel,
+            35 => CommissioningTlvType::VendorSwVersion,
+            37 => CommissioningTlvType::VendorStackVersion,
+            128 => CommissioningTlvType::DiscoveryRequest,
+            129 => CommissioningTlvType::DiscoveryResponse,
+            _ => CommissioningTlvType::NotPresent,
+        }
+    }
+}
+
+impl<'a, 'b> From<&'a CommissioningTlv<'b>> for CommissioningTlvType {
+    fn from(commissioning_tlv: &'a CommissioningTlv<'b>) -> Self {
+        match *commissioning_tlv {
+            CommissioningTlv::State(_) => CommissioningTlvType::State,
+            CommissioningTlv::JoinerUdpPort(_) => CommissioningTlvType::JoinerUdpPort,
+            CommissioningTlv::JoinerIid(_) => CommissioningTlvType::JoinerIid,
+            CommissioningTlv::JoinerRouterLocator(_) => CommissioningTlvType::JoinerRouterLocator,
+            CommissioningTlv::JoinerRouterKek(_) => CommissioningTlvType::JoinerRouterKek,
+            CommissioningTlv::ProvisioningUrl(_) => CommissioningTlvType::ProvisioningUrl,
+            CommissioningTlv::VendorName(_) => CommissioningTlvType::VendorName,
+            CommissioningTlv::VendorModel(_) => CommissioningTlvType::VendorModel,
+            CommissioningTlv::VendorSwVersion(_) => CommissioningTlvType::VendorSwVersion,
+            CommissioningTlv::VendorStackVersion(_) => CommissioningTlvType::VendorStackVersion,
+            CommissioningTlv::DiscoveryRequest { .. } => CommissioningTlvType::DiscoveryRequest,
+            CommissioningTlv::DiscoveryResponse { .. } => CommissioningTlvType::DiscoveryResponse,
+        }
+    }
+}
+
+/// Values of the State TLV.
+/// See 8.10.3.1
+#[repr(u8)]
+pub enum CommissioningState {
+    Pending = 0,
+    Accept = 1,
+    Reject = 0xff, // -1 as a signed byte.
+}
@@ -10,6 +10,7 @@ use core::cell::Cell;
 use core::{cmp, mem};
 use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
 use net::ipv6::ip_utils::IPAddr;
+use net::ipv6::ipv6::DEFAULT_HOP_LIMIT;
 use net::stream::encode_u16;
 use net::stream::encode_u8;
 use net::stream::SResult;
@@ -64,6 +65,10 @@ pub struct App {
     app_rx_cfg: Option<AppSlice<Shared, u8>>,
     pending_tx: Option<[UDPEndpoint; 2]>,
     bound_port: Option<UDPEndpoint>,
+    /// Hop limit of the app's packets, or `None` for the default.
+    hop_limit: Option<u8>,
+    /// Traffic class (DSCP and ECN) of the app's packets.
+    traffic_class: u8,
 }
 
 #[allow(dead_code)]
@@ -247,6 +252,11 @@ impl<'a> UDPDriver<'a> {
             let dst_port = addr_ports[1].port;
             let src_port = addr_ports[0].port;
 
+            self.sender
+                .set_hop_limit(app.hop_limit.unwrap_or(DEFAULT_HOP_LIMIT));
+            self.sender
+                .set_traffic_class(app.traffic_class >> 2, app.traffic_class & 0b11);
+
             // Send UDP payload. Payload will be copied into IP6Packet in kernel mem.
             let result = app
                 .app_write
@@ -429,6 +439,11 @@ impl<'a> Driver for UDPDriver<'a> {
     /// - `4`: Returns the maximum payload that can be transmitted by apps using this driver.
     ///        This represents the size of the payload buffer in the kernel. Apps can use this
     ///        syscall to ensure they do not attempt to send too-large messages.
+    /// - `5`: Set the hop limit of packets this app sends to `arg1`. If `arg1` is 0, the
+    ///        default hop limit is used. Returns EINVAL if `arg1` is greater than 255.
+    /// - `6`: Set the traffic class of packets this app sends to `arg1`: the DSCP in the
+    ///        upper 6 bits and the ECN in the lower 2 bits. Returns EINVAL if `arg1` is
+    ///        greater than 255.
 
     fn command(&self, command_num: usize, arg1: usize, _: usize, appid: AppId) -> ReturnCode {
         match command_num {
@@ -550,6 +565,20 @@ impl<'a> Driver for UDPDriver<'a> {
             4 => ReturnCode::SuccessWithValue {
                 value: self.max_tx_pyld_len,
             },
+            5 => self.do_with_app(appid, |app| {
+                if arg1 > u8::max_value() as usize {
+                    return ReturnCode::EINVAL;
+                }
+                app.hop_limit = if arg1 == 0 { None } else { Some(arg1 as u8) };
+                ReturnCode::SUCCESS
+            }),
+            6 => self.do_with_app(appid, |app| {
+                if arg1 > u8::max_value() as usize {
+                    return ReturnCode::EINVAL;
+                }
+                app.traffic_class = arg1 as u8;
+                ReturnCode::SUCCESS
+            }),
             _ => ReturnCode::ENOSUPPORT,
         }
     }
@@ -32,6 +32,21 @@ pub trait UDPSender<'a> {
     /// for the `UDPSender` instance
     fn set_client(&self, client: &'a UDPSendClient);
 
+    /// This function sets the hop limit of subsequently sent packets.
+    ///
+    /// # Arguments
+    /// `hop_limit` - Hop limit written into the IPv6 header
+    fn set_hop_limit(&self, hop_limit: u8);
+
+    /// This function sets the traffic class of subsequently sent packets,
+    /// e.g. to mark them for a QoS class.
+    ///
+    /// # Arguments
+    /// `dscp` - Differentiated services code point written into the IPv6
+    /// header
+    /// `ecn` - Explicit congestion notification written into the IPv6 header
+    fn set_traffic_class(&self, dscp: u8, ecn: u8);
+
     /// This function constructs a `UDPHeader` and sends the payload to the
     /// provided destination IP address over the provided source and
     /// destination ports.
@@ -76,6 +91,14 @@ impl<T: IP6Sender<'a>> UDPSender<'a> for UDPSendStruct<'a, T> {
         self.client.set(client);
     }
 
+    fn set_hop_limit(&self, hop_limit: u8) {
+        self.ip_send_struct.set_hop_limit(hop_limit);
+    }
+
+    fn set_traffic_class(&self, dscp: u8, ecn: u8) {
+        self.ip_send_struct.set_traffic_class(dscp, ecn);
+    }
+
     fn send_to(&self, dest: IPAddr, dst_port: u16, src_port: u16, buf: &[u8]) -> ReturnCode {
         let mut udp_header = UDPHeader::new();
         udp_header.set_dst_port(dst_port);
@@ -0,0 +1,350 @@
+//! Samples a sensor periodically on behalf of multiple apps.
+//!
+//! Each app asks for a reading every `interval` milliseconds. Rather than each
+//! app running its own timer and triggering its own reads, the sampler keeps a
+//! single alarm armed for the nearest deadline and takes one hardware sample
+//! per tick. Every app whose deadline has passed when the alarm fires receives
+//! the result of that sample, so apps with the same or overlapping intervals
+//! share samples. A kernel client, if set, receives every sample taken.
+//!
+//! The sampler reads any sensor that implements `SampleSource`. This module
+//! provides sources for a `hil::sensors::TemperatureDriver` and for a channel
+//! of a `hil::adc::Adc`.
+//!
+//! Deadlines are advanced relative to when they were due rather than when the
+//! alarm fired, so intervals do not drift.
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `subscribe` System Call
+//!
+//! * `0`: Set the callback that receives each sample. The callback's first
+//!   argument is the reading.
+//!
+//! ### `command` System Call
+//!
+//! * `0`: Check whether the driver exists.
+//! * `1`: Start sampling every `data` milliseconds, replacing any interval
+//!   set before. Returns `EINVAL` if `data` is zero.
+//! * `2`: Stop sampling. Returns `EALREADY` if sampling was not started.
+//!
+//! Usage
+//! -----
+//!
+//! You need a `SampleSource` and a virtual alarm. To sample a device that
+//! provides the `hil::sensors::TemperatureDriver` trait:
+//!
+//! ```rust
+//! let sampler_source = static_init!(
+//!     capsules::periodic_sampler::TemperatureSource<'static>,
+//!     capsules::periodic_sampler::TemperatureSource::new(virtual_temp)
+//! );
+//! kernel::hil::sensors::TemperatureDriver::set_client(virtual_temp, sampler_source);
+//! let sampler_alarm = static_init!(
+//!     capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
+//!     capsules::virtual_alarm::VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! let sampler = static_init!(
+//!     capsules::periodic_sampler::PeriodicSampler<
+//!         'static,
+//!         capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
+//!     >,
+//!     capsules::periodic_sampler::PeriodicSampler::new(
+//!         sampler_source,
+//!         sampler_alarm,
+//!         board_kernel.create_grant(&memory_allocation_capability)
+//!     )
+//! );
+//! sampler_alarm.set_client(sampler);
+//! capsules::periodic_sampler::SampleSource::set_client(sampler_source, sampler);
+//! ```
+//!
+//! To sample an ADC channel instead, use an `AdcSource`:
+//!
+//! ```rust
+//! let sampler_source = static_init!(
+//!     capsules::periodic_sampler::AdcSource<'static, nrf52::adc::Adc>,
+//!     capsules::periodic_sampler::AdcSource::new(
+//!         &nrf52::adc::ADC,
+//!         &nrf52::adc::AdcChannel::AnalogInput0
+//!     )
+//! );
+//! nrf52::adc::ADC.set_client(sampler_source);
+//! ```
+
+use core::cell::Cell;
+use kernel::common::cells::OptionalCell;
+use kernel::hil::adc;
+use kernel::hil::sensors::{TemperatureClient, TemperatureDriver};
+use kernel::hil::time::{self, Alarm, Frequency};
+use kernel::{AppId, Callback, Driver, Grant, ReturnCode};
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = 0x60005;
+
+/// A client that receives every sample the sampler takes.
+pub trait SampleClient {
+    fn sample(&self, value: usize);
+}
+
+/// A sensor that the sampler can read.
+pub trait SampleSource<'a> {
+    fn set_client(&self, client: &'a SampleSourceClient);
+
+    /// Starts a single reading. The client is called with the value once it
+    /// is ready.
+    fn sample(&self) -> ReturnCode;
+}
+
+/// The client of a `SampleSource`.
+pub trait SampleSourceClient {
+    fn sample_ready(&self, value: usize);
+}
+
+/// Samples a temperature sensor, in hundredths of degrees centigrade.
+pub struct TemperatureSource<'a> {
+    sensor: &'a TemperatureDriver,
+    client: OptionalCell<&'a SampleSourceClient>,
+}
+
+impl TemperatureSource<'a> {
+    pub fn new(sensor: &'a TemperatureDriver) -> TemperatureSource<'a> {
+        TemperatureSource {
+            sensor: sensor,
+            client: OptionalCell::empty(),
+        }
+    }
+}
+
+impl SampleSource<'a> for TemperatureSource<'a> {
+    fn set_client(&self, client: &'a SampleSourceClient) {
+        self.client.set(client);
+    }
+
+    fn sample(&self) -> ReturnCode {
+        self.sensor.read_temperature()
+    }
+}
+
+impl TemperatureClient for TemperatureSource<'a> {
+    fn callback(&self, value: usize) {
+        self.client.map(|client| client.sample_ready(value));
+    }
+}
+
+/// Samples one channel of an ADC, as the raw left-justified reading.
+pub struct AdcSource<'a, A: adc::Adc + 'a> {
+    adc: &'a A,
+    channel: &'a A::Channel,
+    client: OptionalCell<&'a SampleSourceClient>,
+}
+
+impl<A: adc::Adc> AdcSource<'a, A> {
+    pub fn new(adc: &'a A, channel: &'a A::Channel) -> AdcSource<'a, A> {
+        AdcSource {
+            adc: adc,
+            channel: channel,
+            client: OptionalCell::empty(),
+        }
+    }
+}
+
+impl<A: adc::Adc> SampleSource<'a> for AdcSource<'a, A> {
+    fn set_client(&self, client: &'a SampleSourceClient) {
+        self.client.set(client);
+    }
+
+    fn sample(&self) -> ReturnCode {
+        self.adc.sample(self.channel)
+    }
+}
+
+impl<A: adc::Adc> adc::Client for AdcSource<'a, A> {
+    fn sample_ready(&self, sample: u16) {
+        self.client.map(|client| client.sample_ready(sample as usize));
+    }
+}
+
+#[derive(Default)]
+pub struct App {
+    callback: Option<Callback>,
+    /// The sampling interval in alarm tics, or `None` if the app is not
+    /// sampling.
+    interval: Option<u32>,
+    /// When the app's next sample is due.
+    next: u32,
+    /// Whether the app is waiting for the sample in progress.
+    pending: bool,
+}
+
+pub struct PeriodicSampler<'a, A: Alarm> {
+    sensor: &'a SampleSource<'a>,
+    alarm: &'a A,
+    apps: Grant<App>,
+    client: OptionalCell<&'a SampleClient>,
+    /// Whether a hardware sample is in progress.
+    busy: Cell<bool>,
+    /// When the alarm was last armed, used to tell which deadlines passed.
+    prev: Cell<u32>,
+}
+
+fn has_expired(deadline: u32, now: u32, prev: u32) -> bool {
+    now.wrapping_sub(prev) >= deadline.wrapping_sub(prev)
+}
+
+impl<A: Alarm> PeriodicSampler<'a, A> {
+    pub fn new(
+        sensor: &'a SampleSource<'a>,
+        alarm: &'a A,
+        grant: Grant<App>,
+    ) -> PeriodicSampler<'a, A> {
+        PeriodicSampler {
+            sensor: sensor,
+            alarm: alarm,
+            apps: grant,
+            client: OptionalCell::empty(),
+            busy: Cell::new(false),
+            prev: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a SampleClient) {
+        self.client.set(client);
+    }
+
+    /// Arms the alarm for the nearest deadline, or disables it if no app is
+    /// sampling.
+    fn reset_alarm(&self, now: u32) {
+        self.prev.set(now);
+        let mut next = None;
+        for app in self.apps.iter() {
+            app.enter(|app, _| {
+                if app.interval.is_some() {
+                    let dist = app.next.wrapping_sub(now);
+                    if next.map_or(true, |(_, d)| dist < d) {
+                        next = Some((app.next, dist));
+                    }
+                }
+            });
+        }
+        match next {
+            Some((deadline, _)) => self.alarm.set_alarm(deadline),
+            None => self.alarm.disable(),
+        }
+    }
+
+    /// Starts a hardware sample unless one is already in progress, in which
+    /// case the apps that are due receive the result of that sample.
+    fn start_sample(&self) {
+        if self.busy.get() {
+            return;
+        }
+        if self.sensor.sample() == ReturnCode::SUCCESS {
+            self.busy.set(true);
+        } else {
+            // The sample for this tick is dropped; the apps receive the next.
+            self.apps.each(|app| app.pending = false);
+        }
+    }
+}
+
+impl<A: Alarm> time::Client for PeriodicSampler<'a, A> {
+    fn fired(&self) {
+        let now = self.alarm.now();
+        let prev = self.prev.get();
+        let due = Cell::new(false);
+        self.apps.each(|app| {
+            if let Some(interval) = app.interval {
+                if has_expired(app.next, now, prev) {
+                    // Skip any intervals that have already passed.
+                    let mut following = app.next.wrapping_add(interval);
+                    while has_expired(following, now, app.next) {
+                        following = following.wrapping_add(interval);
+                    }
+                    app.next = following;
+                    app.pending = true;
+                    due.set(true);
+                }
+            }
+        });
+        if due.get() {
+            self.start_sample();
+        }
+        self.reset_alarm(now);
+    }
+}
+
+impl<A: Alarm> SampleSourceClient for PeriodicSampler<'a, A> {
+    fn sample_ready(&self, value: usize) {
+        self.busy.set(false);
+        self.apps.each(|app| {
+            if app.pending {
+                app.pending = false;
+                app.callback.map(|mut cb| cb.schedule(value, 0, 0));
+            }
+        });
+        self.client.map(|client| client.sample(value));
+    }
+}
+
+impl<A: Alarm> Driver for PeriodicSampler<'a, A> {
+    /// Subscribe to samples.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: Set the callback that receives each sample.
+    fn subscribe(
+        &self,
+        subscribe_num: usize,
+        callback: Option<Callback>,
+        app_id: AppId,
+    ) -> ReturnCode {
+        match subscribe_num {
+            0 => self
+                .apps
+                .enter(app_id, |app, _| {
+                    app.callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into()),
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+
+    /// Start and stop sampling.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Sample every `data` milliseconds.
+    /// - `2`: Stop sampling.
+    fn command(&self, command_num: usize, data: usize, _: usize, app_id: AppId) -> ReturnCode {
+        let now = self.alarm.now();
+        let result = self
+            .apps
+            .enter(app_id, |app, _| match command_num {
+                0 => ReturnCode::SUCCESS,
+                1 => {
+                    let tics = data as u64 * A::Frequency::frequency() as u64 / 1000;
+                    if tics == 0 || tics > u32::max_value() as u64 {
+                        return ReturnCode::EINVAL;
+                    }
+                    app.interval = Some(tics as u32);
+                    app.next = now.wrapping_add(tics as u32);
+                    ReturnCode::SUCCESS
+                }
+                2 => {
+                    if app.interval.take().is_none() {
+                        return ReturnCode::EALREADY;
+                    }
+                    app.pending = false;
+                    ReturnCode::SUCCESS
+                }
+                _ => ReturnCode::ENOSUPPORT,
+            }).unwrap_or_else(|err| err.into());
+        if result == ReturnCode::SUCCESS && (command_num == 1 || command_num == 2) {
+            self.reset_alarm(now);
+        }
+        result
+    }
+}
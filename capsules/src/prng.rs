@@ -0,0 +1,355 @@
+//! Provides userspace applications with a seedable pseudo-random number
+//! generator.
+//!
+//! Unlike the RNG driver, this driver produces a deterministic stream: two
+//! generators seeded with the same value always produce the same sequence of
+//! bytes. This is useful for reproducible tests and for applications that
+//! need to regenerate a stream from a stored seed. Each app has its own
+//! generator, which can be seeded either with a 64-bit value supplied by the
+//! app or with fresh randomness from the hardware random number generator.
+//!
+//! The generator is xoshiro128**, and seeds are expanded into its 128-bit
+//! state with SplitMix64. It is fast and statistically strong but is not a
+//! cryptographic generator, and should not be used to produce keys.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let entropy_to_random = static_init!(
+//!     capsules::rng::Entropy32ToRandom<'static>,
+//!     capsules::rng::Entropy32ToRandom::new(&nrf5x::trng::TRNG)
+//! );
+//! let prng = static_init!(
+//!     capsules::prng::PrngDriver<'static>,
+//!     capsules::prng::PrngDriver::new(
+//!         entropy_to_random,
+//!         board_kernel.create_grant(&memory_allocation_capability)
+//!     )
+//! );
+//! nrf5x::trng::TRNG.set_client(entropy_to_random);
+//! entropy_to_random.set_client(prng);
+//! ```
+
+use core::cell::Cell;
+use kernel::hil::rng;
+use kernel::hil::rng::Rng;
+use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
+
+/// Syscall number
+pub const DRIVER_NUM: usize = 0x40003;
+
+/// Number of 32-bit words of hardware randomness used to seed a generator.
+const SEED_WORDS: usize = 4;
+
+/// The xoshiro128** generator.
+#[derive(Copy, Clone, Default)]
+pub struct Xoshiro128 {
+    state: [u32; 4],
+}
+
+impl Xoshiro128 {
+    /// Creates a generator whose state is expanded from a 64-bit seed with
+    /// SplitMix64, so that similar seeds still yield unrelated streams.
+    pub fn from_seed(seed: u64) -> Xoshiro128 {
+        let mut sm = seed;
+        let a = splitmix64(&mut sm);
+        let b = splitmix64(&mut sm);
+        Xoshiro128::from_state([a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32])
+    }
+
+    /// Creates a generator with the given state. The all-zero state is a
+    /// fixed point of the generator, so it is replaced with a fixed non-zero
+    /// state.
+    pub fn from_state(state: [u32; 4]) -> Xoshiro128 {
+        if state.iter().all(|word| *word == 0) {
+            Xoshiro128::from_seed(0)
+        } else {
+            Xoshiro128 { state: state }
+        }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 9;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(11);
+
+        result
+    }
+
+    /// Fills `buf` with the next bytes of the stream. Each word is consumed
+    /// least significant byte first, and the unused bytes of a final partial
+    /// word are discarded.
+    pub fn fill_bytes(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(4) {
+            let word = self.next_u32();
+            for (i, b) in chunk.iter_mut().enumerate() {
+                *b = (word >> (i * 8)) as u8;
+            }
+        }
+    }
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[derive(Default)]
+pub struct App {
+    callback: Option<Callback>,
+    buffer: Option<AppSlice<Shared, u8>>,
+    generator: Option<Xoshiro128>,
+    /// Whether the app is waiting for its generator to be seeded from the
+    /// hardware random number generator.
+    reseeding: bool,
+}
+
+pub struct PrngDriver<'a> {
+    rng: &'a Rng<'a>,
+    apps: Grant<App>,
+    getting_randomness: Cell<bool>,
+}
+
+impl PrngDriver<'a> {
+    pub fn new(rng: &'a Rng<'a>, grant: Grant<App>) -> PrngDriver<'a> {
+        PrngDriver {
+            rng: rng,
+            apps: grant,
+            getting_randomness: Cell::new(false),
+        }
+    }
+
+    fn reseed_from_hardware(&self, appid: AppId) -> ReturnCode {
+        self.apps
+            .enter(appid, |app, _| {
+                if app.reseeding {
+                    return ReturnCode::EBUSY;
+                }
+                if !self.getting_randomness.get() {
+                    let rval = self.rng.get();
+                    if rval != ReturnCode::SUCCESS {
+                        return rval;
+                    }
+                    self.getting_randomness.set(true);
+                }
+                app.reseeding = true;
+                ReturnCode::SUCCESS
+            }).unwrap_or_else(|err| err.into())
+    }
+
+    /// Fills the app's buffer with up to `len` bytes of the app's stream.
+    fn generate(&self, appid: AppId, len: usize) -> ReturnCode {
+        self.apps
+            .enter(appid, |app, _| {
+                if app.reseeding {
+                    return ReturnCode::EBUSY;
+                }
+                let mut generator = match app.generator {
+                    Some(generator) => generator,
+                    None => return ReturnCode::ERESERVE,
+                };
+                let rval = match app.buffer {
+                    Some(ref mut buffer) => {
+                        let len = if len > buffer.len() {
+                            buffer.len()
+                        } else {
+                            len
+                        };
+                        generator.fill_bytes(&mut buffer.as_mut()[..len]);
+                        ReturnCode::SuccessWithValue { value: len }
+                    }
+                    None => ReturnCode::ERESERVE,
+                };
+                app.generator = Some(generator);
+                rval
+            }).unwrap_or_else(|err| err.into())
+    }
+}
+
+impl rng::Client for PrngDriver<'a> {
+    fn randomness_available(
+        &self,
+        randomness: &mut Iterator<Item = u32>,
+        _error: ReturnCode,
+    ) -> rng::Continue {
+        let mut done = true;
+        for cntr in self.apps.iter() {
+            cntr.enter(|app, _| {
+                if !app.reseeding {
+                    return;
+                }
+                let mut state = [0; SEED_WORDS];
+                for word in state.iter_mut() {
+                    match randomness.next() {
+                        Some(random) => *word = random,
+                        None => {
+                            done = false;
+                            return;
+                        }
+                    }
+                }
+                app.generator = Some(Xoshiro128::from_state(state));
+                app.reseeding = false;
+                app.callback.map(|mut cb| cb.schedule(0, 0, 0));
+            });
+
+            // If this app did not get enough randomness, there is none left
+            // for the apps after it either.
+            if !done {
+                break;
+            }
+        }
+
+        if done {
+            self.getting_randomness.set(false);
+            rng::Continue::Done
+        } else {
+            rng::Continue::More
+        }
+    }
+}
+
+impl Driver for PrngDriver<'a> {
+    /// Setup the buffer that generated bytes are written to.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: Buffer for generated bytes.
+    fn allow(
+        &self,
+        appid: AppId,
+        allow_num: usize,
+        slice: Option<AppSlice<Shared, u8>>,
+    ) -> ReturnCode {
+        match allow_num {
+            0 => self
+                .apps
+                .enter(appid, |app, _| {
+                    app.buffer = slice;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into()),
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+
+    /// Subscribe to reseed completion.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: Called when the generator has been seeded from the hardware
+    ///   random number generator.
+    fn subscribe(
+        &self,
+        subscribe_num: usize,
+        callback: Option<Callback>,
+        app_id: AppId,
+    ) -> ReturnCode {
+        match subscribe_num {
+            0 => self
+                .apps
+                .enter(app_id, |app, _| {
+                    app.callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into()),
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+
+    /// Seed the generator or generate bytes.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Seed the generator with the 64-bit value whose low word is
+    ///   `data` and high word is `data2`.
+    /// - `2`: Seed the generator from the hardware random number generator.
+    ///   The callback is called once the generator is seeded.
+    /// - `3`: Write the next `data` bytes of the stream to the buffer,
+    ///   limited to the buffer length. Returns the number of bytes written,
+    ///   or `ERESERVE` if there is no buffer or the generator is not seeded.
+    fn command(&self, command_num: usize, data: usize, data2: usize, appid: AppId) -> ReturnCode {
+        match command_num {
+            0 => ReturnCode::SUCCESS,
+
+            1 => self
+                .apps
+                .enter(appid, |app, _| {
+                    if app.reseeding {
+                        return ReturnCode::EBUSY;
+                    }
+                    let seed = (data as u32 as u64) | ((data2 as u32 as u64) << 32);
+                    app.generator = Some(Xoshiro128::from_seed(seed));
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into()),
+
+            2 => self.reseed_from_hardware(appid),
+
+            3 => self.generate(appid, data),
+
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(mut generator: Xoshiro128) -> [u8; 37] {
+        let mut buf = [0; 37];
+        generator.fill_bytes(&mut buf);
+        buf
+    }
+
+    #[test]
+    fn same_seed_gives_same_stream() {
+        let first = stream(Xoshiro128::from_seed(0x1234_5678_9abc_def0));
+        let second = stream(Xoshiro128::from_seed(0x1234_5678_9abc_def0));
+        assert_eq!(&first[..], &second[..]);
+
+        // A reseeded generator starts the stream over.
+        let mut generator = Xoshiro128::from_seed(7);
+        let start = generator.next_u32();
+        generator.next_u32();
+        assert_eq!(Xoshiro128::from_seed(7).next_u32(), start);
+    }
+
+    #[test]
+    fn different_seeds_give_different_streams() {
+        // Seeds that differ in a single bit still give unrelated streams.
+        let first = stream(Xoshiro128::from_seed(0));
+        let second = stream(Xoshiro128::from_seed(1));
+        let same = first.iter().zip(second.iter()).filter(|&(a, b)| a == b);
+        assert!(same.count() < 8);
+    }
+
+    #[test]
+    fn bytes_are_taken_least_significant_first() {
+        let mut words = Xoshiro128::from_seed(42);
+        let word = words.next_u32();
+        let mut bytes = Xoshiro128::from_seed(42);
+        let mut buf = [0; 3];
+        bytes.fill_bytes(&mut buf);
+        assert_eq!(buf, [word as u8, (word >> 8) as u8, (word >> 16) as u8]);
+        // The rest of the partial word is discarded.
+        assert_eq!(bytes.next_u32(), words.next_u32());
+    }
+
+    #[test]
+    fn zero_state_is_replaced() {
+        let first = stream(Xoshiro128::from_state([0; 4]));
+        let second = stream(Xoshiro128::from_seed(0));
+        assert_eq!(&first[..], &second[..]);
+    }
+}
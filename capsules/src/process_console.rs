@@ -5,12 +5,13 @@
 //! --------
 //!
 //! This module provides a simple text-based console to inspect and control
-//! which processes are running. The console has five commands:
+//! which processes are running. The console has six commands:
 //!  - 'help' prints the available commands and arguments
 //!  - 'status' prints the current system status
 //!  - 'list' lists the current processes with their IDs and running state
 //!  - 'stop n' stops the process with name n
 //!  - 'start n' starts the stopped process with name n
+//!  - 'terminate n' permanently stops the process with name n
 //!
 //! Setup
 //! -----
@@ -191,7 +192,7 @@ impl<U: UART, C: ProcessManagementCapability> ProcessConsole<'a, U, C> {
                         let clean_str = s.trim();
                         if clean_str.starts_with("help") {
                             debug!("Welcome to the process console.");
-                            debug!("Valid commands are: help status list stop start");
+                            debug!("Valid commands are: help status list stop start terminate");
                         } else if clean_str.starts_with("start") {
                             let argument = clean_str.split_whitespace().nth(1);
                             argument.map(|name| {
@@ -220,6 +221,20 @@ impl<U: UART, C: ProcessManagementCapability> ProcessConsole<'a, U, C> {
                                     },
                                 );
                             });
+                        } else if clean_str.starts_with("terminate") {
+                            let argument = clean_str.split_whitespace().nth(1);
+                            argument.map(|name| {
+                                self.kernel.process_each_capability(
+                                    &self.capability,
+                                    |_i, proc| {
+                                        let proc_name = proc.get_process_name();
+                                        if proc_name == name {
+                                            proc.terminate();
+                                            debug!("Process {} terminated", proc_name);
+                                        }
+                                    },
+                                );
+                            });
                         } else if clean_str.starts_with("list") {
                             debug!(" PID    Name                Quanta  Syscalls  Dropped Callbacks    State");
                             self.kernel
@@ -250,7 +265,7 @@ impl<U: UART, C: ProcessManagementCapability> ProcessConsole<'a, U, C> {
                                 info.timeslice_expirations(&self.capability)
                             );
                         } else {
-                            debug!("Valid commands are: help status list stop start");
+                            debug!("Valid commands are: help status list stop start terminate");
                         }
                     }
                     Err(_e) => debug!("Invalid command: {:?}", command),
@@ -0,0 +1,284 @@
+//! Lets a designated supervisor app control other processes.
+//!
+//! The board designates the process in one slot of its process array as the
+//! supervisor. Only that process may use this driver; calls from any other
+//! app return `EINVAL`. Apps are loaded into slots in the order they appear in
+//! flash, so the board picks the slot its supervisor app is installed into.
+//! Package names are chosen by each app, so they are only used to pick the
+//! target of a command, never to grant access. The supervisor shares a buffer
+//! with the name of the target process and then issues a command.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! struct ProcessMgmtCap;
+//! unsafe impl capabilities::ProcessManagementCapability for ProcessMgmtCap {}
+//! let process_supervisor = static_init!(
+//!     capsules::process_supervisor::ProcessSupervisor<ProcessMgmtCap>,
+//!     capsules::process_supervisor::ProcessSupervisor::new(
+//!         board_kernel,
+//!         0,
+//!         board_kernel.create_grant(&memory_allocation_capability),
+//!         ProcessMgmtCap
+//!     )
+//! );
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! - Allow 0: buffer containing the package name of the target process.
+//! - Allow 1: buffer that process information is copied into.
+//! - Command 0: check whether the driver exists.
+//! - Command 1: terminate the named process.
+//! - Command 2: restart the named process from its init function.
+//! - Command 3: return the number of loaded processes.
+//! - Command 4: copy information about loaded process `data` (counting from
+//!   0) into the allow 1 buffer.
+//! - Command 5: reset the syscall and dropped callback counts of the named
+//!   process, so they can be sampled over an interval. The restart count is
+//!   kept.
+//!
+//! Commands 1, 2 and 5 return `ENODEVICE` if no process has the shared name.
+//! Commands 1 and 2 return `EINVAL` if the shared name is the supervisor's
+//! own, as the supervisor would not survive to receive the return value.
+//!
+//! Process Information
+//! -------------------
+//!
+//! Command 4 returns `ENODEVICE` if there are not that many loaded processes,
+//! `EINVAL` if no buffer was shared, and `ESIZE` if the buffer is shorter
+//! than the fixed fields. Otherwise it fills the buffer with the following
+//! little-endian fields and returns the number of bytes written:
+//!
+//! | Offset | Size | Field                                                  |
+//! |--------|------|--------------------------------------------------------|
+//! | 0      | 4    | State (see below)                                      |
+//! | 4      | 4    | Number of syscalls made                                |
+//! | 8      | 4    | Number of restarts                                     |
+//! | 12     | 4    | Number of dropped callbacks                            |
+//! | 16     | 4    | Length of the package name                             |
+//! | 20     | n    | Package name, truncated to fit the buffer              |
+//!
+//! States are encoded as 0: running, 1: yielded, 2: stopped while running,
+//! 3: stopped while yielded, 4: faulted, 5: terminated.
+
+use core::cell::Cell;
+use core::cmp;
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::procs::{ProcessType, State};
+use kernel::{AppId, AppSlice, Driver, Grant, Kernel, ReturnCode, Shared};
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = 0x10001;
+
+/// Size of the fixed fields of a process information record.
+const INFO_HEADER_LEN: usize = 20;
+
+#[derive(Default)]
+pub struct App {
+    name: Option<AppSlice<Shared, u8>>,
+    info: Option<AppSlice<Shared, u8>>,
+}
+
+/// A snapshot of the information reported about one process.
+#[derive(Copy, Clone)]
+struct ProcessInfo {
+    state: State,
+    syscalls: usize,
+    restarts: usize,
+    dropped_callbacks: usize,
+    name: &'static str,
+}
+
+fn state_code(state: State) -> u32 {
+    match state {
+        State::Running => 0,
+        State::Yielded => 1,
+        State::StoppedRunning => 2,
+        State::StoppedYielded => 3,
+        State::Fault => 4,
+        State::Terminated => 5,
+    }
+}
+
+fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset] = value as u8;
+    buf[offset + 1] = (value >> 8) as u8;
+    buf[offset + 2] = (value >> 16) as u8;
+    buf[offset + 3] = (value >> 24) as u8;
+}
+
+pub struct ProcessSupervisor<C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    supervisor_index: usize,
+    apps: Grant<App>,
+    capability: C,
+}
+
+impl<C: ProcessManagementCapability> ProcessSupervisor<C> {
+    pub fn new(
+        kernel: &'static Kernel,
+        supervisor_index: usize,
+        grant: Grant<App>,
+        capability: C,
+    ) -> ProcessSupervisor<C> {
+        ProcessSupervisor {
+            kernel: kernel,
+            supervisor_index: supervisor_index,
+            apps: grant,
+            capability: capability,
+        }
+    }
+
+    /// Whether `appid` is the process in the slot the board designated as
+    /// the supervisor.
+    fn is_supervisor(&self, appid: AppId) -> bool {
+        appid.idx() == self.supervisor_index
+    }
+
+    /// Run `action` on the process whose package name matches the buffer the
+    /// supervisor shared. Unless `allow_self` is set, the supervisor itself is
+    /// refused with `EINVAL`.
+    fn with_named_process<F>(&self, appid: AppId, allow_self: bool, action: F) -> ReturnCode
+    where
+        F: Fn(&ProcessType),
+    {
+        self.apps
+            .enter(appid, |app, _| match app.name {
+                Some(ref name) => {
+                    let result = Cell::new(ReturnCode::ENODEVICE);
+                    self.kernel
+                        .process_each_capability(&self.capability, |i, process| {
+                            let matches = process.get_process_name().as_bytes() == name.as_ref();
+                            if result.get() == ReturnCode::ENODEVICE && matches {
+                                if i == appid.idx() && !allow_self {
+                                    result.set(ReturnCode::EINVAL);
+                                } else {
+                                    action(process);
+                                    result.set(ReturnCode::SUCCESS);
+                                }
+                            }
+                        });
+                    result.get()
+                }
+                None => ReturnCode::EINVAL,
+            }).unwrap_or_else(|err| err.into())
+    }
+
+    fn process_count(&self) -> usize {
+        let count = Cell::new(0);
+        self.kernel
+            .process_each_capability(&self.capability, |_i, _process| {
+                count.set(count.get() + 1);
+            });
+        count.get()
+    }
+
+    /// Copy information about the `n`th loaded process into the buffer the
+    /// supervisor shared with allow 1.
+    fn report_process(&self, appid: AppId, n: usize) -> ReturnCode {
+        let found: Cell<Option<ProcessInfo>> = Cell::new(None);
+        let seen = Cell::new(0);
+        self.kernel
+            .process_each_capability(&self.capability, |_i, process| {
+                if seen.get() == n {
+                    found.set(Some(ProcessInfo {
+                        state: process.get_state(),
+                        syscalls: process.debug_syscall_count(),
+                        restarts: process.debug_restart_count(),
+                        dropped_callbacks: process.debug_dropped_callback_count(),
+                        name: process.get_process_name(),
+                    }));
+                }
+                seen.set(seen.get() + 1);
+            });
+
+        let info = match found.get() {
+            Some(info) => info,
+            None => return ReturnCode::ENODEVICE,
+        };
+
+        self.apps
+            .enter(appid, |app, _| match app.info {
+                Some(ref mut buffer) => {
+                    let buf = buffer.as_mut();
+                    if buf.len() < INFO_HEADER_LEN {
+                        return ReturnCode::ESIZE;
+                    }
+                    put_u32(buf, 0, state_code(info.state));
+                    put_u32(buf, 4, info.syscalls as u32);
+                    put_u32(buf, 8, info.restarts as u32);
+                    put_u32(buf, 12, info.dropped_callbacks as u32);
+                    put_u32(buf, 16, info.name.len() as u32);
+
+                    let name = info.name.as_bytes();
+                    let name_len = cmp::min(name.len(), buf.len() - INFO_HEADER_LEN);
+                    buf[INFO_HEADER_LEN..INFO_HEADER_LEN + name_len]
+                        .copy_from_slice(&name[..name_len]);
+                    ReturnCode::SuccessWithValue {
+                        value: INFO_HEADER_LEN + name_len,
+                    }
+                }
+                None => ReturnCode::EINVAL,
+            }).unwrap_or_else(|err| err.into())
+    }
+}
+
+impl<C: ProcessManagementCapability> Driver for ProcessSupervisor<C> {
+    fn allow(
+        &self,
+        appid: AppId,
+        allow_num: usize,
+        slice: Option<AppSlice<Shared, u8>>,
+    ) -> ReturnCode {
+        if !self.is_supervisor(appid) {
+            return ReturnCode::EINVAL;
+        }
+
+        match allow_num {
+            0 => self
+                .apps
+                .enter(appid, |app, _| {
+                    app.name = slice;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into()),
+            1 => self
+                .apps
+                .enter(appid, |app, _| {
+                    app.info = slice;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into()),
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+
+    fn command(&self, command_num: usize, data: usize, _: usize, appid: AppId) -> ReturnCode {
+        if !self.is_supervisor(appid) {
+            return ReturnCode::EINVAL;
+        }
+
+        match command_num {
+            0 /* check if present */ => ReturnCode::SUCCESS,
+
+            1 /* terminate */ => {
+                self.with_named_process(appid, false, |process| process.terminate())
+            }
+
+            2 /* restart */ => self.with_named_process(appid, false, |process| process.restart()),
+
+            3 /* number of processes */ => ReturnCode::SuccessWithValue {
+                value: self.process_count(),
+            },
+
+            4 /* process information */ => self.report_process(appid, data),
+
+            5 /* reset statistics */ => {
+                self.with_named_process(appid, true, |process| process.debug_reset_statistics())
+            }
+
+            _ => ReturnCode::ENOSUPPORT,
+        }
+    }
+}
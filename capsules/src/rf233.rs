@@ -21,11 +21,16 @@ use rf233_const::{ExternalState, InteruptFlags, RF233BusCommand, RF233Register,
 // n.b. This is a fairly "C"-like interface presently. Ideally it should move
 // over to the Tock register interface eventually, but this code does work as
 // written. Do not follow this as an example when implementing new code.
+use rf233_const::CSMA_BE_MAX_BE_SHIFT;
 use rf233_const::CSMA_SEED_1;
 use rf233_const::IRQ_MASK;
+use rf233_const::PHY_CC_CCA_MODE_CS;
+use rf233_const::PHY_CC_CCA_MODE_CS_AND_ED;
 use rf233_const::PHY_CC_CCA_MODE_CS_OR_ED;
+use rf233_const::PHY_CC_CCA_MODE_ED;
 use rf233_const::PHY_RSSI_RX_CRC_VALID;
 use rf233_const::PHY_TX_PWR;
+use rf233_const::RSSI_BASE_VAL;
 use rf233_const::SHORT_ADDR_0;
 use rf233_const::SHORT_ADDR_1;
 use rf233_const::TRX_CTRL_1;
@@ -34,6 +39,8 @@ use rf233_const::TRX_RPC;
 use rf233_const::TRX_TRAC_CHANNEL_ACCESS_FAILURE;
 use rf233_const::TRX_TRAC_MASK;
 use rf233_const::XAH_CTRL_0;
+use rf233_const::XAH_CTRL_0_MAX_CSMA_RETRIES_SHIFT;
+use rf233_const::XAH_CTRL_0_NO_CSMA;
 use rf233_const::XAH_CTRL_1;
 
 const INTERRUPT_ID: usize = 0x2154;
@@ -59,6 +66,7 @@ enum InternalState {
     START_IRQMASK_SET,
     START_XAH1_SET,
     START_XAH0_SET,
+    START_CSMA_BE_SET,
     START_PANID0_SET,
     START_PANID1_SET,
     START_IEEE0_SET,
@@ -98,6 +106,7 @@ enum InternalState {
     TX_STATUS_PRECHECK2,
     TX_PLL_START,
     TX_PLL_WAIT,
+    TX_CSMA_SET,
     TX_ARET_ON,
     TX_TRANSMITTING,
     TX_READ_ACK,
@@ -126,6 +135,8 @@ enum InternalState {
     CONFIG_IEEE6_SET,
     CONFIG_IEEE7_SET,
     CONFIG_POWER_SET,
+    CONFIG_CCA_SET,
+    CONFIG_XAH0_SET,
     CONFIG_DONE,
 
     // RX is a short-lived state for when software has detected
@@ -141,7 +152,8 @@ enum InternalState {
     RX_READING_FRAME_LEN_DONE,
     RX_READING_FRAME,      // Reading the packet out of the radio
     RX_READING_FRAME_DONE, // Now read a register to verify FCS
-    RX_READING_FRAME_FCS_DONE,
+    RX_READING_FRAME_FCS_DONE, // Now read a register to get the RSSI
+    RX_READING_FRAME_ED_DONE,
     RX_ENABLING_RECEPTION, // Re-enabling reception
 }
 
@@ -194,6 +206,7 @@ pub struct RF233<'a, S: spi::SpiMasterDevice> {
     receiving: Cell<bool>,
     spi_busy: Cell<bool>,
     crc_valid: Cell<bool>,
+    rssi: Cell<i8>,
     interrupt_handling: Cell<bool>,
     interrupt_pending: Cell<bool>,
     config_pending: Cell<bool>,
@@ -208,6 +221,7 @@ pub struct RF233<'a, S: spi::SpiMasterDevice> {
     tx_buf: TakeCell<'static, [u8]>,
     rx_buf: TakeCell<'static, [u8]>,
     tx_len: Cell<u8>,
+    tx_cca: Cell<bool>,
     tx_client: OptionalCell<&'static radio::TxClient>,
     rx_client: OptionalCell<&'static radio::RxClient>,
     cfg_client: OptionalCell<&'static radio::ConfigClient>,
@@ -217,6 +231,9 @@ pub struct RF233<'a, S: spi::SpiMasterDevice> {
     pan: Cell<u16>,
     tx_power: Cell<i8>,
     channel: Cell<u8>,
+    csma_params: Cell<radio::CsmaParams>,
+    cca_mode: Cell<radio::CcaMode>,
+    limits: Cell<radio::RegulatoryLimits>,
     spi_rx: TakeCell<'static, [u8]>,
     spi_tx: TakeCell<'static, [u8]>,
     spi_buf: TakeCell<'static, [u8]>,
@@ -274,6 +291,17 @@ fn power_to_setting(power: i8) -> u8 {
     }
 }
 
+// The ED level is the energy detected during reception of the last frame, in
+// dB above the receiver sensitivity of -94 dBm. 0xFF means the value is
+// invalid, which we report as the lowest possible RSSI.
+fn ed_level_to_rssi(ed_level: u8) -> i8 {
+    if ed_level == 0xFF {
+        -128
+    } else {
+        (RSSI_BASE_VAL as i16 + ed_level as i16) as i8
+    }
+}
+
 fn interrupt_included(mask: u8, interrupt: InteruptFlags) -> bool {
     let int = interrupt as u8;
     (mask & int) == int
@@ -404,7 +432,8 @@ impl<S: spi::SpiMasterDevice> spi::SpiMasterClient for RF233<'a, S> {
                 InternalState::RX_TURNING_OFF
                 | InternalState::RX_START_READING
                 | InternalState::RX_READING_FRAME_DONE
-                | InternalState::RX_READING_FRAME_FCS_DONE => {}
+                | InternalState::RX_READING_FRAME_FCS_DONE
+                | InternalState::RX_READING_FRAME_ED_DONE => {}
                 _ => {
                     self.interrupt_pending.set(false);
                     self.handle_interrupt();
@@ -485,7 +514,7 @@ impl<S: spi::SpiMasterDevice> spi::SpiMasterClient for RF233<'a, S> {
                 );
             }
             InternalState::START_CTRL1_SET => {
-                let val = self.channel.get() | PHY_CC_CCA_MODE_CS_OR_ED;
+                let val = self.channel.get() | self.cca_mode_bits();
                 self.state_transition_write(
                     RF233Register::PHY_CC_CCA,
                     val,
@@ -526,11 +555,18 @@ impl<S: spi::SpiMasterDevice> spi::SpiMasterClient for RF233<'a, S> {
                 // settings in the RF233 C code
                 self.state_transition_write(
                     RF233Register::XAH_CTRL_0,
-                    XAH_CTRL_0,
+                    self.xah_ctrl_0(true),
                     InternalState::START_XAH0_SET,
                 );
             }
             InternalState::START_XAH0_SET => {
+                self.state_transition_write(
+                    RF233Register::CSMA_BE,
+                    self.csma_be(),
+                    InternalState::START_CSMA_BE_SET,
+                );
+            }
+            InternalState::START_CSMA_BE_SET => {
                 self.state_transition_write(
                     RF233Register::PAN_ID_0,
                     (self.pan.get() >> 8) as u8,
@@ -750,12 +786,19 @@ impl<S: spi::SpiMasterDevice> spi::SpiMasterClient for RF233<'a, S> {
                     );
                 } else {
                     self.state_transition_write(
-                        RF233Register::TRX_STATE,
-                        RF233TrxCmd::TX_ARET_ON as u8,
-                        InternalState::TX_ARET_ON,
+                        RF233Register::XAH_CTRL_0,
+                        self.xah_ctrl_0(self.tx_cca.get()),
+                        InternalState::TX_CSMA_SET,
                     );
                 }
             }
+            InternalState::TX_CSMA_SET => {
+                self.state_transition_write(
+                    RF233Register::TRX_STATE,
+                    RF233TrxCmd::TX_ARET_ON as u8,
+                    InternalState::TX_ARET_ON,
+                );
+            }
             InternalState::TX_ARET_ON => {
                 self.state_transition_write(
                     RF233Register::TRX_STATE,
@@ -845,8 +888,9 @@ impl<S: spi::SpiMasterDevice> spi::SpiMasterClient for RF233<'a, S> {
                 // A frame read starts with a 1-byte chip status followed by a
                 // 1-byte PHY header, which is the length of the frame.
                 // Then, the frame follows, and there are 3 more bytes at the
-                // end corresponding to LQI, ED, and RX_STATUS. Performing a
-                // shorter frame read just drops these bytes.
+                // end corresponding to LQI, ED, and RX_STATUS. We read the
+                // LQI byte along with the frame; performing a shorter frame
+                // read just drops the remaining bytes.
                 let frame_len = result;
                 // If the packet isn't too long to fit in the SPI buffer, read it
                 if (frame_len <= radio::MAX_FRAME_SIZE as u8
@@ -854,7 +898,7 @@ impl<S: spi::SpiMasterDevice> spi::SpiMasterClient for RF233<'a, S> {
                 {
                     self.state.set(InternalState::RX_READING_FRAME);
                     let rbuf = self.rx_buf.take().unwrap();
-                    self.frame_read(rbuf, frame_len);
+                    self.frame_read(rbuf, frame_len + radio::LQI_SIZE as u8);
                 } else if self.transmitting.get() {
                     // Packet was too long and a transmission is pending,
                     // start the transmission
@@ -877,8 +921,17 @@ impl<S: spi::SpiMasterDevice> spi::SpiMasterClient for RF233<'a, S> {
                 );
             }
             InternalState::RX_READING_FRAME_FCS_DONE => {
-                // Store whether the CRC was valid, then turn the radio back on.
+                // Store whether the CRC was valid, then read the energy
+                // detected during reception of the frame.
                 self.crc_valid.set((result & PHY_RSSI_RX_CRC_VALID) != 0);
+                self.state_transition_read(
+                    RF233Register::PHY_ED_LEVEL,
+                    InternalState::RX_READING_FRAME_ED_DONE,
+                );
+            }
+            InternalState::RX_READING_FRAME_ED_DONE => {
+                // Store the RSSI, then turn the radio back on.
+                self.rssi.set(ed_level_to_rssi(result));
                 self.state_transition_write(
                     RF233Register::TRX_STATE,
                     RF233TrxCmd::RX_AACK_ON as u8,
@@ -908,7 +961,15 @@ impl<S: spi::SpiMasterDevice> spi::SpiMasterClient for RF233<'a, S> {
                 self.rx_client.map(|client| {
                     let rbuf = self.rx_buf.take().unwrap();
                     let frame_len = rbuf[1] as usize - radio::MFR_SIZE;
-                    client.receive(rbuf, frame_len, self.crc_valid.get(), ReturnCode::SUCCESS);
+                    let lqi = rbuf[radio::PSDU_OFFSET + rbuf[1] as usize];
+                    client.receive(
+                        rbuf,
+                        frame_len,
+                        lqi,
+                        self.rssi.get(),
+                        self.crc_valid.get(),
+                        ReturnCode::SUCCESS,
+                    );
                 });
             }
 
@@ -998,10 +1059,24 @@ impl<S: spi::SpiMasterDevice> spi::SpiMasterClient for RF233<'a, S> {
                 );
             }
             InternalState::CONFIG_POWER_SET => {
-                let val = self.channel.get() | PHY_CC_CCA_MODE_CS_OR_ED;
+                let val = self.channel.get() | self.cca_mode_bits();
                 self.state_transition_write(
                     RF233Register::PHY_CC_CCA,
                     val,
+                    InternalState::CONFIG_CCA_SET,
+                );
+            }
+            InternalState::CONFIG_CCA_SET => {
+                self.state_transition_write(
+                    RF233Register::XAH_CTRL_0,
+                    self.xah_ctrl_0(true),
+                    InternalState::CONFIG_XAH0_SET,
+                );
+            }
+            InternalState::CONFIG_XAH0_SET => {
+                self.state_transition_write(
+                    RF233Register::CSMA_BE,
+                    self.csma_be(),
                     InternalState::CONFIG_DONE,
                 );
             }
@@ -1044,6 +1119,7 @@ impl<S: spi::SpiMasterDevice> RF233<'a, S> {
             receiving: Cell::new(false),
             spi_busy: Cell::new(false),
             crc_valid: Cell::new(false),
+            rssi: Cell::new(0),
             state: Cell::new(InternalState::START),
             interrupt_handling: Cell::new(false),
             interrupt_pending: Cell::new(false),
@@ -1054,6 +1130,7 @@ impl<S: spi::SpiMasterDevice> RF233<'a, S> {
             tx_buf: TakeCell::empty(),
             rx_buf: TakeCell::empty(),
             tx_len: Cell::new(0),
+            tx_cca: Cell::new(true),
             tx_client: OptionalCell::empty(),
             rx_client: OptionalCell::empty(),
             cfg_client: OptionalCell::empty(),
@@ -1063,6 +1140,9 @@ impl<S: spi::SpiMasterDevice> RF233<'a, S> {
             pan: Cell::new(0),
             tx_power: Cell::new(setting_to_power(PHY_TX_PWR)),
             channel: Cell::new(channel),
+            csma_params: Cell::new(Default::default()),
+            cca_mode: Cell::new(Default::default()),
+            limits: Cell::new(Default::default()),
             spi_rx: TakeCell::empty(),
             spi_tx: TakeCell::empty(),
             spi_buf: TakeCell::empty(),
@@ -1154,6 +1234,33 @@ impl<S: spi::SpiMasterDevice> RF233<'a, S> {
         ReturnCode::SUCCESS
     }
 
+    // The XAH_CTRL_0 value holding the number of CSMA-CA backoffs, or
+    // disabling CSMA-CA if `cca` is false
+    fn xah_ctrl_0(&self, cca: bool) -> u8 {
+        if cca {
+            XAH_CTRL_0
+                | (self.csma_params.get().max_backoffs << XAH_CTRL_0_MAX_CSMA_RETRIES_SHIFT)
+        } else {
+            XAH_CTRL_0 | XAH_CTRL_0_NO_CSMA
+        }
+    }
+
+    // The CCA_MODE field of the PHY_CC_CCA register for the configured mode
+    fn cca_mode_bits(&self) -> u8 {
+        match self.cca_mode.get() {
+            radio::CcaMode::EnergyDetect => PHY_CC_CCA_MODE_ED,
+            radio::CcaMode::CarrierSense => PHY_CC_CCA_MODE_CS,
+            radio::CcaMode::CarrierSenseOrEnergy => PHY_CC_CCA_MODE_CS_OR_ED,
+            radio::CcaMode::CarrierSenseAndEnergy => PHY_CC_CCA_MODE_CS_AND_ED,
+        }
+    }
+
+    // The CSMA_BE value holding the minimum and maximum backoff exponents
+    fn csma_be(&self) -> u8 {
+        let params = self.csma_params.get();
+        (params.max_be << CSMA_BE_MAX_BE_SHIFT) | params.min_be
+    }
+
     fn state_transition_write(&self, reg: RF233Register, val: u8, state: InternalState) {
         self.state.set(state);
         self.register_write(reg, val);
@@ -1271,7 +1378,7 @@ impl<S: spi::SpiMasterDevice> radio::RadioConfig for RF233<'a, S> {
     }
 
     fn set_tx_power(&self, power: i8) -> ReturnCode {
-        if (power > 4 || power < -17) {
+        if power > 4 || power < -17 || !self.limits.get().allows_tx_power(power) {
             ReturnCode::EINVAL
         } else {
             self.tx_power.set(power);
@@ -1280,7 +1387,7 @@ impl<S: spi::SpiMasterDevice> radio::RadioConfig for RF233<'a, S> {
     }
 
     fn set_channel(&self, chan: u8) -> ReturnCode {
-        if chan >= 11 && chan <= 26 {
+        if chan >= 11 && chan <= 26 && self.limits.get().allows_channel(chan) {
             self.channel.set(chan);
             ReturnCode::SUCCESS
         } else {
@@ -1288,6 +1395,33 @@ impl<S: spi::SpiMasterDevice> radio::RadioConfig for RF233<'a, S> {
         }
     }
 
+    fn set_regulatory_limits(&self, limits: radio::RegulatoryLimits) -> ReturnCode {
+        if !limits.allows_channel(self.channel.get()) || !limits.allows_tx_power(-17) {
+            return ReturnCode::EINVAL;
+        }
+        if !limits.allows_tx_power(self.tx_power.get()) {
+            // Round down to the nearest power the radio supports
+            let setting = power_to_setting(limits.max_tx_power);
+            self.tx_power.set(setting_to_power(setting));
+        }
+        self.limits.set(limits);
+        ReturnCode::SUCCESS
+    }
+
+    fn set_csma_params(&self, params: radio::CsmaParams) -> ReturnCode {
+        if params.is_valid() {
+            self.csma_params.set(params);
+            ReturnCode::SUCCESS
+        } else {
+            ReturnCode::EINVAL
+        }
+    }
+
+    fn set_cca_mode(&self, mode: radio::CcaMode) -> ReturnCode {
+        self.cca_mode.set(mode);
+        ReturnCode::SUCCESS
+    }
+
     fn get_address(&self) -> u16 {
         self.addr.get()
     }
@@ -1308,6 +1442,14 @@ impl<S: spi::SpiMasterDevice> radio::RadioConfig for RF233<'a, S> {
     fn get_channel(&self) -> u8 {
         self.channel.get()
     }
+    /// The CSMA-CA backoff parameters
+    fn get_csma_params(&self) -> radio::CsmaParams {
+        self.csma_params.get()
+    }
+
+    fn get_cca_mode(&self) -> radio::CcaMode {
+        self.cca_mode.get()
+    }
 
     fn config_commit(&self) {
         let pending = self.config_pending.get();
@@ -1351,6 +1493,7 @@ impl<S: spi::SpiMasterDevice> radio::RadioData for RF233<'a, S> {
         &self,
         spi_buf: &'static mut [u8],
         frame_len: usize,
+        cca: bool,
     ) -> (ReturnCode, Option<&'static mut [u8]>) {
         let state = self.state.get();
         let frame_len = frame_len + radio::MFR_SIZE;
@@ -1368,6 +1511,7 @@ impl<S: spi::SpiMasterDevice> radio::RadioData for RF233<'a, S> {
         spi_buf[1] = frame_len as u8;
         self.tx_buf.replace(spi_buf);
         self.tx_len.set(frame_len as u8);
+        self.tx_cca.set(cca);
         self.transmitting.set(true);
 
         if !self.receiving.get() && state == InternalState::READY {
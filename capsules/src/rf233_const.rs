@@ -69,6 +69,7 @@ pub const PHY_CC_CCA_MODE_ED: u8 = 1 << 5;
 pub const PHY_CC_CCA_MODE_CS: u8 = 2 << 5;
 pub const PHY_CC_CCA_MODE_CS_AND_ED: u8 = 3 << 5;
 pub const PHY_RSSI_RX_CRC_VALID: u8 = 1 << 7;
+pub const RSSI_BASE_VAL: i8 = -94;
 pub const TRX_CTRL_2_RX_SAFE_MODE: u8 = 1 << 7;
 pub const TRX_CTRL_2_DATA_RATE_250: u8 = 0;
 pub const IRQ_TRXBUF_ACCESS_VIOLATION: u8 = 1 << 6;
@@ -91,6 +92,11 @@ pub const IRQ_MASK: u8 = (IRQ_TRXBUF_ACCESS_VIOLATION | IRQ_TRX_DONE | IRQ_PLL_L
 pub const XAH_CTRL_1: u8 =
     XAH_CTRL_1_AACK_UPLD_RES_FT | XAH_CTRL_1_AACK_FLTR_RES_FT | XAH_CTRL_1_AACK_PROM_MODE;
 pub const XAH_CTRL_0: u8 = 0;
+pub const XAH_CTRL_0_MAX_CSMA_RETRIES_SHIFT: u8 = 1;
+// A MAX_CSMA_RETRIES value of 7 starts the transmission immediately without
+// performing CSMA-CA
+pub const XAH_CTRL_0_NO_CSMA: u8 = 7 << XAH_CTRL_0_MAX_CSMA_RETRIES_SHIFT;
+pub const CSMA_BE_MAX_BE_SHIFT: u8 = 4;
 pub const CSMA_SEED_1: u8 = AACK_FVN_MODE;
 pub const TRX_RPC: u8 = 0xFF;
 pub const TRX_TRAC_MASK: u8 = 0xE0;
@@ -7,6 +7,19 @@
 //! randomness. A single command starts the RNG, the callback is called when the
 //! requested amount of randomness is received, or the buffer is filled.
 //!
+//! Conditioned Randomness
+//! ----------------------
+//!
+//! Command 2 works like command 1, but each word delivered to the app is
+//! extracted from a caller-chosen number of raw samples, at most
+//! `MAX_SAMPLES_PER_WORD`: the word is the first four bytes of the SHA-256
+//! hash of the samples. This is the hash-based conditioning of NIST SP
+//! 800-90B, so as long as the samples together hold at least 32 bits of
+//! entropy, the word is close to uniform even if the source is biased or its
+//! samples are correlated. Conditioning cannot create entropy the source did
+//! not provide, so `data2` should be chosen from the source's assessed
+//! entropy per sample, and it is not a substitute for health testing.
+//!
 //! Usage
 //! -----
 //!
@@ -24,16 +37,52 @@ use kernel::hil::entropy::{Entropy32, Entropy8};
 use kernel::hil::rng;
 use kernel::hil::rng::{Client, Continue, Random, Rng};
 use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
+use sha256::Sha256;
 
 /// Syscall number
 pub const DRIVER_NUM: usize = 0x40001;
 
+/// The largest number of raw samples that command 2 conditions into one
+/// word.
+pub const MAX_SAMPLES_PER_WORD: usize = 16;
+
 #[derive(Default)]
 pub struct App {
     callback: Option<Callback>,
     buffer: Option<AppSlice<Shared, u8>>,
     remaining: usize,
     idx: usize,
+    /// Raw samples conditioned into each delivered word, or 0 for raw
+    /// output.
+    samples_per_word: usize,
+    /// Hash of the raw samples drawn so far for the next conditioned word.
+    pool: Sha256,
+    pool_count: usize,
+}
+
+impl App {
+    /// Produce the next word of randomness for this app, or `None` if the
+    /// generator has no more samples right now. In conditioned mode, the
+    /// samples drawn so far are kept until more arrive.
+    fn next_word(&mut self, randomness: &mut Iterator<Item = u32>) -> Option<u32> {
+        if self.samples_per_word == 0 {
+            return randomness.next();
+        }
+        while self.pool_count < self.samples_per_word {
+            let sample = randomness.next()?;
+            self.pool.update(&[
+                sample as u8,
+                (sample >> 8) as u8,
+                (sample >> 16) as u8,
+                (sample >> 24) as u8,
+            ]);
+            self.pool_count += 1;
+        }
+        let digest = self.pool.finish();
+        self.pool = Sha256::new();
+        self.pool_count = 0;
+        Some(digest[..4].iter().rev().fold(0, |word, &byte| word << 8 | byte as u32))
+    }
 }
 
 pub struct RngDriver<'a> {
@@ -50,6 +99,29 @@ impl RngDriver<'a> {
             getting_randomness: Cell::new(false),
         }
     }
+
+    /// Start delivering `len` bytes of randomness to the app, mixing
+    /// `samples_per_word` raw samples into each word (0 for raw output).
+    fn start(&self, appid: AppId, len: usize, samples_per_word: usize) -> ReturnCode {
+        self.apps
+            .enter(appid, |app, _| {
+                app.remaining = len;
+                app.idx = 0;
+                app.samples_per_word = samples_per_word;
+                app.pool = Sha256::new();
+                app.pool_count = 0;
+
+                if app.callback.is_some() && app.buffer.is_some() {
+                    if !self.getting_randomness.get() {
+                        self.getting_randomness.set(true);
+                        self.rng.get();
+                    }
+                    ReturnCode::SUCCESS
+                } else {
+                    ReturnCode::ERESERVE
+                }
+            }).unwrap_or_else(|err| err.into())
+    }
 }
 
 impl<'a> rng::Client for RngDriver<'a> {
@@ -76,20 +148,15 @@ impl<'a> rng::Client for RngDriver<'a> {
 
                             // 1. Slice buffer to start from current idx
                             let buf = &mut buffer.as_mut()[app.idx..(app.idx + app.remaining)];
-                            // 2. Take at most as many random samples as needed to fill the buffer
-                            //    (if app.remaining is not word-sized, take an extra one).
-                            let remaining_ints = if app.remaining % 4 == 0 {
-                                app.remaining / 4
-                            } else {
-                                app.remaining / 4 + 1
-                            };
-
-                            // 3. Zip over the randomness iterator and chunks
-                            //    of up to 4 bytes from the buffer.
-                            for (inp, outs) in
-                                randomness.take(remaining_ints).zip(buf.chunks_mut(4))
-                            {
-                                // 4. For each word of randomness input, update
+                            // 2. Fill the buffer in chunks of up to 4 bytes,
+                            //    one word of randomness per chunk, until the
+                            //    generator runs out of samples.
+                            for outs in buf.chunks_mut(4) {
+                                let inp = match app.next_word(randomness) {
+                                    Some(inp) => inp,
+                                    None => break,
+                                };
+                                // 3. For each word of randomness input, update
                                 //    the remaining and idx and add to buffer.
                                 for (i, b) in outs.iter_mut().enumerate() {
                                     *b = ((inp >> i * 8) & 0xff) as u8;
@@ -168,7 +235,7 @@ impl<'a> Driver for RngDriver<'a> {
         }
     }
 
-    fn command(&self, command_num: usize, data: usize, _: usize, appid: AppId) -> ReturnCode {
+    fn command(&self, command_num: usize, data: usize, data2: usize, appid: AppId) -> ReturnCode {
         match command_num {
             0 =>
             /* Check if exists */
@@ -177,22 +244,18 @@ impl<'a> Driver for RngDriver<'a> {
             }
 
             // Ask for a given number of random bytes.
-            1 => self
-                .apps
-                .enter(appid, |app, _| {
-                    app.remaining = data;
-                    app.idx = 0;
+            1 => self.start(appid, data, 0),
+
+            // Ask for a given number of random bytes, each word conditioned
+            // from `data2` raw samples.
+            2 => {
+                if data2 == 0 || data2 > MAX_SAMPLES_PER_WORD {
+                    ReturnCode::EINVAL
+                } else {
+                    self.start(appid, data, data2)
+                }
+            }
 
-                    if app.callback.is_some() && app.buffer.is_some() {
-                        if !self.getting_randomness.get() {
-                            self.getting_randomness.set(true);
-                            self.rng.get();
-                        }
-                        ReturnCode::SUCCESS
-                    } else {
-                        ReturnCode::ERESERVE
-                    }
-                }).unwrap_or_else(|err| err.into()),
             _ => ReturnCode::ENOSUPPORT,
         }
     }
@@ -500,3 +563,92 @@ impl Client for SynchronousRandom<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::vec::Vec;
+    use super::*;
+
+    /// A biased source: each sample holds only 4 random-looking bits.
+    fn biased_samples(count: usize) -> Vec<u32> {
+        let mut state: u32 = 1;
+        (0..count)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                state >> 28
+            }).collect()
+    }
+
+    fn words(samples_per_word: usize, samples: &[u32]) -> Vec<u32> {
+        let mut app = App::default();
+        app.samples_per_word = samples_per_word;
+        let mut randomness = samples.iter().cloned();
+        let mut words = Vec::new();
+        while let Some(word) = app.next_word(&mut randomness) {
+            words.push(word);
+        }
+        words
+    }
+
+    /// Pearson's chi-square statistic of the byte values in `words` against
+    /// a uniform distribution.
+    fn chi_square(words: &[u32]) -> f64 {
+        let mut counts = [0u64; 256];
+        for word in words {
+            for i in 0..4 {
+                counts[(word >> (8 * i)) as u8 as usize] += 1;
+            }
+        }
+        let expected = (words.len() * 4) as f64 / 256.0;
+        counts
+            .iter()
+            .map(|&count| (count as f64 - expected) * (count as f64 - expected) / expected)
+            .sum()
+    }
+
+    /// Critical value of the chi-square distribution with 255 degrees of
+    /// freedom at a significance level of 0.001.
+    const CHI_SQUARE_CRITICAL: f64 = 330.52;
+
+    #[test]
+    fn conditioned_output_of_biased_source_is_uniform() {
+        let samples = biased_samples(8 * 4096);
+        assert!(chi_square(&words(0, &samples)) > CHI_SQUARE_CRITICAL);
+
+        let conditioned = words(8, &samples);
+        assert_eq!(conditioned.len(), 4096);
+        assert!(chi_square(&conditioned) < CHI_SQUARE_CRITICAL);
+    }
+
+    #[test]
+    fn conditioning_spans_deliveries() {
+        let samples = biased_samples(3 * 16);
+        let whole = words(16, &samples);
+
+        // The same samples delivered in uneven batches give the same words.
+        let mut app = App::default();
+        app.samples_per_word = 16;
+        let mut split = Vec::new();
+        for batch in samples.chunks(5) {
+            let mut randomness = batch.iter().cloned();
+            while let Some(word) = app.next_word(&mut randomness) {
+                split.push(word);
+            }
+        }
+        assert_eq!(split, whole);
+    }
+
+    #[test]
+    fn conditioned_word_is_truncated_sha256_of_samples() {
+        let mut hasher = Sha256::new();
+        hasher.update(b"abc\0");
+        let digest = hasher.finish();
+        let expected = digest[..4]
+            .iter()
+            .rev()
+            .fold(0, |word, &byte| word << 8 | byte as u32);
+        assert_eq!(words(1, &[0x0063_6261]), [expected]);
+    }
+}
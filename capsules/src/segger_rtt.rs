@@ -41,7 +41,10 @@
 //!         'static,
 //!         capsules::segger_rtt::SeggerRtt<
 //!             'static,
-//!             capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
+//!             capsules::alarm_timer::AlarmTimer<
+//!                 'static,
+//!                 capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
+//!             >,
 //!         >,
 //!     >,
 //! }
@@ -54,6 +57,14 @@
 //!     capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
 //!     capsules::virtual_alarm::VirtualMuxAlarm::new(mux_alarm)
 //! );
+//! let timer_rtt = static_init!(
+//!     capsules::alarm_timer::AlarmTimer<
+//!         'static,
+//!         capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
+//!     >,
+//!     capsules::alarm_timer::AlarmTimer::new(virtual_alarm_rtt)
+//! );
+//! virtual_alarm_rtt.set_client(timer_rtt);
 //!
 //! let rtt_memory = static_init!(
 //!     capsules::segger_rtt::SeggerRttMemory,
@@ -64,18 +75,22 @@
 //! );
 //!
 //! let rtt = static_init!(
-//!     capsules::segger_rtt::SeggerRtt<VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>>,
-//!     capsules::segger_rtt::SeggerRtt::new(virtual_alarm_rtt, rtt_memory,
+//!     capsules::segger_rtt::SeggerRtt<
+//!         capsules::alarm_timer::AlarmTimer<VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>>,
+//!     >,
+//!     capsules::segger_rtt::SeggerRtt::new(timer_rtt, rtt_memory,
 //!         &mut capsules::segger_rtt::UP_BUFFER,
 //!         &mut capsules::segger_rtt::DOWN_BUFFER)
 //! );
-//! virtual_alarm_rtt.set_client(rtt);
+//! timer_rtt.set_client(rtt);
 //!
 //! let console = static_init!(
 //!     capsules::console::Console<
 //!         'static,
 //!         capsules::segger_rtt::SeggerRtt<
-//!             capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
+//!             capsules::alarm_timer::AlarmTimer<
+//!                 capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
+//!             >,
 //!         >,
 //!     >,
 //!     capsules::console::Console::new(
@@ -155,8 +170,8 @@ impl SeggerRttMemory {
     }
 }
 
-pub struct SeggerRtt<'a, A: hil::time::Alarm> {
-    alarm: &'a A, // Dummy alarm so we can get a callback.
+pub struct SeggerRtt<'a, T: hil::time::Timer> {
+    timer: &'a T, // Dummy timer so we can get a callback.
     config: TakeCell<'static, SeggerRttMemory>,
     up_buffer: TakeCell<'static, [u8]>,
     _down_buffer: TakeCell<'static, [u8]>,
@@ -164,15 +179,15 @@ pub struct SeggerRtt<'a, A: hil::time::Alarm> {
     client_buffer: TakeCell<'static, [u8]>,
 }
 
-impl<A: hil::time::Alarm> SeggerRtt<'a, A> {
+impl<T: hil::time::Timer> SeggerRtt<'a, T> {
     pub fn new(
-        alarm: &'a A,
+        timer: &'a T,
         config: &'static mut SeggerRttMemory,
         up_buffer: &'static mut [u8],
         down_buffer: &'static mut [u8],
-    ) -> SeggerRtt<'a, A> {
+    ) -> SeggerRtt<'a, T> {
         SeggerRtt {
-            alarm: alarm,
+            timer: timer,
             config: TakeCell::new(config),
             up_buffer: TakeCell::new(up_buffer),
             _down_buffer: TakeCell::new(down_buffer),
@@ -182,7 +197,7 @@ impl<A: hil::time::Alarm> SeggerRtt<'a, A> {
     }
 }
 
-impl<A: hil::time::Alarm> hil::uart::UART for SeggerRtt<'a, A> {
+impl<T: hil::time::Timer> hil::uart::UART for SeggerRtt<'a, T> {
     fn set_client(&self, client: &'static hil::uart::Client) {
         self.client.set(client);
     }
@@ -214,9 +229,8 @@ impl<A: hil::time::Alarm> hil::uart::UART for SeggerRtt<'a, A> {
 
         // Start a short timer so that we get a callback and can issue the
         // callback to the client.
-        let interval = (100 as u32) * <A::Frequency>::frequency() / 1000000;
-        let tics = self.alarm.now().wrapping_add(interval);
-        self.alarm.set_alarm(tics);
+        let interval = (100 as u32) * <T::Frequency>::frequency() / 1000000;
+        self.timer.oneshot(interval);
     }
 
     fn receive(&self, _rx_buf: &'static mut [u8], _rx_len: usize) {}
@@ -224,7 +238,7 @@ impl<A: hil::time::Alarm> hil::uart::UART for SeggerRtt<'a, A> {
     fn abort_receive(&self) {}
 }
 
-impl<A: hil::time::Alarm> hil::time::Client for SeggerRtt<'a, A> {
+impl<T: hil::time::Timer> hil::time::Client for SeggerRtt<'a, T> {
     fn fired(&self) {
         self.client.map(|client| {
             self.client_buffer.take().map(|buffer| {
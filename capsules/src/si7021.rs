@@ -86,6 +86,26 @@ enum State {
     GotRhMeasurement,
 }
 
+/// Converts a raw temperature code to hundredths of degrees centigrade, as
+/// given in section 5.1.2 of the datasheet.
+pub fn temperature_from_raw(raw: u16) -> i16 {
+    (((raw as i32) * 17572) / 65536 - 4685) as i16
+}
+
+/// Converts a raw relative humidity code to hundredths of a percent, as given
+/// in section 5.1.1 of the datasheet. Codes slightly outside of the 0-100%
+/// range can be measured, so the result is clamped.
+pub fn humidity_from_raw(raw: u16) -> u16 {
+    let humidity = ((raw as i32) * 12500) / 65536 - 600;
+    if humidity < 0 {
+        0
+    } else if humidity > 10000 {
+        10000
+    } else {
+        humidity as u16
+    }
+}
+
 #[derive(PartialEq, Eq, Copy, Clone)]
 enum OnDeck {
     Nothing,
@@ -193,8 +213,8 @@ impl<A: time::Alarm> i2c::I2CClient for SI7021<'a, A> {
             }
             State::GotTempMeasurement => {
                 // Temperature in hundredths of degrees centigrade
-                let temp_raw = (((buffer[0] as u32) << 8) | (buffer[1] as u32)) as u32;
-                let temp = (((temp_raw * 17572) / 65536) - 4685) as i16;
+                let temp_raw = ((buffer[0] as u16) << 8) | (buffer[1] as u16);
+                let temp = temperature_from_raw(temp_raw);
 
                 self.temp_callback.map(|cb| cb.callback(temp as usize));
 
@@ -212,8 +232,8 @@ impl<A: time::Alarm> i2c::I2CClient for SI7021<'a, A> {
             }
             State::GotRhMeasurement => {
                 // Humidity in hundredths of percent
-                let humidity_raw = (((buffer[0] as u32) << 8) | (buffer[1] as u32)) as u32;
-                let humidity = (((humidity_raw * 125 * 100) / 65536) - 600) as u16;
+                let humidity_raw = ((buffer[0] as u16) << 8) | (buffer[1] as u16);
+                let humidity = humidity_from_raw(humidity_raw);
 
                 self.humidity_callback
                     .map(|cb| cb.callback(humidity as usize));
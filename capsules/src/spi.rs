@@ -3,11 +3,11 @@
 
 use core::cell::Cell;
 use core::cmp;
-use kernel::common::cells::{MapCell, TakeCell};
+use kernel::common::cells::{MapCell, OptionalCell, TakeCell};
 use kernel::hil::spi::ClockPhase;
 use kernel::hil::spi::ClockPolarity;
 use kernel::hil::spi::{SpiMasterClient, SpiMasterDevice, SpiSlaveClient, SpiSlaveDevice};
-use kernel::{AppId, AppSlice, Callback, Driver, ReturnCode, Shared};
+use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
 
 /// Syscall number
 pub const DRIVER_NUM: usize = 0x20001;
@@ -22,12 +22,14 @@ pub const DRIVER_NUM: usize = 0x20001;
 // index an ongoing operation is at in the buffers.
 
 #[derive(Default)]
-struct App {
+pub struct App {
     callback: Option<Callback>,
     app_read: Option<AppSlice<Shared, u8>>,
     app_write: Option<AppSlice<Shared, u8>>,
     len: usize,
     index: usize,
+    // Whether this app has a transfer waiting for the bus
+    pending: bool,
 }
 
 // Since we provide an additional callback in slave mode for
@@ -45,8 +47,8 @@ struct SlaveApp {
 
 pub struct Spi<'a, S: SpiMasterDevice> {
     spi_master: &'a S,
-    busy: Cell<bool>,
-    app: MapCell<App>,
+    apps: Grant<App>,
+    current_app: OptionalCell<AppId>,
     kernel_read: TakeCell<'static, [u8]>,
     kernel_write: TakeCell<'static, [u8]>,
     kernel_len: Cell<usize>,
@@ -62,11 +64,11 @@ pub struct SpiSlave<'a, S: SpiSlaveDevice> {
 }
 
 impl<S: SpiMasterDevice> Spi<'a, S> {
-    pub fn new(spi_master: &'a S) -> Spi<'a, S> {
+    pub fn new(spi_master: &'a S, grant: Grant<App>) -> Spi<'a, S> {
         Spi {
             spi_master: spi_master,
-            busy: Cell::new(false),
-            app: MapCell::new(App::default()),
+            apps: grant,
+            current_app: OptionalCell::empty(),
             kernel_len: Cell::new(0),
             kernel_read: TakeCell::empty(),
             kernel_write: TakeCell::empty(),
@@ -101,30 +103,80 @@ impl<S: SpiMasterDevice> Spi<'a, S> {
             len,
         );
     }
+
+    // Starts a transfer of `len` bytes for `appid` if the bus is free,
+    // otherwise queues it until the transfers ahead of it complete.
+    fn enqueue_read_write(&self, len: usize, appid: AppId) -> ReturnCode {
+        self.apps
+            .enter(appid, |app, _| {
+                let mut mlen = 0;
+                app.app_write.as_mut().map(|w| {
+                    mlen = w.len();
+                });
+                app.app_read.as_mut().map(|r| {
+                    mlen = cmp::min(mlen, r.len());
+                });
+                if mlen < len {
+                    return ReturnCode::EINVAL; /* write buffer too small */
+                }
+                if app.pending || self.current_app.map_or(false, |current| *current == appid) {
+                    return ReturnCode::EBUSY;
+                }
+
+                app.len = len;
+                app.index = 0;
+                if self.current_app.is_none() {
+                    self.current_app.set(appid);
+                    self.do_next_read_write(app);
+                } else {
+                    app.pending = true;
+                }
+                ReturnCode::SUCCESS
+            }).unwrap_or_else(|err| err.into())
+    }
+
+    // Starts the transfer of the next app waiting for the bus, if any.
+    fn run_next_pending(&self) {
+        for cntr in self.apps.iter() {
+            let started = cntr.enter(|app, _| {
+                if app.pending {
+                    app.pending = false;
+                    self.current_app.set(app.appid());
+                    self.do_next_read_write(app);
+                    true
+                } else {
+                    false
+                }
+            });
+            if started {
+                break;
+            }
+        }
+    }
 }
 
 impl<S: SpiMasterDevice> Driver for Spi<'a, S> {
     fn allow(
         &self,
-        _appid: AppId,
+        appid: AppId,
         allow_num: usize,
         slice: Option<AppSlice<Shared, u8>>,
     ) -> ReturnCode {
         match allow_num {
             // Pass in a read buffer to receive bytes into.
-            0 => {
-                self.app.map(|app| {
+            0 => self
+                .apps
+                .enter(appid, |app, _| {
                     app.app_read = slice;
-                });
-                ReturnCode::SUCCESS
-            }
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into()),
             // Pass in a write buffer to transmit bytes from.
-            1 => {
-                self.app.map(|app| {
+            1 => self
+                .apps
+                .enter(appid, |app, _| {
                     app.app_write = slice;
-                });
-                ReturnCode::SUCCESS
-            }
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into()),
             _ => ReturnCode::ENOSUPPORT,
         }
     }
@@ -133,14 +185,15 @@ impl<S: SpiMasterDevice> Driver for Spi<'a, S> {
         &self,
         subscribe_num: usize,
         callback: Option<Callback>,
-        _app_id: AppId,
+        app_id: AppId,
     ) -> ReturnCode {
         match subscribe_num {
             0 /* read_write */ => {
-                self.app.map(|app| {
-                    app.callback = callback;
-                });
-                ReturnCode::SUCCESS
+                self.apps
+                    .enter(app_id, |app, _| {
+                        app.callback = callback;
+                        ReturnCode::SUCCESS
+                    }).unwrap_or_else(|err| err.into())
             },
             _ => ReturnCode::ENOSUPPORT
         }
@@ -149,13 +202,15 @@ impl<S: SpiMasterDevice> Driver for Spi<'a, S> {
     // 2: read/write buffers
     //   - requires write buffer registered with allow
     //   - read buffer optional
+    //   - if another app is using the bus, the transfer is queued
+    //     and started once the transfers ahead of it complete
+    //   - returns EBUSY if this app already has a transfer in
+    //     progress or queued
     // 3: set chip select
-    //   - selects which peripheral (CS line) the SPI should
-    //     activate
-    //   - valid values are 0-3 for SAM4L
-    //   - invalid value will result in CS 0
+    //   - not supported, returns ENOSUPPORT: each `Spi` instance is
+    //     bound to the chip select of its `SpiMasterDevice`
     // 4: get chip select
-    //   - returns current selected peripheral
+    //   - not supported, returns ENOSUPPORT
     // 5: set rate on current peripheral
     //   - parameter in bps
     // 6: get rate on current peripheral
@@ -173,43 +228,14 @@ impl<S: SpiMasterDevice> Driver for Spi<'a, S> {
     //   - 0 is idle low
     //   - non-zero is idle high
     //
-    // x: lock spi
-    //   - if you perform an operation without the lock,
-    //     it implicitly acquires the lock before the
-    //     operation and releases it after
-    //   - while an app holds the lock no other app can issue
-    //     operations on SPI (they are buffered)
-    // x+1: unlock spi
-    //   - does nothing if lock not held
+    // The rate, phase, and polarity are shared by all apps.
     //
-    fn command(&self, cmd_num: usize, arg1: usize, _: usize, _: AppId) -> ReturnCode {
+    fn command(&self, cmd_num: usize, arg1: usize, _: usize, appid: AppId) -> ReturnCode {
         match cmd_num {
             0 /* check if present */ => ReturnCode::SUCCESS,
             // No longer supported, wrap inside a read_write_bytes
             1 /* read_write_byte */ => ReturnCode::ENOSUPPORT,
-            2 /* read_write_bytes */ => {
-                if self.busy.get() {
-                    return ReturnCode::EBUSY;
-                }
-                self.app.map_or(ReturnCode::FAIL, |app| {
-                    let mut mlen = 0;
-                    app.app_write.as_mut().map(|w| {
-                        mlen = w.len();
-                    });
-                    app.app_read.as_mut().map(|r| {
-                        mlen = cmp::min(mlen, r.len());
-                    });
-                    if mlen >= arg1 {
-                        app.len = arg1;
-                        app.index = 0;
-                        self.busy.set(true);
-                        self.do_next_read_write(app);
-                        ReturnCode::SUCCESS
-                    } else {
-                        ReturnCode::EINVAL /* write buffer too small */
-                    }
-                })
-            }
+            2 /* read_write_bytes */ => self.enqueue_read_write(arg1, appid),
             3 /* set chip select */ => {
                 // XXX: TODO: do nothing, for now, until we fix interface
                 // so virtual instances can use multiple chip selects
@@ -260,33 +286,48 @@ impl<S: SpiMasterDevice> SpiMasterClient for Spi<'a, S> {
         readbuf: Option<&'static mut [u8]>,
         length: usize,
     ) {
-        self.app.map(move |app| {
-            if app.app_read.is_some() {
-                let src = readbuf.as_ref().unwrap();
-                let dest = app.app_read.as_mut().unwrap();
-                let start = app.index - length;
-                let end = start + length;
-
-                let d = &mut dest.as_mut()[start..end];
-                for (i, c) in src[0..length].iter().enumerate() {
-                    d[i] = *c;
-                }
-            }
+        self.kernel_read.put(readbuf);
+        self.kernel_write.replace(writebuf);
 
-            self.kernel_read.put(readbuf);
-            self.kernel_write.replace(writebuf);
+        // If the app has gone away, the transfer is abandoned and the bus is
+        // handed to the next app.
+        let done = self.current_app.map_or(true, |appid| {
+            self.apps
+                .enter(*appid, |app, _| {
+                    let start = app.index - length;
+                    self.kernel_read.map(|src| {
+                        app.app_read.as_mut().map(|dest| {
+                            // The app may have allowed a shorter buffer since
+                            // the transfer started, so only copy what fits.
+                            let end = cmp::min(start + length, dest.len());
+                            if start < end {
+                                let d = &mut dest.as_mut()[start..end];
+                                for (i, c) in src[0..end - start].iter().enumerate() {
+                                    d[i] = *c;
+                                }
+                            }
+                        });
+                    });
 
-            if app.index == app.len {
-                self.busy.set(false);
-                app.len = 0;
-                app.index = 0;
-                app.callback.take().map(|mut cb| {
-                    cb.schedule(app.len, 0, 0);
-                });
-            } else {
-                self.do_next_read_write(app);
-            }
+                    if app.index == app.len {
+                        let len = app.len;
+                        app.len = 0;
+                        app.index = 0;
+                        app.callback.map(|mut cb| {
+                            cb.schedule(len, 0, 0);
+                        });
+                        true
+                    } else {
+                        self.do_next_read_write(app);
+                        false
+                    }
+                }).unwrap_or(true)
         });
+
+        if done {
+            self.current_app.clear();
+            self.run_next_pending();
+        }
     }
 }
 
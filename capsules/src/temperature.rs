@@ -24,6 +24,9 @@
 //! * `0`: check whether the driver exist
 //! * `1`: read the temperature
 //!
+//! Readings are delivered to the callback in hundredths of degrees Celsius,
+//! after applying the board's calibration (see below).
+//!
 //!
 //! The possible return from the 'command' system call indicates the following:
 //!
@@ -45,6 +48,24 @@
 //!                                                 kernel::Grant::create()), 96/8);
 //! kernel::hil::sensors::TemperatureDriver::set_client(si7021, temp);
 //! ```
+//!
+//! Calibration
+//! -----------
+//!
+//! Sensors often read consistently high or low, for example a die temperature
+//! sensor that is warmed by the chip around it. A board can correct this with
+//! a linear calibration applied to every reading before it reaches an app:
+//!
+//! ```rust
+//! temp.set_calibration(capsules::temperature::Calibration {
+//!     scale: 1000, // 1.000x
+//!     offset: -250, // -2.50 °C
+//! });
+//! ```
+//!
+//! A board that stores a per-unit correction (e.g. measured at manufacture)
+//! can add it separately with `set_unit_offset`, so the same linear
+//! calibration can be shared by all units of a board.
 
 use core::cell::Cell;
 use kernel::hil;
@@ -60,10 +81,38 @@ pub struct App {
     subscribed: bool,
 }
 
+/// A linear correction applied to raw readings.
+#[derive(Copy, Clone, Debug)]
+pub struct Calibration {
+    /// Multiplier in thousandths, so 1000 leaves the reading unscaled.
+    pub scale: i32,
+    /// Offset added after scaling, in hundredths of degrees Celsius.
+    pub offset: i32,
+}
+
+impl Default for Calibration {
+    fn default() -> Calibration {
+        Calibration {
+            scale: 1000,
+            offset: 0,
+        }
+    }
+}
+
+impl Calibration {
+    /// Convert a raw reading to a calibrated one, both in hundredths of
+    /// degrees Celsius.
+    pub fn apply(&self, raw: i32) -> i32 {
+        ((raw as i64 * self.scale as i64) / 1000) as i32 + self.offset
+    }
+}
+
 pub struct TemperatureSensor<'a> {
     driver: &'a hil::sensors::TemperatureDriver,
     apps: Grant<App>,
     busy: Cell<bool>,
+    calibration: Cell<Calibration>,
+    unit_offset: Cell<i32>,
 }
 
 impl TemperatureSensor<'a> {
@@ -75,9 +124,26 @@ impl TemperatureSensor<'a> {
             driver: driver,
             apps: grant,
             busy: Cell::new(false),
+            calibration: Cell::new(Calibration::default()),
+            unit_offset: Cell::new(0),
         }
     }
 
+    /// Set the linear calibration applied to every reading.
+    pub fn set_calibration(&self, calibration: Calibration) {
+        self.calibration.set(calibration);
+    }
+
+    /// Set a per-unit offset, in hundredths of degrees Celsius, added after
+    /// the linear calibration.
+    pub fn set_unit_offset(&self, offset: i32) {
+        self.unit_offset.set(offset);
+    }
+
+    fn calibrate(&self, raw: i32) -> i32 {
+        self.calibration.get().apply(raw) + self.unit_offset.get()
+    }
+
     fn enqueue_command(&self, appid: AppId) -> ReturnCode {
         self.apps
             .enter(appid, |app, _| {
@@ -102,6 +168,8 @@ impl TemperatureSensor<'a> {
 
 impl hil::sensors::TemperatureClient for TemperatureSensor<'a> {
     fn callback(&self, temp_val: usize) {
+        // Readings are signed, carried in a `usize`.
+        let temp_val = self.calibrate(temp_val as i32) as usize;
         for cntr in self.apps.iter() {
             cntr.enter(|app, _| {
                 if app.subscribed {
@@ -139,3 +207,38 @@ impl Driver for TemperatureSensor<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_calibration_leaves_readings_unchanged() {
+        let calibration = Calibration::default();
+        assert_eq!(calibration.apply(0), 0);
+        assert_eq!(calibration.apply(2150), 2150);
+        assert_eq!(calibration.apply(-4000), -4000);
+    }
+
+    #[test]
+    fn calibration_scales_then_offsets() {
+        let calibration = Calibration {
+            scale: 1020,
+            offset: -250,
+        };
+        // 21.50 °C * 1.020 - 2.50 °C = 19.43 °C
+        assert_eq!(calibration.apply(2150), 1943);
+        assert_eq!(calibration.apply(0), -250);
+        // Scaling truncates towards zero for negative readings too.
+        assert_eq!(calibration.apply(-1001), -1271);
+    }
+
+    #[test]
+    fn large_readings_do_not_overflow_while_scaling() {
+        let calibration = Calibration {
+            scale: 2000,
+            offset: 0,
+        };
+        assert_eq!(calibration.apply(1_000_000_000), 2_000_000_000);
+    }
+}
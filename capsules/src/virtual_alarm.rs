@@ -1,5 +1,30 @@
 //! Virtualize the Alarm interface to enable multiple users of an underlying
 //! alarm hardware peripheral.
+//!
+//! The mux keeps the underlying alarm set to the earliest deadline of the
+//! armed virtual alarms. While it is delivering expired alarms, any alarms
+//! their clients set are only recorded, and the underlying alarm is
+//! reprogrammed once, after every expired alarm (including any that expired
+//! while the others were being served) has fired.
+//!
+//! A mux scans all of its virtual alarms whenever the underlying alarm fires.
+//! On chips whose counter has several compare channels (see
+//! `hil::time::MultiAlarm`), a board can create one `MuxAlarm` per compare
+//! channel and spread the virtual alarms among them, so that each group of
+//! virtual alarms is backed by its own hardware compare:
+//!
+//! ```rust
+//! let mux_alarm = static_init!(
+//!     MuxAlarm<'static, cc26x2::rtc::Rtc>,
+//!     MuxAlarm::new(&cc26x2::rtc::RTC));
+//! cc26x2::rtc::RTC.set_client(mux_alarm);
+//!
+//! let channel = cc26x2::rtc::RTC.compare(2).unwrap();
+//! let app_mux_alarm = static_init!(
+//!     MuxAlarm<'static, cc26x2::rtc::RtcChannel>,
+//!     MuxAlarm::new(channel));
+//! channel.set_client(app_mux_alarm);
+//! ```
 
 use core::cell::Cell;
 use kernel::common::cells::OptionalCell;
@@ -53,8 +78,8 @@ impl<Alrm: Alarm> Time for VirtualMuxAlarm<'a, Alrm> {
         self.mux.enabled.set(enabled);
 
         // If there are not more enabled alarms, disable the underlying alarm
-        // completely.
-        if enabled == 0 {
+        // completely. While firing, the mux does this itself afterwards.
+        if enabled == 0 && !self.mux.firing.get() {
             self.mux.alarm.disable();
         }
     }
@@ -77,6 +102,14 @@ impl<Alrm: Alarm> Alarm for VirtualMuxAlarm<'a, Alrm> {
             self.armed.set(true);
         }
 
+        self.when.set(when);
+
+        // The mux reprograms the underlying alarm once it has fired every
+        // expired alarm.
+        if self.mux.firing.get() {
+            return;
+        }
+
         if enabled > 0 {
             let cur_alarm = self.mux.alarm.get_alarm();
             let now = self.now();
@@ -89,13 +122,15 @@ impl<Alrm: Alarm> Alarm for VirtualMuxAlarm<'a, Alrm> {
             self.mux.prev.set(self.mux.alarm.now());
             self.mux.alarm.set_alarm(when);
         }
-
-        self.when.set(when);
     }
 
     fn get_alarm(&self) -> u32 {
         self.when.get()
     }
+
+    fn max_tics(&self) -> u32 {
+        self.mux.alarm.max_tics()
+    }
 }
 
 impl<Alrm: Alarm> time::Client for VirtualMuxAlarm<'a, Alrm> {
@@ -110,6 +145,9 @@ pub struct MuxAlarm<'a, Alrm: Alarm> {
     virtual_alarms: List<'a, VirtualMuxAlarm<'a, Alrm>>,
     enabled: Cell<usize>,
     prev: Cell<u32>,
+    /// Whether expired alarms are being delivered, during which the
+    /// underlying alarm is not reprogrammed.
+    firing: Cell<bool>,
     alarm: &'a Alrm,
 }
 
@@ -119,6 +157,7 @@ impl<Alrm: Alarm> MuxAlarm<'a, Alrm> {
             virtual_alarms: List::new(),
             enabled: Cell::new(0),
             prev: Cell::new(0),
+            firing: Cell::new(false),
             alarm: alarm,
         }
     }
@@ -130,43 +169,252 @@ fn has_expired(alarm: u32, now: u32, prev: u32) -> bool {
 
 impl<Alrm: Alarm> time::Client for MuxAlarm<'a, Alrm> {
     fn fired(&self) {
-        let now = self.alarm.now();
-
-        // Capture this before the loop because it can change while checking
-        // each alarm. If a timer fires, it can immediately set a new timer
-        // by calling `VirtualMuxAlarm.set_alarm()` which can change `self.prev`
-        // to the current timer time.
-        let prev = self.prev.get();
-
-        // Check whether to fire each alarm. At this level, alarms are one-shot,
-        // so a repeating client will set it again in the fired() callback.
-        self.virtual_alarms
-            .iter()
-            .filter(|cur| cur.armed.get() && has_expired(cur.when.get(), now, prev))
-            .for_each(|cur| {
-                cur.armed.set(false);
-                self.enabled.set(self.enabled.get() - 1);
-                cur.fired();
-            });
-
-        // Find the soonest alarm client (if any) and set the "next" underlying
-        // alarm based on it.  This needs to happen after firing all expired
-        // alarms since those may have reset new alarms.
-        let next = self
-            .virtual_alarms
-            .iter()
-            .filter(|cur| cur.armed.get())
-            .min_by_key(|cur| cur.when.get().wrapping_sub(now));
-
-        self.prev.set(now);
-        // If there is an alarm to fire, set the underlying alarm to it
-        if let Some(valrm) = next {
-            self.alarm.set_alarm(valrm.when.get());
-            if has_expired(valrm.when.get(), self.alarm.now(), prev) {
-                self.fired();
+        self.firing.set(true);
+
+        // Keep firing until no armed alarm has expired, so alarms that expire
+        // while others are being served are handled in this same interrupt
+        // rather than by reprogramming the underlying alarm for each.
+        loop {
+            let now = self.alarm.now();
+
+            // Capture this before firing because expired alarms are checked
+            // against the time the underlying alarm was last set.
+            let prev = self.prev.get();
+
+            // Check whether to fire each alarm. At this level, alarms are
+            // one-shot, so a repeating client will set it again in the
+            // fired() callback.
+            self.virtual_alarms
+                .iter()
+                .filter(|cur| cur.armed.get() && has_expired(cur.when.get(), now, prev))
+                .for_each(|cur| {
+                    cur.armed.set(false);
+                    self.enabled.set(self.enabled.get() - 1);
+                    cur.fired();
+                });
+
+            // Find the soonest alarm client (if any). This needs to happen
+            // after firing all expired alarms since those may have reset new
+            // alarms.
+            let next = self
+                .virtual_alarms
+                .iter()
+                .filter(|cur| cur.armed.get())
+                .min_by_key(|cur| cur.when.get().wrapping_sub(now));
+
+            self.prev.set(now);
+            let when = match next {
+                Some(valrm) => valrm.when.get(),
+                None => {
+                    self.firing.set(false);
+                    self.alarm.disable();
+                    return;
+                }
+            };
+
+            if has_expired(when, self.alarm.now(), prev) {
+                continue;
             }
-        } else {
-            self.alarm.disable();
+
+            // Only reprogram the underlying alarm if the earliest deadline
+            // changed, then check that it did not pass while doing so.
+            if !self.alarm.is_armed() || self.alarm.get_alarm() != when {
+                self.alarm.set_alarm(when);
+                if has_expired(when, self.alarm.now(), prev) {
+                    continue;
+                }
+            }
+
+            self.firing.set(false);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::boxed::Box;
+    use super::*;
+    use kernel::hil::time::Freq32KHz;
+
+    /// Give a test value the `'static` lifetime that boards get from
+    /// `static_init!`, so it can be linked into the mux list.
+    fn leak<T>(value: T) -> &'static T {
+        Box::leak(Box::new(value))
+    }
+
+    /// An underlying alarm whose counter is moved by the test and which
+    /// counts how often it is programmed.
+    struct FakeAlarm {
+        now: Cell<u32>,
+        alarm: Cell<u32>,
+        armed: Cell<bool>,
+        sets: Cell<usize>,
+    }
+
+    impl FakeAlarm {
+        fn new() -> FakeAlarm {
+            FakeAlarm {
+                now: Cell::new(0),
+                alarm: Cell::new(0),
+                armed: Cell::new(false),
+                sets: Cell::new(0),
+            }
+        }
+    }
+
+    impl Time for FakeAlarm {
+        type Frequency = Freq32KHz;
+
+        fn disable(&self) {
+            self.armed.set(false);
+        }
+
+        fn is_armed(&self) -> bool {
+            self.armed.get()
         }
     }
+
+    impl Alarm for FakeAlarm {
+        fn now(&self) -> u32 {
+            self.now.get()
+        }
+
+        fn set_alarm(&self, when: u32) {
+            self.alarm.set(when);
+            self.armed.set(true);
+            self.sets.set(self.sets.get() + 1);
+        }
+
+        fn get_alarm(&self) -> u32 {
+            self.alarm.get()
+        }
+    }
+
+    /// A client that counts its callbacks and, when asked to, lets time pass
+    /// or sets its alarm again from within the callback.
+    struct TestClient<'a> {
+        fake: &'a FakeAlarm,
+        alarm: OptionalCell<&'a VirtualMuxAlarm<'a, FakeAlarm>>,
+        fired: Cell<usize>,
+        advance_to: Cell<Option<u32>>,
+        rearm_at: Cell<Option<u32>>,
+    }
+
+    impl TestClient<'a> {
+        fn new(fake: &'a FakeAlarm) -> TestClient<'a> {
+            TestClient {
+                fake: fake,
+                alarm: OptionalCell::empty(),
+                fired: Cell::new(0),
+                advance_to: Cell::new(None),
+                rearm_at: Cell::new(None),
+            }
+        }
+    }
+
+    impl time::Client for TestClient<'a> {
+        fn fired(&self) {
+            self.fired.set(self.fired.get() + 1);
+            if let Some(now) = self.advance_to.take() {
+                self.fake.now.set(now);
+            }
+            if let Some(when) = self.rearm_at.take() {
+                self.alarm.map(|alarm| alarm.set_alarm(when));
+            }
+        }
+    }
+
+    #[test]
+    fn has_expired_handles_wraparound() {
+        assert!(has_expired(0x10, 0x20, 0xFFFF_FF00));
+        assert!(!has_expired(0x30, 0x20, 0xFFFF_FF00));
+        assert!(has_expired(100, 100, 0));
+        assert!(!has_expired(101, 100, 0));
+    }
+
+    #[test]
+    fn alarm_expiring_while_firing_is_delivered_in_same_interrupt() {
+        let fake = leak(FakeAlarm::new());
+        let mux = leak(MuxAlarm::new(fake));
+        let va = leak(VirtualMuxAlarm::new(mux));
+        let vb = leak(VirtualMuxAlarm::new(mux));
+        let ca = leak(TestClient::new(fake));
+        let cb = leak(TestClient::new(fake));
+        va.set_client(ca);
+        vb.set_client(cb);
+
+        va.set_alarm(100);
+        vb.set_alarm(150);
+        assert_eq!(fake.sets.get(), 1);
+        assert_eq!(fake.get_alarm(), 100);
+
+        // The first client takes long enough for the second alarm to expire.
+        ca.advance_to.set(Some(200));
+        fake.now.set(100);
+        time::Client::fired(mux);
+
+        assert_eq!(ca.fired.get(), 1);
+        assert_eq!(cb.fired.get(), 1);
+        assert_eq!(fake.sets.get(), 1);
+        assert!(!fake.is_armed());
+        assert!(!va.is_armed() && !vb.is_armed());
+    }
+
+    #[test]
+    fn alarms_set_while_firing_reprogram_once() {
+        let fake = leak(FakeAlarm::new());
+        let mux = leak(MuxAlarm::new(fake));
+        let va = leak(VirtualMuxAlarm::new(mux));
+        let vb = leak(VirtualMuxAlarm::new(mux));
+        let vc = leak(VirtualMuxAlarm::new(mux));
+        let ca = leak(TestClient::new(fake));
+        let cb = leak(TestClient::new(fake));
+        let cc = leak(TestClient::new(fake));
+        va.set_client(ca);
+        vb.set_client(cb);
+        vc.set_client(cc);
+        ca.alarm.set(va);
+        cb.alarm.set(vb);
+
+        va.set_alarm(100);
+        vb.set_alarm(100);
+        vc.set_alarm(500);
+        assert_eq!(fake.sets.get(), 1);
+
+        ca.rearm_at.set(Some(300));
+        cb.rearm_at.set(Some(250));
+        fake.now.set(100);
+        time::Client::fired(mux);
+
+        assert_eq!(ca.fired.get(), 1);
+        assert_eq!(cb.fired.get(), 1);
+        assert_eq!(cc.fired.get(), 0);
+        assert_eq!(fake.sets.get(), 2);
+        assert_eq!(fake.get_alarm(), 250);
+        assert!(va.is_armed() && vb.is_armed() && vc.is_armed());
+    }
+
+    #[test]
+    fn unchanged_deadline_is_not_reprogrammed() {
+        let fake = leak(FakeAlarm::new());
+        let mux = leak(MuxAlarm::new(fake));
+        let va = leak(VirtualMuxAlarm::new(mux));
+        let ca = leak(TestClient::new(fake));
+        va.set_client(ca);
+
+        va.set_alarm(100);
+        assert_eq!(fake.sets.get(), 1);
+
+        // A spurious interrupt before the deadline leaves the underlying
+        // alarm, which already holds the earliest deadline, alone.
+        fake.now.set(50);
+        time::Client::fired(mux);
+
+        assert_eq!(ca.fired.get(), 0);
+        assert_eq!(fake.sets.get(), 1);
+        assert!(fake.is_armed() && va.is_armed());
+    }
 }
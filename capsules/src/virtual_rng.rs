@@ -0,0 +1,313 @@
+//! Virtualize a random number generator to enable multiple users of it.
+//!
+//! Each `VirtualRngMasterDevice` behaves like an `Rng` with its own client.
+//! Requests are served one at a time: a device's client receives the
+//! randomness until it returns `Continue::Done`, and the rest of the same
+//! run of randomness is then handed to the next device that is waiting.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let mux_rng = static_init!(
+//!     capsules::virtual_rng::MuxRngMaster<'static>,
+//!     capsules::virtual_rng::MuxRngMaster::new(entropy_to_random)
+//! );
+//! entropy_to_random.set_client(mux_rng);
+//!
+//! let rng_device = static_init!(
+//!     capsules::virtual_rng::VirtualRngMasterDevice<'static>,
+//!     capsules::virtual_rng::VirtualRngMasterDevice::new(mux_rng)
+//! );
+//! let rng = static_init!(
+//!     capsules::rng::RngDriver<'static>,
+//!     capsules::rng::RngDriver::new(
+//!         rng_device,
+//!         board_kernel.create_grant(&memory_allocation_capability)
+//!     )
+//! );
+//! rng_device.set_client(rng);
+//! ```
+
+use core::cell::Cell;
+use core::ptr;
+use kernel::common::cells::OptionalCell;
+use kernel::common::{List, ListLink, ListNode};
+use kernel::hil::rng;
+use kernel::ReturnCode;
+
+/// The Mux struct manages multiple Rng clients. Each client may have at most
+/// one outstanding request.
+pub struct MuxRngMaster<'a> {
+    rng: &'a rng::Rng<'a>,
+    devices: List<'a, VirtualRngMasterDevice<'a>>,
+    inflight: OptionalCell<&'a VirtualRngMasterDevice<'a>>,
+}
+
+impl rng::Client for MuxRngMaster<'a> {
+    fn randomness_available(
+        &self,
+        randomness: &mut Iterator<Item = u32>,
+        error: ReturnCode,
+    ) -> rng::Continue {
+        let more = self.inflight.map_or(false, |device| {
+            device.client.map_or(false, |client| {
+                client.randomness_available(randomness, error) == rng::Continue::More
+            })
+        });
+        if more {
+            return rng::Continue::More;
+        }
+
+        // Keep the generator running for the next device that is waiting.
+        self.inflight.clear();
+        match self.next_waiting() {
+            Some(device) => {
+                self.inflight.set(device);
+                rng::Continue::More
+            }
+            None => rng::Continue::Done,
+        }
+    }
+}
+
+impl MuxRngMaster<'a> {
+    pub const fn new(rng: &'a rng::Rng<'a>) -> MuxRngMaster<'a> {
+        MuxRngMaster {
+            rng: rng,
+            devices: List::new(),
+            inflight: OptionalCell::empty(),
+        }
+    }
+
+    /// Takes the request of the first device that is waiting, if any.
+    fn next_waiting(&self) -> Option<&'a VirtualRngMasterDevice<'a>> {
+        self.devices
+            .iter()
+            .find(|device| device.requested.get())
+            .map(|device| {
+                device.requested.set(false);
+                device
+            })
+    }
+
+    fn do_next_op(&self) -> ReturnCode {
+        if self.inflight.is_some() {
+            return ReturnCode::SUCCESS;
+        }
+        match self.next_waiting() {
+            Some(device) => {
+                let rval = self.rng.get();
+                if rval == ReturnCode::SUCCESS {
+                    self.inflight.set(device);
+                }
+                rval
+            }
+            None => ReturnCode::SUCCESS,
+        }
+    }
+
+    fn is_inflight(&self, device: &VirtualRngMasterDevice<'a>) -> bool {
+        self.inflight
+            .map_or(false, |inflight| ptr::eq(*inflight, device))
+    }
+}
+
+pub struct VirtualRngMasterDevice<'a> {
+    mux: &'a MuxRngMaster<'a>,
+    /// Whether the device is waiting for its turn at the generator.
+    requested: Cell<bool>,
+    next: ListLink<'a, VirtualRngMasterDevice<'a>>,
+    client: OptionalCell<&'a rng::Client>,
+}
+
+impl VirtualRngMasterDevice<'a> {
+    pub const fn new(mux: &'a MuxRngMaster<'a>) -> VirtualRngMasterDevice<'a> {
+        VirtualRngMasterDevice {
+            mux: mux,
+            requested: Cell::new(false),
+            next: ListLink::empty(),
+            client: OptionalCell::empty(),
+        }
+    }
+}
+
+impl ListNode<'a, VirtualRngMasterDevice<'a>> for VirtualRngMasterDevice<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, VirtualRngMasterDevice<'a>> {
+        &self.next
+    }
+}
+
+impl rng::Rng<'a> for VirtualRngMasterDevice<'a> {
+    fn get(&self) -> ReturnCode {
+        if self.requested.get() || self.mux.is_inflight(self) {
+            return ReturnCode::SUCCESS;
+        }
+        self.requested.set(true);
+        self.mux.do_next_op()
+    }
+
+    fn cancel(&self) -> ReturnCode {
+        if self.requested.get() {
+            self.requested.set(false);
+        } else if self.mux.is_inflight(self) {
+            // If the generator cannot stop, its callback goes to the next
+            // device that is waiting instead.
+            self.mux.inflight.clear();
+            if self.mux.rng.cancel() == ReturnCode::SUCCESS {
+                return self.mux.do_next_op();
+            }
+        }
+        ReturnCode::SUCCESS
+    }
+
+    fn set_client(&'a self, client: &'a rng::Client) {
+        self.mux.devices.push_head(self);
+        self.client.set(client);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::boxed::Box;
+    use super::*;
+    use kernel::hil::rng::Rng;
+
+    /// A generator that counts requests, and produces consecutive numbers
+    /// when `run` is called.
+    struct TestRng<'a> {
+        gets: Cell<usize>,
+        next: Cell<u32>,
+        client: OptionalCell<&'a rng::Client>,
+    }
+
+    impl TestRng<'a> {
+        /// Produces numbers until the client is done, and returns how many
+        /// were produced.
+        fn run(&self) -> u32 {
+            let start = self.next.get();
+            let mut numbers = (start..).inspect(|n| self.next.set(n + 1));
+            self.client.map(|client| {
+                while client.randomness_available(&mut numbers, ReturnCode::SUCCESS)
+                    == rng::Continue::More
+                {}
+            });
+            self.next.get() - start
+        }
+    }
+
+    impl rng::Rng<'a> for TestRng<'a> {
+        fn get(&self) -> ReturnCode {
+            self.gets.set(self.gets.get() + 1);
+            ReturnCode::SUCCESS
+        }
+
+        fn cancel(&self) -> ReturnCode {
+            ReturnCode::FAIL
+        }
+
+        fn set_client(&'a self, client: &'a rng::Client) {
+            self.client.set(client);
+        }
+    }
+
+    /// A client that takes `wanted` numbers and records the last one.
+    struct Taker {
+        wanted: Cell<usize>,
+        last: Cell<Option<u32>>,
+    }
+
+    impl Taker {
+        fn new() -> Taker {
+            Taker {
+                wanted: Cell::new(0),
+                last: Cell::new(None),
+            }
+        }
+    }
+
+    impl rng::Client for Taker {
+        fn randomness_available(
+            &self,
+            randomness: &mut Iterator<Item = u32>,
+            _error: ReturnCode,
+        ) -> rng::Continue {
+            while self.wanted.get() > 0 {
+                match randomness.next() {
+                    Some(number) => {
+                        self.last.set(Some(number));
+                        self.wanted.set(self.wanted.get() - 1);
+                    }
+                    None => return rng::Continue::More,
+                }
+            }
+            rng::Continue::Done
+        }
+    }
+
+    type Device = (&'static Taker, &'static VirtualRngMasterDevice<'static>);
+
+    /// A generator shared by two devices, each with its own client.
+    fn mux() -> (&'static TestRng<'static>, Device, Device) {
+        let generator = Box::leak(Box::new(TestRng {
+            gets: Cell::new(0),
+            next: Cell::new(0),
+            client: OptionalCell::empty(),
+        }));
+        let mux = Box::leak(Box::new(MuxRngMaster::new(generator)));
+        generator.set_client(mux);
+        (generator, device(mux), device(mux))
+    }
+
+    fn device(mux: &'static MuxRngMaster<'static>) -> Device {
+        let client = Box::leak(Box::new(Taker::new()));
+        let device = Box::leak(Box::new(VirtualRngMasterDevice::new(mux)));
+        device.set_client(client);
+        (client, device)
+    }
+
+    #[test]
+    fn waiting_devices_are_served_in_turn() {
+        let (generator, (first, first_device), (second, second_device)) = mux();
+
+        first.wanted.set(4);
+        second.wanted.set(2);
+        assert_eq!(first_device.get(), ReturnCode::SUCCESS);
+        assert_eq!(second_device.get(), ReturnCode::SUCCESS);
+        // A repeated request is the same request.
+        assert_eq!(second_device.get(), ReturnCode::SUCCESS);
+        assert_eq!(generator.gets.get(), 1);
+
+        // One run of the generator serves both devices, one after the other.
+        assert_eq!(generator.run(), 6);
+        assert_eq!(first.last.get(), Some(3));
+        assert_eq!(second.last.get(), Some(5));
+        assert_eq!(generator.run(), 0);
+
+        // Once idle, a new request starts the generator again.
+        first.wanted.set(1);
+        assert_eq!(first_device.get(), ReturnCode::SUCCESS);
+        assert_eq!(generator.gets.get(), 2);
+        assert_eq!(generator.run(), 1);
+        assert_eq!(first.last.get(), Some(6));
+    }
+
+    #[test]
+    fn cancelled_device_is_skipped() {
+        let (generator, (first, first_device), (second, second_device)) = mux();
+
+        first.wanted.set(4);
+        second.wanted.set(2);
+        first_device.get();
+        second_device.get();
+
+        // The generator cannot stop, so the device that was waiting gets the
+        // randomness that was meant for the cancelled one.
+        assert_eq!(first_device.cancel(), ReturnCode::SUCCESS);
+        assert_eq!(generator.run(), 2);
+        assert_eq!(first.last.get(), None);
+        assert_eq!(second.last.get(), Some(1));
+    }
+}
@@ -0,0 +1,131 @@
+//! Virtualize a temperature sensor.
+//!
+//! This allows multiple Tock capsules to share one temperature sensor. A read
+//! requested while the sensor is already sampling does not start a new
+//! sample; instead, every user with a pending read receives the result of the
+//! sample in progress.
+//!
+//! `MuxTemperature` provides shared access to a single sensor for multiple
+//! users. `VirtualTemperature` provides access for a single client.
+//!
+//! Usage
+//! -----
+//!
+//! ```
+//! let mux_temp = static_init!(
+//!     capsules::virtual_temperature::MuxTemperature<'static>,
+//!     capsules::virtual_temperature::MuxTemperature::new(&nrf5x::temperature::TEMP)
+//! );
+//! hil::sensors::TemperatureDriver::set_client(&nrf5x::temperature::TEMP, mux_temp);
+//!
+//! // Create a VirtualTemperature for each user of the sensor.
+//! let virtual_temp = static_init!(
+//!     capsules::virtual_temperature::VirtualTemperature<'static>,
+//!     capsules::virtual_temperature::VirtualTemperature::new(mux_temp)
+//! );
+//! virtual_temp.setup(); // This is important!
+//! let temp = static_init!(
+//!     capsules::temperature::TemperatureSensor<'static>,
+//!     capsules::temperature::TemperatureSensor::new(
+//!         virtual_temp,
+//!         board_kernel.create_grant(&memory_allocation_capability)
+//!     )
+//! );
+//! hil::sensors::TemperatureDriver::set_client(virtual_temp, temp);
+//! ```
+
+use core::cell::Cell;
+use kernel::common::cells::OptionalCell;
+use kernel::common::{List, ListLink, ListNode};
+use kernel::hil::sensors::{TemperatureClient, TemperatureDriver};
+use kernel::ReturnCode;
+
+/// Keeps the list of users of the temperature sensor and fans each reading out
+/// to all users that requested one.
+pub struct MuxTemperature<'a> {
+    driver: &'a TemperatureDriver,
+    users: List<'a, VirtualTemperature<'a>>,
+    busy: Cell<bool>,
+}
+
+impl MuxTemperature<'a> {
+    pub fn new(driver: &'a TemperatureDriver) -> MuxTemperature<'a> {
+        MuxTemperature {
+            driver: driver,
+            users: List::new(),
+            busy: Cell::new(false),
+        }
+    }
+
+    // Starts a sample unless one is already in progress, in which case the
+    // caller receives the result of that sample.
+    fn request_reading(&self) -> ReturnCode {
+        if self.busy.get() {
+            return ReturnCode::SUCCESS;
+        }
+        let result = self.driver.read_temperature();
+        if result == ReturnCode::SUCCESS {
+            self.busy.set(true);
+        }
+        result
+    }
+}
+
+impl TemperatureClient for MuxTemperature<'a> {
+    fn callback(&self, value: usize) {
+        self.busy.set(false);
+        for user in self.users.iter() {
+            if user.pending.get() {
+                user.pending.set(false);
+                user.client.map(|client| client.callback(value));
+            }
+        }
+    }
+}
+
+/// A single user of a shared temperature sensor.
+pub struct VirtualTemperature<'a> {
+    mux: &'a MuxTemperature<'a>,
+    pending: Cell<bool>,
+    client: OptionalCell<&'static TemperatureClient>,
+    next: ListLink<'a, VirtualTemperature<'a>>,
+}
+
+impl ListNode<'a, VirtualTemperature<'a>> for VirtualTemperature<'a> {
+    fn next(&self) -> &'a ListLink<VirtualTemperature<'a>> {
+        &self.next
+    }
+}
+
+impl VirtualTemperature<'a> {
+    pub fn new(mux: &'a MuxTemperature<'a>) -> VirtualTemperature<'a> {
+        VirtualTemperature {
+            mux: mux,
+            pending: Cell::new(false),
+            client: OptionalCell::empty(),
+            next: ListLink::empty(),
+        }
+    }
+
+    /// Must be called right after `static_init!()`.
+    pub fn setup(&'a self) {
+        self.mux.users.push_head(self);
+    }
+}
+
+impl TemperatureDriver for VirtualTemperature<'a> {
+    fn set_client(&self, client: &'static TemperatureClient) {
+        self.client.set(client);
+    }
+
+    fn read_temperature(&self) -> ReturnCode {
+        if self.pending.get() {
+            return ReturnCode::EBUSY;
+        }
+        let result = self.mux.request_reading();
+        if result == ReturnCode::SUCCESS {
+            self.pending.set(true);
+        }
+        result
+    }
+}
@@ -7,6 +7,10 @@
 //! Clients can choose if they want to receive. Incoming messages will be sent
 //! to all clients that have enabled receiving.
 //!
+//! Transmissions are serialized: each device may have one transmission
+//! outstanding, and devices with a pending transmission are served in
+//! round-robin order, so one client cannot starve the others.
+//!
 //! `UartMux` provides shared access to a single UART bus for multiple users.
 //! `UartDevice` provides access for a single client.
 //!
@@ -39,6 +43,7 @@
 
 use core::cell::Cell;
 use core::cmp;
+use core::ptr;
 
 use kernel::common::cells::{OptionalCell, TakeCell};
 use kernel::common::{List, ListLink, ListNode};
@@ -56,15 +61,21 @@ pub struct UartMux<'a> {
     inflight: OptionalCell<&'a UartDevice<'a>>,
     buffer: TakeCell<'static, [u8]>,
     completing_read: Cell<bool>,
+    completing_write: Cell<bool>,
 }
 
 impl<'a> hil::uart::Client for UartMux<'a> {
     fn transmit_complete(&self, tx_buffer: &'static mut [u8], error: hil::uart::Error) {
-        self.inflight.map(move |device| {
-            self.inflight.clear();
+        // A client that transmits again from its callback must not jump
+        // ahead of other clients that are already waiting, so the next
+        // transmission is only chosen once the callback has returned.
+        self.completing_write.set(true);
+        let completed = self.inflight.take();
+        completed.map(move |device| {
             device.transmit_complete(tx_buffer, error);
         });
-        self.do_next_op();
+        self.completing_write.set(false);
+        self.do_next_op_after(completed);
     }
 
     fn receive_complete(&self, buffer: &'static mut [u8], rx_len: usize, error: hil::uart::Error) {
@@ -80,6 +91,11 @@ impl<'a> hil::uart::Client for UartMux<'a> {
         // client. In the meanwhile, compute the length of the next underlying
         // UART read: if any client has more to read, issue another underlying
         // read.
+        //
+        // If the underlying read ended with an error, every pending client
+        // read completes with that error and the bytes received before it,
+        // and no new underlying read is issued unless a client asks for one
+        // in its callback.
         self.devices.iter().for_each(|device| {
             if device.receiver {
                 device.rx_buffer.take().map(|rxbuf| {
@@ -111,7 +127,16 @@ impl<'a> hil::uart::Client for UartMux<'a> {
                     // If this finishes the read, signal to the caller,
                     // otherwise update state so next read will fill in
                     // more data.
-                    if remaining == 0 {
+                    if error != hil::uart::Error::CommandComplete
+                        && state == UartDeviceReceiveState::Receiving
+                    {
+                        device.state.set(UartDeviceReceiveState::Idle);
+                        device.receive_complete(rxbuf, position, error);
+                        // Need to check if receive was called in callback
+                        if device.state.get() == UartDeviceReceiveState::Receiving {
+                            read_pending = true;
+                        }
+                    } else if remaining == 0 {
                         device.state.set(UartDeviceReceiveState::Idle);
                         device.receive_complete(rxbuf, position, error);
                         // Need to check if receive was called in callback
@@ -149,6 +174,7 @@ impl<'a> UartMux<'a> {
             inflight: OptionalCell::empty(),
             buffer: TakeCell::new(buffer),
             completing_read: Cell::new(false),
+            completing_write: Cell::new(false),
         }
     }
 
@@ -162,8 +188,28 @@ impl<'a> UartMux<'a> {
     }
 
     fn do_next_op(&self) {
-        if self.inflight.is_none() {
-            let mnode = self.devices.iter().find(|node| node.operation.is_some());
+        self.do_next_op_after(None);
+    }
+
+    /// Starts the next pending operation, if the UART is idle. Devices are
+    /// served round-robin: the search starts at the device after `last`, so
+    /// that every device with a pending operation gets a turn before `last`
+    /// is served again.
+    fn do_next_op_after(&self, last: Option<&'a UartDevice<'a>>) {
+        if self.inflight.is_none() && !self.completing_write.get() {
+            let mut passed_last = last.is_none();
+            let mut after_last = None;
+            for node in self.devices.iter() {
+                if passed_last && node.operation.is_some() {
+                    after_last = Some(node);
+                    break;
+                }
+                if last.map_or(false, |last| ptr::eq(last, node)) {
+                    passed_last = true;
+                }
+            }
+            let mnode = after_last
+                .or_else(|| self.devices.iter().find(|node| node.operation.is_some()));
             mnode.map(|node| {
                 node.tx_buffer.take().map(|buf| {
                     node.operation.map(move |op| match op {
@@ -315,3 +361,116 @@ impl<'a> hil::uart::UART for UartDevice<'a> {
         self.mux.uart.abort_receive();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::boxed::Box;
+    use self::std::cell::RefCell;
+    use self::std::vec::Vec;
+    use super::*;
+    use kernel::hil::uart::{Client, UART};
+
+    /// A UART that holds on to each transmitted buffer until the test
+    /// completes the transmission.
+    struct TestUart {
+        tx_buffer: TakeCell<'static, [u8]>,
+        /// The first byte of each transmitted buffer, in order.
+        sent: RefCell<Vec<u8>>,
+    }
+
+    impl hil::uart::UART for TestUart {
+        fn set_client(&self, _client: &'static hil::uart::Client) {}
+
+        fn configure(&self, _params: hil::uart::UARTParameters) -> ReturnCode {
+            ReturnCode::SUCCESS
+        }
+
+        fn transmit(&self, tx_data: &'static mut [u8], _tx_len: usize) {
+            assert!(self.tx_buffer.is_none());
+            self.sent.borrow_mut().push(tx_data[0]);
+            self.tx_buffer.replace(tx_data);
+        }
+
+        fn receive(&self, _rx_buffer: &'static mut [u8], _rx_len: usize) {}
+
+        fn abort_receive(&self) {}
+    }
+
+    /// A client that transmits again from its callback until it has sent
+    /// `remaining` more buffers.
+    struct Writer {
+        device: &'static UartDevice<'static>,
+        remaining: Cell<usize>,
+    }
+
+    impl hil::uart::Client for Writer {
+        fn transmit_complete(&self, tx_buffer: &'static mut [u8], _error: hil::uart::Error) {
+            if self.remaining.get() > 0 {
+                self.remaining.set(self.remaining.get() - 1);
+                self.device.transmit(tx_buffer, 1);
+            }
+        }
+
+        fn receive_complete(
+            &self,
+            _rx_buffer: &'static mut [u8],
+            _rx_len: usize,
+            _error: hil::uart::Error,
+        ) {
+        }
+    }
+
+    fn writer(mux: &'static UartMux<'static>, id: u8, count: usize) -> &'static Writer {
+        let device = Box::leak(Box::new(UartDevice::new(mux, false)));
+        device.setup();
+        let writer = Box::leak(Box::new(Writer {
+            device: device,
+            remaining: Cell::new(count - 1),
+        }));
+        device.set_client(writer);
+        device.transmit(Box::leak(Box::new([id])), 1);
+        writer
+    }
+
+    #[test]
+    fn clients_transmitting_from_callbacks_take_turns() {
+        let uart = Box::leak(Box::new(TestUart {
+            tx_buffer: TakeCell::empty(),
+            sent: RefCell::new(Vec::new()),
+        }));
+        let mux = Box::leak(Box::new(UartMux::new(uart, Box::leak(Box::new([0; 4])), 115200)));
+        writer(mux, b'a', 3);
+        writer(mux, b'b', 3);
+
+        // Each completion lets the other client go first, even though the
+        // completed client queues its next transmission in its callback.
+        while let Some(buffer) = uart.tx_buffer.take() {
+            mux.transmit_complete(buffer, hil::uart::Error::CommandComplete);
+        }
+        assert_eq!(&uart.sent.borrow()[..], b"ababab");
+    }
+
+    #[test]
+    fn waiting_client_is_served_before_repeat() {
+        let uart = Box::leak(Box::new(TestUart {
+            tx_buffer: TakeCell::empty(),
+            sent: RefCell::new(Vec::new()),
+        }));
+        let mux = Box::leak(Box::new(UartMux::new(uart, Box::leak(Box::new([0; 4])), 115200)));
+        writer(mux, b'a', 4);
+        writer(mux, b'b', 1);
+        writer(mux, b'c', 2);
+
+        while let Some(buffer) = uart.tx_buffer.take() {
+            mux.transmit_complete(buffer, hil::uart::Error::CommandComplete);
+        }
+        // Once a client has nothing more to send, the others keep taking
+        // turns without it.
+        let sent = uart.sent.borrow();
+        assert_eq!(sent.len(), 7);
+        assert_eq!(&sent[..3], b"acb");
+        assert_eq!(&sent[3..], b"acaa");
+    }
+}
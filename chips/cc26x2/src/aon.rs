@@ -109,8 +109,9 @@ impl Aon {
         // Default to no events at all
         regs.aux_wu_sel.set(0x3F3F3F3F);
 
-        // Set RTC CH1 as a wakeup source by default
-        regs.mcu_wu_sel.set(0x3F3F3F24);
+        // Set RTC CH1 and CH2 as wakeup sources by default, so that alarms on
+        // either compare channel fire while the MCU is powered down
+        regs.mcu_wu_sel.set(0x3F3F2524);
 
         // Disable RTC combined event
         regs.rtc_sel.set(0x0000003F);
@@ -253,6 +253,12 @@ impl hil::gpio::PinCtl for GPIOPin {
     }
 }
 
+impl hil::gpio::InterruptPin for GPIOPin {
+    fn set_client(&self, client: &'static hil::gpio::Client) {
+        self.client.set(client);
+    }
+}
+
 impl hil::gpio::Pin for GPIOPin {
     fn make_output(&self) {
         self.enable_gpio();
@@ -3,7 +3,7 @@
 use kernel::common::cells::OptionalCell;
 use kernel::common::registers::{ReadOnly, ReadWrite};
 use kernel::common::StaticRef;
-use kernel::hil::time::{self, Alarm, Frequency, Time};
+use kernel::hil::time::{self, Alarm, Frequency, MultiAlarm, Time};
 
 #[repr(C)]
 struct RtcRegisters {
@@ -19,9 +19,9 @@ struct RtcRegisters {
 
     _subsec_inc: ReadOnly<u32>,
     channel_ctl: ReadWrite<u32, ChannelControl::Register>,
-    _channel0_cmp: ReadOnly<u32>,
+    channel0_cmp: ReadWrite<u32>,
     channel1_cmp: ReadWrite<u32>,
-    _channel2_cmp: ReadOnly<u32>,
+    channel2_cmp: ReadWrite<u32>,
     _channel2_cmp_inc: ReadOnly<u32>,
     _channel1_capture: ReadOnly<u32>,
 
@@ -33,12 +33,11 @@ struct RtcRegisters {
 register_bitfields![
     u32,
     Control [
-        COMB_EV_MASK OFFSET(16) NUMBITS(3) [
-            NoEvent = 0b00,
-            Channel0 = 0b01,
-            Channel1 = 0b10,
-            Channel2 = 0b11
-        ],
+        // Events of the selected channels form the combined event, which is
+        // the RTC interrupt.
+        COMB_EV_CH2 OFFSET(18) NUMBITS(1) [],
+        COMB_EV_CH1 OFFSET(17) NUMBITS(1) [],
+        COMB_EV_CH0 OFFSET(16) NUMBITS(1) [],
         RESET       OFFSET(7) NUMBITS(1) [],
         RTC_UPD_EN  OFFSET(1) NUMBITS(1) [],
         ENABLE      OFFSET(0) NUMBITS(1) []
@@ -60,9 +59,163 @@ register_bitfields![
 const RTC_BASE: StaticRef<RtcRegisters> =
     unsafe { StaticRef::new(0x40092000 as *const RtcRegisters) };
 
+/// Number of compare channels on the RTC.
+const NUM_COMPARES: usize = 3;
+
+/// Compare channel used by `Rtc`'s own `Alarm` implementation.
+const DEFAULT_CHANNEL: usize = 1;
+
+/// One of the RTC's compare channels, usable as an independent alarm.
+///
+/// Only channels 1 and 2 are configured as wakeup sources by
+/// `aon::AON.setup()`, so channel 0 only fires while the MCU is not powered
+/// down.
+pub struct RtcChannel {
+    registers: StaticRef<RtcRegisters>,
+    index: usize,
+    client: OptionalCell<&'static time::Client>,
+}
+
+impl RtcChannel {
+    const fn new(index: usize) -> RtcChannel {
+        RtcChannel {
+            registers: RTC_BASE,
+            index: index,
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'static time::Client) {
+        self.client.set(client);
+    }
+
+    fn compare_register(&self) -> &ReadWrite<u32> {
+        let regs = &*self.registers;
+        match self.index {
+            0 => &regs.channel0_cmp,
+            1 => &regs.channel1_cmp,
+            _ => &regs.channel2_cmp,
+        }
+    }
+
+    fn arm(&self, enable: bool) {
+        let regs = &*self.registers;
+        let value = enable as u32;
+        match self.index {
+            0 => {
+                regs.ctl.modify(Control::COMB_EV_CH0.val(value));
+                regs.channel_ctl.modify(ChannelControl::CH0_EN.val(value));
+            }
+            1 => {
+                regs.ctl.modify(Control::COMB_EV_CH1.val(value));
+                regs.channel_ctl.modify(ChannelControl::CH1_EN.val(value));
+            }
+            _ => {
+                regs.ctl.modify(Control::COMB_EV_CH2.val(value));
+                regs.channel_ctl.modify(
+                    ChannelControl::CH2_EN.val(value) + ChannelControl::CH2_CONT_EN::CLEAR,
+                );
+            }
+        }
+    }
+
+    fn event_pending(&self) -> bool {
+        let regs = &*self.registers;
+        match self.index {
+            0 => regs.evflags.is_set(EvFlags::CH0),
+            1 => regs.evflags.is_set(EvFlags::CH1),
+            _ => regs.evflags.is_set(EvFlags::CH2),
+        }
+    }
+
+    fn clear_event(&self) {
+        let regs = &*self.registers;
+        // Event flags are cleared by writing a one to them
+        match self.index {
+            0 => regs.evflags.write(EvFlags::CH0::SET),
+            1 => regs.evflags.write(EvFlags::CH1::SET),
+            _ => regs.evflags.write(EvFlags::CH2::SET),
+        }
+    }
+
+    /// If this channel is armed and has reached its compare value, disarm it
+    /// and notify its client.
+    fn handle_event(&self) {
+        if !self.is_armed() || !self.event_pending() {
+            return;
+        }
+        self.clear_event();
+        self.arm(false);
+
+        let regs = &*self.registers;
+        regs.sync.get();
+
+        self.client.map(|cb| cb.fired());
+    }
+}
+
+impl Time for RtcChannel {
+    type Frequency = RtcFreq;
+
+    fn disable(&self) {
+        self.arm(false);
+
+        let regs = &*self.registers;
+        regs.sync.get();
+    }
+
+    fn is_armed(&self) -> bool {
+        let regs = &*self.registers;
+        match self.index {
+            0 => regs.channel_ctl.is_set(ChannelControl::CH0_EN),
+            1 => regs.channel_ctl.is_set(ChannelControl::CH1_EN),
+            _ => regs.channel_ctl.is_set(ChannelControl::CH2_EN),
+        }
+    }
+}
+
+impl Alarm for RtcChannel {
+    fn now(&self) -> u32 {
+        read_counter(&*self.registers)
+    }
+
+    fn set_alarm(&self, tics: u32) {
+        let regs = &*self.registers;
+
+        self.compare_register().set(tics);
+        self.clear_event();
+        self.arm(true);
+
+        regs.sync.get();
+    }
+
+    fn get_alarm(&self) -> u32 {
+        self.compare_register().get()
+    }
+}
+
+fn read_counter(regs: &RtcRegisters) -> u32 {
+    /*
+        SEC can change during the SUBSEC read, so we need to be certain
+        that the SUBSEC we read belong to the correct SEC counterpart.
+    */
+    let mut current_sec: u32 = 0;
+    let mut current_subsec: u32 = 0;
+    let mut after_subsec_read: u32 = 1;
+    while current_sec != after_subsec_read {
+        current_sec = regs.sec.get();
+        current_subsec = regs.subsec.get();
+        after_subsec_read = regs.sec.get();
+    }
+
+    return (current_sec << 16) | (current_subsec >> 16);
+}
+
+/// The RTC. It implements `Alarm` using compare channel 1, and exposes all
+/// three compare channels as independent alarms through `MultiAlarm`.
 pub struct Rtc {
     registers: StaticRef<RtcRegisters>,
-    callback: OptionalCell<&'static time::Client>,
+    channels: [RtcChannel; NUM_COMPARES],
 }
 
 pub static mut RTC: Rtc = Rtc::new();
@@ -71,7 +224,7 @@ impl Rtc {
     const fn new() -> Rtc {
         Rtc {
             registers: RTC_BASE,
-            callback: OptionalCell::empty(),
+            channels: [RtcChannel::new(0), RtcChannel::new(1), RtcChannel::new(2)],
         }
     }
 
@@ -95,45 +248,19 @@ impl Rtc {
 
         regs.sync.get();
     }
-    fn read_counter(&self) -> u32 {
-        let regs = &*self.registers;
-
-        /*
-            SEC can change during the SUBSEC read, so we need to be certain
-            that the SUBSEC we read belong to the correct SEC counterpart.
-        */
-        let mut current_sec: u32 = 0;
-        let mut current_subsec: u32 = 0;
-        let mut after_subsec_read: u32 = 1;
-        while current_sec != after_subsec_read {
-            current_sec = regs.sec.get();
-            current_subsec = regs.subsec.get();
-            after_subsec_read = regs.sec.get();
-        }
-
-        return (current_sec << 16) | (current_subsec >> 16);
-    }
 
     pub fn is_running(&self) -> bool {
-        let regs = &*self.registers;
-        regs.channel_ctl.read(ChannelControl::CH1_EN) != 0
+        self.channels[DEFAULT_CHANNEL].is_armed()
     }
 
     pub fn handle_interrupt(&self) {
-        let regs = &*self.registers;
-
-        // Event flag is cleared when you set it
-        regs.evflags.write(EvFlags::CH1::SET);
-        regs.ctl.modify(Control::COMB_EV_MASK::NoEvent);
-        regs.channel_ctl.modify(ChannelControl::CH1_EN::CLEAR);
-
-        regs.sync.get();
-
-        self.callback.map(|cb| cb.fired());
+        for channel in self.channels.iter() {
+            channel.handle_event();
+        }
     }
 
     pub fn set_client(&self, client: &'static time::Client) {
-        self.callback.set(client);
+        self.channels[DEFAULT_CHANNEL].set_client(client);
     }
 
     pub fn set_upd_en(&self, value: bool) {
@@ -161,12 +288,7 @@ impl Time for Rtc {
     type Frequency = RtcFreq;
 
     fn disable(&self) {
-        let regs = &*self.registers;
-
-        regs.ctl.modify(Control::COMB_EV_MASK::NoEvent);
-        regs.channel_ctl.modify(ChannelControl::CH1_EN::CLEAR);
-
-        regs.sync.get();
+        self.channels[DEFAULT_CHANNEL].disable();
     }
 
     fn is_armed(&self) -> bool {
@@ -176,21 +298,28 @@ impl Time for Rtc {
 
 impl Alarm for Rtc {
     fn now(&self) -> u32 {
-        self.read_counter()
+        self.channels[DEFAULT_CHANNEL].now()
     }
 
     fn set_alarm(&self, tics: u32) {
-        let regs = &*self.registers;
+        self.channels[DEFAULT_CHANNEL].set_alarm(tics);
+    }
 
-        regs.ctl.modify(Control::COMB_EV_MASK::Channel1);
-        regs.channel1_cmp.set(tics);
-        regs.channel_ctl.modify(ChannelControl::CH1_EN::SET);
+    fn get_alarm(&self) -> u32 {
+        self.channels[DEFAULT_CHANNEL].get_alarm()
+    }
+}
 
-        regs.sync.get();
+/// Channel 1 is shared with `Rtc`'s own `Alarm` implementation, so a board
+/// that uses `RTC` as an alarm should only take channels 0 and 2.
+impl MultiAlarm for Rtc {
+    type Channel = RtcChannel;
+
+    fn num_compares(&self) -> usize {
+        NUM_COMPARES
     }
 
-    fn get_alarm(&self) -> u32 {
-        let regs = &*self.registers;
-        regs.channel1_cmp.get()
+    fn compare(&self, index: usize) -> Option<&RtcChannel> {
+        self.channels.get(index)
     }
 }
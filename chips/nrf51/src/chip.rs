@@ -2,8 +2,10 @@ use cortexm0;
 use cortexm0::nvic;
 use i2c;
 use kernel;
+use kernel::hil::power_manager::PowerManager;
 use nrf5x;
 use nrf5x::peripheral_interrupts;
+use nrf5x::power::SleepMode;
 use radio;
 use uart;
 
@@ -97,6 +99,11 @@ impl kernel::Chip for NRF51 {
 
     fn sleep(&self) {
         unsafe {
+            match nrf5x::power::POWER.sleep_mode() {
+                Some(SleepMode::DeepSleep) => cortexm0::scb::set_sleepdeep(),
+                Some(SleepMode::Sleep) => cortexm0::scb::unset_sleepdeep(),
+                None => return,
+            }
             cortexm0::support::wfi();
         }
     }
@@ -190,26 +190,29 @@ impl UART {
         self.enable();
     }
 
-    fn set_baud_rate(&self, baud_rate: u32) {
+    fn program_baud_rate(&self, baud_rate: u32) -> ReturnCode {
         let regs = &*self.registers;
-        match baud_rate {
-            1200 => regs.baudrate.write(Baudrate::BAUDRATE::Baud1200),
-            2400 => regs.baudrate.write(Baudrate::BAUDRATE::Baud2400),
-            4800 => regs.baudrate.write(Baudrate::BAUDRATE::Baud4800),
-            9600 => regs.baudrate.write(Baudrate::BAUDRATE::Baud9600),
-            14400 => regs.baudrate.write(Baudrate::BAUDRATE::Baud14400),
-            19200 => regs.baudrate.write(Baudrate::BAUDRATE::Baud19200),
-            28800 => regs.baudrate.write(Baudrate::BAUDRATE::Baud28800),
-            38400 => regs.baudrate.write(Baudrate::BAUDRATE::Baud38400),
-            57600 => regs.baudrate.write(Baudrate::BAUDRATE::Baud57600),
-            76800 => regs.baudrate.write(Baudrate::BAUDRATE::Baud76800),
-            115200 => regs.baudrate.write(Baudrate::BAUDRATE::Baud115200),
-            230400 => regs.baudrate.write(Baudrate::BAUDRATE::Baud230400),
-            250000 => regs.baudrate.write(Baudrate::BAUDRATE::Baud250000),
-            460800 => regs.baudrate.write(Baudrate::BAUDRATE::Baud460800),
-            1000000 => regs.baudrate.write(Baudrate::BAUDRATE::Baud1M),
-            _ => panic!("Illegal baud rate"),
-        }
+        let baud_rate_value = match baud_rate {
+            1200 => Baudrate::BAUDRATE::Baud1200,
+            2400 => Baudrate::BAUDRATE::Baud2400,
+            4800 => Baudrate::BAUDRATE::Baud4800,
+            9600 => Baudrate::BAUDRATE::Baud9600,
+            14400 => Baudrate::BAUDRATE::Baud14400,
+            19200 => Baudrate::BAUDRATE::Baud19200,
+            28800 => Baudrate::BAUDRATE::Baud28800,
+            38400 => Baudrate::BAUDRATE::Baud38400,
+            57600 => Baudrate::BAUDRATE::Baud57600,
+            76800 => Baudrate::BAUDRATE::Baud76800,
+            115200 => Baudrate::BAUDRATE::Baud115200,
+            230400 => Baudrate::BAUDRATE::Baud230400,
+            250000 => Baudrate::BAUDRATE::Baud250000,
+            460800 => Baudrate::BAUDRATE::Baud460800,
+            921600 => Baudrate::BAUDRATE::Baud921600,
+            1000000 => Baudrate::BAUDRATE::Baud1M,
+            _ => return ReturnCode::EINVAL,
+        };
+        regs.baudrate.write(baud_rate_value);
+        ReturnCode::SUCCESS
     }
 
     pub fn enable(&self) {
@@ -307,9 +310,7 @@ impl uart::UART for UART {
             return ReturnCode::ENOSUPPORT;
         }
 
-        self.set_baud_rate(params.baud_rate);
-
-        ReturnCode::SUCCESS
+        self.program_baud_rate(params.baud_rate)
     }
 
     fn transmit(&self, tx_data: &'static mut [u8], tx_len: usize) {
@@ -345,3 +346,13 @@ impl uart::UART for UART {
         unimplemented!()
     }
 }
+
+impl uart::UARTBaudRate for UART {
+    fn set_baud_rate(&self, baud_rate: u32) -> ReturnCode {
+        // The transmit buffer is held until the last byte has been sent.
+        if self.buffer.is_some() {
+            return ReturnCode::EBUSY;
+        }
+        self.program_baud_rate(baud_rate)
+    }
+}
@@ -1,11 +1,14 @@
 use adc;
+use comp;
 use cortexm4::{self, nvic};
 use deferred_call_tasks::DeferredCallTask;
 use i2c;
 use kernel;
 use kernel::common::deferred_call;
+use kernel::hil::power_manager::PowerManager;
 use nrf5x;
 use nrf5x::peripheral_interrupts;
+use nrf5x::power::SleepMode;
 use nvmc;
 use radio;
 use spi;
@@ -69,7 +72,9 @@ impl kernel::Chip for NRF52 {
                             // SPI0 and TWI0 share interrupts.
                             // Dispatch the correct handler.
                             match (spi::SPIM0.is_enabled(), i2c::TWIM0.is_enabled()) {
-                                (false, false) => (),
+                                (false, false) => if i2c::TWIS0.is_enabled() {
+                                    i2c::TWIS0.handle_interrupt()
+                                },
                                 (true, false) => spi::SPIM0.handle_interrupt(),
                                 (false, true) => i2c::TWIM0.handle_interrupt(),
                                 (true, true) => debug_assert!(
@@ -83,7 +88,9 @@ impl kernel::Chip for NRF52 {
                             // SPI1 and TWI1 share interrupts.
                             // Dispatch the correct handler.
                             match (spi::SPIM1.is_enabled(), i2c::TWIM1.is_enabled()) {
-                                (false, false) => (),
+                                (false, false) => if i2c::TWIS1.is_enabled() {
+                                    i2c::TWIS1.handle_interrupt()
+                                },
                                 (true, false) => spi::SPIM1.handle_interrupt(),
                                 (false, true) => i2c::TWIM1.handle_interrupt(),
                                 (true, true) => debug_assert!(
@@ -95,6 +102,8 @@ impl kernel::Chip for NRF52 {
                         }
                         peripheral_interrupts::SPIM2_SPIS2_SPI2 => spi::SPIM2.handle_interrupt(),
                         peripheral_interrupts::ADC => adc::ADC.handle_interrupt(),
+                        // COMP shares its interrupt with LPCOMP, which is unused.
+                        peripheral_interrupts::LPCOMP => comp::COMP.handle_interrupt(),
                         _ => debug!("NvicIdx not supported by Tock"),
                     }
                     let n = nvic::Nvic::new(interrupt);
@@ -113,6 +122,11 @@ impl kernel::Chip for NRF52 {
 
     fn sleep(&self) {
         unsafe {
+            match nrf5x::power::POWER.sleep_mode() {
+                Some(SleepMode::DeepSleep) => cortexm4::scb::set_sleepdeep(),
+                Some(SleepMode::Sleep) => cortexm4::scb::unset_sleepdeep(),
+                None => return,
+            }
             cortexm4::support::wfi();
         }
     }
@@ -0,0 +1,367 @@
+//! Analog comparator driver for the nRF52. Uses the COMP peripheral.
+//!
+//! The COMP peripheral has a single comparator that can be connected to any
+//! of the eight analog inputs (AIN0-AIN7). This driver runs it in
+//! single-ended mode, comparing the selected input against a threshold
+//! derived from the internal 2.4V reference. Each channel remembers its own
+//! threshold, which is applied whenever the comparator is switched to that
+//! channel.
+//!
+//! Because there is only one comparator, interrupt-based comparisons can only
+//! be active on one channel at a time. A single comparison on another channel
+//! temporarily switches the comparator input and then restores it.
+//!
+//! The interrupt is shared with LPCOMP, which Tock does not use.
+
+use core::cell::Cell;
+use kernel::common::cells::OptionalCell;
+use kernel::common::registers::{ReadOnly, ReadWrite, WriteOnly};
+use kernel::common::StaticRef;
+use kernel::hil;
+use kernel::ReturnCode;
+
+#[repr(C)]
+struct CompRegisters {
+    /// Start comparator
+    /// Address: 0x000 - 0x004
+    tasks_start: WriteOnly<u32, TASK::Register>,
+    /// Stop comparator
+    /// Address: 0x004 - 0x008
+    tasks_stop: WriteOnly<u32, TASK::Register>,
+    /// Sample comparator value
+    /// Address: 0x008 - 0x00C
+    tasks_sample: WriteOnly<u32, TASK::Register>,
+    _reserved0: [u32; 61],
+    /// COMP is ready and output is valid
+    /// Address: 0x100 - 0x104
+    events_ready: ReadWrite<u32, EVENT::Register>,
+    /// Downward crossing
+    /// Address: 0x104 - 0x108
+    events_down: ReadWrite<u32, EVENT::Register>,
+    /// Upward crossing
+    /// Address: 0x108 - 0x10C
+    events_up: ReadWrite<u32, EVENT::Register>,
+    /// Downward or upward crossing
+    /// Address: 0x10C - 0x110
+    events_cross: ReadWrite<u32, EVENT::Register>,
+    _reserved1: [u32; 60],
+    /// Shortcut register
+    /// Address: 0x200 - 0x204
+    shorts: ReadWrite<u32>,
+    _reserved2: [u32; 63],
+    /// Enable or disable interrupt
+    /// Address: 0x300 - 0x304
+    inten: ReadWrite<u32, INTEN::Register>,
+    /// Enable interrupt
+    /// Address: 0x304 - 0x308
+    intenset: ReadWrite<u32, INTEN::Register>,
+    /// Disable interrupt
+    /// Address: 0x308 - 0x30C
+    intenclr: ReadWrite<u32, INTEN::Register>,
+    _reserved3: [u32; 61],
+    /// Compare result
+    /// Address: 0x400 - 0x404
+    result: ReadOnly<u32, RESULT::Register>,
+    _reserved4: [u32; 63],
+    /// COMP enable
+    /// Address: 0x500 - 0x504
+    enable: ReadWrite<u32, ENABLE::Register>,
+    /// Pin select
+    /// Address: 0x504 - 0x508
+    psel: ReadWrite<u32, PSEL::Register>,
+    /// Reference source select for single-ended mode
+    /// Address: 0x508 - 0x50C
+    refsel: ReadWrite<u32, REFSEL::Register>,
+    /// External reference select
+    /// Address: 0x50C - 0x510
+    extrefsel: ReadWrite<u32>,
+    _reserved5: [u32; 8],
+    /// Threshold configuration for hysteresis unit
+    /// Address: 0x530 - 0x534
+    th: ReadWrite<u32, TH::Register>,
+    /// Mode configuration
+    /// Address: 0x534 - 0x538
+    mode: ReadWrite<u32, MODE::Register>,
+    /// Comparator hysteresis enable
+    /// Address: 0x538 - 0x53C
+    hyst: ReadWrite<u32>,
+    /// Current source select on analog input
+    /// Address: 0x53C - 0x540
+    isource: ReadWrite<u32>,
+}
+
+register_bitfields![u32,
+    TASK [
+        ENABLE OFFSET(0) NUMBITS(1)
+    ],
+
+    EVENT [
+        EVENT OFFSET(0) NUMBITS(1)
+    ],
+
+    INTEN [
+        READY OFFSET(0) NUMBITS(1),
+        DOWN OFFSET(1) NUMBITS(1),
+        UP OFFSET(2) NUMBITS(1),
+        CROSS OFFSET(3) NUMBITS(1)
+    ],
+
+    RESULT [
+        RESULT OFFSET(0) NUMBITS(1) [
+            Below = 0,
+            Above = 1
+        ]
+    ],
+
+    ENABLE [
+        ENABLE OFFSET(0) NUMBITS(2) [
+            Disabled = 0,
+            Enabled = 2
+        ]
+    ],
+
+    PSEL [
+        PSEL OFFSET(0) NUMBITS(3)
+    ],
+
+    REFSEL [
+        REFSEL OFFSET(0) NUMBITS(3) [
+            Int1V2 = 0,
+            Int1V8 = 1,
+            Int2V4 = 2,
+            VDD = 4,
+            ARef = 7
+        ]
+    ],
+
+    TH [
+        THDOWN OFFSET(0) NUMBITS(6),
+        THUP OFFSET(8) NUMBITS(6)
+    ],
+
+    MODE [
+        SP OFFSET(0) NUMBITS(2) [
+            Low = 0,
+            Normal = 1,
+            High = 2
+        ],
+        MAIN OFFSET(8) NUMBITS(1) [
+            SE = 0,
+            Diff = 1
+        ]
+    ]
+];
+
+const COMP_BASE: StaticRef<CompRegisters> =
+    unsafe { StaticRef::new(0x40013000 as *const CompRegisters) };
+
+/// Voltage of the internal reference the thresholds are derived from.
+const REFERENCE_MV: usize = 2400;
+
+/// Number of threshold steps the reference is divided into.
+const THRESHOLD_STEPS: usize = 64;
+
+/// Convert a reference level in millivolts to the 6-bit threshold value for
+/// the TH register, where the level is `(threshold + 1) / 64` of the
+/// reference. Levels are rounded to the nearest step. Returns `None` if the
+/// level is outside what the reference can express.
+pub fn threshold_for_millivolts(millivolts: usize) -> Option<u32> {
+    if millivolts > REFERENCE_MV {
+        return None;
+    }
+    let steps = (millivolts * THRESHOLD_STEPS + REFERENCE_MV / 2) / REFERENCE_MV;
+    if steps == 0 {
+        None
+    } else {
+        Some((steps - 1) as u32)
+    }
+}
+
+/// An analog input the comparator can be connected to.
+pub struct CompChannel {
+    chan_num: u32,
+    threshold: Cell<u32>,
+}
+
+impl CompChannel {
+    /// Create a new channel for analog input AIN`chan_num`, with a threshold
+    /// of half the reference.
+    const fn new(chan_num: u32) -> CompChannel {
+        CompChannel {
+            chan_num: chan_num,
+            threshold: Cell::new((THRESHOLD_STEPS / 2 - 1) as u32),
+        }
+    }
+}
+
+pub static mut CHANNEL_AIN0: CompChannel = CompChannel::new(0);
+pub static mut CHANNEL_AIN1: CompChannel = CompChannel::new(1);
+pub static mut CHANNEL_AIN2: CompChannel = CompChannel::new(2);
+pub static mut CHANNEL_AIN3: CompChannel = CompChannel::new(3);
+pub static mut CHANNEL_AIN4: CompChannel = CompChannel::new(4);
+pub static mut CHANNEL_AIN5: CompChannel = CompChannel::new(5);
+pub static mut CHANNEL_AIN6: CompChannel = CompChannel::new(6);
+pub static mut CHANNEL_AIN7: CompChannel = CompChannel::new(7);
+
+pub struct Comp {
+    registers: StaticRef<CompRegisters>,
+    client: OptionalCell<&'static hil::analog_comparator::Client>,
+    /// Channel number and threshold of the channel with interrupt-based
+    /// comparison active, if any.
+    active: Cell<Option<(u32, u32)>>,
+}
+
+pub static mut COMP: Comp = Comp::new(COMP_BASE);
+
+impl Comp {
+    const fn new(registers: StaticRef<CompRegisters>) -> Comp {
+        Comp {
+            registers: registers,
+            client: OptionalCell::empty(),
+            active: Cell::new(None),
+        }
+    }
+
+    pub fn set_client(&self, client: &'static hil::analog_comparator::Client) {
+        self.client.set(client);
+    }
+
+    pub fn handle_interrupt(&self) {
+        let regs = &*self.registers;
+        if regs.events_up.is_set(EVENT::EVENT) {
+            regs.events_up.write(EVENT::EVENT::CLEAR);
+            if let Some((chan_num, _)) = self.active.get() {
+                self.client.map(|client| client.fired(chan_num as usize));
+            }
+        }
+    }
+
+    fn set_threshold(&self, threshold: u32) {
+        let regs = &*self.registers;
+        // A one step hysteresis window below the threshold avoids repeated
+        // crossings from noise on a slowly changing input.
+        let down = if threshold > 0 { threshold - 1 } else { 0 };
+        regs.th.write(TH::THUP.val(threshold) + TH::THDOWN.val(down));
+    }
+
+    /// Connect the comparator to input AIN`chan_num` and start it, waiting
+    /// until its output is valid.
+    fn start(&self, chan_num: u32, threshold: u32) {
+        let regs = &*self.registers;
+        regs.tasks_stop.write(TASK::ENABLE::SET);
+        regs.psel.write(PSEL::PSEL.val(chan_num));
+        regs.refsel.write(REFSEL::REFSEL::Int2V4);
+        regs.mode.write(MODE::SP::Normal + MODE::MAIN::SE);
+        self.set_threshold(threshold);
+        regs.enable.write(ENABLE::ENABLE::Enabled);
+
+        regs.events_ready.write(EVENT::EVENT::CLEAR);
+        regs.tasks_start.write(TASK::ENABLE::SET);
+        while !regs.events_ready.is_set(EVENT::EVENT) {}
+        regs.events_ready.write(EVENT::EVENT::CLEAR);
+    }
+
+    fn stop(&self) {
+        let regs = &*self.registers;
+        regs.tasks_stop.write(TASK::ENABLE::SET);
+        regs.enable.write(ENABLE::ENABLE::Disabled);
+    }
+}
+
+impl hil::analog_comparator::AnalogComparator for Comp {
+    type Channel = CompChannel;
+
+    /// Returns true if the input is above the channel's threshold.
+    fn comparison(&self, channel: &Self::Channel) -> bool {
+        let regs = &*self.registers;
+        self.start(channel.chan_num, channel.threshold.get());
+        regs.tasks_sample.write(TASK::ENABLE::SET);
+        let above = regs.result.matches_all(RESULT::RESULT::Above);
+
+        // Reconnect the channel being watched, if any.
+        match self.active.get() {
+            Some((chan_num, threshold)) => {
+                self.start(chan_num, threshold);
+                regs.events_up.write(EVENT::EVENT::CLEAR);
+            }
+            None => self.stop(),
+        }
+        above
+    }
+
+    fn start_comparing(&self, channel: &Self::Channel) -> ReturnCode {
+        let regs = &*self.registers;
+        // Only one channel can be watched at a time, so this replaces any
+        // channel that was already being watched.
+        let threshold = channel.threshold.get();
+        regs.intenclr.write(INTEN::UP::SET);
+        self.start(channel.chan_num, threshold);
+        self.active.set(Some((channel.chan_num, threshold)));
+        regs.events_up.write(EVENT::EVENT::CLEAR);
+        regs.intenset.write(INTEN::UP::SET);
+        ReturnCode::SUCCESS
+    }
+
+    fn stop_comparing(&self, channel: &Self::Channel) -> ReturnCode {
+        let regs = &*self.registers;
+        match self.active.get() {
+            Some((chan_num, _)) if chan_num == channel.chan_num => {
+                regs.intenclr.write(INTEN::UP::SET);
+                self.active.set(None);
+                self.stop();
+                ReturnCode::SUCCESS
+            }
+            Some(_) => ReturnCode::EINVAL,
+            None => ReturnCode::SUCCESS,
+        }
+    }
+
+    /// Set the threshold of `channel`, in millivolts. The threshold must be
+    /// between 1/64 of the 2.4V reference and the reference itself.
+    fn set_reference(&self, channel: &Self::Channel, millivolts: usize) -> ReturnCode {
+        match threshold_for_millivolts(millivolts) {
+            Some(threshold) => {
+                channel.threshold.set(threshold);
+                let watching = self
+                    .active
+                    .get()
+                    .map_or(false, |(chan_num, _)| chan_num == channel.chan_num);
+                if watching {
+                    self.active.set(Some((channel.chan_num, threshold)));
+                    self.set_threshold(threshold);
+                }
+                ReturnCode::SUCCESS
+            }
+            None => ReturnCode::EINVAL,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threshold_encodes_nearest_step() {
+        // Each step is 2400mV / 64 = 37.5mV, and the value written is one
+        // less than the number of steps.
+        assert_eq!(threshold_for_millivolts(2400), Some(63));
+        assert_eq!(threshold_for_millivolts(1200), Some(31));
+        assert_eq!(threshold_for_millivolts(75), Some(1));
+        assert_eq!(threshold_for_millivolts(38), Some(0));
+        // 1000mV is 26.67 steps, which rounds up to 27.
+        assert_eq!(threshold_for_millivolts(1000), Some(26));
+        // 1030mV is 27.47 steps, and 1032mV is 27.52 steps.
+        assert_eq!(threshold_for_millivolts(1030), Some(26));
+        assert_eq!(threshold_for_millivolts(1032), Some(27));
+    }
+
+    #[test]
+    fn levels_outside_reference_are_rejected() {
+        assert_eq!(threshold_for_millivolts(0), None);
+        // Less than half a step rounds to zero steps.
+        assert_eq!(threshold_for_millivolts(18), None);
+        assert_eq!(threshold_for_millivolts(19), Some(0));
+        assert_eq!(threshold_for_millivolts(2401), None);
+    }
+}
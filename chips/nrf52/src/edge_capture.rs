@@ -0,0 +1,119 @@
+//! Hardware edge timestamps for the nRF52.
+//!
+//! Timestamps the edges on a GPIO pin at the moment they happen. A GPIOTE
+//! channel generates an event on each edge of the pin, and a PPI channel
+//! connects that event to a CAPTURE task of a free-running TIMER, so the
+//! timer value is latched by the hardware when the edge occurs. The GPIOTE
+//! interrupt then reports the latched value, so the timestamp does not
+//! include the interrupt latency.
+//!
+//! The direction of an edge is the level of the pin when its interrupt is
+//! handled, and an edge that follows another before the interrupt is handled
+//! overwrites the earlier timestamp. Pulses shorter than the interrupt latency
+//! are therefore not reported accurately.
+//!
+//! The timer and PPI channel are dedicated to the capture. TIMER0 is used by
+//! the BLE radio, so boards generally use TIMER2, and one of the programmable
+//! PPI channels 0-19. The timer runs at 1 MHz.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let edge_capture = static_init!(
+//!     nrf52::edge_capture::EdgeCapture<'static>,
+//!     nrf52::edge_capture::EdgeCapture::new(
+//!         &nrf5x::gpio::PORT[11],
+//!         &nrf5x::timer::TIMER2,
+//!         &nrf52::ppi::PPI,
+//!         1
+//!     )
+//! );
+//! nrf5x::gpio::PORT[11].set_client(edge_capture);
+//! ```
+
+use core::cell::Cell;
+use kernel::common::cells::OptionalCell;
+use kernel::hil;
+use kernel::hil::gpio::{InterruptMode, Pin};
+use kernel::ReturnCode;
+use nrf5x::gpio::GPIOPin;
+use nrf5x::timer::Timer;
+use ppi::Ppi;
+
+/// Capture/compare register that edge timestamps are latched into.
+const EDGE_CAPTURE: usize = 0;
+
+/// Timer prescaler for a 1 MHz timestamp clock (16 MHz / 2^4).
+const PRESCALER: u32 = 4;
+
+pub struct EdgeCapture<'a> {
+    pin: &'a GPIOPin,
+    timer: &'a Timer,
+    ppi: &'a Ppi,
+    ppi_channel: usize,
+    capturing: Cell<bool>,
+    client: OptionalCell<&'static hil::gpio::EdgeCaptureClient>,
+}
+
+impl<'a> EdgeCapture<'a> {
+    pub fn new(pin: &'a GPIOPin, timer: &'a Timer, ppi: &'a Ppi, ppi_channel: usize) -> Self {
+        EdgeCapture {
+            pin: pin,
+            timer: timer,
+            ppi: ppi,
+            ppi_channel: ppi_channel,
+            capturing: Cell::new(false),
+            client: OptionalCell::empty(),
+        }
+    }
+}
+
+impl<'a> hil::gpio::EdgeCapture for EdgeCapture<'a> {
+    fn set_client(&self, client: &'static hil::gpio::EdgeCaptureClient) {
+        self.client.set(client);
+    }
+
+    fn frequency(&self) -> u32 {
+        16_000_000 >> PRESCALER
+    }
+
+    fn start(&self) -> ReturnCode {
+        if self.capturing.get() {
+            return ReturnCode::EBUSY;
+        }
+        self.pin.make_input();
+        self.pin.enable_interrupt(0, InterruptMode::EitherEdge);
+        match self.pin.event_address() {
+            Some(event) => {
+                self.timer.start_timer(PRESCALER);
+                self.ppi.configure(
+                    self.ppi_channel,
+                    event,
+                    self.timer.capture_task_address(EDGE_CAPTURE),
+                );
+                self.ppi.enable_channel(self.ppi_channel);
+                self.capturing.set(true);
+                ReturnCode::SUCCESS
+            }
+            None => ReturnCode::ENOMEM,
+        }
+    }
+
+    fn stop(&self) {
+        if self.capturing.get() {
+            self.ppi.disable_channel(self.ppi_channel);
+            self.pin.disable_interrupt();
+            self.timer.stop();
+            self.capturing.set(false);
+        }
+    }
+}
+
+impl<'a> hil::gpio::Client for EdgeCapture<'a> {
+    fn fired(&self, _: usize) {
+        let timestamp = self.timer.captured(EDGE_CAPTURE);
+        let rising = self.pin.read();
+        self.client.map(|client| client.captured(rising, timestamp));
+    }
+}
@@ -0,0 +1,94 @@
+//! Hardware edge counter for the nRF52.
+//!
+//! Counts edges on a GPIO pin without taking an interrupt per edge. A GPIOTE
+//! channel generates an event on each edge of the pin, and a PPI channel
+//! connects that event to the COUNT task of a TIMER running in counter mode.
+//! The count is read by capturing the timer value, so edges are counted at
+//! rates far higher than interrupts could be handled.
+//!
+//! The timer and PPI channel are dedicated to the counter. TIMER0 is used by
+//! the BLE radio, so boards generally use TIMER2, and one of the programmable
+//! PPI channels 0-19.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let edge_counter = static_init!(
+//!     nrf52::edge_counter::EdgeCounter<'static>,
+//!     nrf52::edge_counter::EdgeCounter::new(
+//!         &nrf5x::gpio::PORT[11],
+//!         &nrf5x::timer::TIMER2,
+//!         &nrf52::ppi::PPI,
+//!         0
+//!     )
+//! );
+//! ```
+
+use core::cell::Cell;
+use kernel::hil;
+use kernel::hil::gpio::Pin;
+use kernel::ReturnCode;
+use nrf5x::gpio::GPIOPin;
+use nrf5x::timer::Timer;
+use ppi::Ppi;
+
+/// Capture/compare register used to read the count.
+const COUNT_CAPTURE: usize = 0;
+
+pub struct EdgeCounter<'a> {
+    pin: &'a GPIOPin,
+    timer: &'a Timer,
+    ppi: &'a Ppi,
+    ppi_channel: usize,
+    counting: Cell<bool>,
+}
+
+impl<'a> EdgeCounter<'a> {
+    pub fn new(pin: &'a GPIOPin, timer: &'a Timer, ppi: &'a Ppi, ppi_channel: usize) -> Self {
+        EdgeCounter {
+            pin: pin,
+            timer: timer,
+            ppi: ppi,
+            ppi_channel: ppi_channel,
+            counting: Cell::new(false),
+        }
+    }
+}
+
+impl<'a> hil::gpio::EdgeCounter for EdgeCounter<'a> {
+    fn start(&self, mode: hil::gpio::InterruptMode) -> ReturnCode {
+        if self.counting.get() {
+            return ReturnCode::EBUSY;
+        }
+        self.pin.make_input();
+        match self.pin.enable_event(mode) {
+            Some(event) => {
+                self.timer.start_counter();
+                self.ppi
+                    .configure(self.ppi_channel, event, self.timer.count_task_address());
+                self.ppi.enable_channel(self.ppi_channel);
+                self.counting.set(true);
+                ReturnCode::SUCCESS
+            }
+            None => ReturnCode::ENOMEM,
+        }
+    }
+
+    fn stop(&self) {
+        if self.counting.get() {
+            self.ppi.disable_channel(self.ppi_channel);
+            self.pin.disable_interrupt();
+            self.timer.stop();
+            self.counting.set(false);
+        }
+    }
+
+    fn count(&self) -> u32 {
+        self.timer.capture(COUNT_CAPTURE)
+    }
+
+    fn reset(&self) {
+        self.timer.clear();
+    }
+}
@@ -7,9 +7,11 @@
 //! - Author: Pat Pannuto <ppannuto@berkeley.edu>
 //! - Date: November 27, 2017
 
+use core::cmp;
 use core::fmt;
 use kernel::common::registers::ReadOnly;
 use kernel::common::StaticRef;
+use kernel::hil;
 
 const FICR_BASE: StaticRef<FicrRegisters> =
     unsafe { StaticRef::new(0x10000000 as *const FicrRegisters) };
@@ -313,6 +315,23 @@ impl Ficr {
     }
 }
 
+/// The 64 bit unique device identifier.
+impl hil::device_id::DeviceId for Ficr {
+    fn id_len(&self) -> usize {
+        8
+    }
+
+    fn read_id(&self, buf: &mut [u8]) -> usize {
+        let regs = &*self.registers;
+        let id = (regs.deviceid1.get() as u64) << 32 | regs.deviceid0.get() as u64;
+        let len = cmp::min(buf.len(), self.id_len());
+        for (i, byte) in buf[..len].iter_mut().enumerate() {
+            *byte = (id >> (56 - 8 * i)) as u8;
+        }
+        len
+    }
+}
+
 impl fmt::Display for Ficr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
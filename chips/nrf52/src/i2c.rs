@@ -1,19 +1,29 @@
 //! Implementation of I2C for nRF52 using EasyDMA.
 //!
-//! This module supports nRF52's two I2C master (`TWIM`) peripherals,
-//! but not I2C slave (`TWIS`).
+//! This module supports nRF52's two I2C master (`TWIM`) peripherals and the
+//! two I2C slave (`TWIS`) peripherals that share their address space. Only
+//! one of the master and slave of an instance can be enabled at a time.
+//!
+//! The slave acknowledges its address and then stretches the clock until a
+//! buffer has been provided for the transaction the master started, asking the
+//! client for one with `write_expected` or `read_expected` if needed. Buffers
+//! are returned to the client when the transaction ends, so a new buffer has
+//! to be provided for every transaction.
 //!
 //! - Author: Jay Kickliter
 //! - Author: Andrew Thompson
 //! - Date: Nov 4, 2017
 
+use core::cell::Cell;
 use kernel::common::cells::OptionalCell;
 use kernel::common::cells::TakeCell;
 use kernel::common::cells::VolatileCell;
 use kernel::common::registers::{ReadWrite, WriteOnly};
 use kernel::common::StaticRef;
 use kernel::hil;
+use kernel::hil::power_manager::PowerClient;
 use nrf5x::pinmux::Pinmux;
+use nrf5x::power::SleepMode;
 
 /// Uninitialized `TWIM` instances.
 const INSTANCES: [StaticRef<TwimRegisters>; 2] = unsafe {
@@ -223,11 +233,218 @@ impl hil::i2c::I2CSlave for TWIM {
 
 impl hil::i2c::I2CMasterSlave for TWIM {}
 
+impl PowerClient<SleepMode> for TWIM {
+    fn sleep_requirement(&self) -> Option<SleepMode> {
+        // EasyDMA transfers need the high frequency clock.
+        if self.buf.is_some() {
+            Some(SleepMode::Sleep)
+        } else {
+            None
+        }
+    }
+}
+
 /// I2C master instace 0.
 pub static mut TWIM0: TWIM = TWIM::new(INSTANCES[0]);
 /// I2C master instace 1.
 pub static mut TWIM1: TWIM = TWIM::new(INSTANCES[1]);
 
+/// An I2C slave device.
+pub struct TWIS {
+    registers: StaticRef<TwisRegisters>,
+    client: OptionalCell<&'static hil::i2c::I2CHwSlaveClient>,
+    address: Cell<u8>,
+    rx_buf: TakeCell<'static, [u8]>,
+    tx_buf: TakeCell<'static, [u8]>,
+    /// Whether the master started writing or reading in the current
+    /// transaction.
+    rx_active: Cell<bool>,
+    tx_active: Cell<bool>,
+    /// Whether the peripheral is suspended waiting for a buffer.
+    suspended: Cell<bool>,
+}
+
+impl TWIS {
+    const fn new(registers: StaticRef<TwisRegisters>) -> TWIS {
+        TWIS {
+            registers: registers,
+            client: OptionalCell::empty(),
+            address: Cell::new(0),
+            rx_buf: TakeCell::empty(),
+            tx_buf: TakeCell::empty(),
+            rx_active: Cell::new(false),
+            tx_active: Cell::new(false),
+            suspended: Cell::new(false),
+        }
+    }
+
+    pub fn set_client(&self, client: &'static hil::i2c::I2CHwSlaveClient) {
+        debug_assert!(self.client.is_none());
+        self.client.set(client);
+    }
+
+    /// Configures an already constructed `TWIS`.
+    pub fn configure(&self, scl: Pinmux, sda: Pinmux) {
+        self.registers.psel_scl.set(scl);
+        self.registers.psel_sda.set(sda);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.registers.enable.matches_all(TWIS_ENABLE::ENABLE::Enable)
+    }
+
+    /// Resumes a transaction that was suspended waiting for a buffer.
+    fn resume(&self) {
+        if self.suspended.get() {
+            self.suspended.set(false);
+            self.registers.tasks_resume.write(TASK::TASK::SET);
+        }
+    }
+
+    pub fn handle_interrupt(&self) {
+        let regs = &*self.registers;
+
+        if regs.events_write.is_set(EVENT::EVENT) {
+            regs.events_write.write(EVENT::EVENT::CLEAR);
+            // The WRITE_SUSPEND shortcut has suspended the peripheral
+            self.suspended.set(true);
+            self.rx_active.set(true);
+            if self.rx_buf.is_some() {
+                self.resume();
+            } else {
+                self.client.map(|client| client.write_expected());
+            }
+        }
+
+        if regs.events_read.is_set(EVENT::EVENT) {
+            regs.events_read.write(EVENT::EVENT::CLEAR);
+            // The READ_SUSPEND shortcut has suspended the peripheral
+            self.suspended.set(true);
+            self.tx_active.set(true);
+            if self.tx_buf.is_some() {
+                self.resume();
+            } else {
+                self.client.map(|client| client.read_expected());
+            }
+        }
+
+        if regs.events_error.is_set(EVENT::EVENT) {
+            // Overflows and overreads still complete the transaction, and are
+            // reported through the number of bytes transferred.
+            regs.events_error.write(EVENT::EVENT::CLEAR);
+            regs.errorsrc.write(
+                TWIS_ERRORSRC::OVERFLOW::SET
+                    + TWIS_ERRORSRC::DNACK::SET
+                    + TWIS_ERRORSRC::OVERREAD::SET,
+            );
+        }
+
+        if regs.events_stopped.is_set(EVENT::EVENT) {
+            regs.events_stopped.write(EVENT::EVENT::CLEAR);
+            self.suspended.set(false);
+
+            // A write followed by a repeated start and a read ends with a
+            // single stop, so report both halves.
+            if self.rx_active.get() {
+                self.rx_active.set(false);
+                let len = regs.rxd_amount.get() as u8;
+                self.rx_buf.take().map(|buf| {
+                    self.client.map(move |client| {
+                        client.command_complete(buf, len, hil::i2c::SlaveTransmissionType::Write)
+                    });
+                });
+            }
+            if self.tx_active.get() {
+                self.tx_active.set(false);
+                let len = regs.txd_amount.get() as u8;
+                self.tx_buf.take().map(|buf| {
+                    self.client.map(move |client| {
+                        client.command_complete(buf, len, hil::i2c::SlaveTransmissionType::Read)
+                    });
+                });
+            }
+        }
+
+        // We can blindly clear the following events since we're not using them.
+        regs.events_rxstarted.write(EVENT::EVENT::CLEAR);
+        regs.events_txstarted.write(EVENT::EVENT::CLEAR);
+    }
+}
+
+impl hil::i2c::I2CSlave for TWIS {
+    fn enable(&self) {
+        self.registers.enable.write(TWIS_ENABLE::ENABLE::Enable);
+    }
+
+    fn disable(&self) {
+        self.registers.intenclr.set(!0);
+        self.registers.enable.write(TWIS_ENABLE::ENABLE::Disable);
+    }
+
+    fn set_address(&self, addr: u8) {
+        self.address.set(addr & 0x7f);
+    }
+
+    fn write_receive(&self, data: &'static mut [u8], max_len: u8) {
+        let len = if (max_len as usize) > data.len() {
+            data.len()
+        } else {
+            max_len as usize
+        };
+        self.registers.rxd_ptr.set(data.as_mut_ptr());
+        self.registers
+            .rxd_maxcnt
+            .write(MAXCNT::MAXCNT.val(len as u32));
+        self.registers.tasks_preparerx.write(TASK::TASK::SET);
+        self.rx_buf.replace(data);
+        // Only resume if the master is waiting to write, not to read after
+        // an earlier write in the same transaction.
+        if self.rx_active.get() && !self.tx_active.get() {
+            self.resume();
+        }
+    }
+
+    fn read_send(&self, data: &'static mut [u8], max_len: u8) {
+        let len = if (max_len as usize) > data.len() {
+            data.len()
+        } else {
+            max_len as usize
+        };
+        self.registers.txd_ptr.set(data.as_mut_ptr());
+        self.registers
+            .txd_maxcnt
+            .write(MAXCNT::MAXCNT.val(len as u32));
+        self.registers.tasks_preparetx.write(TASK::TASK::SET);
+        self.tx_buf.replace(data);
+        if self.tx_active.get() {
+            self.resume();
+        }
+    }
+
+    fn listen(&self) {
+        let regs = &*self.registers;
+        regs.address[0].write(ADDRESS::ADDRESS.val(self.address.get() as u32));
+        regs.config.write(TWIS_CONFIG::ADDRESS0::SET);
+        // Suspend on every address match so that the transaction can wait
+        // for a buffer from the client.
+        regs.shorts
+            .write(TWIS_SHORTS::WRITE_SUSPEND::SET + TWIS_SHORTS::READ_SUSPEND::SET);
+        regs.intenset.write(
+            TWIS_INTE::STOPPED::SET
+                + TWIS_INTE::ERROR::SET
+                + TWIS_INTE::WRITE::SET
+                + TWIS_INTE::READ::SET,
+        );
+    }
+}
+
+/// I2C slave instance 0.
+pub static mut TWIS0: TWIS =
+    TWIS::new(unsafe { StaticRef::new(0x40003000 as *const TwisRegisters) });
+/// I2C slave instance 1.
+pub static mut TWIS1: TWIS =
+    TWIS::new(unsafe { StaticRef::new(0x40004000 as *const TwisRegisters) });
+
 // The SPI0_TWI0 and SPI1_TWI1 interrupts are dispatched to the
 // correct handler by the service_pending_interrupts() routine in
 // chip.rs based on which peripheral is enabled.
@@ -312,6 +529,85 @@ struct TwimRegisters {
     address: ReadWrite<u32, ADDRESS::Register>,
 }
 
+#[repr(C)]
+struct TwisRegisters {
+    _reserved0: [u32; 5],
+    /// Stop TWI transaction
+    tasks_stop: WriteOnly<u32, TASK::Register>,
+    _reserved1: [u32; 1],
+    /// Suspend TWI transaction
+    tasks_suspend: WriteOnly<u32, TASK::Register>,
+    /// Resume TWI transaction
+    tasks_resume: WriteOnly<u32, TASK::Register>,
+    _reserved2: [u32; 3],
+    /// Prepare the TWI slave to respond to a write command
+    tasks_preparerx: WriteOnly<u32, TASK::Register>,
+    /// Prepare the TWI slave to respond to a read command
+    tasks_preparetx: WriteOnly<u32, TASK::Register>,
+    _reserved3: [u32; 51],
+    /// TWI stopped
+    events_stopped: ReadWrite<u32, EVENT::Register>,
+    _reserved4: [u32; 7],
+    /// TWI error
+    events_error: ReadWrite<u32, EVENT::Register>,
+    _reserved5: [u32; 9],
+    /// Receive sequence started
+    events_rxstarted: ReadWrite<u32, EVENT::Register>,
+    /// Transmit sequence started
+    events_txstarted: ReadWrite<u32, EVENT::Register>,
+    _reserved6: [u32; 4],
+    /// Write command received
+    events_write: ReadWrite<u32, EVENT::Register>,
+    /// Read command received
+    events_read: ReadWrite<u32, EVENT::Register>,
+    _reserved7: [u32; 37],
+    /// Shortcut register
+    shorts: ReadWrite<u32, TWIS_SHORTS::Register>,
+    _reserved8: [u32; 63],
+    /// Enable or disable interrupt
+    inten: ReadWrite<u32, TWIS_INTE::Register>,
+    /// Enable interrupt
+    intenset: ReadWrite<u32, TWIS_INTE::Register>,
+    /// Disable interrupt
+    intenclr: ReadWrite<u32, TWIS_INTE::Register>,
+    _reserved9: [u32; 113],
+    /// Error source
+    errorsrc: ReadWrite<u32, TWIS_ERRORSRC::Register>,
+    /// Which of the addresses matched the incoming address
+    match_: ReadWrite<u32>,
+    _reserved10: [u32; 10],
+    /// Enable TWIS
+    enable: ReadWrite<u32, TWIS_ENABLE::Register>,
+    _reserved11: [u32; 1],
+    /// Pin select for SCL signal
+    psel_scl: VolatileCell<Pinmux>,
+    /// Pin select for SDA signal
+    psel_sda: VolatileCell<Pinmux>,
+    _reserved12: [u32; 9],
+    /// Data pointer
+    rxd_ptr: VolatileCell<*mut u8>,
+    /// Maximum number of bytes in receive buffer
+    rxd_maxcnt: ReadWrite<u32, MAXCNT::Register>,
+    /// Number of bytes transferred in the last transaction
+    rxd_amount: ReadWrite<u32>,
+    _reserved13: [u32; 1],
+    /// Data pointer
+    txd_ptr: VolatileCell<*mut u8>,
+    /// Maximum number of bytes in transmit buffer
+    txd_maxcnt: ReadWrite<u32, MAXCNT::Register>,
+    /// Number of bytes transferred in the last transaction
+    txd_amount: ReadWrite<u32>,
+    _reserved14: [u32; 14],
+    /// TWI slave addresses
+    address: [ReadWrite<u32, ADDRESS::Register>; 2],
+    _reserved15: [u32; 1],
+    /// Configuration register for the address match mechanism
+    config: ReadWrite<u32, TWIS_CONFIG::Register>,
+    _reserved16: [u32; 10],
+    /// Over-read character, sent when the master reads past the buffer
+    orc: ReadWrite<u32>,
+}
+
 register_bitfields![u32,
     SHORTS [
         /// Shortcut between EVENTS_LASTTX event and TASKS_STARTRX task
@@ -430,5 +726,217 @@ register_bitfields![u32,
     ADDRESS [
         /// Address used in the TWI transfer
         ADDRESS OFFSET(0) NUMBITS(7)
+    ],
+    TWIS_SHORTS [
+        /// Shortcut between EVENTS_WRITE event and TASKS_SUSPEND task
+        WRITE_SUSPEND OFFSET(13) NUMBITS(1),
+        /// Shortcut between EVENTS_READ event and TASKS_SUSPEND task
+        READ_SUSPEND OFFSET(14) NUMBITS(1)
+    ],
+    TWIS_INTE [
+        /// Interrupt on EVENTS_STOPPED event
+        STOPPED OFFSET(1) NUMBITS(1),
+        /// Interrupt on EVENTS_ERROR event
+        ERROR OFFSET(9) NUMBITS(1),
+        /// Interrupt on EVENTS_RXSTARTED event
+        RXSTARTED OFFSET(19) NUMBITS(1),
+        /// Interrupt on EVENTS_TXSTARTED event
+        TXSTARTED OFFSET(20) NUMBITS(1),
+        /// Interrupt on EVENTS_WRITE event
+        WRITE OFFSET(25) NUMBITS(1),
+        /// Interrupt on EVENTS_READ event
+        READ OFFSET(26) NUMBITS(1)
+    ],
+    TWIS_ERRORSRC [
+        /// RX buffer overflow detected, and prevented (write '1' to clear)
+        OVERFLOW OFFSET(0) NUMBITS(1),
+        /// NACK sent after receiving a data byte (write '1' to clear)
+        DNACK OFFSET(2) NUMBITS(1),
+        /// TX buffer over-read detected, and prevented (write '1' to clear)
+        OVERREAD OFFSET(3) NUMBITS(1)
+    ],
+    TWIS_ENABLE [
+        /// Enable or disable TWIS
+        ENABLE OFFSET(0) NUMBITS(4) [
+            Disable = 0,
+            Enable = 9
+        ]
+    ],
+    TWIS_CONFIG [
+        /// Enable or disable address matching on ADDRESS[0]
+        ADDRESS0 OFFSET(0) NUMBITS(1),
+        /// Enable or disable address matching on ADDRESS[1]
+        ADDRESS1 OFFSET(1) NUMBITS(1)
     ]
 ];
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use self::std::boxed::Box;
+    use self::std::cell::RefCell;
+    use self::std::vec::Vec;
+    use super::*;
+    use core::{mem, ptr};
+    use kernel::hil::i2c::{I2CHwSlaveClient, I2CSlave, SlaveTransmissionType};
+
+    fn leak<T>(v: T) -> &'static mut T {
+        Box::leak(Box::new(v))
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        WriteExpected,
+        ReadExpected,
+        Written(u8),
+        Read(u8),
+    }
+
+    #[derive(Default)]
+    struct TestClient {
+        events: RefCell<Vec<Event>>,
+    }
+
+    impl I2CHwSlaveClient for TestClient {
+        fn command_complete(
+            &self,
+            _buffer: &'static mut [u8],
+            length: u8,
+            transmission_type: SlaveTransmissionType,
+        ) {
+            self.events.borrow_mut().push(match transmission_type {
+                SlaveTransmissionType::Write => Event::Written(length),
+                SlaveTransmissionType::Read => Event::Read(length),
+            });
+        }
+
+        fn read_expected(&self) {
+            self.events.borrow_mut().push(Event::ReadExpected);
+        }
+
+        fn write_expected(&self) {
+            self.events.borrow_mut().push(Event::WriteExpected);
+        }
+    }
+
+    /// A listening `TWIS` with fake registers, and its client.
+    fn listening() -> (&'static TWIS, &'static TwisRegisters, &'static TestClient) {
+        let regs = leak(unsafe { mem::zeroed::<TwisRegisters>() });
+        let twis = leak(TWIS::new(unsafe { StaticRef::new(regs) }));
+        let client = leak(TestClient::default());
+        twis.set_client(client);
+        twis.set_address(0x41);
+        twis.enable();
+        twis.listen();
+        (twis, regs, client)
+    }
+
+    /// Whether the driver triggered the RESUME task since the last check.
+    fn resumed(regs: &TwisRegisters) -> bool {
+        let task = &regs.tasks_resume as *const _ as *mut u32;
+        unsafe {
+            let triggered = ptr::read_volatile(task) != 0;
+            ptr::write_volatile(task, 0);
+            triggered
+        }
+    }
+
+    /// Raise `event` and run the interrupt handler.
+    fn interrupt(twis: &TWIS, event: &ReadWrite<u32, EVENT::Register>) {
+        event.write(EVENT::EVENT::SET);
+        twis.handle_interrupt();
+        assert!(!event.is_set(EVENT::EVENT));
+    }
+
+    #[test]
+    fn listen_matches_configured_address() {
+        let (twis, regs, _) = listening();
+        assert!(twis.is_enabled());
+        assert_eq!(regs.address[0].read(ADDRESS::ADDRESS), 0x41);
+        assert!(regs.config.is_set(TWIS_CONFIG::ADDRESS0));
+        assert!(regs.shorts.is_set(TWIS_SHORTS::WRITE_SUSPEND));
+        assert!(regs.shorts.is_set(TWIS_SHORTS::READ_SUSPEND));
+
+        twis.disable();
+        assert!(!twis.is_enabled());
+    }
+
+    #[test]
+    fn write_waits_for_a_buffer() {
+        let (twis, regs, client) = listening();
+
+        // The master writes before the client has provided a buffer, so the
+        // transaction stays suspended until it does.
+        interrupt(twis, &regs.events_write);
+        assert!(!resumed(regs));
+        assert_eq!(*client.events.borrow(), [Event::WriteExpected]);
+
+        let buffer = leak([0u8; 16]);
+        let address = buffer.as_mut_ptr();
+        twis.write_receive(buffer, 8);
+        assert!(resumed(regs));
+        assert_eq!(regs.rxd_ptr.get(), address);
+        assert_eq!(regs.rxd_maxcnt.read(MAXCNT::MAXCNT), 8);
+
+        regs.rxd_amount.set(5);
+        interrupt(twis, &regs.events_stopped);
+        assert_eq!(
+            *client.events.borrow(),
+            [
+                Event::WriteExpected,
+                Event::Written(5),
+            ]
+        );
+    }
+
+    #[test]
+    fn prepared_buffer_resumes_at_once() {
+        let (twis, regs, client) = listening();
+
+        twis.read_send(leak([0u8; 4]), 8);
+        assert!(!resumed(regs));
+        assert_eq!(regs.txd_maxcnt.read(MAXCNT::MAXCNT), 4);
+
+        interrupt(twis, &regs.events_read);
+        assert!(resumed(regs));
+
+        regs.txd_amount.set(4);
+        interrupt(twis, &regs.events_stopped);
+        assert_eq!(*client.events.borrow(), [Event::Read(4)]);
+    }
+
+    #[test]
+    fn write_then_read_reports_both_halves() {
+        let (twis, regs, client) = listening();
+        twis.write_receive(leak([0u8; 8]), 8);
+
+        interrupt(twis, &regs.events_write);
+        assert!(resumed(regs));
+
+        // After a repeated start the master reads, and the client has no data
+        // ready yet.
+        interrupt(twis, &regs.events_read);
+        assert!(!resumed(regs));
+        assert_eq!(*client.events.borrow(), [Event::ReadExpected]);
+
+        // A new receive buffer must not resume the pending read...
+        twis.write_receive(leak([0u8; 8]), 8);
+        assert!(!resumed(regs));
+        // ...but data to send does.
+        twis.read_send(leak([0u8; 8]), 8);
+        assert!(resumed(regs));
+
+        regs.rxd_amount.set(2);
+        regs.txd_amount.set(3);
+        interrupt(twis, &regs.events_stopped);
+        assert_eq!(
+            *client.events.borrow(),
+            [
+                Event::ReadExpected,
+                Event::Written(2),
+                Event::Read(3),
+            ]
+        );
+    }
+
+}
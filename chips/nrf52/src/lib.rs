@@ -20,8 +20,11 @@ extern crate kernel;
 pub mod adc;
 pub mod chip;
 pub mod clock;
+pub mod comp;
 pub mod crt1;
 mod deferred_call_tasks;
+pub mod edge_capture;
+pub mod edge_counter;
 pub mod ficr;
 pub mod i2c;
 pub mod nvmc;
@@ -30,5 +33,6 @@ pub mod radio;
 pub mod spi;
 pub mod uart;
 pub mod uicr;
+pub mod wdt;
 
 pub use crt1::init;
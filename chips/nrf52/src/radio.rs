@@ -6,6 +6,9 @@
 //!
 //! For more readability the Bluetooth specific configuration may be moved to separate trait
 //!
+//! The radio also implements `hil::radio::EnergyDetect`, which samples the RSSI on an 802.15.4
+//! channel so that the channel energy can be measured while the radio is otherwise idle.
+//!
 //! ### Author
 //! * Niklas Adolfsson <niklasadolfsson1@gmail.com>
 //! * Date: July 18, 2017
@@ -41,9 +44,12 @@ use kernel::common::registers::{ReadOnly, ReadWrite, WriteOnly};
 use kernel::common::StaticRef;
 use kernel::hil::ble_advertising;
 use kernel::hil::ble_advertising::RadioChannel;
+use kernel::hil::power_manager::PowerClient;
+use kernel::hil::radio;
 use kernel::ReturnCode;
 use nrf5x;
 use nrf5x::constants::TxPower;
+use nrf5x::power::SleepMode;
 
 const RADIO_BASE: StaticRef<RadioRegisters> =
     unsafe { StaticRef::new(0x40001000 as *const RadioRegisters) };
@@ -535,6 +541,11 @@ pub struct Radio {
     tx_power: Cell<TxPower>,
     rx_client: OptionalCell<&'static ble_advertising::RxClient>,
     tx_client: OptionalCell<&'static ble_advertising::TxClient>,
+    ed_client: OptionalCell<&'static radio::EnergyDetectClient>,
+    ed_active: Cell<bool>,
+    // Radio state to put back once an energy detect measurement is done
+    ed_was_powered: Cell<bool>,
+    ed_saved_frequency: Cell<u32>,
 }
 
 pub static mut RADIO: Radio = Radio::new();
@@ -546,6 +557,10 @@ impl Radio {
             tx_power: Cell::new(TxPower::ZerodBm),
             rx_client: OptionalCell::empty(),
             tx_client: OptionalCell::empty(),
+            ed_client: OptionalCell::empty(),
+            ed_active: Cell::new(false),
+            ed_was_powered: Cell::new(false),
+            ed_saved_frequency: Cell::new(0),
         }
     }
 
@@ -604,6 +619,27 @@ impl Radio {
             regs.event_ready.write(Event::READY::CLEAR);
             regs.event_end.write(Event::READY::CLEAR);
             regs.task_start.write(Task::ENABLE::SET);
+            if self.ed_active.get() {
+                regs.event_rssiend.write(Event::READY::CLEAR);
+                regs.task_rssistart.write(Task::ENABLE::SET);
+            }
+        }
+
+        // An energy detect sample is done, nothing else is expected from the
+        // receiver so turn it off before reporting the result
+        if self.ed_active.get() {
+            if regs.event_rssiend.is_set(Event::READY) {
+                regs.event_rssiend.write(Event::READY::CLEAR);
+                let rssi = rssi_sample_to_dbm(regs.rssisample.read(RssiSample::RSSISAMPLE));
+                regs.task_rssistop.write(Task::ENABLE::SET);
+                self.energy_detect_restore();
+                self.ed_active.set(false);
+                self.ed_client
+                    .map(|client| client.energy_detect_done(rssi, ReturnCode::SUCCESS));
+            } else {
+                self.enable_interrupts();
+            }
+            return;
         }
 
         if regs.event_address.is_set(Event::READY) {
@@ -654,12 +690,17 @@ impl Radio {
 
     pub fn enable_interrupts(&self) {
         let regs = &*self.registers;
-        regs.intenset.write(
-            Interrupt::READY::SET
-                + Interrupt::ADDRESS::SET
-                + Interrupt::PAYLOAD::SET
-                + Interrupt::END::SET,
-        );
+        if self.ed_active.get() {
+            regs.intenset
+                .write(Interrupt::READY::SET + Interrupt::RSSIEND::SET);
+        } else {
+            regs.intenset.write(
+                Interrupt::READY::SET
+                    + Interrupt::ADDRESS::SET
+                    + Interrupt::PAYLOAD::SET
+                    + Interrupt::END::SET,
+            );
+        }
     }
 
     pub fn enable_interrupt(&self, intr: u32) {
@@ -678,6 +719,17 @@ impl Radio {
         regs.intenclr.set(0xffffffff);
     }
 
+    // Stops the receiver started for an energy detect measurement and puts
+    // back the radio configuration that was in place before it.
+    fn energy_detect_restore(&self) {
+        let regs = &*self.registers;
+        regs.task_disable.write(Task::ENABLE::SET);
+        regs.frequency.set(self.ed_saved_frequency.get());
+        if !self.ed_was_powered.get() {
+            self.radio_off();
+        }
+    }
+
     fn replace_radio_buffer(&self, buf: &'static mut [u8]) -> &'static mut [u8] {
         // set payload
         for (i, c) in buf.as_ref().iter().enumerate() {
@@ -821,6 +873,65 @@ impl ble_advertising::BleAdvertisementDriver for Radio {
     }
 }
 
+// The RSSISAMPLE register holds the magnitude of the received signal strength,
+// which is always negative in dBm.
+fn rssi_sample_to_dbm(sample: u32) -> i8 {
+    -((sample & 0x7f) as i8)
+}
+
+// IEEE 802.15.4-2011, section 8.1.2.2: channels 11 to 26 are centered on
+// 2405 + 5 * (k - 11) MHz.
+fn ieee802154_channel_freq(channel: u8) -> Option<u32> {
+    if channel >= 11 && channel <= 26 {
+        Some(5 + 5 * (channel as u32 - 11))
+    } else {
+        None
+    }
+}
+
+impl PowerClient<SleepMode> for Radio {
+    fn sleep_requirement(&self) -> Option<SleepMode> {
+        // The radio needs the high frequency clock whenever it is powered.
+        if self.registers.power.is_set(Task::ENABLE) {
+            Some(SleepMode::Sleep)
+        } else {
+            None
+        }
+    }
+}
+
+impl radio::EnergyDetect for Radio {
+    fn energy_detect(&self, channel: u8) -> ReturnCode {
+        let freq = match ieee802154_channel_freq(channel) {
+            Some(freq) => freq,
+            None => return ReturnCode::EINVAL,
+        };
+        let regs = &*self.registers;
+        if self.ed_active.get() || regs.state.get() != nrf5x::constants::RADIO_STATE_DISABLE {
+            return ReturnCode::EBUSY;
+        }
+
+        // Power the radio without resetting it, so that the rest of its
+        // configuration is kept, and save what is changed here so it can be
+        // put back. The receiver is started once it has ramped up, so point
+        // it at the payload buffer rather than leaving PACKETPTR at 0.
+        self.ed_active.set(true);
+        self.ed_was_powered.set(regs.power.is_set(Task::ENABLE));
+        self.ed_saved_frequency.set(regs.frequency.get());
+        regs.power.write(Task::ENABLE::SET);
+        regs.frequency
+            .write(Frequency::FREQUENCY.val(freq) + Frequency::MAP::DEFAULT);
+        self.set_dma_ptr();
+        self.rx();
+        self.enable_interrupts();
+        ReturnCode::SUCCESS
+    }
+
+    fn set_energy_detect_client(&self, client: &'static radio::EnergyDetectClient) {
+        self.ed_client.set(client);
+    }
+}
+
 impl ble_advertising::BleConfig for Radio {
     // The BLE Advertising Driver validates that the `tx_power` is between -20 to 10 dBm but then
     // underlying chip must validate if the current `tx_power` is supported as well
@@ -20,8 +20,8 @@
 //! * ✓ get_clock
 //! * ✓ set_phase
 //! * ✓ get_phase
-//! * hold_low
-//! * release_low
+//! * ✓ hold_low
+//! * ✓ release_low
 //!
 //! Author
 //! -------------------
@@ -35,8 +35,10 @@ use kernel::common::cells::{OptionalCell, TakeCell, VolatileCell};
 use kernel::common::registers::{ReadWrite, WriteOnly};
 use kernel::common::StaticRef;
 use kernel::hil;
+use kernel::hil::power_manager::PowerClient;
 use kernel::ReturnCode;
 use nrf5x::pinmux::Pinmux;
+use nrf5x::power::SleepMode;
 
 /// SPI master instance 0.
 pub static mut SPIM0: SPIM = SPIM::new(0);
@@ -234,6 +236,8 @@ pub struct SPIM {
     chip_select: OptionalCell<&'static hil::gpio::Pin>,
     initialized: Cell<bool>,
     busy: Cell<bool>,
+    /// Whether chip select stays asserted after a transfer completes.
+    hold_cs: Cell<bool>,
     tx_buf: TakeCell<'static, [u8]>,
     rx_buf: TakeCell<'static, [u8]>,
     transfer_len: Cell<usize>,
@@ -247,6 +251,7 @@ impl SPIM {
             chip_select: OptionalCell::empty(),
             initialized: Cell::new(false),
             busy: Cell::new(false),
+            hold_cs: Cell::new(false),
             tx_buf: TakeCell::empty(),
             rx_buf: TakeCell::empty(),
             transfer_len: Cell::new(0),
@@ -263,17 +268,21 @@ impl SPIM {
                 return;
             }
 
-            self.chip_select.map(|cs| cs.set());
+            if !self.hold_cs.get() {
+                self.chip_select.map(|cs| cs.set());
+            }
             self.registers.events_end.write(EVENT::EVENT::CLEAR);
 
+            // The client may start the next transfer or release chip select
+            // from its callback, so the transfer must be over by then.
+            self.busy.set(false);
+
             self.client.map(|client| match self.tx_buf.take() {
                 None => (),
                 Some(tx_buf) => {
                     client.read_write_done(tx_buf, self.rx_buf.take(), self.transfer_len.take())
                 }
             });
-
-            self.busy.set(false);
         }
 
         // Although we only configured the chip interrupt on the
@@ -324,6 +333,17 @@ impl SPIM {
     }
 }
 
+impl PowerClient<SleepMode> for SPIM {
+    fn sleep_requirement(&self) -> Option<SleepMode> {
+        // EasyDMA transfers need the high frequency clock.
+        if self.busy.get() {
+            Some(SleepMode::Sleep)
+        } else {
+            None
+        }
+    }
+}
+
 impl hil::spi::SpiMaster for SPIM {
     type ChipSelect = &'static hil::gpio::Pin;
 
@@ -460,14 +480,19 @@ impl hil::spi::SpiMaster for SPIM {
         }
     }
 
-    // The following two trait functions are not implemented for
-    // SAM4L, and appear to not provide much functionality. Let's not
-    // bother implementing them unless needed.
+    // Chip select is a GPIO pin driven by this driver, so holding it is a
+    // matter of not deasserting it when a transfer ends.
     fn hold_low(&self) {
-        unimplemented!("SPI: Use `read_write_bytes()` instead.");
+        self.hold_cs.set(true);
     }
 
+    // Deasserts chip select now if no transfer is in progress, ending the
+    // sequence of held transfers, or otherwise when the current transfer
+    // completes.
     fn release_low(&self) {
-        unimplemented!("SPI: Use `read_write_bytes()` instead.");
+        self.hold_cs.set(false);
+        if !self.busy.get() {
+            self.chip_select.map(|cs| cs.set());
+        }
     }
 }
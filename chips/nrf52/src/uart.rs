@@ -5,6 +5,21 @@
 //!
 //! * Author: Niklas Adolfsson <niklasadolfsson1@gmail.com>
 //! * Date: March 10 2018
+//!
+//! Double-buffered receive
+//! -----------------------
+//!
+//! A receive normally stops the receiver when the requested bytes have
+//! arrived, and bytes that arrive before the client posts its next receive are
+//! lost once the RX FIFO fills. Giving the UARTE a spare buffer with
+//! `enable_rx_double_buffering` keeps the receiver running instead: when a
+//! chunk starts, the next chunk's buffer is queued with the EasyDMA pointer,
+//! and the ENDRX->STARTRX shortcut switches to it in hardware. The client gets
+//! one `receive_complete` per chunk of at most 255 bytes, and should post its
+//! next receive from that callback. The buffers are swapped, so the buffer
+//! passed to `receive_complete` may be the spare rather than the one the client
+//! posted. If the client has not posted a receive by the time a chunk starts,
+//! the receiver is stopped and the bytes of that chunk are dropped.
 
 use core;
 use core::cell::Cell;
@@ -13,13 +28,19 @@ use kernel;
 use kernel::common::cells::OptionalCell;
 use kernel::common::registers::{ReadOnly, ReadWrite, WriteOnly};
 use kernel::common::StaticRef;
+use kernel::hil::power_manager::PowerClient;
 use kernel::ReturnCode;
 use nrf5x::pinmux;
+use nrf5x::power::SleepMode;
 
 const UARTE_MAX_BUFFER_SIZE: u32 = 0xff;
 
 static mut BYTE: u8 = 0;
 
+/// Spare receive buffer for `enable_rx_double_buffering`.
+pub static mut RX_SPARE_BUF: [u8; UARTE_MAX_BUFFER_SIZE as usize] =
+    [0; UARTE_MAX_BUFFER_SIZE as usize];
+
 const UARTE_BASE: StaticRef<UarteRegisters> =
     unsafe { StaticRef::new(0x40002000 as *const UarteRegisters) };
 
@@ -163,7 +184,21 @@ pub struct Uarte {
     rx_buffer: kernel::common::cells::TakeCell<'static, [u8]>,
     rx_remaining_bytes: Cell<usize>,
     rx_abort_in_progress: Cell<bool>,
+    rx_error: Cell<Option<kernel::hil::uart::Error>>,
     offset: Cell<usize>,
+    rx_double_buffered: Cell<bool>,
+    /// Buffer that the next chunk will be received into, once the current
+    /// one completes.
+    rx_next: kernel::common::cells::TakeCell<'static, [u8]>,
+    /// Buffer that is neither being received into nor queued.
+    rx_spare: kernel::common::cells::TakeCell<'static, [u8]>,
+    rx_chunk_len: Cell<usize>,
+    /// Whether the client has a receive outstanding, which the chunk being
+    /// received will complete.
+    rx_requested: Cell<bool>,
+    /// Whether the chunk being received was stopped because no client wants
+    /// it.
+    rx_discard: Cell<bool>,
 }
 
 #[derive(Copy, Clone)]
@@ -186,7 +221,14 @@ impl Uarte {
             rx_buffer: kernel::common::cells::TakeCell::empty(),
             rx_remaining_bytes: Cell::new(0),
             rx_abort_in_progress: Cell::new(false),
+            rx_error: Cell::new(None),
             offset: Cell::new(0),
+            rx_double_buffered: Cell::new(false),
+            rx_next: kernel::common::cells::TakeCell::empty(),
+            rx_spare: kernel::common::cells::TakeCell::empty(),
+            rx_chunk_len: Cell::new(0),
+            rx_requested: Cell::new(false),
+            rx_discard: Cell::new(false),
         }
     }
 
@@ -207,27 +249,36 @@ impl Uarte {
         self.enable_uart();
     }
 
-    fn set_baud_rate(&self, baud_rate: u32) {
+    /// Keep receiving between receives, using `spare` for every other chunk.
+    /// See the module documentation.
+    pub fn enable_rx_double_buffering(&self, spare: &'static mut [u8]) {
+        self.rx_spare.replace(spare);
+        self.rx_double_buffered.set(true);
+    }
+
+    fn program_baud_rate(&self, baud_rate: u32) -> ReturnCode {
         let regs = &*self.registers;
-        match baud_rate {
-            1200 => regs.baudrate.set(0x0004F000),
-            2400 => regs.baudrate.set(0x0009D000),
-            4800 => regs.baudrate.set(0x0013B000),
-            9600 => regs.baudrate.set(0x00275000),
-            14400 => regs.baudrate.set(0x003AF000),
-            19200 => regs.baudrate.set(0x004EA000),
-            28800 => regs.baudrate.set(0x0075C000),
-            38400 => regs.baudrate.set(0x009D0000),
-            57600 => regs.baudrate.set(0x00EB0000),
-            76800 => regs.baudrate.set(0x013A9000),
-            115200 => regs.baudrate.set(0x01D60000),
-            230400 => regs.baudrate.set(0x03B00000),
-            250000 => regs.baudrate.set(0x04000000),
-            460800 => regs.baudrate.set(0x07400000),
-            921600 => regs.baudrate.set(0x0F000000),
-            1000000 => regs.baudrate.set(0x10000000),
-            _ => regs.baudrate.set(0x01D60000), //setting default to 115200
-        }
+        let baud_rate_value = match baud_rate {
+            1200 => 0x0004F000,
+            2400 => 0x0009D000,
+            4800 => 0x0013B000,
+            9600 => 0x00275000,
+            14400 => 0x003AF000,
+            19200 => 0x004EA000,
+            28800 => 0x0075C000,
+            38400 => 0x009D0000,
+            57600 => 0x00EB0000,
+            76800 => 0x013A9000,
+            115200 => 0x01D60000,
+            230400 => 0x03B00000,
+            250000 => 0x04000000,
+            460800 => 0x07400000,
+            921600 => 0x0F000000,
+            1000000 => 0x10000000,
+            _ => return ReturnCode::EINVAL,
+        };
+        regs.baudrate.set(baud_rate_value);
+        ReturnCode::SUCCESS
     }
 
     // Enable UART peripheral, this need to disabled for low power applications
@@ -244,7 +295,11 @@ impl Uarte {
 
     fn enable_rx_interrupts(&self) {
         let regs = &*self.registers;
-        regs.intenset.write(Interrupt::ENDRX::SET);
+        regs.intenset
+            .write(Interrupt::ENDRX::SET + Interrupt::ERROR::SET);
+        if self.rx_double_buffered.get() {
+            regs.intenset.write(Interrupt::RXSTARTED::SET);
+        }
     }
 
     fn enable_tx_interrupts(&self) {
@@ -254,7 +309,115 @@ impl Uarte {
 
     fn disable_rx_interrupts(&self) {
         let regs = &*self.registers;
-        regs.intenclr.write(Interrupt::ENDRX::SET);
+        regs.intenclr.write(
+            Interrupt::ENDRX::SET + Interrupt::ERROR::SET + Interrupt::RXSTARTED::SET,
+        );
+    }
+
+    /// End the current receive early. The receiver does not move on to a
+    /// queued chunk, and ENDRX fires once it has stopped.
+    fn stop_rx(&self) {
+        let regs = &*self.registers;
+        regs.shorts.modify(Shorts::ENDRX_STARTRX::CLEAR);
+        regs.task_stoprx.write(Task::ENABLE::SET);
+    }
+
+    /// Start receiving `len` bytes into `rx_buf` with the receiver idle.
+    fn start_rx(&self, rx_buf: &'static mut [u8], len: usize) {
+        let regs = &*self.registers;
+
+        self.rx_remaining_bytes.set(len);
+        self.offset.set(0);
+        self.rx_buffer.replace(rx_buf);
+        self.set_rx_dma_pointer_to_buffer();
+
+        regs.rxd_maxcnt
+            .write(Counter::COUNTER.val(min(len, UARTE_MAX_BUFFER_SIZE as usize) as u32));
+        // A stale RXSTARTED would otherwise move the pointer before this
+        // receive has latched it.
+        regs.event_rxstarted.write(Event::READY::CLEAR);
+        regs.shorts.modify(Shorts::ENDRX_STARTRX::CLEAR);
+        regs.task_stoprx.write(Task::ENABLE::SET);
+        regs.task_startrx.write(Task::ENABLE::SET);
+
+        self.enable_rx_interrupts();
+    }
+
+    /// Called on RXSTARTED when double buffered: queue the buffer for the
+    /// chunk after the one that just started.
+    fn queue_next_rx(&self) {
+        let regs = &*self.registers;
+        if self.rx_buffer.is_none() || self.rx_next.is_some() {
+            return;
+        }
+        match self.rx_spare.take() {
+            Some(next) => {
+                let len = min(self.rx_chunk_len.get(), next.len());
+                regs.rxd_ptr.set(next.as_ptr() as u32);
+                regs.rxd_maxcnt.write(Counter::COUNTER.val(len as u32));
+                self.rx_next.replace(next);
+                regs.shorts.modify(Shorts::ENDRX_STARTRX::SET);
+            }
+            None => {
+                regs.shorts.modify(Shorts::ENDRX_STARTRX::CLEAR);
+                if !self.rx_requested.get() {
+                    // Nobody will read this chunk, so stop instead of
+                    // receiving into a buffer no client will see.
+                    self.rx_discard.set(true);
+                    self.stop_rx();
+                }
+            }
+        }
+    }
+
+    /// Called on ENDRX when double buffered: pass the completed chunk to the
+    /// client.
+    fn complete_rx_chunk(&self, rx_bytes: usize) {
+        let stopped = self.rx_discard.get()
+            || self.rx_abort_in_progress.get()
+            || self.rx_error.get().is_some();
+        let completed = self.rx_buffer.take();
+        // Unless the receiver was stopped, the ENDRX->STARTRX shortcut has
+        // already started the queued chunk.
+        self.rx_next.take().map(|next| {
+            if stopped {
+                self.rx_spare.replace(next);
+            } else {
+                self.rx_buffer.replace(next);
+            }
+        });
+        if self.rx_buffer.is_none() {
+            self.disable_rx_interrupts();
+        }
+
+        let error = if self.rx_discard.get() {
+            None
+        } else if let Some(error) = self.rx_error.take() {
+            Some(error)
+        } else {
+            Some(kernel::hil::uart::Error::CommandComplete)
+        };
+        self.rx_discard.set(false);
+        self.rx_abort_in_progress.set(false);
+
+        completed.map(|buffer| match error {
+            Some(error) => {
+                self.rx_requested.set(false);
+                self.client.map(move |client| {
+                    client.receive_complete(buffer, rx_bytes, error);
+                });
+            }
+            None => {
+                self.rx_spare.replace(buffer);
+            }
+        });
+
+        // A receive posted while a discarded chunk was stopping starts now.
+        if self.rx_buffer.is_none() && self.rx_requested.get() {
+            self.rx_spare.take().map(|buffer| {
+                self.start_rx(buffer, self.rx_chunk_len.get());
+            });
+        }
     }
 
     fn disable_tx_interrupts(&self) {
@@ -262,11 +425,41 @@ impl Uarte {
         regs.intenclr.write(Interrupt::ENDTX::SET);
     }
 
+    /// Returns the most severe receive error flagged in ERRORSRC, and clears
+    /// the flags.
+    fn take_rx_error(&self) -> kernel::hil::uart::Error {
+        let regs = &*self.registers;
+        let error = if regs.errorsrc.is_set(ErrorSrc::BREAK) {
+            kernel::hil::uart::Error::BreakError
+        } else if regs.errorsrc.is_set(ErrorSrc::FRAMING) {
+            kernel::hil::uart::Error::FramingError
+        } else if regs.errorsrc.is_set(ErrorSrc::PARITY) {
+            kernel::hil::uart::Error::ParityError
+        } else {
+            kernel::hil::uart::Error::OverrunError
+        };
+        // The flags are cleared by writing 1 to them
+        regs.errorsrc.set(regs.errorsrc.get());
+        error
+    }
+
     /// UART interrupt handler that listens for both tx_end and rx_end events
     #[inline(never)]
     pub fn handle_interrupt(&mut self) {
         let regs = &*self.registers;
 
+        // A line error ends the receive, which is reported with the error once
+        // the receiver has stopped and ENDRX fires, so that the bytes
+        // received after the error are not passed on as valid data.
+        if regs.event_error.is_set(Event::READY) {
+            regs.event_error.write(Event::READY::CLEAR);
+            let error = self.take_rx_error();
+            if self.rx_buffer.is_some() && self.rx_error.get().is_none() {
+                self.rx_error.set(Some(error));
+                self.stop_rx();
+            }
+        }
+
         if self.tx_ready() {
             self.disable_tx_interrupts();
             let regs = &*self.registers;
@@ -309,7 +502,10 @@ impl Uarte {
             }
         }
 
-        if self.rx_ready() {
+        if self.rx_ready() && self.rx_double_buffered.get() {
+            regs.event_endrx.write(Event::READY::CLEAR);
+            self.complete_rx_chunk(regs.rxd_amount.get() as usize);
+        } else if self.rx_ready() {
             self.disable_rx_interrupts();
 
             // Clear the ENDRX event
@@ -318,9 +514,16 @@ impl Uarte {
             // Get the number of bytes in the buffer that was received this time
             let rx_bytes = regs.rxd_amount.get() as usize;
 
-            // Check if this ENDRX is due to an abort. If so, we want to
-            // do the receive callback immediately.
-            if self.rx_abort_in_progress.get() {
+            // Check if this ENDRX is due to a line error or an abort. If so,
+            // we want to do the receive callback immediately.
+            if let Some(error) = self.rx_error.take() {
+                self.rx_abort_in_progress.set(false);
+                self.client.map(|client| {
+                    self.rx_buffer.take().map(|rx_buffer| {
+                        client.receive_complete(rx_buffer, self.offset.get() + rx_bytes, error);
+                    });
+                });
+            } else if self.rx_abort_in_progress.get() {
                 self.rx_abort_in_progress.set(false);
                 self.client.map(|client| {
                     self.rx_buffer.take().map(|rx_buffer| {
@@ -366,6 +569,13 @@ impl Uarte {
                 }
             }
         }
+
+        // Handled after ENDRX, since the chunk that just started follows the
+        // one that just ended.
+        if regs.event_rxstarted.is_set(Event::READY) {
+            regs.event_rxstarted.write(Event::READY::CLEAR);
+            self.queue_next_rx();
+        }
     }
 
     /// Transmit one byte at the time and the client is responsible for polling
@@ -411,6 +621,17 @@ impl Uarte {
     }
 }
 
+impl PowerClient<SleepMode> for Uarte {
+    fn sleep_requirement(&self) -> Option<SleepMode> {
+        // EasyDMA transfers need the high frequency clock.
+        if self.tx_buffer.is_some() || self.rx_buffer.is_some() {
+            Some(SleepMode::Sleep)
+        } else {
+            None
+        }
+    }
+}
+
 impl kernel::hil::uart::UART for Uarte {
     fn set_client(&self, client: &'static kernel::hil::uart::Client) {
         self.client.set(client);
@@ -429,9 +650,7 @@ impl kernel::hil::uart::UART for Uarte {
             return ReturnCode::ENOSUPPORT;
         }
 
-        self.set_baud_rate(params.baud_rate);
-
-        ReturnCode::SUCCESS
+        self.program_baud_rate(params.baud_rate)
     }
 
     fn transmit(&self, tx_data: &'static mut [u8], tx_len: usize) {
@@ -455,30 +674,255 @@ impl kernel::hil::uart::UART for Uarte {
     }
 
     fn receive(&self, rx_buf: &'static mut [u8], rx_len: usize) {
-        let regs = &*self.registers;
-
         // truncate rx_len if necessary
         let truncated_length = core::cmp::min(rx_len, rx_buf.len());
 
-        self.rx_remaining_bytes.set(truncated_length);
-        self.offset.set(0);
-        self.rx_buffer.replace(rx_buf);
-        self.set_rx_dma_pointer_to_buffer();
-
-        let truncated_uart_max_length = core::cmp::min(truncated_length, 255);
-
-        regs.rxd_maxcnt
-            .write(Counter::COUNTER.val(truncated_uart_max_length as u32));
-        regs.task_stoprx.write(Task::ENABLE::SET);
-        regs.task_startrx.write(Task::ENABLE::SET);
-
-        self.enable_rx_interrupts();
+        if self.rx_double_buffered.get() {
+            self.rx_chunk_len
+                .set(min(truncated_length, UARTE_MAX_BUFFER_SIZE as usize));
+            self.rx_requested.set(true);
+            if self.rx_buffer.is_some() {
+                // The receiver is still running, and will use this buffer
+                // for a later chunk.
+                self.rx_spare.replace(rx_buf);
+                return;
+            }
+        }
+        self.start_rx(rx_buf, truncated_length);
     }
 
     fn abort_receive(&self) {
+        if self.rx_double_buffered.get() && self.rx_buffer.is_none() {
+            return;
+        }
         // Trigger the STOPRX event to cancel the current receive call.
-        let regs = &*self.registers;
         self.rx_abort_in_progress.set(true);
-        regs.task_stoprx.write(Task::ENABLE::SET);
+        self.stop_rx();
+    }
+}
+
+impl kernel::hil::uart::UARTBaudRate for Uarte {
+    fn set_baud_rate(&self, baud_rate: u32) -> ReturnCode {
+        // The transmit buffer is held until the last byte has been sent.
+        if self.tx_buffer.is_some() {
+            return ReturnCode::EBUSY;
+        }
+        self.program_baud_rate(baud_rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use self::std::boxed::Box;
+    use self::std::cell::RefCell;
+    use self::std::vec::Vec;
+    use super::*;
+    use core::ptr;
+    use kernel::common::cells::TakeCell;
+    use kernel::hil::uart::{Client, Error, UART};
+
+    const CHUNK: usize = 8;
+
+    fn leak<T>(v: T) -> &'static mut T {
+        Box::leak(Box::new(v))
+    }
+
+    /// Plays the part of the UARTE hardware: latches the DMA pointer when a
+    /// chunk starts, writes received bytes through it, and raises the events
+    /// that the driver's interrupt handler reacts to.
+    struct Hardware {
+        regs: &'static UarteRegisters,
+        /// The buffers the driver may receive into, as the hardware only sees
+        /// the low 32 bits of their address.
+        buffers: Vec<*mut u8>,
+        dma: Option<(*mut u8, usize)>,
+        amount: usize,
+    }
+
+    impl Hardware {
+        fn task(&self, task: &WriteOnly<u32, Task::Register>) -> bool {
+            let task = task as *const _ as *mut u32;
+            unsafe {
+                let triggered = ptr::read_volatile(task) != 0;
+                ptr::write_volatile(task, 0);
+                triggered
+            }
+        }
+
+        /// Act on the tasks the driver triggered.
+        fn sync(&mut self) {
+            let stop = self.task(&self.regs.task_stoprx);
+            if self.task(&self.regs.task_startrx) {
+                self.start();
+            } else if stop && self.dma.is_some() {
+                self.end();
+            }
+        }
+
+        fn start(&mut self) {
+            let address = self.regs.rxd_ptr.get();
+            let buffer = *self
+                .buffers
+                .iter()
+                .find(|buffer| **buffer as u32 == address)
+                .expect("DMA pointer is not a receive buffer");
+            self.dma = Some((buffer, self.regs.rxd_maxcnt.get() as usize));
+            self.amount = 0;
+            self.regs.event_rxstarted.set(1);
+        }
+
+        fn end(&mut self) {
+            let amount = &self.regs.rxd_amount as *const _ as *mut u32;
+            unsafe { ptr::write_volatile(amount, self.amount as u32) };
+            self.regs.event_endrx.set(1);
+            self.dma = None;
+            if self.regs.shorts.is_set(Shorts::ENDRX_STARTRX) {
+                self.start();
+            }
+        }
+
+        /// Receive `byte`, ending the chunk if it is now full. Returns whether
+        /// the byte was written to a buffer.
+        fn receive(&mut self, byte: u8) -> bool {
+            match self.dma {
+                Some((buffer, maxcnt)) => {
+                    unsafe { ptr::write_volatile(buffer.offset(self.amount as isize), byte) };
+                    self.amount += 1;
+                    if self.amount == maxcnt {
+                        self.end();
+                    }
+                    true
+                }
+                None => false,
+            }
+        }
+
+        /// Run the driver's interrupt handler, then act on the tasks it
+        /// triggered.
+        fn interrupt(&mut self, uarte: *mut Uarte) {
+            unsafe { (*uarte).handle_interrupt() };
+            self.sync();
+        }
+    }
+
+    struct TestClient {
+        uart: &'static Uarte,
+        received: RefCell<Vec<u8>>,
+        callbacks: Cell<usize>,
+        repost: Cell<bool>,
+        buffer: TakeCell<'static, [u8]>,
+    }
+
+    impl Client for TestClient {
+        fn transmit_complete(&self, _tx_buffer: &'static mut [u8], _error: Error) {}
+
+        fn receive_complete(&self, rx_buffer: &'static mut [u8], rx_len: usize, error: Error) {
+            assert_eq!(error, Error::CommandComplete);
+            self.received
+                .borrow_mut()
+                .extend_from_slice(&rx_buffer[..rx_len]);
+            self.callbacks.set(self.callbacks.get() + 1);
+            if self.repost.get() {
+                self.uart.receive(rx_buffer, CHUNK);
+            } else {
+                self.buffer.replace(rx_buffer);
+            }
+        }
+    }
+
+    /// A double-buffered UARTE with fake registers, its client, and the
+    /// hardware. The client has posted a receive and the first chunk has
+    /// started.
+    fn receiving() -> (*mut Uarte, &'static TestClient, Hardware) {
+        let regs = leak(unsafe { core::mem::zeroed::<UarteRegisters>() });
+        let uarte: *mut Uarte = leak(Uarte {
+            registers: unsafe { StaticRef::new(regs) },
+            ..Uarte::new()
+        });
+        let spare = leak([0u8; CHUNK]);
+        let posted = leak([0u8; CHUNK]);
+        let mut buffers = Vec::new();
+        buffers.push(spare.as_mut_ptr());
+        buffers.push(posted.as_mut_ptr());
+        let mut hardware = Hardware {
+            regs: regs,
+            buffers: buffers,
+            dma: None,
+            amount: 0,
+        };
+        let client = leak(TestClient {
+            uart: unsafe { &*uarte },
+            received: RefCell::new(Vec::new()),
+            callbacks: Cell::new(0),
+            repost: Cell::new(true),
+            buffer: TakeCell::empty(),
+        });
+        client.uart.set_client(client);
+        client.uart.enable_rx_double_buffering(spare);
+
+        client.uart.receive(posted, CHUNK);
+        hardware.sync();
+        hardware.interrupt(uarte);
+        (uarte, client, hardware)
+    }
+
+    #[test]
+    fn back_to_back_chunks_are_not_dropped() {
+        let (uarte, client, mut hardware) = receiving();
+
+        // Bytes keep arriving while each ENDRX waits for the interrupt, so
+        // the next chunk has started, and already holds a byte, by the time
+        // the interrupt runs.
+        assert!(hardware.receive(0));
+        let mut byte = 1;
+        for _ in 0..10 {
+            for _ in 0..CHUNK {
+                assert!(hardware.receive(byte));
+                byte += 1;
+            }
+            assert!(hardware.regs.event_endrx.get() != 0);
+            assert!(hardware.regs.event_rxstarted.get() != 0);
+            hardware.interrupt(uarte);
+        }
+
+        assert_eq!(client.callbacks.get(), 10);
+        let expected: Vec<u8> = (0..10 * CHUNK as u8).collect();
+        assert_eq!(*client.received.borrow(), expected);
+    }
+
+    #[test]
+    fn receiver_stops_until_the_next_receive() {
+        let (uarte, client, mut hardware) = receiving();
+        client.repost.set(false);
+
+        for byte in 0..CHUNK as u8 {
+            assert!(hardware.receive(byte));
+        }
+        assert!(hardware.receive(0xff));
+        hardware.interrupt(uarte);
+        assert_eq!(client.callbacks.get(), 1);
+
+        // No receive was posted, so the chunk that started is stopped and
+        // its byte dropped rather than reported later.
+        hardware.interrupt(uarte);
+        assert!(!hardware.receive(0xfe));
+        assert_eq!(client.callbacks.get(), 1);
+
+        client.repost.set(true);
+        client
+            .buffer
+            .take()
+            .map(|buffer| client.uart.receive(buffer, CHUNK));
+        hardware.sync();
+        hardware.interrupt(uarte);
+        for byte in CHUNK as u8..2 * CHUNK as u8 {
+            assert!(hardware.receive(byte));
+        }
+        hardware.interrupt(uarte);
+
+        assert_eq!(client.callbacks.get(), 2);
+        let expected: Vec<u8> = (0..2 * CHUNK as u8).collect();
+        assert_eq!(*client.received.borrow(), expected);
     }
 }
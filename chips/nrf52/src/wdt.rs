@@ -0,0 +1,169 @@
+//! Watchdog Timer (WDT)
+//!
+//! The watchdog counts down from a reload value at 32.768 kHz and resets the
+//! chip when it reaches zero, unless it is reloaded first. It is configured to
+//! pause while the CPU is sleeping and while it is halted by a debugger, so a
+//! kernel that is idle waiting for interrupts is not reset, but a kernel stuck
+//! in its main loop is.
+//!
+//! Once started, the WDT can not be stopped or reconfigured until the chip is
+//! reset.
+
+use core::cell::Cell;
+use kernel::common::registers::{ReadOnly, ReadWrite, WriteOnly};
+use kernel::common::StaticRef;
+use kernel::hil;
+
+const WDT_BASE: StaticRef<WdtRegisters> =
+    unsafe { StaticRef::new(0x40010000 as *const WdtRegisters) };
+
+/// Frequency of the low frequency clock the watchdog runs on.
+const WDT_CLOCK_HZ: u64 = 32768;
+
+/// The smallest reload value accepted by the counter reload value register.
+const MIN_CRV: u64 = 0xF;
+
+/// Value that has to be written to a reload request register to reload the
+/// counter.
+const RELOAD_VALUE: u32 = 0x6E524635;
+
+#[repr(C)]
+struct WdtRegisters {
+    /// Start the watchdog
+    /// Address: 0x000 - 0x004
+    task_start: WriteOnly<u32, Task::Register>,
+    _reserved1: [u32; 63],
+    /// Watchdog timeout
+    /// Address: 0x100 - 0x104
+    _event_timeout: ReadWrite<u32, Event::Register>,
+    _reserved2: [u32; 128],
+    /// Enable interrupt
+    /// Address: 0x304 - 0x308
+    _intenset: ReadWrite<u32, Interrupt::Register>,
+    /// Disable interrupt
+    /// Address: 0x308 - 0x30C
+    intenclr: ReadWrite<u32, Interrupt::Register>,
+    _reserved3: [u32; 61],
+    /// Run status
+    /// Address: 0x400 - 0x404
+    runstatus: ReadOnly<u32, RunStatus::Register>,
+    /// Request status
+    /// Address: 0x404 - 0x408
+    _reqstatus: ReadOnly<u32>,
+    _reserved4: [u32; 63],
+    /// Counter reload value
+    /// Address: 0x504 - 0x508
+    crv: ReadWrite<u32>,
+    /// Enable register for reload request registers
+    /// Address: 0x508 - 0x50C
+    rren: ReadWrite<u32, ReloadRequestEnable::Register>,
+    /// Configuration register
+    /// Address: 0x50C - 0x510
+    config: ReadWrite<u32, Config::Register>,
+    _reserved5: [u32; 60],
+    /// Reload request registers
+    /// Address: 0x600 - 0x620
+    rr: [WriteOnly<u32>; 8],
+}
+
+register_bitfields! [u32,
+    /// Start task
+    Task [
+        ENABLE OFFSET(0) NUMBITS(1)
+    ],
+    /// Timeout event
+    Event [
+        READY OFFSET(0) NUMBITS(1)
+    ],
+    /// Timeout interrupt
+    Interrupt [
+        TIMEOUT OFFSET(0) NUMBITS(1)
+    ],
+    /// Run status
+    RunStatus [
+        /// Indicates whether or not the watchdog is running
+        RUNSTATUS OFFSET(0) NUMBITS(1) [
+            NotRunning = 0,
+            Running = 1
+        ]
+    ],
+    /// Enable or disable reload request registers
+    ReloadRequestEnable [
+        RR0 OFFSET(0) NUMBITS(1)
+    ],
+    /// Configuration register
+    Config [
+        /// Keep the watchdog running or pause it while the CPU is sleeping
+        SLEEP OFFSET(0) NUMBITS(1) [
+            Pause = 0,
+            Run = 1
+        ],
+        /// Keep the watchdog running or pause it while the CPU is halted by
+        /// the debugger
+        HALT OFFSET(3) NUMBITS(1) [
+            Pause = 0,
+            Run = 1
+        ]
+    ]
+];
+
+pub struct Wdt {
+    registers: StaticRef<WdtRegisters>,
+    enabled: Cell<bool>,
+}
+
+pub static mut WDT: Wdt = Wdt::new();
+
+impl Wdt {
+    const fn new() -> Wdt {
+        Wdt {
+            registers: WDT_BASE,
+            enabled: Cell::new(false),
+        }
+    }
+
+    /// Converts a timeout period in milliseconds into a counter reload value.
+    fn period_to_crv(period: usize) -> u32 {
+        let ticks = (period as u64) * WDT_CLOCK_HZ / 1000;
+        if ticks < MIN_CRV {
+            MIN_CRV as u32
+        } else if ticks > u32::max_value() as u64 {
+            u32::max_value()
+        } else {
+            ticks as u32
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        let regs = &*self.registers;
+        regs.runstatus.is_set(RunStatus::RUNSTATUS)
+    }
+}
+
+impl hil::watchdog::Watchdog for Wdt {
+    fn start(&self, period: usize) {
+        let regs = &*self.registers;
+
+        // The configuration registers are locked while the watchdog runs
+        if !self.is_running() {
+            regs.intenclr.write(Interrupt::TIMEOUT::SET);
+            regs.config
+                .write(Config::SLEEP::Pause + Config::HALT::Pause);
+            regs.crv.set(Wdt::period_to_crv(period));
+            regs.rren.write(ReloadRequestEnable::RR0::SET);
+            regs.task_start.write(Task::ENABLE::SET);
+        }
+        self.enabled.set(true);
+    }
+
+    /// The WDT can not be stopped once it has been started; it keeps running
+    /// until the chip is reset.
+    fn stop(&self) {}
+
+    fn tickle(&self) {
+        if self.enabled.get() {
+            let regs = &*self.registers;
+            regs.rr[0].set(RELOAD_VALUE);
+        }
+    }
+}
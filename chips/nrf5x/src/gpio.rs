@@ -354,6 +354,35 @@ impl GPIOPin {
         let gpio_regs = &*self.gpio_registers;
         gpio_regs.pin_cnf[self.pin as usize].write(config);
     }
+
+    /// Allocate a GPIOTE channel that generates an event, but no interrupt,
+    /// on the given edges of this pin, so that the event can trigger tasks of
+    /// other peripherals through the PPI. Returns the address of the event
+    /// register, or `None` if all channels are in use. The channel is freed by
+    /// `disable_interrupt`.
+    pub fn enable_event(&self, mode: hil::gpio::InterruptMode) -> Option<u32> {
+        self.allocate_channel().ok().map(|channel| {
+            let polarity = match mode {
+                hil::gpio::InterruptMode::EitherEdge => Config::POLARITY::Toggle,
+                hil::gpio::InterruptMode::RisingEdge => Config::POLARITY::LoToHi,
+                hil::gpio::InterruptMode::FallingEdge => Config::POLARITY::HiToLo,
+            };
+            let regs = &*self.gpiote_registers;
+            regs.config[channel]
+                .write(Config::MODE::Event + Config::PSEL.val(self.pin as u32) + polarity);
+            &regs.event_in[channel] as *const _ as u32
+        })
+    }
+
+    /// The address of the event register of the GPIOTE channel allocated to
+    /// this pin by `enable_interrupt`, or `None` if it has none.
+    pub fn event_address(&self) -> Option<u32> {
+        let regs = &*self.gpiote_registers;
+        self.find_channel(self.pin)
+            .ok()
+            .filter(|&channel| regs.config[channel].matches_all(Config::MODE::Event))
+            .map(|channel| &regs.event_in[channel] as *const _ as u32)
+    }
 }
 
 impl hil::gpio::PinCtl for GPIOPin {
@@ -367,6 +396,12 @@ impl hil::gpio::PinCtl for GPIOPin {
     }
 }
 
+impl hil::gpio::InterruptPin for GPIOPin {
+    fn set_client(&self, client: &'static hil::gpio::Client) {
+        self.client.set(client);
+    }
+}
+
 impl hil::gpio::Pin for GPIOPin {
     fn make_output(&self) {
         let gpio_regs = &*self.gpio_registers;
@@ -430,6 +465,21 @@ impl hil::gpio::Pin for GPIOPin {
             regs.intenclr.set(1 << channel);
         }
     }
+
+    fn port_bit(&self) -> Option<(usize, usize)> {
+        Some((0, self.pin as usize))
+    }
+
+    fn write_port(&self, mask: u32, value: u32) {
+        let gpio_regs = &*self.gpio_registers;
+        let out = gpio_regs.out.get();
+        gpio_regs.out.set(out & !mask | value & mask);
+    }
+
+    fn read_port(&self) -> u32 {
+        let gpio_regs = &*self.gpio_registers;
+        gpio_regs.in_.get()
+    }
 }
 
 impl GPIOPin {
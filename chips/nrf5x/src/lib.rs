@@ -17,6 +17,7 @@ pub mod constants;
 pub mod gpio;
 pub mod peripheral_interrupts;
 pub mod pinmux;
+pub mod power;
 pub mod rtc;
 pub mod temperature;
 pub mod timer;
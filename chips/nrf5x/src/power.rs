@@ -0,0 +1,173 @@
+//! Sleep mode selection for nRF5x chips.
+//!
+//! The nRF5x cores support two useful sleep modes while the system is on:
+//!
+//! - `Sleep`: the core executes WFI with SLEEPDEEP cleared, so the high
+//!   frequency clock stays available to peripherals that are mid-operation.
+//! - `DeepSleep`: the core executes WFI with SLEEPDEEP set. Only peripherals
+//!   running from the low frequency clock (e.g. the RTC backing the kernel
+//!   alarm) and GPIOTE are expected to wake the chip.
+//!
+//! Peripherals register with `POWER` and report the deepest mode they can
+//! tolerate. The chip picks the deepest mode that all of them allow, and does
+//! not sleep while a deferred call is pending.
+
+use kernel::common::cells::OptionalCell;
+use kernel::common::deferred_call;
+use kernel::hil::power_manager::{PowerClient, PowerManager};
+use kernel::ReturnCode;
+
+/// Maximum number of peripherals that can register power requirements.
+const MAX_CLIENTS: usize = 8;
+
+/// Sleep modes, ordered from lightest to deepest.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SleepMode {
+    Sleep,
+    DeepSleep,
+}
+
+pub struct Power<'a> {
+    clients: [OptionalCell<&'a PowerClient<SleepMode>>; MAX_CLIENTS],
+}
+
+pub static mut POWER: Power<'static> = Power::new();
+
+impl Power<'a> {
+    const fn new() -> Power<'a> {
+        Power {
+            clients: [
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+            ],
+        }
+    }
+}
+
+impl PowerManager<'a> for Power<'a> {
+    type SleepMode = SleepMode;
+
+    fn register(&self, client: &'a PowerClient<SleepMode>) -> ReturnCode {
+        match self.clients.iter().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                slot.set(client);
+                ReturnCode::SUCCESS
+            }
+            None => ReturnCode::ENOMEM,
+        }
+    }
+
+    fn sleep_mode(&self) -> Option<SleepMode> {
+        // Deferred calls do not raise an interrupt, so WFI would not return
+        // until something unrelated woke the core.
+        if deferred_call::has_tasks() {
+            return None;
+        }
+        let mode = self
+            .clients
+            .iter()
+            .filter_map(|slot| slot.map_or(None, |client| client.sleep_requirement()))
+            .min()
+            .unwrap_or(SleepMode::DeepSleep);
+        Some(mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+    use core::convert::TryFrom;
+    use kernel::common::deferred_call::DeferredCall;
+
+    struct Peripheral {
+        requirement: Cell<Option<SleepMode>>,
+    }
+
+    impl Peripheral {
+        fn idle() -> Peripheral {
+            Peripheral {
+                requirement: Cell::new(None),
+            }
+        }
+    }
+
+    impl PowerClient<SleepMode> for Peripheral {
+        fn sleep_requirement(&self) -> Option<SleepMode> {
+            self.requirement.get()
+        }
+    }
+
+    #[derive(Copy, Clone)]
+    struct Task;
+
+    impl TryFrom<usize> for Task {
+        type Error = ();
+
+        fn try_from(_value: usize) -> Result<Task, ()> {
+            Ok(Task)
+        }
+    }
+
+    impl Into<usize> for Task {
+        fn into(self) -> usize {
+            0
+        }
+    }
+
+    // The deferred call flags are global, so every check of `sleep_mode` is
+    // in this one test to keep them from running in parallel with the
+    // deferred call.
+    #[test]
+    fn sleep_mode_follows_peripherals_and_kernel_work() {
+        let uart = Peripheral::idle();
+        let timer = Peripheral::idle();
+        let rtc = Peripheral::idle();
+        let power = Power::new();
+        assert_eq!(power.sleep_mode(), Some(SleepMode::DeepSleep));
+
+        assert_eq!(power.register(&uart), ReturnCode::SUCCESS);
+        assert_eq!(power.register(&timer), ReturnCode::SUCCESS);
+        assert_eq!(power.register(&rtc), ReturnCode::SUCCESS);
+        assert_eq!(power.sleep_mode(), Some(SleepMode::DeepSleep));
+
+        // A peripheral running from the low frequency clock allows deep
+        // sleep.
+        rtc.requirement.set(Some(SleepMode::DeepSleep));
+        assert_eq!(power.sleep_mode(), Some(SleepMode::DeepSleep));
+
+        // Any active peripheral that needs the high frequency clock keeps
+        // the chip in light sleep.
+        uart.requirement.set(Some(SleepMode::Sleep));
+        assert_eq!(power.sleep_mode(), Some(SleepMode::Sleep));
+        timer.requirement.set(Some(SleepMode::Sleep));
+        uart.requirement.set(None);
+        assert_eq!(power.sleep_mode(), Some(SleepMode::Sleep));
+        timer.requirement.set(None);
+        assert_eq!(power.sleep_mode(), Some(SleepMode::DeepSleep));
+
+        // A pending deferred call would not wake the chip, so it must not
+        // sleep at all until the call is serviced.
+        let call = unsafe { DeferredCall::new(Task) };
+        call.set();
+        assert_eq!(power.sleep_mode(), None);
+        assert!(DeferredCall::<Task>::next_pending().is_some());
+        assert_eq!(power.sleep_mode(), Some(SleepMode::DeepSleep));
+    }
+
+    #[test]
+    fn register_fails_when_full() {
+        let peripheral = Peripheral::idle();
+        let power = Power::new();
+        for _ in 0..MAX_CLIENTS {
+            assert_eq!(power.register(&peripheral), ReturnCode::SUCCESS);
+        }
+        assert_eq!(power.register(&peripheral), ReturnCode::ENOMEM);
+    }
+}
@@ -162,4 +162,9 @@ impl Alarm for Rtc {
     fn get_alarm(&self) -> u32 {
         self.registers.cc[0].read(CC::CC)
     }
+
+    // The RTC counter is 24 bits wide
+    fn max_tics(&self) -> u32 {
+        0xFFFFFF
+    }
 }
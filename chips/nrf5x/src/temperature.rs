@@ -126,8 +126,9 @@ impl Temp {
         let regs = &*self.registers;
 
         // get temperature
-        // Result of temperature measurement in °C, 2's complement format, 0.25 °C
-        let temp = (regs.temp.get() / 4) * 100;
+        // Result of temperature measurement in °C, 2's complement format, 0.25 °C,
+        // converted to hundredths of a degree without losing the fraction.
+        let temp = (regs.temp.get() as i32) * 25;
 
         // stop measurement
         regs.task_stop.write(Task::ENABLE::SET);
@@ -26,6 +26,8 @@ use kernel::common::cells::OptionalCell;
 use kernel::common::registers::{self, ReadWrite, WriteOnly};
 use kernel::common::StaticRef;
 use kernel::hil;
+use kernel::hil::power_manager::PowerClient;
+use power::SleepMode;
 
 const INSTANCES: [StaticRef<TimerRegisters>; 3] = unsafe {
     [
@@ -195,6 +197,12 @@ register_bitfields![u32,
     ]
 ];
 
+/// Value of the MODE register that makes the timer count clock tics
+const MODE_TIMER: u32 = 0;
+
+/// Value of the MODE register that makes the timer a counter
+const MODE_COUNTER: u32 = 1;
+
 pub enum BitmodeValue {
     Size16Bits = 0,
     Size8Bits = 1,
@@ -228,6 +236,56 @@ impl Timer {
         self.client.set(client);
     }
 
+    /// Configure the timer as a 32-bit counter that is incremented by its
+    /// COUNT task rather than by the clock, and start it. The counter keeps
+    /// its current value.
+    pub fn start_counter(&self) {
+        self.registers.mode.set(MODE_COUNTER);
+        self.registers.bitmode.write(Bitmode::BITMODE::Bit32);
+        self.registers.tasks_start.write(Task::ENABLE::SET);
+    }
+
+    /// Configure the timer as a free-running 32-bit timer that counts at
+    /// 16 MHz / 2^`prescaler`, and start it.
+    pub fn start_timer(&self, prescaler: u32) {
+        self.registers.mode.set(MODE_TIMER);
+        self.registers.bitmode.write(Bitmode::BITMODE::Bit32);
+        self.registers.prescaler.set(prescaler);
+        self.registers.tasks_start.write(Task::ENABLE::SET);
+    }
+
+    pub fn stop(&self) {
+        self.registers.tasks_stop.write(Task::ENABLE::SET);
+    }
+
+    pub fn clear(&self) {
+        self.registers.tasks_clear.write(Task::ENABLE::SET);
+    }
+
+    /// Capture the current value of the timer into capture/compare register
+    /// `cc` and return it.
+    pub fn capture(&self, cc: usize) -> u32 {
+        self.registers.tasks_capture[cc].write(Task::ENABLE::SET);
+        self.registers.cc[cc].get()
+    }
+
+    /// The value last captured into capture/compare register `cc`.
+    pub fn captured(&self, cc: usize) -> u32 {
+        self.registers.cc[cc].get()
+    }
+
+    /// The address of the COUNT task register, for triggering it from an
+    /// event through the PPI.
+    pub fn count_task_address(&self) -> u32 {
+        &self.registers.tasks_count as *const _ as u32
+    }
+
+    /// The address of the CAPTURE task register of capture/compare register
+    /// `cc`, for triggering it from an event through the PPI.
+    pub fn capture_task_address(&self, cc: usize) -> u32 {
+        &self.registers.tasks_capture[cc] as *const _ as u32
+    }
+
     /// When an interrupt occurs, check if any of the 4 compares have
     /// created an event, and if so, add it to the bitmask of triggered
     /// events that is passed to the client.
@@ -327,6 +385,17 @@ impl hil::time::Time for TimerAlarm {
     }
 }
 
+impl PowerClient<SleepMode> for TimerAlarm {
+    fn sleep_requirement(&self) -> Option<SleepMode> {
+        // The timer runs from the high frequency clock.
+        if self.interrupts_enabled() {
+            Some(SleepMode::Sleep)
+        } else {
+            None
+        }
+    }
+}
+
 impl hil::time::Alarm for TimerAlarm {
     fn now(&self) -> u32 {
         self.value()
@@ -492,6 +492,12 @@ impl hil::gpio::PinCtl for GPIOPin {
     }
 }
 
+impl hil::gpio::InterruptPin for GPIOPin {
+    fn set_client(&self, client: &'static hil::gpio::Client) {
+        self.client.set(client);
+    }
+}
+
 impl hil::gpio::Pin for GPIOPin {
     fn disable(&self) {
         GPIOPin::disable(self);
@@ -539,4 +545,21 @@ impl hil::gpio::Pin for GPIOPin {
     fn disable_interrupt(&self) {
         GPIOPin::disable_interrupt(self);
     }
+
+    fn port_bit(&self) -> Option<(usize, usize)> {
+        let port: &GpioRegisters = &*self.port;
+        let port_num = (port as *const GpioRegisters as usize - BASE_ADDRESS) / SIZE;
+        Some((port_num, self.pin_mask.trailing_zeros() as usize))
+    }
+
+    fn write_port(&self, mask: u32, value: u32) {
+        let port: &GpioRegisters = &*self.port;
+        let ovr = port.ovr.val.get();
+        port.ovr.val.set(ovr & !mask | value & mask);
+    }
+
+    fn read_port(&self) -> u32 {
+        let port: &GpioRegisters = &*self.port;
+        port.pvr.get()
+    }
 }
@@ -2,6 +2,7 @@
 //! flash on the sam4l.
 
 use kernel::common::StaticRef;
+use kernel::hil;
 
 // The sam4l stores a unique 120 bit serial number readable from address 0x0080020C to 0x0080021A
 // This value cannot be written to normally, and instead requires special instructions to overwrite,
@@ -45,3 +46,16 @@ impl SerialNum {
             .fold(0u64, |sum, (i, &val)| sum + ((val as u64) << i * 8))
     }
 }
+
+impl hil::device_id::DeviceId for SerialNum {
+    fn id_len(&self) -> usize {
+        self.regs.serial_num.len()
+    }
+
+    fn read_id(&self, buf: &mut [u8]) -> usize {
+        let serial_num = self.get();
+        let len = buf.len().min(serial_num.len());
+        buf[..len].copy_from_slice(&serial_num[..len]);
+        len
+    }
+}
@@ -50,6 +50,15 @@ impl AppId {
             (start, end)
         })
     }
+
+    /// Checks whether the `size` bytes starting at `address` lie within the
+    /// flash range returned by `get_editable_flash_range`, so that a capsule
+    /// can validate a flash address passed in by the app.
+    pub fn in_app_flash_bounds(&self, address: usize, size: usize) -> bool {
+        self.kernel.process_map_or(false, self.idx, |process| {
+            process.in_app_flash_bounds(address as *const u8, size)
+        })
+    }
 }
 
 /// Type for calling a callback in a process.
@@ -18,4 +18,10 @@ pub trait Queue<T> {
 
     /// Remove all elements from the ring buffer.
     fn empty(&mut self);
+
+    /// Remove the first element for which `f` returns true, keeping the other
+    /// elements in order.
+    fn remove_first_matching<F>(&mut self, f: F) -> Option<T>
+    where
+        F: Fn(&T) -> bool;
 }
@@ -63,4 +63,28 @@ impl<T: Copy> queue::Queue<T> for RingBuffer<'a, T> {
         self.head = 0;
         self.tail = 0;
     }
+
+    fn remove_first_matching<F>(&mut self, f: F) -> Option<T>
+    where
+        F: Fn(&T) -> bool,
+    {
+        let len = self.ring.len();
+        let mut slot = self.head;
+        while slot != self.tail {
+            if f(&self.ring[slot]) {
+                let val = self.ring[slot];
+                // Shift the elements behind the removed one forward.
+                let mut next = (slot + 1) % len;
+                while next != self.tail {
+                    self.ring[slot] = self.ring[next];
+                    slot = next;
+                    next = (next + 1) % len;
+                }
+                self.tail = slot;
+                return Some(val);
+            }
+            slot = (slot + 1) % len;
+        }
+        None
+    }
 }
@@ -131,6 +131,86 @@ pub unsafe fn panic_process_info<W: Write>(
     }
 }
 
+/// Marks the start of a crash record saved by `panic_crash_log`.
+pub const CRASH_LOG_MAGIC: &[u8] = b"TOCK CRASH LOG\r\n";
+
+/// Writes into a byte slice, silently dropping whatever does not fit.
+struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Write for SliceWriter<'a> {
+    fn write_str(&mut self, s: &str) -> Result {
+        let count = cmp::min(s.len(), self.buf.len() - self.len);
+        self.buf[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+/// Saves a compact crash record to a page of flash, so that the cause of a
+/// panic can be recovered after the board reboots even if no one was
+/// watching the console.
+///
+/// The record holds the panic location and message, the fault status, and
+/// the name, state, and program counter of each process. It is formatted
+/// into `page` in RAM first, and then written with a single `write_page`
+/// call, so the flash is touched as little as possible. Boards should call
+/// it after printing the panic to the console, so that the console report is
+/// not lost if writing the flash faults.
+///
+/// **NOTE:** Interrupts are not serviced during a panic, so `flash` must
+/// complete the write before `write_page` returns, as the nRF52 NVMC does.
+/// The completion callback is never delivered.
+pub unsafe fn panic_crash_log<F: hil::flash::Flash>(
+    flash: &F,
+    page_number: usize,
+    page: &'static mut F::Page,
+    panic_info: &PanicInfo,
+    procs: &'static [Option<&'static ProcessType>],
+) {
+    {
+        let buf = page.as_mut();
+        for byte in buf.iter_mut() {
+            *byte = 0xff;
+        }
+        let mut writer = SliceWriter { buf: buf, len: 0 };
+        let _ = writer.write_str(str::from_utf8_unchecked(CRASH_LOG_MAGIC));
+        panic_banner(&mut writer, panic_info);
+        if !procs.is_empty() {
+            procs[0].as_ref().map(|process| {
+                process.fault_fmt(&mut writer);
+            });
+        }
+        for process in procs.iter() {
+            process.as_ref().map(|process| {
+                process.crash_fmt(&mut writer);
+            });
+        }
+    }
+    flash.write_page(page_number, page);
+}
+
+/// Returns the crash record saved by `panic_crash_log` in `page`, the
+/// contents of the flash page it was written to, or `None` if the page does
+/// not hold one.
+pub fn crash_log_record(page: &[u8]) -> Option<&str> {
+    if !page.starts_with(CRASH_LOG_MAGIC) {
+        return None;
+    }
+    let record = &page[CRASH_LOG_MAGIC.len()..];
+    let len = record
+        .iter()
+        .position(|&byte| byte == 0xff)
+        .unwrap_or(record.len());
+    match str::from_utf8(&record[..len]) {
+        Ok(s) => Some(s),
+        // The record was cut short in the middle of a character.
+        Err(e) => str::from_utf8(&record[..e.valid_up_to()]).ok(),
+    }
+}
+
 /// Blinks a recognizable pattern forever.
 ///
 /// If a multi-color LED is used for the panic pattern, it is
@@ -604,3 +684,123 @@ pub unsafe fn flush<W: Write>(writer: &mut W) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::boxed::Box;
+    use self::std::cell::RefCell;
+    use self::std::panic;
+    use self::std::thread::{self, ThreadId};
+    use self::std::vec::Vec;
+    use super::*;
+    use returncode::ReturnCode;
+    use test_util::{self, TestApp};
+
+    struct Page([u8; 512]);
+
+    impl AsMut<[u8]> for Page {
+        fn as_mut(&mut self) -> &mut [u8] {
+            &mut self.0
+        }
+    }
+
+    /// Flash that keeps the last page written to it.
+    struct TestFlash {
+        written: RefCell<Option<(usize, Vec<u8>)>>,
+    }
+
+    impl hil::flash::Flash for TestFlash {
+        type Page = Page;
+
+        fn read_page(&self, _page_number: usize, _buf: &'static mut Page) -> ReturnCode {
+            ReturnCode::FAIL
+        }
+
+        fn write_page(&self, page_number: usize, buf: &'static mut Page) -> ReturnCode {
+            *self.written.borrow_mut() = Some((page_number, buf.0.to_vec()));
+            ReturnCode::SUCCESS
+        }
+
+        fn erase_page(&self, _page_number: usize) -> ReturnCode {
+            ReturnCode::FAIL
+        }
+    }
+
+    /// Where the panic hook saves a crash record, and the thread that is
+    /// expected to panic. Only written while the hook is not installed.
+    static mut CRASH: Option<(
+        ThreadId,
+        &'static TestFlash,
+        &'static [Option<&'static ProcessType>],
+    )> = None;
+
+    /// Panics with `message` and saves a crash record of `procs` from the
+    /// panic hook, the way a board's panic handler does.
+    fn crash(message: &'static str, procs: &'static [Option<&'static ProcessType>]) -> Vec<u8> {
+        let flash: &'static TestFlash = Box::leak(Box::new(TestFlash {
+            written: RefCell::new(None),
+        }));
+        unsafe {
+            CRASH = Some((thread::current().id(), flash, procs));
+        }
+
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| match unsafe { CRASH } {
+            Some((id, flash, procs)) if id == thread::current().id() => unsafe {
+                let page = Box::leak(Box::new(Page([0; 512])));
+                panic_crash_log(flash, 7, page, info, procs);
+            },
+            _ => default_hook(info),
+        }));
+        let result = panic::catch_unwind(|| panic!("{}", message));
+        let _ = panic::take_hook();
+        unsafe {
+            CRASH = None;
+        }
+        assert!(result.is_err());
+
+        let (page_number, page) = flash.written.borrow_mut().take().unwrap();
+        assert_eq!(page_number, 7);
+        page
+    }
+
+    #[test]
+    fn crash_record_is_written_and_parseable() {
+        let procs = {
+            let _debug = test_util::capture_debug();
+            test_util::load_apps(1, &[TestApp::new("blinky")]).1
+        };
+        let page = crash("disk on fire", procs);
+
+        let record = crash_log_record(&page).unwrap();
+        assert!(record.contains("Kernel panic at "));
+        assert!(record.contains("\"disk on fire\""));
+        assert!(record.contains("App: blinky [Yielded]"));
+        // The rest of the page is left erased.
+        assert_eq!(page[page.len() - 1], 0xff);
+    }
+
+    #[test]
+    fn erased_page_holds_no_record() {
+        assert_eq!(crash_log_record(&[0xff; 64]), None);
+        assert_eq!(crash_log_record(&[]), None);
+    }
+
+    #[test]
+    fn slice_writer_drops_what_does_not_fit() {
+        let mut buf = [0xff; 19];
+        {
+            let mut writer = SliceWriter {
+                buf: &mut buf,
+                len: 0,
+            };
+            let _ = writer.write_str(str::from_utf8(CRASH_LOG_MAGIC).unwrap());
+            let _ = writer.write_str("ab\u{e9}cd");
+            assert_eq!(writer.len, 19);
+        }
+        // The record is cut short in the middle of the two byte character.
+        assert_eq!(crash_log_record(&buf), Some("ab"));
+    }
+}
@@ -1,4 +1,19 @@
 //! Data structure to store a list of userspace applications.
+//!
+//! The memory for an app's grant region is allocated from the app's memory the
+//! first time the grant is entered for that app. This fails if the grant
+//! region would collide with the app's heap, in which case `enter` returns
+//! `Error::OutOfMemory`. Capsules must not silently ignore this error when
+//! handling a system call; instead they should report it to the app, which
+//! `ReturnCode::from` maps to `ENOMEM`:
+//!
+//! ```ignore
+//! self.apps
+//!     .enter(appid, |app, _| {
+//!         app.callback = callback;
+//!         ReturnCode::SUCCESS
+//!     }).unwrap_or_else(|err| err.into())
+//! ```
 
 use core::marker::PhantomData;
 use core::mem::size_of;
@@ -158,6 +173,9 @@ impl<T: Default> Grant<T> {
         }
     }
 
+    /// Runs `fun` with access to the grant region of `appid`, allocating the
+    /// region first if needed. Returns `Error::OutOfMemory` if the region
+    /// cannot be allocated, and `Error::NoSuchApp` if `appid` is not valid.
     pub fn enter<F, R>(&self, appid: AppId, fun: F) -> Result<R, Error>
     where
         F: FnOnce(&mut Borrowed<T>, &mut Allocator) -> R,
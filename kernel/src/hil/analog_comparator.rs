@@ -22,6 +22,14 @@ pub trait AnalogComparator {
 
     /// Stop interrupt-based comparison for the chosen channel.
     fn stop_comparing(&self, channel: &Self::Channel) -> ReturnCode;
+
+    /// Set the reference level, in millivolts, that the chosen channel's input
+    /// is compared against, for comparators with a configurable internal
+    /// reference. Returns `EINVAL` if the level cannot be represented and
+    /// `ENOSUPPORT` if the comparator's reference is fixed or external.
+    fn set_reference(&self, _channel: &Self::Channel, _millivolts: usize) -> ReturnCode {
+        ReturnCode::ENOSUPPORT
+    }
 }
 
 pub trait Client {
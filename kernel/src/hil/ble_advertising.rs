@@ -164,3 +164,156 @@ impl RadioChannel {
         }
     }
 }
+
+/// The largest advertising data payload, since the maximum advertising channel
+/// PDU is 37 bytes and includes the 6-byte advertiser address.
+pub const MAX_ADV_DATA_LEN: usize = 31;
+
+// Bluetooth Core Specification Supplement, Part A, section 1: data types
+pub const AD_TYPE_FLAGS: u8 = 0x01;
+pub const AD_TYPE_COMPLETE_LIST_16BIT_SERVICE_UUIDS: u8 = 0x03;
+pub const AD_TYPE_COMPLETE_LOCAL_NAME: u8 = 0x09;
+pub const AD_TYPE_MANUFACTURER_SPECIFIC_DATA: u8 = 0xff;
+
+/// Assembles advertising data from AD structures, each of which is a length
+/// byte, an AD type byte, and the data. The data is limited to
+/// `MAX_ADV_DATA_LEN` bytes, or the length of the buffer if that is shorter,
+/// and adding a structure that does not fit returns `ESIZE`, leaving the data
+/// unchanged.
+pub struct AdvData<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl AdvData<'a> {
+    /// Starts empty advertising data in `buf`.
+    pub fn new(buf: &'a mut [u8]) -> AdvData<'a> {
+        AdvData { buf: buf, len: 0 }
+    }
+
+    /// Continues advertising data whose first `len` bytes are already in
+    /// `buf`.
+    pub fn with_len(buf: &'a mut [u8], len: usize) -> AdvData<'a> {
+        let len = ::core::cmp::min(len, buf.len());
+        AdvData { buf: buf, len: len }
+    }
+
+    /// The length of the advertising data assembled so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Appends an AD structure of type `ad_type` holding `data`.
+    pub fn add(&mut self, ad_type: u8, data: &[u8]) -> ReturnCode {
+        self.add_with(ad_type, data.len(), |buf| buf.copy_from_slice(data))
+    }
+
+    pub fn add_flags(&mut self, flags: u8) -> ReturnCode {
+        self.add(AD_TYPE_FLAGS, &[flags])
+    }
+
+    pub fn add_complete_local_name(&mut self, name: &[u8]) -> ReturnCode {
+        self.add(AD_TYPE_COMPLETE_LOCAL_NAME, name)
+    }
+
+    pub fn add_service_uuids16(&mut self, uuids: &[u16]) -> ReturnCode {
+        self.add_with(
+            AD_TYPE_COMPLETE_LIST_16BIT_SERVICE_UUIDS,
+            uuids.len() * 2,
+            |buf| {
+                for (i, uuid) in uuids.iter().enumerate() {
+                    buf[i * 2] = *uuid as u8;
+                    buf[i * 2 + 1] = (*uuid >> 8) as u8;
+                }
+            },
+        )
+    }
+
+    /// Appends manufacturer specific data, which starts with the company
+    /// identifier assigned by the Bluetooth SIG.
+    pub fn add_manufacturer_data(&mut self, company_id: u16, data: &[u8]) -> ReturnCode {
+        self.add_with(AD_TYPE_MANUFACTURER_SPECIFIC_DATA, data.len() + 2, |buf| {
+            buf[0] = company_id as u8;
+            buf[1] = (company_id >> 8) as u8;
+            buf[2..].copy_from_slice(data);
+        })
+    }
+
+    /// Appends an AD structure of type `ad_type` with `data_len` bytes of data,
+    /// written by `write`.
+    fn add_with<F: FnOnce(&mut [u8])>(
+        &mut self,
+        ad_type: u8,
+        data_len: usize,
+        write: F,
+    ) -> ReturnCode {
+        let limit = ::core::cmp::min(self.buf.len(), MAX_ADV_DATA_LEN);
+        let end = self.len + 2 + data_len;
+        if end > limit {
+            return ReturnCode::ESIZE;
+        }
+        // The length byte covers the AD type and the data
+        self.buf[self.len] = (data_len + 1) as u8;
+        self.buf[self.len + 1] = ad_type;
+        write(&mut self.buf[self.len + 2..end]);
+        self.len = end;
+        ReturnCode::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ad_structures_are_length_prefixed() {
+        let mut buf = [0; 40];
+        let len = {
+            let mut adv_data = AdvData::new(&mut buf);
+            assert_eq!(adv_data.add_flags(0x06), ReturnCode::SUCCESS);
+            assert_eq!(adv_data.add_complete_local_name(b"tock"), ReturnCode::SUCCESS);
+            assert_eq!(adv_data.add_service_uuids16(&[0x180f, 0x181a]), ReturnCode::SUCCESS);
+            assert_eq!(adv_data.add_manufacturer_data(0x0059, &[0xaa]), ReturnCode::SUCCESS);
+            adv_data.len()
+        };
+        assert_eq!(
+            &buf[..len],
+            &[
+                2, 0x01, 0x06, //
+                5, 0x09, b't', b'o', b'c', b'k', //
+                5, 0x03, 0x0f, 0x18, 0x1a, 0x18, //
+                4, 0xff, 0x59, 0x00, 0xaa,
+            ]
+        );
+    }
+
+    #[test]
+    fn data_is_limited_to_advertising_payload() {
+        let mut buf = [0; 40];
+        let mut adv_data = AdvData::new(&mut buf);
+        assert_eq!(adv_data.add(0x09, &[0; 29]), ReturnCode::SUCCESS);
+        assert_eq!(adv_data.len(), MAX_ADV_DATA_LEN);
+        assert_eq!(adv_data.add_flags(0x06), ReturnCode::ESIZE);
+        assert_eq!(adv_data.len(), MAX_ADV_DATA_LEN);
+    }
+
+    #[test]
+    fn data_is_limited_to_buffer() {
+        let mut buf = [0; 8];
+        let mut adv_data = AdvData::new(&mut buf);
+        assert_eq!(adv_data.add(0x09, &[0; 7]), ReturnCode::ESIZE);
+        assert_eq!(adv_data.len(), 0);
+        assert_eq!(adv_data.add(0x09, &[0; 6]), ReturnCode::SUCCESS);
+    }
+
+    #[test]
+    fn structures_are_appended_after_existing_data() {
+        let mut buf = [2, 0x01, 0x06, 0, 0, 0];
+        {
+            let mut adv_data = AdvData::with_len(&mut buf, 3);
+            assert_eq!(adv_data.add(0x09, b"a"), ReturnCode::SUCCESS);
+            assert_eq!(adv_data.len(), 6);
+        }
+        assert_eq!(buf, [2, 0x01, 0x06, 2, 0x09, b'a']);
+    }
+}
@@ -0,0 +1,15 @@
+//! Interface for reading a chip's unique device identifier.
+//!
+//! Many chips are programmed in the factory with an identifier that is unique
+//! to each device, which can be used to derive network addresses or to
+//! identify a device to a server.
+
+pub trait DeviceId {
+    /// Returns the length of the identifier in bytes.
+    fn id_len(&self) -> usize;
+
+    /// Copies the identifier into `buf`, most significant byte first.
+    /// Returns the number of bytes copied, which is the smaller of
+    /// `buf.len()` and `id_len()`.
+    fn read_id(&self, buf: &mut [u8]) -> usize;
+}
@@ -1,5 +1,7 @@
 //! Interface for direct control of GPIO pins.
 
+use returncode::ReturnCode;
+
 /// Enum for configuring any pull-up or pull-down resistors on the GPIO pin.
 #[derive(Debug)]
 pub enum InputMode {
@@ -54,6 +56,231 @@ pub trait Pin {
 
     /// Disable the interrupt for the GPIO pin.
     fn disable_interrupt(&self);
+
+    /// The port this pin belongs to and the pin's bit within that port, if
+    /// the chip supports accessing all pins of a port at once through
+    /// `write_port` and `read_port`. Pins with the same port number share
+    /// the same port registers.
+    fn port_bit(&self) -> Option<(usize, usize)> {
+        None
+    }
+
+    /// Drive the pins of this pin's port selected by `mask` to the levels of
+    /// the corresponding bits of `value`, leaving the other pins unchanged.
+    /// All selected pins change in a single register write. Only called if
+    /// `port_bit` returns `Some`.
+    fn write_port(&self, _mask: u32, _value: u32) {}
+
+    /// Read the levels of all pins of this pin's port in a single register
+    /// read. Only called if `port_bit` returns `Some`.
+    fn read_port(&self) -> u32 {
+        0
+    }
+}
+
+/// Interface for GPIO pins that deliver their interrupts to a `Client`.
+pub trait InterruptPin: Pin {
+    /// Set the client that `enable_interrupt` interrupts are delivered to.
+    fn set_client(&self, client: &'static Client);
+}
+
+/// A fixed set of pins that are configured, written, and read together, such
+/// as the pins a board exposes through the GPIO capsule.
+///
+/// Pin `i` of the group is selected by bit `i` of a mask, and its level is
+/// bit `i` of a value. Selected pins that share a hardware port (see
+/// `Pin::port_bit`) are written in a single register write, so they change
+/// at the same instant, and read in a single register read. Other pins are
+/// accessed one after another. One client can receive the interrupts of every
+/// pin in the group through `set_client`.
+pub struct PinGroup<'a, P: Pin + 'a> {
+    pins: &'a [&'a P],
+}
+
+impl<P: Pin> PinGroup<'a, P> {
+    pub fn new(pins: &'a [&'a P]) -> PinGroup<'a, P> {
+        PinGroup { pins: pins }
+    }
+
+    /// Number of pins in the group.
+    pub fn len(&self) -> usize {
+        self.pins.len()
+    }
+
+    /// Whether `mask` only selects pins in the group.
+    fn valid_mask(&self, mask: usize) -> bool {
+        (mask as u64) >> self.pins.len() == 0
+    }
+
+    /// Configure the selected pins as inputs. Returns `EINVAL` if `mask`
+    /// selects pins outside the group.
+    pub fn make_input(&self, mask: usize) -> ReturnCode {
+        if !self.valid_mask(mask) {
+            return ReturnCode::EINVAL;
+        }
+        for (i, pin) in self.pins.iter().enumerate() {
+            if mask & (1 << i) != 0 {
+                pin.make_input();
+            }
+        }
+        ReturnCode::SUCCESS
+    }
+
+    /// Configure the selected pins as outputs. Returns `EINVAL` if `mask`
+    /// selects pins outside the group.
+    pub fn make_output(&self, mask: usize) -> ReturnCode {
+        if !self.valid_mask(mask) {
+            return ReturnCode::EINVAL;
+        }
+        for (i, pin) in self.pins.iter().enumerate() {
+            if mask & (1 << i) != 0 {
+                pin.make_output();
+            }
+        }
+        ReturnCode::SUCCESS
+    }
+
+    /// Drive the selected pins to the levels of the corresponding bits of
+    /// `values`. Returns `EINVAL` if `mask` selects pins outside the group.
+    pub fn write(&self, mask: usize, values: usize) -> ReturnCode {
+        if !self.valid_mask(mask) {
+            return ReturnCode::EINVAL;
+        }
+        let pins = self.pins;
+        let mut done = 0;
+        for (i, pin) in pins.iter().enumerate() {
+            if mask & !done & (1 << i) == 0 {
+                continue;
+            }
+            match pin.port_bit() {
+                Some((port, _)) => {
+                    let (mut port_mask, mut port_value) = (0u32, 0u32);
+                    for (j, other) in pins.iter().enumerate().skip(i) {
+                        match other.port_bit() {
+                            Some((p, bit)) if p == port && mask & (1 << j) != 0 => {
+                                port_mask |= 1 << bit;
+                                if values & (1 << j) != 0 {
+                                    port_value |= 1 << bit;
+                                }
+                                done |= 1 << j;
+                            }
+                            _ => {}
+                        }
+                    }
+                    pin.write_port(port_mask, port_value);
+                }
+                None => {
+                    if values & (1 << i) != 0 {
+                        pin.set();
+                    } else {
+                        pin.clear();
+                    }
+                    done |= 1 << i;
+                }
+            }
+        }
+        ReturnCode::SUCCESS
+    }
+
+    /// Read the levels of the selected pins into the corresponding bits of
+    /// the returned `SuccessWithValue`, reading each port once. Returns
+    /// `EINVAL` if `mask` selects pins outside the group.
+    pub fn read(&self, mask: usize) -> ReturnCode {
+        if !self.valid_mask(mask) {
+            return ReturnCode::EINVAL;
+        }
+        let pins = self.pins;
+        let mut done = 0;
+        let mut values = 0;
+        for (i, pin) in pins.iter().enumerate() {
+            if mask & !done & (1 << i) == 0 {
+                continue;
+            }
+            match pin.port_bit() {
+                Some((port, _)) => {
+                    let levels = pin.read_port();
+                    for (j, other) in pins.iter().enumerate().skip(i) {
+                        match other.port_bit() {
+                            Some((p, bit)) if p == port && mask & (1 << j) != 0 => {
+                                if levels & (1 << bit) != 0 {
+                                    values |= 1 << j;
+                                }
+                                done |= 1 << j;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                None => {
+                    if pin.read() {
+                        values |= 1 << i;
+                    }
+                    done |= 1 << i;
+                }
+            }
+        }
+        ReturnCode::SuccessWithValue { value: values }
+    }
+}
+
+impl<P: InterruptPin> PinGroup<'a, P> {
+    /// Set `client` as the interrupt client of every pin in the group. Pass
+    /// a pin's index in the group as the `identifier` to `enable_interrupt`
+    /// to learn which pin fired.
+    pub fn set_client(&self, client: &'static Client) {
+        for pin in self.pins.iter() {
+            pin.set_client(client);
+        }
+    }
+}
+
+impl<P: Pin> AsRef<[&'a P]> for PinGroup<'a, P> {
+    fn as_ref(&self) -> &[&'a P] {
+        self.pins
+    }
+}
+
+/// Interface for counting edges on a GPIO pin in hardware, for signals that
+/// change too quickly to take an interrupt on every edge, such as tachometers
+/// and encoders.
+pub trait EdgeCounter {
+    /// Start counting edges of the given kind. Counting continues from the
+    /// current count. Returns `EBUSY` if already counting, and `ENOMEM` if the
+    /// hardware resources needed to count are in use.
+    fn start(&self, mode: InterruptMode) -> ReturnCode;
+
+    /// Stop counting. The count is kept until it is reset.
+    fn stop(&self);
+
+    /// Get the number of edges counted since the count was last reset.
+    fn count(&self) -> u32;
+
+    /// Reset the count to zero. If counting, counting continues.
+    fn reset(&self);
+}
+
+/// Interface for timestamping the edges on a GPIO pin in hardware, so that a
+/// timestamp is the time of the edge itself rather than the time the kernel
+/// handled its interrupt.
+pub trait EdgeCapture {
+    fn set_client(&self, client: &'static EdgeCaptureClient);
+
+    /// The frequency of the timestamps, in Hz.
+    fn frequency(&self) -> u32;
+
+    /// Start timestamping rising and falling edges. Returns `EBUSY` if
+    /// already started, and `ENOMEM` if the hardware resources needed are in
+    /// use.
+    fn start(&self) -> ReturnCode;
+
+    /// Stop timestamping edges.
+    fn stop(&self);
+}
+
+/// The client of an `EdgeCapture`.
+pub trait EdgeCaptureClient {
+    /// Called for each edge with its direction and its timestamp.
+    fn captured(&self, rising: bool, timestamp: u32);
 }
 
 /// Interface for users of synchronous GPIO. In order
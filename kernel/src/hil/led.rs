@@ -1,5 +1,9 @@
 //! Interface for LEDs that abstract away polarity and pin.
 //!
+//! `LedHigh` and `LedLow` implement the interface for LEDs driven directly by
+//! a GPIO pin. LEDs driven some other way, such as by a PWM channel or a GPIO
+//! expander, can implement it too, and used with the same LED capsule.
+//!
 //!  Author: Philip Levis <pal@cs.stanford.edu>
 //!  Date: July 31, 2015
 //!
@@ -7,49 +11,49 @@
 use hil::gpio;
 
 pub trait Led {
-    fn init(&mut self);
-    fn on(&mut self);
-    fn off(&mut self);
-    fn toggle(&mut self);
+    fn init(&self);
+    fn on(&self);
+    fn off(&self);
+    fn toggle(&self);
     fn read(&self) -> bool;
 }
 
 /// For LEDs in which on is when GPIO is high.
 pub struct LedHigh<'a> {
-    pub pin: &'a mut gpio::Pin,
+    pub pin: &'a gpio::Pin,
 }
 
 /// For LEDs in which on is when GPIO is low.
 pub struct LedLow<'a> {
-    pub pin: &'a mut gpio::Pin,
+    pub pin: &'a gpio::Pin,
 }
 
 impl LedHigh<'a> {
-    pub fn new(p: &'a mut gpio::Pin) -> LedHigh {
+    pub fn new(p: &'a gpio::Pin) -> LedHigh {
         LedHigh { pin: p }
     }
 }
 
 impl LedLow<'a> {
-    pub fn new(p: &'a mut gpio::Pin) -> LedLow {
+    pub fn new(p: &'a gpio::Pin) -> LedLow {
         LedLow { pin: p }
     }
 }
 
 impl Led for LedHigh<'a> {
-    fn init(&mut self) {
+    fn init(&self) {
         self.pin.make_output();
     }
 
-    fn on(&mut self) {
+    fn on(&self) {
         self.pin.set();
     }
 
-    fn off(&mut self) {
+    fn off(&self) {
         self.pin.clear();
     }
 
-    fn toggle(&mut self) {
+    fn toggle(&self) {
         self.pin.toggle();
     }
 
@@ -59,19 +63,19 @@ impl Led for LedHigh<'a> {
 }
 
 impl Led for LedLow<'a> {
-    fn init(&mut self) {
+    fn init(&self) {
         self.pin.make_output();
     }
 
-    fn on(&mut self) {
+    fn on(&self) {
         self.pin.clear();
     }
 
-    fn off(&mut self) {
+    fn off(&self) {
         self.pin.set();
     }
 
-    fn toggle(&mut self) {
+    fn toggle(&self) {
         self.pin.toggle();
     }
 
@@ -5,6 +5,7 @@ pub mod analog_comparator;
 pub mod ble_advertising;
 pub mod crc;
 pub mod dac;
+pub mod device_id;
 pub mod entropy;
 pub mod flash;
 pub mod gpio;
@@ -12,6 +13,7 @@ pub mod gpio_async;
 pub mod i2c;
 pub mod led;
 pub mod nonvolatile_storage;
+pub mod power_manager;
 pub mod radio;
 pub mod rng;
 pub mod sensors;
@@ -0,0 +1,106 @@
+//! Interface for choosing how deeply a chip may sleep.
+//!
+//! Peripherals that need to stay clocked while the core sleeps (for example a
+//! UART in the middle of a transfer) implement `PowerClient` and register
+//! with the chip's `PowerManager`. When the kernel has no work to do, the chip
+//! asks its power manager for the deepest sleep mode every registered
+//! peripheral can tolerate and enters that mode.
+//!
+//! Kernel work that does not raise an interrupt, such as a pending deferred
+//! call, would not wake the core again. While such work is pending the power
+//! manager returns `None` and the chip does not sleep at all.
+//!
+//! Sleep modes are ordered from the lightest to the deepest, so the mode that
+//! is safe to enter is the minimum of all peripheral requirements.
+//!
+//! # Example
+//!
+//! ```
+//! use std::cell::Cell;
+//! use kernel::hil::power_manager::{PowerClient, PowerManager};
+//! use kernel::ReturnCode;
+//!
+//! #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+//! enum SleepMode {
+//!     Sleep,
+//!     DeepSleep,
+//! }
+//!
+//! struct Peripheral {
+//!     busy: Cell<bool>,
+//! }
+//!
+//! impl PowerClient<SleepMode> for Peripheral {
+//!     fn sleep_requirement(&self) -> Option<SleepMode> {
+//!         if self.busy.get() {
+//!             Some(SleepMode::Sleep)
+//!         } else {
+//!             None
+//!         }
+//!     }
+//! }
+//!
+//! struct Manager<'a> {
+//!     clients: [Cell<Option<&'a PowerClient<SleepMode>>>; 2],
+//! }
+//!
+//! impl<'a> PowerManager<'a> for Manager<'a> {
+//!     type SleepMode = SleepMode;
+//!
+//!     fn register(&self, client: &'a PowerClient<SleepMode>) -> ReturnCode {
+//!         for slot in self.clients.iter() {
+//!             if slot.get().is_none() {
+//!                 slot.set(Some(client));
+//!                 return ReturnCode::SUCCESS;
+//!             }
+//!         }
+//!         ReturnCode::ENOMEM
+//!     }
+//!
+//!     fn sleep_mode(&self) -> Option<SleepMode> {
+//!         let mode = self
+//!             .clients
+//!             .iter()
+//!             .filter_map(|slot| slot.get().and_then(|c| c.sleep_requirement()))
+//!             .min()
+//!             .unwrap_or(SleepMode::DeepSleep);
+//!         Some(mode)
+//!     }
+//! }
+//!
+//! let uart = Peripheral { busy: Cell::new(false) };
+//! let spi = Peripheral { busy: Cell::new(false) };
+//! let manager = Manager { clients: [Cell::new(None), Cell::new(None)] };
+//! assert_eq!(manager.register(&uart), ReturnCode::SUCCESS);
+//! assert_eq!(manager.register(&spi), ReturnCode::SUCCESS);
+//!
+//! assert_eq!(manager.sleep_mode(), Some(SleepMode::DeepSleep));
+//! spi.busy.set(true);
+//! assert_eq!(manager.sleep_mode(), Some(SleepMode::Sleep));
+//! spi.busy.set(false);
+//! assert_eq!(manager.sleep_mode(), Some(SleepMode::DeepSleep));
+//! ```
+
+use returncode::ReturnCode;
+
+/// Implemented by peripherals that constrain how deeply the chip may sleep.
+pub trait PowerClient<M: Copy> {
+    /// Return the deepest sleep mode this peripheral can currently tolerate,
+    /// or `None` if it places no constraint on sleep (e.g. it is idle).
+    fn sleep_requirement(&self) -> Option<M>;
+}
+
+/// Implemented by chips to pick a sleep mode from peripheral requirements.
+pub trait PowerManager<'a> {
+    /// Sleep modes supported by the chip, ordered from lightest to deepest.
+    type SleepMode: Copy + Ord;
+
+    /// Register a peripheral whose requirements must be honored before
+    /// sleeping. Returns `ENOMEM` if no more peripherals can be registered.
+    fn register(&self, client: &'a PowerClient<Self::SleepMode>) -> ReturnCode;
+
+    /// Return the deepest sleep mode that all registered peripherals can
+    /// currently tolerate, or `None` if the kernel has pending work that
+    /// would not wake the chip from any sleep mode.
+    fn sleep_mode(&self) -> Option<Self::SleepMode>;
+}
@@ -13,10 +13,16 @@ pub trait TxClient {
 }
 
 pub trait RxClient {
+    /// Called when a frame has been received into `buf`. `frame_len` is the
+    /// length of the frame starting at `PSDU_OFFSET`. `lqi` is the link
+    /// quality indicator reported by the radio (0-255, higher is better) and
+    /// `rssi` is the received signal strength in dBm.
     fn receive(
         &self,
         buf: &'static mut [u8],
         frame_len: usize,
+        lqi: u8,
+        rssi: i8,
         crc_valid: bool,
         result: ReturnCode,
     );
@@ -30,6 +36,13 @@ pub trait PowerClient {
     fn changed(&self, on: bool);
 }
 
+pub trait EnergyDetectClient {
+    /// Called when an energy detect measurement finishes. `rssi` is the
+    /// measured channel energy in dBm and is only valid if `result` is
+    /// `SUCCESS`.
+    fn energy_detect_done(&self, rssi: i8, result: ReturnCode);
+}
+
 /// These constants are used for interacting with the SPI buffer, which contains
 /// a 1-byte SPI command, a 1-byte PHY header, and then the 802.15.4 frame. In
 /// theory, the number of extra bytes in front of the frame can depend on the
@@ -43,13 +56,16 @@ pub trait PowerClient {
 /// - There are no IEs
 ///
 /// ```text
-/// +---------+-----+-----+-------------+-----+
-/// | SPI com | PHR | MHR | MAC payload | MFR |
-/// +---------+-----+-----+-------------+-----+
-/// \______ Static buffer rx/txed to SPI _____/
+/// +---------+-----+-----+-------------+-----+-----+
+/// | SPI com | PHR | MHR | MAC payload | MFR | LQI |
+/// +---------+-----+-----+-------------+-----+-----+
+/// \_________ Static buffer rx/txed to SPI _________/
 ///                 \__ PSDU / frame length __/
 /// \___ 2 bytes ___/
 /// ```
+///
+/// The LQI byte is only present in received frames, and leaves room for
+/// radios that append it after the frame.
 
 pub const MIN_MHR_SIZE: usize = 9;
 pub const MFR_SIZE: usize = 2;
@@ -58,9 +74,105 @@ pub const MIN_FRAME_SIZE: usize = MIN_MHR_SIZE + MFR_SIZE;
 pub const MAX_FRAME_SIZE: usize = MAX_MTU;
 
 pub const PSDU_OFFSET: usize = 2;
-pub const MAX_BUF_SIZE: usize = PSDU_OFFSET + MAX_MTU;
+pub const LQI_SIZE: usize = 1;
+pub const MAX_BUF_SIZE: usize = PSDU_OFFSET + MAX_MTU + LQI_SIZE;
 pub const MIN_PAYLOAD_OFFSET: usize = PSDU_OFFSET + MIN_MHR_SIZE;
 
+/// IEEE 802.15.4-2015, 6.2.5.1: unslotted CSMA-CA parameters
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct CsmaParams {
+    /// The minimum value of the backoff exponent (macMinBe)
+    pub min_be: u8,
+    /// The maximum value of the backoff exponent (macMaxBe)
+    pub max_be: u8,
+    /// The number of backoffs before declaring a channel access failure
+    /// (macMaxCsmaBackoffs)
+    pub max_backoffs: u8,
+}
+
+/// The smallest macMaxBe allowed by IEEE 802.15.4-2015, 8.4.2
+pub const CSMA_MIN_MAX_BE: u8 = 3;
+/// The largest backoff exponent allowed by IEEE 802.15.4-2015, 8.4.2
+pub const CSMA_MAX_BE: u8 = 8;
+/// The largest number of backoffs allowed by IEEE 802.15.4-2015, 8.4.2
+pub const CSMA_MAX_BACKOFFS: u8 = 5;
+
+impl CsmaParams {
+    pub fn is_valid(&self) -> bool {
+        self.min_be <= self.max_be
+            && self.max_be >= CSMA_MIN_MAX_BE
+            && self.max_be <= CSMA_MAX_BE
+            && self.max_backoffs <= CSMA_MAX_BACKOFFS
+    }
+}
+
+impl Default for CsmaParams {
+    /// The default values of IEEE 802.15.4-2015, 8.4.2
+    fn default() -> CsmaParams {
+        CsmaParams {
+            min_be: 3,
+            max_be: 5,
+            max_backoffs: 4,
+        }
+    }
+}
+
+/// IEEE 802.15.4-2015, 10.2.8: the clear channel assessment (CCA) mode, which
+/// decides when the medium is considered busy during CSMA-CA
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CcaMode {
+    /// Busy if the received energy is above the threshold (mode 1)
+    EnergyDetect,
+    /// Busy if an 802.15.4 signal is detected (mode 2)
+    CarrierSense,
+    /// Busy if either of the above is (mode 3, logical OR)
+    CarrierSenseOrEnergy,
+    /// Busy if both of the above are (mode 3, logical AND)
+    CarrierSenseAndEnergy,
+}
+
+impl Default for CcaMode {
+    fn default() -> CcaMode {
+        CcaMode::EnergyDetect
+    }
+}
+
+/// Bitmask of the channels of the 2450 MHz O-QPSK PHY (channels 11-26), with
+/// bit `n` set if channel `n` is included.
+pub const CHANNELS_2450MHZ: u32 = 0x07ff_f800;
+
+/// The channels and transmit power a radio may use in the regulatory region
+/// the board is deployed in. Boards set these with `set_regulatory_limits`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RegulatoryLimits {
+    /// Bitmask of the allowed channels, with bit `n` set if channel `n` may be
+    /// used
+    pub channels: u32,
+    /// The highest allowed transmit power, in dBm
+    pub max_tx_power: i8,
+}
+
+impl RegulatoryLimits {
+    pub fn allows_channel(&self, chan: u8) -> bool {
+        chan < 32 && self.channels & (1 << chan) != 0
+    }
+
+    pub fn allows_tx_power(&self, power: i8) -> bool {
+        power <= self.max_tx_power
+    }
+}
+
+impl Default for RegulatoryLimits {
+    /// All 2450 MHz channels, with the transmit power limited only by the
+    /// radio
+    fn default() -> RegulatoryLimits {
+        RegulatoryLimits {
+            channels: CHANNELS_2450MHZ,
+            max_tx_power: i8::max_value(),
+        }
+    }
+}
+
 pub trait Radio: RadioConfig + RadioData {}
 
 /// Configure the 802.15.4 radio.
@@ -92,12 +204,41 @@ pub trait RadioConfig {
     fn get_pan(&self) -> u16; //........... The 16-bit PAN ID
     fn get_tx_power(&self) -> i8; //....... The transmit power, in dBm
     fn get_channel(&self) -> u8; // ....... The 802.15.4 channel
+    fn get_csma_params(&self) -> CsmaParams; // CSMA-CA backoff parameters
+    fn get_cca_mode(&self) -> CcaMode; // ...... The CCA mode
 
     fn set_address(&self, addr: u16);
     fn set_address_long(&self, addr: [u8; 8]);
     fn set_pan(&self, id: u16);
+    /// Returns `EINVAL` if `power` is outside the range supported by the
+    /// radio or above the regulatory limit.
     fn set_tx_power(&self, power: i8) -> ReturnCode;
+    /// Returns `EINVAL` if `chan` is not supported by the radio or not
+    /// allowed by the regulatory limits.
     fn set_channel(&self, chan: u8) -> ReturnCode;
+    /// Restrict the channels and transmit power to those allowed in the
+    /// board's regulatory region. The transmit power is lowered to the new
+    /// maximum if necessary. Returns `EINVAL` if the current channel is not
+    /// allowed by `limits` or the radio can not transmit below the maximum
+    /// power, in which case the limits are not changed.
+    fn set_regulatory_limits(&self, limits: RegulatoryLimits) -> ReturnCode;
+    /// Set the CSMA-CA backoff parameters used for transmissions that
+    /// perform CCA. Returns `EINVAL` if the parameters are out of range.
+    fn set_csma_params(&self, params: CsmaParams) -> ReturnCode;
+    /// Set the CCA mode used for transmissions that perform CCA. Defaults to
+    /// `CcaMode::EnergyDetect`. Returns `EINVAL` if the radio does not support
+    /// `mode`.
+    fn set_cca_mode(&self, mode: CcaMode) -> ReturnCode;
+}
+
+/// Measure the energy on an 802.15.4 channel, e.g. for CCA tuning or an energy
+/// detect scan.
+pub trait EnergyDetect {
+    /// Sample the received signal strength on `channel` (11-26). The result is
+    /// reported through `EnergyDetectClient::energy_detect_done`. Returns
+    /// `EINVAL` for an invalid channel and `EBUSY` if the radio is in use.
+    fn energy_detect(&self, channel: u8) -> ReturnCode;
+    fn set_energy_detect_client(&self, client: &'static EnergyDetectClient);
 }
 
 pub trait RadioData {
@@ -105,9 +246,12 @@ pub trait RadioData {
     fn set_receive_client(&self, client: &'static RxClient, receive_buffer: &'static mut [u8]);
     fn set_receive_buffer(&self, receive_buffer: &'static mut [u8]);
 
+    /// Transmit a frame. If `cca` is false, the frame is sent immediately
+    /// without performing CSMA-CA.
     fn transmit(
         &self,
         spi_buf: &'static mut [u8],
         frame_len: usize,
+        cca: bool,
     ) -> (ReturnCode, Option<&'static mut [u8]>);
 }
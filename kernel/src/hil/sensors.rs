@@ -31,6 +31,20 @@ pub trait HumidityClient {
     fn callback(&self, value: usize);
 }
 
+/// A basic interface for a barometric pressure sensor
+pub trait PressureDriver {
+    fn set_client(&self, client: &'static PressureClient);
+    fn read_pressure(&self) -> ReturnCode;
+}
+
+/// Client for receiving pressure readings.
+pub trait PressureClient {
+    /// Called when a pressure reading has completed.
+    ///
+    /// - `value`: the most recently read pressure in pascals.
+    fn callback(&self, value: usize);
+}
+
 /// A basic interface for an ambient light sensor.
 pub trait AmbientLight {
     /// Set the client to be notified when the capsule has data ready or has
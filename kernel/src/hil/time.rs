@@ -81,6 +81,45 @@ pub trait Alarm: Time {
 
     /// Returns the value set in [`set_alarm`](#tymethod.set_alarm)
     fn get_alarm(&self) -> u32;
+
+    /// Returns the largest value [`now`](#tymethod.now) can return, after
+    /// which the counter wraps to zero. Alarms whose counter is narrower
+    /// than 32 bits must override this.
+    fn max_tics(&self) -> u32 {
+        u32::max_value()
+    }
+
+    /// Returns the number of tics until the alarm fires, or `None` if no alarm
+    /// is armed. The difference is computed modulo the counter width given by
+    /// [`max_tics`](#method.max_tics), so it is correct across a counter wrap
+    /// as long as the alarm was set less than a full counter period ahead. An
+    /// alarm whose time has passed but that has not been handled yet appears
+    /// to be almost a full period away.
+    fn remaining_tics(&self) -> Option<u32> {
+        if self.is_armed() {
+            Some(self.get_alarm().wrapping_sub(self.now()) & self.max_tics())
+        } else {
+            None
+        }
+    }
+}
+
+/// A counter with several independent hardware compare channels.
+///
+/// Each compare channel is exposed as its own [`Alarm`](trait.Alarm.html)
+/// sharing the counter. A board can hand a channel directly to a client that
+/// needs precise or frequent alarms, instead of having it share a single
+/// compare register with other clients through a software multiplexer.
+pub trait MultiAlarm {
+    /// The alarm type of a single compare channel.
+    type Channel: Alarm;
+
+    /// Returns the number of hardware compare channels.
+    fn num_compares(&self) -> usize;
+
+    /// Returns compare channel `index`, or `None` if `index` is not less than
+    /// [`num_compares`](#tymethod.num_compares).
+    fn compare(&self, index: usize) -> Option<&Self::Channel>;
 }
 
 /// A client of an implementor of the [`Alarm`](trait.Alarm.html) trait.
@@ -92,9 +131,99 @@ pub trait Client {
 
 /// The `Timer` trait models a timer that can notify when a particular interval
 /// has elapsed.
+///
+/// Implementors signal the interval elapsing through the
+/// [`Client`](trait.Client.html) trait. A timer is cancelled with
+/// [`Time#disable`](trait.Time.html#tymethod.disable).
 pub trait Timer: Time {
     /// Sets a one-shot timer to fire in `interval` clock-tics.
     fn oneshot(&self, interval: u32);
-    /// Sets repeating timer to fire every `interval` clock-tics.
+    /// Sets repeating timer to fire every `interval` clock-tics. The n-th
+    /// firing is due `n * interval` clock-tics after this call, regardless of
+    /// how late earlier firings were handled.
     fn repeat(&self, interval: u32);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    /// An alarm on a 24-bit counter, like the nRF5x RTC.
+    struct Alarm24 {
+        now: Cell<u32>,
+        alarm: Cell<u32>,
+        armed: Cell<bool>,
+    }
+
+    impl Alarm24 {
+        fn new(now: u32) -> Alarm24 {
+            Alarm24 {
+                now: Cell::new(now),
+                alarm: Cell::new(0),
+                armed: Cell::new(false),
+            }
+        }
+    }
+
+    impl Time for Alarm24 {
+        type Frequency = Freq32KHz;
+
+        fn disable(&self) {
+            self.armed.set(false);
+        }
+
+        fn is_armed(&self) -> bool {
+            self.armed.get()
+        }
+    }
+
+    impl Alarm for Alarm24 {
+        fn now(&self) -> u32 {
+            self.now.get()
+        }
+
+        fn set_alarm(&self, tics: u32) {
+            self.alarm.set(tics & self.max_tics());
+            self.armed.set(true);
+        }
+
+        fn get_alarm(&self) -> u32 {
+            self.alarm.get()
+        }
+
+        fn max_tics(&self) -> u32 {
+            0xFFFFFF
+        }
+    }
+
+    #[test]
+    fn remaining_tics_is_none_when_disarmed() {
+        let alarm = Alarm24::new(100);
+        assert_eq!(alarm.remaining_tics(), None);
+        alarm.set_alarm(200);
+        alarm.disable();
+        assert_eq!(alarm.remaining_tics(), None);
+    }
+
+    #[test]
+    fn remaining_tics_counts_down() {
+        let alarm = Alarm24::new(100);
+        alarm.set_alarm(1100);
+        assert_eq!(alarm.remaining_tics(), Some(1000));
+        alarm.now.set(1000);
+        assert_eq!(alarm.remaining_tics(), Some(100));
+    }
+
+    #[test]
+    fn remaining_tics_handles_counter_wraparound() {
+        // Set 0x20 tics before the 24-bit counter wraps, to fire 0x10 tics
+        // after it has wrapped.
+        let alarm = Alarm24::new(0xFFFFE0);
+        alarm.set_alarm(0xFFFFE0 + 0x30);
+        assert_eq!(alarm.get_alarm(), 0x10);
+        assert_eq!(alarm.remaining_tics(), Some(0x30));
+        alarm.now.set(0x8);
+        assert_eq!(alarm.remaining_tics(), Some(0x8));
+    }
+}
@@ -35,6 +35,10 @@ pub enum Error {
     /// Overrun error during receive
     OverrunError,
 
+    /// Break condition, the line held low for longer than a frame, during
+    /// receive
+    BreakError,
+
     /// Repeat call of transmit or receive before initial command complete
     RepeatCallError,
 
@@ -101,6 +105,22 @@ pub trait UARTReceiveAdvanced: UART {
     fn receive_automatic(&self, rx_buffer: &'static mut [u8], interbyte_timeout: u8);
 }
 
+/// Trait for UARTs whose baud rate can be changed while they are in use, e.g.
+/// for auto-baud or for protocols that switch rates after a handshake.
+pub trait UARTBaudRate: UART {
+    /// Change the baud rate, leaving the rest of the configuration as it is.
+    ///
+    /// Returns SUCCESS, or
+    ///
+    /// - EBUSY: A transmission is in progress. Changing the rate partway
+    ///          through would corrupt the bytes not yet sent, so the caller
+    ///          should retry once it receives `transmit_complete`.
+    /// - EINVAL: The UART does not support `baud_rate`.
+    ///
+    /// A byte that is being received while the rate changes may be corrupted.
+    fn set_baud_rate(&self, baud_rate: u32) -> ReturnCode;
+}
+
 /// Implement Client to receive callbacks from UART.
 pub trait Client {
     /// UART transmit complete.
@@ -80,6 +80,28 @@ impl IPC {
                     }).unwrap_or(());
             }).unwrap_or(());
     }
+
+    /// Find the `mpu_regions` slot of `appid` that covers the buffer the
+    /// process with `target_id` shares with it.
+    fn shared_region(&self, target_id: usize, appid: AppId) -> ReturnCode {
+        let kernel = self.data.kernel;
+        kernel.process_map_or(ReturnCode::EINVAL, target_id - 1, |target| {
+            self.data
+                .enter(target.appid(), |otherdata, _| {
+                    otherdata
+                        .shared_memory
+                        .get(appid.idx())
+                        .and_then(|smem| smem.as_ref())
+                        .and_then(|slice| {
+                            kernel.process_map_or(None, appid.idx(), |process| {
+                                process.mpu_region_index(slice.ptr(), slice.len())
+                            })
+                        }).map_or(ReturnCode::EINVAL, |index| ReturnCode::SuccessWithValue {
+                            value: index,
+                        })
+                }).unwrap_or_else(|err| err.into())
+        })
+    }
 }
 
 impl Driver for IPC {
@@ -100,12 +122,16 @@ impl Driver for IPC {
             // application name stored in the TBF header of the application.
             // The callback that is passed to subscribe is called when another
             // process notifies the server process.
+            //
+            // The callback arguments are the notifying process's ID, and the
+            // length and address of the buffer it shares with this process
+            // (or 0 if it shares none). The same applies to client callbacks.
             0 => self
                 .data
                 .enter(app_id, |data, _| {
                     data.callback = callback;
                     ReturnCode::SUCCESS
-                }).unwrap_or(ReturnCode::EBUSY),
+                }).unwrap_or_else(|err| err.into()),
 
             // subscribe(>=1)
             //
@@ -122,7 +148,7 @@ impl Driver for IPC {
                         .enter(app_id, |data, _| {
                             data.client_callbacks[svc_id - 1] = callback;
                             ReturnCode::SUCCESS
-                        }).unwrap_or(ReturnCode::EBUSY)
+                        }).unwrap_or_else(|err| err.into())
                 }
             }
         }
@@ -134,6 +160,14 @@ impl Driver for IPC {
     /// In either case, the target_id is the same number as provided in a notify
     /// callback or as returned by allow.
     ///
+    /// Setting client_or_svc to 2 instead returns the region through which
+    /// the buffer that target_id shares with this process is accessible, so
+    /// a service with many clients can tell which buffer a notification
+    /// refers to. The region is this process's slot in the kernel's
+    /// `mpu_regions` table, not the hardware MPU region number, and is
+    /// allocated when the buffer is first notified. Returns EINVAL if there
+    /// is no such buffer or it has not been exposed yet.
+    ///
     /// Returns EINVAL if the other process doesn't exist.
     fn command(
         &self,
@@ -142,6 +176,10 @@ impl Driver for IPC {
         _: usize,
         appid: AppId,
     ) -> ReturnCode {
+        if client_or_svc == 2 {
+            return self.shared_region(target_id, appid);
+        }
+
         let cb_type = if client_or_svc == 0 {
             process::IPCType::Service
         } else {
@@ -165,13 +203,17 @@ impl Driver for IPC {
     /// If allow is called with target_id == 0, it is an IPC service discover
     /// call. The contents of the slice should be the string name of the IPC
     /// service. If this mechanism can find that service, allow will return
-    /// an ID that can be used to notify that service. Otherwise an error will
-    /// be returned.
+    /// an ID that can be used to notify that service. If no process has that
+    /// package name, `ENODEVICE` is returned.
     ///
     /// If allow is called with target_id >= 1, it is a share command where the
     /// application is explicitly sharing a slice with an IPC service (as
     /// specified by the target_id). allow() simply allows both processes to
-    /// access the buffer, it does not signal the service.
+    /// access the buffer, it does not signal the service. Because the buffer
+    /// is exposed to the service with an MPU region, it must lie within the
+    /// sharing process's memory and meet the MPU's size and alignment
+    /// requirements (e.g. a power of two size, aligned to its size, on
+    /// Cortex-M). Otherwise `EINVAL` is returned.
     fn allow(
         &self,
         appid: AppId,
@@ -197,12 +239,16 @@ impl Driver for IPC {
                     if ret != ReturnCode::FAIL {
                         return ret;
                     }
+                    return ReturnCode::ENODEVICE; /* No service with that name */
                 }
                 None => {}
             }
 
             return ReturnCode::EINVAL; /* AppSlice must have non-zero length */
         }
+        if slice.as_ref().map_or(false, |slice| !slice.is_shareable()) {
+            return ReturnCode::EINVAL; /* Buffer cannot be exposed with the MPU */
+        }
         return self
             .data
             .enter(appid, |data, _| {
@@ -212,6 +258,76 @@ impl Driver for IPC {
                         *smem = slice;
                         ReturnCode::SUCCESS
                     }).unwrap_or(ReturnCode::EINVAL) /* Target process does not exist */
-            }).unwrap_or(ReturnCode::EBUSY);
+            }).unwrap_or_else(|err| err.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::vec::Vec;
+    use super::*;
+    use process::{FaultResponse, ProcessType};
+    use test_util::{self, TestApp, TestCapability};
+
+    /// Loads apps named `names`, with room for grants, and sets up the IPC
+    /// driver the way a board does.
+    fn services(names: &[&'static str]) -> (IPC, &'static [Option<&'static ProcessType>]) {
+        let _debug = test_util::capture_debug();
+        let apps: Vec<_> = names
+            .iter()
+            .map(|name| TestApp::new(name).minimum_ram_size(4 * 1024))
+            .collect();
+        let (kernel, processes) = test_util::kernel(apps.len());
+        let processes_ptr = processes as *const [Option<&'static ProcessType>];
+        let ipc = IPC::new(kernel, &TestCapability);
+        test_util::load(kernel, processes, &apps, 64 * 1024, FaultResponse::Panic);
+        (ipc, unsafe { &*processes_ptr })
+    }
+
+    /// Allows `len` bytes of `process`'s memory starting at `offset`.
+    fn slice(process: &ProcessType, offset: usize, len: usize) -> AppSlice<Shared, u8> {
+        let start = process.mem_start().wrapping_offset(offset as isize);
+        process.allow(start, len).unwrap().unwrap()
+    }
+
+    #[test]
+    fn share_requires_mpu_alignable_buffer() {
+        let (ipc, processes) = services(&["client", "service"]);
+        let client = processes[0].unwrap();
+
+        // Offset of the first 256 byte aligned address in the client's memory.
+        let aligned = 256 - client.mem_start() as usize % 256;
+        let share = |offset, len| ipc.allow(client.appid(), 2, Some(slice(client, offset, len)));
+
+        assert_eq!(share(aligned, 256), ReturnCode::SUCCESS);
+        assert_eq!(share(aligned + 8, 256), ReturnCode::EINVAL);
+        assert_eq!(share(aligned, 100), ReturnCode::EINVAL);
+        // Unsharing is always allowed.
+        assert_eq!(ipc.allow(client.appid(), 2, None), ReturnCode::SUCCESS);
+    }
+
+    /// Writes `name` into `process`'s memory and allows it, as an app does to
+    /// discover a service.
+    fn name(process: &ProcessType, name: &str) -> AppSlice<Shared, u8> {
+        let mut name_slice = slice(process, 0, name.len());
+        name_slice.as_mut().copy_from_slice(name.as_bytes());
+        name_slice
+    }
+
+    #[test]
+    fn discover_services_by_name() {
+        let (ipc, processes) = services(&["client", "alpha", "beta"]);
+        let client = processes[0].unwrap().appid();
+        let discover = |service| ipc.allow(client, 0, Some(name(processes[0].unwrap(), service)));
+
+        assert_eq!(discover("alpha"), ReturnCode::SuccessWithValue { value: 2 });
+        assert_eq!(discover("beta"), ReturnCode::SuccessWithValue { value: 3 });
+        // Names must match exactly.
+        assert_eq!(discover("gamma"), ReturnCode::ENODEVICE);
+        assert_eq!(discover("alph"), ReturnCode::ENODEVICE);
+        assert_eq!(ipc.allow(client, 0, None), ReturnCode::EINVAL);
     }
 }
+
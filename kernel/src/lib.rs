@@ -40,6 +40,9 @@ mod returncode;
 mod sched;
 mod tbfheader;
 
+#[cfg(test)]
+mod test_util;
+
 pub use callback::{AppId, Callback};
 pub use driver::Driver;
 pub use grant::Grant;
@@ -55,5 +58,6 @@ pub use sched::Kernel;
 // functions and types are used by board files to setup the platform and setup
 // processes.
 pub mod procs {
-    pub use process::{load_processes, FaultResponse, FunctionCall, Process, ProcessType};
+    pub use process::{load_processes, load_processes_verified};
+    pub use process::{FaultResponse, FunctionCall, ImageVerifier, Process, ProcessType, State};
 }
@@ -83,20 +83,37 @@ impl<L, T> AppSlice<L, T> {
         self.ptr.ptr.as_ptr()
     }
 
+    /// Check that this slice can be shared with another app over IPC: it must
+    /// lie within the memory of the app that allowed it, and the MPU must be
+    /// able to cover it exactly with a single region.
+    crate fn is_shareable(&self) -> bool {
+        self.ptr
+            .process
+            .kernel
+            .process_map_or(false, self.ptr.process.idx(), |process| {
+                process.in_mpu_alignable_bounds(self.ptr() as *const u8, self.len())
+            })
+    }
+
     /// Provide access to one app's AppSlice to another app. This is used for
-    /// IPC.
-    crate unsafe fn expose_to(&self, appid: AppId) -> bool {
+    /// IPC. Returns the index of the other app's MPU region that covers the
+    /// slice, reusing the region if the slice was already exposed.
+    crate unsafe fn expose_to(&self, appid: AppId) -> Option<usize> {
         if appid.idx() != self.ptr.process.idx() {
+            let start = self.ptr() as *const u8;
+            let len = self.len();
             self.ptr
                 .process
                 .kernel
-                .process_map_or(false, appid.idx(), |process| {
-                    process
-                        .add_mpu_region(self.ptr() as *const u8, self.len(), self.len())
-                        .is_some()
+                .process_map_or(None, appid.idx(), |process| {
+                    process.mpu_region_index(start, len).or_else(|| {
+                        process
+                            .add_mpu_region(start, len, len)
+                            .and_then(|_| process.mpu_region_index(start, len))
+                    })
                 })
         } else {
-            false
+            None
         }
     }
 
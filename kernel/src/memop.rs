@@ -35,7 +35,8 @@ use returncode::ReturnCode;
 /// - `11`: Specify where the start of the app heap is. This tells the kernel
 ///   where the app has put the start of its heap. This is not strictly
 ///   necessary for correct operation, but allows for better debugging if the
-///   app crashes.
+///   app crashes. Returns `EINVAL` if the address is not within the app's
+///   RAM allocation.
 crate fn memop(process: &ProcessType, op_type: usize, r1: usize) -> ReturnCode {
     match op_type {
         // Op Type 0: BRK
@@ -103,10 +104,7 @@ crate fn memop(process: &ProcessType, op_type: usize, r1: usize) -> ReturnCode {
         }
 
         // Op Type 11: Specify where the start of the app heap is.
-        11 => {
-            process.update_heap_start_pointer(r1 as *const u8);
-            ReturnCode::SUCCESS
-        }
+        11 => process.update_heap_start_pointer(r1 as *const u8),
 
         _ => ReturnCode::ENOSUPPORT,
     }
@@ -1,9 +1,10 @@
 //! Interface for configuring the Memory Protection Unit.
 
 use core::cmp;
+use core::fmt::{self, Display};
 
 /// User mode access permissions.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub enum Permissions {
     ReadWriteExecute,
     ReadWriteOnly,
@@ -36,8 +37,21 @@ impl Region {
     }
 }
 
+/// Region configuration for MPUs that do not keep any.
+#[derive(Default)]
+pub struct MpuConfigDefault;
+
+impl Display for MpuConfigDefault {
+    fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result {
+        Ok(())
+    }
+}
+
 pub trait MPU {
-    type MpuConfig: Default = ();
+    /// Region configuration for a process. It is printed as part of the
+    /// process details when debugging a fault, so its `Display`
+    /// implementation should list the configured regions.
+    type MpuConfig: Default + Display = MpuConfigDefault;
 
     /// Enables the MPU.
     fn enable_mpu(&self) {}
@@ -26,8 +26,9 @@ use tbfheader;
 /// through Tock Binary Format headers. Processes are given memory out of the
 /// `app_memory` buffer until either the memory is exhausted or the allocated
 /// number of processes are created, with process structures placed in the
-/// provided array. How process faults are handled by the kernel is also
-/// selected.
+/// provided array. An app that does not fit in the remaining memory is skipped
+/// with a debug message, and loading continues with the next app in flash. How
+/// process faults are handled by the kernel is also selected.
 pub fn load_processes<C: Chip>(
     kernel: &'static Kernel,
     chip: &'static C,
@@ -35,12 +36,72 @@ pub fn load_processes<C: Chip>(
     app_memory: &mut [u8],
     procs: &'static mut [Option<&'static ProcessType>],
     fault_response: FaultResponse,
+    capability: &ProcessManagementCapability,
+) {
+    load_processes_inner(
+        kernel,
+        chip,
+        start_of_flash,
+        app_memory,
+        procs,
+        fault_response,
+        None,
+        capability,
+    );
+}
+
+/// Verifies the authenticity of app images before they are loaded.
+///
+/// The algorithm and the key are up to the implementation. The signature is
+/// read from the signature element of the app's TBF header, and covers the
+/// app's whole image except for the signature itself.
+pub trait ImageVerifier {
+    /// Returns whether `signature` is a valid signature of the image, which is
+    /// given as the parts of the image before and after the signature.
+    fn verify(&self, image: [&[u8]; 2], signature: &[u8]) -> bool;
+}
+
+/// Like `load_processes`, but only loads apps whose image `verifier` accepts.
+/// Apps without a signature, or whose signature is rejected, are skipped with
+/// a debug message.
+pub fn load_processes_verified<C: Chip>(
+    kernel: &'static Kernel,
+    chip: &'static C,
+    start_of_flash: *const u8,
+    app_memory: &mut [u8],
+    procs: &'static mut [Option<&'static ProcessType>],
+    fault_response: FaultResponse,
+    verifier: &ImageVerifier,
+    capability: &ProcessManagementCapability,
+) {
+    load_processes_inner(
+        kernel,
+        chip,
+        start_of_flash,
+        app_memory,
+        procs,
+        fault_response,
+        Some(verifier),
+        capability,
+    );
+}
+
+fn load_processes_inner<C: Chip>(
+    kernel: &'static Kernel,
+    chip: &'static C,
+    start_of_flash: *const u8,
+    app_memory: &mut [u8],
+    procs: &'static mut [Option<&'static ProcessType>],
+    fault_response: FaultResponse,
+    verifier: Option<&ImageVerifier>,
     _capability: &ProcessManagementCapability,
 ) {
     let mut apps_in_flash_ptr = start_of_flash;
     let mut app_memory_ptr = app_memory.as_mut_ptr();
     let mut app_memory_size = app_memory.len();
-    for i in 0..procs.len() {
+    // Apps that are skipped do not use up a process slot.
+    let mut index = 0;
+    while index < procs.len() {
         unsafe {
             let (process, flash_offset, memory_offset) = Process::create(
                 kernel,
@@ -49,7 +110,8 @@ pub fn load_processes<C: Chip>(
                 app_memory_ptr,
                 app_memory_size,
                 fault_response,
-                i,
+                index,
+                verifier,
             );
 
             if process.is_none() {
@@ -61,7 +123,8 @@ pub fn load_processes<C: Chip>(
                     break;
                 }
             } else {
-                procs[i] = process;
+                procs[index] = process;
+                index += 1;
             }
 
             apps_in_flash_ptr = apps_in_flash_ptr.offset(flash_offset as isize);
@@ -99,6 +162,12 @@ pub trait ProcessType {
     /// Move this process from the running state to the yielded state.
     fn set_yielded_state(&self);
 
+    /// Move this process from the running state to the yielded state, and only
+    /// resume it with a callback to the function at `callback_pc`. Other tasks
+    /// stay queued, and are not counted as pending work, until that callback
+    /// has been dequeued.
+    fn set_yielded_for_state(&self, callback_pc: usize);
+
     /// Move this process from running or yielded state into the stopped state
     fn stop(&self);
 
@@ -109,6 +178,17 @@ pub trait ProcessType {
     /// `FaultResponse` for this process to occur.
     fn set_fault_state(&self);
 
+    /// Start this process over from its init function without treating it
+    /// as a fault. Pending tasks are dropped, the grant region is released,
+    /// and the memory break and stack pointer are returned to their original
+    /// values.
+    fn restart(&self);
+
+    /// Stop this process permanently. Any pending tasks are dropped, the
+    /// grant region is released, and the process is moved to the
+    /// `Terminated` state so it is never scheduled again.
+    fn terminate(&self);
+
     /// Get the name of the process. Used for IPC.
     fn get_process_name(&self) -> &'static str;
 
@@ -146,14 +226,20 @@ pub trait ProcessType {
     /// writeable flash region.
     fn get_writeable_flash_region(&self, region_index: usize) -> (u32, u32);
 
+    /// Whether the process may use the driver with number `driver_num`. A
+    /// process whose TBF header lists permitted drivers is only given access
+    /// to those drivers; other processes may use every driver.
+    fn driver_permitted(&self, driver_num: usize) -> bool;
+
     /// Debug function to update the kernel on where the stack starts for this
     /// process. Processes are not required to call this through the memop
     /// system call, but it aids in debugging the process.
     fn update_stack_start_pointer(&self, stack_pointer: *const u8);
 
     /// Debug function to update the kernel on where the process heap starts.
-    /// Also optional.
-    fn update_heap_start_pointer(&self, heap_pointer: *const u8);
+    /// Also optional. Returns `EINVAL` if `heap_pointer` is not within the
+    /// process's memory.
+    fn update_heap_start_pointer(&self, heap_pointer: *const u8) -> ReturnCode;
 
     // additional memop like functions
 
@@ -176,6 +262,11 @@ pub trait ProcessType {
     /// and cannot be edited by the process.
     fn flash_non_protected_start(&self) -> *const u8;
 
+    /// Check whether the `size` bytes starting at `buf_start_addr` lie within
+    /// the part of this process's flash that it may edit, from
+    /// `flash_non_protected_start` to `flash_end`.
+    fn in_app_flash_bounds(&self, buf_start_addr: *const u8, size: usize) -> bool;
+
     // mpu
 
     /// Configure the MPU to use the process's allocated regions.
@@ -190,6 +281,18 @@ pub trait ProcessType {
         min_region_size: usize,
     ) -> Option<mpu::Region>;
 
+    /// Get the index of the MPU region allocated with `add_mpu_region` that
+    /// covers the `size` bytes starting at `start`, if there is one. This is
+    /// the slot in the process's `mpu_regions`, which IPC reports to identify
+    /// a shared buffer, not the hardware MPU region number.
+    fn mpu_region_index(&self, start: *const u8, size: usize) -> Option<usize>;
+
+    /// Check whether the buffer starting at `buf_start_addr` of `size` bytes
+    /// lies within this process's memory and can be covered exactly by a
+    /// single MPU region. Buffers shared over IPC must satisfy this, since
+    /// they are exposed to other processes with an MPU region.
+    fn in_mpu_alignable_bounds(&self, buf_start_addr: *const u8, size: usize) -> bool;
+
     // grants
 
     /// Create new memory in the grant region, and check that the MPU region
@@ -222,6 +325,8 @@ pub trait ProcessType {
 
     unsafe fn fault_fmt(&self, writer: &mut Write);
     unsafe fn process_detail_fmt(&self, writer: &mut Write);
+    /// Print a one-line summary of the process for a crash log.
+    unsafe fn crash_fmt(&self, writer: &mut Write);
 
     // debug
 
@@ -236,6 +341,11 @@ pub trait ProcessType {
 
     /// Returns how many times this process has exceeded its timeslice.
     fn debug_timeslice_expiration_count(&self) -> usize;
+
+    /// Resets the syscall, dropped callback and timeslice expiration counts to
+    /// zero, so they can be sampled over an interval. The restart count covers
+    /// the lifetime of the process and is kept.
+    fn debug_reset_statistics(&self);
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -265,6 +375,7 @@ pub enum State {
     StoppedRunning,
     StoppedYielded,
     Fault,
+    Terminated,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -285,6 +396,14 @@ pub enum Task {
     IPC((AppId, IPCType)),
 }
 
+/// Whether `task` is a callback to the function at `callback_pc`.
+fn task_matches(task: &Task, callback_pc: usize) -> bool {
+    match *task {
+        Task::FunctionCall(ref fc) => fc.pc == callback_pc,
+        Task::IPC(_) => false,
+    }
+}
+
 /// Struct that defines a callback that can be passed to a process. The callback
 /// takes four arguments that are `Driver` and callback specific, so they are
 /// represented generically here.
@@ -424,6 +543,14 @@ pub struct Process<'a, C: 'static + Chip> {
     /// process.
     tasks: MapCell<RingBuffer<'a, Task>>,
 
+    /// The function of the callback the process yielded for, if it is waiting
+    /// for a specific callback.
+    yield_for: Cell<Option<usize>>,
+
+    /// How many of the queued tasks are set aside, and not counted as kernel
+    /// work, because the process is waiting for a different callback.
+    parked_tasks: Cell<usize>,
+
     /// Name of the app.
     process_name: &'static str,
 
@@ -437,19 +564,27 @@ impl<C: Chip> ProcessType for Process<'a, C> {
     }
 
     fn enqueue_task(&self, task: Task) -> bool {
-        // If this app is in the `Fault` state then we shouldn't schedule
-        // any work for it.
-        if self.state.get() == State::Fault {
-            return false;
+        // If this app is in the `Fault` or `Terminated` state then we
+        // shouldn't schedule any work for it.
+        match self.state.get() {
+            State::Fault | State::Terminated => return false,
+            _ => {}
         }
 
-        self.kernel.increment_work();
-
         let ret = self.tasks.map_or(false, |tasks| tasks.enqueue(task));
 
-        // Make a note that we lost this callback if the enqueue function
-        // fails.
-        if ret == false {
+        if ret {
+            // Tasks that a process yielding for a specific callback is not
+            // waiting for are set aside, so that they don't keep the kernel
+            // from sleeping.
+            if self.yield_for.get().map_or(true, |pc| task_matches(&task, pc)) {
+                self.kernel.increment_work();
+            } else {
+                self.parked_tasks.set(self.parked_tasks.get() + 1);
+            }
+        } else {
+            // Make a note that we lost this callback if the enqueue function
+            // fails.
             self.debug.map(|debug| {
                 debug.dropped_callback_count += 1;
             });
@@ -469,6 +604,13 @@ impl<C: Chip> ProcessType for Process<'a, C> {
         }
     }
 
+    fn set_yielded_for_state(&self, callback_pc: usize) {
+        if self.state.get() == State::Running {
+            self.yield_for.set(Some(callback_pc));
+            self.set_yielded_state();
+        }
+    }
+
     fn stop(&self) {
         match self.state.get() {
             State::Running => self.state.set(State::StoppedRunning),
@@ -486,82 +628,153 @@ impl<C: Chip> ProcessType for Process<'a, C> {
     }
 
     fn set_fault_state(&self) {
-        self.state.set(State::Fault);
-
         match self.fault_response {
             FaultResponse::Panic => {
                 // process faulted. Panic and print status
+                self.state.set(State::Fault);
                 panic!("Process {} had a fault", self.process_name);
             }
             FaultResponse::Restart => {
-                // Remove the tasks that were scheduled for the app from the
-                // amount of work queue.
-                let tasks_len = self.tasks.map_or(0, |tasks| tasks.len());
-                for _ in 0..tasks_len {
-                    self.kernel.decrement_work();
-                }
+                // The restart needs the state the process faulted in, to
+                // release the work a running process accounts for.
+                self.restart();
+            }
+        }
+    }
 
-                // And remove those tasks
-                self.tasks.map(|tasks| {
-                    tasks.empty();
-                });
+    fn restart(&self) {
+        match self.state.get() {
+            // A running process counts as one unit of work until it yields.
+            State::Running | State::StoppedRunning => self.kernel.decrement_work(),
+            _ => {}
+        }
 
-                // Update debug information
-                self.debug.map(|debug| {
-                    // Mark that we restarted this process.
-                    debug.restart_count += 1;
+        // Remove the tasks that were scheduled for the app from the amount of
+        // work queue.
+        let tasks_len = self.tasks.map_or(0, |tasks| tasks.len());
+        for _ in 0..tasks_len.saturating_sub(self.parked_tasks.get()) {
+            self.kernel.decrement_work();
+        }
 
-                    // Reset some state for the process.
-                    debug.syscall_count = 0;
-                    debug.last_syscall = None;
-                    debug.dropped_callback_count = 0;
-                });
+        // And remove those tasks
+        self.tasks.map(|tasks| {
+            tasks.empty();
+        });
+        self.parked_tasks.set(0);
+        self.yield_for.set(None);
 
-                // We are going to start this process over again, so need
-                // the init_fn location.
-                let app_flash_address = self.flash_start();
-                let init_fn = unsafe {
-                    app_flash_address.offset(self.header.get_init_function_offset() as isize)
-                        as usize
-                };
-                self.state.set(State::Yielded);
-
-                // Need to reset the grant region.
-                unsafe {
-                    self.grant_ptrs_reset();
-                }
-                self.kernel_memory_break
-                    .set(self.original_kernel_memory_break);
-
-                // Reset other memory pointers.
-                self.app_break.set(self.original_app_break);
-                self.current_stack_pointer.set(self.original_stack_pointer);
-
-                // And queue up this app to be restarted.
-                let flash_protected_size = self.header.get_protected_size() as usize;
-                let flash_app_start = app_flash_address as usize + flash_protected_size;
-
-                self.tasks.map(|tasks| {
-                    tasks.enqueue(Task::FunctionCall(FunctionCall {
-                        pc: init_fn,
-                        argument0: flash_app_start,
-                        argument1: self.memory.as_ptr() as usize,
-                        argument2: self.memory.len() as usize,
-                        argument3: self.app_break.get() as usize,
-                    }));
-                });
+        // Update debug information
+        self.debug.map(|debug| {
+            // Mark that we restarted this process.
+            debug.restart_count += 1;
 
-                self.kernel.increment_work();
-            }
+            // Reset some state for the process.
+            debug.syscall_count = 0;
+            debug.last_syscall = None;
+            debug.dropped_callback_count = 0;
+        });
+
+        // We are going to start this process over again, so need the init_fn
+        // location.
+        let app_flash_address = self.flash_start();
+        let init_fn = unsafe {
+            app_flash_address.offset(self.header.get_init_function_offset() as isize) as usize
+        };
+        self.state.set(State::Yielded);
+
+        // Need to reset the grant region.
+        unsafe {
+            self.grant_ptrs_reset();
+        }
+        self.kernel_memory_break.set(self.original_kernel_memory_break);
+
+        // Reset other memory pointers.
+        self.app_break.set(self.original_app_break);
+        self.current_stack_pointer.set(self.original_stack_pointer);
+
+        // And queue up this app to be restarted.
+        let flash_protected_size = self.header.get_protected_size() as usize;
+        let flash_app_start = app_flash_address as usize + flash_protected_size;
+
+        self.tasks.map(|tasks| {
+            tasks.enqueue(Task::FunctionCall(FunctionCall {
+                pc: init_fn,
+                argument0: flash_app_start,
+                argument1: self.memory.as_ptr() as usize,
+                argument2: self.memory.len() as usize,
+                argument3: self.app_break.get() as usize,
+            }));
+        });
+
+        self.kernel.increment_work();
+    }
+
+    fn terminate(&self) {
+        match self.state.get() {
+            // A running process counts as one unit of work until it yields.
+            State::Running | State::StoppedRunning => self.kernel.decrement_work(),
+            State::Terminated => return,
+            _ => {}
+        }
+
+        // Remove the tasks that were scheduled for the app from the amount of
+        // work queue, and then drop them.
+        let tasks_len = self.tasks.map_or(0, |tasks| tasks.len());
+        for _ in 0..tasks_len.saturating_sub(self.parked_tasks.get()) {
+            self.kernel.decrement_work();
+        }
+        self.tasks.map(|tasks| {
+            tasks.empty();
+        });
+        self.parked_tasks.set(0);
+        self.yield_for.set(None);
+
+        // Release the grant region so capsules no longer see this process's
+        // state.
+        unsafe {
+            self.grant_ptrs_reset();
         }
+        self.kernel_memory_break.set(self.original_kernel_memory_break);
+
+        self.state.set(State::Terminated);
     }
 
     fn dequeue_task(&self) -> Option<Task> {
+        let callback_pc = match self.yield_for.get() {
+            Some(callback_pc) => callback_pc,
+            None => {
+                return self.tasks.map_or(None, |tasks| {
+                    tasks.dequeue().map(|cb| {
+                        self.kernel.decrement_work();
+                        cb
+                    })
+                })
+            }
+        };
+
         self.tasks.map_or(None, |tasks| {
-            tasks.dequeue().map(|cb| {
-                self.kernel.decrement_work();
-                cb
-            })
+            match tasks.remove_first_matching(|task| task_matches(task, callback_pc)) {
+                Some(cb) => {
+                    // Done waiting, so the tasks that were set aside count as
+                    // work again.
+                    self.kernel.decrement_work();
+                    for _ in 0..self.parked_tasks.get() {
+                        self.kernel.increment_work();
+                    }
+                    self.parked_tasks.set(0);
+                    self.yield_for.set(None);
+                    Some(cb)
+                }
+                None => {
+                    // Set aside everything that is queued, none of it is what
+                    // the process is waiting for.
+                    for _ in 0..tasks.len().saturating_sub(self.parked_tasks.get()) {
+                        self.kernel.decrement_work();
+                    }
+                    self.parked_tasks.set(tasks.len());
+                    None
+                }
+            }
         })
     }
 
@@ -585,6 +798,14 @@ impl<C: Chip> ProcessType for Process<'a, C> {
         unsafe { self.flash.as_ptr().offset(self.flash.len() as isize) }
     }
 
+    fn in_app_flash_bounds(&self, buf_start_addr: *const u8, size: usize) -> bool {
+        let buf_end_addr = buf_start_addr.wrapping_offset(size as isize);
+
+        buf_end_addr >= buf_start_addr
+            && buf_start_addr >= self.flash_non_protected_start()
+            && buf_end_addr <= self.flash_end()
+    }
+
     fn kernel_memory_break(&self) -> *const u8 {
         self.kernel_memory_break.get()
     }
@@ -597,6 +818,10 @@ impl<C: Chip> ProcessType for Process<'a, C> {
         self.header.get_writeable_flash_region(region_index)
     }
 
+    fn driver_permitted(&self, driver_num: usize) -> bool {
+        self.header.driver_permitted(driver_num)
+    }
+
     fn update_stack_start_pointer(&self, stack_pointer: *const u8) {
         if stack_pointer >= self.mem_start() && stack_pointer < self.mem_end() {
             self.debug.map(|debug| {
@@ -609,11 +834,14 @@ impl<C: Chip> ProcessType for Process<'a, C> {
         }
     }
 
-    fn update_heap_start_pointer(&self, heap_pointer: *const u8) {
+    fn update_heap_start_pointer(&self, heap_pointer: *const u8) -> ReturnCode {
         if heap_pointer >= self.mem_start() && heap_pointer < self.mem_end() {
             self.debug.map(|debug| {
                 debug.app_heap_start_pointer = Some(heap_pointer);
             });
+            ReturnCode::SUCCESS
+        } else {
+            ReturnCode::EINVAL
         }
     }
 
@@ -623,6 +851,30 @@ impl<C: Chip> ProcessType for Process<'a, C> {
         });
     }
 
+    fn in_mpu_alignable_bounds(&self, buf_start_addr: *const u8, size: usize) -> bool {
+        let buf_end_addr = buf_start_addr.wrapping_offset(size as isize);
+        if buf_end_addr < buf_start_addr
+            || buf_start_addr < self.mem_start()
+            || buf_end_addr > self.mem_end()
+        {
+            return false;
+        }
+
+        // Try to allocate the region in an empty configuration. This only
+        // checks that the MPU can express the region, not that the process
+        // has a free region left for it.
+        let mut config = Default::default();
+        self.chip
+            .mpu()
+            .allocate_region(
+                buf_start_addr,
+                size,
+                size,
+                mpu::Permissions::ReadWriteExecute,
+                &mut config,
+            ).is_some()
+    }
+
     fn add_mpu_region(
         &self,
         unallocated_memory_start: *const u8,
@@ -654,6 +906,18 @@ impl<C: Chip> ProcessType for Process<'a, C> {
         })
     }
 
+    fn mpu_region_index(&self, start: *const u8, size: usize) -> Option<usize> {
+        let end = start.wrapping_offset(size as isize);
+        self.mpu_regions.iter().position(|region| {
+            region.get().map_or(false, |region| {
+                let region_end = region
+                    .start_address()
+                    .wrapping_offset(region.size() as isize);
+                region.start_address() <= start && end <= region_end
+            })
+        })
+    }
+
     fn sbrk(&self, increment: isize) -> Result<*const u8, Error> {
         let new_break = unsafe { self.app_break.get().offset(increment) };
         self.brk(new_break)
@@ -863,10 +1127,32 @@ impl<C: Chip> ProcessType for Process<'a, C> {
             .map_or(0, |debug| debug.timeslice_expiration_count)
     }
 
+    fn debug_reset_statistics(&self) {
+        self.debug.map(|debug| {
+            debug.syscall_count = 0;
+            debug.dropped_callback_count = 0;
+            debug.timeslice_expiration_count = 0;
+        });
+    }
+
     unsafe fn fault_fmt(&self, writer: &mut Write) {
         self.chip.userspace_kernel_boundary().fault_fmt(writer);
     }
 
+    unsafe fn crash_fmt(&self, writer: &mut Write) {
+        let _ = writer.write_fmt(format_args!(
+            "App: {} [{:?}] ",
+            self.process_name,
+            self.state.get()
+        ));
+        self.chip.userspace_kernel_boundary().process_crash_fmt(
+            self.sp(),
+            &self.stored_state.get(),
+            writer,
+        );
+        let _ = writer.write_str("\r\n");
+    }
+
     unsafe fn process_detail_fmt(&self, writer: &mut Write) {
         // Flash
         let flash_end = self.flash.as_ptr().offset(self.flash.len() as isize) as usize;
@@ -981,6 +1267,21 @@ impl<C: Chip> ProcessType for Process<'a, C> {
   flash_protected_size,
   flash_start));
 
+        let _ = writer.write_fmt(format_args!("\r\n---| MPU Configuration |---\r\n"));
+        self.mpu_config.map(|config| {
+            let _ = writer.write_fmt(format_args!("{}", config));
+        });
+        for region in self.mpu_regions.iter() {
+            if let Some(region) = region.get() {
+                let _ = writer.write_fmt(format_args!(
+                    " Shared: {:#010X} - {:#010X} ({} bytes)\r\n",
+                    region.start_address() as usize,
+                    region.start_address() as usize + region.size(),
+                    region.size()
+                ));
+            }
+        }
+
         self.chip.userspace_kernel_boundary().process_detail_fmt(
             self.sp(),
             &self.stored_state.get(),
@@ -1005,6 +1306,7 @@ impl<C: 'static + Chip> Process<'a, C> {
         remaining_app_memory_size: usize,
         fault_response: FaultResponse,
         index: usize,
+        verifier: Option<&ImageVerifier>,
     ) -> (Option<&'static ProcessType>, usize, usize) {
         if let Some(tbf_header) = tbfheader::parse_and_validate_tbf_header(app_flash_address) {
             let app_flash_size = tbf_header.get_total_size() as usize;
@@ -1021,6 +1323,31 @@ impl<C: 'static + Chip> Process<'a, C> {
             let init_fn =
                 app_flash_address.offset(tbf_header.get_init_function_offset() as isize) as usize;
 
+            // A malformed image is not loaded, but does not stop the other
+            // apps from loading.
+            if (init_fn & 0x1) != 1 {
+                debug!(
+                    "Skipping process {}: init_fn address must end in 1 to be Thumb, got {:#X}",
+                    process_name, init_fn
+                );
+                return (None, app_flash_size, 0);
+            }
+
+            // With a verifier, only apps with a valid signature are loaded.
+            if let Some(verifier) = verifier {
+                let image = slice::from_raw_parts(app_flash_address, app_flash_size);
+                let verified = tbf_header.get_signature().map_or(false, |signature| {
+                    let start = signature.as_ptr() as usize - app_flash_address as usize;
+                    let end = start + signature.len();
+                    end <= image.len()
+                        && verifier.verify([&image[..start], &image[end..]], signature)
+                });
+                if !verified {
+                    debug!("Skipping process {}: image not verified", process_name);
+                    return (None, app_flash_size, 0);
+                }
+            }
+
             // Initialize MPU region configuration.
             let mut mpu_config: <<C as Chip>::MPU as MPU>::MpuConfig = Default::default();
 
@@ -1052,11 +1379,35 @@ impl<C: 'static + Chip> Process<'a, C> {
             // Make room to store this process's metadata.
             let process_struct_offset = mem::size_of::<Process<C>>();
 
+            // The stack grows down from the initial stack pointer, which is
+            // placed at the end of the initial app-owned memory, so that memory
+            // must hold at least the stack the app asked for. An app whose
+            // minimum RAM size cannot even hold its stack is not loaded.
+            // Stacks are 8 byte aligned on all supported architectures.
+            let requested_stack_size = tbf_header.get_minimum_stack_size() as usize;
+            let min_stack_size = match requested_stack_size.checked_add(7) {
+                Some(size) => size & !7,
+                None => {
+                    debug!(
+                        "Skipping process {}: invalid minimum stack size",
+                        process_name
+                    );
+                    return (None, app_flash_size, 0);
+                }
+            };
+            if min_app_ram_size != 0 && min_stack_size > min_app_ram_size {
+                debug!(
+                    "Skipping process {}: stack of {} bytes does not fit in {} bytes of RAM",
+                    process_name, min_stack_size, min_app_ram_size
+                );
+                return (None, app_flash_size, 0);
+            }
+
             // Initial sizes of the app-owned and kernel-owned parts of process memory.
             // Provide the app with plenty of initial process accessible memory.
             let initial_kernel_memory_size =
                 grant_ptrs_offset + callbacks_offset + process_struct_offset;
-            let initial_app_memory_size = 3 * 1024;
+            let initial_app_memory_size = max(3 * 1024, min_stack_size);
 
             if min_app_ram_size < initial_app_memory_size {
                 min_app_ram_size = initial_app_memory_size;
@@ -1077,7 +1428,12 @@ impl<C: 'static + Chip> Process<'a, C> {
             ) {
                 Some((memory_start, memory_size)) => (memory_start, memory_size),
                 None => {
-                    // Failed to load process. Insufficient memory.
+                    // Failed to load process. Insufficient memory. Skip past
+                    // its flash so that later apps can still be loaded.
+                    debug!(
+                        "Skipping process {}: needs {} bytes of RAM, {} available",
+                        process_name, min_total_memory_size, remaining_app_memory_size
+                    );
                     return (None, app_flash_size, 0);
                 }
             };
@@ -1088,7 +1444,8 @@ impl<C: 'static + Chip> Process<'a, C> {
             // Set up process memory.
             let app_memory = slice::from_raw_parts_mut(memory_start as *mut u8, memory_size);
 
-            // Set the initial process stack and memory to 3072 bytes.
+            // Set the initial process stack and memory to 3072 bytes, or the
+            // requested minimum stack size if that is larger.
             let initial_stack_pointer = memory_start.offset(initial_app_memory_size as isize);
             let initial_sbrk_pointer = memory_start.offset(initial_app_memory_size as isize);
 
@@ -1158,6 +1515,8 @@ impl<C: 'static + Chip> Process<'a, C> {
                 Cell::new(None),
             ];
             process.tasks = MapCell::new(tasks);
+            process.yield_for = Cell::new(None);
+            process.parked_tasks = Cell::new(0);
             process.process_name = process_name;
 
             process.debug = MapCell::new(ProcessDebug {
@@ -1171,14 +1530,6 @@ impl<C: 'static + Chip> Process<'a, C> {
                 timeslice_expiration_count: 0,
             });
 
-            if (init_fn & 0x1) != 1 {
-                panic!(
-                    "{:?} process image invalid. \
-                     init_fn address must end in 1 to be Thumb, got {:#X}",
-                    process_name, init_fn
-                );
-            }
-
             let flash_protected_size = process.header.get_protected_size() as usize;
             let flash_app_start = app_flash_address as usize + flash_protected_size;
 
@@ -1238,3 +1589,329 @@ impl<C: 'static + Chip> Process<'a, C> {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::vec::Vec;
+    use super::*;
+    use test_util::{self, TestApp, TestChip, FRAME_SIZE};
+
+    /// The concrete process behind `process`, to check its private state.
+    fn concrete(process: &ProcessType) -> &Process<'static, TestChip> {
+        unsafe { &*(process as *const ProcessType as *const Process<'static, TestChip>) }
+    }
+
+    fn callback(pc: usize) -> Task {
+        Task::FunctionCall(FunctionCall {
+            argument0: 0,
+            argument1: 0,
+            argument2: 0,
+            argument3: 0,
+            pc: pc,
+        })
+    }
+
+    fn pc(task: Option<Task>) -> Option<usize> {
+        match task {
+            Some(Task::FunctionCall(fc)) => Some(fc.pc),
+            _ => None,
+        }
+    }
+
+    /// Loads one app, whose faults are handled with `fault_response`, and
+    /// starts running its init function.
+    fn running_process_with(
+        fault_response: FaultResponse,
+    ) -> (&'static Kernel, &'static Process<'static, TestChip>) {
+        let _debug = test_util::capture_debug();
+        let app = TestApp::new("busy").minimum_ram_size(4 * 1024);
+        let (kernel, processes) = test_util::kernel(1);
+        let processes_ptr = processes as *const [Option<&'static ProcessType>];
+        test_util::load(kernel, processes, &[app], 64 * 1024, fault_response);
+        let process = concrete(unsafe { &*processes_ptr }[0].unwrap());
+
+        let init = process.dequeue_task();
+        assert_eq!(pc(init), Some(process.flash_non_protected_start() as usize + 0x101));
+        unsafe {
+            process.push_function_call(match init {
+                Some(Task::FunctionCall(fc)) => fc,
+                _ => unreachable!(),
+            });
+        }
+        assert_eq!(kernel.work(), 1);
+        (kernel, process)
+    }
+
+    /// Loads one app and starts running its init function.
+    fn running_process() -> (&'static Kernel, &'static Process<'static, TestChip>) {
+        running_process_with(FaultResponse::Panic)
+    }
+
+    /// Runs an app's init function until it has grown its heap, pushed a
+    /// callback on its stack and queued two more callbacks.
+    fn busy_process() -> (&'static Kernel, &'static Process<'static, TestChip>) {
+        let (kernel, process) = running_process();
+        assert!(process.sbrk(512).is_ok());
+        assert!(process.enqueue_task(callback(0x201)));
+        assert!(process.enqueue_task(callback(0x301)));
+
+        assert_eq!(process.get_state(), State::Running);
+        assert_eq!(
+            process.current_stack_pointer.get(),
+            process.original_stack_pointer.wrapping_offset(-(FRAME_SIZE as isize))
+        );
+        assert_eq!(
+            process.app_break.get(),
+            process.original_app_break.wrapping_offset(512)
+        );
+        assert_eq!(kernel.work(), 3);
+        (kernel, process)
+    }
+
+    #[test]
+    fn terminate_drops_callbacks() {
+        let (kernel, process) = busy_process();
+
+        process.terminate();
+        assert_eq!(process.get_state(), State::Terminated);
+        assert_eq!(kernel.work(), 0);
+        assert!(process.dequeue_task().is_none());
+        assert!(!process.enqueue_task(callback(0x201)));
+        assert_eq!(process.kernel_memory_break(), process.original_kernel_memory_break);
+
+        // A second terminate has nothing left to undo.
+        process.terminate();
+        assert_eq!(kernel.work(), 0);
+    }
+
+    #[test]
+    fn restart_drops_callbacks_and_resets_memory() {
+        let (kernel, process) = busy_process();
+
+        process.restart();
+        assert_eq!(process.get_state(), State::Yielded);
+        assert_eq!(process.debug_restart_count(), 1);
+        assert_eq!(process.app_break.get(), process.original_app_break);
+        assert_eq!(
+            process.current_stack_pointer.get(),
+            process.original_stack_pointer
+        );
+        assert_eq!(process.kernel_memory_break(), process.original_kernel_memory_break);
+
+        // Only the init function is queued, and it is the only work left.
+        assert_eq!(kernel.work(), 1);
+        match process.dequeue_task() {
+            Some(Task::FunctionCall(fc)) => {
+                assert_eq!(fc.pc, process.flash_non_protected_start() as usize + 0x101);
+                assert_eq!(fc.argument3, process.original_app_break as usize);
+            }
+            _ => panic!("expected the init function"),
+        }
+        assert!(process.dequeue_task().is_none());
+        assert_eq!(kernel.work(), 0);
+    }
+
+    #[test]
+    fn fault_while_running_restarts_process() {
+        let (kernel, process) = running_process_with(FaultResponse::Restart);
+        assert!(process.enqueue_task(callback(0x201)));
+        assert_eq!(kernel.work(), 2);
+
+        process.set_fault_state();
+        assert_eq!(process.get_state(), State::Yielded);
+        assert_eq!(process.debug_restart_count(), 1);
+
+        // The running init function and the queued callback are no longer
+        // work. Only the new init function is.
+        assert_eq!(kernel.work(), 1);
+        assert!(pc(process.dequeue_task()).is_some());
+        assert!(process.dequeue_task().is_none());
+        assert_eq!(kernel.work(), 0);
+    }
+
+    const TIMER: usize = 0x201;
+    const GPIO: usize = 0x301;
+
+    /// Runs an app until it yields for a timer callback while a GPIO callback
+    /// is already queued, and the scheduler has looked at its queue.
+    fn process_waiting_for_timer() -> (&'static Kernel, &'static Process<'static, TestChip>) {
+        let (kernel, process) = running_process();
+        assert!(process.enqueue_task(callback(GPIO)));
+        assert_eq!(kernel.work(), 2);
+
+        process.set_yielded_for_state(TIMER);
+        assert_eq!(process.get_state(), State::Yielded);
+        assert_eq!(kernel.work(), 1);
+
+        // The GPIO callback is set aside, so the kernel may sleep.
+        assert!(process.dequeue_task().is_none());
+        assert_eq!(process.parked_tasks.get(), 1);
+        assert_eq!(kernel.work(), 0);
+        (kernel, process)
+    }
+
+    #[test]
+    fn yield_for_delivers_only_the_awaited_callback() {
+        let (kernel, process) = process_waiting_for_timer();
+
+        // Another unrelated callback is parked as soon as it arrives.
+        assert!(process.enqueue_task(callback(GPIO)));
+        assert_eq!(process.parked_tasks.get(), 2);
+        assert_eq!(kernel.work(), 0);
+
+        // The timer callback wakes the process ahead of the older callbacks,
+        // which then count as work again.
+        assert!(process.enqueue_task(callback(TIMER)));
+        assert_eq!(kernel.work(), 1);
+        assert_eq!(pc(process.dequeue_task()), Some(TIMER));
+        assert_eq!(process.parked_tasks.get(), 0);
+        assert_eq!(kernel.work(), 2);
+
+        assert_eq!(pc(process.dequeue_task()), Some(GPIO));
+        assert_eq!(pc(process.dequeue_task()), Some(GPIO));
+        assert!(process.dequeue_task().is_none());
+        assert_eq!(kernel.work(), 0);
+    }
+
+    #[test]
+    fn terminate_while_yielded_for_drops_parked_callbacks() {
+        let (kernel, process) = process_waiting_for_timer();
+
+        process.terminate();
+        assert_eq!(process.parked_tasks.get(), 0);
+        assert_eq!(process.yield_for.get(), None);
+        assert_eq!(kernel.work(), 0);
+        assert!(process.dequeue_task().is_none());
+    }
+
+    #[test]
+    fn restart_while_yielded_for_drops_parked_callbacks() {
+        let (kernel, process) = process_waiting_for_timer();
+
+        process.restart();
+        assert_eq!(process.parked_tasks.get(), 0);
+        assert_eq!(process.yield_for.get(), None);
+        assert_eq!(kernel.work(), 1);
+
+        // The init function is not held back by the abandoned yield.
+        assert!(pc(process.dequeue_task()).is_some());
+        assert!(process.dequeue_task().is_none());
+        assert_eq!(kernel.work(), 0);
+    }
+
+    /// Loads `app` and returns how far into its memory the initial stack
+    /// pointer and memory break are placed.
+    fn initial_placement(app: TestApp) -> (usize, usize) {
+        let _debug = test_util::capture_debug();
+        let (_kernel, processes) = test_util::load_apps(1, &[app]);
+        let process = concrete(processes[0].unwrap());
+        assert_eq!(process.original_stack_pointer, process.original_app_break);
+        (
+            process.original_stack_pointer as usize - process.mem_start() as usize,
+            process.app_break.get() as usize - process.mem_start() as usize,
+        )
+    }
+
+    #[test]
+    fn small_minimum_stack_keeps_default_placement() {
+        let app = TestApp::new("small").minimum_stack_size(2 * 1024);
+        assert_eq!(initial_placement(app), (3 * 1024, 3 * 1024));
+    }
+
+    #[test]
+    fn large_minimum_stack_moves_initial_stack_pointer() {
+        // Rounded up to keep the stack 8 byte aligned.
+        let app = TestApp::new("large").minimum_stack_size(5 * 1024 + 4);
+        assert_eq!(initial_placement(app), (5 * 1024 + 8, 5 * 1024 + 8));
+    }
+
+    #[test]
+    fn app_too_large_for_memory_is_skipped() {
+        let debug = test_util::capture_debug();
+        let (kernel, processes) = test_util::kernel(2);
+        let processes_ptr = processes as *const [Option<&'static ProcessType>];
+        let apps = [
+            TestApp::new("first").minimum_ram_size(16 * 1024),
+            TestApp::new("second"),
+        ];
+        test_util::load(kernel, processes, &apps, 8 * 1024, FaultResponse::Panic);
+        let processes = unsafe { &*processes_ptr };
+
+        assert!(debug.output().contains("Skipping process first: needs"));
+        // The skipped app does not use up a process slot.
+        assert_eq!(processes[0].map(|p| p.get_process_name()), Some("second"));
+        assert!(processes[1].is_none());
+        assert_eq!(kernel.work(), 1);
+    }
+
+    #[test]
+    fn app_with_even_init_function_is_skipped() {
+        let debug = test_util::capture_debug();
+        let apps = [
+            TestApp::new("arm").init_fn_offset(0x100),
+            TestApp::new("thumb"),
+            TestApp::new("later"),
+        ];
+        let (kernel, processes) = test_util::load_apps(2, &apps);
+
+        assert!(
+            debug
+                .output()
+                .contains("Skipping process arm: init_fn address must end in 1")
+        );
+        let names: Vec<_> = processes
+            .iter()
+            .map(|p| p.map(|p| p.get_process_name()))
+            .collect();
+        assert_eq!(names, [Some("thumb"), Some("later")]);
+        assert_eq!(kernel.work(), 2);
+    }
+
+    fn loaded_process() -> &'static Process<'static, TestChip> {
+        let _debug = test_util::capture_debug();
+        let (_kernel, processes) = test_util::load_apps(1, &[TestApp::new("app")]);
+        concrete(processes[0].unwrap())
+    }
+
+    #[test]
+    fn flash_bounds_cover_only_editable_flash() {
+        let process = loaded_process();
+        let start = process.flash_non_protected_start();
+        let end = process.flash_end();
+        let len = end as usize - start as usize;
+        assert!(start > process.flash_start());
+
+        // In bounds.
+        assert!(process.in_app_flash_bounds(start, len));
+        assert!(process.in_app_flash_bounds(start.wrapping_offset(4), 16));
+        assert!(process.in_app_flash_bounds(end, 0));
+
+        // Straddling the header or the end of the app's flash.
+        assert!(!process.in_app_flash_bounds(start.wrapping_offset(-4), 8));
+        assert!(!process.in_app_flash_bounds(end.wrapping_offset(-8), 16));
+
+        // Out of bounds, or wrapping around the address space.
+        assert!(!process.in_app_flash_bounds(process.flash_start(), 4));
+        assert!(!process.in_app_flash_bounds(end, 4));
+        assert!(!process.in_app_flash_bounds(start, usize::max_value()));
+    }
+
+    #[test]
+    fn mpu_alignable_bounds_need_an_exact_region_in_memory() {
+        let process = loaded_process();
+        let start = process.mem_start();
+        let end = process.mem_end();
+        let aligned = start.wrapping_offset((256 - start as usize % 256) as isize);
+
+        assert!(process.in_mpu_alignable_bounds(aligned, 256));
+        // Unaligned, or not a size the MPU can cover exactly.
+        assert!(!process.in_mpu_alignable_bounds(aligned.wrapping_offset(8), 256));
+        assert!(!process.in_mpu_alignable_bounds(aligned, 100));
+        // Outside the process's memory, or straddling its edges.
+        assert!(!process.in_mpu_alignable_bounds(start.wrapping_offset(-32), 64));
+        assert!(!process.in_mpu_alignable_bounds(end.wrapping_offset(-32), 64));
+        assert!(!process.in_mpu_alignable_bounds(end, 32));
+    }
+}
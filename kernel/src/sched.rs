@@ -5,8 +5,9 @@ use core::ptr::NonNull;
 
 use callback::Callback;
 use capabilities;
-use common::cells::NumericCellExt;
+use common::cells::{NumericCellExt, OptionalCell};
 use grant::Grant;
+use hil::watchdog::Watchdog;
 use ipc;
 use memop;
 use platform::mpu::MPU;
@@ -37,6 +38,8 @@ pub struct Kernel {
     /// created and the data structures for grants have already been
     /// established.
     grants_finalized: Cell<bool>,
+    /// Optional watchdog that is serviced on every iteration of the main loop.
+    watchdog: OptionalCell<&'static Watchdog>,
 }
 
 impl Kernel {
@@ -46,6 +49,7 @@ impl Kernel {
             processes: processes,
             grant_counter: Cell::new(0),
             grants_finalized: Cell::new(false),
+            watchdog: OptionalCell::empty(),
         }
     }
 
@@ -60,6 +64,12 @@ impl Kernel {
         self.work.decrement();
     }
 
+    /// How many things are scheduled across all processes.
+    #[cfg(test)]
+    crate fn work(&self) -> usize {
+        self.work.get()
+    }
+
     /// Helper function for determining if we should service processes or go to
     /// sleep.
     fn processes_blocked(&self) -> bool {
@@ -199,6 +209,17 @@ impl Kernel {
         }
     }
 
+    /// Have the main loop service `watchdog` on every iteration, so that the
+    /// chip is reset if the kernel stops making progress. The board is
+    /// responsible for starting the watchdog with a suitable period.
+    pub fn set_watchdog(
+        &self,
+        watchdog: &'static Watchdog,
+        _capability: &capabilities::MainLoopCapability,
+    ) {
+        self.watchdog.set(watchdog);
+    }
+
     /// Main loop.
     pub fn kernel_loop<P: Platform, C: Chip>(
         &'static self,
@@ -208,6 +229,8 @@ impl Kernel {
         _capability: &capabilities::MainLoopCapability,
     ) {
         loop {
+            self.watchdog.map(|watchdog| watchdog.tickle());
+
             unsafe {
                 chip.service_pending_interrupts();
 
@@ -283,6 +306,13 @@ impl Kernel {
                                     // There might be already enqueued callbacks
                                     continue;
                                 }
+                                Some(Syscall::YIELDFOR { callback_ptr }) => {
+                                    process.set_yielded_for_state(callback_ptr as usize);
+                                    process.pop_syscall_stack_frame();
+
+                                    // The callback might already be enqueued
+                                    continue;
+                                }
                                 Some(Syscall::SUBSCRIBE {
                                     driver_number,
                                     subdriver_number,
@@ -293,10 +323,13 @@ impl Kernel {
                                     let callback = callback_ptr
                                         .map(|ptr| Callback::new(appid, appdata, ptr.cast()));
 
+                                    // Processes may be restricted to the drivers listed
+                                    // in their TBF header.
+                                    let permitted = process.driver_permitted(driver_number);
                                     let res =
                                         platform.with_driver(
                                             driver_number,
-                                            |driver| match driver {
+                                            |driver| match driver.filter(|_| permitted) {
                                                 Some(d) => {
                                                     d.subscribe(subdriver_number, callback, appid)
                                                 }
@@ -311,10 +344,11 @@ impl Kernel {
                                     arg0,
                                     arg1,
                                 }) => {
+                                    let permitted = process.driver_permitted(driver_number);
                                     let res =
                                         platform.with_driver(
                                             driver_number,
-                                            |driver| match driver {
+                                            |driver| match driver.filter(|_| permitted) {
                                                 Some(d) => {
                                                     d.command(subdriver_number, arg0, arg1, appid)
                                                 }
@@ -329,8 +363,9 @@ impl Kernel {
                                     allow_address,
                                     allow_size,
                                 }) => {
+                                    let permitted = process.driver_permitted(driver_number);
                                     let res = platform.with_driver(driver_number, |driver| {
-                                        match driver {
+                                        match driver.filter(|_| permitted) {
                                             Some(d) => {
                                                 match process.allow(allow_address, allow_size) {
                                                     Ok(oslice) => {
@@ -399,6 +434,10 @@ impl Kernel {
                     break;
                     // Do nothing
                 }
+                process::State::Terminated => {
+                    // Terminated processes are never scheduled again.
+                    break;
+                }
             }
         }
         systick.reset();
@@ -47,6 +47,12 @@ pub enum Syscall {
     ///
     /// SVC_NUM = 4
     MEMOP { operand: usize, arg0: usize },
+
+    /// Like `YIELD`, but only resume the process with the callback whose
+    /// function is `callback_ptr`. Other callbacks stay queued until then.
+    ///
+    /// SVC_NUM = 5
+    YIELDFOR { callback_ptr: *mut () },
 }
 
 /// Why the process stopped executing and execution returned to the kernel.
@@ -131,4 +137,13 @@ pub trait UserspaceKernelBoundary {
         state: &Self::StoredState,
         writer: &mut Write,
     );
+
+    /// Display the registers that locate a fault (e.g. the program counter)
+    /// for a process identified by its stack pointer, on a single line.
+    unsafe fn process_crash_fmt(
+        &self,
+        stack_pointer: *const usize,
+        state: &Self::StoredState,
+        writer: &mut Write,
+    );
 }
@@ -29,6 +29,9 @@ crate enum TbfHeaderTypes {
     TbfHeaderWriteableFlashRegions = 2,
     TbfHeaderPackageName = 3,
     Unused = 5,
+    TbfHeaderMinimumStack = 6,
+    TbfHeaderPermittedDrivers = 7,
+    TbfHeaderSignature = 8,
 }
 
 /// The TLV header (T and L).
@@ -51,6 +54,14 @@ crate struct TbfHeaderV2Main {
     minimum_ram_size: u32,
 }
 
+/// The minimum stack size the app needs. The kernel places the initial stack
+/// pointer at least this far above the start of the app's memory.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+crate struct TbfHeaderV2MinimumStack {
+    minimum_stack_size: u32,
+}
+
 /// Writeable flash regions only need an offset and size.
 ///
 /// There can be multiple (or zero) flash regions defined, so this is its own
@@ -69,6 +80,29 @@ crate struct TbfHeaderV2 {
     main: Option<&'static TbfHeaderV2Main>,
     package_name: Option<&'static str>,
     writeable_regions: Option<&'static [TbfHeaderV2WriteableFlashRegion]>,
+    minimum_stack: Option<&'static TbfHeaderV2MinimumStack>,
+    permitted_drivers: Option<&'static [u32]>,
+    signature: Option<&'static [u8]>,
+}
+
+/// Name used for an app whose package name does not start with any valid
+/// UTF-8, so that it can still be told apart from apps without a name.
+const INVALID_PACKAGE_NAME: &'static str = "<invalid name>";
+
+/// Converts the bytes of a package name TLV to a string. If the name is not
+/// valid UTF-8 it is truncated at the first invalid byte, rather than dropped,
+/// so that the app can still be identified.
+fn package_name_from_bytes(bytes: &'static [u8]) -> &'static str {
+    match str::from_utf8(bytes) {
+        Ok(name) => name,
+        Err(err) => {
+            let valid = &bytes[..err.valid_up_to()];
+            match str::from_utf8(valid) {
+                Ok(name) if name.len() > 0 => name,
+                _ => INVALID_PACKAGE_NAME,
+            }
+        }
+    }
 }
 
 /// Type that represents the fields of the Tock Binary Format header.
@@ -122,6 +156,15 @@ impl TbfHeader {
         }
     }
 
+    /// Get the minimum stack size the app requested, or 0 if it did not
+    /// request one.
+    crate fn get_minimum_stack_size(&self) -> u32 {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd.minimum_stack.map_or(0, |m| m.minimum_stack_size),
+            _ => 0,
+        }
+    }
+
     /// Get the number of bytes from the start of the app's region in flash that
     /// is for kernel use only. The app cannot write this region.
     crate fn get_protected_size(&self) -> u32 {
@@ -160,6 +203,25 @@ impl TbfHeader {
         }
     }
 
+    /// Whether the app may use the driver with number `driver_num`. Apps whose
+    /// header does not list permitted drivers may use every driver.
+    crate fn driver_permitted(&self, driver_num: usize) -> bool {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd.permitted_drivers.map_or(true, |drivers| {
+                drivers.iter().any(|driver| *driver as usize == driver_num)
+            }),
+            _ => false,
+        }
+    }
+
+    /// Get the signature of the app image, if the header has one.
+    crate fn get_signature(&self) -> Option<&'static [u8]> {
+        match *self {
+            TbfHeader::TbfHeaderV2(hd) => hd.signature,
+            _ => None,
+        }
+    }
+
     /// Get the offset and size of a given flash region.
     crate fn get_writeable_flash_region(&self, index: usize) -> (u32, u32) {
         match *self {
@@ -246,6 +308,9 @@ crate unsafe fn parse_and_validate_tbf_header(address: *const u8) -> Option<TbfH
                     &'static [TbfHeaderV2WriteableFlashRegion],
                 > = None;
                 let mut app_name_str = "";
+                let mut minimum_stack_pointer: Option<&TbfHeaderV2MinimumStack> = None;
+                let mut permitted_drivers_pointer: Option<&'static [u32]> = None;
+                let mut signature_pointer: Option<&'static [u8]> = None;
 
                 // Loop through the header looking for known options.
                 while remaining_length > mem::size_of::<TbfHeaderTlv>() {
@@ -254,9 +319,12 @@ crate unsafe fn parse_and_validate_tbf_header(address: *const u8) -> Option<TbfH
                     remaining_length -= mem::size_of::<TbfHeaderTlv>();
                     offset += mem::size_of::<TbfHeaderTlv>() as isize;
 
-                    // Only parse known TLV blocks. There is no type 0.
-                    if (tbf_tlv_header.tipe as u16) < TbfHeaderTypes::Unused as u16
-                        && (tbf_tlv_header.tipe as u16) > 0
+                    // Only parse known TLV blocks. There is no type 0, and
+                    // types 4 and 5 are not used.
+                    let tipe = tbf_tlv_header.tipe as u16;
+                    if tipe > 0
+                        && tipe != 4
+                        && tipe <= TbfHeaderTypes::TbfHeaderSignature as u16
                     {
                         // This lets us skip unknown header types.
 
@@ -298,10 +366,52 @@ crate unsafe fn parse_and_validate_tbf_header(address: *const u8) -> Option<TbfH
                                         address.offset(offset),
                                         tbf_tlv_header.length as usize,
                                     );
-                                    let _ =
-                                        str::from_utf8(package_name_byte_array).map(|name_str| {
-                                            app_name_str = name_str;
-                                        });
+                                    app_name_str = package_name_from_bytes(
+                                        package_name_byte_array,
+                                    );
+                                }
+                            }
+                            TbfHeaderTypes::TbfHeaderMinimumStack =>
+                            /* Minimum Stack */
+                            {
+                                if remaining_length >= mem::size_of::<TbfHeaderV2MinimumStack>()
+                                    && tbf_tlv_header.length as usize
+                                        == mem::size_of::<TbfHeaderV2MinimumStack>()
+                                {
+                                    let tbf_minimum_stack = &*(address.offset(offset)
+                                        as *const TbfHeaderV2MinimumStack);
+                                    minimum_stack_pointer = Some(tbf_minimum_stack);
+                                }
+                            }
+                            TbfHeaderTypes::TbfHeaderPermittedDrivers =>
+                            /* Permitted Drivers */
+                            {
+                                // A list of 32 bit driver numbers.
+                                if remaining_length >= tbf_tlv_header.length as usize
+                                    && tbf_tlv_header.length as usize % mem::size_of::<u32>() == 0
+                                {
+                                    let number_drivers =
+                                        tbf_tlv_header.length as usize / mem::size_of::<u32>();
+                                    let drivers = slice::from_raw_parts(
+                                        address.offset(offset) as *const u32,
+                                        number_drivers,
+                                    );
+                                    permitted_drivers_pointer = Some(drivers);
+                                } else {
+                                    // Ignoring a malformed list would let the
+                                    // app use every driver, so reject the app.
+                                    return None;
+                                }
+                            }
+                            TbfHeaderTypes::TbfHeaderSignature =>
+                            /* Signature */
+                            {
+                                if remaining_length >= tbf_tlv_header.length as usize {
+                                    let signature = slice::from_raw_parts(
+                                        address.offset(offset),
+                                        tbf_tlv_header.length as usize,
+                                    );
+                                    signature_pointer = Some(signature);
                                 }
                             }
                             TbfHeaderTypes::Unused => {}
@@ -319,6 +429,9 @@ crate unsafe fn parse_and_validate_tbf_header(address: *const u8) -> Option<TbfH
                     main: main_pointer,
                     package_name: Some(app_name_str),
                     writeable_regions: wfr_pointer,
+                    minimum_stack: minimum_stack_pointer,
+                    permitted_drivers: permitted_drivers_pointer,
+                    signature: signature_pointer,
                 };
 
                 Some(TbfHeader::TbfHeaderV2(tbf_header))
@@ -330,3 +443,97 @@ crate unsafe fn parse_and_validate_tbf_header(address: *const u8) -> Option<TbfH
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::boxed::Box;
+    use self::std::vec;
+    use self::std::vec::Vec;
+    use super::*;
+
+    const DRIVERS: u16 = TbfHeaderTypes::TbfHeaderPermittedDrivers as u16;
+    const SIGNATURE: u16 = TbfHeaderTypes::TbfHeaderSignature as u16;
+
+    /// Builds a checksummed v2 header with the given TLV blocks and parses it.
+    fn parse(tlvs: &[(u16, &[u8])]) -> Option<TbfHeader> {
+        let mut bytes = Vec::new();
+        for &(tipe, value) in tlvs {
+            bytes.extend_from_slice(&[tipe as u8, (tipe >> 8) as u8]);
+            bytes.extend_from_slice(&[value.len() as u8, (value.len() >> 8) as u8]);
+            bytes.extend_from_slice(value);
+            while bytes.len() % 4 != 0 {
+                bytes.push(0);
+            }
+        }
+
+        let header_size = mem::size_of::<TbfHeaderV2Base>() + bytes.len();
+        let mut words = vec![2 | (header_size as u32) << 16, 0x1000, 1, 0];
+        for chunk in bytes.chunks(4) {
+            words.push(chunk.iter().rev().fold(0, |word, byte| word << 8 | *byte as u32));
+        }
+        words[3] = words.iter().fold(0, |checksum, word| checksum ^ word);
+
+        let words: &'static [u32] = Box::leak(words.into_boxed_slice());
+        unsafe { parse_and_validate_tbf_header(words.as_ptr() as *const u8) }
+    }
+
+    fn driver_list(drivers: &[u32]) -> Vec<u8> {
+        drivers
+            .iter()
+            .flat_map(|driver| (0..4).map(move |i| (driver >> (8 * i)) as u8))
+            .collect()
+    }
+
+    #[test]
+    fn app_without_driver_list_may_use_every_driver() {
+        let header = parse(&[(TbfHeaderTypes::TbfHeaderMain as u16, &[0; 12])]).unwrap();
+        assert!(header.driver_permitted(0));
+        assert!(header.driver_permitted(0x60000));
+    }
+
+    #[test]
+    fn app_may_only_use_listed_drivers() {
+        let list = driver_list(&[1, 0x60000]);
+        let header = parse(&[(DRIVERS, &list)]).unwrap();
+        assert!(header.driver_permitted(1));
+        assert!(header.driver_permitted(0x60000));
+        assert!(!header.driver_permitted(0));
+        assert!(!header.driver_permitted(2));
+    }
+
+    #[test]
+    fn malformed_driver_list_rejects_app() {
+        assert!(parse(&[(DRIVERS, &[1, 0, 0, 0, 2, 0])]).is_none());
+    }
+
+    #[test]
+    fn app_without_signature_has_none() {
+        let header = parse(&[(TbfHeaderTypes::TbfHeaderMain as u16, &[0; 12])]).unwrap();
+        assert_eq!(header.get_signature(), None);
+    }
+
+    #[test]
+    fn signature_is_returned_unpadded() {
+        let signature = [0xA5, 0x5A, 0x01, 0x02, 0x03, 0x04, 0x05];
+        let main = [0, 0, 0, 0, 0, 0, 0, 0, 0x00, 0x10, 0, 0];
+        let header = parse(&[
+            (SIGNATURE, &signature),
+            (TbfHeaderTypes::TbfHeaderMain as u16, &main),
+        ]).unwrap();
+        assert_eq!(header.get_signature(), Some(&signature[..]));
+        // The block after the padded signature is still found.
+        assert_eq!(header.get_minimum_app_ram_size(), 0x1000);
+    }
+
+    #[test]
+    fn minimum_stack_size_is_parsed() {
+        let header = parse(&[(TbfHeaderTypes::TbfHeaderMain as u16, &[0; 12])]).unwrap();
+        assert_eq!(header.get_minimum_stack_size(), 0);
+
+        let stack = [0x00, 0x08, 0, 0];
+        let header = parse(&[(TbfHeaderTypes::TbfHeaderMinimumStack as u16, &stack)]).unwrap();
+        assert_eq!(header.get_minimum_stack_size(), 2048);
+    }
+}
@@ -0,0 +1,389 @@
+//! Support for the kernel's hosted unit tests.
+//!
+//! Provides a chip whose processes never actually run, Tock Binary Format
+//! images built in memory, and a debug writer that captures `debug!` output
+//! instead of sending it to a UART.
+//!
+//! `debug!` writes to a single kernel-wide writer, so a test that can print,
+//! which includes any test that loads processes, must hold the guard returned
+//! by [`capture_debug`](fn.capture_debug.html) while it runs.
+
+extern crate std;
+
+use self::std::boxed::Box;
+use self::std::string::String;
+use self::std::sync::atomic::{AtomicBool, Ordering};
+use self::std::sync::{Once, ONCE_INIT};
+use self::std::vec;
+use self::std::vec::Vec;
+use core::cmp::max;
+use core::fmt::Write;
+
+use capabilities::{MemoryAllocationCapability, ProcessManagementCapability};
+use debug::{set_debug_writer_wrapper, DebugWriter, DebugWriterWrapper};
+use hil::uart::{self, UARTParameters};
+use platform::mpu::{Permissions, Region, MPU};
+use platform::Chip;
+use process::{self, FaultResponse, FunctionCall, ProcessType};
+use returncode::ReturnCode;
+use sched::Kernel;
+use syscall::{ContextSwitchReason, Syscall, UserspaceKernelBoundary};
+use tbfheader::TbfHeaderTypes;
+
+/// Gives a value a `'static` lifetime by leaking it.
+crate fn leak<T>(value: T) -> &'static mut T {
+    Box::leak(Box::new(value))
+}
+
+/// Returns a zeroed buffer of `len` bytes that starts at a multiple of
+/// `align`.
+crate fn aligned_buffer(len: usize, align: usize) -> &'static mut [u8] {
+    let buffer: &'static mut [u8] = Box::leak(vec![0; len + align].into_boxed_slice());
+    let offset = (align - buffer.as_ptr() as usize % align) % align;
+    &mut buffer[offset..offset + len]
+}
+
+/// Holds every capability, for calling restricted kernel functions.
+crate struct TestCapability;
+unsafe impl MemoryAllocationCapability for TestCapability {}
+unsafe impl ProcessManagementCapability for TestCapability {}
+
+/// Size of the stack frame `TestBoundary` pushes for a function call.
+crate const FRAME_SIZE: usize = 32;
+
+/// A userspace boundary for processes that never run. Function calls only
+/// move the stack pointer, so that stack accounting can be checked.
+crate struct TestBoundary;
+
+impl UserspaceKernelBoundary for TestBoundary {
+    type StoredState = ();
+
+    unsafe fn get_syscall(&self, _stack_pointer: *const usize) -> Option<Syscall> {
+        None
+    }
+
+    unsafe fn set_syscall_return_value(&self, _stack_pointer: *const usize, _value: isize) {}
+
+    unsafe fn pop_syscall_stack_frame(
+        &self,
+        stack_pointer: *const usize,
+        _state: &mut (),
+    ) -> *mut usize {
+        (stack_pointer as *mut u8).wrapping_offset(FRAME_SIZE as isize) as *mut usize
+    }
+
+    unsafe fn push_function_call(
+        &self,
+        stack_pointer: *const usize,
+        remaining_stack_memory: usize,
+        _callback: FunctionCall,
+        _state: &(),
+    ) -> Result<*mut usize, *mut usize> {
+        let new_stack_pointer =
+            (stack_pointer as *mut u8).wrapping_offset(-(FRAME_SIZE as isize)) as *mut usize;
+        if remaining_stack_memory < FRAME_SIZE {
+            Err(new_stack_pointer)
+        } else {
+            Ok(new_stack_pointer)
+        }
+    }
+
+    unsafe fn switch_to_process(
+        &self,
+        stack_pointer: *const usize,
+        _state: &mut (),
+    ) -> (*mut usize, ContextSwitchReason) {
+        (stack_pointer as *mut usize, ContextSwitchReason::SyscallFired)
+    }
+
+    unsafe fn fault_fmt(&self, _writer: &mut Write) {}
+
+    unsafe fn process_detail_fmt(
+        &self,
+        _stack_pointer: *const usize,
+        _state: &(),
+        _writer: &mut Write,
+    ) {
+    }
+
+    unsafe fn process_crash_fmt(
+        &self,
+        _stack_pointer: *const usize,
+        _state: &(),
+        _writer: &mut Write,
+    ) {
+    }
+}
+
+/// An MPU whose regions, like those of the Cortex-M MPU, are a power of two
+/// of at least 32 bytes in size and start at a multiple of their size.
+crate struct TestMpu;
+
+impl MPU for TestMpu {
+    fn allocate_region(
+        &self,
+        unallocated_memory_start: *const u8,
+        unallocated_memory_size: usize,
+        min_region_size: usize,
+        _permissions: Permissions,
+        _config: &mut Self::MpuConfig,
+    ) -> Option<Region> {
+        let size = max(32, min_region_size.next_power_of_two());
+        let start = unallocated_memory_start as usize;
+        let region_start = (start + size - 1) & !(size - 1);
+        if region_start + size > start + unallocated_memory_size {
+            None
+        } else {
+            Some(Region::new(region_start as *const u8, size))
+        }
+    }
+}
+
+crate struct TestChip {
+    mpu: TestMpu,
+    boundary: TestBoundary,
+}
+
+impl Chip for TestChip {
+    type MPU = TestMpu;
+    type UserspaceKernelBoundary = TestBoundary;
+    type SysTick = ();
+
+    fn service_pending_interrupts(&self) {}
+
+    fn has_pending_interrupts(&self) -> bool {
+        false
+    }
+
+    fn mpu(&self) -> &TestMpu {
+        &self.mpu
+    }
+
+    fn systick(&self) -> &() {
+        &()
+    }
+
+    fn userspace_kernel_boundary(&self) -> &TestBoundary {
+        &self.boundary
+    }
+
+    fn sleep(&self) {}
+
+    unsafe fn atomic<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        f()
+    }
+}
+
+/// Flash used by each app image built by `TestApp`.
+crate const APP_SIZE: usize = 0x200;
+
+/// Describes a Tock Binary Format image for an app named `name`.
+#[derive(Clone, Copy)]
+crate struct TestApp {
+    name: &'static str,
+    init_fn_offset: u32,
+    minimum_ram_size: u32,
+    minimum_stack_size: Option<u32>,
+}
+
+impl TestApp {
+    crate fn new(name: &'static str) -> TestApp {
+        TestApp {
+            name: name,
+            init_fn_offset: 0x101,
+            minimum_ram_size: 0,
+            minimum_stack_size: None,
+        }
+    }
+
+    crate fn init_fn_offset(mut self, offset: u32) -> TestApp {
+        self.init_fn_offset = offset;
+        self
+    }
+
+    crate fn minimum_ram_size(mut self, size: u32) -> TestApp {
+        self.minimum_ram_size = size;
+        self
+    }
+
+    crate fn minimum_stack_size(mut self, size: u32) -> TestApp {
+        self.minimum_stack_size = Some(size);
+        self
+    }
+
+    /// The app's image as `APP_SIZE / 4` little-endian words, starting with
+    /// a checksummed v2 header.
+    fn image(&self) -> Vec<u32> {
+        let main = [self.init_fn_offset, 0, self.minimum_ram_size];
+        let mut tlvs: Vec<(TbfHeaderTypes, Vec<u8>)> = vec![
+            (TbfHeaderTypes::TbfHeaderMain, words_to_bytes(&main)),
+            (
+                TbfHeaderTypes::TbfHeaderPackageName,
+                self.name.as_bytes().to_vec(),
+            ),
+        ];
+        if let Some(size) = self.minimum_stack_size {
+            tlvs.push((TbfHeaderTypes::TbfHeaderMinimumStack, words_to_bytes(&[size])));
+        }
+
+        let mut bytes = Vec::new();
+        for (tipe, value) in tlvs {
+            let tipe = tipe as u16;
+            bytes.extend_from_slice(&[tipe as u8, (tipe >> 8) as u8]);
+            bytes.extend_from_slice(&[value.len() as u8, (value.len() >> 8) as u8]);
+            bytes.extend_from_slice(&value);
+            while bytes.len() % 4 != 0 {
+                bytes.push(0);
+            }
+        }
+
+        let header_size = 16 + bytes.len();
+        let mut words = vec![2 | (header_size as u32) << 16, APP_SIZE as u32, 1, 0];
+        for chunk in bytes.chunks(4) {
+            words.push(chunk.iter().rev().fold(0, |word, byte| word << 8 | *byte as u32));
+        }
+        words[3] = words.iter().fold(0, |checksum, word| checksum ^ word);
+        words.resize(APP_SIZE / 4, 0);
+        words
+    }
+}
+
+fn words_to_bytes(words: &[u32]) -> Vec<u8> {
+    words
+        .iter()
+        .flat_map(|word| (0..4).map(move |i| (word >> (8 * i)) as u8))
+        .collect()
+}
+
+/// Lays out `apps` back to back in flash, followed by an empty slot that ends
+/// the list of apps. Each image is aligned to its size, as the MPU requires.
+crate fn flash(apps: &[TestApp]) -> &'static [u8] {
+    let flash = aligned_buffer((apps.len() + 1) * APP_SIZE, APP_SIZE);
+    for (app, slot) in apps.iter().zip(flash.chunks_mut(APP_SIZE)) {
+        slot.copy_from_slice(&words_to_bytes(&app.image()));
+    }
+    flash
+}
+
+/// Creates a kernel with `slots` process slots. The slots are also returned,
+/// so that processes can be loaded into them.
+crate fn kernel(slots: usize) -> (&'static Kernel, &'static mut [Option<&'static ProcessType>]) {
+    let processes: &'static mut [Option<&'static ProcessType>] =
+        Box::leak(vec![None; slots].into_boxed_slice());
+    let processes_ptr = processes as *mut [Option<&'static ProcessType>];
+    let kernel = leak(Kernel::new(unsafe { &*processes_ptr }));
+    (kernel, unsafe { &mut *processes_ptr })
+}
+
+/// Loads `apps` into `slots` of `kernel` from `app_memory_size` bytes of RAM.
+/// A fault in a process is handled with `fault_response`.
+crate fn load(
+    kernel: &'static Kernel,
+    slots: &'static mut [Option<&'static ProcessType>],
+    apps: &[TestApp],
+    app_memory_size: usize,
+    fault_response: FaultResponse,
+) {
+    process::load_processes(
+        kernel,
+        leak(TestChip {
+            mpu: TestMpu,
+            boundary: TestBoundary,
+        }),
+        flash(apps).as_ptr(),
+        aligned_buffer(app_memory_size, 8),
+        slots,
+        fault_response,
+        &TestCapability,
+    );
+}
+
+/// Creates a kernel with `slots` process slots and loads `apps` into it with
+/// plenty of RAM. Returns the kernel and the loaded processes, a fault in
+/// which panics the test.
+crate fn load_apps(
+    slots: usize,
+    apps: &[TestApp],
+) -> (&'static Kernel, &'static [Option<&'static ProcessType>]) {
+    let (kernel, processes) = kernel(slots);
+    let processes_ptr = processes as *mut [Option<&'static ProcessType>];
+    load(kernel, processes, apps, 64 * 1024, FaultResponse::Panic);
+    (kernel, unsafe { &*processes_ptr })
+}
+
+/// A UART that sends nothing. Transmitted bytes are appended to the captured
+/// debug output, and each transmission completes at once.
+struct CaptureUart;
+
+static CAPTURE_UART: CaptureUart = CaptureUart;
+static mut CAPTURE_CLIENT: Option<&'static uart::Client> = None;
+static mut CAPTURED: Option<Vec<u8>> = None;
+
+impl uart::UART for CaptureUart {
+    fn set_client(&self, client: &'static uart::Client) {
+        unsafe {
+            CAPTURE_CLIENT = Some(client);
+        }
+    }
+
+    fn configure(&self, _params: UARTParameters) -> ReturnCode {
+        ReturnCode::SUCCESS
+    }
+
+    fn transmit(&self, tx_data: &'static mut [u8], tx_len: usize) {
+        unsafe {
+            CAPTURED
+                .get_or_insert_with(Vec::new)
+                .extend_from_slice(&tx_data[..tx_len]);
+            if let Some(client) = CAPTURE_CLIENT {
+                client.transmit_complete(tx_data, uart::Error::CommandComplete);
+            }
+        }
+    }
+
+    fn receive(&self, _rx_buffer: &'static mut [u8], _rx_len: usize) {}
+
+    fn abort_receive(&self) {}
+}
+
+static DEBUG_LOCK: AtomicBool = AtomicBool::new(false);
+static DEBUG_INIT: Once = ONCE_INIT;
+
+/// Exclusive access to the captured `debug!` output. Released when dropped.
+crate struct DebugCapture;
+
+/// Waits until no other test is using `debug!`, then starts capturing its
+/// output.
+crate fn capture_debug() -> DebugCapture {
+    while DEBUG_LOCK.compare_and_swap(false, true, Ordering::Acquire) {}
+    DEBUG_INIT.call_once(|| unsafe {
+        let writer = leak(DebugWriter::new(
+            &CAPTURE_UART,
+            leak([0; 64]),
+            leak([0; 1024]),
+        ));
+        uart::UART::set_client(&CAPTURE_UART, writer);
+        set_debug_writer_wrapper(leak(DebugWriterWrapper::new(writer)));
+    });
+    unsafe {
+        CAPTURED = Some(Vec::new());
+    }
+    DebugCapture
+}
+
+impl DebugCapture {
+    /// Everything printed with `debug!` since capturing started.
+    crate fn output(&self) -> String {
+        let captured = unsafe { CAPTURED.clone().unwrap_or_default() };
+        String::from_utf8_lossy(&captured).into_owned()
+    }
+}
+
+impl Drop for DebugCapture {
+    fn drop(&mut self) {
+        DEBUG_LOCK.store(false, Ordering::Release);
+    }
+}